@@ -0,0 +1,515 @@
+//! Bearer token authentication which refreshes expired tokens and replays the rejected request,
+//! tokens or API keys declared with the [`api!`](crate::api) macro, with the `jwt` feature JWTs
+//! minted from a private key, see `JwtAuth`, and with the `digest-auth` feature HTTP Digest
+//! access authentication, see `DigestAuth`.
+//!
+//! A [`TokenAuth`] added with [`ApiState::interceptor`](crate::ApiState::interceptor) sends its
+//! token in the `Authorization` header of every request. When a response has the status
+//! `401 Unauthorized`, it calls the refresh callback once, stores the new token and sends the
+//! request again with it. The response to the replay is returned as it is, even if it is another
+//! `401`, so a refresh which doesn't help can't loop.
+//!
+//! Requests rejected at the same time share a single refresh, and requests which were sent with
+//! an older token are only replayed with the current one. Requests with streamed bodies, e.g.
+//! multipart uploads, can't be replayed and return the `401` response.
+//!
+//! # Usage
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use api_client::{api, auth::TokenAuth, ApiState, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! async fn refresh_token() -> ResultType<String> {
+//!     // e.g. exchange a refresh token with the identity provider
+//!     Ok("new-token".to_string())
+//! }
+//!
+//! let auth = Arc::new(TokenAuth::new("initial-token", refresh_token));
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().interceptor(auth.clone()))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! # Declared credentials
+//! For the common case of a fixed token or API key, the struct generated by the
+//! [`api!`](crate::api) macro can hold it: with `auth = Bearer` it sends the token in the
+//! `Authorization` header, with `auth = ApiKey(header "...")` as the value of the given header.
+//! The generated `set_token` method sets or replaces it, see [`HeaderAuth`]. Until a token is set,
+//! requests are sent without the header.
+//! ```rust
+//! use api_client::{api, Api};
+//!
+//! api!(pub struct GitHub, auth = Bearer);
+//! api!(pub struct Weather, auth = ApiKey(header "X-Api-Key"));
+//!
+//! let github = GitHub::new();
+//! github.set_token("secret");
+//! let weather = Weather::new();
+//! weather.set_token("key");
+//! ```
+//!
+//! # Login endpoints
+//! An endpoint declared `with { login: ... }` stores the token in its successful responses with
+//! [`Api::on_login`](crate::Api::on_login), so the struct sends it with every later request
+//! without calling `set_token` in between. The token is read from the string at a JSON pointer
+//! into the body with `token("/access_token")`, from a header with `header("X-Auth-Token")` or
+//! from a cookie with `cookie("session")`. A successful response without it fails with
+//! [`Error::NoLoginToken`]. Sessions kept in cookies don't need a login endpoint, only
+//! [`ApiState::cookies`](crate::ApiState::cookies).
+//! ```rust
+//! use api_client::{api, Api};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize)]
+//! pub struct Credentials {
+//!     pub username: String,
+//!     pub password: String,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! pub struct Session {
+//!     pub access_token: String,
+//!     pub expires_in: u64,
+//! }
+//!
+//! api!(pub struct ExampleApi, auth = Bearer);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         pub fn login(request: Json<Credentials>) -> Json<Session> {
+//!             POST "https://example.com/login"
+//!             with { login: token("/access_token") }
+//!         }
+//!
+//!         pub fn profile() -> String {
+//!             GET "https://example.com/profile"
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Mutex, PoisonError, RwLock},
+};
+
+use reqwest::{
+    header::{HeaderName, HeaderValue, AUTHORIZATION, SET_COOKIE},
+    StatusCode,
+};
+
+#[cfg(feature = "json")]
+use crate::single_flight::SharedResponse;
+use crate::{
+    endpoint::Login,
+    interceptor::{Interceptor, Next},
+    trace, Api, Error, RequestBuilder, ResultType,
+};
+
+#[cfg(feature = "digest-auth")]
+mod digest;
+#[cfg(feature = "jwt")]
+mod jwt;
+
+#[cfg(feature = "digest-auth")]
+#[cfg_attr(docsrs, doc(cfg(feature = "digest-auth")))]
+pub use digest::DigestAuth;
+
+#[cfg(feature = "jwt")]
+#[cfg_attr(docsrs, doc(cfg(feature = "jwt")))]
+pub use jwt::JwtAuth;
+
+/// Callback returning a new token.
+type Refresh = Box<dyn Fn() -> Pin<Box<dyn Future<Output = ResultType<String>>>> + Send + Sync>;
+
+/// Bearer token sent with every request and refreshed when it is rejected, see
+/// [`auth`](crate::auth).
+pub struct TokenAuth {
+    /// The current token and the number of refreshes which produced it.
+    token: Mutex<(String, u64)>,
+    /// Held while the token is refreshed, so concurrent rejections refresh it once.
+    refreshing: tokio::sync::Mutex<()>,
+    /// Fetches a new token.
+    refresh: Refresh,
+}
+
+impl fmt::Debug for TokenAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenAuth").finish_non_exhaustive()
+    }
+}
+
+impl TokenAuth {
+    /// Creates an authentication sending `token`, which calls `refresh` for a new one when a
+    /// request is rejected with `401 Unauthorized`.
+    pub fn new<F, Fut>(token: impl Into<String>, refresh: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ResultType<String>> + 'static,
+    {
+        Self {
+            token: Mutex::new((token.into(), 0)),
+            refreshing: tokio::sync::Mutex::new(()),
+            refresh: Box::new(move || Box::pin(refresh())),
+        }
+    }
+
+    /// Returns the current token, e.g. to persist it.
+    #[must_use]
+    pub fn token(&self) -> String {
+        self.current().0
+    }
+
+    /// Replaces the current token, e.g. after logging in again.
+    pub fn set_token(&self, token: impl Into<String>) {
+        let mut current = self.token.lock().unwrap_or_else(PoisonError::into_inner);
+        *current = (token.into(), current.1 + 1);
+    }
+
+    /// Returns the current token and the number of refreshes which produced it.
+    fn current(&self) -> (String, u64) {
+        self.token
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns a token newer than the one of `generation`, refreshing it unless another request
+    /// already did.
+    async fn refreshed(&self, generation: u64) -> ResultType<String> {
+        let _refreshing = self.refreshing.lock().await;
+        let (token, current) = self.current();
+        if current != generation {
+            return Ok(token);
+        }
+        let token = (self.refresh)().await?;
+        *self.token.lock().unwrap_or_else(PoisonError::into_inner) = (token.clone(), current + 1);
+        Ok(token)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Interceptor for TokenAuth {
+    async fn intercept(
+        &self,
+        mut request: reqwest::Request,
+        next: Next<'_>,
+    ) -> ResultType<reqwest::Response> {
+        let (token, generation) = self.current();
+        authorize(&mut request, &token)?;
+        let replay = request.try_clone();
+        let response = next.run(request).await?;
+        match replay {
+            Some(mut replay) if response.status() == StatusCode::UNAUTHORIZED => {
+                let token = self.refreshed(generation).await?;
+                authorize(&mut replay, &token)?;
+                trace::retry(None, 2, &response.status());
+                next.run(replay).await
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+/// Sets the `Authorization` header of `request` to the bearer `token`.
+fn authorize(request: &mut reqwest::Request, token: &str) -> ResultType<()> {
+    request
+        .headers_mut()
+        .insert(AUTHORIZATION, sensitive(&format!("Bearer {token}"))?);
+    Ok(())
+}
+
+/// Returns `value` as a header value hidden from debug output.
+fn sensitive(value: &str) -> ResultType<HeaderValue> {
+    let mut value = HeaderValue::from_str(value).map_err(|_| Error::InvalidToken)?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// Token or API key sent in a header of every request, held by structs declared with
+/// `api!(struct X, auth = ...)`, see [`auth`](crate::auth).
+pub struct HeaderAuth {
+    /// Header the credential is sent in.
+    header: HeaderName,
+    /// Whether the credential is sent as a bearer token.
+    bearer: bool,
+    /// The credential, if one was set.
+    token: RwLock<Option<String>>,
+}
+
+impl fmt::Debug for HeaderAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeaderAuth")
+            .field("header", &self.header)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HeaderAuth {
+    /// Creates an authentication sending a bearer token in the `Authorization` header.
+    #[must_use]
+    pub fn bearer() -> Self {
+        Self {
+            header: AUTHORIZATION,
+            bearer: true,
+            token: RwLock::new(None),
+        }
+    }
+
+    /// Creates an authentication sending an API key as the value of `header`.
+    ///
+    /// # Panics
+    /// Panics if `header` is not a valid header name.
+    #[must_use]
+    pub fn api_key(header: &str) -> Self {
+        Self {
+            header: HeaderName::from_bytes(header.as_bytes()).expect("invalid API key header name"),
+            bearer: false,
+            token: RwLock::new(None),
+        }
+    }
+
+    /// Returns the current token, if one was set.
+    #[must_use]
+    pub fn token(&self) -> Option<String> {
+        self.token
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Sets or replaces the token sent with every request.
+    pub fn set_token(&self, token: impl Into<String>) {
+        *self.token.write().unwrap_or_else(PoisonError::into_inner) = Some(token.into());
+    }
+
+    /// Adds the header to `request`, if a token is set.
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidToken`] if the token contains characters which are not allowed in
+    /// headers.
+    pub fn apply(&self, request: RequestBuilder) -> ResultType<RequestBuilder> {
+        let value = match self.token() {
+            Some(token) if self.bearer => sensitive(&format!("Bearer {token}"))?,
+            Some(token) => sensitive(&token)?,
+            None => return Ok(request),
+        };
+        Ok(request.header(self.header.clone(), value))
+    }
+}
+
+/// Passes the token in `response` of a login endpoint to [`Api::on_login`], returning the
+/// response to decode.
+#[cfg_attr(not(feature = "json"), allow(clippy::unused_async))]
+pub(crate) async fn capture<A: Api + ?Sized>(
+    api: &A,
+    login: Login,
+    response: reqwest::Response,
+) -> ResultType<reqwest::Response> {
+    let (token, response) = match login {
+        #[cfg(feature = "json")]
+        Login::Token(pointer) => {
+            let shared = SharedResponse::read(response).await?;
+            let token = serde_json::from_slice::<serde_json::Value>(shared.body())
+                .ok()
+                .and_then(|body| body.pointer(pointer)?.as_str().map(ToString::to_string));
+            (token, shared.to_response())
+        }
+        Login::Header(name) => {
+            let token = response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok());
+            (token.map(ToString::to_string), response)
+        }
+        Login::Cookie(name) => {
+            let token = response
+                .headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .find_map(|cookie| {
+                    let (cookie, value) = cookie.split(';').next()?.split_once('=')?;
+                    (cookie.trim() == name).then(|| value.trim().trim_matches('"').to_string())
+                });
+            (token, response)
+        }
+    };
+    api.on_login(token.ok_or(Error::NoLoginToken(login))?);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::TokenAuth;
+    use crate::{api, test_server, Api, ApiState, Error};
+
+    api!(struct Protected);
+
+    impl Protected {
+        api! {
+            fn echo(&self, request: Json<Vec<u32>>, base_url: &str) -> Json<Vec<u32>> {
+                POST "{base_url}/echo"
+            }
+        }
+    }
+
+    #[test]
+    fn refreshes_and_replays() {
+        tokio_test::block_on(async {
+            let hits = Arc::new(AtomicUsize::new(0));
+            let server_hits = hits.clone();
+            let base_url = test_server::serve(move |request| {
+                server_hits.fetch_add(1, Ordering::SeqCst);
+                match request.header("authorization") {
+                    Some("Bearer valid") => test_server::Response::new(200).body(request.body),
+                    _ => test_server::Response::new(401),
+                }
+            })
+            .await;
+            let refreshes = Arc::new(AtomicUsize::new(0));
+            let counted = refreshes.clone();
+            let auth = Arc::new(TokenAuth::new("expired", move || {
+                let refresh = counted.fetch_add(1, Ordering::SeqCst);
+                async move { Ok(if refresh == 0 { "valid" } else { "revoked" }.to_string()) }
+            }));
+            let api = Protected::builder()
+                .state(ApiState::new().interceptor(auth.clone()))
+                .build()
+                .unwrap();
+
+            let (one, two) = (vec![1], vec![2]);
+            let (first, second) =
+                futures_util::join!(api.echo(&one, &base_url), api.echo(&two, &base_url));
+            assert_eq!((first.unwrap(), second.unwrap()), (vec![1], vec![2]));
+            assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+            assert_eq!(auth.token(), "valid");
+            assert_eq!(api.echo(&vec![3], &base_url).await.unwrap(), vec![3]);
+            assert_eq!(hits.load(Ordering::SeqCst), 5);
+
+            auth.set_token("stale");
+            assert!(api.echo(&vec![4], &base_url).await.is_err());
+            assert_eq!(refreshes.load(Ordering::SeqCst), 2);
+            assert_eq!(hits.load(Ordering::SeqCst), 7);
+        });
+    }
+
+    #[derive(Debug, PartialEq, Eq, serde::Deserialize)]
+    struct Session {
+        access_token: String,
+    }
+
+    api!(struct Bearer, auth = Bearer);
+    api!(struct Keyed, auth = ApiKey(header "X-Api-Key"));
+
+    impl Bearer {
+        api! {
+            fn whoami(&self, base_url: &str) -> String {
+                GET "{base_url}/whoami"
+            }
+
+            fn login(&self, base_url: &str, user: &str) -> Json<Session> {
+                POST "{base_url}/login?user={user}"
+                with { login: token("/access_token") }
+            }
+        }
+    }
+
+    impl Keyed {
+        api! {
+            fn whoami(&self, base_url: &str) -> String {
+                GET "{base_url}/whoami"
+            }
+
+            fn login(&self, base_url: &str) -> StatusCode {
+                POST "{base_url}/login"
+                with { login: header("X-Auth-Token") }
+            }
+
+            fn cookie_login(&self, base_url: &str) -> StatusCode {
+                POST "{base_url}/login"
+                with { login: cookie("key") }
+            }
+        }
+    }
+
+    #[test]
+    fn sends_declared_credentials() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let credentials = [
+                    request.header("authorization").unwrap_or("-"),
+                    request.header("x-api-key").unwrap_or("-"),
+                ];
+                test_server::Response::new(200).body(credentials.join(" "))
+            })
+            .await;
+
+            let bearer = Bearer::new();
+            assert_eq!(bearer.whoami(&base_url).await.unwrap(), "- -");
+            bearer.set_token("secret");
+            assert_eq!(bearer.whoami(&base_url).await.unwrap(), "Bearer secret -");
+
+            let keyed = Keyed::new();
+            keyed.set_token("key");
+            assert_eq!(keyed.whoami(&base_url).await.unwrap(), "- key");
+            keyed.set_token("new\nkey");
+            assert!(matches!(
+                keyed.whoami(&base_url).await,
+                Err(Error::InvalidToken)
+            ));
+        });
+    }
+
+    #[test]
+    fn captures_login_tokens() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                if request.path == "/login?user=nobody" {
+                    return test_server::Response::new(200).json("{}");
+                }
+                if request.path.starts_with("/login") {
+                    return test_server::Response::new(200)
+                        .header("x-auth-token", "from-header")
+                        .header("set-cookie", "key=from-cookie; HttpOnly")
+                        .json(r#"{"access_token": "from-body"}"#);
+                }
+                let credentials = [
+                    request.header("authorization").unwrap_or("-"),
+                    request.header("x-api-key").unwrap_or("-"),
+                ];
+                test_server::Response::new(200).body(credentials.join(" "))
+            })
+            .await;
+
+            let bearer = Bearer::new();
+            let session = bearer.login(&base_url, "admin").await.unwrap();
+            assert_eq!(session.access_token, "from-body");
+            assert_eq!(
+                bearer.whoami(&base_url).await.unwrap(),
+                "Bearer from-body -"
+            );
+            let error = bearer.login(&base_url, "nobody").await.unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "login response carried no token in the JSON body at `/access_token`"
+            );
+
+            let keyed = Keyed::new();
+            keyed.login(&base_url).await.unwrap();
+            assert_eq!(keyed.whoami(&base_url).await.unwrap(), "- from-header");
+            keyed.cookie_login(&base_url).await.unwrap();
+            assert_eq!(keyed.whoami(&base_url).await.unwrap(), "- from-cookie");
+        });
+    }
+}
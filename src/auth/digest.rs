@@ -0,0 +1,497 @@
+//! HTTP Digest access authentication, see [`DigestAuth`].
+
+use std::{
+    fmt::{self, Write},
+    sync::{Mutex, PoisonError},
+};
+
+use md5::Md5;
+use reqwest::{
+    header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    StatusCode,
+};
+use sha2::{Digest, Sha256, Sha512_256};
+
+use super::sensitive;
+use crate::{
+    idempotency,
+    interceptor::{Interceptor, Next},
+    trace, ResultType,
+};
+
+/// Username and password sent with HTTP Digest access authentication ([RFC 7616]), which some
+/// devices and older APIs require instead of basic authentication.
+///
+/// When a request is rejected with `401 Unauthorized` and a `Digest` challenge in the
+/// `WWW-Authenticate` header, the request is sent again with the response to the challenge. The
+/// `SHA-512-256`, `SHA-256` and `MD5` algorithms, their `-sess` variants and the `auth` and
+/// `auth-int` qualities of protection are supported; of several challenges the strongest one is
+/// answered. The challenge is kept, so later requests answer it right away, counting up the
+/// nonce count, until the server rejects its nonce as stale.
+///
+/// Like [`TokenAuth`](super::TokenAuth) it is added with
+/// [`ApiState::interceptor`](crate::ApiState::interceptor), replays a request once and can't
+/// replay requests with streamed bodies.
+///
+/// # Usage
+/// ```rust
+/// use api_client::{api, auth::DigestAuth, ApiState};
+///
+/// api!(pub struct Camera);
+///
+/// let api = Camera::builder()
+///     .state(ApiState::new().interceptor(DigestAuth::new("admin", "password")))
+///     .build()
+///     .unwrap();
+/// ```
+///
+/// [RFC 7616]: https://www.rfc-editor.org/rfc/rfc7616
+pub struct DigestAuth {
+    /// Username of the account.
+    username: String,
+    /// Password of the account.
+    password: String,
+    /// The last challenge of the server and the number of times its nonce was used.
+    challenge: Mutex<Option<(Challenge, u32)>>,
+}
+
+impl fmt::Debug for DigestAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DigestAuth")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DigestAuth {
+    /// Creates an authentication answering challenges with `username` and `password`.
+    #[must_use]
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            password: password.into(),
+            challenge: Mutex::new(None),
+        }
+    }
+
+    /// Adds the answer to the stored challenge to `request`, returning whether there was one.
+    fn authorize(&self, request: &mut reqwest::Request) -> ResultType<bool> {
+        let mut stored = self
+            .challenge
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let Some((challenge, count)) = &mut *stored else {
+            return Ok(false);
+        };
+        let body = match (challenge.qop, request.body()) {
+            (Some(Qop::AuthInt), Some(body)) => match body.as_bytes() {
+                Some(body) => body,
+                None => return Ok(false),
+            },
+            _ => &[],
+        };
+        *count += 1;
+        let url = request.url();
+        let uri = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        let authorization = challenge.answer(
+            &self.username,
+            &self.password,
+            request.method().as_str(),
+            &uri,
+            body,
+            *count,
+            &cnonce(),
+        );
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, sensitive(&authorization)?);
+        Ok(true)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Interceptor for DigestAuth {
+    async fn intercept(
+        &self,
+        mut request: reqwest::Request,
+        next: Next<'_>,
+    ) -> ResultType<reqwest::Response> {
+        let answered = self.authorize(&mut request)?;
+        let replay = request.try_clone();
+        let response = next.run(request).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        let challenge = match Challenge::strongest(&response) {
+            Some(challenge) if !answered || challenge.stale => challenge,
+            _ => return Ok(response),
+        };
+        match replay {
+            Some(mut replay) => {
+                *self
+                    .challenge
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner) = Some((challenge, 0));
+                if !self.authorize(&mut replay)? {
+                    return Ok(response);
+                }
+                trace::retry(None, 2, &response.status());
+                next.run(replay).await
+            }
+            None => Ok(response),
+        }
+    }
+}
+
+/// Hash algorithm of a challenge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Algorithm {
+    /// `MD5`, the default.
+    Md5,
+    /// `SHA-256`.
+    Sha256,
+    /// `SHA-512-256`.
+    Sha512_256,
+}
+
+impl Algorithm {
+    /// Returns the hex encoded hash of `data`.
+    fn hash(self, data: &[u8]) -> String {
+        let hash = match self {
+            Algorithm::Md5 => Md5::digest(data).to_vec(),
+            Algorithm::Sha256 => Sha256::digest(data).to_vec(),
+            Algorithm::Sha512_256 => Sha512_256::digest(data).to_vec(),
+        };
+        hash.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+    }
+}
+
+/// Quality of protection of a challenge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Qop {
+    /// `auth`, authentication of the request line.
+    Auth,
+    /// `auth-int`, also covering the body.
+    AuthInt,
+}
+
+/// A `Digest` challenge of a `WWW-Authenticate` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Challenge {
+    /// Protection space of the credentials.
+    realm: String,
+    /// Nonce chosen by the server.
+    nonce: String,
+    /// Opaque value which is sent back unchanged.
+    opaque: Option<String>,
+    /// Hash algorithm.
+    algorithm: Algorithm,
+    /// Whether the `-sess` variant of the algorithm is used.
+    session: bool,
+    /// Quality of protection, `None` for servers following RFC 2069.
+    qop: Option<Qop>,
+    /// Whether the username is sent hashed.
+    userhash: bool,
+    /// Whether a request was rejected only because its nonce expired.
+    stale: bool,
+}
+
+impl Challenge {
+    /// Returns the strongest supported challenge of `response`.
+    fn strongest(response: &reqwest::Response) -> Option<Self> {
+        response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(challenges)
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("digest"))
+            .filter_map(|(_, params)| Self::parse(&params))
+            .max_by_key(|challenge| challenge.algorithm)
+    }
+
+    /// Parses the parameters of a challenge, returning `None` if it is not supported.
+    fn parse(params: &[(String, String)]) -> Option<Self> {
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        };
+        let algorithm = param("algorithm").unwrap_or("MD5").to_ascii_uppercase();
+        let (algorithm, session) = match algorithm.strip_suffix("-SESS") {
+            Some(algorithm) => (algorithm.to_string(), true),
+            None => (algorithm, false),
+        };
+        let algorithm = match algorithm.as_str() {
+            "MD5" => Algorithm::Md5,
+            "SHA-256" => Algorithm::Sha256,
+            "SHA-512-256" => Algorithm::Sha512_256,
+            _ => return None,
+        };
+        let qop = match param("qop") {
+            None => None,
+            Some(qop) => {
+                let offered: Vec<_> = qop.split(',').map(str::trim).collect();
+                if offered.contains(&"auth") {
+                    Some(Qop::Auth)
+                } else if offered.contains(&"auth-int") {
+                    Some(Qop::AuthInt)
+                } else {
+                    return None;
+                }
+            }
+        };
+        let flag = |name| param(name).is_some_and(|value| value.eq_ignore_ascii_case("true"));
+        Some(Self {
+            realm: param("realm")?.to_string(),
+            nonce: param("nonce")?.to_string(),
+            opaque: param("opaque").map(ToString::to_string),
+            algorithm,
+            session,
+            qop,
+            userhash: flag("userhash"),
+            stale: flag("stale"),
+        })
+    }
+
+    /// Returns the `Authorization` header answering the challenge for a request.
+    #[allow(clippy::too_many_arguments)]
+    fn answer(
+        &self,
+        username: &str,
+        password: &str,
+        method: &str,
+        uri: &str,
+        body: &[u8],
+        count: u32,
+        cnonce: &str,
+    ) -> String {
+        let hash = |data: &str| self.algorithm.hash(data.as_bytes());
+        let mut a1 = hash(&format!("{username}:{}:{password}", self.realm));
+        if self.session {
+            a1 = hash(&format!("{a1}:{}:{cnonce}", self.nonce));
+        }
+        let a2 = match self.qop {
+            Some(Qop::AuthInt) => hash(&format!("{method}:{uri}:{}", self.algorithm.hash(body))),
+            _ => hash(&format!("{method}:{uri}")),
+        };
+        let nc = format!("{count:08x}");
+        let qop = match self.qop {
+            Some(Qop::Auth) => "auth",
+            Some(Qop::AuthInt) => "auth-int",
+            None => "",
+        };
+        let response = if self.qop.is_some() {
+            hash(&format!("{a1}:{}:{nc}:{cnonce}:{qop}:{a2}", self.nonce))
+        } else {
+            hash(&format!("{a1}:{}:{a2}", self.nonce))
+        };
+        let username = if self.userhash {
+            hash(&format!("{username}:{}", self.realm))
+        } else {
+            username.to_string()
+        };
+        let algorithm = match (self.algorithm, self.session) {
+            (Algorithm::Md5, false) => "MD5",
+            (Algorithm::Md5, true) => "MD5-sess",
+            (Algorithm::Sha256, false) => "SHA-256",
+            (Algorithm::Sha256, true) => "SHA-256-sess",
+            (Algorithm::Sha512_256, false) => "SHA-512-256",
+            (Algorithm::Sha512_256, true) => "SHA-512-256-sess",
+        };
+        let mut header = format!(
+            "Digest username={}, realm={}, uri={}, algorithm={algorithm}, nonce={}",
+            quote(&username),
+            quote(&self.realm),
+            quote(uri),
+            quote(&self.nonce)
+        );
+        if self.qop.is_some() {
+            let _ = write!(header, ", nc={nc}, cnonce={}, qop={qop}", quote(cnonce));
+        }
+        let _ = write!(header, ", response={}", quote(&response));
+        if let Some(opaque) = &self.opaque {
+            let _ = write!(header, ", opaque={}", quote(opaque));
+        }
+        if self.userhash {
+            header.push_str(", userhash=true");
+        }
+        header
+    }
+}
+
+/// Splits a `WWW-Authenticate` header into its challenges, with their schemes and parameters.
+fn challenges(header: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut challenges: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    let mut rest = header;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c == '=' || c.is_whitespace());
+        let end = rest
+            .find(|c: char| c == '=' || c == ',' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        if end == 0 {
+            break challenges;
+        }
+        let token = rest[..end].to_string();
+        rest = rest[end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(value) if !value.starts_with('=') => value.trim_start(),
+            _ => {
+                challenges.push((token, Vec::new()));
+                continue;
+            }
+        };
+        let value = if let Some(quoted) = value.strip_prefix('"') {
+            let mut unquoted = String::new();
+            let mut chars = quoted.char_indices();
+            rest = "";
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '\\' => unquoted.extend(chars.next().map(|(_, c)| c)),
+                    '"' => {
+                        rest = &quoted[index + 1..];
+                        break;
+                    }
+                    c => unquoted.push(c),
+                }
+            }
+            unquoted
+        } else {
+            let end = value.find(',').unwrap_or(value.len());
+            rest = &value[end..];
+            value[..end].trim().to_string()
+        };
+        if let Some((_, params)) = challenges.last_mut() {
+            params.push((token, value));
+        }
+    }
+}
+
+/// Returns `value` as a quoted string.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Returns a new random client nonce.
+fn cnonce() -> String {
+    format!("{:032x}", u128::from_be_bytes(idempotency::random()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{challenges, Algorithm, Challenge, DigestAuth, Qop};
+    use crate::{api, test_server, ApiState};
+
+    /// Challenge of the examples in RFC 7616, section 3.9.1.
+    fn example(algorithm: Algorithm) -> Challenge {
+        Challenge {
+            realm: "http-auth@example.org".to_string(),
+            nonce: "7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v".to_string(),
+            opaque: Some("FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS".to_string()),
+            algorithm,
+            session: false,
+            qop: Some(Qop::Auth),
+            userhash: false,
+            stale: false,
+        }
+    }
+
+    #[test]
+    fn answers_rfc_7616_examples() {
+        let header = r#"Digest realm="http-auth@example.org", qop="auth, auth-int", algorithm=SHA-256, nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v", opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS", Basic realm="fallback""#;
+        let parsed = challenges(header);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].0, "Basic");
+        assert_eq!(
+            Challenge::parse(&parsed[0].1),
+            Some(example(Algorithm::Sha256))
+        );
+
+        let cnonce = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+        let answer = |algorithm| {
+            example(algorithm).answer(
+                "Mufasa",
+                "Circle of Life",
+                "GET",
+                "/dir/index.html",
+                b"",
+                1,
+                cnonce,
+            )
+        };
+        assert!(answer(Algorithm::Md5).contains(r#"response="8ca523f5e9506fed4657c9700eebdbec""#));
+        assert_eq!(
+            answer(Algorithm::Sha256),
+            r#"Digest username="Mufasa", realm="http-auth@example.org", uri="/dir/index.html", algorithm=SHA-256, nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v", nc=00000001, cnonce="f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ", qop=auth, response="753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1", opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#
+        );
+    }
+
+    api!(struct Device);
+
+    impl Device {
+        api! {
+            fn status(&self, base_url: &str) -> StatusCode {
+                GET "{base_url}/status"
+            }
+        }
+    }
+
+    #[test]
+    fn answers_challenges() {
+        tokio_test::block_on(async {
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let server_received = received.clone();
+            let base_url = test_server::serve(move |request| {
+                let authorization = request.header("authorization").map(ToString::to_string);
+                let mut received = server_received.lock().unwrap();
+                received.push(authorization.clone().unwrap_or_default());
+                let nonce = if received.len() < 4 {
+                    "first"
+                } else {
+                    "second"
+                };
+                match authorization {
+                    Some(authorization)
+                        if authorization.contains(&format!("nonce=\"{nonce}\"")) =>
+                    {
+                        test_server::Response::new(200)
+                    }
+                    authorization => test_server::Response::new(401).header(
+                        "www-authenticate",
+                        &format!(
+                            r#"Digest realm="device", qop="auth", nonce="{nonce}", stale={}"#,
+                            authorization.is_some()
+                        ),
+                    ),
+                }
+            })
+            .await;
+            let api = Device::builder()
+                .state(ApiState::new().interceptor(DigestAuth::new("admin", "password")))
+                .build()
+                .unwrap();
+            for _ in 0..3 {
+                assert_eq!(api.status(&base_url).await.unwrap().as_u16(), 200);
+            }
+
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), 5);
+            assert_eq!(received[0], "");
+            assert!(received[1].contains("nc=00000001"));
+            assert!(received[2].contains("nc=00000002"));
+            assert!(received[3].contains(r#"nonce="first""#));
+            assert!(
+                received[4].contains(r#"nonce="second""#) && received[4].contains("nc=00000001")
+            );
+        });
+    }
+}
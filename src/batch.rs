@@ -0,0 +1,282 @@
+//! Running one endpoint for many inputs with bounded parallelism.
+//!
+//! See [`Api::batch`](crate::Api::batch). Fewer calls are started while the server's rate limit
+//! budget or the tokens of the rate limiter of the instance are lower than the concurrency of the
+//! batch, see [`rate_limit`](crate::rate_limit).
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, Api};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn todo(id: u32) -> String {
+//!            GET "https://example.com/todos/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn todos(api: &ExampleApi) -> Result<Vec<String>, api_client::batch::BatchError> {
+//!     api.batch(1..=100, |api, id| api.todo(id))
+//!         .concurrency(8)
+//!         .try_collect()
+//!         .await
+//! }
+//! ```
+
+use std::{fmt, future::Future};
+
+use futures_util::{stream::FuturesOrdered, StreamExt};
+
+use crate::{rate_limit, runtime, Api, Error, ResultType};
+
+/// Number of calls a [`Batch`] runs at once unless configured otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Calls an endpoint for every input, created by [`Api::batch`](crate::Api::batch).
+#[must_use = "a batch does nothing until it is collected"]
+pub struct Batch<'a, A: ?Sized, I, F> {
+    /// The api the calls are made on.
+    api: &'a A,
+    /// The remaining inputs.
+    inputs: I,
+    /// Creates the call for an input.
+    call: F,
+    /// Maximum number of calls running at once.
+    concurrency: usize,
+}
+
+impl<A: ?Sized, I, F> fmt::Debug for Batch<'_, A, I, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Batch")
+            .field("concurrency", &self.concurrency)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, A, I, F, Fut, T> Batch<'a, A, I, F>
+where
+    A: Api + ?Sized,
+    I: Iterator,
+    F: FnMut(&'a A, I::Item) -> Fut,
+    Fut: Future<Output = ResultType<T>> + 'a,
+{
+    /// Creates a batch calling `call` for every item of `inputs`.
+    pub fn new(api: &'a A, inputs: impl IntoIterator<IntoIter = I>, call: F) -> Self {
+        Self {
+            api,
+            inputs: inputs.into_iter(),
+            call,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Sets the maximum number of calls running at once, [`DEFAULT_CONCURRENCY`] by default.
+    ///
+    /// # Panics
+    /// Panics if `concurrency` is zero.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        assert!(
+            concurrency > 0,
+            "a batch has to run at least one call at once"
+        );
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Runs all calls, returning their results in the order of the inputs.
+    ///
+    /// While the rate limit budget is lower than the concurrency, fewer calls run at once and
+    /// other tasks run first before each further call.
+    pub async fn collect(self) -> Vec<ResultType<T>> {
+        let Self {
+            api,
+            inputs,
+            mut call,
+            concurrency,
+        } = self;
+        let mut inputs = inputs.peekable();
+        let mut in_flight = FuturesOrdered::new();
+        let mut results = Vec::new();
+        loop {
+            if inputs.peek().is_some() {
+                let budget = rate_limit::concurrency(api.state(), concurrency);
+                if budget < concurrency {
+                    runtime::yield_now().await;
+                }
+                let started = budget.saturating_sub(in_flight.len());
+                in_flight.extend(inputs.by_ref().take(started).map(|input| call(api, input)));
+            }
+            match in_flight.next().await {
+                Some(result) => results.push(result),
+                None => return results,
+            }
+        }
+    }
+
+    /// Runs all calls, returning their values in the order of the inputs if all succeeded.
+    ///
+    /// # Errors
+    /// Returns a [`BatchError`] listing every failed call.
+    pub async fn try_collect(self) -> Result<Vec<T>, BatchError> {
+        let mut values = Vec::new();
+        let mut failures = Vec::new();
+        for (index, result) in self.collect().await.into_iter().enumerate() {
+            match result {
+                Ok(value) => values.push(value),
+                Err(error) => failures.push(BatchFailure { index, error }),
+            }
+        }
+        if failures.is_empty() {
+            Ok(values)
+        } else {
+            Err(BatchError { failures })
+        }
+    }
+}
+
+/// A single failed call of a [`Batch`].
+#[derive(Debug)]
+pub struct BatchFailure {
+    /// Position of the input within the batch, starting at 0.
+    pub index: usize,
+    /// The error returned by the call.
+    pub error: Error,
+}
+
+/// Error returned by [`Batch::try_collect`] when at least one of the calls failed.
+#[derive(Debug)]
+pub struct BatchError {
+    /// All failed calls, in input order.
+    failures: Vec<BatchFailure>,
+}
+
+impl BatchError {
+    /// Returns all failed calls, in input order.
+    #[must_use]
+    pub fn failures(&self) -> &[BatchFailure] {
+        &self.failures
+    }
+
+    /// Consumes the error, returning all failed calls.
+    #[must_use]
+    pub fn into_failures(self) -> Vec<BatchFailure> {
+        self.failures
+    }
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} batched call(s) failed", self.failures.len())?;
+        for failure in &self.failures {
+            write!(f, "; #{}: {}", failure.index, failure.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.failures
+            .first()
+            .map(|failure| &failure.error as &(dyn std::error::Error + 'static))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{api, rate_limit::TokenBucket, test_server, Api, ApiState};
+
+    api!(struct Todos);
+
+    impl Todos {
+        api! {
+            fn todo(&self, base_url: &str, id: u32) -> Json<u32> {
+                GET "{base_url}/{id}"
+            }
+        }
+    }
+
+    #[test]
+    fn collects_results_in_order() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.path.as_str() {
+                "/3" => test_server::Response::new(404),
+                path => test_server::Response::new(200).json(&path[1..]),
+            })
+            .await;
+            let api = Todos::new();
+
+            let values = api
+                .batch(1..=2, |api, id| api.todo(&base_url, id))
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(values, [1, 2]);
+
+            let results = api
+                .batch(1..=6, |api, id| api.todo(&base_url, id))
+                .concurrency(2)
+                .collect()
+                .await;
+            assert_eq!(results.len(), 6);
+            assert!(results[2].is_err());
+            assert_eq!(*results[5].as_ref().unwrap(), 6);
+
+            let error = api
+                .batch(1..=6, |api, id| api.todo(&base_url, id))
+                .try_collect()
+                .await
+                .unwrap_err();
+            assert_eq!(error.failures().len(), 1);
+            assert_eq!(error.failures()[0].index, 2);
+        });
+    }
+
+    #[test]
+    fn follows_rate_limiter() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                test_server::Response::new(200)
+                    .delay(Duration::from_millis(10))
+                    .json(&request.path[1..])
+            })
+            .await;
+            let api = Todos::builder()
+                .state(ApiState::new().rate_limiter(TokenBucket::new(1000.0, 1)))
+                .build()
+                .unwrap();
+            let (running, most) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+
+            let values = api
+                .batch(1..=4, |api, id| {
+                    let (running, most) = (running.clone(), most.clone());
+                    let base_url = &base_url;
+                    async move {
+                        most.fetch_max(
+                            running.fetch_add(1, Ordering::SeqCst) + 1,
+                            Ordering::SeqCst,
+                        );
+                        let value = api.todo(base_url, id).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        value
+                    }
+                })
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(values, [1, 2, 3, 4]);
+            assert_eq!(most.load(Ordering::SeqCst), 1);
+        });
+    }
+}
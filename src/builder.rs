@@ -0,0 +1,17 @@
+//! Support for the builders generated by the [`api!`](crate::api) macro.
+
+use std::fmt;
+
+/// Error returned when building an api struct without setting one of its fields.
+///
+/// Contains the name of the missing field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingField(pub &'static str);
+
+impl fmt::Display for MissingField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "missing field `{}`", self.0)
+    }
+}
+
+impl std::error::Error for MissingField {}
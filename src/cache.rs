@@ -0,0 +1,1032 @@
+//! HTTP cache following the caching rules of RFC 9111.
+//!
+//! With a [`CacheStore`] set through [`ApiState::cache`](crate::ApiState::cache), successful
+//! `GET` responses are stored by URL. While a stored response is fresh according to its
+//! `Cache-Control`, `Expires` or `Last-Modified` headers, it is returned without sending a
+//! request. Once it is stale, the request is sent with `If-None-Match` and `If-Modified-Since`
+//! taken from its `ETag` and `Last-Modified` headers, and a `304 Not Modified` response is
+//! answered with the stored body.
+//!
+//! Responses with `Cache-Control: no-store` or `Vary: *` are not stored, and `no-cache` ones are
+//! revalidated on every use. Responses are only used for requests sending the same values of the
+//! headers named by their `Vary` header, and are stored separately for every value of the
+//! [`DEFAULT_VARY_HEADERS`], whether the server names them or not, so e.g. responses in different
+//! languages don't replace each other. Headers which don't affect responses, but are named by
+//! `Vary` anyway, are ignored with [`ApiState::cache_ignore_vary`](crate::ApiState::cache_ignore_vary). Requests can bypass the cache with the same directives in their own
+//! `Cache-Control` header. Successful requests with other methods remove the responses stored for
+//! their URL, in all variants. Stale responses with a `stale-while-revalidate` window are used while it lasts and
+//! revalidated in the background, unless the instance sends or records requests in ways a
+//! background task can't, see [`CacheMode::StaleWhileRevalidate`].
+//!
+//! A [`CacheMode`] changes how requests are answered, e.g. to work offline with
+//! [`CacheMode::OnlyIfCached`]. It is set for all requests of an instance with
+//! [`ApiState::cache_mode`](crate::ApiState::cache_mode), for those of an endpoint with
+//! `with { cache: only_if_cached }`, or for a single call with [`CacheMode::apply`] in the `_with`
+//! method generated for its endpoint. Endpoints declared e.g. `with { cache: 5m }` have their
+//! responses stored and fresh for that long if their headers don't say otherwise:
+//! ```rust
+//! use api_client::{api, cache::CacheMode, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn motd() -> String {
+//!            GET "https://example.com/motd"
+//!         }
+//!     }
+//! }
+//!
+//! async fn offline_motd(api: &ExampleApi) -> ResultType<String> {
+//!     api.motd_with(|request| CacheMode::OnlyIfCached.apply(request))
+//!         .await
+//! }
+//! ```
+//!
+//! Responses are kept in memory by a [`MemoryCache`] or on disk by a [`DiskCache`], which
+//! survives restarts. Other backends, such as Redis, implement [`CacheStore`] and can keep
+//! responses encoded with [`CachedResponse::to_bytes`].
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, cache::MemoryCache, ApiState};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().cache(MemoryCache::new(256)))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use reqwest::{
+    header::{
+        HeaderMap, HeaderName, HeaderValue, AGE, AUTHORIZATION, CACHE_CONTROL, DATE, ETAG, EXPIRES,
+        IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, VARY,
+    },
+    Method, ResponseBuilderExt, StatusCode, Url,
+};
+
+use crate::{runtime::Runtime, Error, RequestBuilder, ResultType};
+
+mod disk;
+
+pub use disk::DiskCache;
+
+/// First line of [`CachedResponse::to_bytes`], identifying the version of the encoding.
+const FORMAT: &str = "api-client-cache 1";
+
+/// Request headers which select the stored response for a URL, see [`cache`](crate::cache).
+///
+/// The values of `Accept` and `Accept-Language` are part of the keys of responses, while for
+/// `Authorization` only its presence is, as the server names it with `Vary` when its value
+/// matters.
+pub const DEFAULT_VARY_HEADERS: &[&str] = &["accept", "accept-language", "authorization"];
+
+/// How the cache answers a request.
+///
+/// Selected for a single request by a directive in its `Cache-Control` header, see
+/// [`CacheMode::apply`], or else by [`ApiState::cache_mode`](crate::ApiState::cache_mode).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheMode {
+    /// Uses fresh responses and revalidates stale ones, following their caching headers.
+    #[default]
+    Default,
+    /// Only uses stored responses, fresh or stale, and fails with [`Error::NotCached`] instead
+    /// of sending a request if there is none. Selected by `only-if-cached`.
+    OnlyIfCached,
+    /// Uses stale responses right away and revalidates them in the background, so a later
+    /// request gets the fresh one. Selected by `stale-while-revalidate`.
+    ///
+    /// Background revalidations are sent with the client of the instance alone, so they follow
+    /// its URL policy, but not its limits or circuit breaker. Instances with interceptors, a
+    /// transport, a capture buffer, a HAR recorder or request logging, which the background task
+    /// can't run or would be missing from, revalidate stale responses through them before they
+    /// are used instead.
+    StaleWhileRevalidate,
+    /// Never uses stored responses without sending a request, which revalidates or replaces
+    /// them. Selected by `no-cache`.
+    ForceRefresh,
+}
+
+impl CacheMode {
+    /// Selects this mode for `request` by adding its directive to the `Cache-Control` header,
+    /// e.g. in the `_with` method generated for an endpoint.
+    pub fn apply(self, request: RequestBuilder) -> RequestBuilder {
+        let directive = match self {
+            CacheMode::Default => return request,
+            CacheMode::OnlyIfCached => "only-if-cached",
+            CacheMode::StaleWhileRevalidate => "stale-while-revalidate",
+            CacheMode::ForceRefresh => "no-cache",
+        };
+        request.header(CACHE_CONTROL, directive)
+    }
+
+    /// Returns the mode selected by the `Cache-Control` directives of a request, or `default`.
+    fn select(request: &CacheControl, default: Self) -> Self {
+        if request.only_if_cached {
+            CacheMode::OnlyIfCached
+        } else if request.no_cache {
+            CacheMode::ForceRefresh
+        } else if request.stale_while_revalidate.is_some() {
+            CacheMode::StaleWhileRevalidate
+        } else {
+            default
+        }
+    }
+}
+
+/// Storage of the responses kept by the HTTP cache.
+///
+/// Implement this to keep responses somewhere else than in memory, e.g. on disk or in a shared
+/// cache. Keys are the URLs of the requests, followed by a space and the values of the
+/// [`DEFAULT_VARY_HEADERS`] they send, if any.
+#[async_trait::async_trait]
+pub trait CacheStore: fmt::Debug + Send + Sync {
+    /// Returns the response stored for `key`, if any.
+    async fn get(&self, key: &str) -> Option<CachedResponse>;
+
+    /// Stores `response` for `key`, replacing any previous one.
+    async fn put(&self, key: String, response: CachedResponse);
+
+    /// Removes the response stored for `key`, if any.
+    async fn remove(&self, key: &str);
+
+    /// Removes the responses stored for `url` in all variants: the one for the key `url` and the
+    /// ones for keys starting with `url` followed by a space.
+    async fn remove_url(&self, url: &str);
+}
+
+#[async_trait::async_trait]
+impl<S: CacheStore + ?Sized> CacheStore for Arc<S> {
+    async fn get(&self, key: &str) -> Option<CachedResponse> {
+        (**self).get(key).await
+    }
+
+    async fn put(&self, key: String, response: CachedResponse) {
+        (**self).put(key, response).await;
+    }
+
+    async fn remove(&self, key: &str) {
+        (**self).remove(key).await;
+    }
+
+    async fn remove_url(&self, url: &str) {
+        (**self).remove_url(url).await;
+    }
+}
+
+/// A response kept by a [`CacheStore`].
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    /// Status of the response.
+    pub status: StatusCode,
+    /// Headers of the response, updated by revalidations.
+    pub headers: HeaderMap,
+    /// Final URL of the response.
+    pub url: Url,
+    /// Whole body of the response.
+    pub body: Bytes,
+    /// Request headers named by the `Vary` header of the response, as sent with the request.
+    pub vary: HeaderMap,
+    /// Point in time at which the response was received or last revalidated.
+    pub stored_at: SystemTime,
+}
+
+impl CachedResponse {
+    /// Returns whether the response may be used without revalidating it, given the
+    /// `Cache-Control` directives of the request.
+    #[must_use]
+    pub fn is_fresh(&self, request: &HeaderMap) -> bool {
+        let request = CacheControl::parse(request);
+        let response = CacheControl::parse(&self.headers);
+        if request.no_cache || response.no_cache {
+            return false;
+        }
+        let age = self.age();
+        let lifetime = match request.max_age {
+            Some(max_age) => self.freshness_lifetime().min(max_age),
+            None => self.freshness_lifetime(),
+        };
+        age < lifetime
+    }
+
+    /// Returns how long the response is fresh after it was generated.
+    ///
+    /// This is its `max-age`, or the time from its `Date` to its `Expires` header, or a tenth of
+    /// the time since it was last modified.
+    #[must_use]
+    pub fn freshness_lifetime(&self) -> Duration {
+        if let Some(max_age) = CacheControl::parse(&self.headers).max_age {
+            return max_age;
+        }
+        let generated = date(&self.headers, DATE).unwrap_or(self.stored_at);
+        if self.headers.contains_key(EXPIRES) {
+            // Invalid dates such as `0` mean the response has already expired.
+            return date(&self.headers, EXPIRES)
+                .and_then(|expires| expires.duration_since(generated).ok())
+                .unwrap_or_default();
+        }
+        date(&self.headers, LAST_MODIFIED)
+            .and_then(|modified| generated.duration_since(modified).ok())
+            .map_or(Duration::ZERO, |modified| modified / 10)
+    }
+
+    /// Returns how long ago the response was generated, including its `Age` header.
+    #[must_use]
+    pub fn age(&self) -> Duration {
+        let received = self
+            .headers
+            .get(AGE)
+            .and_then(|age| age.to_str().ok()?.trim().parse().ok())
+            .map_or(Duration::ZERO, Duration::from_secs);
+        received + self.stored_at.elapsed().unwrap_or_default()
+    }
+
+    /// Returns whether the response was received for a request with the same headers as
+    /// `request`, as far as they are named by its `Vary` header.
+    #[must_use]
+    pub fn matches(&self, request: &HeaderMap) -> bool {
+        self.matches_ignoring(request, &[])
+    }
+
+    /// Same as [`CachedResponse::matches`], but ignoring the headers named in `ignored`.
+    fn matches_ignoring(&self, request: &HeaderMap, ignored: &[String]) -> bool {
+        vary(&self.headers)
+            .filter(|name| !ignored.iter().any(|ignored| ignored == name.as_str()))
+            .all(|name| {
+                request
+                    .get_all(&name)
+                    .iter()
+                    .eq(self.vary.get_all(&name).iter())
+            })
+    }
+
+    /// Encodes the response, e.g. to keep it in an external store.
+    ///
+    /// The encoding is a text header followed by the raw body, see [`CachedResponse::from_bytes`].
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let stored_at = self
+            .stored_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let mut bytes = format!(
+            "{FORMAT}\n{}\n{}\n{stored_at}\n",
+            self.status.as_u16(),
+            self.url
+        )
+        .into_bytes();
+        for (prefix, headers) in [("h", &self.headers), ("v", &self.vary)] {
+            for (name, value) in headers {
+                bytes.extend_from_slice(format!("{prefix} {name}: ").as_bytes());
+                bytes.extend_from_slice(value.as_bytes());
+                bytes.push(b'\n');
+            }
+        }
+        bytes.push(b'\n');
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+
+    /// Decodes a response encoded with [`CachedResponse::to_bytes`].
+    ///
+    /// Returns `None` if `bytes` is not a valid encoding.
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut rest = bytes;
+        let mut text = || std::str::from_utf8(next_line(&mut rest)?).ok();
+        if text()? != FORMAT {
+            return None;
+        }
+        let status = StatusCode::from_u16(text()?.parse().ok()?).ok()?;
+        let url = Url::parse(text()?).ok()?;
+        let stored_at = UNIX_EPOCH + Duration::from_millis(text()?.parse().ok()?);
+
+        let (mut headers, mut vary) = (HeaderMap::new(), HeaderMap::new());
+        loop {
+            let line = next_line(&mut rest)?;
+            if line.is_empty() {
+                break;
+            }
+            let target = match line.get(..2)? {
+                b"h " => &mut headers,
+                b"v " => &mut vary,
+                _ => return None,
+            };
+            let separator = line.windows(2).position(|pair| pair == b": ")?;
+            target.append(
+                HeaderName::from_bytes(&line[2..separator]).ok()?,
+                HeaderValue::from_bytes(&line[separator + 2..]).ok()?,
+            );
+        }
+
+        Some(Self {
+            status,
+            headers,
+            url,
+            body: Bytes::copy_from_slice(rest),
+            vary,
+            stored_at,
+        })
+    }
+
+    /// Reads the whole body of `response`, sent with the headers `request`.
+    async fn read(response: reqwest::Response, request: &HeaderMap) -> ResultType<Self> {
+        let headers = response.headers().clone();
+        let mut vary_headers = HeaderMap::new();
+        for name in vary(&headers) {
+            for value in request.get_all(&name) {
+                vary_headers.append(name.clone(), value.clone());
+            }
+        }
+        Ok(Self {
+            status: response.status(),
+            url: response.url().clone(),
+            body: response.bytes().await?,
+            headers,
+            vary: vary_headers,
+            stored_at: SystemTime::now(),
+        })
+    }
+
+    /// Returns whether the stale response may be used while it is revalidated in the
+    /// background, in `mode`.
+    fn may_serve_stale(&self, mode: CacheMode) -> bool {
+        let directives = CacheControl::parse(&self.headers);
+        match (mode, directives.stale_while_revalidate) {
+            (CacheMode::StaleWhileRevalidate, _) => true,
+            (CacheMode::Default, Some(window)) if !directives.no_cache => {
+                self.age() < self.freshness_lifetime().saturating_add(window)
+            }
+            _ => false,
+        }
+    }
+
+    /// Makes the response fresh for `lifetime`, if any, unless its headers set how long it is
+    /// fresh, by adding the `max-age` directive a server would have sent.
+    fn default_lifetime(&mut self, lifetime: Option<Duration>) {
+        let explicit = CacheControl::parse(&self.headers).max_age.is_some()
+            || self.headers.contains_key(EXPIRES);
+        if let (Some(lifetime), false) = (lifetime, explicit) {
+            let directive = format!("max-age={}", lifetime.as_secs());
+            if let Ok(directive) = HeaderValue::try_from(directive) {
+                self.headers.append(CACHE_CONTROL, directive);
+            }
+        }
+    }
+
+    /// Updates the headers with the ones of a `304 Not Modified` response.
+    fn revalidate(&mut self, headers: &HeaderMap) {
+        for name in headers.keys() {
+            self.headers.remove(name);
+            for value in headers.get_all(name) {
+                self.headers.append(name.clone(), value.clone());
+            }
+        }
+        self.stored_at = SystemTime::now();
+    }
+
+    /// Creates a response returning the stored body.
+    fn to_response(&self) -> reqwest::Response {
+        let mut response = http::Response::builder()
+            .status(self.status)
+            .url(self.url.clone())
+            .body(self.body.clone())
+            .expect("the parts of a received response are valid");
+        *response.headers_mut() = self.headers.clone();
+        reqwest::Response::from(response)
+    }
+}
+
+/// In-memory [`CacheStore`] keeping a bounded number of responses, evicting the least recently
+/// used one.
+#[derive(Debug)]
+pub struct MemoryCache {
+    /// Maximum number of stored responses.
+    capacity: usize,
+    /// Stored responses, from the least to the most recently used.
+    entries: Mutex<VecDeque<(String, CachedResponse)>>,
+}
+
+impl MemoryCache {
+    /// Creates an empty cache keeping at most `capacity` responses.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "a cache has to keep at least one response");
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns the number of stored responses.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Returns whether no responses are stored.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all stored responses.
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheStore for MemoryCache {
+    async fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = entries.iter().position(|(stored, _)| stored == key)?;
+        let entry = entries.remove(index)?;
+        let response = entry.1.clone();
+        entries.push_back(entry);
+        Some(response)
+    }
+
+    async fn put(&self, key: String, response: CachedResponse) {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.retain(|(stored, _)| *stored != key);
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back((key, response));
+    }
+
+    async fn remove(&self, key: &str) {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|(stored, _)| stored != key);
+    }
+
+    async fn remove_url(&self, url: &str) {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|(stored, _)| !is_variant(stored, url));
+    }
+}
+
+/// Sends `request` with `send` through the cache in `store`, in `mode` unless the request
+/// selects another one, ignoring the `Vary` headers in `ignored`. Responses whose headers don't
+/// say how long they are fresh are fresh for `lifetime`, if any. Background revalidations are
+/// spawned on the `background` runtime, without it stale responses are revalidated with `send`
+/// before they are used.
+pub(crate) async fn send<F, Fut>(
+    store: &Arc<dyn CacheStore>,
+    mode: CacheMode,
+    ignored: &[String],
+    lifetime: Option<Duration>,
+    background: Option<&dyn Runtime>,
+    request: RequestBuilder,
+    send: F,
+) -> ResultType<reqwest::Response>
+where
+    F: FnOnce(RequestBuilder) -> Fut,
+    Fut: Future<Output = ResultType<reqwest::Response>>,
+{
+    let Some(inspected) = request.try_clone().and_then(|request| request.build().ok()) else {
+        return send(request).await;
+    };
+    let url = inspected.url().to_string();
+    let headers = inspected.headers();
+    let key = key(&url, headers, ignored);
+
+    if inspected.method() != Method::GET {
+        let response = send(request).await?;
+        if response.status().is_success() || response.status().is_redirection() {
+            // The change affects the resource, not only the variant the request would select.
+            store.remove_url(&url).await;
+        }
+        return Ok(response);
+    }
+    let directives = CacheControl::parse(headers);
+    if directives.no_store {
+        return send(request).await;
+    }
+    let mode = CacheMode::select(&directives, mode);
+
+    let cached = store
+        .get(&key)
+        .await
+        .filter(|cached| cached.matches_ignoring(headers, ignored));
+    if mode == CacheMode::OnlyIfCached {
+        return cached
+            .map(|cached| cached.to_response())
+            .ok_or(Error::NotCached(url));
+    }
+    let mut request = request;
+    if let Some(cached) = &cached {
+        if mode != CacheMode::ForceRefresh && cached.is_fresh(headers) {
+            return Ok(cached.to_response());
+        }
+        if let Some(etag) = cached.headers.get(ETAG) {
+            request = request.header(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(modified) = cached.headers.get(LAST_MODIFIED) {
+            request = request.header(IF_MODIFIED_SINCE, modified.clone());
+        }
+        let runtime = background.filter(|_| cached.may_serve_stale(mode));
+        if let Some(runtime) = runtime {
+            if let Some(revalidation) = request.try_clone() {
+                let stale = cached.to_response();
+                let (store, cached) = (Arc::clone(store), cached.clone());
+                let headers = headers.clone();
+                let background = (key, cached, headers, lifetime);
+                revalidate_in_background(runtime, store, background, revalidation);
+                return Ok(stale);
+            }
+        }
+    }
+
+    let response = send(request).await?;
+    match cached {
+        Some(mut cached) if response.status() == StatusCode::NOT_MODIFIED => {
+            cached.revalidate(response.headers());
+            cached.default_lifetime(lifetime);
+            let response = cached.to_response();
+            store.put(key, cached).await;
+            Ok(response)
+        }
+        _ if is_storable(&response, lifetime) => {
+            let mut cached = CachedResponse::read(response, headers).await?;
+            cached.default_lifetime(lifetime);
+            let response = cached.to_response();
+            store.put(key, cached).await;
+            Ok(response)
+        }
+        _ => Ok(response),
+    }
+}
+
+/// Sends the conditional `request` for the stale `cached` response in a background task and
+/// stores the result, see [`CacheMode::StaleWhileRevalidate`].
+///
+/// Failed revalidations are ignored and leave the stale response in place.
+fn revalidate_in_background(
+    runtime: &dyn Runtime,
+    store: Arc<dyn CacheStore>,
+    (key, mut cached, headers, lifetime): (String, CachedResponse, HeaderMap, Option<Duration>),
+    request: RequestBuilder,
+) {
+    crate::trace::revalidation(&key);
+    runtime.spawn(Box::pin(async move {
+        let Ok(response) = request.send().await else {
+            return;
+        };
+        if response.status() == StatusCode::NOT_MODIFIED {
+            cached.revalidate(response.headers());
+            cached.default_lifetime(lifetime);
+            store.put(key, cached).await;
+        } else if is_storable(&response, lifetime) {
+            if let Ok(mut fresh) = CachedResponse::read(response, &headers).await {
+                fresh.default_lifetime(lifetime);
+                store.put(key, fresh).await;
+            }
+        }
+    }));
+}
+
+/// Returns whether a response to a `GET` request may and should be stored, which responses
+/// without validators or a lifetime only should if their endpoint declares a `lifetime`.
+fn is_storable(response: &reqwest::Response, lifetime: Option<Duration>) -> bool {
+    let headers = response.headers();
+    let cacheable_status = matches!(
+        response.status().as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    );
+    let has_validator = headers.contains_key(ETAG) || headers.contains_key(LAST_MODIFIED);
+    let has_lifetime =
+        headers.contains_key(EXPIRES) || CacheControl::parse(headers).max_age.is_some();
+    let varies_always = headers
+        .get_all(VARY)
+        .iter()
+        .any(|value| value.to_str().map_or(true, |value| value.contains('*')));
+
+    cacheable_status
+        && !CacheControl::parse(headers).no_store
+        && !varies_always
+        && (has_validator || has_lifetime || lifetime.is_some())
+}
+
+/// Returns the key of the response to a request for `url` sending `headers`: the URL, followed by
+/// the [`DEFAULT_VARY_HEADERS`] which are sent and not `ignored`.
+fn key(url: &str, headers: &HeaderMap, ignored: &[String]) -> String {
+    let mut parts = vec![url.to_string()];
+    for &name in DEFAULT_VARY_HEADERS {
+        if ignored.iter().any(|ignored| ignored == name) {
+            continue;
+        }
+        if name == AUTHORIZATION.as_str() {
+            if headers.contains_key(AUTHORIZATION) {
+                parts.push(name.to_string());
+            }
+            continue;
+        }
+        for value in headers.get_all(name) {
+            parts.push(format!("{name}={value:?}"));
+        }
+    }
+    parts.join(" ")
+}
+
+/// Returns whether `key` is the key of a variant of the response for `url`, see [`key`].
+fn is_variant(key: &str, url: &str) -> bool {
+    key.strip_prefix(url)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+}
+
+/// Returns the header names listed in the `Vary` header.
+fn vary(headers: &HeaderMap) -> impl Iterator<Item = HeaderName> + '_ {
+    headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+}
+
+/// Splits the first line off `bytes`, returning `None` if there is no line break.
+fn next_line<'a>(bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let end = bytes.iter().position(|&byte| byte == b'\n')?;
+    let line = &bytes[..end];
+    *bytes = &bytes[end + 1..];
+    Some(line)
+}
+
+/// Parses an HTTP date header.
+fn date(headers: &HeaderMap, name: HeaderName) -> Option<SystemTime> {
+    httpdate::parse_http_date(headers.get(name)?.to_str().ok()?).ok()
+}
+
+/// Directives of a `Cache-Control` header relevant to a private cache.
+#[derive(Debug, Default)]
+struct CacheControl {
+    /// Responses must not be stored.
+    no_store: bool,
+    /// Stored responses have to be revalidated before they are used.
+    no_cache: bool,
+    /// Maximum age of a stored response which may be used.
+    max_age: Option<Duration>,
+    /// Only stored responses may be used, in requests.
+    only_if_cached: bool,
+    /// How long a stale response may be used while it is revalidated, unlimited in requests.
+    stale_while_revalidate: Option<Duration>,
+}
+
+impl CacheControl {
+    /// Parses the `Cache-Control` headers in `headers`.
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut directives = Self::default();
+        let values = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for directive in values {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name, Some(argument.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            match (name.trim().to_ascii_lowercase().as_str(), argument) {
+                ("no-store", _) => directives.no_store = true,
+                ("no-cache", _) => directives.no_cache = true,
+                ("max-age", Some(seconds)) => {
+                    directives.max_age = seconds.parse().ok().map(Duration::from_secs);
+                }
+                ("only-if-cached", _) => directives.only_if_cached = true,
+                ("stale-while-revalidate", Some(seconds)) => {
+                    directives.stale_while_revalidate =
+                        seconds.parse().ok().map(Duration::from_secs);
+                }
+                ("stale-while-revalidate", None) => {
+                    directives.stale_while_revalidate = Some(Duration::MAX);
+                }
+                _ => {}
+            }
+        }
+        directives
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::{CacheMode, MemoryCache};
+    use crate::{
+        api,
+        interceptor::{Interceptor, Next},
+        test_server, ApiState, Error, ResultType,
+    };
+
+    /// Interceptor signing requests, without which the server rejects them.
+    #[derive(Debug)]
+    struct Signing;
+
+    #[async_trait::async_trait(?Send)]
+    impl Interceptor for Signing {
+        async fn intercept(
+            &self,
+            mut request: reqwest::Request,
+            next: Next<'_>,
+        ) -> ResultType<reqwest::Response> {
+            let signature = reqwest::header::HeaderValue::from_static("1");
+            request.headers_mut().insert("x-signature", signature);
+            next.run(request).await
+        }
+    }
+
+    api!(struct Cached);
+
+    impl Cached {
+        api! {
+            fn get(&self, base_url: &str, path: &str) -> String {
+                GET "{base_url}/{path}"
+            }
+
+            fn update(&self, base_url: &str, path: &str) -> StatusCode {
+                PUT "{base_url}/{path}"
+            }
+
+            fn create(&self, base_url: &str, path: &str) -> StatusCode {
+                POST "{base_url}/{path}"
+            }
+
+            fn kept(&self, base_url: &str, path: &str) -> String {
+                GET "{base_url}/{path}"
+                with { cache: 5m }
+            }
+
+            fn refreshed(&self, base_url: &str, path: &str) -> String {
+                GET "{base_url}/{path}"
+                with { cache: force_refresh }
+            }
+        }
+    }
+
+    #[test]
+    fn serves_fresh_and_revalidated_responses() {
+        tokio_test::block_on(async {
+            let hits = Arc::new(AtomicUsize::new(0));
+            let server_hits = hits.clone();
+            let base_url = test_server::serve(move |request| {
+                server_hits.fetch_add(1, Ordering::SeqCst);
+                match (request.path.as_str(), request.header("if-none-match")) {
+                    ("/fresh", _) => test_server::Response::new(200)
+                        .header("cache-control", "max-age=60")
+                        .body("fresh"),
+                    ("/etag", Some("\"v1\"")) => test_server::Response::new(304),
+                    ("/etag", _) => test_server::Response::new(200)
+                        .header("cache-control", "no-cache")
+                        .header("etag", "\"v1\"")
+                        .body("tagged"),
+                    _ => test_server::Response::new(200)
+                        .header("cache-control", "no-store")
+                        .body("private"),
+                }
+            })
+            .await;
+            let cache = Arc::new(MemoryCache::new(8));
+            let api = Cached::builder()
+                .state(ApiState::new().cache(cache.clone()))
+                .build()
+                .unwrap();
+
+            assert_eq!(api.get(&base_url, "fresh").await.unwrap(), "fresh");
+            assert_eq!(api.get(&base_url, "fresh").await.unwrap(), "fresh");
+            assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+            assert_eq!(api.get(&base_url, "etag").await.unwrap(), "tagged");
+            assert_eq!(api.get(&base_url, "etag").await.unwrap(), "tagged");
+            assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+            assert_eq!(api.get(&base_url, "secret").await.unwrap(), "private");
+            assert_eq!(api.get(&base_url, "secret").await.unwrap(), "private");
+            assert_eq!(hits.load(Ordering::SeqCst), 5);
+            assert_eq!(cache.len(), 2);
+
+            api.update(&base_url, "fresh").await.unwrap();
+            assert_eq!(cache.len(), 1);
+            assert_eq!(api.get(&base_url, "fresh").await.unwrap(), "fresh");
+            assert_eq!(hits.load(Ordering::SeqCst), 7);
+        });
+    }
+
+    #[test]
+    fn applies_endpoint_options() {
+        tokio_test::block_on(async {
+            let hits = Arc::new(AtomicUsize::new(0));
+            let server_hits = hits.clone();
+            let base_url = test_server::serve(move |request| {
+                server_hits.fetch_add(1, Ordering::SeqCst);
+                match request.path.as_str() {
+                    "/plain" => test_server::Response::new(200).body("plain"),
+                    _ => test_server::Response::new(200)
+                        .header("cache-control", "max-age=60")
+                        .body("fresh"),
+                }
+            })
+            .await;
+            let api = Cached::builder()
+                .state(ApiState::new().cache(MemoryCache::new(8)))
+                .build()
+                .unwrap();
+
+            assert_eq!(api.get(&base_url, "plain").await.unwrap(), "plain");
+            assert_eq!(api.kept(&base_url, "plain").await.unwrap(), "plain");
+            assert_eq!(api.kept(&base_url, "plain").await.unwrap(), "plain");
+            assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+            assert_eq!(api.get(&base_url, "fresh").await.unwrap(), "fresh");
+            assert_eq!(api.refreshed(&base_url, "fresh").await.unwrap(), "fresh");
+            assert_eq!(api.get(&base_url, "fresh").await.unwrap(), "fresh");
+            assert_eq!(hits.load(Ordering::SeqCst), 4);
+        });
+    }
+
+    #[test]
+    fn answers_requests_in_cache_modes() {
+        tokio_test::block_on(async {
+            let version = Arc::new(AtomicUsize::new(1));
+            let server_version = version.clone();
+            let base_url = test_server::serve(move |_| {
+                test_server::Response::new(200)
+                    .header("cache-control", "max-age=0")
+                    .body(server_version.load(Ordering::SeqCst).to_string())
+            })
+            .await;
+            let cache = Arc::new(MemoryCache::new(8));
+            let api = |mode| {
+                Cached::builder()
+                    .state(ApiState::new().cache(cache.clone()).cache_mode(mode))
+                    .build()
+                    .unwrap()
+            };
+
+            let offline = api(CacheMode::OnlyIfCached);
+            let error = offline.get(&base_url, "a").await.unwrap_err();
+            assert!(matches!(error, Error::NotCached(_)));
+
+            assert_eq!(
+                api(CacheMode::Default).get(&base_url, "a").await.unwrap(),
+                "1"
+            );
+            version.store(2, Ordering::SeqCst);
+            assert_eq!(offline.get(&base_url, "a").await.unwrap(), "1");
+
+            let stale = api(CacheMode::StaleWhileRevalidate);
+            assert_eq!(stale.get(&base_url, "a").await.unwrap(), "1");
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            assert_eq!(offline.get(&base_url, "a").await.unwrap(), "2");
+
+            version.store(3, Ordering::SeqCst);
+            let refresh = api(CacheMode::ForceRefresh);
+            assert_eq!(refresh.get(&base_url, "a").await.unwrap(), "3");
+            assert_eq!(offline.get(&base_url, "a").await.unwrap(), "3");
+        });
+    }
+
+    #[test]
+    fn revalidates_through_interceptors() {
+        tokio_test::block_on(async {
+            let version = Arc::new(AtomicUsize::new(1));
+            let server_version = version.clone();
+            let base_url = test_server::serve(move |request| {
+                if request.header("x-signature").is_none() {
+                    return test_server::Response::new(401);
+                }
+                test_server::Response::new(200)
+                    .header("cache-control", "max-age=0")
+                    .body(server_version.load(Ordering::SeqCst).to_string())
+            })
+            .await;
+            let api = Cached::builder()
+                .state(
+                    ApiState::new()
+                        .cache(MemoryCache::new(8))
+                        .cache_mode(CacheMode::StaleWhileRevalidate)
+                        .interceptor(Signing),
+                )
+                .build()
+                .unwrap();
+
+            assert_eq!(api.get(&base_url, "a").await.unwrap(), "1");
+            version.store(2, Ordering::SeqCst);
+            assert_eq!(api.get(&base_url, "a").await.unwrap(), "2");
+        });
+    }
+
+    /// Gets `/a` in `language`, with the `X-Request-Id` `id`.
+    async fn get(api: &Cached, base_url: &str, language: &str, id: &str) -> String {
+        api.get_with(base_url, "a", |request| {
+            request
+                .header("accept-language", language)
+                .header("x-request-id", id)
+        })
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn stores_variants_by_headers() {
+        tokio_test::block_on(async {
+            let hits = Arc::new(AtomicUsize::new(0));
+            let server_hits = hits.clone();
+            let base_url = test_server::serve(move |request| {
+                server_hits.fetch_add(1, Ordering::SeqCst);
+                let language = request.header("accept-language").unwrap_or("en");
+                test_server::Response::new(200)
+                    .header("cache-control", "max-age=60")
+                    .header("vary", "x-request-id")
+                    .body(language)
+            })
+            .await;
+            let cache = Arc::new(MemoryCache::new(8));
+            let api = Cached::builder()
+                .state(ApiState::new().cache(cache.clone()))
+                .build()
+                .unwrap();
+
+            assert_eq!(get(&api, &base_url, "de", "1").await, "de");
+            assert_eq!(get(&api, &base_url, "fr", "1").await, "fr");
+            assert_eq!(get(&api, &base_url, "de", "1").await, "de");
+            assert_eq!(get(&api, &base_url, "fr", "1").await, "fr");
+            assert_eq!((hits.load(Ordering::SeqCst), cache.len()), (2, 2));
+            assert_eq!(get(&api, &base_url, "de", "2").await, "de");
+            assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+            let api = Cached::builder()
+                .state(
+                    ApiState::new()
+                        .cache(cache.clone())
+                        .cache_ignore_vary("X-Request-Id")
+                        .cache_ignore_vary("accept-language"),
+                )
+                .build()
+                .unwrap();
+            assert_eq!(get(&api, &base_url, "it", "3").await, "it");
+            assert_eq!(get(&api, &base_url, "es", "4").await, "it");
+            assert_eq!(hits.load(Ordering::SeqCst), 4);
+        });
+    }
+    #[test]
+    fn invalidates_all_variants() {
+        tokio_test::block_on(async {
+            let hits = Arc::new(AtomicUsize::new(0));
+            let server_hits = hits.clone();
+            let base_url = test_server::serve(move |request| {
+                server_hits.fetch_add(1, Ordering::SeqCst);
+                let language = request.header("accept-language").unwrap_or("en");
+                test_server::Response::new(200)
+                    .header("cache-control", "max-age=60")
+                    .body(language)
+            })
+            .await;
+            let cache = Arc::new(MemoryCache::new(8));
+            let api = Cached::builder()
+                .state(ApiState::new().cache(cache.clone()))
+                .build()
+                .unwrap();
+
+            assert_eq!(get(&api, &base_url, "de", "1").await, "de");
+            assert_eq!(get(&api, &base_url, "fr", "1").await, "fr");
+            assert_eq!(api.get(&base_url, "ab").await.unwrap(), "en");
+            assert_eq!((hits.load(Ordering::SeqCst), cache.len()), (3, 3));
+
+            api.create(&base_url, "a").await.unwrap();
+            assert_eq!(cache.len(), 1);
+            assert_eq!(get(&api, &base_url, "de", "1").await, "de");
+            assert_eq!(get(&api, &base_url, "fr", "1").await, "fr");
+            assert_eq!(hits.load(Ordering::SeqCst), 6);
+        });
+    }
+}
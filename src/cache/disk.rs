@@ -0,0 +1,244 @@
+//! [`CacheStore`] keeping responses in files, see [`DiskCache`].
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use tokio::io::AsyncBufReadExt;
+
+use super::{is_variant, CacheStore, CachedResponse};
+
+/// [`CacheStore`] keeping one file per response in a directory, so the cache survives restarts.
+///
+/// Files are written atomically and shared safely by several instances using the same
+/// directory. With [`DiskCache::max_size`], the least recently stored responses are removed
+/// whenever the directory grows beyond the limit. I/O errors are not reported, they only cause
+/// responses to be fetched again.
+/// ```rust
+/// use api_client::{api, cache::DiskCache, ApiState};
+///
+/// api!(pub struct ExampleApi);
+///
+/// let cache = DiskCache::new(std::env::temp_dir().join("example-api")).max_size(64 << 20);
+/// let api = ExampleApi::builder()
+///     .state(ApiState::new().cache(cache))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct DiskCache {
+    /// Directory holding the files.
+    directory: PathBuf,
+    /// Maximum total size of the files in bytes, if any.
+    max_size: Option<u64>,
+}
+
+impl DiskCache {
+    /// Creates a cache keeping its files in `directory`, which is created when needed.
+    #[must_use]
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            max_size: None,
+        }
+    }
+
+    /// Limits the total size of the stored files to `bytes`, by default there is no limit.
+    #[must_use]
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Returns the directory holding the files.
+    #[must_use]
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Removes all stored responses.
+    ///
+    /// # Errors
+    /// Returns an error if the files could not be removed.
+    pub async fn clear(&self) -> io::Result<()> {
+        for (path, ..) in self.files().await? {
+            remove_file(&path).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the file for `key`.
+    ///
+    /// File names are FNV-1a hashes of the keys, which are stable across builds. The key is
+    /// stored in the file as well to detect collisions.
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        self.directory.join(format!("{hash:016x}.response"))
+    }
+
+    /// Returns the path, modification time and size of all stored files.
+    async fn files(&self) -> io::Result<Vec<(PathBuf, SystemTime, u64)>> {
+        let mut files = Vec::new();
+        let mut entries = match tokio::fs::read_dir(&self.directory).await {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(files),
+            Err(error) => return Err(error),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "response")
+            {
+                let metadata = entry.metadata().await?;
+                files.push((path, metadata.modified()?, metadata.len()));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Removes the oldest files until the total size is within the limit.
+    async fn evict(&self) -> io::Result<()> {
+        let Some(max_size) = self.max_size else {
+            return Ok(());
+        };
+        let mut files = self.files().await?;
+        let mut size: u64 = files.iter().map(|(.., size)| size).sum();
+        files.sort_by_key(|(_, modified, _)| *modified);
+        for (path, _, file_size) in files {
+            if size <= max_size {
+                break;
+            }
+            remove_file(&path).await?;
+            size = size.saturating_sub(file_size);
+        }
+        Ok(())
+    }
+
+    /// Writes `response` for `key`, through a temporary file so readers never see partial files.
+    async fn write(&self, key: &str, response: &CachedResponse) -> io::Result<()> {
+        tokio::fs::create_dir_all(&self.directory).await?;
+        let path = self.path(key);
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        let mut contents = format!("{key}\n").into_bytes();
+        contents.extend_from_slice(&response.to_bytes());
+        tokio::fs::write(&temporary, contents).await?;
+        tokio::fs::rename(&temporary, &path).await
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheStore for DiskCache {
+    async fn get(&self, key: &str) -> Option<CachedResponse> {
+        let contents = tokio::fs::read(self.path(key)).await.ok()?;
+        let separator = contents.iter().position(|&byte| byte == b'\n')?;
+        if &contents[..separator] != key.as_bytes() {
+            return None;
+        }
+        CachedResponse::from_bytes(&contents[separator + 1..])
+    }
+
+    async fn put(&self, key: String, response: CachedResponse) {
+        if self.write(&key, &response).await.is_ok() {
+            self.evict().await.ok();
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        remove_file(&self.path(key)).await.ok();
+    }
+
+    async fn remove_url(&self, url: &str) {
+        for (path, ..) in self.files().await.unwrap_or_default() {
+            if stored_key(&path)
+                .await
+                .is_some_and(|key| is_variant(&key, url))
+            {
+                remove_file(&path).await.ok();
+            }
+        }
+    }
+}
+
+/// Reads the key stored in the first line of the file at `path`.
+async fn stored_key(path: &Path) -> Option<String> {
+    let file = tokio::fs::File::open(path).await.ok()?;
+    let mut key = String::new();
+    tokio::io::BufReader::new(file)
+        .read_line(&mut key)
+        .await
+        .ok()?;
+    key.pop().filter(|&end| end == '\n')?;
+    Some(key)
+}
+
+/// Removes a file, ignoring files which were already removed, e.g. by another instance.
+async fn remove_file(path: &Path) -> io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use bytes::Bytes;
+    use reqwest::{
+        header::{HeaderMap, HeaderValue, ETAG},
+        StatusCode, Url,
+    };
+
+    use super::DiskCache;
+    use crate::cache::{CacheStore, CachedResponse};
+
+    #[test]
+    fn persists_and_evicts_responses() {
+        tokio_test::block_on(async {
+            let directory =
+                std::env::temp_dir().join(format!("api-client-disk-cache-{}", std::process::id()));
+            let mut headers = HeaderMap::new();
+            headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+            let response = CachedResponse {
+                status: StatusCode::OK,
+                headers,
+                url: Url::parse("https://example.com/a").unwrap(),
+                body: Bytes::from_static(b"first line\n\nsecond line"),
+                vary: HeaderMap::new(),
+                stored_at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_234),
+            };
+
+            let cache = DiskCache::new(&directory).max_size(150);
+            cache.put("a".to_string(), response.clone()).await;
+            let restored = DiskCache::new(&directory).get("a").await.unwrap();
+            assert_eq!(restored.body, response.body);
+            assert_eq!(restored.headers, response.headers);
+            assert_eq!(restored.stored_at, response.stored_at);
+            assert!(cache.get("b").await.is_none());
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            cache.put("b".to_string(), response.clone()).await;
+            assert!(cache.get("a").await.is_none());
+            assert!(cache.get("b").await.is_some());
+
+            cache.remove("b").await;
+            assert!(cache.get("b").await.is_none());
+
+            let cache = DiskCache::new(&directory);
+            for key in ["a", "a accept=\"*/*\"", "ab"] {
+                cache.put(key.to_string(), response.clone()).await;
+            }
+            cache.remove_url("a").await;
+            assert!(cache.get("a").await.is_none());
+            assert!(cache.get("a accept=\"*/*\"").await.is_none());
+            assert!(cache.get("ab").await.is_some());
+            cache.clear().await.unwrap();
+            std::fs::remove_dir(&directory).unwrap();
+        });
+    }
+}
@@ -0,0 +1,165 @@
+//! Cancelling calls in flight, e.g. when a user navigates away or the application shuts down.
+//!
+//! [`Cancel::cancel_on`] aborts a call as soon as a signal completes, which can be any future,
+//! such as `CancellationToken::cancelled` of `tokio-util` or the receiver of a channel. The call
+//! is dropped wherever it is, while waiting for a response, between the retries of interceptors or
+//! while reading the body, and fails with [`Error::Cancelled`]. Fallbacks of the endpoint don't
+//! apply to it. Calls whose signal has already completed are not started.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, cancel::Cancel, ResultType};
+//! use tokio_util::sync::CancellationToken;
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn report(id: u32) -> String {
+//!            GET "https://example.com/reports/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn load_report(
+//!     api: &ExampleApi,
+//!     id: u32,
+//!     shutdown: &CancellationToken,
+//! ) -> ResultType<String> {
+//!     api.report(id).cancel_on(shutdown.cancelled()).await
+//! }
+//! ```
+
+use std::{future::Future, pin::Pin, task::Poll};
+
+use crate::{Error, ResultType};
+
+/// Aborts a call when a signal completes, see [`cancel`](crate::cancel).
+///
+/// Implemented for the futures returned by endpoints and other calls returning a [`ResultType`].
+pub trait Cancel: Sized {
+    /// Aborts the call with [`Error::Cancelled`] as soon as `signal` completes.
+    fn cancel_on<S: Future>(self, signal: S) -> CancelOn<Self, S>;
+}
+
+impl<F, T> Cancel for F
+where
+    F: Future<Output = ResultType<T>>,
+{
+    fn cancel_on<S: Future>(self, signal: S) -> CancelOn<Self, S> {
+        CancelOn {
+            future: Some(Box::pin(self)),
+            signal: Box::pin(signal),
+        }
+    }
+}
+
+/// Future aborting a call when a signal completes, created by [`Cancel::cancel_on`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct CancelOn<F, S> {
+    /// The call, dropped when it is cancelled.
+    future: Option<Pin<Box<F>>>,
+    /// The signal cancelling the call.
+    signal: Pin<Box<S>>,
+}
+
+impl<F, S, T> Future for CancelOn<F, S>
+where
+    F: Future<Output = ResultType<T>>,
+    S: Future,
+{
+    type Output = ResultType<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        if self.future.is_none() || self.signal.as_mut().poll(cx).is_ready() {
+            self.future = None;
+            return Poll::Ready(Err(Error::Cancelled));
+        }
+        match self.future.as_mut().map(|future| future.as_mut().poll(cx)) {
+            Some(Poll::Ready(output)) => {
+                self.future = None;
+                Poll::Ready(output)
+            }
+            _ => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::ready,
+        time::{Duration, Instant},
+    };
+
+    use super::Cancel;
+    use crate::{
+        api,
+        interceptor::{Interceptor, Next},
+        test_server, ApiState, Error, ResultType,
+    };
+
+    /// Interceptor retrying requests after server errors, sleeping a second before every retry.
+    #[derive(Debug)]
+    struct Retry;
+
+    #[async_trait::async_trait(?Send)]
+    impl Interceptor for Retry {
+        async fn intercept(
+            &self,
+            request: reqwest::Request,
+            next: Next<'_>,
+        ) -> ResultType<reqwest::Response> {
+            loop {
+                let response = next.run(request.try_clone().unwrap()).await?;
+                if !response.status().is_server_error() {
+                    return Ok(response);
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+
+    api!(struct Reports);
+
+    impl Reports {
+        api! {
+            fn report(&self, base_url: &str, id: u32) -> String {
+                GET "{base_url}/reports/{id}"
+                with { fallback: default }
+            }
+        }
+    }
+
+    #[test]
+    fn cancels_calls() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.path.as_str() {
+                "/reports/1" => test_server::Response::new(200).body("ready"),
+                "/reports/2" => test_server::Response::new(200).delay(Duration::from_secs(5)),
+                _ => test_server::Response::new(503),
+            })
+            .await;
+            let api = Reports::builder()
+                .state(ApiState::new().interceptor(Retry))
+                .build()
+                .unwrap();
+            let signal = || tokio::time::sleep(Duration::from_millis(100));
+
+            let report = api.report(&base_url, 1).cancel_on(signal()).await;
+            assert_eq!(report.unwrap(), "ready");
+
+            for id in [2, 3] {
+                let started = Instant::now();
+                let report = api.report(&base_url, id).cancel_on(signal()).await;
+                assert!(matches!(report, Err(Error::Cancelled)), "{id}");
+                assert!(started.elapsed() < Duration::from_millis(500), "{id}");
+            }
+
+            let report = api.report(&base_url, 1).cancel_on(ready(())).await;
+            assert!(matches!(report, Err(Error::Cancelled)));
+            assert_eq!(Error::Cancelled.to_string(), "the call was cancelled");
+        });
+    }
+}
@@ -0,0 +1,472 @@
+//! Keeping summaries of the last requests for post-mortem debugging.
+//!
+//! With a [`CaptureBuffer`] set through [`ApiState::capture`](crate::ApiState::capture), the
+//! method, URL, headers and status of the last requests sent by an instance are kept in memory,
+//! bodies are not. Credentials are redacted before anything is stored: the headers and query
+//! parameters named by [`DEFAULT_REDACTED_HEADERS`] and [`DEFAULT_REDACTED_PARAMS`], and any
+//! others added with [`CaptureBuffer::redact_header`] and [`CaptureBuffer::redact_param`].
+//!
+//! [`CaptureBuffer::dump`] renders the summaries as text for a bug report, e.g. once
+//! [`CaptureBuffer::failures`] crosses a threshold.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, capture::CaptureBuffer, Api, ApiState, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn status() -> String {
+//!            GET "https://example.com/status"
+//!         }
+//!     }
+//! }
+//!
+//! async fn status(api: &ExampleApi) -> ResultType<String> {
+//!     let status = api.status().await;
+//!     if let Some(capture) = api.state().capture_buffer() {
+//!         if capture.failures() >= 3 {
+//!             eprintln!("{}", capture.dump());
+//!         }
+//!     }
+//!     status
+//! }
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().capture(CaptureBuffer::new(50)))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use reqwest::header::{HeaderMap, HeaderValue};
+
+use crate::ResultType;
+
+/// Headers whose values are redacted unless configured otherwise.
+pub const DEFAULT_REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+    "x-api-key",
+];
+
+/// Query parameters whose values are redacted unless configured otherwise.
+pub const DEFAULT_REDACTED_PARAMS: &[&str] = &["access_token", "api_key", "key", "token"];
+
+/// Body fields whose values are redacted unless configured otherwise, in addition to
+/// [`DEFAULT_REDACTED_PARAMS`], where bodies are shown.
+pub const DEFAULT_REDACTED_FIELDS: &[&str] =
+    &["client_secret", "password", "refresh_token", "secret"];
+
+/// Placeholder replacing redacted values.
+pub const REDACTED: &str = "[redacted]";
+
+/// Summary of a request and its response, see [`CaptureBuffer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    /// Point in time at which the request was sent.
+    pub sent_at: SystemTime,
+    /// HTTP method of the request.
+    pub method: String,
+    /// URL of the request, with redacted query parameters.
+    pub url: String,
+    /// Headers of the request, with redacted values.
+    pub request_headers: Vec<(String, String)>,
+    /// Status of the response, if one was received.
+    pub status: Option<u16>,
+    /// Headers of the response, with redacted values.
+    pub response_headers: Vec<(String, String)>,
+    /// Time until the response headers were received or the request failed.
+    pub elapsed: Duration,
+    /// Error of the request, if no response was received.
+    pub error: Option<String>,
+}
+
+impl Exchange {
+    /// Returns whether the request failed or was answered with an error status.
+    #[must_use]
+    pub fn is_failure(&self) -> bool {
+        self.error.is_some() || self.status.is_some_and(|status| status >= 400)
+    }
+}
+
+impl fmt::Display for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} -> ",
+            httpdate::fmt_http_date(self.sent_at),
+            self.method,
+            self.url
+        )?;
+        match (self.status, &self.error) {
+            (_, Some(error)) => write!(f, "error: {error}")?,
+            (Some(status), None) => write!(f, "{status}")?,
+            (None, None) => f.write_str("no response")?,
+        }
+        write!(f, " ({} ms)", self.elapsed.as_millis())?;
+        for (name, value) in &self.request_headers {
+            write!(f, "\n> {name}: {value}")?;
+        }
+        for (name, value) in &self.response_headers {
+            write!(f, "\n< {name}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Bounded buffer of the last [`Exchange`]s of an instance, dropping the oldest one when full.
+#[derive(Debug)]
+pub struct CaptureBuffer {
+    /// Maximum number of kept exchanges.
+    capacity: usize,
+    /// Headers and query parameters whose values are redacted.
+    redaction: Redaction,
+    /// Kept exchanges, from the oldest to the newest.
+    exchanges: Mutex<VecDeque<Exchange>>,
+}
+
+impl CaptureBuffer {
+    /// Creates an empty buffer keeping the last `capacity` exchanges.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "a capture buffer has to keep at least one exchange"
+        );
+        Self {
+            capacity,
+            redaction: Redaction::default(),
+            exchanges: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Also redacts the values of the header `name`, in requests and responses.
+    #[must_use]
+    pub fn redact_header(mut self, name: &str) -> Self {
+        self.redaction.headers.push(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Also redacts the values of the query parameter `name`.
+    #[must_use]
+    pub fn redact_param(mut self, name: impl Into<String>) -> Self {
+        self.redaction.params.push(name.into());
+        self
+    }
+
+    /// Returns the kept exchanges, from the oldest to the newest.
+    #[must_use]
+    pub fn snapshot(&self) -> Vec<Exchange> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// Returns the number of kept exchanges which failed, see [`Exchange::is_failure`].
+    #[must_use]
+    pub fn failures(&self) -> usize {
+        self.lock()
+            .iter()
+            .filter(|exchange| exchange.is_failure())
+            .count()
+    }
+
+    /// Renders the kept exchanges as text, from the oldest to the newest, separated by blank
+    /// lines.
+    ///
+    /// Each exchange starts with a line like
+    /// `Fri, 16 Oct 2026 09:30:00 GMT GET https://example.com/status -> 503 (120 ms)`, followed
+    /// by one line per request header starting with `> ` and per response header starting with
+    /// `< `.
+    #[must_use]
+    pub fn dump(&self) -> String {
+        self.lock()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Returns the number of kept exchanges.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns whether no exchanges are kept.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all kept exchanges.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Keeps a summary of `request`, sent at `sent`, and its `response`.
+    pub(crate) fn record(
+        &self,
+        request: &reqwest::Request,
+        sent: Instant,
+        response: &ResultType<reqwest::Response>,
+    ) {
+        let elapsed = sent.elapsed();
+        let (status, response_headers, error) = match response {
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                self.redaction.headers(response.headers()),
+                None,
+            ),
+            Err(error) => (None, Vec::new(), Some(error.to_string())),
+        };
+        let exchange = Exchange {
+            sent_at: SystemTime::now()
+                .checked_sub(elapsed)
+                .unwrap_or_else(SystemTime::now),
+            method: request.method().to_string(),
+            url: self.redaction.url(request.url()),
+            request_headers: self.redaction.headers(request.headers()),
+            status,
+            response_headers,
+            elapsed,
+            error,
+        };
+
+        let mut exchanges = self.lock();
+        if exchanges.len() == self.capacity {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+
+    /// Locks the kept exchanges.
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<Exchange>> {
+        self.exchanges
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Names of the headers and query parameters whose values are redacted.
+#[derive(Debug, Clone)]
+pub(crate) struct Redaction {
+    /// Lowercase names of the headers whose values are redacted.
+    pub(crate) headers: Vec<String>,
+    /// Names of the query parameters whose values are redacted.
+    pub(crate) params: Vec<String>,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self {
+            headers: DEFAULT_REDACTED_HEADERS
+                .iter()
+                .map(|name| (*name).to_string())
+                .collect(),
+            params: DEFAULT_REDACTED_PARAMS
+                .iter()
+                .map(|name| (*name).to_string())
+                .collect(),
+        }
+    }
+}
+
+impl Redaction {
+    /// Returns the default names, with [`DEFAULT_REDACTED_FIELDS`] added to the parameters for
+    /// redacting bodies.
+    pub(crate) fn for_bodies() -> Self {
+        let mut redaction = Self::default();
+        redaction.params.extend(
+            DEFAULT_REDACTED_FIELDS
+                .iter()
+                .map(|name| (*name).to_string()),
+        );
+        redaction
+    }
+
+    /// Returns `headers` as text, with redacted values.
+    pub(crate) fn headers(&self, headers: &HeaderMap) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if self
+                    .headers
+                    .iter()
+                    .any(|redacted| redacted == name.as_str())
+                {
+                    REDACTED.to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+                (name.to_string(), value)
+            })
+            .collect()
+    }
+
+    /// Returns `url` as text, with redacted query parameters.
+    pub(crate) fn url(&self, url: &reqwest::Url) -> String {
+        if !url.query_pairs().any(|(name, _)| self.redacts(&name)) {
+            return url.to_string();
+        }
+        let mut url = url.clone();
+        let pairs: Vec<_> = url
+            .query_pairs()
+            .map(|(name, value)| {
+                let value = if self.redacts(&name) {
+                    REDACTED.into()
+                } else {
+                    value
+                };
+                (name.into_owned(), value.into_owned())
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+        url.to_string()
+    }
+
+    /// Returns `body` as text, with the redacted fields of form and JSON bodies.
+    pub(crate) fn body(&self, body: &[u8], content_type: Option<&HeaderValue>) -> String {
+        let content_type = content_type
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if content_type.starts_with("application/x-www-form-urlencoded") {
+            return self.form(body);
+        }
+        #[cfg(feature = "json")]
+        if let Some(json) = self.json(body) {
+            return json;
+        }
+        String::from_utf8_lossy(body).into_owned()
+    }
+
+    /// Returns the form `body` with redacted fields.
+    fn form(&self, body: &[u8]) -> String {
+        let mut pairs: Vec<(String, String)> =
+            serde_urlencoded::from_bytes(body).unwrap_or_default();
+        for (name, value) in &mut pairs {
+            if self.redacts(name) {
+                *value = REDACTED.to_string();
+            }
+        }
+        serde_urlencoded::to_string(pairs).unwrap_or_default()
+    }
+
+    /// Returns the JSON `body` with redacted fields, or `None` if it is not JSON.
+    #[cfg(feature = "json")]
+    fn json(&self, body: &[u8]) -> Option<String> {
+        /// Replaces the values of redacted fields in `value` and all values nested in it.
+        fn redact(redaction: &Redaction, value: &mut serde_json::Value) {
+            match value {
+                serde_json::Value::Object(fields) => {
+                    for (name, value) in fields {
+                        if redaction.redacts(name) {
+                            *value = REDACTED.into();
+                        } else {
+                            redact(redaction, value);
+                        }
+                    }
+                }
+                serde_json::Value::Array(items) => {
+                    for item in items {
+                        redact(redaction, item);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut value = serde_json::from_slice(body).ok()?;
+        redact(self, &mut value);
+        Some(value.to_string())
+    }
+
+    /// Returns whether the values of the parameter or field `name` are redacted.
+    fn redacts(&self, name: &str) -> bool {
+        self.params.iter().any(|redacted| redacted == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CaptureBuffer, REDACTED};
+    use crate::{api, test_server, Api, ApiState, RequestBuilder, ResultType};
+
+    api! {
+        struct Captured {}
+
+        impl Api {
+            fn pre_request(&self, request: RequestBuilder) -> ResultType<RequestBuilder> {
+                Ok(request.bearer_auth("secret").header("x-session", "s1"))
+            }
+        }
+    }
+
+    impl Captured {
+        api! {
+            fn get(&self, base_url: &str, path: &str) -> StatusCode {
+                GET "{base_url}/{path}"
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_redacted_exchanges() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let status = if request.path.starts_with("/broken") {
+                    500
+                } else {
+                    200
+                };
+                test_server::Response::new(status).header("x-request-id", "r1")
+            })
+            .await;
+            let api = Captured::builder()
+                .state(ApiState::new().capture(CaptureBuffer::new(2).redact_header("X-Session")))
+                .build()
+                .unwrap();
+
+            api.get(&base_url, "first").await.unwrap();
+            api.get(&base_url, "ok?page=2&token=t1").await.unwrap();
+            api.get(&base_url, "broken").await.unwrap();
+
+            let capture = api.state().capture_buffer().unwrap();
+            let exchanges = capture.snapshot();
+            assert_eq!(exchanges.len(), 2);
+            assert_eq!(
+                exchanges[0].url,
+                format!("{base_url}/ok?page=2&token=%5Bredacted%5D")
+            );
+            assert_eq!(exchanges[0].status, Some(200));
+            let header = |name: &str| {
+                exchanges[0]
+                    .request_headers
+                    .iter()
+                    .find(|(header, _)| header == name)
+                    .map(|(_, value)| value.as_str())
+            };
+            assert_eq!(header("authorization"), Some(REDACTED));
+            assert_eq!(header("x-session"), Some(REDACTED));
+            assert_eq!(capture.failures(), 1);
+
+            let dump = capture.dump();
+            assert!(dump.contains(&format!("GET {base_url}/broken -> 500 (")));
+            assert!(dump.contains("< x-request-id: r1"));
+            assert!(!dump.contains("secret"));
+            capture.clear();
+            assert!(capture.is_empty());
+        });
+    }
+}
@@ -0,0 +1,301 @@
+//! Checking response bodies against the checksums servers announce for them, or ones known
+//! beforehand, e.g. from the release notes of an artifact.
+//!
+//! [`Checksum::from_headers`] reads the checksums of a response from its `Content-Digest`
+//! ([RFC 9530]), `Digest` ([RFC 3230]) and `Content-MD5` headers, with the SHA-512, SHA-256 and
+//! MD5 algorithms. [`Downloading::verify`](crate::response::Downloading::verify) checks a
+//! download against them, and [`Downloading::expect_checksum`](crate::response::Downloading::expect_checksum)
+//! against a checksum given by the caller, while the body is written. A body which doesn't
+//! match fails with [`Error::ChecksumMismatch`] and its file is not kept. [`verify`] checks
+//! bodies read otherwise, e.g. by `Bytes` endpoints.
+//!
+//! [RFC 9530]: https://www.rfc-editor.org/rfc/rfc9530
+//! [RFC 3230]: https://www.rfc-editor.org/rfc/rfc3230
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, checksum::Checksum, ResultType};
+//!
+//! api!(pub struct Packages);
+//!
+//! impl Packages {
+//!     api! {
+//!         fn package(name: &str) -> Download {
+//!            GET "https://example.com/packages/{name}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn fetch_package(api: &Packages) -> ResultType<()> {
+//!     let expected = Checksum::sha256_hex(
+//!         "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+//!     );
+//!     api.package("tool-1.0.tar.gz")
+//!         .await?
+//!         .verify()
+//!         .expect_checksum(expected)
+//!         .save("target/tool-1.0.tar.gz")
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::fmt;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md5::Md5;
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{Error, ResultType};
+
+/// Hash algorithm of a [`Checksum`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// MD5, as sent in `Content-MD5`.
+    Md5,
+    /// SHA-256.
+    Sha256,
+    /// SHA-512.
+    Sha512,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Algorithm::Md5 => "MD5",
+            Algorithm::Sha256 => "SHA-256",
+            Algorithm::Sha512 => "SHA-512",
+        })
+    }
+}
+
+/// Expected hash of a body, see [`checksum`](crate::checksum).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    /// Algorithm of the hash.
+    pub algorithm: Algorithm,
+    /// The hash.
+    pub digest: Vec<u8>,
+}
+
+impl Checksum {
+    /// Creates the checksum `digest` computed with `algorithm`.
+    pub fn new(algorithm: Algorithm, digest: impl Into<Vec<u8>>) -> Self {
+        Self {
+            algorithm,
+            digest: digest.into(),
+        }
+    }
+
+    /// Creates a SHA-256 checksum from its hex encoding, as published with many artifacts.
+    ///
+    /// # Panics
+    /// Panics if `hex` is not 64 hex digits.
+    #[must_use]
+    pub fn sha256_hex(hex: &str) -> Self {
+        assert!(
+            hex.len() == 64 && hex.bytes().all(|digit| digit.is_ascii_hexdigit()),
+            "SHA-256 checksums are 64 hex digits"
+        );
+        let digest = (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).unwrap_or_default())
+            .collect::<Vec<_>>();
+        Self::new(Algorithm::Sha256, digest)
+    }
+
+    /// Returns the checksums announced in the `Content-Digest`, `Digest` and `Content-MD5`
+    /// `headers` of a response, skipping ones with other algorithms.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Vec<Self> {
+        let values = |name| {
+            headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .filter_map(|entry| entry.split_once('='))
+                .map(|(algorithm, digest)| (algorithm.trim().to_ascii_lowercase(), digest.trim()))
+        };
+        let content_digest = values("content-digest").filter_map(|(algorithm, digest)| {
+            let digest = digest.strip_prefix(':')?.strip_suffix(':')?;
+            Some((algorithm, digest.to_string()))
+        });
+        let digest = values("digest").map(|(algorithm, digest)| (algorithm, digest.to_string()));
+        let content_md5 = headers
+            .get_all("content-md5")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(|digest| ("md5".to_string(), digest.trim().to_string()));
+        content_digest
+            .chain(digest)
+            .chain(content_md5)
+            .filter_map(|(algorithm, digest)| {
+                let algorithm = match algorithm.as_str() {
+                    "md5" => Algorithm::Md5,
+                    "sha-256" => Algorithm::Sha256,
+                    "sha-512" => Algorithm::Sha512,
+                    _ => return None,
+                };
+                Some(Self::new(algorithm, STANDARD.decode(digest).ok()?))
+            })
+            .collect()
+    }
+}
+
+/// Checks that `body` matches all checksums announced in the `headers` of its response, see
+/// [`Checksum::from_headers`].
+///
+/// # Errors
+/// Returns [`Error::ChecksumMismatch`] for the first checksum `body` doesn't match.
+pub fn verify(headers: &HeaderMap, body: &[u8]) -> ResultType<()> {
+    let mut verifier = Verifier::new(Checksum::from_headers(headers));
+    verifier.update(body);
+    verifier.finish()
+}
+
+/// A body which doesn't match its checksum, see [`Error::ChecksumMismatch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    /// Algorithm of the checksum.
+    pub algorithm: Algorithm,
+    /// The expected hash.
+    pub expected: Vec<u8>,
+    /// The hash of the body.
+    pub actual: Vec<u8>,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the {} checksum of the body is {}, expected {}",
+            self.algorithm,
+            crate::signing::hex(&self.actual),
+            crate::signing::hex(&self.expected)
+        )
+    }
+}
+
+/// Hashes a body as it is read and checks it against the expected checksums.
+#[derive(Default)]
+pub(crate) struct Verifier {
+    /// The expected checksums.
+    expected: Vec<Checksum>,
+    /// Hashes the body with MD5, if expected.
+    md5: Option<Md5>,
+    /// Hashes the body with SHA-256, if expected.
+    sha256: Option<Sha256>,
+    /// Hashes the body with SHA-512, if expected.
+    sha512: Option<Sha512>,
+}
+
+impl Verifier {
+    /// Creates a verifier checking against `expected`.
+    pub(crate) fn new(expected: Vec<Checksum>) -> Self {
+        let uses = |algorithm| {
+            expected
+                .iter()
+                .any(|checksum| checksum.algorithm == algorithm)
+        };
+        Self {
+            md5: uses(Algorithm::Md5).then(Md5::new),
+            sha256: uses(Algorithm::Sha256).then(Sha256::new),
+            sha512: uses(Algorithm::Sha512).then(Sha512::new),
+            expected,
+        }
+    }
+
+    /// Adds `checksums` to the expected ones.
+    pub(crate) fn expect(self, checksums: impl IntoIterator<Item = Checksum>) -> Self {
+        let mut expected = self.expected;
+        expected.extend(checksums);
+        Self::new(expected)
+    }
+
+    /// Hashes the next bytes of the body.
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        if let Some(md5) = &mut self.md5 {
+            md5.update(data);
+        }
+        if let Some(sha256) = &mut self.sha256 {
+            sha256.update(data);
+        }
+        if let Some(sha512) = &mut self.sha512 {
+            sha512.update(data);
+        }
+    }
+
+    /// Checks the hashes of the whole body against the expected checksums.
+    pub(crate) fn finish(self) -> ResultType<()> {
+        let md5 = self.md5.map(|md5| md5.finalize().to_vec());
+        let sha256 = self.sha256.map(|sha256| sha256.finalize().to_vec());
+        let sha512 = self.sha512.map(|sha512| sha512.finalize().to_vec());
+        for checksum in self.expected {
+            let actual = match checksum.algorithm {
+                Algorithm::Md5 => &md5,
+                Algorithm::Sha256 => &sha256,
+                Algorithm::Sha512 => &sha512,
+            };
+            let actual = actual.clone().unwrap_or_default();
+            if actual != checksum.digest {
+                return Err(Error::ChecksumMismatch(ChecksumMismatch {
+                    algorithm: checksum.algorithm,
+                    expected: checksum.digest,
+                    actual,
+                }));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::{verify, Algorithm, Checksum};
+    use crate::Error;
+
+    #[test]
+    fn verifies_announced_checksums() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "content-digest",
+            HeaderValue::from_static(
+                "sha-256=:ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=:, unknown=:AA==:",
+            ),
+        );
+        headers.insert(
+            "digest",
+            HeaderValue::from_static("MD5=kAFQmDzST7DWlj99KOF/cg=="),
+        );
+        headers.insert(
+            "content-md5",
+            HeaderValue::from_static("kAFQmDzST7DWlj99KOF/cg=="),
+        );
+        let checksums = Checksum::from_headers(&headers);
+        let algorithms = checksums.iter().map(|checksum| checksum.algorithm);
+        assert_eq!(
+            algorithms.collect::<Vec<_>>(),
+            [Algorithm::Sha256, Algorithm::Md5, Algorithm::Md5]
+        );
+        assert_eq!(
+            checksums[0],
+            Checksum::sha256_hex(
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            )
+        );
+
+        verify(&headers, b"abc").unwrap();
+        verify(&HeaderMap::new(), b"anything").unwrap();
+        let error = verify(&headers, b"abd").unwrap_err();
+        assert!(
+            matches!(&error, Error::ChecksumMismatch(mismatch) if mismatch.algorithm == Algorithm::Sha256)
+        );
+        assert!(error.to_string().ends_with(
+            "expected ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        ));
+    }
+}
@@ -0,0 +1,292 @@
+//! Circuit breaker failing requests fast while the upstream is down.
+//!
+//! After `failure_threshold` consecutive failures, the breaker opens and requests fail
+//! immediately with [`Error::CircuitOpen`](crate::Error::CircuitOpen) instead of being sent. Once
+//! the open duration has passed, a limited number of probe requests are let through: if they
+//! succeed the breaker closes again, if one fails it reopens.
+//!
+//! A request fails if it could not be sent or the server responded with a `5xx` status.
+//!
+//! # Usage
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use api_client::{api, circuit_breaker::CircuitBreaker, ApiState};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().circuit_breaker(
+//!         CircuitBreaker::new(5, Duration::from_secs(30)).half_open_probes(2),
+//!     ))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::Error;
+
+/// State of a [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent normally.
+    Closed,
+    /// Requests fail immediately.
+    Open,
+    /// A limited number of probe requests are sent to test whether the upstream recovered.
+    HalfOpen,
+}
+
+/// Internal state of a [`CircuitBreaker`].
+#[derive(Debug)]
+enum Circuit {
+    /// Requests are sent, counting consecutive failures.
+    Closed {
+        /// Number of consecutive failures.
+        failures: u32,
+    },
+    /// Requests fail until `until`.
+    Open {
+        /// Point in time at which probes are let through.
+        until: Instant,
+    },
+    /// Probe requests are sent.
+    HalfOpen {
+        /// Number of probes which have been sent but not completed.
+        in_flight: u32,
+        /// Number of probes which succeeded.
+        successes: u32,
+    },
+}
+
+/// Circuit breaker shared by all requests of an [`Api`](crate::Api) instance.
+///
+/// Set one with [`ApiState::circuit_breaker`](crate::ApiState::circuit_breaker).
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// Number of consecutive failures which open the breaker.
+    failure_threshold: u32,
+    /// Time the breaker stays open before sending probes.
+    open_duration: Duration,
+    /// Number of successful probes which close the breaker again.
+    probes: u32,
+    /// Current state.
+    circuit: Mutex<Circuit>,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed breaker which opens for `open_duration` after `failure_threshold`
+    /// consecutive failures.
+    ///
+    /// # Panics
+    /// Panics if `failure_threshold` is zero.
+    #[must_use]
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        assert!(
+            failure_threshold > 0,
+            "the failure threshold of a circuit breaker must not be zero"
+        );
+        Self {
+            failure_threshold,
+            open_duration,
+            probes: 1,
+            circuit: Mutex::new(Circuit::Closed { failures: 0 }),
+        }
+    }
+
+    /// Sets how many probes have to succeed in a row to close the breaker, 1 by default.
+    ///
+    /// At most this many probes are in flight at once.
+    ///
+    /// # Panics
+    /// Panics if `probes` is zero.
+    #[must_use]
+    pub fn half_open_probes(mut self, probes: u32) -> Self {
+        assert!(probes > 0, "a circuit breaker needs at least one probe");
+        self.probes = probes;
+        self
+    }
+
+    /// Returns the current state.
+    #[must_use]
+    pub fn state(&self) -> CircuitState {
+        match *self.circuit() {
+            Circuit::Closed { .. } => CircuitState::Closed,
+            Circuit::Open { until } if until > Instant::now() => CircuitState::Open,
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Closes the breaker, forgetting all failures.
+    pub fn reset(&self) {
+        *self.circuit() = Circuit::Closed { failures: 0 };
+    }
+
+    /// Locks the internal state.
+    fn circuit(&self) -> MutexGuard<'_, Circuit> {
+        self.circuit.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Checks whether a request may be sent.
+    ///
+    /// The returned permit has to be completed with the outcome of the request.
+    pub(crate) fn permit(&self) -> Result<Permit<'_>, Error> {
+        let mut circuit = self.circuit();
+        if let Circuit::Open { until } = *circuit {
+            if until > Instant::now() {
+                return Err(Error::CircuitOpen);
+            }
+            *circuit = Circuit::HalfOpen {
+                in_flight: 0,
+                successes: 0,
+            };
+        }
+        let probe = match &mut *circuit {
+            Circuit::HalfOpen {
+                in_flight,
+                successes,
+            } => {
+                if *in_flight + *successes >= self.probes {
+                    return Err(Error::CircuitOpen);
+                }
+                *in_flight += 1;
+                true
+            }
+            _ => false,
+        };
+        Ok(Permit {
+            breaker: self,
+            probe,
+        })
+    }
+}
+
+/// Permission to send a request, see [`CircuitBreaker::permit`].
+///
+/// Dropping it without completing it, e.g. when the request is cancelled, counts as neither
+/// success nor failure.
+#[derive(Debug)]
+pub(crate) struct Permit<'a> {
+    /// The breaker which issued the permit.
+    breaker: &'a CircuitBreaker,
+    /// Whether the request is a probe of the half-open breaker.
+    probe: bool,
+}
+
+impl Permit<'_> {
+    /// Records the outcome of the request.
+    pub(crate) fn complete(mut self, success: bool) {
+        let breaker = self.breaker;
+        let probe = std::mem::take(&mut self.probe);
+        let mut circuit = breaker.circuit();
+        match &mut *circuit {
+            Circuit::Closed { failures } if success => *failures = 0,
+            Circuit::Closed { failures } => {
+                *failures += 1;
+                if *failures >= breaker.failure_threshold {
+                    *circuit = Circuit::Open {
+                        until: Instant::now() + breaker.open_duration,
+                    };
+                }
+            }
+            Circuit::HalfOpen {
+                in_flight,
+                successes,
+            } if probe => {
+                *in_flight = in_flight.saturating_sub(1);
+                if success {
+                    *successes += 1;
+                    if *successes >= breaker.probes {
+                        *circuit = Circuit::Closed { failures: 0 };
+                    }
+                } else {
+                    *circuit = Circuit::Open {
+                        until: Instant::now() + breaker.open_duration,
+                    };
+                }
+            }
+            // Outcomes of requests sent before the breaker opened don't affect it.
+            Circuit::HalfOpen { .. } | Circuit::Open { .. } => {}
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe {
+            if let Circuit::HalfOpen { in_flight, .. } = &mut *self.breaker.circuit() {
+                *in_flight = in_flight.saturating_sub(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::{CircuitBreaker, CircuitState};
+    use crate::{api, test_server, Api, ApiState, Error};
+
+    api!(struct Flaky);
+
+    impl Flaky {
+        api! {
+            fn get(&self, base_url: &str) -> StatusCode {
+                GET "{base_url}/"
+            }
+        }
+    }
+
+    #[test]
+    fn opens_and_recovers() {
+        tokio_test::block_on(async {
+            let healthy = Arc::new(AtomicBool::new(false));
+            let hits = Arc::new(AtomicUsize::new(0));
+            let (server_healthy, server_hits) = (healthy.clone(), hits.clone());
+            let base_url = test_server::serve(move |_| {
+                server_hits.fetch_add(1, Ordering::SeqCst);
+                test_server::Response::new(if server_healthy.load(Ordering::SeqCst) {
+                    200
+                } else {
+                    500
+                })
+            })
+            .await;
+            let api = Flaky::builder()
+                .state(
+                    ApiState::new()
+                        .circuit_breaker(CircuitBreaker::new(2, Duration::from_millis(100))),
+                )
+                .build()
+                .unwrap();
+            let breaker = api.state().breaker().unwrap();
+
+            assert_eq!(api.get(&base_url).await.unwrap().as_u16(), 500);
+            assert_eq!(api.get(&base_url).await.unwrap().as_u16(), 500);
+            assert_eq!(breaker.state(), CircuitState::Open);
+            assert!(matches!(api.get(&base_url).await, Err(Error::CircuitOpen)));
+            assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            assert_eq!(breaker.state(), CircuitState::HalfOpen);
+            assert_eq!(api.get(&base_url).await.unwrap().as_u16(), 500);
+            assert_eq!(breaker.state(), CircuitState::Open);
+
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            healthy.store(true, Ordering::SeqCst);
+            assert_eq!(api.get(&base_url).await.unwrap().as_u16(), 200);
+            assert_eq!(breaker.state(), CircuitState::Closed);
+        });
+    }
+}
@@ -0,0 +1,125 @@
+//! Labelling calls with the operation they are part of, so their errors say what failed.
+//!
+//! [`Context::with_context`] attaches a label such as `"syncing invoice 42"` to any error of a
+//! call, which is then reported as `syncing invoice 42: <error>`. Labels can be nested by
+//! attaching them at several levels; [`Error::root`] returns the error without them.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, context::Context, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn invoice(id: u32) -> String {
+//!            GET "https://example.com/invoices/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn sync_invoice(api: &ExampleApi, id: u32) -> ResultType<String> {
+//!     api.invoice(id)
+//!         .with_context(format!("syncing invoice {id}"))
+//!         .await
+//! }
+//! ```
+
+use std::{future::Future, pin::Pin, task::Poll};
+
+use futures_util::ready;
+
+use crate::ResultType;
+
+/// Attaches a label to the error of a call, see [`context`](crate::context).
+///
+/// Implemented for the futures returned by endpoints and other calls returning a [`ResultType`].
+pub trait Context: Sized {
+    /// Labels any error of the call with `label`, e.g. the operation it is part of.
+    fn with_context(self, label: impl Into<String>) -> WithContext<Self>;
+}
+
+impl<F, T> Context for F
+where
+    F: Future<Output = ResultType<T>>,
+{
+    fn with_context(self, label: impl Into<String>) -> WithContext<Self> {
+        WithContext {
+            future: Box::pin(self),
+            label: Some(label.into()),
+        }
+    }
+}
+
+/// Future labelling the error of a call, created by [`Context::with_context`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WithContext<F> {
+    /// The labelled call.
+    future: Pin<Box<F>>,
+    /// The label, taken when the call completes.
+    label: Option<String>,
+}
+
+impl<F, T> Future for WithContext<F>
+where
+    F: Future<Output = ResultType<T>>,
+{
+    type Output = ResultType<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        let result = ready!(self.future.as_mut().poll(cx));
+        let label = self.label.take().unwrap_or_default();
+        Poll::Ready(result.map_err(|error| error.context(label)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Context;
+    use crate::{api, test_server, Api, Error};
+
+    api!(struct Invoices);
+
+    impl Invoices {
+        api! {
+            fn invoice(&self, base_url: &str, id: u32) -> Json<u32> {
+                GET "{base_url}/invoices/{id}"
+            }
+        }
+    }
+
+    #[test]
+    fn labels_errors() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.path.as_str() {
+                "/invoices/1" => test_server::Response::new(200).json("1"),
+                _ => test_server::Response::new(200).json("\"missing\""),
+            })
+            .await;
+            let api = Invoices::new();
+
+            let invoice = api
+                .invoice(&base_url, 1)
+                .with_context("syncing invoice 1")
+                .await;
+            assert_eq!(invoice.unwrap(), 1);
+
+            let error = api
+                .invoice(&base_url, 42)
+                .with_context("syncing invoice 42")
+                .with_context("nightly sync")
+                .await
+                .unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with("nightly sync: syncing invoice 42: "));
+            assert!(matches!(error.root(), Error::Client(_)));
+            assert!(error.client_error().is_some());
+            assert_eq!(
+                error.labels().collect::<Vec<_>>(),
+                ["nightly sync", "syncing invoice 42"]
+            );
+        });
+    }
+}
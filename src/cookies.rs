@@ -0,0 +1,278 @@
+//! Cookie jar which can be exported and restored, e.g. to keep sessions across restarts.
+//!
+//! Enabled with the `cookies` feature. Cookies are only stored for instances whose state enables
+//! them with [`ApiState::cookies`](crate::ApiState::cookies); their snapshots are available
+//! through [`Api::export_cookies`](crate::Api::export_cookies) and
+//! [`Api::import_cookies`](crate::Api::import_cookies). With the `json` feature the
+//! [`session`](crate::session) module keeps them in a file.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, cookies::CookieSnapshot, Api, ApiState};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().cookies(true))
+//!     .build()
+//!     .unwrap();
+//!
+//! // e.g. loaded from disk, `CookieSnapshot` implements `Serialize` and `Deserialize`
+//! let snapshot = CookieSnapshot::default();
+//! api.import_cookies(snapshot);
+//! // ... log in ...
+//! let snapshot = api.export_cookies();
+//! ```
+
+use std::{
+    sync::{PoisonError, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{cookie::CookieStore, header::HeaderValue, Url};
+use serde::{Deserialize, Serialize};
+
+/// A cookie stored in a [`CookieJar`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredCookie {
+    /// Name of the cookie.
+    pub name: String,
+    /// Value of the cookie.
+    pub value: String,
+    /// Domain the cookie is sent to, without a leading dot.
+    pub domain: String,
+    /// Whether the cookie is only sent to `domain` itself and not to its subdomains.
+    pub host_only: bool,
+    /// Path prefix of the URLs the cookie is sent to.
+    pub path: String,
+    /// Whether the cookie is only sent over HTTPS.
+    pub secure: bool,
+    /// Expiry as a unix timestamp in seconds, or `None` for session cookies.
+    pub expires: Option<i64>,
+}
+
+impl StoredCookie {
+    /// Returns whether the cookie has expired.
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= now())
+    }
+
+    /// Returns whether the cookie should be sent with a request to `url`.
+    #[must_use]
+    pub fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return false,
+        };
+        let domain_matches = host == self.domain
+            || (!self.host_only && host.ends_with(&format!(".{}", self.domain)));
+        let path = url.path();
+        let path_matches = path == self.path
+            || (path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+
+        domain_matches && path_matches && (!self.secure || url.scheme() == "https")
+    }
+
+    /// Parses a `Set-Cookie` header received from `url`.
+    ///
+    /// Returns `None` for malformed cookies and cookies for domains `url` may not set them for.
+    pub(crate) fn parse(header: &str, url: &Url) -> Option<Self> {
+        let cookie = cookie::Cookie::parse(header).ok()?;
+        let host = url.host_str()?.to_ascii_lowercase();
+        let (domain, host_only) = match cookie.domain() {
+            Some(domain) => {
+                let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+                if host != domain && !host.ends_with(&format!(".{domain}")) {
+                    return None;
+                }
+                (domain, false)
+            }
+            None => (host, true),
+        };
+        let path = match cookie.path() {
+            Some(path) if path.starts_with('/') => path.to_string(),
+            _ => match url.path().rfind('/') {
+                Some(0) | None => "/".to_string(),
+                Some(end) => url.path()[..end].to_string(),
+            },
+        };
+        let expires = match (cookie.max_age(), cookie.expires_datetime()) {
+            (Some(max_age), _) => Some(now().saturating_add(max_age.whole_seconds())),
+            (None, Some(expires)) => Some(expires.unix_timestamp()),
+            (None, None) => None,
+        };
+
+        Some(Self {
+            name: cookie.name().to_string(),
+            value: cookie.value().to_string(),
+            domain,
+            host_only,
+            path,
+            secure: cookie.secure().unwrap_or(false),
+            expires,
+        })
+    }
+}
+
+/// Serializable snapshot of the cookies in a [`CookieJar`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CookieSnapshot {
+    /// The cookies which had not expired when the snapshot was taken.
+    pub cookies: Vec<StoredCookie>,
+}
+
+/// Cookie store whose contents can be exported and restored.
+///
+/// Install it in a client with [`reqwest::ClientBuilder::cookie_provider`]. Clients built by the
+/// [`api!`](crate::api) macro do so when [`ApiState::cookies`](crate::ApiState::cookies) is
+/// enabled.
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    /// The stored cookies.
+    cookies: RwLock<Vec<StoredCookie>>,
+}
+
+impl CookieJar {
+    /// Creates an empty cookie jar.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a snapshot of all cookies which have not expired.
+    #[must_use]
+    pub fn snapshot(&self) -> CookieSnapshot {
+        let cookies = self.cookies.read().unwrap_or_else(PoisonError::into_inner);
+        CookieSnapshot {
+            cookies: cookies
+                .iter()
+                .filter(|cookie| !cookie.is_expired())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Replaces all cookies with the ones in `snapshot`.
+    pub fn restore(&self, snapshot: CookieSnapshot) {
+        *self.cookies.write().unwrap_or_else(PoisonError::into_inner) = snapshot.cookies;
+    }
+
+    /// Returns the cookie named `name` which would be sent with a request to `url`, if any.
+    #[must_use]
+    pub fn get(&self, name: &str, url: &Url) -> Option<StoredCookie> {
+        let cookies = self.cookies.read().unwrap_or_else(PoisonError::into_inner);
+        cookies
+            .iter()
+            .find(|cookie| cookie.name == name && !cookie.is_expired() && cookie.matches(url))
+            .cloned()
+    }
+
+    /// Removes all cookies.
+    pub fn clear(&self) {
+        self.restore(CookieSnapshot::default());
+    }
+
+    /// Stores `cookie`, replacing a cookie with the same name, domain and path.
+    ///
+    /// Expired cookies only remove the cookie they replace, which is how servers delete cookies.
+    pub fn insert(&self, cookie: StoredCookie) {
+        let mut cookies = self.cookies.write().unwrap_or_else(PoisonError::into_inner);
+        cookies.retain(|stored| {
+            stored.name != cookie.name
+                || stored.domain != cookie.domain
+                || stored.path != cookie.path
+        });
+        if !cookie.is_expired() {
+            cookies.push(cookie);
+        }
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        for header in cookie_headers {
+            if let Some(cookie) = header
+                .to_str()
+                .ok()
+                .and_then(|header| StoredCookie::parse(header, url))
+            {
+                self.insert(cookie);
+            }
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let cookies = self.cookies.read().unwrap_or_else(PoisonError::into_inner);
+        let header = cookies
+            .iter()
+            .filter(|cookie| !cookie.is_expired() && cookie.matches(url))
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if header.is_empty() {
+            None
+        } else {
+            HeaderValue::from_str(&header).ok()
+        }
+    }
+}
+
+/// Returns the current unix timestamp in seconds.
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| i64::try_from(now.as_secs()).unwrap_or(i64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{api, test_server, Api, ApiState};
+
+    api!(struct Session);
+
+    impl Session {
+        api! {
+            fn login(&self, base_url: &str) -> StatusCode {
+                POST "{base_url}/login"
+            }
+
+            fn me(&self, base_url: &str) -> String {
+                GET "{base_url}/me"
+            }
+        }
+    }
+
+    #[test]
+    fn exports_and_imports_cookies() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.path.as_str() {
+                "/login" => test_server::Response::new(204)
+                    .header("set-cookie", "session=secret; Path=/; HttpOnly")
+                    .header("set-cookie", "old=1; Max-Age=0"),
+                _ => test_server::Response::new(200)
+                    .body(request.header("cookie").unwrap_or_default().to_string()),
+            })
+            .await;
+            let session = || {
+                Session::builder()
+                    .state(ApiState::new().cookies(true))
+                    .build()
+                    .unwrap()
+            };
+
+            let api = session();
+            api.login(&base_url).await.unwrap();
+            assert_eq!(api.me(&base_url).await.unwrap(), "session=secret");
+
+            let snapshot = api.export_cookies();
+            assert_eq!(snapshot.cookies.len(), 1);
+
+            let restored = session();
+            assert_eq!(restored.me(&base_url).await.unwrap(), "");
+            restored.import_cookies(snapshot);
+            assert_eq!(restored.me(&base_url).await.unwrap(), "session=secret");
+        });
+    }
+}
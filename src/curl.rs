@@ -0,0 +1,104 @@
+//! Rendering requests as `curl` commands, to reproduce issues against an API outside of the
+//! client.
+//!
+//! [`to_curl`] renders any built request. With [`ApiState::debug_curl`](crate::ApiState::debug_curl),
+//! every request of an instance is printed to standard error as a command before it is sent,
+//! after [`Api::pre_request`](crate::Api::pre_request) has added authentication.
+//!
+//! Credentials are masked as in [`capture`](crate::capture): the headers named by
+//! [`DEFAULT_REDACTED_HEADERS`](crate::capture::DEFAULT_REDACTED_HEADERS), the query parameters
+//! named by [`DEFAULT_REDACTED_PARAMS`](crate::capture::DEFAULT_REDACTED_PARAMS), and those and
+//! the fields named by [`DEFAULT_REDACTED_FIELDS`](crate::capture::DEFAULT_REDACTED_FIELDS) in
+//! form and JSON bodies. Streamed bodies, e.g. multipart ones, are left out.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, curl::to_curl, ApiState};
+//!
+//! let request = reqwest::Client::new()
+//!     .post("https://example.com/login?token=secret")
+//!     .bearer_auth("secret")
+//!     .form(&[("user", "me")])
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(
+//!     to_curl(&request),
+//!     "curl -X POST 'https://example.com/login?token=%5Bredacted%5D' \\\n  \
+//!      -H 'authorization: [redacted]' \\\n  \
+//!      -H 'content-type: application/x-www-form-urlencoded' \\\n  \
+//!      --data-raw 'user=me'"
+//! );
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().debug_curl(true))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use reqwest::{header::CONTENT_TYPE, Method};
+
+use crate::capture::Redaction;
+
+/// Returns `request` as a `curl` command with masked credentials, see [`curl`](crate::curl).
+#[must_use]
+pub fn to_curl(request: &reqwest::Request) -> String {
+    let redaction = Redaction::for_bodies();
+    let method = match *request.method() {
+        Method::GET => String::new(),
+        Method::HEAD => "--head ".to_string(),
+        ref method => format!("-X {method} "),
+    };
+    let mut args = vec![format!(
+        "curl {method}{}",
+        quote(&redaction.url(request.url()))
+    )];
+    for (name, value) in redaction.headers(request.headers()) {
+        args.push(format!("-H {}", quote(&format!("{name}: {value}"))));
+    }
+    if let Some(body) = request.body().and_then(reqwest::Body::as_bytes) {
+        let body = redaction.body(body, request.headers().get(CONTENT_TYPE));
+        args.push(format!("--data-raw {}", quote(&body)));
+    }
+    args.join(" \\\n  ")
+}
+
+/// Prints `request` as a `curl` command to standard error.
+pub(crate) fn print(request: &reqwest::Request) {
+    eprintln!("{}", to_curl(request));
+}
+
+/// Quotes `value` as a single shell argument.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_curl;
+
+    #[test]
+    fn renders_commands() {
+        let client = reqwest::Client::new();
+        let request = client
+            .get("https://example.com/todos?page=2")
+            .build()
+            .unwrap();
+        assert_eq!(to_curl(&request), "curl 'https://example.com/todos?page=2'");
+
+        let request = client
+            .put("https://example.com/users/1")
+            .header("x-api-key", "secret")
+            .json(&serde_json::json!({ "name": "O'Brien", "password": "hunter2" }))
+            .build()
+            .unwrap();
+        assert_eq!(
+            to_curl(&request),
+            "curl -X PUT 'https://example.com/users/1' \\\n  \
+             -H 'x-api-key: [redacted]' \\\n  \
+             -H 'content-type: application/json' \\\n  \
+             --data-raw '{\"name\":\"O'\\''Brien\",\"password\":\"[redacted]\"}'"
+        );
+    }
+}
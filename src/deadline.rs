@@ -0,0 +1,247 @@
+//! Deadlines bounding whole calls, including retries, backoff and reading the body.
+//!
+//! The `timeout` of an endpoint, or of its client, bounds a single request, so
+//! [retries](crate::retry) with backoff can make a call take several times as long. A deadline
+//! bounds everything a call does until its output is decoded: waiting for limits, all attempts,
+//! the sleeps between them and reading the body. A call still running at its deadline is abandoned
+//! and fails with [`Error::DeadlineExceeded`], to which a `fallback` of the endpoint applies as to
+//! any error.
+//! Streams returned by endpoints are read after the call has finished and are not bounded.
+//!
+//! An endpoint declared with `with { deadline: 2s }` has a deadline for every call. [`within`]
+//! sets one for a whole operation made of several calls, e.g. to keep the operation behind a
+//! service's response within its SLO: every call in it ends at the deadline, and calls started
+//! after it fail at once. Calls with both end at the earlier one. Interceptors read the time left
+//! with [`remaining`], e.g. to give up instead of waiting for a backoff which ends after it.
+//!
+//! # Usage
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use api_client::{api, deadline, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn quote(&self, id: u32) -> String {
+//!             GET "https://example.com/quotes/{id}"
+//!             with { deadline: 500ms }
+//!         }
+//!
+//!         fn stock(&self, id: u32) -> String {
+//!             GET "https://example.com/stock/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn product(api: &ExampleApi, id: u32) -> ResultType<(String, String)> {
+//!     deadline::within(Duration::from_secs(2), async {
+//!         Ok((api.quote(id).await?, api.stock(id).await?))
+//!     })
+//!     .await
+//! }
+//! ```
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use futures_util::future::{select, Either};
+
+use crate::{runtime::Runtime, Error, ResultType};
+
+tokio::task_local! {
+    /// Deadline of the operation being run by [`within`].
+    static DEADLINE: Deadline;
+}
+
+/// Point in time a call has to finish by.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Deadline {
+    /// When the call is abandoned.
+    at: Instant,
+    /// Time the call was given, reported in the error.
+    budget: Duration,
+}
+
+impl Deadline {
+    /// Returns the deadline `budget` from now.
+    fn after(budget: Duration) -> Self {
+        Self {
+            at: Instant::now() + budget,
+            budget,
+        }
+    }
+
+    /// Returns the earlier of the deadline and `other`, if any.
+    fn min(self, other: Option<Self>) -> Self {
+        match other {
+            Some(other) if other.at < self.at => other,
+            _ => self,
+        }
+    }
+}
+
+/// Runs `operation` with a deadline `budget` from now, which every call in it has to finish by,
+/// see [`deadline`](self).
+///
+/// Calls in `operation` end at the deadline, `operation` itself is not interrupted.
+pub async fn within<F: Future>(budget: Duration, operation: F) -> F::Output {
+    let deadline = Deadline::after(budget).min(current());
+    DEADLINE.scope(deadline, operation).await
+}
+
+/// Returns the time left until the deadline of the current call or operation, if it has one.
+///
+/// It is zero once the deadline has passed.
+#[must_use]
+pub fn remaining() -> Option<Duration> {
+    current().map(|deadline| deadline.at.saturating_duration_since(Instant::now()))
+}
+
+/// Returns the deadline of the current operation, if any.
+fn current() -> Option<Deadline> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Runs `call`, abandoning it with [`Error::DeadlineExceeded`] if it is still running at the
+/// earlier of the deadline of the current operation and `budget` from now, timed by `runtime`.
+pub(crate) async fn enforce<T>(
+    runtime: &dyn Runtime,
+    budget: Option<Duration>,
+    call: impl Future<Output = ResultType<T>>,
+) -> ResultType<T> {
+    let deadline = match (budget, current()) {
+        (Some(budget), current) => Deadline::after(budget).min(current),
+        (None, Some(current)) => current,
+        (None, None) => return Box::pin(call).await,
+    };
+    let remaining = deadline.at.saturating_duration_since(Instant::now());
+    if remaining == Duration::ZERO {
+        return Err(Error::DeadlineExceeded(deadline.budget));
+    }
+    let call = Box::pin(DEADLINE.scope(deadline, call));
+    match select(call, runtime.sleep(remaining)).await {
+        Either::Left((output, _)) => output,
+        Either::Right(_) => Err(Error::DeadlineExceeded(deadline.budget)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    };
+
+    use super::{remaining, within};
+    use crate::{
+        api,
+        interceptor::{Interceptor, Next},
+        test_server, ApiState, Error, ResultType,
+    };
+
+    /// Interceptor retrying requests after server errors, sleeping 100ms before every retry.
+    #[derive(Debug, Default)]
+    struct Retry {
+        /// Number of attempts.
+        attempts: AtomicUsize,
+        /// Time left until the deadline at the first attempt.
+        remaining: Mutex<Option<Duration>>,
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl Interceptor for Retry {
+        async fn intercept(
+            &self,
+            request: reqwest::Request,
+            next: Next<'_>,
+        ) -> ResultType<reqwest::Response> {
+            self.remaining
+                .lock()
+                .unwrap()
+                .get_or_insert(remaining().unwrap());
+            loop {
+                self.attempts.fetch_add(1, Ordering::SeqCst);
+                let response = next.run(request.try_clone().unwrap()).await?;
+                if !response.status().is_server_error() {
+                    return Ok(response);
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+
+    api!(struct Flaky);
+
+    impl Flaky {
+        api! {
+            fn bounded(&self, base_url: &str) -> StatusCode {
+                GET "{base_url}/bounded"
+                with { deadline: 250ms }
+            }
+
+            fn unbounded(&self, base_url: &str) -> StatusCode {
+                GET "{base_url}/unbounded"
+            }
+        }
+    }
+
+    /// Returns an instance retrying requests to a server which always fails with `503`.
+    async fn flaky() -> (Flaky, Arc<Retry>, String) {
+        let base_url = test_server::serve(|_| test_server::Response::new(503)).await;
+        let retry = Arc::new(Retry::default());
+        let api = Flaky::builder()
+            .state(ApiState::new().interceptor(retry.clone()))
+            .build()
+            .unwrap();
+        (api, retry, base_url)
+    }
+
+    #[test]
+    fn bounds_retries() {
+        tokio_test::block_on(async {
+            let (api, retry, base_url) = flaky().await;
+            let started = Instant::now();
+            let error = api.bounded(&base_url).await.unwrap_err();
+            assert!(matches!(error, Error::DeadlineExceeded(budget) if budget.as_millis() == 250));
+            assert!(started.elapsed() < Duration::from_millis(400));
+            assert_eq!(retry.attempts.load(Ordering::SeqCst), 3);
+            let remaining = retry.remaining.lock().unwrap().unwrap();
+            assert!(
+                remaining > Duration::from_millis(200) && remaining <= Duration::from_millis(250)
+            );
+            assert_eq!(
+                error.to_string(),
+                "the call did not finish within its deadline of 250ms and was abandoned"
+            );
+        });
+    }
+
+    #[test]
+    fn bounds_operations() {
+        tokio_test::block_on(async {
+            let (api, retry, base_url) = flaky().await;
+            let started = Instant::now();
+            let result = within(
+                Duration::from_millis(150),
+                Box::pin(api.unbounded(&base_url)),
+            )
+            .await;
+            assert!(matches!(result, Err(Error::DeadlineExceeded(_))));
+            assert!(started.elapsed() < Duration::from_millis(250));
+            assert_eq!(retry.attempts.load(Ordering::SeqCst), 2);
+            assert!(retry.remaining.lock().unwrap().unwrap() <= Duration::from_millis(150));
+
+            let result = within(Duration::ZERO, Box::pin(api.bounded(&base_url))).await;
+            assert!(matches!(result, Err(Error::DeadlineExceeded(budget)) if budget.is_zero()));
+            assert_eq!(retry.attempts.load(Ordering::SeqCst), 2);
+            assert_eq!(remaining(), None);
+        });
+    }
+}
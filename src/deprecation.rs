@@ -0,0 +1,255 @@
+//! Detection of deprecated endpoints from `Deprecation` ([RFC 9745]) and `Sunset` ([RFC 8594])
+//! response headers.
+//!
+//! APIs announce that an endpoint is going away in the headers of its responses, long before it
+//! stops working, but nobody reads those. The first response of each endpoint of an instance
+//! which carries either header is passed to [`Api::on_deprecation`](crate::Api::on_deprecation),
+//! which by default logs a `WARN` event with the `tracing` feature. Implement it, e.g. in the
+//! `impl Api` block of a struct generated by the [`api!`](crate::api) macro, to report it
+//! elsewhere.
+//!
+//! Endpoints are told apart by their method and URL template, so endpoints of sub-clients, or of
+//! other structs sharing the state, which have the same name are reported separately.
+//!
+//! `Deprecation` is read as the `@<seconds>` date of RFC 9745, or as `true` or an HTTP date as
+//! earlier drafts sent it. Links with the relations `deprecation` and `sunset` point to
+//! documentation of the change.
+//!
+//! Implementations of [`Api`](crate::Api) which don't store their own
+//! [`ApiState`](crate::ApiState) are never notified.
+//!
+//! [RFC 9745]: https://www.rfc-editor.org/rfc/rfc9745
+//! [RFC 8594]: https://www.rfc-editor.org/rfc/rfc8594
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, deprecation::Deprecation, Api};
+//!
+//! api! {
+//!     pub struct ExampleApi {}
+//!
+//!     impl Api {
+//!         fn on_deprecation(&self, endpoint: &str, deprecation: &Deprecation) {
+//!             eprintln!("{endpoint}: {deprecation}");
+//!         }
+//!     }
+//! }
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn todos() -> String {
+//!            GET "https://example.com/v1/todos"
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::header::{HeaderMap, HeaderValue};
+
+use crate::links::Links;
+
+/// Deprecation announced in the headers of a response, see
+/// [`deprecation`](crate::deprecation).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deprecation {
+    /// Whether the response carried a `Deprecation` header, rather than only a `Sunset` header.
+    pub deprecated: bool,
+    /// Date the endpoint was or will be deprecated, if the `Deprecation` header carried one.
+    pub since: Option<SystemTime>,
+    /// Date after which the endpoint may stop working, from the `Sunset` header.
+    pub sunset: Option<SystemTime>,
+    /// Link to documentation of the deprecation or sunset, if any.
+    pub link: Option<String>,
+}
+
+impl Deprecation {
+    /// Reads the deprecation announced in the `headers` of a response, if any.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let deprecation = headers.get("deprecation");
+        let sunset = headers.get("sunset").and_then(http_date);
+        if deprecation.is_none() && sunset.is_none() {
+            return None;
+        }
+        let links = Links::from_headers(headers);
+        let link = links.get("deprecation").or_else(|| links.get("sunset"));
+        Some(Self {
+            deprecated: deprecation.is_some(),
+            since: deprecation.and_then(date),
+            sunset,
+            link: link.map(|link| link.href.clone()),
+        })
+    }
+}
+
+impl fmt::Display for Deprecation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.since {
+            Some(since) if self.deprecated && since > SystemTime::now() => write!(
+                f,
+                "will be deprecated on {}",
+                httpdate::fmt_http_date(since)
+            )?,
+            Some(since) if self.deprecated => {
+                write!(f, "deprecated since {}", httpdate::fmt_http_date(since))?;
+            }
+            _ if self.deprecated => f.write_str("deprecated")?,
+            _ => f.write_str("going away")?,
+        }
+        if let Some(sunset) = self.sunset {
+            write!(f, ", sunset on {}", httpdate::fmt_http_date(sunset))?;
+        }
+        if let Some(link) = &self.link {
+            write!(f, ", see {link}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a `Deprecation` header, as `@<seconds>` or an HTTP date.
+fn date(value: &HeaderValue) -> Option<SystemTime> {
+    let value = value.to_str().ok()?.trim();
+    match value.strip_prefix('@') {
+        Some(seconds) => Some(UNIX_EPOCH + Duration::from_secs(seconds.parse().ok()?)),
+        None => httpdate::parse_http_date(value).ok(),
+    }
+}
+
+/// Parses a header holding an HTTP date.
+fn http_date(value: &HeaderValue) -> Option<SystemTime> {
+    httpdate::parse_http_date(value.to_str().ok()?.trim()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::Deprecation;
+    use crate::{api, test_server};
+
+    /// Returns headers with the given names and values.
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn parses_headers() {
+        assert_eq!(Deprecation::from_headers(&headers(&[])), None);
+
+        let deprecation = Deprecation::from_headers(&headers(&[
+            ("deprecation", "@1688169599"),
+            ("sunset", "Wed, 11 Nov 2026 23:59:59 GMT"),
+            (
+                "link",
+                r#"<https://example.com/changes>; rel="deprecation""#,
+            ),
+        ]))
+        .unwrap();
+        assert!(deprecation.deprecated);
+        assert_eq!(
+            deprecation.since,
+            Some(UNIX_EPOCH + Duration::from_secs(1_688_169_599))
+        );
+        assert_eq!(
+            deprecation.sunset,
+            Some(UNIX_EPOCH + Duration::from_secs(1_794_441_599))
+        );
+        assert_eq!(
+            deprecation.to_string(),
+            "deprecated since Fri, 30 Jun 2023 23:59:59 GMT, sunset on \
+             Wed, 11 Nov 2026 23:59:59 GMT, see https://example.com/changes"
+        );
+
+        let deprecation = Deprecation::from_headers(&headers(&[("deprecation", "true")])).unwrap();
+        assert_eq!((deprecation.deprecated, deprecation.since), (true, None));
+        assert_eq!(deprecation.to_string(), "deprecated");
+
+        let sunset = Deprecation::from_headers(&headers(&[
+            ("sunset", "Wed, 11 Nov 2026 23:59:59 GMT"),
+            ("link", r#"<https://example.com/v2>; rel="sunset""#),
+        ]))
+        .unwrap();
+        assert!(!sunset.deprecated);
+        assert_eq!(sunset.link.as_deref(), Some("https://example.com/v2"));
+        assert_eq!(
+            Deprecation::from_headers(&headers(&[("sunset", "soon")])),
+            None
+        );
+    }
+
+    #[test]
+    fn notifies_once_per_endpoint() {
+        api! {
+            struct Todos {
+                notices: Arc<Mutex<Vec<String>>> = Arc::default(),
+                mod archive {
+                    fn list(&self, base_url: &str) -> String {
+                        GET "{base_url}/v1/archive"
+                    }
+                }
+            }
+
+            impl Api {
+                fn on_deprecation(&self, endpoint: &str, deprecation: &Deprecation) {
+                    self.notices
+                        .lock()
+                        .unwrap()
+                        .push(format!("{endpoint}: {deprecation}"));
+                }
+            }
+        }
+
+        impl Todos {
+            api! {
+                fn list(&self, base_url: &str) -> String {
+                    GET "{base_url}/v1/todos"
+                }
+
+                fn get(&self, base_url: &str) -> String {
+                    GET "{base_url}/v1/todos/1"
+                }
+
+                fn current(&self, base_url: &str) -> String {
+                    GET "{base_url}/v2/todos"
+                }
+            }
+        }
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                if request.path.starts_with("/v1") {
+                    test_server::Response::new(200).header("deprecation", "true")
+                } else {
+                    test_server::Response::new(200)
+                }
+            })
+            .await;
+            let api = Todos::builder().build().unwrap();
+
+            for _ in 0..2 {
+                api.list(&base_url).await.unwrap();
+                api.get(&base_url).await.unwrap();
+                api.current(&base_url).await.unwrap();
+                api.archive().list(&base_url).await.unwrap();
+            }
+            assert_eq!(
+                *api.notices.lock().unwrap(),
+                ["list: deprecated", "get: deprecated", "list: deprecated"]
+            );
+        });
+    }
+}
@@ -0,0 +1,145 @@
+//! Support for discovery documents, such as an `OpenID` Connect provider configuration.
+//!
+//! A [`Discovery`] holds a document fetched from one endpoint so other endpoints can resolve
+//! their URLs from it at runtime rather than hardcoding them.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, discovery::Discovery, Api};
+//! use reqwest::Client;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! pub struct ProviderMetadata {
+//!     pub token_endpoint: String,
+//!     pub userinfo_endpoint: String,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! pub struct UserInfo {
+//!     pub sub: String,
+//! }
+//!
+//! pub struct Oidc {
+//!     client: Client,
+//!     provider: Discovery<ProviderMetadata>,
+//! }
+//!
+//! impl Api for Oidc {
+//!     fn client(&self) -> &Client {
+//!         &self.client
+//!     }
+//! }
+//!
+//! const ISSUER: &str = "https://accounts.example.com";
+//!
+//! impl Oidc {
+//!     api! {
+//!         pub fn provider_metadata() -> Json<ProviderMetadata> {
+//!             GET "{ISSUER}/.well-known/openid-configuration"
+//!         }
+//!
+//!         pub fn user_info(&self) -> Json<UserInfo> {
+//!             GET (self.provider.get_or_discover(self.provider_metadata()).await?.userinfo_endpoint)
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    future::Future,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+/// Client state populated from a discovery document.
+///
+/// The document is fetched on first use and cached until [`Discovery::invalidate`] is called.
+#[derive(Debug)]
+pub struct Discovery<T> {
+    /// The cached document, if it has been discovered.
+    document: RwLock<Option<Arc<T>>>,
+}
+
+impl<T> Default for Discovery<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Discovery<T> {
+    /// Creates an empty instance, the document will be discovered on first use.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            document: RwLock::new(None),
+        }
+    }
+
+    /// Returns the cached document, if it has been discovered.
+    #[must_use]
+    pub fn get(&self) -> Option<Arc<T>> {
+        self.document
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Stores `document`, replacing any previously discovered one.
+    pub fn set(&self, document: T) -> Arc<T> {
+        let document = Arc::new(document);
+        *self
+            .document
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(document.clone());
+        document
+    }
+
+    /// Drops the cached document, so it is discovered again on next use.
+    pub fn invalidate(&self) {
+        *self
+            .document
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    /// Returns the cached document, awaiting `discover` to fetch it if it is not cached yet.
+    ///
+    /// `discover` is usually a call to the discovery endpoint, it is not polled at all when the
+    /// document is already cached.
+    ///
+    /// # Errors
+    /// Returns the error of `discover` if the document had to be fetched and that failed.
+    pub async fn get_or_discover<F, E>(&self, discover: F) -> Result<Arc<T>, E>
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        if let Some(document) = self.get() {
+            return Ok(document);
+        }
+        discover.await.map(|document| self.set(document))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Discovery;
+
+    #[test]
+    fn discovers_once() {
+        tokio_test::block_on(async {
+            let discovery = Discovery::new();
+            let first = discovery
+                .get_or_discover(async { Ok::<_, ()>("first") })
+                .await
+                .unwrap();
+            let second = discovery
+                .get_or_discover(async { Ok::<_, ()>("second") })
+                .await
+                .unwrap();
+            assert_eq!((*first, *second), ("first", "first"));
+
+            discovery.invalidate();
+            assert!(discovery.get().is_none());
+        });
+    }
+}
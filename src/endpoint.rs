@@ -0,0 +1,797 @@
+//! Description of endpoints declared with the [`api!`](crate::api) macro and the executor which
+//! runs them.
+//!
+//! Every generated method expands to an [`EndpointSpec`] and a single call to [`execute`], so all
+//! endpoints share the same request path regardless of their body and return kinds.
+
+#[cfg(feature = "json")]
+use std::time::SystemTime;
+use std::time::{Duration, Instant};
+
+use reqwest::header::HeaderName;
+use serde::Serialize;
+
+pub use crate::protocol::{EndpointMeta, EndpointSpec, Login};
+use crate::{
+    auth, cache, circuit_breaker::CircuitBreaker, curl, deadline, deprecation::Deprecation, grpc,
+    locale, meter, response::ResponseKind, retry, single_flight::SharedResponse, trace, Api, Body,
+    Error, RequestBuilder, ResultType,
+};
+
+/// Changes to the request of a single call, passed to the `*_with` methods generated for
+/// endpoints.
+///
+/// They are applied after [`Api::pre_request`] and the body have been applied.
+pub type RequestOptions<'a> = Box<dyn FnOnce(RequestBuilder) -> RequestBuilder + 'a>;
+
+/// Sends a request for the endpoint described by `spec` and decodes the response as `K`.
+///
+/// Used internally in the [`api!`](crate::api) macro. This only glues together the parts which
+/// are generic over a single type each: encoding the body (`T`), sending the request (`A`) and
+/// decoding the response (`K`), so endpoints sharing those types share most of their code.
+///
+/// # Errors
+/// Returns any error from sending the request or decoding the response.
+#[doc(hidden)]
+pub async fn execute<A, K, T>(
+    api: &A,
+    spec: &EndpointSpec,
+    url: &str,
+    body: Body<'_, T>,
+    options: Option<RequestOptions<'_>>,
+) -> ResultType<K::Output>
+where
+    A: Api + ?Sized,
+    K: ResponseKind,
+    T: Serialize + ?Sized,
+{
+    trace::instrument(spec, async {
+        let state = api.state();
+        let request_id = state.request_id_header_name().map(|header| {
+            let id = crate::idempotency::key();
+            trace::request_id(&id);
+            state.set_last_request_id(&id);
+            (header, id)
+        });
+        let output = call::<A, K, T>(api, spec, url, body, options, request_id.as_ref()).await;
+        match request_id {
+            Some((_, id)) => output.map_err(|error| Error::RequestId {
+                id,
+                error: Box::new(error),
+            }),
+            None => output,
+        }
+    })
+    .await
+}
+
+/// Runs the call of [`execute`] within its deadline, sending `request_id` with it, if any.
+async fn call<A, K, T>(
+    api: &A,
+    spec: &EndpointSpec,
+    url: &str,
+    body: Body<'_, T>,
+    options: Option<RequestOptions<'_>>,
+    request_id: Option<&(&HeaderName, String)>,
+) -> ResultType<K::Output>
+where
+    A: Api + ?Sized,
+    K: ResponseKind,
+    T: Serialize + ?Sized,
+{
+    let state = api.state();
+    #[cfg(debug_assertions)]
+    state.check_call_order(std::any::type_name::<A>(), spec);
+    if !spec.scopes.is_empty() {
+        if let Some(granted) = api.granted_scopes() {
+            granted.require(Some(spec.name), spec.scopes)?;
+        }
+    }
+    let call = run::<A, K, T>(api, spec, url, body, options, request_id);
+    let output = deadline::enforce(state.async_runtime(), spec.deadline, call).await;
+    #[cfg(debug_assertions)]
+    if output.is_ok() {
+        state.record_call(std::any::type_name::<A>(), spec.name);
+    }
+    output
+}
+
+/// Sends the request of [`execute`] through the cache, deduplication, limits and retries of the
+/// instance, and decodes the response.
+async fn run<A, K, T>(
+    api: &A,
+    spec: &EndpointSpec,
+    url: &str,
+    body: Body<'_, T>,
+    options: Option<RequestOptions<'_>>,
+    request_id: Option<&(&HeaderName, String)>,
+) -> ResultType<K::Output>
+where
+    A: Api + ?Sized,
+    K: ResponseKind,
+    T: Serialize + ?Sized,
+{
+    let state = api.state();
+    let mut request = retry::idempotency_key(spec, prepare(api, spec, url, body, options)?);
+    if let Some((header, id)) = request_id {
+        request = request.header(*header, id);
+    }
+    let started = Instant::now();
+    // The turn and slot of the last attempt, held until the response is decoded, unless it is
+    // buffered while they are held.
+    let mut _held = None;
+    let response = if let Some(cache) = state.cache_store() {
+        let mode = spec
+            .cache_mode
+            .unwrap_or_else(|| state.default_cache_mode());
+        let (ignored, background) = (state.cache_ignored_vary(), state.background_revalidation());
+        let send = cache::send(
+            cache,
+            mode,
+            ignored,
+            spec.cache_lifetime,
+            background,
+            request,
+            |request| buffered(api, spec, request),
+        );
+        Box::pin(send).await
+    } else if state.single_flight().is_some() && coalesces(spec) {
+        buffered(api, spec, request).await
+    } else {
+        let send = |request| async move {
+            let held = (state.turn().await, state.slot().await);
+            Ok((dispatch(api, request).await?, held))
+        };
+        retry::send(spec, state.async_runtime(), request, send)
+            .await
+            .map(|(response, held)| {
+                _held = Some(held);
+                response
+            })
+    };
+    let response = match response {
+        Ok(response) => response,
+        Err(error) => {
+            meter::record(spec, None, started, true);
+            return Err(error);
+        }
+    };
+    let status = response.status();
+    trace::status(&response);
+    if let Some(deprecation) = Deprecation::from_headers(response.headers()) {
+        if state.first_deprecation(spec) {
+            api.on_deprecation(spec.name, &deprecation);
+        }
+    }
+    let output = decode::<A, K>(api, spec, response).await;
+    meter::record(spec, Some(status), started, output.is_err());
+    output
+}
+
+/// Checks the gRPC status of `response`, passes the token of a login endpoint to
+/// [`Api::on_login`] and decodes the response as `K`.
+async fn decode<A, K>(
+    api: &A,
+    spec: &EndpointSpec,
+    response: reqwest::Response,
+) -> ResultType<K::Output>
+where
+    A: Api + ?Sized,
+    K: ResponseKind,
+{
+    grpc::check(spec, &response)?;
+    #[cfg(feature = "json")]
+    let response = if K::DETECTS_PROBLEMS {
+        crate::problem::check(response).await?
+    } else {
+        response
+    };
+    let response = match spec.login {
+        Some(login) if response.status().is_success() => {
+            auth::capture(api, login, response).await?
+        }
+        _ => response,
+    };
+    K::from_response_of(api, response, spec).await
+}
+
+/// Builds the request of the endpoint described by `spec` as [`execute`] would send it, after
+/// [`Api::pre_request`] and the body have been applied.
+///
+/// Used internally in the [`api!`](crate::api) macro for the generated `build_*_request` methods.
+///
+/// # Errors
+/// Returns any error from building the request, e.g. an invalid URL.
+#[doc(hidden)]
+pub fn build<A, T>(
+    api: &A,
+    spec: &EndpointSpec,
+    url: &str,
+    body: Body<'_, T>,
+) -> ResultType<reqwest::Request>
+where
+    A: Api + ?Sized,
+    T: Serialize + ?Sized,
+{
+    Ok(prepare(api, spec, url, body, None)?.build()?)
+}
+
+/// Creates the request of an endpoint with the hooks of `api`, the body and `options` applied.
+fn prepare<A, T>(
+    api: &A,
+    spec: &EndpointSpec,
+    url: &str,
+    body: Body<'_, T>,
+    options: Option<RequestOptions<'_>>,
+) -> ResultType<RequestBuilder>
+where
+    A: Api + ?Sized,
+    T: Serialize + ?Sized,
+{
+    let request = api.build_request(spec, url)?;
+    Ok(locale::scope(api.state(), || {
+        let request = body.apply(request);
+        match options {
+            Some(options) => options(request),
+            None => request,
+        }
+    }))
+}
+
+/// Returns whether identical requests of the endpoint in flight may share one response: those of
+/// `GET` endpoints, and of endpoints with methods which aren't idempotent declared
+/// `with { idempotent }`, such as searches sent as `POST`.
+fn coalesces(spec: &EndpointSpec) -> bool {
+    spec.method == reqwest::Method::GET || (spec.idempotent && !spec.method.is_idempotent())
+}
+
+/// Sends a request, again as the retry policy of the endpoint allows, and reads the whole
+/// response, sharing it with identical requests in flight if deduplication is enabled and the
+/// endpoint [`coalesces`] them.
+async fn buffered<A: Api + ?Sized>(
+    api: &A,
+    spec: &EndpointSpec,
+    request: RequestBuilder,
+) -> ResultType<reqwest::Response> {
+    let state = api.state();
+    let attempt = |request| async move {
+        let _turn = state.turn().await;
+        let _slot = state.slot().await;
+        SharedResponse::read(dispatch(api, request).await?).await
+    };
+    let send = |request| retry::send(spec, state.async_runtime(), request, attempt);
+    match state.single_flight().filter(|_| coalesces(spec)) {
+        Some(single_flight) => single_flight.run(request, send).await,
+        None => send(request).await.map(|response| response.to_response()),
+    }
+}
+
+/// Sends a request once the rate limits and the circuit breaker of the instance allow it.
+async fn dispatch<A: Api + ?Sized>(
+    api: &A,
+    request: RequestBuilder,
+) -> ResultType<reqwest::Response> {
+    let state = api.state();
+    state.wait_for_rate_limit().await;
+    let permit = state.breaker().map(CircuitBreaker::permit).transpose()?;
+    let captured = state.capture_buffer().and_then(|capture| {
+        let sent = request.try_clone()?.build().ok()?;
+        Some((capture, sent, Instant::now()))
+    });
+    #[cfg(feature = "json")]
+    let recorded = state.har_recorder().and_then(|har| {
+        let sent = request.try_clone()?.build().ok()?;
+        Some((har, sent, SystemTime::now(), Instant::now()))
+    });
+    if state.prints_curl() {
+        if let Some(sent) = request.try_clone().and_then(|request| request.build().ok()) {
+            curl::print(&sent);
+        }
+    }
+    #[cfg(feature = "tracing")]
+    if let Some(log) = state.request_log() {
+        if let Some(sent) = request.try_clone().and_then(|request| request.build().ok()) {
+            log.request(&sent);
+        }
+    }
+    let response = api.send(request).await;
+    if let Some((capture, sent, started)) = captured {
+        capture.record(&sent, started, &response);
+    }
+    #[cfg(feature = "json")]
+    let response = match recorded {
+        Some((har, sent, started_at, started)) => {
+            Box::pin(har.record(&sent, started_at, started, response)).await
+        }
+        None => response,
+    };
+    if let Some(permit) = permit {
+        permit.complete(matches!(&response, Ok(response) if !response.status().is_server_error()));
+    }
+    let response = response?;
+    state.update_rate_limit(response.headers());
+    #[cfg(feature = "tracing")]
+    if let Some(log) = state.request_log() {
+        return Box::pin(log.response(response)).await;
+    }
+    Ok(response)
+}
+
+/// Parses a duration written in an endpoint declaration, such as `30s` or `500ms`.
+///
+/// Supported units are `ms`, `s`, `m` and `h`. Used internally in the [`api!`](crate::api) macro,
+/// where it is evaluated at compile time so invalid durations fail the build.
+///
+/// # Panics
+/// Panics if `duration` is not a number followed by a supported unit.
+#[doc(hidden)]
+#[must_use]
+pub const fn parse_duration(duration: &str) -> Duration {
+    let bytes = duration.as_bytes();
+    let mut value = 0;
+    let mut index = 0;
+    while index < bytes.len() && bytes[index].is_ascii_digit() {
+        value = value * 10 + (bytes[index] - b'0') as u64;
+        index += 1;
+    }
+    assert!(index > 0, "durations must start with a number, e.g. `30s`");
+
+    match bytes.len() - index {
+        1 if bytes[index] == b's' => Duration::from_secs(value),
+        1 if bytes[index] == b'm' => Duration::from_secs(value * 60),
+        1 if bytes[index] == b'h' => Duration::from_secs(value * 60 * 60),
+        2 if bytes[index] == b'm' && bytes[index + 1] == b's' => Duration::from_millis(value),
+        _ => panic!("unsupported duration unit, expected one of `ms`, `s`, `m` or `h`"),
+    }
+}
+
+/// Checks that `method` is a valid HTTP method name.
+///
+/// Used internally in the [`api!`](crate::api) macro, where it is evaluated at compile time so
+/// invalid method names fail the build.
+///
+/// # Panics
+/// Panics if `method` is empty or contains characters not allowed in a method name.
+#[doc(hidden)]
+#[must_use]
+pub const fn validate_method(method: &str) -> &str {
+    let bytes = method.as_bytes();
+    assert!(!bytes.is_empty(), "method names must not be empty");
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        assert!(
+            byte.is_ascii_alphanumeric()
+                || matches!(
+                    byte,
+                    b'!' | b'#'
+                        | b'$'
+                        | b'%'
+                        | b'&'
+                        | b'\''
+                        | b'*'
+                        | b'+'
+                        | b'-'
+                        | b'.'
+                        | b'^'
+                        | b'_'
+                        | b'`'
+                        | b'|'
+                        | b'~'
+                ),
+            "method names may only contain letters, digits and the characters !#$%&'*+-.^_`|~"
+        );
+        index += 1;
+    }
+    method
+}
+
+/// Creates a method which is not one of the [`reqwest::Method`] constants.
+///
+/// Used internally in the [`api!`](crate::api) macro.
+///
+/// # Panics
+/// Panics if `method` is not a valid method name, which [`validate_method`] rules out.
+#[doc(hidden)]
+#[must_use]
+pub fn extension_method(method: &str) -> reqwest::Method {
+    reqwest::Method::from_bytes(method.as_bytes())
+        .expect("method names are validated at compile time")
+}
+
+/// Reports that the call of the endpoint described by `spec` failed with `error` and falls back,
+/// as an event with the `tracing` feature.
+///
+/// Used internally in the [`api!`](crate::api) macro for `fallback`.
+#[doc(hidden)]
+pub fn fall_back(spec: &EndpointSpec, error: &Error) {
+    trace::fallback(spec, error);
+}
+
+/// Records successful outputs of an endpoint in the [`LastGoodStore`](crate::last_good::LastGoodStore)
+/// of `state` and returns the last one for the same URL in place of an error.
+///
+/// Used internally in the [`api!`](crate::api) macro for `fallback: last_good`.
+///
+/// # Errors
+/// Returns `result` if it is an error and no output for `url` is stored.
+#[cfg(feature = "json")]
+#[doc(hidden)]
+pub fn last_good<T: Serialize + serde::de::DeserializeOwned>(
+    state: &crate::ApiState,
+    spec: &EndpointSpec,
+    url: &str,
+    result: ResultType<T>,
+) -> ResultType<T> {
+    let Some(store) = state.last_good() else {
+        return result;
+    };
+    let key = crate::last_good::LastGoodStore::key(spec.name, url);
+    match result {
+        Ok(output) => {
+            store.insert(key, &output);
+            Ok(output)
+        }
+        Err(error) => {
+            fall_back(spec, &error);
+            store.get(&key).ok_or(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::{parse_duration, EndpointMeta};
+    use crate::last_good::LastGoodStore;
+    use crate::retry::RetryPolicy;
+    use crate::{
+        api,
+        test_server::{self, Response},
+        Api, ApiState, ClientType,
+    };
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Authorization {
+        code: String,
+    }
+
+    struct TestApi {
+        client: ClientType,
+        base_url: String,
+    }
+
+    impl Api for TestApi {
+        fn client(&self) -> &ClientType {
+            &self.client
+        }
+    }
+
+    impl TestApi {
+        api! {
+            #![endpoints]
+
+            fn status(&self, code: u16) -> StatusCode {
+                GET "{}/status/{code}", self.base_url
+            }
+
+            fn echo(&self, request: Json<Vec<u32>>) -> Json<Vec<u32>> {
+                POST "{}/echo", self.base_url
+            }
+
+            fn properties(&self) -> StatusCode {
+                "PROPFIND" "{}/dav", self.base_url
+            }
+
+            fn headers(&self) -> WithHeaders<Json<Vec<u32>>> {
+                GET "{}/headers", self.base_url
+            }
+
+            fn authorize(&self) -> Redirect<Authorization> {
+                GET "{}/authorize", self.base_url
+            }
+
+            fn slow(&self) -> StatusCode {
+                GET "{}/slow", self.base_url TIMEOUT 50ms
+            }
+
+            fn slow_post(&self) -> StatusCode {
+                POST "{}/slow", self.base_url
+                with { timeout: 50ms, idempotent, scopes: ["slow"] }
+            }
+        }
+    }
+
+    #[test]
+    fn executes_endpoints() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                match (request.method.as_str(), request.path.as_str()) {
+                    ("GET", "/status/204") => Response::new(204),
+                    ("PROPFIND", "/dav") => Response::new(207),
+                    ("GET", "/headers") => Response::new(200).header("etag", "\"1\"").json("[1]"),
+                    ("GET", "/authorize") => {
+                        Response::new(302).header("location", "/callback?code=abc")
+                    }
+                    (_, "/slow") => Response::new(200).delay(Duration::from_secs(5)),
+                    ("POST", "/echo") => {
+                        Response::new(200).json(std::str::from_utf8(&request.body).unwrap())
+                    }
+                    _ => Response::new(404),
+                }
+            })
+            .await;
+            let api = TestApi {
+                client: crate::__private::wrap_client(
+                    ApiState::new().follow_redirects(false).default_client(),
+                ),
+                base_url,
+            };
+
+            assert_eq!(api.status(204).await.unwrap().as_u16(), 204);
+            assert_eq!(api.echo(&vec![1, 2, 3]).await.unwrap(), vec![1, 2, 3]);
+            assert_eq!(api.properties().await.unwrap().as_u16(), 207);
+            let headers = api.headers().await.unwrap();
+            assert_eq!(headers.header("etag"), Some("\"1\""));
+            assert_eq!(headers.body, vec![1]);
+            let redirected = api.authorize().await.unwrap();
+            assert_eq!(redirected.url.path(), "/callback");
+            assert_eq!(
+                redirected.query,
+                Authorization {
+                    code: "abc".to_string()
+                }
+            );
+            assert!(api.slow().await.is_err());
+            assert!(api.slow_post().await.is_err());
+        });
+    }
+
+    api! {
+        struct Signed {}
+
+        impl Api {
+            fn pre_request(&self, request: crate::RequestBuilder) -> crate::ResultType<crate::RequestBuilder> {
+                Ok(request.header("x-signature", "s1"))
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    impl Signed {
+        api! {
+            fn update(&self, request: Json<Vec<u32>>, id: u32) -> StatusCode {
+                PUT "https://example.com/todos/{id}"
+            }
+        }
+    }
+
+    #[test]
+    fn builds_requests() {
+        let api = Signed::builder().build().unwrap();
+        let request = api.build_update_request(&vec![1, 2], 7).unwrap();
+        assert_eq!(request.method(), reqwest::Method::PUT);
+        assert_eq!(request.url().as_str(), "https://example.com/todos/7");
+        assert_eq!(request.headers()["x-signature"], "s1");
+        assert_eq!(request.headers()["content-type"], "application/json");
+        let body = request.body().and_then(reqwest::Body::as_bytes);
+        assert_eq!(body, Some(&b"[1,2]"[..]));
+    }
+
+    api!(
+        struct Fallbacks {
+            base_url: String,
+        }
+    );
+
+    impl Fallbacks {
+        api! {
+            fn zero(&self) -> Json<u32> {
+                GET "{}/fail", self.base_url
+                with { fallback: default }
+            }
+
+            fn seven(&self) -> Json<u32> {
+                GET "{}/fail", self.base_url
+                with { timeout: 1s, fallback: value(7) }
+            }
+
+            fn mirror(&self) -> Json<u32> {
+                GET "{}/mirror", self.base_url
+            }
+
+            fn mirrored(&self) -> Json<u32> {
+                GET "{}/fail", self.base_url
+                with { fallback: endpoint(self.mirror()) }
+            }
+
+            fn flaky(&self, id: u32) -> Json<u32> {
+                GET "{}/flaky/{id}", self.base_url
+                with { fallback: last_good }
+            }
+        }
+    }
+
+    #[test]
+    fn applies_fallbacks() {
+        tokio_test::block_on(async {
+            let failing = Arc::new(AtomicBool::new(false));
+            let server_failing = failing.clone();
+            let base_url = test_server::serve(move |request| match request.path.as_str() {
+                "/mirror" => Response::new(200).json("3"),
+                "/flaky/1" if !server_failing.load(Ordering::SeqCst) => {
+                    Response::new(200).json("5")
+                }
+                _ => Response::new(500),
+            })
+            .await;
+            let api = Fallbacks::builder()
+                .base_url(base_url.clone())
+                .state(ApiState::new().last_good_store(LastGoodStore::new(10)))
+                .build()
+                .unwrap();
+
+            assert_eq!(api.zero().await.unwrap(), 0);
+            assert_eq!(api.seven().await.unwrap(), 7);
+            assert_eq!(api.mirrored().await.unwrap(), 3);
+
+            assert_eq!(api.flaky(1).await.unwrap(), 5);
+            failing.store(true, Ordering::SeqCst);
+            assert_eq!(api.flaky(1).await.unwrap(), 5);
+            assert!(api.flaky(2).await.is_err());
+            assert_eq!(
+                api.last_good::<u32>("flaky", &format!("{base_url}/flaky/1")),
+                Some(5)
+            );
+        });
+    }
+
+    #[test]
+    fn applies_options() {
+        let spec = crate::__api_spec!(report POST "https://example.com/{id}" with { timeout: 2s, idempotent, deadline: 5s });
+        assert_eq!(spec.url, "https://example.com/{id}");
+        assert_eq!(spec.timeout, Some(Duration::from_secs(2)));
+        assert_eq!(spec.deadline, Some(Duration::from_secs(5)));
+        assert!(spec.idempotent);
+
+        let spec =
+            crate::__api_spec!(remove DELETE "https://example.com" with { idempotent: false });
+        assert_eq!(spec.timeout, None);
+        assert_eq!(spec.deadline, None);
+        assert!(!spec.idempotent);
+        assert!(crate::__api_spec!(todo GET "https://example.com").idempotent);
+
+        let spec = crate::__api_spec!(todos GET "https://example.com" with { cache: 5m, cache: force_refresh });
+        assert_eq!(spec.cache_lifetime, Some(Duration::from_secs(300)));
+        assert_eq!(spec.cache_mode, Some(crate::cache::CacheMode::ForceRefresh));
+        assert_eq!(spec.retry, None);
+
+        let spec = crate::__api_spec!(quote GET "https://example.com" with { retry: default, timeout: 10s });
+        assert_eq!(spec.retry, Some(RetryPolicy::DEFAULT));
+        assert_eq!(spec.timeout, Some(Duration::from_secs(10)));
+        let spec = crate::__api_spec!(quote GET "https://example.com" with { retry: 5 });
+        assert_eq!(spec.retry, Some(RetryPolicy::new(5)));
+        let spec = crate::__api_spec!(quote GET "https://example.com" with { retry: RetryPolicy::DEFAULT, retry: none });
+        assert_eq!(spec.retry, None);
+    }
+
+    #[test]
+    fn lists_endpoints() {
+        let endpoints = TestApi::endpoints();
+        let names: Vec<_> = endpoints.iter().map(|endpoint| endpoint.name).collect();
+        assert_eq!(
+            names,
+            [
+                "status",
+                "echo",
+                "properties",
+                "headers",
+                "authorize",
+                "slow",
+                "slow_post"
+            ]
+        );
+        assert_eq!(
+            endpoints[0],
+            EndpointMeta {
+                name: "status",
+                method: "GET",
+                url: "{}/status/{code}",
+                params: &["code"],
+                scopes: &[],
+                requires: &[],
+            }
+        );
+        assert_eq!(endpoints[1].params, ["request"]);
+        assert_eq!(endpoints[2].method, "PROPFIND");
+        assert_eq!(endpoints[6].scopes, ["slow"]);
+    }
+
+    api!(struct Session);
+
+    impl Session {
+        api! {
+            #![endpoints]
+
+            fn login(&self, base_url: &str) -> StatusCode {
+                POST "{base_url}/login"
+            }
+
+            fn profile(&self, base_url: &str) -> String {
+                GET "{base_url}/profile"
+                with { requires: [login] }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "`profile` was called before `login`")]
+    fn checks_call_order() {
+        assert_eq!(Session::endpoints()[1].requires, ["login"]);
+        tokio_test::block_on(async {
+            let base_url =
+                test_server::serve(|_| test_server::Response::new(200).body("jane")).await;
+            let api = Session::new();
+            api.login(&base_url).await.unwrap();
+            assert_eq!(api.profile(&base_url).await.unwrap(), "jane");
+
+            let restored = Session::new();
+            restored.state().mark_called("login");
+            assert_eq!(restored.profile(&base_url).await.unwrap(), "jane");
+
+            Session::new().profile(&base_url).await.ok();
+        });
+    }
+
+    api! {
+        struct Portal {
+            mod admin {
+                fn login(&self, base_url: &str) -> StatusCode {
+                    POST "{base_url}/admin/login"
+                }
+
+                fn users(&self, base_url: &str) -> StatusCode {
+                    GET "{base_url}/admin/users"
+                    with { requires: [login] }
+                }
+            }
+            mod customer {
+                fn login(&self, base_url: &str) -> StatusCode {
+                    POST "{base_url}/login"
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "`users` was called before `login`")]
+    fn checks_call_order_per_sub_client() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|_| test_server::Response::new(200)).await;
+            let portal = Portal::builder().build().unwrap();
+            portal.admin().login(&base_url).await.unwrap();
+            portal.admin().users(&base_url).await.unwrap();
+
+            let portal = Portal::builder().build().unwrap();
+            portal.customer().login(&base_url).await.unwrap();
+            portal.admin().users(&base_url).await.ok();
+        });
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms"), Duration::from_millis(500));
+        assert_eq!(parse_duration("30s"), Duration::from_secs(30));
+        assert_eq!(parse_duration("2m"), Duration::from_secs(120));
+        assert_eq!(parse_duration("1h"), Duration::from_secs(3600));
+    }
+}
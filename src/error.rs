@@ -0,0 +1,279 @@
+//! Error type of the requests sent by [`Api`](crate::Api) implementations.
+
+use std::fmt;
+
+use crate::ClientError;
+
+/// Error returned by endpoints declared with the [`api!`](crate::api) macro.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Sending the request or reading the response failed.
+    Client(ClientError),
+    /// A [`Transport`](crate::transport::Transport) other than reqwest failed to send the request,
+    /// or the request could not be handed to it.
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    /// A [`Redirect`](crate::response::Redirect) endpoint responded without redirecting.
+    NotRedirected(reqwest::StatusCode),
+    /// The target of a [`Redirect`](crate::response::Redirect) endpoint could not be parsed.
+    InvalidRedirect(String),
+    /// The response body of a [`Download`](crate::response::Download) endpoint could not be
+    /// written into its file.
+    Io(std::io::Error),
+    /// A response body doesn't match its checksum, see [`checksum`](crate::checksum).
+    ChecksumMismatch(crate::checksum::ChecksumMismatch),
+    /// The server rejected a step of a resumable upload, see [`tus`](crate::tus).
+    Tus(String),
+    /// The response body of a [`FrameStream`](crate::response::FrameStream) or gRPC-Web endpoint
+    /// is not made of valid frames.
+    InvalidFrame(String),
+    /// The response body is not the expected JSON.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    Json(serde_json::Error),
+    /// The response of a GraphQL endpoint carried errors, see [`graphql`](crate::graphql).
+    #[cfg(feature = "graphql")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "graphql")))]
+    GraphQl(crate::graphql::GraphQlErrors),
+    /// The WebSocket connection of a GraphQL subscription failed, see
+    /// [`ws`](crate::graphql::ws).
+    #[cfg(feature = "graphql-ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "graphql-ws")))]
+    WebSocket(String),
+    /// A JSON-RPC call failed with an error object, see [`jsonrpc`](crate::jsonrpc).
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    Rpc(crate::jsonrpc::RpcError),
+    /// The server answered with a Problem Details document, see [`problem`](crate::problem).
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    Problem(Box<crate::problem::ProblemDetails>),
+    /// No response for the URL is stored and the request was not sent, see
+    /// [`CacheMode::OnlyIfCached`](crate::cache::CacheMode::OnlyIfCached).
+    NotCached(String),
+    /// The request was not sent because the token lacks scopes the endpoint requires, see
+    /// [`permissions`](crate::permissions).
+    MissingScopes(crate::permissions::MissingScopes),
+    /// The request was not sent because its URL is not allowed by the
+    /// [`UrlPolicy`](crate::url_policy::UrlPolicy) of the instance.
+    UrlRejected(crate::url_policy::UrlRejected),
+    /// The request was not sent because its token contains characters which are not allowed in
+    /// headers, see [`auth`](crate::auth).
+    InvalidToken,
+    /// The request was not sent because it could not be signed, see [`signing`](crate::signing).
+    Signing(String),
+    /// A received message has no valid HTTP message signature, see
+    /// [`MessageVerifier`](crate::signing::message::MessageVerifier).
+    InvalidSignature(String),
+    /// A root certificate or client identity could not be loaded, see [`tls`](crate::tls).
+    Certificate(String),
+    /// The server presented no certificate matching the pins of the instance, see
+    /// [`tls`](crate::tls#pinning).
+    PinMismatch(crate::tls::PinMismatch),
+    /// A proxy URL is invalid or needs a feature which is not enabled, see
+    /// [`proxy`](crate::proxy).
+    InvalidProxy(String),
+    /// The request was not sent because a query parameter could not be encoded, see
+    /// [`query`](crate::query).
+    InvalidQuery(String),
+    /// The call did not finish within its deadline and was abandoned, see
+    /// [`deadline`](crate::deadline).
+    DeadlineExceeded(std::time::Duration),
+    /// The call was aborted by its cancellation signal, see [`cancel`](crate::cancel).
+    Cancelled,
+    /// The response of a login endpoint carried no token where it was declared, see
+    /// [`auth`](crate::auth#login-endpoints).
+    NoLoginToken(crate::endpoint::Login),
+    /// The request was not sent because the [circuit breaker](crate::circuit_breaker) is open.
+    CircuitOpen,
+    /// The response carried a `grpc-status` other than `0`, see [`grpc`](crate::grpc).
+    Grpc(crate::grpc::GrpcStatus),
+    /// A change was rejected because the resource changed since its `ETag` was read, see
+    /// [`etag`](crate::etag).
+    PreconditionFailed(crate::etag::PreconditionFailed),
+    /// A token or the configuration of an `OpenID` Connect provider was rejected.
+    #[cfg(feature = "oidc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "oidc")))]
+    Oidc(crate::oidc::OidcError),
+    /// An error labelled with the operation it occurred in, see [`context`](crate::context).
+    Context {
+        /// Label of the operation, e.g. `syncing invoice 42`.
+        label: String,
+        /// The labelled error.
+        error: Box<Error>,
+    },
+    /// An error of a call which sent the request id `id`, see [`request_id`](crate::request_id).
+    RequestId {
+        /// Request id sent with the call, e.g. to quote it to the support of the API.
+        id: String,
+        /// The error of the call.
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Returns the underlying client error, if the request itself failed.
+    #[must_use]
+    pub fn client_error(&self) -> Option<&ClientError> {
+        match self.root() {
+            Error::Client(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Labels the error with the operation it occurred in, see [`context`](crate::context).
+    #[must_use]
+    pub fn context(self, label: impl Into<String>) -> Self {
+        Error::Context {
+            label: label.into(),
+            error: Box::new(self),
+        }
+    }
+
+    /// Returns the error without the labels attached with [`Error::context`] and the request id
+    /// of the call.
+    #[must_use]
+    pub fn root(&self) -> &Self {
+        let mut error = self;
+        while let Error::Context { error: inner, .. } | Error::RequestId { error: inner, .. } =
+            error
+        {
+            error = inner;
+        }
+        error
+    }
+
+    /// Returns the request id sent with the call which failed, if request ids are enabled, see
+    /// [`request_id`](crate::request_id).
+    #[must_use]
+    pub fn request_id(&self) -> Option<&str> {
+        let mut error = self;
+        loop {
+            match error {
+                Error::RequestId { id, .. } => return Some(id),
+                Error::Context { error: inner, .. } => error = inner,
+                _ => return None,
+            }
+        }
+    }
+
+    /// Returns the labels attached with [`Error::context`], from the outermost to the innermost.
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        let mut error = self;
+        std::iter::from_fn(move || loop {
+            match error {
+                Error::Context {
+                    label,
+                    error: inner,
+                } => {
+                    error = inner;
+                    return Some(label.as_str());
+                }
+                Error::RequestId { error: inner, .. } => error = inner,
+                _ => return None,
+            }
+        })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Client(error) => error.fmt(f),
+            Error::Transport(error) => write!(f, "transport failed to send the request: {error}"),
+            Error::NotRedirected(status) => {
+                write!(
+                    f,
+                    "expected a redirect, got a response with status {status}"
+                )
+            }
+            Error::InvalidRedirect(reason) => write!(f, "invalid redirect target: {reason}"),
+            Error::Io(error) => write!(f, "failed to write the response body: {error}"),
+            Error::ChecksumMismatch(mismatch) => mismatch.fmt(f),
+            Error::Tus(reason) => write!(f, "tus upload failed: {reason}"),
+            Error::InvalidFrame(reason) => write!(f, "invalid frame: {reason}"),
+            #[cfg(feature = "json")]
+            Error::Json(error) => write!(f, "invalid JSON response: {error}"),
+            #[cfg(feature = "json")]
+            Error::Problem(problem) => problem.fmt(f),
+            #[cfg(feature = "json")]
+            Error::Rpc(error) => error.fmt(f),
+            #[cfg(feature = "graphql")]
+            Error::GraphQl(errors) => errors.fmt(f),
+            #[cfg(feature = "graphql-ws")]
+            Error::WebSocket(reason) => write!(f, "WebSocket connection failed: {reason}"),
+            Error::NotCached(url) => {
+                write!(f, "no cached response for {url}, the request was not sent")
+            }
+            Error::MissingScopes(missing) => missing.fmt(f),
+            Error::UrlRejected(rejected) => rejected.fmt(f),
+            Error::InvalidToken => {
+                f.write_str("token is not a valid header value, the request was not sent")
+            }
+            Error::Signing(reason) => {
+                write!(f, "request could not be signed, it was not sent: {reason}")
+            }
+            Error::InvalidSignature(reason) => write!(f, "invalid message signature: {reason}"),
+            Error::Certificate(reason) => write!(f, "invalid certificate: {reason}"),
+            Error::PinMismatch(mismatch) => mismatch.fmt(f),
+            Error::InvalidProxy(reason) => write!(f, "invalid proxy: {reason}"),
+            Error::InvalidQuery(reason) => write!(
+                f,
+                "query parameter could not be encoded, the request was not sent: {reason}"
+            ),
+            Error::DeadlineExceeded(budget) => write!(
+                f,
+                "the call did not finish within its deadline of {budget:?} and was abandoned"
+            ),
+            Error::Cancelled => f.write_str("the call was cancelled"),
+            Error::NoLoginToken(login) => write!(f, "login response carried no token in {login}"),
+            Error::CircuitOpen => f.write_str("circuit breaker is open, the request was not sent"),
+            Error::Grpc(status) => status.fmt(f),
+            Error::PreconditionFailed(failed) => failed.fmt(f),
+            #[cfg(feature = "oidc")]
+            Error::Oidc(error) => error.fmt(f),
+            Error::Context { label, error } => write!(f, "{label}: {error}"),
+            Error::RequestId { id, error } => write!(f, "{error} (request id {id})"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Client(error) => Some(error),
+            Error::Transport(error) => Some(error.as_ref()),
+            Error::Io(error) => Some(error),
+            #[cfg(feature = "json")]
+            Error::Json(error) => Some(error),
+            #[cfg(feature = "oidc")]
+            Error::Oidc(error) => Some(error),
+            Error::PinMismatch(mismatch) => Some(mismatch),
+            Error::Context { error, .. } | Error::RequestId { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ClientError> for Error {
+    fn from(error: ClientError) -> Self {
+        #[cfg(feature = "rustls-tls")]
+        if let Some(mismatch) = crate::tls::pin_mismatch(&error) {
+            return Error::PinMismatch(mismatch);
+        }
+        Error::Client(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+#[cfg(feature = "middleware")]
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        Error::Client(error.into())
+    }
+}
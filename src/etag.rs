@@ -0,0 +1,275 @@
+//! Optimistic concurrency with `ETag` and `If-Match`.
+//!
+//! A client which reads a resource, changes it and writes it back overwrites the changes other
+//! clients made in between. APIs supporting conditional requests prevent that: the `ETag` of the
+//! resource read is sent back as `If-Match`, and the server rejects the write with
+//! `412 Precondition Failed` if the resource changed since.
+//!
+//! An [`EtagTracker`] added with [`ApiState::interceptor`](crate::ApiState::interceptor) does
+//! this for every endpoint of the instance:
+//! - It remembers the `ETag` of successful `GET` and `HEAD` responses per resource, the URL
+//!   without its query and fragment.
+//! - It sends the remembered `ETag` as `If-Match` with `PUT`, `PATCH` and `DELETE` requests to
+//!   the same resource, unless they carry an `If-Match` header already.
+//! - Requests carrying `If-Match` which are rejected with `412 Precondition Failed` fail with
+//!   [`Error::PreconditionFailed`], after which the resource should be read again before it is
+//!   changed.
+//! - Successful writes replace the remembered `ETag` with the one of their response, or forget
+//!   it if there is none, as do successful `DELETE` requests.
+//!
+//! Weak `ETag`s, such as `W/"v1"`, never match `If-Match` and are not remembered.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, etag::EtagTracker, ApiState, Error, ResultType};
+//!
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Todo {
+//!     title: String,
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn todo(id: u32) -> Json<Todo> {
+//!            GET "https://example.com/todos/{id}"
+//!         }
+//!
+//!         fn replace_todo(request: Json<Todo>, id: u32) -> StatusCode {
+//!            PUT "https://example.com/todos/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn rename(api: &ExampleApi, title: &str) -> ResultType<()> {
+//!     loop {
+//!         let mut todo = api.todo(1).await?;
+//!         todo.title = title.to_string();
+//!         match api.replace_todo(&todo, 1).await {
+//!             // Changed by someone else in between, read it again.
+//!             Err(Error::PreconditionFailed(_)) => continue,
+//!             result => return result.map(|_| ()),
+//!         }
+//!     }
+//! }
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().interceptor(EtagTracker::new()))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Mutex, PoisonError},
+};
+
+use reqwest::{
+    header::{HeaderValue, ETAG, IF_MATCH},
+    Method, StatusCode, Url,
+};
+
+use crate::{
+    interceptor::{Interceptor, Next},
+    Error, ResultType,
+};
+
+/// Remembers the `ETag`s of resources and sends them as `If-Match` with changes, see
+/// [`etag`](crate::etag).
+#[derive(Debug, Default)]
+pub struct EtagTracker {
+    /// Strong `ETag`s of the resources read, by resource URL.
+    etags: Mutex<HashMap<String, HeaderValue>>,
+}
+
+impl EtagTracker {
+    /// Creates a tracker which has not seen any `ETag`s.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the `ETag` remembered for the resource at `url`, if any.
+    #[must_use]
+    pub fn etag(&self, url: &str) -> Option<String> {
+        let resource = resource(&Url::parse(url).ok()?);
+        let etags = self.lock();
+        etags.get(&resource)?.to_str().ok().map(str::to_string)
+    }
+
+    /// Forgets the `ETag` of the resource at `url`, so the next change is sent unconditionally.
+    pub fn forget(&self, url: &str) {
+        if let Ok(url) = Url::parse(url) {
+            self.lock().remove(&resource(&url));
+        }
+    }
+
+    /// Forgets all `ETag`s.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Locks the remembered `ETag`s.
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, HeaderValue>> {
+        self.etags.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Interceptor for EtagTracker {
+    async fn intercept(
+        &self,
+        mut request: reqwest::Request,
+        next: Next<'_>,
+    ) -> ResultType<reqwest::Response> {
+        let resource = resource(request.url());
+        let method = request.method().clone();
+        let changes = matches!(method, Method::PUT | Method::PATCH | Method::DELETE);
+        if changes && !request.headers().contains_key(IF_MATCH) {
+            if let Some(etag) = self.lock().get(&resource) {
+                request.headers_mut().insert(IF_MATCH, etag.clone());
+            }
+        }
+        let sent = request.headers().get(IF_MATCH).cloned();
+
+        let response = next.run(request).await?;
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+            .cloned();
+        if let (Some(sent), StatusCode::PRECONDITION_FAILED) = (&sent, response.status()) {
+            return Err(Error::PreconditionFailed(PreconditionFailed {
+                url: resource,
+                etag: String::from_utf8_lossy(sent.as_bytes()).into_owned(),
+                current: etag.map(|etag| String::from_utf8_lossy(etag.as_bytes()).into_owned()),
+            }));
+        }
+        if response.status().is_success() {
+            let mut etags = self.lock();
+            match (method, etag) {
+                (Method::GET | Method::HEAD | Method::PUT | Method::PATCH, Some(etag)) => {
+                    etags.insert(resource, etag);
+                }
+                (Method::DELETE, _) | (Method::PUT | Method::PATCH, None) => {
+                    etags.remove(&resource);
+                }
+                _ => {}
+            }
+        }
+        Ok(response)
+    }
+}
+
+/// Change rejected because the resource changed since its `ETag` was read, see
+/// [`Error::PreconditionFailed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreconditionFailed {
+    /// URL of the resource, without its query and fragment.
+    pub url: String,
+    /// `ETag` sent as `If-Match`.
+    pub etag: String,
+    /// Current `ETag` of the resource, if the server sent it with the rejection.
+    pub current: Option<String>,
+}
+
+impl fmt::Display for PreconditionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} changed since its ETag {} was read, the change was rejected",
+            self.url, self.etag
+        )
+    }
+}
+
+/// Returns the URL of the resource at `url`, without its query and fragment.
+fn resource(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_query(None);
+    url.set_fragment(None);
+    url.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{EtagTracker, PreconditionFailed};
+    use crate::{api, test_server, ApiState, Error};
+
+    api!(struct Todos);
+
+    impl Todos {
+        api! {
+            fn get(&self, base_url: &str, id: u32) -> String {
+                GET "{base_url}/todos/{id}?fields=all"
+            }
+
+            fn put(&self, request: Json<&str>, base_url: &str, id: u32) -> StatusCode {
+                PUT "{base_url}/todos/{id}"
+            }
+
+            fn delete(&self, base_url: &str, id: u32) -> StatusCode {
+                DELETE "{base_url}/todos/{id}"
+            }
+        }
+    }
+
+    #[test]
+    fn sends_if_match() {
+        tokio_test::block_on(async {
+            let version = Arc::new(Mutex::new(1));
+            let server_version = version.clone();
+            let base_url = test_server::serve(move |request| {
+                let mut version = server_version.lock().unwrap();
+                let etag = format!("\"v{version}\"");
+                match (request.method.as_str(), request.header("if-match")) {
+                    ("GET", _) if request.path.starts_with("/todos/2") => {
+                        test_server::Response::new(200).header("etag", "W/\"weak\"")
+                    }
+                    ("GET", _) => test_server::Response::new(200).header("etag", &etag),
+                    (_, Some(sent)) if sent != etag => {
+                        test_server::Response::new(412).header("etag", &etag)
+                    }
+                    ("PUT", _) => {
+                        *version += 1;
+                        test_server::Response::new(204).header("etag", &format!("\"v{version}\""))
+                    }
+                    _ => test_server::Response::new(204),
+                }
+            })
+            .await;
+            let tracker = Arc::new(EtagTracker::new());
+            let api = Todos::builder()
+                .state(ApiState::new().interceptor(tracker.clone()))
+                .build()
+                .unwrap();
+            let url = format!("{base_url}/todos/1");
+
+            api.get(&base_url, 1).await.unwrap();
+            assert_eq!(tracker.etag(&url).as_deref(), Some("\"v1\""));
+            api.put(&"a", &base_url, 1).await.unwrap();
+            assert_eq!(tracker.etag(&url).as_deref(), Some("\"v2\""));
+            api.put(&"b", &base_url, 1).await.unwrap();
+
+            *version.lock().unwrap() = 7;
+            let error = api.put(&"c", &base_url, 1).await.unwrap_err();
+            assert!(matches!(
+                error,
+                Error::PreconditionFailed(PreconditionFailed { ref etag, ref current, .. })
+                    if etag == "\"v3\"" && current.as_deref() == Some("\"v7\"")
+            ));
+            api.get(&base_url, 1).await.unwrap();
+            api.put(&"c", &base_url, 1).await.unwrap();
+
+            api.delete(&base_url, 1).await.unwrap();
+            assert_eq!(tracker.etag(&url), None);
+            api.get(&base_url, 2).await.unwrap();
+            assert_eq!(tracker.etag(&format!("{base_url}/todos/2")), None);
+            api.put(&"d", &base_url, 2).await.unwrap();
+        });
+    }
+}
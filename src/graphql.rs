@@ -0,0 +1,234 @@
+//! GraphQL queries and mutations over HTTP.
+//!
+//! Endpoints declared with `request: GraphQl<V>` take a [`GraphQlRequest<V>`](GraphQlRequest),
+//! sent as the standard JSON envelope of the query, its variables of type `V` and the name of the
+//! operation. Endpoints returning `GraphQl<T>` unwrap the `data` of the response as `T`, and fail
+//! with [`Error::GraphQl`](crate::Error::GraphQl) if it carries `errors`, keeping the partial
+//! data the server returned with them.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, graphql::GraphQlRequest, ResultType};
+//!
+//! #[derive(serde::Serialize)]
+//! struct UserVariables {
+//!     id: u32,
+//! }
+//!
+//! #[derive(serde::Deserialize)]
+//! struct UserData {
+//!     user: User,
+//! }
+//!
+//! #[derive(serde::Deserialize)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! const USER: &str = "query User($id: ID!) { user(id: $id) { name } }";
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn query_user(request: GraphQl<UserVariables>) -> GraphQl<UserData> {
+//!            POST "https://example.com/graphql"
+//!         }
+//!     }
+//! }
+//!
+//! async fn user_name(api: &ExampleApi) -> ResultType<String> {
+//!     let request = GraphQlRequest::new(USER, UserVariables { id: 1 }).operation_name("User");
+//!     Ok(api.query_user(&request).await?.user.name)
+//! }
+//! ```
+
+use std::{borrow::Cow, fmt, marker::PhantomData};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{endpoint::EndpointSpec, response::ResponseKind, Error, ResultType};
+
+#[cfg(feature = "graphql-ws")]
+#[cfg_attr(docsrs, doc(cfg(feature = "graphql-ws")))]
+pub mod ws;
+
+/// Request body of a GraphQL endpoint, see [`graphql`](crate::graphql).
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphQlRequest<V> {
+    /// The query or mutation document.
+    pub query: Cow<'static, str>,
+    /// Variables of the operation.
+    pub variables: V,
+    /// Name of the operation to run, if the document holds several.
+    #[serde(rename = "operationName", skip_serializing_if = "Option::is_none")]
+    pub operation_name: Option<Cow<'static, str>>,
+}
+
+impl<V> GraphQlRequest<V> {
+    /// Creates a request running `query` with `variables`.
+    pub fn new(query: impl Into<Cow<'static, str>>, variables: V) -> Self {
+        Self {
+            query: query.into(),
+            variables,
+            operation_name: None,
+        }
+    }
+
+    /// Sets the name of the operation to run.
+    #[must_use]
+    pub fn operation_name(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.operation_name = Some(name.into());
+        self
+    }
+}
+
+/// Error reported in the `errors` of a GraphQL response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphQlError {
+    /// Description of the error.
+    pub message: String,
+    /// Locations in the document the error refers to.
+    #[serde(default)]
+    pub locations: Vec<GraphQlLocation>,
+    /// Path of the field which failed, as names and list indices.
+    #[serde(default)]
+    pub path: Vec<serde_json::Value>,
+    /// Further details defined by the server.
+    #[serde(default)]
+    pub extensions: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Location in a GraphQL document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphQlLocation {
+    /// Line, starting at `1`.
+    pub line: u32,
+    /// Column, starting at `1`.
+    pub column: u32,
+}
+
+/// Errors of a GraphQL response, see [`Error::GraphQl`](crate::Error::GraphQl).
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphQlErrors {
+    /// The errors, in the order the server reported them.
+    pub errors: Vec<GraphQlError>,
+    /// Data the server returned along with the errors, if any.
+    pub data: Option<serde_json::Value>,
+}
+
+impl fmt::Display for GraphQlErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("GraphQL request failed")?;
+        for (index, error) in self.errors.iter().enumerate() {
+            f.write_str(if index == 0 { ": " } else { "; " })?;
+            f.write_str(&error.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the `data` of a GraphQL response as `T`, see [`graphql`](crate::graphql).
+#[derive(Debug)]
+pub struct GraphQl<T>(PhantomData<T>);
+
+/// Body of a GraphQL response.
+#[derive(Deserialize)]
+struct Envelope {
+    /// Result of the operation.
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    /// Errors of the operation.
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[async_trait::async_trait(?Send)]
+impl<T: DeserializeOwned> ResponseKind for GraphQl<T> {
+    type Output = T;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        let envelope: Envelope = response.json().await?;
+        if !envelope.errors.is_empty() {
+            return Err(Error::GraphQl(GraphQlErrors {
+                errors: envelope.errors,
+                data: envelope.data,
+            }));
+        }
+        serde_json::from_value(envelope.data.unwrap_or_default()).map_err(Error::Json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GraphQlRequest;
+    use crate::{api, test_server, Api, Error};
+
+    #[derive(serde::Serialize)]
+    struct Variables {
+        id: u32,
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Data {
+        name: String,
+    }
+
+    api!(struct Users);
+
+    impl Users {
+        api! {
+            fn user(&self, request: GraphQl<Variables>, base_url: &str) -> GraphQl<Data> {
+                POST "{base_url}/graphql"
+            }
+        }
+    }
+
+    #[test]
+    fn unwraps_data_and_errors() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                assert_eq!(body["query"], "query User($id: ID!) { name }");
+                assert_eq!(body["operationName"], "User");
+                if body["variables"]["id"] == 1 {
+                    test_server::Response::new(200).json(r#"{"data": {"name": "Ada"}}"#)
+                } else {
+                    test_server::Response::new(200).json(
+                        r#"{
+                            "data": null,
+                            "errors": [{
+                                "message": "user not found",
+                                "locations": [{"line": 1, "column": 19}],
+                                "path": ["user"],
+                                "extensions": {"code": "NOT_FOUND"}
+                            }]
+                        }"#,
+                    )
+                }
+            })
+            .await;
+            let api = Users::new();
+            let query = |id| {
+                GraphQlRequest::new("query User($id: ID!) { name }", Variables { id })
+                    .operation_name("User")
+            };
+
+            let user = api.user(&query(1), &base_url).await.unwrap();
+            assert_eq!(user.name, "Ada");
+
+            let error = api.user(&query(2), &base_url).await.unwrap_err();
+            assert_eq!(error.to_string(), "GraphQL request failed: user not found");
+            let errors = match error {
+                Error::GraphQl(errors) => errors,
+                error => panic!("expected GraphQL errors, got {error:?}"),
+            };
+            assert_eq!(errors.errors[0].locations[0].column, 19);
+            assert_eq!(errors.errors[0].extensions["code"], "NOT_FOUND");
+            assert_eq!(errors.data, None);
+        });
+    }
+}
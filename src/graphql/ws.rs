@@ -0,0 +1,462 @@
+//! GraphQL subscriptions over WebSocket, with the `graphql-transport-ws` protocol.
+//!
+//! Endpoints declared with `request: GraphQl<V>` and returning `GraphQlSubscription<T>` open a
+//! WebSocket to the declared URL, `ws://` and `wss://` or `http://` and `https://`, through the
+//! same client and hooks as every endpoint, and subscribe with the [`GraphQlRequest`]. The
+//! returned [`Subscription`] yields the `data` of every event as `T` until the server completes
+//! it.
+//!
+//! The payload of the `connection_init` message comes from
+//! [`Api::graphql_init_payload`](crate::Api::graphql_init_payload), which by default passes on
+//! the `Authorization` header the `pre_request` hook sets, as servers often authenticate
+//! subscriptions with it.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, graphql::GraphQlRequest, ResultType};
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Review {
+//!     stars: u8,
+//! }
+//!
+//! #[derive(serde::Deserialize)]
+//! struct ReviewAdded {
+//!     review_added: Review,
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn reviews(request: GraphQl<()>) -> GraphQlSubscription<ReviewAdded> {
+//!            GET "wss://example.com/graphql"
+//!         }
+//!     }
+//! }
+//!
+//! async fn watch(api: &ExampleApi) -> ResultType<()> {
+//!     let request = GraphQlRequest::new("subscription { review_added { stars } }", ());
+//!     let mut reviews = api.reviews(&request).await?;
+//!     while let Some(event) = reviews.next().await {
+//!         println!("{} stars", event?.review_added.stars);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::{fmt, marker::PhantomData};
+
+use futures_util::{SinkExt, StreamExt};
+use reqwest::{
+    header::{
+        HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL,
+        SEC_WEBSOCKET_VERSION, UPGRADE,
+    },
+    StatusCode, Upgraded,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::{client::generate_key, derive_accept_key},
+        protocol::Role,
+        Message,
+    },
+    WebSocketStream,
+};
+
+use super::{Envelope, GraphQlError, GraphQlErrors, GraphQlRequest};
+use crate::{endpoint::EndpointSpec, response::Raw, Api, Body, Error, RequestBuilder, ResultType};
+
+/// WebSocket subprotocol of the `graphql-transport-ws` protocol.
+pub const PROTOCOL: &str = "graphql-transport-ws";
+
+/// Id of the one operation of a subscription's connection.
+const OPERATION_ID: &str = "1";
+
+/// Message of the `graphql-transport-ws` protocol.
+#[derive(Serialize, Deserialize)]
+struct ProtocolMessage<P> {
+    /// Type of the message, e.g. `next`.
+    #[serde(rename = "type")]
+    kind: String,
+    /// Id of the operation the message belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    /// Payload of the message.
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    payload: Option<P>,
+}
+
+impl<P> ProtocolMessage<P> {
+    /// Creates a message of type `kind` for the operation of the subscription.
+    fn new(kind: &str, payload: Option<P>) -> Self {
+        Self {
+            kind: kind.to_string(),
+            id: Some(OPERATION_ID.to_string()),
+            payload,
+        }
+    }
+}
+
+/// Events of a GraphQL subscription, see [`ws`](crate::graphql::ws).
+pub struct Subscription<T> {
+    /// The WebSocket of the subscription.
+    socket: WebSocketStream<Upgraded>,
+    /// Whether the subscription completed or failed.
+    done: bool,
+    /// Type of the events.
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for Subscription<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: DeserializeOwned> Subscription<T> {
+    /// Waits for the next event, or returns `None` once the server completed the subscription or
+    /// closed the connection.
+    ///
+    /// Events carrying `errors` fail with [`Error::GraphQl`]. After an error, no further events
+    /// are read.
+    pub async fn next(&mut self) -> Option<ResultType<T>> {
+        if self.done {
+            return None;
+        }
+        let event = self.next_event().await.transpose();
+        if !matches!(event, Some(Ok(_))) {
+            self.done = true;
+        }
+        event
+    }
+
+    /// Turns the events into a [`Stream`](futures_util::Stream).
+    pub fn into_stream(self) -> impl futures_util::Stream<Item = ResultType<T>> {
+        futures_util::stream::unfold(self, |mut subscription| async move {
+            subscription.next().await.map(|event| (event, subscription))
+        })
+    }
+
+    /// Ends the subscription and closes its connection.
+    ///
+    /// # Errors
+    /// Returns an error if the server could not be told.
+    pub async fn close(mut self) -> ResultType<()> {
+        if !self.done {
+            send(
+                &mut self.socket,
+                &ProtocolMessage::<()>::new("complete", None),
+            )
+            .await?;
+        }
+        self.socket
+            .close(None)
+            .await
+            .map_err(|error| Error::WebSocket(error.to_string()))
+    }
+
+    /// Reads messages until the next event.
+    async fn next_event(&mut self) -> ResultType<Option<T>> {
+        loop {
+            let Some(message) = receive(&mut self.socket).await? else {
+                return Ok(None);
+            };
+            match message.kind.as_str() {
+                "next" => {
+                    let envelope: Envelope = payload(message.payload)?;
+                    if !envelope.errors.is_empty() {
+                        return Err(Error::GraphQl(GraphQlErrors {
+                            errors: envelope.errors,
+                            data: envelope.data,
+                        }));
+                    }
+                    let data = envelope.data.unwrap_or_default();
+                    return serde_json::from_value(data).map(Some).map_err(Error::Json);
+                }
+                "error" => {
+                    let errors: Vec<GraphQlError> = payload(message.payload)?;
+                    return Err(Error::GraphQl(GraphQlErrors { errors, data: None }));
+                }
+                "complete" => return Ok(None),
+                "ping" => send(&mut self.socket, &ProtocolMessage::<()>::new("pong", None)).await?,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Opens the subscription of an endpoint returning `GraphQlSubscription<T>`.
+///
+/// Used internally in the [`api!`](crate::api) macro.
+///
+/// # Errors
+/// Returns an error if the connection could not be opened or the server rejected it.
+#[doc(hidden)]
+pub async fn subscribe<A, T, V>(
+    api: &A,
+    spec: &EndpointSpec,
+    url: &str,
+    request: &GraphQlRequest<V>,
+) -> ResultType<Subscription<T>>
+where
+    A: Api + ?Sized,
+    V: Serialize,
+{
+    let url = http_url(url);
+    let built = crate::endpoint::build::<A, ()>(api, spec, &url, Body::None)?;
+    let payload = api.graphql_init_payload(&built);
+
+    let key = generate_key();
+    let handshake = {
+        let key = key.clone();
+        move |request: RequestBuilder| {
+            request
+                .header(CONNECTION, "Upgrade")
+                .header(UPGRADE, "websocket")
+                .header(SEC_WEBSOCKET_VERSION, "13")
+                .header(SEC_WEBSOCKET_KEY, key)
+                .header(SEC_WEBSOCKET_PROTOCOL, PROTOCOL)
+        }
+    };
+    let response = crate::endpoint::execute::<A, Raw, ()>(
+        api,
+        spec,
+        &url,
+        Body::None,
+        Some(Box::new(handshake)),
+    )
+    .await?;
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return Err(Error::WebSocket(format!(
+            "the server answered the handshake with status {}",
+            response.status()
+        )));
+    }
+    let accept = response.headers().get(SEC_WEBSOCKET_ACCEPT);
+    if accept.map(HeaderValue::as_bytes) != Some(derive_accept_key(key.as_bytes()).as_bytes()) {
+        return Err(Error::WebSocket(
+            "the server answered the handshake with a wrong `Sec-WebSocket-Accept`".to_string(),
+        ));
+    }
+    let upgraded = response.upgrade().await?;
+    let mut socket = WebSocketStream::from_raw_socket(upgraded, Role::Client, None).await;
+
+    let init = ProtocolMessage {
+        kind: "connection_init".to_string(),
+        id: None,
+        payload,
+    };
+    send(&mut socket, &init).await?;
+    loop {
+        match receive(&mut socket).await? {
+            Some(message) if message.kind == "connection_ack" => break,
+            Some(message) if message.kind == "ping" => {
+                send(&mut socket, &ProtocolMessage::<()>::new("pong", None)).await?;
+            }
+            Some(message) => {
+                return Err(Error::WebSocket(format!(
+                    "expected `connection_ack`, the server sent `{}`",
+                    message.kind
+                )));
+            }
+            None => {
+                return Err(Error::WebSocket(
+                    "the server closed the connection before acknowledging it".to_string(),
+                ));
+            }
+        }
+    }
+    send(
+        &mut socket,
+        &ProtocolMessage::new("subscribe", Some(request)),
+    )
+    .await?;
+    Ok(Subscription {
+        socket,
+        done: false,
+        marker: PhantomData,
+    })
+}
+
+/// Returns `url` with the `ws` and `wss` schemes replaced by `http` and `https`, which the
+/// handshake is sent with.
+fn http_url(url: &str) -> String {
+    if let Some(rest) = url.strip_prefix("ws://") {
+        format!("http://{rest}")
+    } else if let Some(rest) = url.strip_prefix("wss://") {
+        format!("https://{rest}")
+    } else {
+        url.to_string()
+    }
+}
+
+/// Sends `message` as a text frame.
+async fn send<P: Serialize>(
+    socket: &mut WebSocketStream<Upgraded>,
+    message: &ProtocolMessage<P>,
+) -> ResultType<()> {
+    let text = serde_json::to_string(message).map_err(Error::Json)?;
+    socket
+        .send(Message::Text(text))
+        .await
+        .map_err(|error| Error::WebSocket(error.to_string()))
+}
+
+/// Receives the next message, or `None` once the connection is closed.
+async fn receive(
+    socket: &mut WebSocketStream<Upgraded>,
+) -> ResultType<Option<ProtocolMessage<serde_json::Value>>> {
+    while let Some(frame) = socket.next().await {
+        match frame.map_err(|error| Error::WebSocket(error.to_string()))? {
+            Message::Text(text) => {
+                return serde_json::from_str(&text).map(Some).map_err(Error::Json)
+            }
+            Message::Close(_) => return Ok(None),
+            Message::Binary(_) => {
+                return Err(Error::WebSocket(
+                    "the server sent a binary message".to_string(),
+                ));
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Decodes the payload of a message.
+fn payload<P: DeserializeOwned>(payload: Option<serde_json::Value>) -> ResultType<P> {
+    serde_json::from_value(payload.unwrap_or_default()).map_err(Error::Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::{
+        tungstenite::{
+            handshake::server::{Request, Response},
+            Message,
+        },
+        WebSocketStream,
+    };
+
+    use crate::{api, graphql::GraphQlRequest, Error};
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Tick {
+        count: u32,
+    }
+
+    api! {
+        struct Clock {
+            token: String,
+        }
+
+        impl Api {
+            fn pre_request(
+                &self,
+                request: crate::RequestBuilder,
+            ) -> crate::ResultType<crate::RequestBuilder> {
+                Ok(request.bearer_auth(&self.token))
+            }
+        }
+    }
+
+    impl Clock {
+        api! {
+            fn ticks(&self, request: GraphQl<()>, base_url: &str) -> GraphQlSubscription<Tick> {
+                GET "{base_url}/graphql"
+            }
+        }
+    }
+
+    /// Reads the next message the client sent.
+    async fn read(socket: &mut WebSocketStream<TcpStream>) -> serde_json::Value {
+        let text = socket.next().await.unwrap().unwrap().into_text().unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    /// Serves one subscription sending `events`, checking the protocol and the init payload.
+    async fn serve(events: &'static [&'static str]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            #[allow(clippy::result_large_err)]
+            let callback = |request: &Request, mut response: Response| {
+                let protocol = request.headers()["sec-websocket-protocol"].clone();
+                assert_eq!(protocol, "graphql-transport-ws");
+                response
+                    .headers_mut()
+                    .insert("sec-websocket-protocol", protocol);
+                Ok(response)
+            };
+            let mut socket = tokio_tungstenite::accept_hdr_async(stream, callback)
+                .await
+                .unwrap();
+            let init = read(&mut socket).await;
+            assert_eq!(init["type"], "connection_init");
+            assert_eq!(init["payload"]["Authorization"], "Bearer secret");
+            let ack = r#"{"type":"connection_ack"}"#.to_string();
+            socket.send(Message::Text(ack)).await.unwrap();
+            let subscribe = read(&mut socket).await;
+            assert_eq!(subscribe["type"], "subscribe");
+            assert_eq!(subscribe["payload"]["query"], "subscription { count }");
+            for event in events {
+                socket
+                    .send(Message::Text((*event).to_string()))
+                    .await
+                    .unwrap();
+            }
+            while socket.next().await.is_some() {}
+        });
+        base_url
+    }
+
+    #[test]
+    fn streams_events() {
+        tokio_test::block_on(async {
+            let base_url = serve(&[
+                r#"{"type":"ping"}"#,
+                r#"{"id":"1","type":"next","payload":{"data":{"count":1}}}"#,
+                r#"{"id":"1","type":"next","payload":{"data":{"count":2}}}"#,
+                r#"{"id":"1","type":"complete"}"#,
+            ])
+            .await;
+            let api = Clock::builder()
+                .token("secret".to_string())
+                .build()
+                .unwrap();
+            let request = GraphQlRequest::new("subscription { count }", ());
+
+            let ticks = api.ticks(&request, &base_url).await.unwrap().into_stream();
+            let ticks = ticks.map(Result::unwrap).collect::<Vec<_>>().await;
+            assert_eq!(ticks, [Tick { count: 1 }, Tick { count: 2 }]);
+        });
+    }
+
+    #[test]
+    fn fails_on_errors() {
+        tokio_test::block_on(async {
+            let base_url =
+                serve(&[r#"{"id":"1","type":"error","payload":[{"message":"unknown field"}]}"#])
+                    .await;
+            let api = Clock::builder()
+                .token("secret".to_string())
+                .build()
+                .unwrap();
+            let request = GraphQlRequest::new("subscription { count }", ());
+
+            let mut ticks = api.ticks(&request, &base_url).await.unwrap();
+            let error = ticks.next().await.unwrap().unwrap_err();
+            assert!(
+                matches!(&error, Error::GraphQl(errors) if errors.errors[0].message == "unknown field")
+            );
+            assert!(ticks.next().await.is_none());
+        });
+    }
+}
@@ -0,0 +1,280 @@
+//! Mapping gRPC statuses in responses to errors, for gRPC-gateway and Connect endpoints.
+//!
+//! gRPC servers answer failed calls with the HTTP status `200` and signal the error in the
+//! `grpc-status` and `grpc-message` trailers, or in headers of the same names if the response
+//! has no body. Endpoints declared with `with { grpc_status }` turn a `grpc-status` other than
+//! `0` into [`Error::Grpc`](crate::Error::Grpc). Trailers take precedence over headers.
+//!
+//! reqwest doesn't expose trailers, so statuses sent in them are only seen with a
+//! [`Transport`](crate::transport::Transport) which provides them, see
+//! [`Trailers`](crate::protocol::Trailers).
+//!
+//! With the `grpc-web` feature, endpoints can call unary gRPC-Web methods as well, see [`web`].
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, grpc::GrpcStatus, Error, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn user(id: u32) -> String {
+//!            GET "https://example.com/v1/users/{id}"
+//!            with { grpc_status }
+//!         }
+//!     }
+//! }
+//!
+//! async fn user(api: &ExampleApi) -> ResultType<Option<String>> {
+//!     match api.user(1).await {
+//!         Ok(user) => Ok(Some(user)),
+//!         Err(Error::Grpc(status)) if status.code == GrpcStatus::NOT_FOUND => Ok(None),
+//!         Err(error) => Err(error),
+//!     }
+//! }
+//! ```
+
+use std::fmt;
+
+use reqwest::header::HeaderMap;
+
+use crate::{endpoint::EndpointSpec, response, Error, ResultType};
+
+#[cfg(feature = "grpc-web")]
+#[cfg_attr(docsrs, doc(cfg(feature = "grpc-web")))]
+pub mod web;
+
+/// Names of the gRPC status codes, indexed by their value.
+const NAMES: [&str; 17] = [
+    "OK",
+    "CANCELLED",
+    "UNKNOWN",
+    "INVALID_ARGUMENT",
+    "DEADLINE_EXCEEDED",
+    "NOT_FOUND",
+    "ALREADY_EXISTS",
+    "PERMISSION_DENIED",
+    "RESOURCE_EXHAUSTED",
+    "FAILED_PRECONDITION",
+    "ABORTED",
+    "OUT_OF_RANGE",
+    "UNIMPLEMENTED",
+    "INTERNAL",
+    "UNAVAILABLE",
+    "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+
+/// gRPC status of a failed call, read from the `grpc-status` and `grpc-message` trailers or
+/// headers of a response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrpcStatus {
+    /// Status code, e.g. [`GrpcStatus::NOT_FOUND`].
+    pub code: u32,
+    /// Message describing the error, percent-decoded.
+    pub message: String,
+}
+
+impl GrpcStatus {
+    /// The call was cancelled.
+    pub const CANCELLED: u32 = 1;
+    /// The cause of the error is unknown.
+    pub const UNKNOWN: u32 = 2;
+    /// The arguments of the call are invalid.
+    pub const INVALID_ARGUMENT: u32 = 3;
+    /// The deadline expired before the call completed.
+    pub const DEADLINE_EXCEEDED: u32 = 4;
+    /// A requested entity was not found.
+    pub const NOT_FOUND: u32 = 5;
+    /// An entity to be created already exists.
+    pub const ALREADY_EXISTS: u32 = 6;
+    /// The caller is not allowed to make the call.
+    pub const PERMISSION_DENIED: u32 = 7;
+    /// A resource, such as a quota, is exhausted.
+    pub const RESOURCE_EXHAUSTED: u32 = 8;
+    /// The system is not in the state the call requires.
+    pub const FAILED_PRECONDITION: u32 = 9;
+    /// The call was aborted, e.g. because of a concurrency conflict.
+    pub const ABORTED: u32 = 10;
+    /// The call went past the valid range.
+    pub const OUT_OF_RANGE: u32 = 11;
+    /// The call is not implemented by the server.
+    pub const UNIMPLEMENTED: u32 = 12;
+    /// An internal error of the server.
+    pub const INTERNAL: u32 = 13;
+    /// The service is currently unavailable and the call may be retried.
+    pub const UNAVAILABLE: u32 = 14;
+    /// Data was lost or corrupted.
+    pub const DATA_LOSS: u32 = 15;
+    /// The caller is not authenticated.
+    pub const UNAUTHENTICATED: u32 = 16;
+
+    /// Reads the status from the `grpc-status` and `grpc-message` entries of `headers`.
+    ///
+    /// Returns `None` if `headers` carry no valid `grpc-status` or it is `0`.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let code = headers
+            .get("grpc-status")?
+            .to_str()
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+        if code == 0 {
+            return None;
+        }
+        let message = headers
+            .get("grpc-message")
+            .map(|message| percent_decode(message.as_bytes()))
+            .unwrap_or_default();
+        Some(Self { code, message })
+    }
+
+    /// Returns the name of the code, e.g. `NOT_FOUND`, or `UNKNOWN` for codes outside of the
+    /// specification.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        usize::try_from(self.code)
+            .ok()
+            .and_then(|code| NAMES.get(code))
+            .copied()
+            .unwrap_or("UNKNOWN")
+    }
+}
+
+impl fmt::Display for GrpcStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gRPC call failed with {} ({})", self.name(), self.code)?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns [`Error::Grpc`] if `spec` maps gRPC statuses and `response` carries a failed one.
+///
+/// # Errors
+/// Returns [`Error::Grpc`] with the status of the response.
+pub(crate) fn check(spec: &EndpointSpec, response: &reqwest::Response) -> ResultType<()> {
+    if !spec.grpc_status {
+        return Ok(());
+    }
+    let status = match response::trailers(response) {
+        Some(trailers) if trailers.contains_key("grpc-status") => {
+            GrpcStatus::from_headers(trailers)
+        }
+        _ => GrpcStatus::from_headers(response.headers()),
+    };
+    match status {
+        Some(status) => Err(Error::Grpc(status)),
+        None => Ok(()),
+    }
+}
+
+/// Decodes the percent-encoded `value` of a `grpc-message`, replacing invalid UTF-8.
+fn percent_decode(value: &[u8]) -> String {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.iter();
+    while let Some(&byte) = bytes.next() {
+        let escaped = if byte == b'%' {
+            let digits = bytes
+                .as_slice()
+                .get(..2)
+                .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok());
+            if digits.is_some() {
+                bytes.nth(1);
+            }
+            digits
+        } else {
+            None
+        };
+        decoded.push(escaped.unwrap_or(byte));
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderMap;
+
+    use super::GrpcStatus;
+    use crate::{
+        api,
+        protocol::{Request, Response, Trailers},
+        test_server,
+        transport::Transport,
+        Api, ApiState, Error, ResultType,
+    };
+
+    /// Transport answering with the `grpc-status` and `grpc-message` trailers of a `NOT_FOUND`.
+    #[derive(Debug)]
+    struct NotFound;
+
+    #[async_trait::async_trait(?Send)]
+    impl Transport for NotFound {
+        async fn send(&self, _request: Request) -> ResultType<Response> {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", "5".parse().unwrap());
+            trailers.insert("grpc-message", "user%201%20not%20found".parse().unwrap());
+            let mut response = Response::new("{}".into());
+            response.extensions_mut().insert(Trailers(trailers));
+            Ok(response)
+        }
+    }
+
+    api!(struct Gateway);
+
+    impl Gateway {
+        api! {
+            fn user(&self, base_url: &str) -> String {
+                GET "{base_url}/users/1"
+                with { grpc_status }
+            }
+
+            fn parts(&self, base_url: &str) -> WithHeaders<String> {
+                GET "{base_url}/users/1"
+            }
+        }
+    }
+
+    #[test]
+    fn maps_statuses() {
+        tokio_test::block_on(async {
+            let api = Gateway::builder()
+                .state(ApiState::new().transport(NotFound))
+                .build()
+                .unwrap();
+            match api.user("http://example.com").await {
+                Err(Error::Grpc(status)) => {
+                    assert_eq!(status.code, GrpcStatus::NOT_FOUND);
+                    assert_eq!(
+                        status.to_string(),
+                        "gRPC call failed with NOT_FOUND (5): user 1 not found"
+                    );
+                }
+                other => panic!("expected a gRPC error, got {other:?}"),
+            }
+            let parts = api.parts("http://example.com").await.unwrap();
+            assert_eq!(parts.trailer("grpc-status"), Some("5"));
+
+            let base_url =
+                test_server::serve(|_| test_server::Response::new(200).header("grpc-status", "16"))
+                    .await;
+            let api = Gateway::new();
+            match api.user(&base_url).await {
+                Err(Error::Grpc(status)) => assert_eq!(status.name(), "UNAUTHENTICATED"),
+                other => panic!("expected a gRPC error, got {other:?}"),
+            }
+            assert!(api.parts(&base_url).await.unwrap().trailers.is_none());
+        });
+    }
+
+    #[test]
+    fn decodes_messages() {
+        assert_eq!(super::percent_decode(b"caf%C3%A9 100%"), "café 100%");
+        assert_eq!(super::percent_decode(b"%zz%4"), "%zz%4");
+    }
+}
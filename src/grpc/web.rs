@@ -0,0 +1,291 @@
+//! Unary gRPC-Web calls with protobuf messages.
+//!
+//! Endpoints declared with `request: GrpcWeb<Req>` and returning `GrpcWeb<Res>` send the
+//! [prost](https://docs.rs/prost) message `Req` as an `application/grpc-web+proto` frame and decode
+//! the `Res` message of the response. gRPC-Web sends the status of the call in a trailer frame at
+//! the end of the body, or in headers if the call failed before any message; statuses other than
+//! `OK` fail with [`Error::Grpc`](crate::Error::Grpc). Responses without a status, e.g. of a proxy
+//! in front of the server, are mapped from their HTTP status as gRPC clients do.
+//!
+//! Compressed frames are not supported.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, ResultType};
+//!
+//! #[derive(Clone, PartialEq, prost::Message)]
+//! struct HelloRequest {
+//!     #[prost(string, tag = "1")]
+//!     name: String,
+//! }
+//!
+//! #[derive(Clone, PartialEq, prost::Message)]
+//! struct HelloReply {
+//!     #[prost(string, tag = "1")]
+//!     message: String,
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn say_hello(request: GrpcWeb<HelloRequest>) -> GrpcWeb<HelloReply> {
+//!            POST "https://example.com/helloworld.Greeter/SayHello"
+//!         }
+//!     }
+//! }
+//!
+//! async fn greet(api: &ExampleApi) -> ResultType<String> {
+//!     let request = HelloRequest { name: "Ada".to_string() };
+//!     Ok(api.say_hello(&request).await?.message)
+//! }
+//! ```
+
+use std::marker::PhantomData;
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    StatusCode,
+};
+
+use super::GrpcStatus;
+use crate::{endpoint::EndpointSpec, response::ResponseKind, Error, ResultType};
+
+/// Content type of requests and responses.
+pub const CONTENT_TYPE: &str = "application/grpc-web+proto";
+
+/// Flag of frames carrying compressed data.
+const COMPRESSED: u8 = 0x01;
+
+/// Flag of frames carrying trailers.
+const TRAILERS: u8 = 0x80;
+
+/// Encodes `message` as the frame of a request body.
+///
+/// Used internally in the [`api!`](crate::api) macro.
+///
+/// # Panics
+/// Panics if the encoded message is larger than 4 GiB, which frames can't carry.
+#[doc(hidden)]
+pub fn encode<M: prost::Message>(message: &M) -> bytes::Bytes {
+    let length = message.encoded_len();
+    let mut frame = Vec::with_capacity(5 + length);
+    frame.push(0);
+    frame.extend_from_slice(
+        &u32::try_from(length)
+            .expect("gRPC-Web messages are at most 4 GiB")
+            .to_be_bytes(),
+    );
+    message
+        .encode(&mut frame)
+        .expect("the frame has room for the message");
+    frame.into()
+}
+
+/// Returns the message of a unary gRPC-Web call as `T`, see [`web`](crate::grpc::web).
+#[derive(Debug)]
+pub struct GrpcWeb<T>(PhantomData<T>);
+
+#[async_trait::async_trait(?Send)]
+impl<T: prost::Message + Default> ResponseKind for GrpcWeb<T> {
+    type Output = T;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+        let (message, trailers) = decode_frames(&body)?;
+        let grpc_status = if trailers.contains_key("grpc-status") {
+            GrpcStatus::from_headers(&trailers)
+        } else if headers.contains_key("grpc-status") {
+            GrpcStatus::from_headers(&headers)
+        } else if !status.is_success() {
+            Some(from_http_status(status))
+        } else {
+            return Err(Error::InvalidFrame(
+                "the gRPC-Web response carries no status".to_string(),
+            ));
+        };
+        if let Some(grpc_status) = grpc_status {
+            return Err(Error::Grpc(grpc_status));
+        }
+        let message = message.ok_or_else(|| {
+            Error::InvalidFrame("the gRPC-Web response carries no message".to_string())
+        })?;
+        T::decode(message).map_err(|error| Error::InvalidFrame(error.to_string()))
+    }
+}
+
+/// Splits `body` into its message, if any, and its trailers.
+fn decode_frames(body: &bytes::Bytes) -> ResultType<(Option<bytes::Bytes>, HeaderMap)> {
+    let (mut message, mut trailers) = (None, HeaderMap::new());
+    let mut offset = 0;
+    while offset < body.len() {
+        let header = body
+            .get(offset..offset + 5)
+            .ok_or_else(|| Error::InvalidFrame("truncated gRPC-Web frame header".to_string()))?;
+        let flags = header[0];
+        let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+        let start = offset + 5;
+        let end = usize::try_from(length)
+            .ok()
+            .and_then(|length| start.checked_add(length))
+            .filter(|&end| end <= body.len())
+            .ok_or_else(|| Error::InvalidFrame("truncated gRPC-Web frame".to_string()))?;
+        if flags & COMPRESSED != 0 {
+            return Err(Error::InvalidFrame(
+                "compressed gRPC-Web frames are not supported".to_string(),
+            ));
+        }
+        if flags & TRAILERS != 0 {
+            parse_trailers(&body[start..end], &mut trailers);
+        } else if message.replace(body.slice(start..end)).is_some() {
+            return Err(Error::InvalidFrame(
+                "the unary gRPC-Web response carries several messages".to_string(),
+            ));
+        }
+        offset = end;
+    }
+    Ok((message, trailers))
+}
+
+/// Adds the `name: value` lines of a trailer frame to `trailers`, skipping malformed ones.
+fn parse_trailers(frame: &[u8], trailers: &mut HeaderMap) {
+    for line in String::from_utf8_lossy(frame).split("\r\n") {
+        if let Some((name, value)) = line.split_once(':') {
+            let name = HeaderName::from_bytes(name.trim().to_ascii_lowercase().as_bytes());
+            if let (Ok(name), Ok(value)) = (name, HeaderValue::from_str(value.trim())) {
+                trailers.append(name, value);
+            }
+        }
+    }
+}
+
+/// Maps the HTTP `status` of a response without a gRPC status to one, as gRPC clients do.
+fn from_http_status(status: StatusCode) -> GrpcStatus {
+    let code = match status.as_u16() {
+        400 => GrpcStatus::INTERNAL,
+        401 => GrpcStatus::UNAUTHENTICATED,
+        403 => GrpcStatus::PERMISSION_DENIED,
+        404 => GrpcStatus::UNIMPLEMENTED,
+        429 | 502 | 503 | 504 => GrpcStatus::UNAVAILABLE,
+        _ => GrpcStatus::UNKNOWN,
+    };
+    GrpcStatus {
+        code,
+        message: format!("HTTP status {status}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode;
+    use crate::{api, grpc::GrpcStatus, test_server, Api, Error};
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct HelloRequest {
+        #[prost(string, tag = "1")]
+        name: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct HelloReply {
+        #[prost(string, tag = "1")]
+        message: String,
+    }
+
+    api!(struct Greeter);
+
+    impl Greeter {
+        api! {
+            fn say_hello(&self, request: GrpcWeb<HelloRequest>, base_url: &str) -> GrpcWeb<HelloReply> {
+                POST "{base_url}/helloworld.Greeter/SayHello"
+            }
+        }
+    }
+
+    /// Returns a trailer frame carrying `trailers`.
+    fn trailer_frame(trailers: &str) -> Vec<u8> {
+        let mut frame = vec![0x80];
+        frame.extend_from_slice(&u32::try_from(trailers.len()).unwrap().to_be_bytes());
+        frame.extend_from_slice(trailers.as_bytes());
+        frame
+    }
+
+    #[test]
+    fn calls_unary_methods() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                assert_eq!(
+                    request.header("content-type"),
+                    Some("application/grpc-web+proto")
+                );
+                let name = <HelloRequest as prost::Message>::decode(&request.body[5..])
+                    .unwrap()
+                    .name;
+                match name.as_str() {
+                    "Ada" => {
+                        let mut body = encode(&HelloReply {
+                            message: format!("Hello {name}"),
+                        })
+                        .to_vec();
+                        body.extend(trailer_frame("grpc-status: 0\r\ngrpc-message: \r\n"));
+                        test_server::Response::new(200)
+                            .header("content-type", "application/grpc-web+proto")
+                            .body(body)
+                    }
+                    "Bob" => test_server::Response::new(200)
+                        .header("content-type", "application/grpc-web+proto")
+                        .body(trailer_frame(
+                            "Grpc-Status: 5\r\nGrpc-Message: no%20Bob\r\n",
+                        )),
+                    "Eve" => test_server::Response::new(200)
+                        .header("content-type", "application/grpc-web+proto")
+                        .header("grpc-status", "7"),
+                    _ => test_server::Response::new(503),
+                }
+            })
+            .await;
+            let api = Greeter::new();
+            let hello = |name: &str| HelloRequest {
+                name: name.to_string(),
+            };
+
+            let reply = api.say_hello(&hello("Ada"), &base_url).await.unwrap();
+            assert_eq!(reply.message, "Hello Ada");
+
+            let codes = [
+                ("Bob", GrpcStatus::NOT_FOUND),
+                ("Eve", GrpcStatus::PERMISSION_DENIED),
+                ("Mallory", GrpcStatus::UNAVAILABLE),
+            ];
+            for (name, code) in codes {
+                match api.say_hello(&hello(name), &base_url).await {
+                    Err(Error::Grpc(status)) => assert_eq!(status.code, code),
+                    other => panic!("expected a gRPC error, got {other:?}"),
+                }
+            }
+            match api.say_hello(&hello("Bob"), &base_url).await {
+                Err(Error::Grpc(status)) => assert_eq!(status.message, "no Bob"),
+                other => panic!("expected a gRPC error, got {other:?}"),
+            }
+        });
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        let body = bytes::Bytes::from_static(&[0, 0, 0, 0, 9, 1]);
+        assert!(matches!(
+            super::decode_frames(&body),
+            Err(Error::InvalidFrame(_))
+        ));
+        let body = bytes::Bytes::from_static(&[1, 0, 0, 0, 0]);
+        assert!(matches!(
+            super::decode_frames(&body),
+            Err(Error::InvalidFrame(_))
+        ));
+    }
+}
@@ -0,0 +1,504 @@
+//! Recording the traffic of an instance as a HAR file, with the `json` feature.
+//!
+//! With a [`HarRecorder`] set through [`ApiState::har`](crate::ApiState::har), every request sent
+//! by an instance and its response are kept in memory, including their bodies.
+//! [`HarRecorder::to_json`] and [`HarRecorder::write`] export them in the
+//! [HAR 1.2](http://www.softwareishard.com/blog/har-12-spec/) format, which the network panels
+//! of browser devtools import, e.g. to share the traffic with the vendor of an API in a support
+//! ticket.
+//!
+//! Credentials are redacted before anything is kept, as in [`logging`](crate::logging): the
+//! headers named by [`DEFAULT_REDACTED_HEADERS`](crate::capture::DEFAULT_REDACTED_HEADERS), the
+//! query parameters, form fields and JSON fields named by
+//! [`DEFAULT_REDACTED_PARAMS`](crate::capture::DEFAULT_REDACTED_PARAMS) or
+//! [`DEFAULT_REDACTED_FIELDS`](crate::capture::DEFAULT_REDACTED_FIELDS), and any others added
+//! with [`HarRecorder::redact_header`] and [`HarRecorder::redact_field`]. Requests which received
+//! no response are kept with the status `0` and the error in the `_error` field of the response.
+//!
+//! Responses are read completely before they are decoded, so recording disables streaming, e.g.
+//! of [`JsonArrayStream`](crate::response::JsonArrayStream) endpoints.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, har::HarRecorder, Api, ApiState, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn status() -> String {
+//!            GET "https://example.com/status"
+//!         }
+//!     }
+//! }
+//!
+//! async fn report(api: &ExampleApi) -> std::io::Result<()> {
+//!     let _ = api.status().await;
+//!     if let Some(har) = api.state().har_recorder() {
+//!         har.write("support-ticket.har")?;
+//!     }
+//!     Ok(())
+//! }
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().har(HarRecorder::new().redact_header("X-Session")))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    io,
+    path::Path,
+    sync::{Mutex, PoisonError},
+    time::{Instant, SystemTime},
+};
+
+use base64::Engine;
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use serde::Serialize;
+
+use crate::{capture::Redaction, locale, single_flight::SharedResponse, ResultType};
+
+/// Recorder keeping the requests and responses of an instance for a HAR file, see
+/// [`har`](crate::har).
+#[derive(Debug)]
+pub struct HarRecorder {
+    /// Headers, query parameters and body fields whose values are redacted.
+    redaction: Redaction,
+    /// Recorded entries, from the oldest to the newest.
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Default for HarRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HarRecorder {
+    /// Creates an empty recorder.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            redaction: Redaction::for_bodies(),
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Also redacts the values of the header `name`, in requests and responses.
+    #[must_use]
+    pub fn redact_header(mut self, name: &str) -> Self {
+        self.redaction.headers.push(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Also redacts the values of the query parameter, form field or JSON field `name`, in
+    /// requests and responses.
+    #[must_use]
+    pub fn redact_field(mut self, name: impl Into<String>) -> Self {
+        self.redaction.params.push(name.into());
+        self
+    }
+
+    /// Returns the recorded traffic as a HAR 1.2 document.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let entries = self.lock();
+        let har = Har {
+            log: Log {
+                version: "1.2",
+                creator: Creator {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                },
+                entries: &entries,
+            },
+        };
+        // Serializing fails only for maps with non-string keys, which HAR documents don't have.
+        serde_json::to_string_pretty(&har).unwrap_or_default()
+    }
+
+    /// Writes the recorded traffic as a HAR 1.2 document to the file at `path`.
+    ///
+    /// # Errors
+    /// Returns an error if the file could not be written.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+
+    /// Returns the number of recorded requests.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns whether no requests were recorded.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all recorded requests.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Records `request`, sent at `started_at`, and its `response`, reading its body.
+    ///
+    /// # Errors
+    /// Returns the error of `response`, or an error if its body could not be read.
+    pub(crate) async fn record(
+        &self,
+        request: &reqwest::Request,
+        started_at: SystemTime,
+        started: Instant,
+        response: ResultType<reqwest::Response>,
+    ) -> ResultType<reqwest::Response> {
+        let read = match response {
+            Ok(response) => SharedResponse::read(response).await,
+            Err(error) => Err(error),
+        };
+        let time = started.elapsed().as_secs_f64() * 1000.0;
+        let (received, response) = match read {
+            Ok(shared) => {
+                let response = shared.to_response();
+                (self.response(&response, shared.body()), Ok(response))
+            }
+            Err(error) => (Response::failed(error.to_string()), Err(error)),
+        };
+        let entry = Entry {
+            started_date_time: locale::utc_datetime(started_at),
+            time,
+            request: self.request(request),
+            response: received,
+            cache: Cache {},
+            timings: Timings {
+                send: 0.0,
+                wait: time,
+                receive: 0.0,
+            },
+        };
+        self.lock().push(entry);
+        response
+    }
+
+    /// Returns the HAR description of `request`.
+    fn request(&self, request: &reqwest::Request) -> Request {
+        let url = self.redaction.url(request.url());
+        let query_string = reqwest::Url::parse(&url)
+            .map(|url| {
+                url.query_pairs()
+                    .map(|(name, value)| NameValue {
+                        name: name.into_owned(),
+                        value: value.into_owned(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let body = request.body().and_then(reqwest::Body::as_bytes);
+        let post_data = body.map(|body| PostData {
+            mime_type: mime_type(request.headers()),
+            text: self
+                .redaction
+                .body(body, request.headers().get(CONTENT_TYPE)),
+        });
+        Request {
+            method: request.method().to_string(),
+            url,
+            http_version: format!("{:?}", request.version()),
+            cookies: Vec::new(),
+            headers: self.headers(request.headers()),
+            query_string,
+            post_data,
+            headers_size: -1,
+            body_size: body.map_or(0, |body| size(body.len())),
+        }
+    }
+
+    /// Returns the HAR description of `response`, whose whole body is `body`.
+    fn response(&self, response: &reqwest::Response, body: &[u8]) -> Response {
+        let status = response.status();
+        let (text, encoding) = if std::str::from_utf8(body).is_ok() {
+            let text = self
+                .redaction
+                .body(body, response.headers().get(CONTENT_TYPE));
+            (text, None)
+        } else {
+            let text = base64::engine::general_purpose::STANDARD.encode(body);
+            (text, Some("base64"))
+        };
+        Response {
+            status: status.as_u16(),
+            status_text: status.canonical_reason().unwrap_or_default().to_string(),
+            http_version: format!("{:?}", response.version()),
+            cookies: Vec::new(),
+            headers: self.headers(response.headers()),
+            content: Content {
+                size: size(body.len()),
+                mime_type: mime_type(response.headers()),
+                text,
+                encoding,
+            },
+            redirect_url: String::new(),
+            headers_size: -1,
+            body_size: size(body.len()),
+            error: None,
+        }
+    }
+
+    /// Returns `headers` with redacted values.
+    fn headers(&self, headers: &HeaderMap) -> Vec<NameValue> {
+        self.redaction
+            .headers(headers)
+            .into_iter()
+            .map(|(name, value)| NameValue { name, value })
+            .collect()
+    }
+
+    /// Locks the recorded entries.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Returns the content type in `headers`, or an empty string.
+fn mime_type(headers: &HeaderMap) -> String {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Returns `length` as a HAR size, which is signed since `-1` stands for unknown sizes.
+fn size(length: usize) -> i64 {
+    i64::try_from(length).unwrap_or(i64::MAX)
+}
+
+/// HAR document.
+#[derive(Serialize)]
+struct Har<'a> {
+    /// The recorded traffic.
+    log: Log<'a>,
+}
+
+/// Recorded traffic of a HAR document.
+#[derive(Serialize)]
+struct Log<'a> {
+    /// Version of the HAR format.
+    version: &'static str,
+    /// Application which recorded the traffic.
+    creator: Creator,
+    /// Requests and their responses.
+    entries: &'a [Entry],
+}
+
+/// Application which recorded the traffic of a HAR document.
+#[derive(Serialize)]
+struct Creator {
+    /// Name of the application.
+    name: &'static str,
+    /// Version of the application.
+    version: &'static str,
+}
+
+/// Request and its response in a HAR document.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    /// Point in time at which the request was sent, in ISO 8601.
+    started_date_time: String,
+    /// Time until the response was read, in milliseconds.
+    time: f64,
+    /// The request.
+    request: Request,
+    /// The response.
+    response: Response,
+    /// Use of the browser cache, which doesn't apply.
+    cache: Cache,
+    /// Phases of the request, which are not measured separately.
+    timings: Timings,
+}
+
+/// Header or query parameter in a HAR document.
+#[derive(Debug, Serialize)]
+struct NameValue {
+    /// Name of the header or parameter.
+    name: String,
+    /// Value of the header or parameter, possibly redacted.
+    value: String,
+}
+
+/// Request in a HAR document.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request {
+    /// HTTP method of the request.
+    method: String,
+    /// URL of the request, with redacted query parameters.
+    url: String,
+    /// HTTP version of the request, e.g. `HTTP/1.1`.
+    http_version: String,
+    /// Cookies of the request, which are listed in its headers.
+    cookies: Vec<NameValue>,
+    /// Headers of the request, with redacted values.
+    headers: Vec<NameValue>,
+    /// Query parameters of the request, with redacted values.
+    query_string: Vec<NameValue>,
+    /// Body of the request, unless it has none or it is streamed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_data: Option<PostData>,
+    /// Size of the headers, `-1` as it is unknown.
+    headers_size: i64,
+    /// Size of the body in bytes.
+    body_size: i64,
+}
+
+/// Body of a request in a HAR document.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostData {
+    /// Content type of the body.
+    mime_type: String,
+    /// The body, with redacted fields.
+    text: String,
+}
+
+/// Response in a HAR document.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    /// Status of the response, `0` if no response was received.
+    status: u16,
+    /// Reason phrase of the status.
+    status_text: String,
+    /// HTTP version of the response, e.g. `HTTP/1.1`.
+    http_version: String,
+    /// Cookies of the response, which are listed in its headers.
+    cookies: Vec<NameValue>,
+    /// Headers of the response, with redacted values.
+    headers: Vec<NameValue>,
+    /// Body of the response.
+    content: Content,
+    /// Target of a redirect, empty as redirects are followed.
+    #[serde(rename = "redirectURL")]
+    redirect_url: String,
+    /// Size of the headers, `-1` as it is unknown.
+    headers_size: i64,
+    /// Size of the body in bytes.
+    body_size: i64,
+    /// Error of the request, if no response was received.
+    #[serde(rename = "_error", skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Response {
+    /// Returns the response of a request which received none, because of `error`.
+    fn failed(error: String) -> Self {
+        Self {
+            status: 0,
+            status_text: String::new(),
+            http_version: String::new(),
+            cookies: Vec::new(),
+            headers: Vec::new(),
+            content: Content {
+                size: 0,
+                mime_type: String::new(),
+                text: String::new(),
+                encoding: None,
+            },
+            redirect_url: String::new(),
+            headers_size: -1,
+            body_size: -1,
+            error: Some(error),
+        }
+    }
+}
+
+/// Body of a response in a HAR document.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    /// Size of the body in bytes.
+    size: i64,
+    /// Content type of the body.
+    mime_type: String,
+    /// The body, with redacted fields, or encoded as `encoding`.
+    text: String,
+    /// Encoding of `text`, `base64` for binary bodies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+}
+
+/// Use of the browser cache in a HAR document, which is always empty.
+#[derive(Debug, Serialize)]
+struct Cache {}
+
+/// Phases of a request in a HAR document, in milliseconds.
+#[derive(Debug, Serialize)]
+struct Timings {
+    /// Time to send the request.
+    send: f64,
+    /// Time until the response was read.
+    wait: f64,
+    /// Time to receive the response, included in `wait`.
+    receive: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HarRecorder;
+    use crate::{api, test_server, Api, ApiState};
+
+    api!(struct Recorded);
+
+    impl Recorded {
+        api! {
+            fn login(&self, request: Json<serde_json::Value>, base_url: &str) -> Json<serde_json::Value> {
+                POST "{base_url}/login?key=secret"
+            }
+        }
+    }
+
+    #[test]
+    fn records_traffic() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|_| {
+                test_server::Response::new(200)
+                    .header("content-type", "application/json")
+                    .json(r#"{"token":"abc","user":"me"}"#)
+            })
+            .await;
+            let api = Recorded::builder()
+                .state(ApiState::new().har(HarRecorder::new()))
+                .build()
+                .unwrap();
+            let request = serde_json::json!({ "user": "me", "password": "hunter2" });
+            let response = api.login(&request, &base_url).await.unwrap();
+            assert_eq!(response["token"], "abc");
+            assert!(api.login(&request, "http://localhost:1").await.is_err());
+
+            let har = api.state().har_recorder().unwrap();
+            let har: serde_json::Value = serde_json::from_str(&har.to_json()).unwrap();
+            assert_eq!(har["log"]["version"], "1.2");
+            let entries = har["log"]["entries"].as_array().unwrap();
+            assert_eq!(entries.len(), 2);
+
+            let request = &entries[0]["request"];
+            assert_eq!(request["method"], "POST");
+            assert_eq!(request["queryString"][0]["value"], "[redacted]");
+            assert_eq!(
+                request["postData"]["text"],
+                r#"{"password":"[redacted]","user":"me"}"#
+            );
+            let response = &entries[0]["response"];
+            assert_eq!(response["status"], 200);
+            assert_eq!(
+                response["content"]["text"],
+                r#"{"token":"[redacted]","user":"me"}"#
+            );
+            assert_eq!(entries[1]["response"]["status"], 0);
+            assert!(entries[1]["response"]["_error"].is_string());
+        });
+    }
+}
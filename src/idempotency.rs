@@ -0,0 +1,242 @@
+//! Idempotency keys, which make retrying requests that aren't idempotent safe.
+//!
+//! A `POST` which timed out may have been processed or not, so retrying it may create a second
+//! payment or order. APIs such as Stripe's accept an `Idempotency-Key` header instead: requests
+//! carrying a key the server has seen already get the response of the first one, without being
+//! processed again.
+//!
+//! Endpoints declared `with { retry: ... }` send their `POST` and `PATCH` requests with a key
+//! shared by all attempts of a call on their own, named by their
+//! [`RetryPolicy`](crate::retry::RetryPolicy), unless they are declared `with { idempotent }`.
+//!
+//! An [`IdempotencyKey`] added with [`ApiState::interceptor`](crate::ApiState::interceptor)
+//! gives every `POST` and `PATCH` request a new random UUID as its key, unless it carries one
+//! already. Interceptors added after it, such as one which retries requests, see the request
+//! with its key, so all attempts of one call send the same key while the next call sends
+//! another. Interceptors added before it wrap it, and their attempts each get a new key.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{
+//!     api,
+//!     idempotency::IdempotencyKey,
+//!     interceptor::{Interceptor, Next},
+//!     ApiState, ResultType,
+//! };
+//!
+//! /// Retries requests once if the server failed.
+//! #[derive(Debug)]
+//! struct RetryOnce;
+//!
+//! #[async_trait::async_trait(?Send)]
+//! impl Interceptor for RetryOnce {
+//!     async fn intercept(
+//!         &self,
+//!         request: reqwest::Request,
+//!         next: Next<'_>,
+//!     ) -> ResultType<reqwest::Response> {
+//!         let retry = request.try_clone();
+//!         let response = next.run(request).await?;
+//!         match retry {
+//!             Some(retry) if response.status().is_server_error() => next.run(retry).await,
+//!             _ => Ok(response),
+//!         }
+//!     }
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let api = ExampleApi::builder()
+//!     .state(
+//!         ApiState::new()
+//!             // Before the retries, so they reuse the key.
+//!             .interceptor(IdempotencyKey::new().header_name("X-Idempotency-Key"))
+//!             .interceptor(RetryOnce),
+//!     )
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Method,
+};
+
+use crate::{
+    interceptor::{Interceptor, Next},
+    ResultType,
+};
+
+/// Name of the header carrying the key unless another one is set.
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Sends a random key with every `POST` and `PATCH` request, see
+/// [`idempotency`](crate::idempotency).
+#[derive(Debug, Clone)]
+pub struct IdempotencyKey {
+    /// Header carrying the key.
+    header: HeaderName,
+}
+
+impl IdempotencyKey {
+    /// Creates an interceptor sending keys as `Idempotency-Key`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            header: HeaderName::from_static(IDEMPOTENCY_KEY),
+        }
+    }
+
+    /// Sends the keys as `name` instead of `Idempotency-Key`.
+    ///
+    /// # Panics
+    /// Panics if `name` is not a valid header name.
+    #[must_use]
+    pub fn header_name(mut self, name: &str) -> Self {
+        self.header = HeaderName::try_from(name).expect("invalid idempotency key header name");
+        self
+    }
+}
+
+impl Default for IdempotencyKey {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Interceptor for IdempotencyKey {
+    async fn intercept(
+        &self,
+        mut request: reqwest::Request,
+        next: Next<'_>,
+    ) -> ResultType<reqwest::Response> {
+        let unsafe_method = matches!(*request.method(), Method::POST | Method::PATCH);
+        if unsafe_method && !request.headers().contains_key(&self.header) {
+            if let Ok(key) = HeaderValue::try_from(key()) {
+                request.headers_mut().insert(self.header.clone(), key);
+            }
+        }
+        next.run(request).await
+    }
+}
+
+/// Returns a new random UUID, e.g. `8f14e45f-ceea-467f-a0e6-7a8b2c7d1f3e`.
+///
+/// # Panics
+/// Panics if the operating system provides no random numbers.
+#[must_use]
+pub fn key() -> String {
+    let random = u128::from_be_bytes(random());
+    // Version 4 and the RFC 9562 variant.
+    let uuid = random & !(0xf000_u128 << 64) | 0x4000_u128 << 64;
+    let uuid = uuid & !(0xc_u128 << 60) | 0x8_u128 << 60;
+    let hex = format!("{uuid:032x}");
+    let mut key = String::with_capacity(36);
+    for (start, end) in [(0, 8), (8, 12), (12, 16), (16, 20), (20, 32)] {
+        if start > 0 {
+            key.push('-');
+        }
+        key.push_str(&hex[start..end]);
+    }
+    key
+}
+
+/// Returns `N` bytes from the random number generator of the operating system, which keys and
+/// nonces sent to servers have to be unpredictable with.
+///
+/// # Panics
+/// Panics if the operating system provides no random numbers.
+pub(crate) fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).expect("the operating system provides no random numbers");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{key, IdempotencyKey};
+    use crate::{
+        api,
+        interceptor::{Interceptor, Next},
+        test_server, ApiState, ResultType,
+    };
+
+    /// Interceptor sending every request twice, returning the second response.
+    #[derive(Debug)]
+    struct Twice;
+
+    #[async_trait::async_trait(?Send)]
+    impl Interceptor for Twice {
+        async fn intercept(
+            &self,
+            request: reqwest::Request,
+            next: Next<'_>,
+        ) -> ResultType<reqwest::Response> {
+            let retry = request.try_clone().unwrap();
+            next.run(request).await?;
+            next.run(retry).await
+        }
+    }
+
+    api!(struct Payments);
+
+    impl Payments {
+        api! {
+            fn create(&self, base_url: &str) -> StatusCode {
+                POST "{base_url}/payments"
+            }
+
+            fn list(&self, base_url: &str) -> StatusCode {
+                GET "{base_url}/payments"
+            }
+        }
+    }
+
+    #[test]
+    fn generates_uuids() {
+        let first = key();
+        assert_eq!(first.len(), 36);
+        assert_eq!(
+            first.split('-').map(str::len).collect::<Vec<_>>(),
+            [8, 4, 4, 4, 12]
+        );
+        assert_eq!(&first[14..15], "4");
+        assert!(matches!(&first[19..20], "8" | "9" | "a" | "b"));
+        assert_ne!(first, key());
+    }
+
+    #[test]
+    fn reuses_keys_across_attempts() {
+        tokio_test::block_on(async {
+            let keys = Arc::new(Mutex::new(Vec::new()));
+            let server_keys = keys.clone();
+            let base_url = test_server::serve(move |request| {
+                let key = request.header("x-idempotency-key").map(str::to_string);
+                server_keys.lock().unwrap().push(key);
+                test_server::Response::new(200)
+            })
+            .await;
+            let api = Payments::builder()
+                .state(
+                    ApiState::new()
+                        .interceptor(IdempotencyKey::new().header_name("X-Idempotency-Key"))
+                        .interceptor(Twice),
+                )
+                .build()
+                .unwrap();
+
+            api.create(&base_url).await.unwrap();
+            api.create(&base_url).await.unwrap();
+            api.list(&base_url).await.unwrap();
+            let keys = keys.lock().unwrap();
+            assert!(keys[0].is_some());
+            assert_eq!(keys[0], keys[1]);
+            assert_ne!(keys[1], keys[2]);
+            assert_eq!(keys[2], keys[3]);
+            assert_eq!(keys[4..], [None, None]);
+        });
+    }
+}
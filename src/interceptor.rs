@@ -0,0 +1,223 @@
+//! Layers around the sending of requests, independent of the `middleware` feature.
+//!
+//! [`Api::pre_request`](crate::Api::pre_request) and
+//! [`Api::post_response`](crate::Api::post_response) change a request before it is sent and its
+//! response afterwards, but can't wrap the send itself. An [`Interceptor`] receives the built
+//! request together with the [`Next`] step of the chain, and decides whether, how often and with
+//! which request to run it, e.g. to retry, to sign requests or to answer them from elsewhere.
+//!
+//! Interceptors added with [`ApiState::interceptor`](crate::ApiState::interceptor) run in the
+//! order they were added, the first one outermost, after the limits and the circuit breaker of
+//! the instance. The last step sends the request with the
+//! [`Transport`](crate::transport::Transport) of the instance, or else its client.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{
+//!     api,
+//!     interceptor::{Interceptor, Next},
+//!     ApiState, ResultType,
+//! };
+//!
+//! /// Retries requests once if the server failed.
+//! #[derive(Debug)]
+//! struct RetryOnce;
+//!
+//! #[async_trait::async_trait(?Send)]
+//! impl Interceptor for RetryOnce {
+//!     async fn intercept(
+//!         &self,
+//!         request: reqwest::Request,
+//!         next: Next<'_>,
+//!     ) -> ResultType<reqwest::Response> {
+//!         let retry = request.try_clone();
+//!         let response = next.run(request).await?;
+//!         match retry {
+//!             Some(retry) if response.status().is_server_error() => next.run(retry).await,
+//!             _ => Ok(response),
+//!         }
+//!     }
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().interceptor(RetryOnce))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{fmt, sync::Arc};
+
+use crate::{
+    transport::{self, Transport},
+    ClientType, ResultType,
+};
+
+/// Layer around the sending of requests, see [`interceptor`](crate::interceptor).
+#[async_trait::async_trait(?Send)]
+pub trait Interceptor: fmt::Debug + Send + Sync {
+    /// Handles `request`, usually by passing it on with [`Next::run`] and returning its response.
+    async fn intercept(
+        &self,
+        request: reqwest::Request,
+        next: Next<'_>,
+    ) -> ResultType<reqwest::Response>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl<I: Interceptor + ?Sized> Interceptor for Arc<I> {
+    async fn intercept(
+        &self,
+        request: reqwest::Request,
+        next: Next<'_>,
+    ) -> ResultType<reqwest::Response> {
+        (**self).intercept(request, next).await
+    }
+}
+
+/// The rest of an interceptor chain, passed to [`Interceptor::intercept`].
+///
+/// It can be run several times, e.g. to retry a request.
+#[derive(Debug, Clone, Copy)]
+pub struct Next<'a> {
+    /// Interceptors which have not run yet.
+    chain: &'a [Arc<dyn Interceptor>],
+    /// Client sending the request unless there is a transport.
+    client: &'a ClientType,
+    /// Transport sending the request, if any.
+    transport: Option<&'a dyn Transport>,
+}
+
+impl<'a> Next<'a> {
+    /// Creates the chain of `interceptors` which ends in sending requests with `transport`, or
+    /// else `client`.
+    pub(crate) fn new(
+        interceptors: &'a [Arc<dyn Interceptor>],
+        client: &'a ClientType,
+        transport: Option<&'a dyn Transport>,
+    ) -> Self {
+        Self {
+            chain: interceptors,
+            client,
+            transport,
+        }
+    }
+
+    /// Runs the remaining interceptors and sends `request`.
+    ///
+    /// # Errors
+    /// Returns any error of the remaining interceptors, or from sending the request.
+    pub async fn run(self, request: reqwest::Request) -> ResultType<reqwest::Response> {
+        match self.chain.split_first() {
+            Some((interceptor, chain)) => {
+                let next = Self { chain, ..self };
+                interceptor.intercept(request, next).await
+            }
+            None => match self.transport {
+                Some(transport) => transport::send(transport, request).await,
+                None => Ok(self.client.execute(request).await?),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use super::{Interceptor, Next};
+    use crate::{api, test_server, ApiState, ResultType};
+
+    /// Interceptor recording its name before and after the rest of the chain.
+    #[derive(Debug)]
+    struct Recording(&'static str, Arc<Mutex<Vec<String>>>);
+
+    #[async_trait::async_trait(?Send)]
+    impl Interceptor for Recording {
+        async fn intercept(
+            &self,
+            mut request: reqwest::Request,
+            next: Next<'_>,
+        ) -> ResultType<reqwest::Response> {
+            self.1.lock().unwrap().push(format!("> {}", self.0));
+            request
+                .headers_mut()
+                .append("x-layer", self.0.parse().unwrap());
+            let response = next.run(request).await;
+            self.1.lock().unwrap().push(format!("< {}", self.0));
+            response
+        }
+    }
+
+    /// Interceptor retrying requests once after a server error.
+    #[derive(Debug)]
+    struct RetryOnce;
+
+    #[async_trait::async_trait(?Send)]
+    impl Interceptor for RetryOnce {
+        async fn intercept(
+            &self,
+            request: reqwest::Request,
+            next: Next<'_>,
+        ) -> ResultType<reqwest::Response> {
+            let retry = request.try_clone().unwrap();
+            let response = next.run(request).await?;
+            if response.status().is_server_error() {
+                return next.run(retry).await;
+            }
+            Ok(response)
+        }
+    }
+
+    api!(struct Layered);
+
+    impl Layered {
+        api! {
+            fn get(&self, base_url: &str) -> String {
+                GET "{base_url}/"
+            }
+        }
+    }
+
+    #[test]
+    fn runs_chain_in_order() {
+        tokio_test::block_on(async {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let server_calls = calls.clone();
+            let base_url = test_server::serve(move |request| {
+                if server_calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return test_server::Response::new(503);
+                }
+                let layers: Vec<_> = request
+                    .headers
+                    .iter()
+                    .filter(|(name, _)| name == "x-layer")
+                    .map(|(_, value)| value.as_str())
+                    .collect();
+                test_server::Response::new(200).body(layers.join(", "))
+            })
+            .await;
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let api = Layered::builder()
+                .state(
+                    ApiState::new()
+                        .interceptor(Recording("outer", log.clone()))
+                        .interceptor(RetryOnce)
+                        .interceptor(Recording("inner", log.clone())),
+                )
+                .build()
+                .unwrap();
+
+            assert_eq!(api.get(&base_url).await.unwrap(), "outer, inner");
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+            assert_eq!(
+                *log.lock().unwrap(),
+                ["> outer", "> inner", "< inner", "> inner", "< inner", "< outer"]
+            );
+        });
+    }
+}
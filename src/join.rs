@@ -0,0 +1,193 @@
+//! Helpers for running several api calls concurrently.
+//!
+//! See the [`api_join!`](crate::api_join) macro.
+
+use std::fmt;
+
+use crate::Error;
+
+/// A single failed call from an [`api_join!`](crate::api_join) invocation.
+#[derive(Debug)]
+pub struct JoinFailure<E = Error> {
+    /// Position of the call within the macro invocation, starting at 0.
+    pub index: usize,
+    /// Source text of the call expression, e.g. `api.todo(1)`.
+    pub call: &'static str,
+    /// The error returned by the call.
+    pub error: E,
+}
+
+/// Error returned by [`api_join!`](crate::api_join) when at least one of the calls failed.
+///
+/// Every failed call is recorded, not just the first one.
+#[derive(Debug)]
+pub struct JoinError<E = Error> {
+    /// All failed calls, in invocation order.
+    failures: Vec<JoinFailure<E>>,
+}
+
+impl<E> JoinError<E> {
+    /// Returns all failed calls, in invocation order.
+    #[must_use]
+    pub fn failures(&self) -> &[JoinFailure<E>] {
+        &self.failures
+    }
+
+    /// Consumes the error, returning all failed calls.
+    #[must_use]
+    pub fn into_failures(self) -> Vec<JoinFailure<E>> {
+        self.failures
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for JoinError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} joined call(s) failed", self.failures.len())?;
+        for failure in &self.failures {
+            write!(
+                f,
+                "; #{} `{}`: {}",
+                failure.index, failure.call, failure.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for JoinError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.failures
+            .first()
+            .map(|failure| &failure.error as &(dyn std::error::Error + 'static))
+    }
+}
+
+/// Used internally in the [`api_join!`](crate::api_join) macro.
+///
+/// Implemented for tuples of up to 12 results sharing the same error type.
+#[doc(hidden)]
+pub trait JoinResults<E> {
+    /// Tuple of the successful values.
+    type Output;
+
+    /// Turns a tuple of results into a result of a tuple, collecting every failure.
+    ///
+    /// # Errors
+    /// Returns a [`JoinError`] listing every result that was an error.
+    fn collect(self, calls: &[&'static str]) -> Result<Self::Output, JoinError<E>>;
+}
+
+/// Implements [`JoinResults`] for a tuple of the given arity.
+macro_rules! impl_join_results {
+    ($($ty:ident $var:ident $idx:tt),+) => {
+        impl<E, $($ty),+> JoinResults<E> for ($(Result<$ty, E>,)+) {
+            type Output = ($($ty,)+);
+
+            fn collect(self, calls: &[&'static str]) -> Result<Self::Output, JoinError<E>> {
+                let mut failures = Vec::new();
+                $(
+                    let $var = match self.$idx {
+                        Ok(value) => Some(value),
+                        Err(error) => {
+                            failures.push(JoinFailure { index: $idx, call: calls[$idx], error });
+                            None
+                        }
+                    };
+                )+
+                match ($($var,)+) {
+                    ($(Some($var),)+) => Ok(($($var,)+)),
+                    _ => Err(JoinError { failures }),
+                }
+            }
+        }
+    };
+}
+
+impl_join_results!(A a 0);
+impl_join_results!(A a 0, B b 1);
+impl_join_results!(A a 0, B b 1, C c 2);
+impl_join_results!(A a 0, B b 1, C c 2, D d 3);
+impl_join_results!(A a 0, B b 1, C c 2, D d 3, F f 4);
+impl_join_results!(A a 0, B b 1, C c 2, D d 3, F f 4, G g 5);
+impl_join_results!(A a 0, B b 1, C c 2, D d 3, F f 4, G g 5, H h 6);
+impl_join_results!(A a 0, B b 1, C c 2, D d 3, F f 4, G g 5, H h 6, I i 7);
+impl_join_results!(A a 0, B b 1, C c 2, D d 3, F f 4, G g 5, H h 6, I i 7, J j 8);
+impl_join_results!(A a 0, B b 1, C c 2, D d 3, F f 4, G g 5, H h 6, I i 7, J j 8, K k 9);
+impl_join_results!(A a 0, B b 1, C c 2, D d 3, F f 4, G g 5, H h 6, I i 7, J j 8, K k 9, L l 10);
+impl_join_results!(A a 0, B b 1, C c 2, D d 3, F f 4, G g 5, H h 6, I i 7, J j 8, K k 9, L l 10, M m 11);
+
+/// Runs several api calls concurrently and returns all of their results as a tuple.
+///
+/// All calls are polled on the current task, so they can borrow the same [`Api`](crate::Api)
+/// instance. Every call runs to completion; if any of them failed, a [`JoinError`] is
+/// returned describing each failed call.
+///
+/// # Usage
+/// ```rust
+/// use api_client::{api, api_join, Api};
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn todo(id: u32) -> String {
+///            GET "https://example.com/todos/{id}"
+///         }
+///
+///         fn user(id: u32) -> String {
+///            GET "https://example.com/users/{id}"
+///         }
+///     }
+/// }
+///
+/// async fn todo_with_user(api: &ExampleApi) -> Result<(String, String), api_client::join::JoinError> {
+///     api_join!(api.todo(1), api.user(2))
+/// }
+/// ```
+#[macro_export]
+macro_rules! api_join {
+    ($($call:expr),+ $(,)?) => {
+        $crate::join::JoinResults::collect(
+            $crate::__private::join!($($call),+),
+            &[$(::core::stringify!($call)),+],
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JoinResults;
+
+    #[test]
+    fn collects_all_failures() {
+        let results = (
+            Err::<u32, _>("first"),
+            Ok::<_, &str>(2),
+            Err::<u32, _>("third"),
+        );
+        let error = results.collect(&["a()", "b()", "c()"]).unwrap_err();
+
+        let failures = error.failures();
+        assert_eq!(failures.len(), 2);
+        assert_eq!((failures[0].index, failures[0].call), (0, "a()"));
+        assert_eq!((failures[1].index, failures[1].call), (2, "c()"));
+        assert_eq!(
+            error.to_string(),
+            "2 joined call(s) failed; #0 `a()`: first; #2 `c()`: third"
+        );
+    }
+
+    #[test]
+    fn joins_borrowing_calls() {
+        tokio_test::block_on(async {
+            let value = std::cell::Cell::new(0);
+            let bump = || async {
+                value.set(value.get() + 1);
+                Ok::<_, &str>(value.get())
+            };
+
+            let (a, b) = crate::api_join!(bump(), bump()).unwrap();
+            assert_eq!(a + b, 3);
+        });
+    }
+}
@@ -0,0 +1,414 @@
+//! JSON-RPC 2.0 calls over HTTP.
+//!
+//! Endpoints declared with `params: RpcParams<P>` and returning `Rpc<T>` call the remote method
+//! named like the endpoint, or as given with `with { rpc: "name" }`, with `params` of type `P`.
+//! The request envelope and its id are built for every call, and the `result` of the response is
+//! returned as `T`, or its `error` object as [`Error::Rpc`](crate::Error::Rpc).
+//!
+//! Several calls can be sent in one request with a [`Batch`] and
+//! [`Api::rpc_batch`](crate::Api::rpc_batch), whose responses are matched to the calls by their
+//! ids.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, jsonrpc::Batch, Api, ResultType};
+//!
+//! #[derive(serde::Serialize)]
+//! struct GetBlock {
+//!     height: u64,
+//! }
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Block {
+//!     hash: String,
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn get_block(params: RpcParams<GetBlock>) -> Rpc<Block> {
+//!            POST "https://example.com/rpc"
+//!            with { rpc: "getblock" }
+//!         }
+//!     }
+//! }
+//!
+//! async fn hashes(api: &ExampleApi) -> ResultType<(String, String)> {
+//!     let first = api.get_block(&GetBlock { height: 1 }).await?.hash;
+//!
+//!     let mut batch = Batch::new();
+//!     let second = batch.call::<_, Block>("getblock", &GetBlock { height: 2 })?;
+//!     let responses = api.rpc_batch("https://example.com/rpc", &batch).await?;
+//!     let second = responses.get(&second).expect("no response for the call")?.hash;
+//!     Ok((first, second))
+//! }
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    endpoint::{self, EndpointSpec},
+    response::{Bytes, ResponseKind},
+    Api, Body, Error, ResultType,
+};
+
+/// Version of the protocol sent in every request.
+pub const VERSION: &str = "2.0";
+
+/// Error code of a request which is not valid JSON.
+pub const PARSE_ERROR: i64 = -32700;
+/// Error code of a request which is not a valid request object.
+pub const INVALID_REQUEST: i64 = -32600;
+/// Error code of a call to a method which does not exist.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// Error code of a call with invalid parameters.
+pub const INVALID_PARAMS: i64 = -32602;
+/// Error code of an internal error of the server.
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// Id of the next call, unique within the process.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Request object of a call, see [`jsonrpc`](crate::jsonrpc).
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcRequest<'a, P: ?Sized> {
+    /// Version of the protocol, always [`VERSION`].
+    pub jsonrpc: &'static str,
+    /// Name of the method to call.
+    pub method: Cow<'static, str>,
+    /// Parameters of the call, an object or an array.
+    pub params: &'a P,
+    /// Id of the call, `None` for notifications, which the server doesn't answer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+}
+
+impl<'a, P: ?Sized> RpcRequest<'a, P> {
+    /// Creates a call of `method` with `params` and a new id.
+    pub fn new(method: impl Into<Cow<'static, str>>, params: &'a P) -> Self {
+        Self {
+            jsonrpc: VERSION,
+            method: method.into(),
+            params,
+            id: Some(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+        }
+    }
+
+    /// Creates a notification of `method` with `params`.
+    pub fn notification(method: impl Into<Cow<'static, str>>, params: &'a P) -> Self {
+        Self {
+            id: None,
+            ..Self::new(method, params)
+        }
+    }
+}
+
+/// Error object of a failed call, see [`Error::Rpc`](crate::Error::Rpc).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    /// Code of the error, e.g. [`METHOD_NOT_FOUND`].
+    pub code: i64,
+    /// Short description of the error.
+    pub message: String,
+    /// Further details defined by the server, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "JSON-RPC call failed with code {}: {}",
+            self.code, self.message
+        )
+    }
+}
+
+/// Response object of a call.
+#[derive(Deserialize)]
+struct RpcResponse {
+    /// Id of the call, `None` if the server could not read it.
+    #[serde(default)]
+    id: Option<u64>,
+    /// Result of a successful call.
+    #[serde(default)]
+    result: Option<serde_json::Value>,
+    /// Error of a failed call.
+    #[serde(default)]
+    error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    /// Returns the result, or the error of a failed call.
+    fn into_result(self) -> Result<serde_json::Value, RpcError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.result.unwrap_or_default()),
+        }
+    }
+}
+
+/// Decodes `result` as `T`, failing with [`Error::Rpc`] for errors of the call.
+fn decode<T: DeserializeOwned>(result: Result<serde_json::Value, RpcError>) -> ResultType<T> {
+    serde_json::from_value(result.map_err(Error::Rpc)?).map_err(Error::Json)
+}
+
+/// Returns the `result` of a call as `T`, see [`jsonrpc`](crate::jsonrpc).
+#[derive(Debug)]
+pub struct Rpc<T>(PhantomData<T>);
+
+#[async_trait::async_trait(?Send)]
+impl<T: DeserializeOwned> ResponseKind for Rpc<T> {
+    type Output = T;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        let response: RpcResponse = response.json().await?;
+        decode(response.into_result())
+    }
+}
+
+/// Calls and notifications sent in one request, see [`Api::rpc_batch`](crate::Api::rpc_batch).
+#[derive(Debug, Clone, Default)]
+pub struct Batch {
+    /// Request objects of the calls.
+    requests: Vec<serde_json::Value>,
+}
+
+impl Batch {
+    /// Creates an empty batch.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a call of `method` with `params`, returning the handle its result is read with.
+    ///
+    /// # Errors
+    /// Returns an error if `params` could not be serialized.
+    pub fn call<P: Serialize + ?Sized, T>(
+        &mut self,
+        method: impl Into<Cow<'static, str>>,
+        params: &P,
+    ) -> ResultType<Call<T>> {
+        let request = RpcRequest::new(method, params);
+        let id = request.id.unwrap_or_default();
+        self.push(&request)?;
+        Ok(Call {
+            id,
+            marker: PhantomData,
+        })
+    }
+
+    /// Adds a notification of `method` with `params`.
+    ///
+    /// # Errors
+    /// Returns an error if `params` could not be serialized.
+    pub fn notify<P: Serialize + ?Sized>(
+        &mut self,
+        method: impl Into<Cow<'static, str>>,
+        params: &P,
+    ) -> ResultType<()> {
+        self.push(&RpcRequest::notification(method, params))
+    }
+
+    /// Returns the number of calls and notifications.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Returns whether the batch holds no calls or notifications.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Adds a request object.
+    fn push<P: Serialize + ?Sized>(&mut self, request: &RpcRequest<'_, P>) -> ResultType<()> {
+        let request = serde_json::to_value(request).map_err(Error::Json)?;
+        self.requests.push(request);
+        Ok(())
+    }
+}
+
+/// Handle of a call in a [`Batch`], reading its result as `T` from the [`BatchResponse`].
+#[derive(Debug)]
+pub struct Call<T> {
+    /// Id of the call.
+    id: u64,
+    /// Type of the result.
+    marker: PhantomData<fn() -> T>,
+}
+
+/// Responses to the calls of a [`Batch`].
+#[derive(Debug, Clone, Default)]
+pub struct BatchResponse {
+    /// Results of the calls by their ids.
+    results: HashMap<u64, Result<serde_json::Value, RpcError>>,
+}
+
+impl BatchResponse {
+    /// Returns the result of `call`, or `None` if the server sent no response for it.
+    pub fn get<T: DeserializeOwned>(&self, call: &Call<T>) -> Option<ResultType<T>> {
+        self.results.get(&call.id).cloned().map(decode)
+    }
+
+    /// Returns the number of responses.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Returns whether the server sent no responses, as for batches of notifications.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+/// Sends the calls of `batch` to `url`, see [`Api::rpc_batch`](crate::Api::rpc_batch).
+pub(crate) async fn send_batch<A: Api + ?Sized>(
+    api: &A,
+    url: &str,
+    batch: &Batch,
+) -> ResultType<BatchResponse> {
+    if batch.is_empty() {
+        return Ok(BatchResponse::default());
+    }
+    let spec = EndpointSpec {
+        name: "rpc_batch",
+        method: reqwest::Method::POST,
+        url: "{url}",
+        timeout: None,
+        deadline: None,
+        retry: None,
+        pointer: None,
+        idempotent: false,
+        scopes: &[],
+        requires: &[],
+        grpc_status: false,
+        login: None,
+        cache_mode: None,
+        cache_lifetime: None,
+    };
+    let body = endpoint::execute::<A, Bytes, _>(api, &spec, url, Body::Json(&batch.requests), None)
+        .await?;
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(BatchResponse::default());
+    }
+    let responses: Vec<RpcResponse> = serde_json::from_slice(&body).map_err(Error::Json)?;
+    let results = responses
+        .into_iter()
+        .filter_map(|response| Some((response.id?, response.into_result())))
+        .collect();
+    Ok(BatchResponse { results })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Batch, METHOD_NOT_FOUND};
+    use crate::{api, test_server, Api, Error};
+
+    #[derive(serde::Serialize)]
+    struct Add {
+        a: u32,
+        b: u32,
+    }
+
+    api!(struct Calculator);
+
+    impl Calculator {
+        api! {
+            fn add(&self, params: RpcParams<Add>, base_url: &str) -> Rpc<u32> {
+                POST "{base_url}/rpc"
+            }
+
+            fn subtract(&self, params: RpcParams<[u32; 2]>, base_url: &str) -> Rpc<u32> {
+                POST "{base_url}/rpc"
+                with { rpc: "math.subtract" }
+            }
+        }
+    }
+
+    /// Answers a request object of the calculator.
+    fn answer(request: &serde_json::Value) -> Option<serde_json::Value> {
+        assert_eq!(request["jsonrpc"], "2.0");
+        let params = &request["params"];
+        let id = request.get("id")?;
+        Some(match request["method"].as_str().unwrap() {
+            "add" => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": params["a"].as_u64().unwrap() + params["b"].as_u64().unwrap(),
+            }),
+            "math.subtract" => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": params[0].as_u64().unwrap() - params[1].as_u64().unwrap(),
+            }),
+            _ => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": -32601, "message": "Method not found"},
+            }),
+        })
+    }
+
+    #[test]
+    fn calls_methods() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                let response = match body.as_array() {
+                    Some(requests) => {
+                        let responses = requests.iter().filter_map(answer).collect::<Vec<_>>();
+                        if responses.is_empty() {
+                            return test_server::Response::new(204);
+                        }
+                        serde_json::Value::from(responses)
+                    }
+                    None => answer(&body).unwrap(),
+                };
+                test_server::Response::new(200).json(&response.to_string())
+            })
+            .await;
+            let api = Calculator::new();
+
+            assert_eq!(api.add(&Add { a: 1, b: 2 }, &base_url).await.unwrap(), 3);
+            assert_eq!(api.subtract(&[5, 3], &base_url).await.unwrap(), 2);
+
+            let mut batch = Batch::new();
+            let sum = batch.call::<_, u32>("add", &Add { a: 2, b: 2 }).unwrap();
+            let missing = batch.call::<_, u32>("divide", &[1, 0]).unwrap();
+            batch.notify("add", &Add { a: 0, b: 0 }).unwrap();
+            let url = format!("{base_url}/rpc");
+            let responses = api.rpc_batch(&url, &batch).await.unwrap();
+            assert_eq!(responses.len(), 2);
+            assert_eq!(responses.get(&sum).unwrap().unwrap(), 4);
+            let error = responses.get(&missing).unwrap().unwrap_err();
+            assert!(matches!(&error, Error::Rpc(error) if error.code == METHOD_NOT_FOUND));
+            assert_eq!(
+                error.to_string(),
+                "JSON-RPC call failed with code -32601: Method not found"
+            );
+
+            let mut notifications = Batch::new();
+            notifications.notify("add", &Add { a: 0, b: 0 }).unwrap();
+            let responses = api.rpc_batch(&url, &notifications).await.unwrap();
+            assert!(responses.is_empty());
+        });
+    }
+}
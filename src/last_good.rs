@@ -0,0 +1,152 @@
+//! Store of the last successful responses, served in place of errors during outages.
+//!
+//! Endpoints declared with `with { fallback: last_good }` record their decoded outputs in the
+//! store of their instance, set with
+//! [`ApiState::last_good_store`](crate::ApiState::last_good_store), and return them when a later
+//! call with the same parameters fails. They are also available directly through
+//! [`Api::last_good`](crate::Api::last_good), e.g. to show stale data with a warning.
+//!
+//! The store keeps a bounded number of responses, evicting the least recently used one, and can
+//! be saved to disk with [`LastGoodStore::snapshot`] and loaded with [`LastGoodStore::restore`].
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, last_good::LastGoodStore, Api, ApiState};
+//!
+//! api!(pub struct StatusApi);
+//!
+//! impl StatusApi {
+//!     api! {
+//!         fn status(&self, service: &str) -> Json<serde_json::Value> {
+//!            GET "https://status.example.com/{service}"
+//!            with { fallback: last_good }
+//!         }
+//!     }
+//! }
+//!
+//! let api = StatusApi::builder()
+//!     .state(ApiState::new().last_good_store(LastGoodStore::new(100)))
+//!     .build()
+//!     .unwrap();
+//!
+//! let stale: Option<serde_json::Value> =
+//!     api.last_good("status", "https://status.example.com/database");
+//! ```
+
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, PoisonError},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Serializable contents of a [`LastGoodStore`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LastGoodSnapshot {
+    /// Keys and outputs, from the least to the most recently used.
+    pub entries: Vec<(String, serde_json::Value)>,
+}
+
+/// Bounded store of the last successful output per endpoint and parameters.
+#[derive(Debug)]
+pub struct LastGoodStore {
+    /// Maximum number of stored outputs.
+    capacity: usize,
+    /// Stored outputs, from the least to the most recently used.
+    entries: Mutex<VecDeque<(String, serde_json::Value)>>,
+}
+
+impl LastGoodStore {
+    /// Creates an empty store keeping at most `capacity` outputs.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "a last good store has to keep at least one output"
+        );
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns the key of the output of `endpoint` requested from `url`.
+    ///
+    /// The URL includes all parameters of the endpoint which affect its output.
+    #[must_use]
+    pub fn key(endpoint: &str, url: &str) -> String {
+        format!("{endpoint} {url}")
+    }
+
+    /// Returns the output stored for `key`, if any and if it can be deserialized as `T`.
+    #[must_use]
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = entries.iter().position(|(stored, _)| stored == key)?;
+        let entry = entries.remove(index)?;
+        let output = serde_json::from_value(entry.1.clone()).ok();
+        entries.push_back(entry);
+        output
+    }
+
+    /// Stores `output` for `key`, evicting the least recently used output if the store is full.
+    ///
+    /// Outputs which cannot be serialized are not stored.
+    pub fn insert<T: Serialize>(&self, key: String, output: &T) {
+        let Ok(value) = serde_json::to_value(output) else {
+            return;
+        };
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.retain(|(stored, _)| *stored != key);
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back((key, value));
+    }
+
+    /// Returns a snapshot of all stored outputs, e.g. to save them to disk.
+    #[must_use]
+    pub fn snapshot(&self) -> LastGoodSnapshot {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        LastGoodSnapshot {
+            entries: entries.iter().cloned().collect(),
+        }
+    }
+
+    /// Replaces all stored outputs with the ones in `snapshot`, keeping the most recently used
+    /// ones if it holds more than the capacity.
+    pub fn restore(&self, snapshot: LastGoodSnapshot) {
+        let skip = snapshot.entries.len().saturating_sub(self.capacity);
+        *self.entries.lock().unwrap_or_else(PoisonError::into_inner) =
+            snapshot.entries.into_iter().skip(skip).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LastGoodSnapshot, LastGoodStore};
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let store = LastGoodStore::new(2);
+        store.insert("a".to_string(), &1);
+        store.insert("b".to_string(), &2);
+        assert_eq!(store.get::<u32>("a"), Some(1));
+        store.insert("c".to_string(), &3);
+        assert_eq!(store.get::<u32>("b"), None);
+        assert_eq!(store.get::<String>("a"), None);
+
+        let snapshot = store.snapshot();
+        assert_eq!(snapshot.entries.len(), 2);
+        let restored = LastGoodStore::new(1);
+        restored.restore(snapshot);
+        assert_eq!(restored.get::<u32>("c"), None);
+        assert_eq!(restored.get::<u32>("a"), Some(1));
+
+        restored.restore(LastGoodSnapshot::default());
+        assert_eq!(restored.get::<u32>("a"), None);
+    }
+}
@@ -3,7 +3,156 @@
 #![warn(clippy::missing_docs_in_private_items)]
 #![warn(clippy::pedantic)]
 
+pub use error::Error;
+pub use reqwest;
+#[cfg(feature = "middleware")]
+#[cfg_attr(docsrs, doc(cfg(feature = "middleware")))]
+pub use reqwest_middleware;
 use serde::Serialize;
+pub use state::ApiState;
+
+pub mod auth;
+pub mod batch;
+pub mod builder;
+pub mod cache;
+pub mod cancel;
+pub mod capture;
+pub mod checksum;
+pub mod circuit_breaker;
+pub mod context;
+#[cfg(feature = "cookies")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+pub mod cookies;
+pub mod curl;
+pub mod deadline;
+pub mod deprecation;
+pub mod discovery;
+pub mod endpoint;
+mod error;
+pub mod etag;
+#[cfg(feature = "graphql")]
+#[cfg_attr(docsrs, doc(cfg(feature = "graphql")))]
+pub mod graphql;
+pub mod grpc;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod har;
+pub mod idempotency;
+pub mod interceptor;
+pub mod join;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod jsonrpc;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod last_good;
+pub mod links;
+pub mod locale;
+#[cfg(feature = "tracing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+pub mod logging;
+#[cfg(all(test, feature = "json"))]
+mod matrix;
+mod meter;
+pub mod multipart_upload;
+#[cfg(feature = "oidc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "oidc")))]
+pub mod oidc;
+#[cfg(feature = "openapi")]
+#[cfg_attr(docsrs, doc(cfg(feature = "openapi")))]
+pub mod openapi;
+pub mod pagination;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod patch;
+pub mod permissions;
+pub mod poll;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod postman;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod problem;
+pub mod progress;
+pub mod protocol;
+pub mod proxy;
+pub mod query;
+pub mod rate_limit;
+pub mod related;
+pub mod request_id;
+pub mod response;
+pub mod retry;
+pub mod runtime;
+#[cfg(all(feature = "cookies", feature = "json"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "cookies", feature = "json"))))]
+pub mod session;
+pub mod signing;
+mod single_flight;
+pub mod smoke;
+mod state;
+#[cfg(any(test, feature = "test-support"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-support")))]
+pub mod test_server;
+pub mod tls;
+mod trace;
+pub mod transport;
+pub mod tus;
+#[cfg(feature = "stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+pub mod upload;
+pub mod url_policy;
+
+/// Re-exports and helpers used by the exported macros.
+#[doc(hidden)]
+pub mod __private {
+    pub use futures_util::join;
+    pub use paste::paste;
+    #[cfg(feature = "graphql-ws")]
+    pub use serde_json;
+
+    /// Compile time check that exactly one TLS backend feature is enabled.
+    ///
+    /// The check is only evaluated when `CHECK` is used with a concrete type, which the
+    /// constructors generated by the api! macro do, so the error shows up in the crate using them.
+    pub struct TlsBackend<T>(core::marker::PhantomData<T>);
+
+    impl<T> TlsBackend<T> {
+        /// Evaluates to `()` if exactly one TLS backend feature is enabled.
+        #[cfg(any(
+            all(feature = "rustls-tls", not(feature = "native-tls")),
+            all(feature = "native-tls", not(feature = "rustls-tls"))
+        ))]
+        pub const CHECK: () = ();
+
+        /// Fails to evaluate, since both TLS backend features are enabled.
+        #[cfg(all(feature = "rustls-tls", feature = "native-tls"))]
+        pub const CHECK: () = panic!(
+            "api-client: both the `rustls-tls` and `native-tls` features are enabled, \
+             disable default features to select exactly one TLS backend"
+        );
+
+        /// Fails to evaluate, since no TLS backend feature is enabled.
+        #[cfg(not(any(feature = "rustls-tls", feature = "native-tls")))]
+        pub const CHECK: () = panic!(
+            "api-client: no TLS backend is enabled, \
+             enable exactly one of the `rustls-tls` or `native-tls` features"
+        );
+    }
+
+    /// Turns a plain reqwest client into a [`ClientType`](crate::ClientType).
+    #[cfg(not(feature = "middleware"))]
+    #[must_use]
+    pub fn wrap_client(client: reqwest::Client) -> crate::ClientType {
+        client
+    }
+
+    /// Turns a plain reqwest client into a [`ClientType`](crate::ClientType), without any middleware.
+    #[cfg(feature = "middleware")]
+    #[must_use]
+    pub fn wrap_client(client: reqwest::Client) -> crate::ClientType {
+        reqwest_middleware::ClientBuilder::new(client).build()
+    }
+}
 
 #[cfg(not(feature = "middleware"))]
 /// Type of the reqwest client, depending on the features
@@ -21,13 +170,8 @@ pub type ClientError = reqwest::Error;
 /// Type of the reqwest client, depending on the features
 pub type ClientError = reqwest_middleware::Error;
 
-#[cfg(not(feature = "middleware"))]
-/// Type of the reqwest `Result`type, depending on the features
-pub type ResultType<T> = reqwest::Result<T>;
-
-#[cfg(feature = "middleware")]
-/// Type of the reqwest `Result`type, depending on the features
-pub type ResultType<T> = reqwest_middleware::Result<T>;
+/// Result type of the requests sent by [`Api`] implementations, see [`Error`].
+pub type ResultType<T> = Result<T, Error>;
 
 #[cfg(not(feature = "middleware"))]
 /// Type of the reqwest request builder, depending on the features
@@ -46,17 +190,79 @@ pub enum Body<'a, T: Serialize + ?Sized = ()> {
     #[cfg(feature = "json")]
     #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
     Json(&'a T),
+    /// JSON body sent with another media type than `application/json`, e.g.
+    /// `application/vnd.api+json`.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    JsonAs(&'a T, &'static str),
+    /// JSON Merge Patch body, see [`patch`].
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    MergePatch(&'a T),
+    /// JSON Patch body, see [`patch`].
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    JsonPatch(&'a T),
     /// Form body.
     Form(&'a T),
     #[cfg(feature = "multipart")]
     #[cfg_attr(docsrs, doc(cfg(feature = "multipart")))]
     /// Multipart body.
     Multipart(reqwest::multipart::Form),
+    /// Body streamed as it is sent.
+    #[cfg(feature = "stream")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+    Stream(upload::Upload),
+    /// Framed gRPC-Web message.
+    #[cfg(feature = "grpc-web")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "grpc-web")))]
+    GrpcWeb(bytes::Bytes),
+}
+
+impl<T: Serialize + ?Sized> Body<'_, T> {
+    /// Attaches the body to `request`.
+    #[doc(hidden)]
+    pub fn apply(self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Body::None => request,
+            #[cfg(feature = "json")]
+            Body::Json(body) => request.json(body),
+            #[cfg(feature = "json")]
+            Body::JsonAs(body, media_type) => request
+                .header(reqwest::header::CONTENT_TYPE, media_type)
+                .json(body),
+            #[cfg(feature = "json")]
+            Body::MergePatch(body) => request
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    patch::MERGE_PATCH_CONTENT_TYPE,
+                )
+                .json(body),
+            #[cfg(feature = "json")]
+            Body::JsonPatch(body) => request
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    patch::JSON_PATCH_CONTENT_TYPE,
+                )
+                .json(body),
+            Body::Form(body) => request.form(body),
+            #[cfg(feature = "multipart")]
+            Body::Multipart(form) => request.multipart(form),
+            #[cfg(feature = "stream")]
+            Body::Stream(upload) => upload.apply(request),
+            #[cfg(feature = "grpc-web")]
+            Body::GrpcWeb(frame) => request
+                .header(reqwest::header::CONTENT_TYPE, grpc::web::CONTENT_TYPE)
+                .header(reqwest::header::ACCEPT, grpc::web::CONTENT_TYPE)
+                .header("x-grpc-web", "1")
+                .body(frame),
+        }
+    }
 }
 
 /// The main API trait.
 ///
-/// If you need custom behavior, such as authentication, you should implement this trait on your custom struct. See the [Api::pre_request] method for more details.
+/// If you need custom behavior, such as authentication, you should implement this trait on your custom struct. See the [`Api::pre_request`] method for more details.
 ///
 /// Otherwise, you can use the [api] macro to generate a struct with a proper implementation of this trait.
 #[async_trait::async_trait(?Send)]
@@ -64,15 +270,73 @@ pub trait Api {
     /// Returns a reference to a reqwest Client to create requests.
     fn client(&self) -> &ClientType;
 
+    /// Returns the runtime state shared by all requests, such as the options of [`ApiState`].
+    ///
+    /// Structs generated by the [api] macro store their own, configured through their builder.
+    /// When implementing this trait manually, store an [`ApiState`] and return it here.
+    fn state(&self) -> &ApiState {
+        ApiState::empty()
+    }
+
+    /// Returns the rate limit announced by the server in its last response which carried rate
+    /// limit headers, see [`rate_limit`].
+    ///
+    /// Always `None` for implementations which don't store their own [`ApiState`].
+    fn rate_limit_state(&self) -> Option<rate_limit::RateLimit> {
+        self.state().rate_limit()
+    }
+
+    /// Returns the id sent with the last call, see [`request_id`].
+    ///
+    /// Always `None` unless request ids are enabled with [`ApiState::request_ids`].
+    fn last_request_id(&self) -> Option<String> {
+        self.state().last_request_id()
+    }
+
+    /// Returns a snapshot of the cookies stored for this instance, e.g. to persist a session.
+    ///
+    /// Empty unless cookies are enabled with [`ApiState::cookies`], see [`cookies`].
+    #[cfg(feature = "cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+    fn export_cookies(&self) -> cookies::CookieSnapshot {
+        self.state()
+            .cookie_jar()
+            .map(|jar| jar.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the cookies stored for this instance with the ones in `snapshot`.
+    ///
+    /// Does nothing unless cookies are enabled with [`ApiState::cookies`], see [`cookies`].
+    #[cfg(feature = "cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+    fn import_cookies(&self, snapshot: cookies::CookieSnapshot) {
+        if let Some(jar) = self.state().cookie_jar() {
+            jar.restore(snapshot);
+        }
+    }
+
+    /// Adds the headers and query parameters declared for every request of a struct generated by
+    /// the [api] macro with `headers { ... }` and `query { ... }`, see [Defaults](api#defaults).
+    ///
+    /// Runs before [`Api::pre_request`]. The default adds none.
+    #[inline]
+    fn apply_defaults(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+    }
+
     /// You can use this method to modify the request before sending it.
     ///
     /// Some good examples of usage are:
     ///  - Authentication
     ///  - Custom headers (can also be done with a method on Client)
     ///
+    /// # Errors
+    /// Any error returned here aborts the request before it is sent.
+    ///
     /// # Authentication
     /// ```rust
-    /// use api_client::{api, Api};
+    /// use api_client::{api, Api, ResultType};
     /// use reqwest::{Client, RequestBuilder};
     ///
     /// struct ExampleApi {
@@ -86,7 +350,7 @@ pub trait Api {
     ///         &self.client
     ///     }
     ///
-    ///     fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+    ///     fn pre_request(&self, request: RequestBuilder) -> ResultType<RequestBuilder> {
     ///         Ok(request.basic_auth(&self.username, Some(&self.password)))
     ///     }
     /// }
@@ -110,15 +374,19 @@ pub trait Api {
     ///  - Authentication
     ///  - Updating client fields
     ///
+    /// Requests are made through `&self` so that calls can run concurrently; use interior
+    /// mutability for any state that needs updating here.
+    ///
     /// # Authentication
     /// ```rust
+    /// use std::sync::Mutex;
+    ///
     /// use api_client::{api, Api};
-    /// use reqwest::{Client, RequestBuilder};
+    /// use reqwest::{Client, Response};
     ///
     /// struct ExampleApi {
     ///     client: Client,
-    ///     username: String,
-    ///     password: String
+    ///     session: Mutex<Option<String>>
     /// }
     ///
     /// impl Api for ExampleApi {
@@ -126,9 +394,9 @@ pub trait Api {
     ///         &self.client
     ///     }
     ///
-    ///     fn post_response(&mut self, response: Response) -> Response {
-    ///         for cookie in self.cookies() {
-    ///             // do something with cookie
+    ///     fn post_response(&self, response: Response) -> Response {
+    ///         if let Some(session) = response.headers().get("x-session") {
+    ///             *self.session.lock().unwrap() = session.to_str().ok().map(String::from);
     ///         }
     ///         response
     ///     }
@@ -142,10 +410,207 @@ pub trait Api {
     ///     }
     /// }
     /// ```
-    fn post_response(&mut self, response: reqwest::Response) -> reqwest::Response {
+    fn post_response(&self, response: reqwest::Response) -> reqwest::Response {
         response
     }
 
+    /// Receives the token in the response of an endpoint declared `with { login: ... }`, see
+    /// [`auth`](auth#login-endpoints).
+    ///
+    /// Structs declared with `auth = ...` send it with every later request. The default ignores
+    /// it; manually implemented clients store it where their
+    /// [`pre_request`](Api::pre_request) hook reads it.
+    fn on_login(&self, token: String) {
+        let _ = token;
+    }
+
+    /// Receives the deprecation announced by the first response of `endpoint` which carried
+    /// `Deprecation` or `Sunset` headers, see [`deprecation`].
+    ///
+    /// The default logs a `WARN` event with the `tracing` feature.
+    fn on_deprecation(&self, endpoint: &str, deprecation: &deprecation::Deprecation) {
+        trace::deprecation(endpoint, deprecation);
+    }
+
+    /// Returns the payload of the `connection_init` message opening a GraphQL subscription, see
+    /// [`graphql::ws`].
+    ///
+    /// `request` is the handshake request of the subscription with the
+    /// [`pre_request`](Api::pre_request) hook applied. The default passes on its `Authorization`
+    /// header as `{"Authorization": "..."}`, and sends no payload without one.
+    #[cfg(feature = "graphql-ws")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "graphql-ws")))]
+    fn graphql_init_payload(&self, request: &reqwest::Request) -> Option<serde_json::Value> {
+        let authorization = request.headers().get(reqwest::header::AUTHORIZATION)?;
+        let authorization = authorization.to_str().ok()?;
+        Some(serde_json::json!({ "Authorization": authorization }))
+    }
+
+    /// Returns the scopes granted to the current token, if known, see [`permissions`].
+    ///
+    /// Calls to endpoints declared with scopes which are not granted fail before a request is
+    /// sent. The default returns `None`, which skips these checks.
+    fn granted_scopes(&self) -> Option<permissions::Scopes> {
+        None
+    }
+
+    /// Checks that all of the `required` scopes are granted to the current token, e.g. at
+    /// startup, see [`permissions`].
+    ///
+    /// The scopes of endpoints declared with the [api] macro are listed by the `endpoints()`
+    /// function of their block, see [`EndpointMeta`](endpoint::EndpointMeta).
+    ///
+    /// # Errors
+    /// Returns [`Error::MissingScopes`] listing the scopes which are not granted. Succeeds if
+    /// [`Api::granted_scopes`] returns `None`.
+    fn check_permissions(&self, required: &[&str]) -> ResultType<()> {
+        match self.granted_scopes() {
+            Some(granted) => granted.require(None, required),
+            None => Ok(()),
+        }
+    }
+
+    /// Calls an endpoint for every item of `inputs`, running several calls at once.
+    ///
+    /// See [`batch`] for an example.
+    fn batch<'a, I, F, Fut, T>(
+        &'a self,
+        inputs: I,
+        call: F,
+    ) -> batch::Batch<'a, Self, I::IntoIter, F>
+    where
+        I: IntoIterator,
+        F: FnMut(&'a Self, I::Item) -> Fut,
+        Fut: std::future::Future<Output = ResultType<T>> + 'a,
+    {
+        batch::Batch::new(self, inputs, call)
+    }
+
+    /// Fetches a collection of `total` items in pages of `page_size` items, several pages at once.
+    ///
+    /// See [`pagination`] for an example.
+    fn parallel_scan<'a, F, Fut, T>(
+        &'a self,
+        total: usize,
+        page_size: usize,
+        fetch: F,
+    ) -> pagination::ParallelScan<'a, Self, F>
+    where
+        F: FnMut(&'a Self, pagination::Page) -> Fut,
+        Fut: std::future::Future<Output = ResultType<Vec<T>>> + 'a,
+    {
+        pagination::ParallelScan::new(self, total, page_size, fetch)
+    }
+
+    /// Calls an endpoint with `call` until `done` returns `true` for its output, e.g. the status
+    /// endpoint of a long-running operation.
+    ///
+    /// See [`poll`] for an example.
+    fn poll_until<'a, F, Fut, P, T>(&'a self, call: F, done: P) -> poll::Poll<'a, Self, F, P, T>
+    where
+        F: FnMut(&'a Self) -> Fut,
+        Fut: std::future::Future<Output = ResultType<T>>,
+        P: FnMut(&T) -> bool,
+    {
+        poll::Poll::new(self, call, done)
+    }
+
+    /// Returns the last successful output of `endpoint` requested from `url`, if the instance
+    /// keeps a [`LastGoodStore`](last_good::LastGoodStore) and the endpoint is declared with
+    /// `fallback: last_good`.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    fn last_good<T: serde::de::DeserializeOwned>(&self, endpoint: &str, url: &str) -> Option<T> {
+        self.state()
+            .last_good()?
+            .get(&last_good::LastGoodStore::key(endpoint, url))
+    }
+
+    /// Fetches a [`Related`](related::Related) resource, using the same hooks as every endpoint.
+    ///
+    /// # Errors
+    /// Returns an error if the request failed or the response could not be deserialized.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    async fn fetch<T: serde::de::DeserializeOwned>(
+        &self,
+        related: &related::Related<T>,
+    ) -> ResultType<T> {
+        let spec = endpoint::EndpointSpec {
+            name: "fetch",
+            method: reqwest::Method::GET,
+            url: "{related}",
+            timeout: None,
+            deadline: None,
+            retry: None,
+            pointer: None,
+            idempotent: true,
+            scopes: &[],
+            requires: &[],
+            grpc_status: false,
+            login: None,
+            cache_mode: None,
+            cache_lifetime: None,
+        };
+        endpoint::execute::<Self, response::Json<T>, ()>(
+            self,
+            &spec,
+            related.url(),
+            Body::None,
+            None,
+        )
+        .await
+    }
+
+    /// Fetches the resource a [`Link`](links::Link) points to, using the same hooks as every
+    /// endpoint.
+    ///
+    /// See [`links`] for an example. Relative links have to be
+    /// [resolved](links::Links::resolve) first.
+    ///
+    /// # Errors
+    /// Returns an error if the request failed or the response could not be deserialized.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    async fn follow<T: serde::de::DeserializeOwned>(&self, link: &links::Link) -> ResultType<T> {
+        let spec = endpoint::EndpointSpec {
+            name: "follow",
+            method: reqwest::Method::GET,
+            url: "{link}",
+            timeout: None,
+            deadline: None,
+            retry: None,
+            pointer: None,
+            idempotent: true,
+            scopes: &[],
+            requires: &[],
+            grpc_status: false,
+            login: None,
+            cache_mode: None,
+            cache_lifetime: None,
+        };
+        endpoint::execute::<Self, response::Json<T>, ()>(self, &spec, &link.href, Body::None, None)
+            .await
+    }
+
+    /// Sends the calls and notifications of a JSON-RPC [`Batch`](jsonrpc::Batch) to `url` in one
+    /// request, using the same hooks as every endpoint.
+    ///
+    /// See [`jsonrpc`] for an example. Empty batches are not sent.
+    ///
+    /// # Errors
+    /// Returns an error if the request failed or the response is not a batch of response objects.
+    /// Errors of single calls are returned by [`BatchResponse::get`](jsonrpc::BatchResponse::get).
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    async fn rpc_batch(
+        &self,
+        url: &str,
+        batch: &jsonrpc::Batch,
+    ) -> ResultType<jsonrpc::BatchResponse> {
+        jsonrpc::send_batch(self, url, batch).await
+    }
+
     /// Used internally in the api! macro. Mostly for ergonmics.
     ///
     /// # Usage
@@ -168,241 +633,1616 @@ pub trait Api {
         unimplemented!()
     }
 
-    /// Used internally in the api! macro to handle all requests.
+    /// Used internally in the api! macro to create the request for an endpoint, before its body
+    /// is attached.
     #[doc(hidden)]
-    #[inline]
-    async fn request<T: Serialize + ?Sized>(
-        &mut self,
-        method: reqwest::Method,
+    fn build_request(
+        &self,
+        spec: &endpoint::EndpointSpec,
         url: &str,
-        body: Body<'_, T>,
-    ) -> ResultType<reqwest::Response> {
-        let request = self.pre_request(self.client().request(method, url))?;
-        let request = match body {
-            Body::None => request,
-            #[cfg(feature = "json")]
-            Body::Json(body) => request.json(body),
-            Body::Form(body) => request.form(body),
-            #[cfg(feature = "multipart")]
-            Body::Multipart(form) => request.multipart(form),
+    ) -> ResultType<RequestBuilder> {
+        if let (Some(policy), Ok(parsed)) = (self.state().allowed_urls(), reqwest::Url::parse(url))
+        {
+            policy.check(&parsed).map_err(Error::UrlRejected)?;
+        }
+        let mut request = self.client().request(spec.method.clone(), url);
+        if let Some(timeout) = spec.timeout {
+            request = request.timeout(timeout);
+        }
+        let request = self.pre_request(self.apply_defaults(request))?;
+        #[cfg(feature = "trace-context")]
+        let request = trace::propagate(self.state(), request);
+        Ok(request)
+    }
+
+    /// Used internally in the api! macro to send all requests.
+    ///
+    /// This is only generic over the implementing type, so it is compiled once per api struct
+    /// rather than once per endpoint.
+    #[doc(hidden)]
+    async fn send(&self, request: RequestBuilder) -> ResultType<reqwest::Response> {
+        let state = self.state();
+        let transport = state.transport_backend();
+        let response = match (state.interceptors(), transport) {
+            ([], None) => request.send().await?,
+            ([], Some(transport)) => transport::send(transport, request.build()?).await?,
+            (interceptors, transport) => {
+                let next = interceptor::Next::new(interceptors, self.client(), transport);
+                next.run(request.build()?).await?
+            }
         };
-        request.send().await.map(|r| self.post_response(r))
+        Ok(self.post_response(response))
     }
 }
 
-/// Magic macro for API structs.
-///
-/// # Simple Usage (auto generated struct)
-/// ```rust
-/// use api_client::{api, Api};
-/// use reqwest::{Client, RequestBuilder};
-///
-/// api!(pub struct ExampleApi);
-///
-/// impl ExampleApi {
-///     api! {
-///         fn example() -> String {
-///            GET "https://example.com"
-///         }
-///     }
-/// }
-/// ```
-///
-/// # Advanced Usage (manually created struct and [Api] implementation)
-/// ```rust
-/// use api_client::{api, Api};
-/// use reqwest::{Client, RequestBuilder};
-///
-/// struct ExampleApi {
-///     client: Client,
-///     username: String,
-///     password: String
-/// }
-///
-/// impl Api for ExampleApi {
-///     fn client(&self) -> &Client {
-///         &self.client
-///     }
-///
-///     fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
-///         Ok(request.basic_auth(&self.username, Some(&self.password)))
-///     }
-/// }
+/// Used internally in the api! macro to expand to the items in brackets unless the URL after
+/// them awaits, e.g. to resolve its base URL, as the items can't.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_unless_await {
+    ([$($item:tt)*] await $($rest:tt)*) => {};
+
+    ([$($item:tt)*] with { $($options:tt)* } $($rest:tt)*) => {
+        $crate::__api_unless_await!([$($item)*] $($rest)*);
+    };
+
+    ([$($item:tt)*] ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::__api_unless_await!([$($item)*] $($inner)* $($rest)*);
+    };
+
+    ([$($item:tt)*] [$($inner:tt)*] $($rest:tt)*) => {
+        $crate::__api_unless_await!([$($item)*] $($inner)* $($rest)*);
+    };
+
+    ([$($item:tt)*] { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::__api_unless_await!([$($item)*] $($inner)* $($rest)*);
+    };
+
+    ([$($item:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_unless_await!([$($item)*] $($rest)*);
+    };
+
+    ([$($item:tt)*]) => {
+        $($item)*
+    };
+}
+
+/// Used internally in the api! macro to build endpoint URLs, formatting their parameters in the
+/// locale of the given [`ApiState`].
 ///
-/// impl ExampleApi {
-///     api! {
-///         fn example() -> String {
-///            GET "https://example.com"
-///         }
-///     }
-/// }
-/// ```
+/// The URL ends at the first endpoint modifier, such as a `with` block, `TIMEOUT` or `POINTER`.
+#[doc(hidden)]
 #[macro_export]
-#[cfg(not(feature = "middleware"))]
-macro_rules! api {
-    () => {};
+macro_rules! __api_url {
+    (@split [$($mode:tt)*] [$($url:tt)*] TIMEOUT $($rest:tt)*) => {
+        $crate::__api_url!(@query [$($mode)*] [$($url)*] $($rest)*)
+    };
 
-    ($(#[$attr:meta])* $vis:vis struct $ident:ident) => {
-        $(#[$attr])*
-        $vis struct $ident(::reqwest::Client);
+    (@split [$($mode:tt)*] [$($url:tt)*] with { $($options:tt)* } $($rest:tt)*) => {
+        $crate::__api_url!(@query [$($mode)*] [$($url)*] with { $($options)* } $($rest)*)
+    };
 
-        impl $crate::Api for $ident {
-            fn client(&self) -> &::reqwest::Client {
-                &self.0
-            }
+    (@split [$($mode:tt)*] [$($url:tt)*] POINTER $($rest:tt)*) => {
+        $crate::__api_url!(@query [$($mode)*] [$($url)*] $($rest)*)
+    };
 
-            fn new() -> Self where Self: Sized {
-                $ident(::reqwest::Client::new())
-            }
-        }
+    (@split [$($mode:tt)*] [$($url:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_url!(@split [$($mode)*] [$($url)* $next] $($rest)*)
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> StatusCode { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name $ty),*) -> ::reqwest::Result<::reqwest::StatusCode> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Json(request)).await.map(|res| res.status())
-        }
-        api!($($rest)*);
+    (@split [$($mode:tt)*] [$($url:tt)*]) => {
+        $crate::__api_url!(@build $($mode)* $($url)*)
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> String { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<String> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Json(request)).await?.text().await
-        }
-        api!($($rest)*);
+    (@build @template $url:literal $(, $arg:expr)* $(,)?) => {
+        $url
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> Bytes { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<::bytes::Bytes> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Json(request)).await?.bytes().await
-        }
-        api!($($rest)*);
+    (@build @template ($url:expr)) => {
+        ::core::stringify!($url)
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> Json<$res:ty> { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<$res> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Json(request)).await?.json().await
-        }
-        api!($($rest)*);
+    (@build [$state:expr] $url:literal $(, $arg:expr)* $(,)?) => {
+        $crate::locale::render($state, ::core::format_args!($url $(, $arg)*))
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> StatusCode { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<::reqwest::StatusCode> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Form(request)).await.map(|res| res.status())
-        }
-        api!($($rest)*);
+    (@build [$state:expr] ($url:expr)) => {
+        $crate::locale::render($state, ::core::format_args!("{}", $url))
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> String { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<String> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Form(request)).await?.text().await
-        }
-        api!($($rest)*);
+    (@build @template [$prefix:literal] $($url:tt)+) => {
+        $crate::__api_url!(@build @template [($prefix)] $($url)+)
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> Bytes { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<::bytes::Bytes> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Form(request)).await?.bytes().await
-        }
-        api!($($rest)*);
+    (@build @template [($prefix:literal $(, $prefix_arg:expr)* $(,)?)] $url:literal $(, $arg:expr)* $(,)?) => {
+        ::core::concat!($prefix, $url)
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> Json<$res:ty> { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<$res> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Form(request)).await?.json().await
-        }
-        api!($($rest)*);
+    (@build @template [($prefix:literal $(, $prefix_arg:expr)* $(,)?)] ($url:expr)) => {
+        ::core::concat!($prefix, ::core::stringify!($url))
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> StatusCode { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, $($name: $ty),*) -> ::reqwest::Result<::reqwest::StatusCode> {
-            use $crate::Api as _;
-            self.request::<()>(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::None).await.map(|res| res.status())
-        }
-        api!($($rest)*);
+    (@build [$state:expr] [$prefix:literal] $($url:tt)+) => {
+        $crate::__api_url!(@build [$state] [($prefix)] $($url)+)
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> String { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, $($name: $ty),*) -> ::reqwest::Result<String> {
-            use $crate::Api as _;
-            self.request::<()>(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::None).await?.text().await
-        }
-        api!($($rest)*);
+    (@build [$state:expr] [($prefix:literal $(, $prefix_arg:expr)* $(,)?)] $url:literal $(, $arg:expr)* $(,)?) => {
+        $crate::locale::render($state, ::core::format_args!(
+            "{}{}",
+            ::core::format_args!($prefix $(, $prefix_arg)*),
+            ::core::format_args!($url $(, $arg)*),
+        ))
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> Bytes { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, $($name: $ty),*) -> ::reqwest::Result<::bytes::Bytes> {
-            use $crate::Api as _;
-            self.request::<()>(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::None).await?.bytes().await
-        }
-        api!($($rest)*);
+    (@build [$state:expr] [($prefix:literal $(, $prefix_arg:expr)* $(,)?)] ($url:expr)) => {
+        $crate::locale::render($state, ::core::format_args!(
+            "{}{}",
+            ::core::format_args!($prefix $(, $prefix_arg)*),
+            $url,
+        ))
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> Json<$res:ty> { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, $($name: $ty),*) -> ::reqwest::Result<$res> {
-            use $crate::Api as _;
-            self.request::<()>(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::None).await?.json().await
-        }
-        api!($($rest)*);
+    (@query [@template] [$($url:tt)*] $($rest:tt)*) => {
+        $crate::__api_url!(@build @template $($url)*)
     };
-}
 
-/// Magic macro for API structs.
-///
-/// # Simple Usage (auto generated struct)
-/// ```rust
-/// use api_client::{api, Api};
-/// use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
-///
-/// api!(pub struct ExampleApi);
-///
-/// impl ExampleApi {
-///     api! {
-///         fn example() -> String {
-///            GET "https://example.com"
-///         }
-///     }
-/// }
-/// ```
-///
-/// # Advanced Usage (manually created struct and [Api] implementation)
-/// ```rust
-/// use api_client::{api, Api};
-/// use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
-///
-/// struct ExampleApi {
-///     client: ClientWithMiddleware,
+    (@query [[$state:expr]] [$($url:tt)*] with { $($options:tt)* } $($rest:tt)*) => {
+        $crate::__api_url!(@query_option [$state] [$($url)*] [$($options)*] $($options)*)
+    };
+
+    (@query [[$state:expr]] [$($url:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_url!(@query [[$state]] [$($url)*] $($rest)*)
+    };
+
+    (@query [[$state:expr]] [$($url:tt)*]) => {
+        $crate::__api_url!(@build [$state] $($url)*)
+    };
+
+    (@query_option [$state:expr] [$($url:tt)*] [$($options:tt)*] query: [$($params:tt)*] $($rest:tt)*) => {{
+        let mut url = $crate::__api_url!(@build [$state] $($url)*);
+        let format = $crate::query::array_format($state, $crate::__api_url!(@arrays $($options)*));
+        $crate::__api_url!(@params url format $($params)*);
+        url
+    }};
+
+    (@query_option [$state:expr] [$($url:tt)*] [$($options:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_url!(@query_option [$state] [$($url)*] [$($options)*] $($rest)*)
+    };
+
+    (@query_option [$state:expr] [$($url:tt)*] [$($options:tt)*]) => {
+        $crate::__api_url!(@build [$state] $($url)*)
+    };
+
+    (@arrays arrays: $format:ident $($rest:tt)*) => {
+        ::core::option::Option::Some($crate::__api_url!(@array_format $format))
+    };
+
+    (@arrays $next:tt $($rest:tt)*) => {
+        $crate::__api_url!(@arrays $($rest)*)
+    };
+
+    (@arrays) => {
+        ::core::option::Option::None
+    };
+
+    (@array_format repeat) => {
+        $crate::query::ArrayFormat::Repeat
+    };
+
+    (@array_format comma) => {
+        $crate::query::ArrayFormat::Comma
+    };
+
+    (@array_format brackets) => {
+        $crate::query::ArrayFormat::Brackets
+    };
+
+    (@array_format pipes) => {
+        $crate::query::ArrayFormat::Pipes
+    };
+
+    // Unknown formats are reported by `__api_spec!`.
+    (@array_format $format:ident) => {
+        $crate::query::ArrayFormat::Repeat
+    };
+
+    (@params $url:ident $format:ident .. $param:ident $(, $($rest:tt)*)?) => {
+        $crate::query::extend(&mut $url, &$param, $format)?;
+        $crate::__api_url!(@params $url $format $($($rest)*)?);
+    };
+
+    (@params $url:ident $format:ident $param:ident $(, $($rest:tt)*)?) => {
+        $crate::query::append(&mut $url, ::core::stringify!($param), &$param, $format)?;
+        $crate::__api_url!(@params $url $format $($($rest)*)?);
+    };
+
+    (@params $url:ident $format:ident) => {};
+
+    (@template $($tokens:tt)+) => {
+        $crate::__api_url!(@split [@template] [] $($tokens)+)
+    };
+
+    ([$state:expr] $($tokens:tt)+) => {
+        $crate::__api_url!(@split [[$state]] [] $($tokens)+)
+    };
+}
+
+/// Used internally in the api! macro to describe an endpoint.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_spec {
+    (@timeout TIMEOUT $timeout:tt $($rest:tt)*) => {
+        ::core::option::Option::Some({
+            const TIMEOUT: ::core::time::Duration =
+                $crate::endpoint::parse_duration(::core::stringify!($timeout));
+            TIMEOUT
+        })
+    };
+
+    (@timeout $next:tt $($rest:tt)*) => {
+        $crate::__api_spec!(@timeout $($rest)*)
+    };
+
+    (@timeout) => {
+        ::core::option::Option::None
+    };
+
+    (@pointer POINTER $pointer:literal $($rest:tt)*) => {
+        ::core::option::Option::Some($pointer)
+    };
+
+    (@pointer $next:tt $($rest:tt)*) => {
+        $crate::__api_spec!(@pointer $($rest)*)
+    };
+
+    (@pointer) => {
+        ::core::option::Option::None
+    };
+
+    (@method $method:ident) => {
+        $crate::reqwest::Method::$method
+    };
+
+    (@method $method:literal) => {{
+        const METHOD: &str = $crate::endpoint::validate_method($method);
+        $crate::endpoint::extension_method(METHOD)
+    }};
+
+    (@check_method $ident:ident GET) => {};
+    (@check_method $ident:ident POST) => {};
+    (@check_method $ident:ident PUT) => {};
+    (@check_method $ident:ident DELETE) => {};
+    (@check_method $ident:ident HEAD) => {};
+    (@check_method $ident:ident OPTIONS) => {};
+    (@check_method $ident:ident CONNECT) => {};
+    (@check_method $ident:ident PATCH) => {};
+    (@check_method $ident:ident TRACE) => {};
+    (@check_method $ident:ident $method:literal) => {};
+
+    (@check_method $ident:ident $method:tt) => {
+        ::core::compile_error!(::core::concat!(
+            "unsupported method `", ::core::stringify!($method), "` of the endpoint `",
+            ::core::stringify!($ident), "`, expected one of `GET`, `POST`, `PUT`, `DELETE`, `HEAD`, ",
+            "`OPTIONS`, `CONNECT`, `PATCH` or `TRACE`, or a string such as `\"PROPFIND\"`"
+        ));
+    };
+
+    (@rpc $ident:ident with { $($options:tt)* } $($rest:tt)*) => {
+        $crate::__api_spec!(@rpc_option $ident $($options)*)
+    };
+
+    (@rpc $ident:ident $next:tt $($rest:tt)*) => {
+        $crate::__api_spec!(@rpc $ident $($rest)*)
+    };
+
+    (@rpc $ident:ident) => {
+        ::core::stringify!($ident)
+    };
+
+    (@rpc_option $ident:ident rpc: $name:literal $($rest:tt)*) => {
+        $name
+    };
+
+    (@rpc_option $ident:ident $next:tt $($rest:tt)*) => {
+        $crate::__api_spec!(@rpc_option $ident $($rest)*)
+    };
+
+    (@rpc_option $ident:ident) => {
+        ::core::stringify!($ident)
+    };
+
+    (@with $spec:ident with { $($options:tt)* } $($rest:tt)*) => {
+        $crate::__api_spec!(@option $spec $($options)*);
+    };
+
+    (@with $spec:ident $next:tt $($rest:tt)*) => {
+        $crate::__api_spec!(@with $spec $($rest)*);
+    };
+
+    (@with $spec:ident) => {};
+
+    (@option $spec:ident timeout: $timeout:tt $(, $($rest:tt)*)?) => {
+        $spec.timeout = $crate::__api_spec!(@timeout TIMEOUT $timeout);
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident deadline: $deadline:tt $(, $($rest:tt)*)?) => {
+        $spec.deadline = $crate::__api_spec!(@timeout TIMEOUT $deadline);
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident idempotent: $idempotent:literal $(, $($rest:tt)*)?) => {
+        $spec.idempotent = $idempotent;
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident idempotent $(, $($rest:tt)*)?) => {
+        $spec.idempotent = true;
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident cache: only_if_cached $(, $($rest:tt)*)?) => {
+        $spec.cache_mode = ::core::option::Option::Some($crate::cache::CacheMode::OnlyIfCached);
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident cache: stale_while_revalidate $(, $($rest:tt)*)?) => {
+        $spec.cache_mode =
+            ::core::option::Option::Some($crate::cache::CacheMode::StaleWhileRevalidate);
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident cache: force_refresh $(, $($rest:tt)*)?) => {
+        $spec.cache_mode = ::core::option::Option::Some($crate::cache::CacheMode::ForceRefresh);
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident cache: $lifetime:literal $(, $($rest:tt)*)?) => {
+        $spec.cache_lifetime = $crate::__api_spec!(@timeout TIMEOUT $lifetime);
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident cache: $($rest:tt)*) => {
+        ::core::compile_error!(
+            "unknown cache option, expected a lifetime such as `5m`, or one of `only_if_cached`, `stale_while_revalidate` or `force_refresh`"
+        );
+    };
+
+    (@option $spec:ident retry: default $(, $($rest:tt)*)?) => {
+        $spec.retry = ::core::option::Option::Some($crate::retry::RetryPolicy::DEFAULT);
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident retry: none $(, $($rest:tt)*)?) => {
+        $spec.retry = ::core::option::Option::None;
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident retry: $attempts:literal $(, $($rest:tt)*)?) => {
+        $spec.retry = ::core::option::Option::Some($crate::retry::RetryPolicy::new($attempts));
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident retry: $policy:path $(, $($rest:tt)*)?) => {
+        $spec.retry = ::core::option::Option::Some($policy);
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident retry: $($rest:tt)*) => {
+        ::core::compile_error!(
+            "unknown retry option, expected `default`, `none`, a number of attempts or a `RetryPolicy` constant"
+        );
+    };
+
+    (@option $spec:ident scopes: [$($scope:literal),* $(,)?] $(, $($rest:tt)*)?) => {
+        $spec.scopes = &[$($scope),*];
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident requires: [$($endpoint:ident),* $(,)?] $(, $($rest:tt)*)?) => {
+        $(let _ = Self::$endpoint;)*
+        $spec.requires = &[$(::core::stringify!($endpoint)),*];
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident grpc_status $(, $($rest:tt)*)?) => {
+        $spec.grpc_status = true;
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident raw $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident login: token($pointer:literal) $(, $($rest:tt)*)?) => {
+        $spec.login = ::core::option::Option::Some($crate::endpoint::Login::Token($pointer));
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident login: header($name:literal) $(, $($rest:tt)*)?) => {
+        $spec.login = ::core::option::Option::Some($crate::endpoint::Login::Header($name));
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident login: cookie($name:literal) $(, $($rest:tt)*)?) => {
+        $spec.login = ::core::option::Option::Some($crate::endpoint::Login::Cookie($name));
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident login: $($rest:tt)*) => {
+        ::core::compile_error!(
+            "unknown login source, expected one of `token(\"/pointer\")`, `header(\"...\")` or `cookie(\"...\")`"
+        );
+    };
+
+    (@option $spec:ident query: [$($params:tt)*] $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident arrays: repeat $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident arrays: comma $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident arrays: brackets $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident arrays: pipes $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident arrays: $($rest:tt)*) => {
+        ::core::compile_error!(
+            "unknown array format, expected one of `repeat`, `comma`, `brackets` or `pipes`"
+        );
+    };
+
+    (@option $spec:ident rpc: $name:literal $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident fallback: default $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident fallback: last_good $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident fallback: value($value:expr) $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident fallback: endpoint($call:expr) $(, $($rest:tt)*)?) => {
+        $crate::__api_spec!(@option $spec $($($rest)*)?);
+    };
+
+    (@option $spec:ident fallback: $($rest:tt)*) => {
+        ::core::compile_error!(
+            "unknown fallback, expected one of `default`, `last_good`, `value(...)` or `endpoint(...)`"
+        );
+    };
+
+    (@option $spec:ident) => {};
+
+    (@option $spec:ident $option:ident $($rest:tt)*) => {
+        ::core::compile_error!(::core::concat!(
+            "unknown endpoint option `", ::core::stringify!($option),
+            "`, expected one of `timeout`, `deadline`, `retry`, `idempotent`, `cache`, `fallback`, `scopes`, `requires`, ",
+            "`grpc_status`, `raw`, `login`, `rpc`, `query` or `arrays`"
+        ));
+    };
+
+    ($ident:ident $method:tt $($url:tt)+) => {{
+        let method = $crate::__api_spec!(@method $method);
+        #[allow(unused_mut)]
+        let mut spec = $crate::endpoint::EndpointSpec {
+            name: ::core::stringify!($ident),
+            idempotent: method.is_idempotent(),
+            method,
+            url: $crate::__api_url!(@template $($url)+),
+            timeout: $crate::__api_spec!(@timeout $($url)+),
+            deadline: None,
+            retry: None,
+            pointer: $crate::__api_spec!(@pointer $($url)+),
+            scopes: &[],
+            requires: &[],
+            grpc_status: false,
+            login: None,
+            cache_mode: None,
+            cache_lifetime: None,
+        };
+        $crate::__api_spec!(@with spec $($url)+);
+        spec
+    }};
+}
+
+/// Used internally in the api! macro to apply the fallback declared for an endpoint, if any.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_fallback {
+    (@with [$($context:tt)*] with { $($options:tt)* } $($rest:tt)*) => {
+        $crate::__api_fallback!(@option [$($context)*] $($options)*)
+    };
+
+    (@with [$($context:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_fallback!(@with [$($context)*] $($rest)*)
+    };
+
+    (@with [$this:ident, $spec:ident, $url:ident, $result:ident]) => {
+        $result
+    };
+
+    (@option [$this:ident, $spec:ident, $url:ident, $result:ident] fallback: default $($rest:tt)*) => {
+        match $result {
+            ::core::result::Result::Err(error) => {
+                $crate::endpoint::fall_back(&$spec, &error);
+                ::core::result::Result::Ok(::core::default::Default::default())
+            }
+            result => result,
+        }
+    };
+
+    (@option [$this:ident, $spec:ident, $url:ident, $result:ident] fallback: last_good $($rest:tt)*) => {
+        $crate::endpoint::last_good($crate::Api::state($this), &$spec, &$url, $result)
+    };
+
+    (@option [$this:ident, $spec:ident, $url:ident, $result:ident] fallback: value($value:expr) $($rest:tt)*) => {
+        match $result {
+            ::core::result::Result::Err(error) => {
+                $crate::endpoint::fall_back(&$spec, &error);
+                ::core::result::Result::Ok($value)
+            }
+            result => result,
+        }
+    };
+
+    (@option [$this:ident, $spec:ident, $url:ident, $result:ident] fallback: endpoint($call:expr) $($rest:tt)*) => {
+        match $result {
+            ::core::result::Result::Err(error) => {
+                $crate::endpoint::fall_back(&$spec, &error);
+                $call.await
+            }
+            result => result,
+        }
+    };
+
+    (@option [$($context:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_fallback!(@option [$($context)*] $($rest)*)
+    };
+
+    (@option [$this:ident, $spec:ident, $url:ident, $result:ident]) => {
+        $result
+    };
+}
+
+/// Used internally in the api! macro to generate the `_raw` sibling of endpoints declared
+/// `with { raw }`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_raw {
+    (@with [$($context:tt)*] with { $($options:tt)* } $($rest:tt)*) => {
+        $crate::__api_raw!(@option [$($context)*] $($options)*);
+    };
+
+    (@with [$($context:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_raw!(@with [$($context)*] $($rest)*);
+    };
+
+    (@with [$($context:tt)*]) => {};
+
+    (@option [$($context:tt)*] raw $(, $($rest:tt)*)?) => {
+        $crate::__api_raw!(@method $($context)*);
+    };
+
+    (@option [$($context:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_raw!(@option [$($context)*] $($rest)*);
+    };
+
+    (@option [$($context:tt)*]) => {};
+
+    (@method [$this:ident] [$($attr:tt)*] [$vis:vis] $ident:ident [$($generics:tt)*] [$($where:tt)*] [$($params:tt)*] [$req:ty] [$body:expr] [$method:tt $($url:tt)+]) => {
+        $crate::__private::paste! {
+            $($attr)*
+            ///
+            #[doc = ::core::concat!("Same as [`", ::core::stringify!($ident), "`](Self::", ::core::stringify!($ident), "), returning the response without decoding it, e.g. to stream its body or read its headers.")]
+            #[allow(dead_code, clippy::extra_unused_type_parameters)]
+            $vis async fn [<$ident _raw>]<$($generics)*>(&$this, $($params)*) -> $crate::ResultType<$crate::reqwest::Response> where $($where)* {
+                let spec = $crate::__api_spec!($ident $method $($url)+);
+                let url = $crate::__api_url!([$crate::Api::state($this)] $($url)+);
+                $crate::endpoint::execute::<_, $crate::response::Raw, $req>(
+                    $this,
+                    &spec,
+                    &url,
+                    $body,
+                    ::core::option::Option::None,
+                )
+                .await
+            }
+        }
+    };
+}
+
+/// Used internally in the api! macro to list the endpoints of a block as `$list()`.
+///
+/// Endpoints are found by their `fn name(...)` signature followed by a `{ METHOD url }` body.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_endpoints {
+    (@collect [$list:ident $smoke:ident] [$($endpoints:tt)*] fn $ident:ident [$($generics:tt)*] ($($params:tt)*) $($rest:tt)*) => {
+        $crate::__api_endpoints!(@body [$list $smoke] [$($endpoints)*] $ident [$($params)*] $($rest)*);
+    };
+
+    (@collect [$list:ident $smoke:ident] [$($endpoints:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_endpoints!(@collect [$list $smoke] [$($endpoints)*] $($rest)*);
+    };
+
+    (@collect [$list:ident $smoke:ident] [$($endpoints:tt)*]) => {
+        /// Returns the endpoints declared in this block, see
+        /// [`EndpointMeta`]($crate::endpoint::EndpointMeta).
+        #[allow(dead_code)]
+        pub fn $list() -> &'static [$crate::endpoint::EndpointMeta] {
+            const ENDPOINTS: &[$crate::endpoint::EndpointMeta] = &[$($endpoints)*];
+            ENDPOINTS
+        }
+
+        /// Calls every `GET` endpoint declared in this block with the sample values of `params`
+        /// and reports their outcomes, see [`smoke`]($crate::smoke).
+        #[allow(dead_code)]
+        pub async fn $smoke(
+            &self,
+            params: $crate::smoke::SmokeParams,
+        ) -> $crate::smoke::SmokeReport {
+            $crate::smoke::run(self, Self::$list(), &params).await
+        }
+    };
+
+    (@body [$list:ident $smoke:ident] [$($endpoints:tt)*] $ident:ident [$($params:tt)*] { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        $crate::__api_endpoints!(@collect [$list $smoke] [$($endpoints)* $crate::endpoint::EndpointMeta {
+            name: ::core::stringify!($ident),
+            method: $crate::__api_endpoints!(@method $method),
+            url: $crate::__api_url!(@template $($url)+),
+            params: &$crate::__api_endpoints!(@params [] $($params)*),
+            scopes: $crate::__api_endpoints!(@list scopes $($url)+),
+            requires: $crate::__api_endpoints!(@list requires $($url)+),
+        },] $($rest)*);
+    };
+
+    (@body [$list:ident $smoke:ident] [$($endpoints:tt)*] $ident:ident [$($params:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_endpoints!(@body [$list $smoke] [$($endpoints)*] $ident [$($params)*] $($rest)*);
+    };
+
+    (@method $method:ident) => {
+        ::core::stringify!($method)
+    };
+
+    (@method $method:literal) => {
+        $method
+    };
+
+    (@params [$($names:tt)*] &$this:ident $(, $($rest:tt)*)?) => {
+        $crate::__api_endpoints!(@params [$($names)*] $($($rest)*)?)
+    };
+
+    (@params [$($names:tt)*] $name:ident: $ty:ty $(as $media:literal)? $(, $($rest:tt)*)?) => {
+        $crate::__api_endpoints!(@params [$($names)* ::core::stringify!($name),] $($($rest)*)?)
+    };
+
+    (@params [$($names:tt)*]) => {
+        [$($names)*]
+    };
+
+    (@list $key:ident with { $($options:tt)* } $($rest:tt)*) => {
+        $crate::__api_endpoints!(@list_option $key $($options)*)
+    };
+
+    (@list $key:ident $next:tt $($rest:tt)*) => {
+        $crate::__api_endpoints!(@list $key $($rest)*)
+    };
+
+    (@list $key:ident) => {
+        &[]
+    };
+
+    (@list_option scopes scopes: [$($scope:literal),* $(,)?] $($rest:tt)*) => {
+        &[$($scope),*]
+    };
+
+    (@list_option requires requires: [$($endpoint:ident),* $(,)?] $($rest:tt)*) => {
+        &[$(::core::stringify!($endpoint)),*]
+    };
+
+    (@list_option $key:ident $next:tt $($rest:tt)*) => {
+        $crate::__api_endpoints!(@list_option $key $($rest)*)
+    };
+
+    (@list_option $key:ident) => {
+        &[]
+    };
+}
+
+/// Used internally in the api! macro to describe the endpoints of a block as `OpenAPI`
+/// operations, see [`openapi::export`].
+///
+/// Endpoints are found like in `__api_endpoints!`, with the doc comments before them.
+#[cfg(feature = "openapi")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_openapi {
+    (@collect [$name:ident] [$($operations:tt)*] [$($docs:tt)*] #[doc = $doc:literal] $($rest:tt)*) => {
+        $crate::__api_openapi!(@collect [$name] [$($operations)*] [$($docs)* $doc] $($rest)*);
+    };
+
+    (@collect [$name:ident] [$($operations:tt)*] [$($docs:tt)*] fn $ident:ident [$($generics:tt)*] ($($params:tt)*) $($rest:tt)*) => {
+        $crate::__api_openapi!(@fn [$name] [$($operations)*] [$($docs)*] $ident [$($generics)*] [$($params)*] $($rest)*);
+    };
+
+    (@collect [$name:ident] [$($operations:tt)*] [$($docs:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__api_openapi!(@collect [$name] [$($operations)*] [$($docs)*] $($rest)*);
+    };
+
+    (@collect [$name:ident] [$($operations:tt)*] [$($docs:tt)*]) => {
+        /// Returns the endpoints declared in this block as `OpenAPI` operations, see
+        /// [`export`]($crate::openapi::export).
+        #[allow(dead_code)]
+        pub fn $name() -> ::std::vec::Vec<$crate::openapi::export::Operation> {
+            ::std::vec![$($operations)*]
+        }
+    };
+
+    (@fn [$name:ident] [$($operations:tt)*] [$($docs:tt)*] $ident:ident [$($generics:tt)*] [$($params:tt)*] -> $kind:ident<$res:ty>($pointer:literal) [$($where:tt)*] { $($endpoint:tt)+ } $($rest:tt)*) => {
+        $crate::__api_openapi!(@operation [$name] [$($operations)*] [$($docs)*] $ident [$($generics)*] [$($params)*] [$kind<$res>] { $($endpoint)+ } $($rest)*);
+    };
+
+    (@fn [$name:ident] [$($operations:tt)*] [$($docs:tt)*] $ident:ident [$($generics:tt)*] [$($params:tt)*] -> $kind:ident<$inner:ident<$res:ty>> [$($where:tt)*] { $($endpoint:tt)+ } $($rest:tt)*) => {
+        $crate::__api_openapi!(@operation [$name] [$($operations)*] [$($docs)*] $ident [$($generics)*] [$($params)*] [$kind<$inner<$res> >] { $($endpoint)+ } $($rest)*);
+    };
+
+    (@fn [$name:ident] [$($operations:tt)*] [$($docs:tt)*] $ident:ident [$($generics:tt)*] [$($params:tt)*] -> $kind:ident<$inner:ident$(<$res:ty>)?> [$($where:tt)*] { $($endpoint:tt)+ } $($rest:tt)*) => {
+        $crate::__api_openapi!(@operation [$name] [$($operations)*] [$($docs)*] $ident [$($generics)*] [$($params)*] [$kind<$inner$(<$res>)?>] { $($endpoint)+ } $($rest)*);
+    };
+
+    (@fn [$name:ident] [$($operations:tt)*] [$($docs:tt)*] $ident:ident [$($generics:tt)*] [$($params:tt)*] -> $kind:ident$(<$res:ty>)? [$($where:tt)*] { $($endpoint:tt)+ } $($rest:tt)*) => {
+        $crate::__api_openapi!(@operation [$name] [$($operations)*] [$($docs)*] $ident [$($generics)*] [$($params)*] [$kind$(<$res>)?] { $($endpoint)+ } $($rest)*);
+    };
+
+    (@operation [$name:ident] [$($operations:tt)*] [$($docs:tt)*] $ident:ident [$($generics:tt)*] [$($params:tt)*] [$($kind:tt)+] { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        $crate::__api_openapi!(@collect [$name] [$($operations)*
+            $crate::__api_openapi!(@params [$($generics)*] [
+                $crate::openapi::export::Operation::new(
+                    ::core::stringify!($ident),
+                    $crate::__api_endpoints!(@method $method),
+                    $crate::__api_url!(@template $($url)+),
+                )
+                $(.doc($docs))*
+            ] $($params)*)
+            .request($crate::__api_openapi!(@request [$($generics)*] $($params)*))
+            .response($crate::__api_openapi!(@response [$($generics)*] $($kind)+)),
+        ] [] $($rest)*);
+    };
+
+    (@params [$($generics:tt)*] [$($operation:tt)*] &$this:ident $(, $($rest:tt)*)?) => {
+        $crate::__api_openapi!(@params [$($generics)*] [$($operation)*] $($($rest)*)?)
+    };
+
+    (@params [$($generics:tt)*] [$($operation:tt)*] request: $body:ident$(<$req:ty>)? $(as $media:literal)? $(, $($rest:tt)*)?) => {
+        $crate::__api_openapi!(@params [$($generics)*] [$($operation)*] $($($rest)*)?)
+    };
+
+    (@params [$($generics:tt)*] [$($operation:tt)*] params: RpcParams<$req:ty> $(, $($rest:tt)*)?) => {
+        $crate::__api_openapi!(@params [$($generics)*] [$($operation)*] $($($rest)*)?)
+    };
+
+    (@params [$($generics:tt)*] [$($operation:tt)*] $name:ident: $ty:ty $(, $($rest:tt)*)?) => {
+        $crate::__api_openapi!(@params [$($generics)*] [$($operation)* .param(::core::stringify!($name), $crate::__api_openapi!(@schema [$($generics)*] $ty))] $($($rest)*)?)
+    };
+
+    (@params [$($generics:tt)*] [$($operation:tt)*]) => {
+        $($operation)*
+    };
+
+    (@request [$($generics:tt)*] &$this:ident $(, $($rest:tt)*)?) => {
+        $crate::__api_openapi!(@request [$($generics)*] $($($rest)*)?)
+    };
+
+    (@request [$($generics:tt)*] request: Json<$req:ty> as $media:literal $(, $($rest:tt)*)?) => {
+        $crate::openapi::export::Content::new($media, $crate::__api_openapi!(@schema [$($generics)*] $req))
+    };
+
+    (@request [$($generics:tt)*] request: Json<$req:ty> $(, $($rest:tt)*)?) => {
+        $crate::openapi::export::Content::new("application/json", $crate::__api_openapi!(@schema [$($generics)*] $req))
+    };
+
+    (@request [$($generics:tt)*] request: MergePatch<$req:ty> $(, $($rest:tt)*)?) => {
+        $crate::openapi::export::Content::new($crate::patch::MERGE_PATCH_CONTENT_TYPE, $crate::__api_openapi!(@schema [$($generics)*] $req))
+    };
+
+    (@request [$($generics:tt)*] request: JsonPatch$(<$req:ty>)? $(, $($rest:tt)*)?) => {
+        $crate::openapi::export::Content::opaque($crate::patch::JSON_PATCH_CONTENT_TYPE)
+    };
+
+    (@request [$($generics:tt)*] request: Form<$req:ty> $(, $($rest:tt)*)?) => {
+        $crate::openapi::export::Content::new("application/x-www-form-urlencoded", $crate::__api_openapi!(@schema [$($generics)*] $req))
+    };
+
+    (@request [$($generics:tt)*] request: Multipart<$req:ty> $(, $($rest:tt)*)?) => {
+        $crate::openapi::export::Content::opaque("multipart/form-data")
+    };
+
+    (@request [$($generics:tt)*] request: GraphQl<$req:ty> $(, $($rest:tt)*)?) => {
+        $crate::openapi::export::Content::opaque("application/json")
+    };
+
+    (@request [$($generics:tt)*] request: GrpcWeb<$req:ty> $(, $($rest:tt)*)?) => {
+        $crate::openapi::export::Content::opaque("application/grpc-web+proto")
+    };
+
+    (@request [$($generics:tt)*] request: Stream $(, $($rest:tt)*)?) => {
+        $crate::openapi::export::Content::opaque("application/octet-stream")
+    };
+
+    (@request [$($generics:tt)*] params: RpcParams<$req:ty> $(, $($rest:tt)*)?) => {
+        $crate::openapi::export::Content::opaque("application/json")
+    };
+
+    (@request [$($generics:tt)*] $($params:tt)*) => {
+        ::core::option::Option::None
+    };
+
+    (@response [$($generics:tt)*] Json<$res:ty>) => {
+        $crate::openapi::export::Content::new("application/json", $crate::__api_openapi!(@schema [$($generics)*] $res))
+    };
+
+    (@response [$($generics:tt)*] Redirect<$res:ty>) => {
+        $crate::__api_openapi!(@response [$($generics)*] Json<$res>)
+    };
+
+    (@response [$($generics:tt)*] GraphQl<$res:ty>) => {
+        $crate::openapi::export::Content::opaque("application/json")
+    };
+
+    (@response [$($generics:tt)*] Rpc<$res:ty>) => {
+        $crate::openapi::export::Content::opaque("application/json")
+    };
+
+    (@response [$($generics:tt)*] JsonArrayStream<$res:ty>) => {
+        $crate::openapi::export::Content::opaque("application/json")
+    };
+
+    (@response [$($generics:tt)*] GrpcWeb<$res:ty>) => {
+        $crate::openapi::export::Content::opaque("application/grpc-web+proto")
+    };
+
+    (@response [$($generics:tt)*] String) => {
+        $crate::openapi::export::Content::new("text/plain", $crate::__api_openapi!(@schema [] ::std::string::String))
+    };
+
+    (@response [$($generics:tt)*] Bytes) => {
+        $crate::openapi::export::Content::opaque("application/octet-stream")
+    };
+
+    (@response [$($generics:tt)*] Download) => {
+        $crate::openapi::export::Content::opaque("application/octet-stream")
+    };
+
+    (@response [$($generics:tt)*] Created<$kind:ident$(<$res:ty>)?>) => {
+        $crate::__api_openapi!(@response [$($generics)*] $kind$(<$res>)?)
+    };
+
+    (@response [$($generics:tt)*] WithHeaders<$kind:ident$(<$res:ty>)?>) => {
+        $crate::__api_openapi!(@response [$($generics)*] $kind$(<$res>)?)
+    };
+
+    (@response [$($generics:tt)*] $($kind:tt)+) => {
+        ::core::option::Option::None
+    };
+
+    (@schema [$($generics:tt)+] $ty:ty) => {
+        |_: &mut $crate::openapi::export::SchemaGenerator| ::core::option::Option::None
+    };
+
+    (@schema [] $ty:ty) => {
+        |generator: &mut $crate::openapi::export::SchemaGenerator| {
+            #[allow(unused_imports)]
+            use $crate::openapi::export::{WithSchema as _, WithoutSchema as _};
+            (&$crate::openapi::export::Probe::<$ty>::new()).schema(generator)
+        }
+    };
+}
+
+/// Used internally in the api! macro, which only describes endpoints as `OpenAPI` operations with
+/// the `openapi` feature.
+#[cfg(not(feature = "openapi"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_openapi {
+    ($($tokens:tt)*) => {};
+}
+
+/// Used internally in the api! macro to implement the [`Api`] hooks of sub-clients by calling
+/// those of the client in their `$field`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_delegate {
+    ($field:ident) => {
+        fn client(&self) -> &$crate::ClientType {
+            $crate::Api::client(self.$field)
+        }
+
+        fn state(&self) -> &$crate::ApiState {
+            $crate::Api::state(self.$field)
+        }
+
+        fn apply_defaults(&self, request: $crate::RequestBuilder) -> $crate::RequestBuilder {
+            $crate::Api::apply_defaults(self.$field, request)
+        }
+
+        fn pre_request(
+            &self,
+            request: $crate::RequestBuilder,
+        ) -> $crate::ResultType<$crate::RequestBuilder> {
+            $crate::Api::pre_request(self.$field, request)
+        }
+
+        fn post_response(&self, response: $crate::reqwest::Response) -> $crate::reqwest::Response {
+            $crate::Api::post_response(self.$field, response)
+        }
+
+        fn on_login(&self, token: ::std::string::String) {
+            $crate::Api::on_login(self.$field, token);
+        }
+
+        fn on_deprecation(&self, endpoint: &str, deprecation: &$crate::deprecation::Deprecation) {
+            $crate::Api::on_deprecation(self.$field, endpoint, deprecation);
+        }
+
+        fn granted_scopes(&self) -> ::core::option::Option<$crate::permissions::Scopes> {
+            $crate::Api::granted_scopes(self.$field)
+        }
+
+        $crate::__api_delegate_graphql!($field);
+    };
+}
+
+/// Used internally in the api! macro to delegate [`Api::graphql_init_payload`] in sub-clients.
+#[cfg(feature = "graphql-ws")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_delegate_graphql {
+    ($field:ident) => {
+        fn graphql_init_payload(
+            &self,
+            request: &$crate::reqwest::Request,
+        ) -> ::core::option::Option<$crate::__private::serde_json::Value> {
+            $crate::Api::graphql_init_payload(self.$field, request)
+        }
+    };
+}
+
+/// Used internally in the api! macro, which only delegates [`Api`] hooks of sub-clients that
+/// exist with the enabled features.
+#[cfg(not(feature = "graphql-ws"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_delegate_graphql {
+    ($field:ident) => {};
+}
+
+/// Used internally in the api! macro to map return kinds to their [`ResponseKind`](response::ResponseKind).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __api_response {
+    (@check $ident:ident Created<$kind:ident$(<$res:ty>)?>) => {
+        $crate::__api_response!(@check $ident $kind$(<$res>)?);
+    };
+
+    (@check $ident:ident WithHeaders<$kind:ident$(<$res:ty>)?>) => {
+        $crate::__api_response!(@check $ident $kind$(<$res>)?);
+    };
+
+    (@check $ident:ident StatusCode) => {};
+    (@check $ident:ident String) => {};
+    (@check $ident:ident Bytes) => {};
+    (@check $ident:ident Response) => {};
+    (@check $ident:ident Download) => {};
+    (@check $ident:ident Json<$res:ty>) => {};
+    (@check $ident:ident JsonArrayStream<$res:ty>) => {};
+    (@check $ident:ident FrameStream<$codec:ty>) => {};
+    (@check $ident:ident GraphQl<$res:ty>) => {};
+    (@check $ident:ident GraphQlSubscription<$res:ty>) => {};
+    (@check $ident:ident GrpcWeb<$res:ty>) => {};
+    (@check $ident:ident Rpc<$res:ty>) => {};
+    (@check $ident:ident Redirect<$res:ty>) => {};
+
+    (@check $ident:ident $kind:tt $($rest:tt)*) => {
+        ::core::compile_error!(::core::concat!(
+            "unsupported return kind `", ::core::stringify!($kind), "` of the endpoint `",
+            ::core::stringify!($ident), "`, expected one of `StatusCode`, `String`, `Bytes`, ",
+            "`Response`, `Download`, `Json<T>`, `JsonArrayStream<T>`, `FrameStream<C>`, `GraphQl<T>`, ",
+            "`GraphQlSubscription<T>`, `GrpcWeb<T>`, `Rpc<T>`, `Redirect<T>`, `Created<K>` or ",
+            "`WithHeaders<K>`"
+        ));
+    };
+
+    (StatusCode) => {
+        $crate::response::Status
+    };
+
+    (String) => {
+        $crate::response::Text
+    };
+
+    (Bytes) => {
+        $crate::response::Bytes
+    };
+
+    (Response) => {
+        $crate::response::Raw
+    };
+
+    (Download) => {
+        $crate::response::Download
+    };
+
+    (Json<$res:ty>) => {
+        $crate::response::Json<$res>
+    };
+
+    (JsonArrayStream<$res:ty>) => {
+        $crate::response::JsonArrayStream<$res>
+    };
+
+    (FrameStream<$codec:ty>) => {
+        $crate::response::FrameStream<$codec>
+    };
+
+    (GraphQl<$res:ty>) => {
+        $crate::graphql::GraphQl<$res>
+    };
+
+    (GrpcWeb<$res:ty>) => {
+        $crate::grpc::web::GrpcWeb<$res>
+    };
+
+    (Rpc<$res:ty>) => {
+        $crate::jsonrpc::Rpc<$res>
+    };
+
+    (Redirect<$res:ty>) => {
+        $crate::response::Redirect<$res>
+    };
+
+    (Created<$kind:ident$(<$res:ty>)?>) => {
+        $crate::response::Created<$crate::__api_response!($kind$(<$res>)?)>
+    };
+
+    (WithHeaders<$kind:ident$(<$res:ty>)?>) => {
+        $crate::response::WithHeaders<$crate::__api_response!($kind$(<$res>)?)>
+    };
+
+    // Unsupported kinds are reported by `@check`, this only keeps the expansion going.
+    ($($kind:tt)*) => {
+        $crate::response::Raw
+    };
+}
+
+/// Magic macro for API structs.
+///
+/// # Simple Usage (auto generated struct)
+/// ```rust
+/// use api_client::{api, Api};
+/// use reqwest::{Client, RequestBuilder};
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn example() -> String {
+///            GET "https://example.com"
+///         }
+///     }
+/// }
+/// ```
+///
+/// The generated struct can also be created from a preconfigured client:
+/// ```rust
+/// use std::time::Duration;
+///
+/// use api_client::api;
+///
+/// api!(pub struct ExampleApi);
+///
+/// let with_client = ExampleApi::with_client(reqwest::Client::new());
+/// let from_builder = ExampleApi::from_builder(
+///     reqwest::Client::builder().timeout(Duration::from_secs(10)),
+/// )
+/// .unwrap();
+/// ```
+///
+/// # Authentication
+/// The generated struct can send a token with every request, as a bearer token with
+/// `auth = Bearer` or as the value of a header with `auth = ApiKey(header "...")`. It gets a
+/// `set_token` method to set or replace it, see [`auth`](auth#declared-credentials):
+/// ```rust
+/// use api_client::{api, Api};
+///
+/// api!(pub struct ExampleApi, auth = ApiKey(header "X-Api-Key"));
+///
+/// let api = ExampleApi::new();
+/// api.set_token("key");
+/// ```
+///
+/// # Custom Fields
+/// The generated struct can hold additional fields, which are set through a generated builder.
+/// Fields with a default value may be left unset. Hooks of the [Api] trait can be implemented in
+/// an `impl Api` block following the struct:
+/// ```rust
+/// use api_client::{api, Api, RequestBuilder, ResultType};
+///
+/// api! {
+///     pub struct GitHub {
+///         token: String,
+///         base_url: String = "https://api.github.com".to_string(),
+///     }
+///
+///     impl Api {
+///         fn pre_request(&self, request: RequestBuilder) -> ResultType<RequestBuilder> {
+///             Ok(request.bearer_auth(&self.token))
+///         }
+///     }
+/// }
+///
+/// impl GitHub {
+///     api! {
+///         pub fn user(&self, name: &str) -> String {
+///            GET "{}/users/{name}", self.base_url
+///         }
+///     }
+/// }
+///
+/// let github = GitHub::builder().token("secret".to_string()).build().unwrap();
+/// ```
+///
+/// # Defaults
+/// Headers and query parameters which every request carries can be declared after the fields in
+/// `headers { ... }` and `query { ... }` blocks, instead of adding them in [`Api::pre_request`].
+/// Their values are expressions, which reach the fields through `self`:
+/// ```rust
+/// use api_client::{api, Api};
+///
+/// api! {
+///     pub struct Weather {
+///         key: String,
+///     }
+///     headers { "X-Api-Version": "2024-06" }
+///     query { "api_key": self.key }
+/// }
+///
+/// impl Weather {
+///     api! {
+///         pub fn forecast(city: &str) -> String {
+///            GET "https://example.com/forecast/{city}"
+///         }
+///     }
+/// }
+///
+/// let weather = Weather::builder().key("secret".to_string()).build().unwrap();
+/// ```
+///
+/// # Sub-clients
+/// Endpoints declared in `mod` blocks after the fields are grouped into sub-clients, returned by
+/// a method named after the block. Sub-clients borrow the struct, so they share its client,
+/// state and hooks, including the auth it adds, and reach its fields through `&self`:
+/// ```rust
+/// use api_client::{api, Api};
+///
+/// api! {
+///     pub struct Shop {
+///         base_url: String = "https://example.com".to_string(),
+///         mod orders {
+///             pub fn get(&self, id: u32) -> String {
+///                GET "{}/orders/{id}", self.base_url
+///             }
+///         }
+///         mod customers {
+///             pub fn me(&self) -> String {
+///                GET "{}/customers/me", self.base_url
+///             }
+///         }
+///     }
+/// }
+///
+/// async fn order(shop: &Shop) -> api_client::ResultType<String> {
+///     shop.orders().get(1).await
+/// }
+/// ```
+///
+/// The sub-client of `orders` is a `ShopOrders<'_>`, which lists its endpoints with its own
+/// `endpoints()` function.
+///
+/// # Endpoint URLs
+/// The URL is a format string which can use the endpoint's parameters and anything else in scope.
+/// Declaring `&self` as the first parameter gives access to the struct, either through
+/// additional format arguments or by computing the whole URL from an expression in parentheses:
+/// ```rust
+/// use api_client::{api, Api};
+/// use reqwest::Client;
+///
+/// struct ExampleApi {
+///     client: Client,
+///     base_url: String,
+/// }
+///
+/// impl Api for ExampleApi {
+///     fn client(&self) -> &Client {
+///         &self.client
+///     }
+/// }
+///
+/// impl ExampleApi {
+///     api! {
+///         fn todo(&self, id: u32) -> String {
+///            GET "{}/todos/{id}", self.base_url
+///         }
+///
+///         fn todos(&self) -> String {
+///            GET (self.base_url.clone() + "/todos")
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Path prefixes
+/// Endpoints sharing the start of their URL can be declared in a `prefix` block, so bumping the
+/// version of an API is a one-line change. The prefix is a format string too, given alone or in
+/// parentheses with its arguments, and is prepended to the URL of every endpoint in the block:
+/// ```rust
+/// use api_client::{api, Api};
+/// # use reqwest::Client;
+/// # struct ExampleApi {
+/// #     client: Client,
+/// #     base_url: String,
+/// # }
+/// # impl Api for ExampleApi {
+/// #     fn client(&self) -> &Client {
+/// #         &self.client
+/// #     }
+/// # }
+///
+/// impl ExampleApi {
+///     api! {
+///         prefix ("{}/v2/todos", self.base_url) {
+///             fn todos(&self) -> String {
+///                GET ""
+///             }
+///
+///             fn todo(&self, id: u32) -> String {
+///                GET "/{id}"
+///             }
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Generic endpoints
+/// Endpoints can declare generic parameters, lifetimes and a `where` clause like other functions,
+/// which are passed on to the generated methods, e.g. to send or decode any serializable type:
+/// ```rust
+/// use api_client::{api, Api};
+/// use serde::{de::DeserializeOwned, Serialize};
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn upload<T: Serialize>(request: Json<T>) -> StatusCode {
+///            POST "https://example.com/uploads"
+///         }
+///
+///         fn setting<'a, T>(name: &'a str) -> Json<T>
+///         where
+///             T: DeserializeOwned,
+///         {
+///            GET "https://example.com/settings/{name}"
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Media types
+/// `Json<T>` bodies are sent as `application/json`. APIs expecting another JSON media type, such
+/// as JSON:API, get it after `as`, while the body is still serialized as JSON:
+/// ```rust
+/// # use api_client::{api, Api};
+/// # use serde::Serialize;
+/// # api!(pub struct ExampleApi);
+/// #[derive(Serialize)]
+/// struct Article {
+///     title: String,
+/// }
+///
+/// impl ExampleApi {
+///     api! {
+///         fn create_article(request: Json<Article> as "application/vnd.api+json") -> StatusCode {
+///            POST "https://example.com/articles"
+///         }
+///     }
+/// }
+/// ```
+///
+/// Other request kinds don't take a media type and fail to compile with one.
+///
+/// # Methods
+/// Methods other than the ones defined by [`reqwest::Method`] can be given as a string, such as
+/// the `WebDAV` method `PROPFIND`:
+/// ```rust
+/// # use api_client::{api, Api};
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn properties(path: &str) -> String {
+///            "PROPFIND" "https://example.com/dav/{path}"
+///         }
+///     }
+/// }
+/// ```
+///
+/// Other methods, as well as return kinds the macro doesn't know, fail to compile with an error
+/// naming the endpoint and listing the supported forms:
+/// ```compile_fail
+/// # use api_client::{api, Api};
+/// # api!(pub struct ExampleApi);
+/// impl ExampleApi {
+///     api! {
+///         fn properties(path: &str) -> String {
+///            PROPFIND "https://example.com/dav/{path}"
+///         }
+///     }
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use api_client::{api, Api};
+/// # api!(pub struct ExampleApi);
+/// impl ExampleApi {
+///     api! {
+///         fn todo(id: u32) -> Jsn<u32> {
+///            GET "https://example.com/todos/{id}"
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Endpoint options
+/// Options for a single endpoint are declared in a `with` block after its URL. Unknown options
+/// fail to compile.
+///
+/// | Option          | Description                                                          |
+/// |-----------------|----------------------------------------------------------------------|
+/// | `timeout: 30s`  | Timeout of a single request, overriding the one of the client. Units are `ms`, `s`, `m` and `h`. |
+/// | `deadline: 2s`  | Time a whole call has to finish in, including retries, backoff and reading the body, see [`deadline`]. |
+/// | `retry: default` | Sends the request again after transient failures: `default`, a number of attempts such as `5`, a [`RetryPolicy`](retry::RetryPolicy) constant or `none`, see [`retry`]. |
+/// | `idempotent`    | Marks the endpoint as safe to repeat, which `GET`, `HEAD`, `PUT`, `DELETE`, `OPTIONS` and `TRACE` endpoints are by default. `idempotent: false` unmarks it. Identical requests of other endpoints marked idempotent are coalesced like `GET` requests, see [`ApiState::deduplicate`]. |
+/// | `cache: 5m`     | How long responses stay fresh in the HTTP cache if their headers don't say, or the [`CacheMode`](cache::CacheMode) of its requests: `only_if_cached`, `stale_while_revalidate` or `force_refresh`, see [`cache`]. Both can be declared. |
+/// | `fallback: ...` | Value returned instead of an error, see below.                       |
+/// | `scopes: [...]` | Scopes the token needs for the endpoint, checked before sending requests, see [`permissions`]. |
+/// | `requires: [...]` | Endpoints which have to succeed before this one is called, checked in debug builds, see below. |
+/// | `grpc_status`   | Turns a `grpc-status` other than `0` in the trailers or headers of responses into [`Error::Grpc`], see [`grpc`]. |
+/// | `raw`           | Also generates a `_raw` method returning the undecoded [`reqwest::Response`], e.g. to stream the body or read headers. |
+/// | `login: ...`    | Marks a login endpoint, whose successful responses carry the token sent with later requests, see [`auth`](auth#login-endpoints). |
+/// | `rpc: "name"`   | Name of the JSON-RPC method an endpoint taking `RpcParams<P>` calls, the name of the endpoint by default, see [`jsonrpc`](crate::jsonrpc). |
+/// | `query: [...]`  | Parameters appended to the query of the URL, left out when `None`, see [`query`]. |
+/// | `arrays: comma` | Format of sequences in `query` parameters, `repeat`, `comma`, `brackets` or `pipes`, see [`query`](query#sequences). |
+///
+/// A fallback turns failures into a successful result, once all attempts allowed by `retry` have
+/// failed:
+/// - `fallback: default` returns [`Default::default`].
+/// - `fallback: value(expr)` returns `expr`.
+/// - `fallback: last_good` returns the last successful response for the same URL, if any, see
+///   [`last_good`]. The output has to implement `Serialize` and `Deserialize`.
+/// - `fallback: endpoint(self.other(...))` calls another endpoint instead.
+/// ```rust
+/// # use api_client::{api, Api};
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn motd(&self) -> String {
+///            GET "https://example.com/motd"
+///            with { fallback: value("Welcome!".to_string()) }
+///         }
+///
+///         fn todos(&self, page: u32) -> String {
+///            GET "https://example.com/todos?page={page}"
+///            with { fallback: endpoint(self.mirrored_todos(page)) }
+///         }
+///
+///         fn mirrored_todos(&self, page: u32) -> String {
+///            GET "https://mirror.example.com/todos?page={page}"
+///            with { fallback: last_good }
+///         }
+///     }
+/// }
+/// ```
+///
+/// With `raw`, callers who occasionally need the response itself don't have to declare the
+/// endpoint twice. Endpoints returning `Response` always return it undecoded:
+/// ```rust
+/// # use api_client::{api, Api, ResultType};
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn export(id: u32) -> Bytes {
+///            GET "https://example.com/exports/{id}"
+///            with { raw }
+///         }
+///     }
+/// }
+///
+/// async fn export_size(api: &ExampleApi) -> ResultType<Option<u64>> {
+///     Ok(api.export_raw(42).await?.content_length())
+/// }
+/// ```
+///
+/// `requires` catches calls made in the wrong order early: in debug builds, calling an endpoint
+/// before all endpoints it requires have succeeded on the same instance panics. It names endpoints
+/// of the same struct or sub-client, so a `login` of one sub-client doesn't satisfy another.
+/// Sessions which are restored instead, e.g. from cookies, are recorded with
+/// [`ApiState::mark_called`](ApiState::mark_called).
+/// ```rust
+/// # use api_client::{api, Api};
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn login(&self, user: &str) -> StatusCode {
+///            POST "https://example.com/login?user={user}"
+///         }
+///
+///         fn profile(&self) -> String {
+///            GET "https://example.com/profile"
+///            with { requires: [login] }
+///         }
+///     }
+/// }
+/// ```
+///
+/// The options end up in the [`EndpointSpec`](endpoint::EndpointSpec) of the endpoint.
+/// ```rust
+/// # use api_client::{api, Api};
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn report() -> String {
+///            POST "https://example.com/report"
+///            with { retry: default, timeout: 10s, cache: 5m, idempotent }
+///         }
+///     }
+/// }
+/// ```
+///
+/// `TIMEOUT 30s` after the URL is a shorthand for `with { timeout: 30s }`. Misspelled options are
+/// rejected:
+/// ```compile_fail
+/// # use api_client::{api, Api};
+/// # api!(pub struct ExampleApi);
+/// impl ExampleApi {
+///     api! {
+///         fn report() -> String {
+///            GET "https://example.com/report"
+///            with { timout: 30s }
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Per-call options
+/// Every endpoint also gets a sibling method with a `_with` suffix, which takes a closure
+/// changing the request of that call only, e.g. to add a header, query parameter or timeout
+/// without changing the declaration. It runs after [`Api::pre_request`]:
+/// ```rust
+/// use std::time::Duration;
+///
+/// use api_client::{api, Api, ResultType};
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn search(query: &str) -> String {
+///            GET "https://example.com/search?q={query}"
+///         }
+///     }
+/// }
+///
+/// async fn search_slowly(api: &ExampleApi) -> ResultType<String> {
+///     api.search_with("rust", |request| {
+///         request
+///             .header("x-request-id", "42")
+///             .query(&[("page", "2")])
+///             .timeout(Duration::from_secs(60))
+///     })
+///     .await
+/// }
+/// ```
+///
+/// # Building requests
+/// Every endpoint also gets a `build_<name>_request` method, which returns the request of a call
+/// with [`Api::pre_request`] and the body applied, without sending it, e.g. to sign it, queue it
+/// while offline or check its shape in tests. Endpoints whose URL awaits, e.g. to discover it,
+/// get none:
+/// ```rust
+/// use api_client::{api, ResultType};
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn todo(id: u32) -> String {
+///            GET "https://example.com/todos/{id}"
+///         }
+///     }
+/// }
+///
+/// fn inspect(api: &ExampleApi) -> ResultType<()> {
+///     let request = api.build_todo_request(1)?;
+///     assert_eq!(request.url().as_str(), "https://example.com/todos/1");
+///     Ok(())
+/// }
+/// ```
+///
+/// # Introspection
+/// Blocks of endpoints starting with `#![endpoints]` also generate an `endpoints()` function
+/// listing their names, methods, URL templates and parameters, see
+/// [`EndpointMeta`](endpoint::EndpointMeta). A type with several blocks names the listing of the
+/// others, e.g. `#![endpoints(admin)]` generates `admin_endpoints()`:
+/// ```rust
+/// use api_client::{api, Api};
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         #![endpoints]
+///
+///         fn todo(id: u32) -> String {
+///            GET "https://example.com/todos/{id}"
+///         }
+///     }
+/// }
+///
+/// impl ExampleApi {
+///     api! {
+///         #![endpoints(admin)]
+///
+///         fn users() -> String {
+///            GET "https://example.com/admin/users"
+///         }
+///     }
+/// }
+///
+/// assert_eq!(ExampleApi::endpoints()[0].name, "todo");
+/// assert_eq!(ExampleApi::admin_endpoints()[0].name, "users");
+/// ```
+///
+/// # Middleware
+/// With the `middleware` feature the generated struct wraps a
+/// [`ClientWithMiddleware`](reqwest_middleware::ClientWithMiddleware) instead, see [`ClientType`].
+///
+/// # Advanced Usage (manually created struct and [Api] implementation)
+/// ```rust
+/// use api_client::{api, Api, ResultType};
+/// use reqwest::{Client, RequestBuilder};
+///
+/// struct ExampleApi {
+///     client: Client,
 ///     username: String,
 ///     password: String
 /// }
@@ -412,7 +2252,7 @@ macro_rules! api {
 ///         &self.client
 ///     }
 ///
-///     fn pre_request(&self, request: RequestBuilder) -> reqwest_middleware::Result<RequestBuilder> {
+///     fn pre_request(&self, request: RequestBuilder) -> ResultType<RequestBuilder> {
 ///         Ok(request.basic_auth(&self.username, Some(&self.password)))
 ///     }
 /// }
@@ -426,144 +2266,756 @@ macro_rules! api {
 /// }
 /// ```
 #[macro_export]
-#[cfg(feature = "middleware")]
 macro_rules! api {
     () => {};
 
-    ($(#[$attr:meta])* $vis:vis struct $ident:ident) => {
-        $(#[$attr])*
-        $vis struct $ident(::reqwest_middleware::ClientWithMiddleware);
+    ($(#[$attr:meta])* $vis:vis struct $ident:ident) => {
+        api!(@struct [$(#[$attr])*] [$vis] $ident [] []);
+    };
+
+    ($(#[$attr:meta])* $vis:vis struct $ident:ident, auth = $($auth:tt)+) => {
+        api!(@struct [$(#[$attr])*] [$vis] $ident [
+            /// Credential sent with every request.
+            auth: $crate::auth::HeaderAuth = api!(@auth $($auth)+)
+        ] [
+            fn pre_request(&self, request: $crate::RequestBuilder) -> $crate::ResultType<$crate::RequestBuilder> {
+                self.auth.apply(request)
+            }
+
+            fn on_login(&self, token: ::std::string::String) {
+                self.auth.set_token(token);
+            }
+        ]);
+
+        impl $ident {
+            /// Sets or replaces the token sent with every request, see [`HeaderAuth`]($crate::auth::HeaderAuth).
+            #[allow(dead_code)]
+            $vis fn set_token(&self, token: impl ::core::convert::Into<::std::string::String>) {
+                self.auth.set_token(token);
+            }
+        }
+    };
+
+    (@auth Bearer) => {
+        $crate::auth::HeaderAuth::bearer()
+    };
+
+    (@auth ApiKey(header $header:literal)) => {
+        $crate::auth::HeaderAuth::api_key($header)
+    };
+
+    (@struct [$(#[$attr:meta])*] [$vis:vis] $ident:ident [$($(#[$field_attr:meta])* $field:ident: $field_ty:ty = $default:expr),*] [$($hooks:tt)*]) => {
+        api! {
+            $(#[$attr])*
+            $vis struct $ident {
+                $($(#[$field_attr])* $field: $field_ty = $default),*
+            }
+
+            impl Api {
+                fn new() -> Self where Self: Sized {
+                    let () = $crate::__private::TlsBackend::<Self>::CHECK;
+                    let client = $crate::ApiState::new().default_client();
+                    Self::with_client($crate::__private::wrap_client(client))
+                }
+
+                $($hooks)*
+            }
+        }
+
+        impl $ident {
+            /// Creates a new instance using an existing client.
+            #[allow(dead_code)]
+            $vis fn with_client(client: $crate::ClientType) -> Self {
+                $ident {
+                    client,
+                    state: $crate::ApiState::new(),
+                    $($field: $default,)*
+                }
+            }
+
+            /// Creates a new instance using a client built from `builder`.
+            #[allow(dead_code)]
+            $vis fn from_builder(builder: $crate::reqwest::ClientBuilder) -> $crate::reqwest::Result<Self> {
+                let () = $crate::__private::TlsBackend::<Self>::CHECK;
+                builder.build().map(|client| Self::with_client($crate::__private::wrap_client(client)))
+            }
+        }
+    };
+
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $ident:ident { $($body:tt)* }
+        headers { $($headers:tt)* }
+        $(query { $($query:tt)* })?
+        $(impl Api { $($hooks:tt)* })?
+    ) => {
+        api!(@receiver [[$(#[$attr])* $vis struct $ident { $($body)* }] [$($headers)*] [$($($query)*)?] [$($($hooks)*)?]] [$($headers)* $($($query)*)?]);
+    };
+
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $ident:ident { $($body:tt)* }
+        query { $($query:tt)* }
+        $(impl Api { $($hooks:tt)* })?
+    ) => {
+        api!(@receiver [[$(#[$attr])* $vis struct $ident { $($body)* }] [] [$($query)*] [$($($hooks)*)?]] [$($query)*]);
+    };
+
+    (@receiver [$($args:tt)*] [( $($inner:tt)* ) $($rest:tt)*]) => {
+        api!(@receiver [$($args)*] [$($inner)* $($rest)*]);
+    };
+
+    (@receiver [$($args:tt)*] [[ $($inner:tt)* ] $($rest:tt)*]) => {
+        api!(@receiver [$($args)*] [$($inner)* $($rest)*]);
+    };
+
+    (@receiver [$($args:tt)*] [{ $($inner:tt)* } $($rest:tt)*]) => {
+        api!(@receiver [$($args)*] [$($inner)* $($rest)*]);
+    };
+
+    (@receiver [$($args:tt)*] [$next:tt $($rest:tt)*]) => {
+        api!(@receiver_is_self [$($args)*] $next [$next] [$($rest)*]);
+    };
+
+    (@receiver [$($args:tt)*] []) => {
+        api!(@defaults [self] $($args)*);
+    };
+
+    (@receiver_is_self [$($args:tt)*] self [$this:tt] [$($rest:tt)*]) => {
+        api!(@defaults [$this] $($args)*);
+    };
+
+    (@receiver_is_self [$($args:tt)*] $next:tt [$this:tt] [$($rest:tt)*]) => {
+        api!(@receiver [$($args)*] [$($rest)*]);
+    };
+
+    (
+        @defaults [$this:tt]
+        [$($decl:tt)*]
+        [$($header:literal: $header_value:expr),* $(,)?]
+        [$($param:literal: $param_value:expr),* $(,)?]
+        [$($hooks:tt)*]
+    ) => {
+        api! {
+            $($decl)*
+
+            impl Api {
+                fn apply_defaults(&$this, request: $crate::RequestBuilder) -> $crate::RequestBuilder {
+                    request
+                        $(.header($header, $header_value))*
+                        $(.query(&[($param, &$param_value)]))*
+                }
+
+                $($hooks)*
+            }
+        }
+    };
+
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $ident:ident {
+            $($(#[$field_attr:meta])* $field_vis:vis $field:ident: $field_ty:ty $(= $default:expr)?),* $(,)?
+        }
+        $(impl Api { $($hooks:tt)* })?
+    ) => {
+        $(#[$attr])*
+        $vis struct $ident {
+            /// The client used to send requests.
+            client: $crate::ClientType,
+            /// Runtime state shared by all requests.
+            state: $crate::ApiState,
+            $($(#[$field_attr])* $field_vis $field: $field_ty),*
+        }
+
+        impl $crate::Api for $ident {
+            fn client(&self) -> &$crate::ClientType {
+                &self.client
+            }
+
+            fn state(&self) -> &$crate::ApiState {
+                &self.state
+            }
+
+            $($($hooks)*)?
+        }
+
+        $crate::__private::paste! {
+            #[doc = ::core::concat!("Builder for [`", ::core::stringify!($ident), "`].")]
+            #[derive(Default)]
+            $vis struct [<$ident Builder>] {
+                /// The client used to send requests, if not the default one.
+                client: ::core::option::Option<$crate::ClientType>,
+                /// Runtime state shared by all requests.
+                state: $crate::ApiState,
+                /// Root certificates and client identity of the default client, if set.
+                tls: ::core::option::Option<$crate::tls::TlsConfig>,
+                /// Proxies of the default client, if set.
+                proxy: ::core::option::Option<$crate::proxy::ProxyConfig>,
+                $(
+                    #[doc = ::core::concat!("Value of the `", ::core::stringify!($field), "` field.")]
+                    $field: ::core::option::Option<$field_ty>,
+                )*
+            }
+
+            impl [<$ident Builder>] {
+                /// Uses an existing client to send requests.
+                #[allow(dead_code)]
+                $vis fn client(mut self, client: $crate::ClientType) -> Self {
+                    self.client = ::core::option::Option::Some(client);
+                    self
+                }
+
+                /// Sets the runtime state shared by all requests, see [`ApiState`]($crate::ApiState).
+                #[allow(dead_code)]
+                $vis fn state(mut self, state: $crate::ApiState) -> Self {
+                    self.state = state;
+                    self
+                }
+
+                /// Sets the extra root certificates and the client identity of the default client,
+                /// see [`tls`]($crate::tls). Replaces the ones of the state.
+                #[allow(dead_code)]
+                $vis fn tls(mut self, tls: $crate::tls::TlsConfig) -> Self {
+                    self.tls = ::core::option::Option::Some(tls);
+                    self
+                }
+
+                /// Sets the proxies of the default client, see [`proxy`]($crate::proxy). Replaces
+                /// the ones of the state.
+                #[allow(dead_code)]
+                $vis fn proxy(mut self, proxy: $crate::proxy::ProxyConfig) -> Self {
+                    self.proxy = ::core::option::Option::Some(proxy);
+                    self
+                }
+
+                /// Trusts the root certificates in a PEM bundle or DER encoded certificate, see
+                /// [`TlsConfig::root_certificates_pem`]($crate::tls::TlsConfig::root_certificates_pem).
+                ///
+                /// # Errors
+                /// Returns an error if `certificates` contains no valid certificate.
+                #[allow(dead_code)]
+                $vis fn root_certificates_pem(mut self, certificates: &[u8]) -> $crate::ResultType<Self> {
+                    let tls = self.tls.take().unwrap_or_default();
+                    self.tls = ::core::option::Option::Some(tls.root_certificates_pem(certificates)?);
+                    ::core::result::Result::Ok(self)
+                }
+
+                /// Presents the identity in a PEM file with its certificate chain and PKCS#8
+                /// private key, see [`TlsConfig::identity_pem`]($crate::tls::TlsConfig::identity_pem).
+                ///
+                /// # Errors
+                /// Returns an error if `pem` doesn't contain a certificate and a private key.
+                #[allow(dead_code)]
+                $vis fn identity_pem(mut self, pem: &[u8]) -> $crate::ResultType<Self> {
+                    let tls = self.tls.take().unwrap_or_default();
+                    self.tls = ::core::option::Option::Some(tls.identity_pem(pem)?);
+                    ::core::result::Result::Ok(self)
+                }
+
+                /// Presents the identity in a PKCS#12 archive, see
+                /// [`TlsConfig::identity_pkcs12`]($crate::tls::TlsConfig::identity_pkcs12).
+                ///
+                /// # Errors
+                /// Returns an error if the archive can't be read, or without the `native-tls`
+                /// backend.
+                #[allow(dead_code)]
+                $vis fn identity_pkcs12(mut self, der: &[u8], password: &str) -> $crate::ResultType<Self> {
+                    let tls = self.tls.take().unwrap_or_default();
+                    self.tls = ::core::option::Option::Some(tls.identity_pkcs12(der, password)?);
+                    ::core::result::Result::Ok(self)
+                }
+
+                $(
+                    #[doc = ::core::concat!("Sets the `", ::core::stringify!($field), "` field.")]
+                    #[allow(dead_code)]
+                    $vis fn $field(mut self, $field: $field_ty) -> Self {
+                        self.$field = ::core::option::Option::Some($field);
+                        self
+                    }
+                )*
+
+                #[doc = ::core::concat!("Builds the [`", ::core::stringify!($ident), "`].")]
+                ///
+                /// # Errors
+                /// Returns an error naming the first field that was not set and has no default.
+                #[allow(dead_code)]
+                $vis fn build(self) -> ::core::result::Result<$ident, $crate::builder::MissingField> {
+                    let () = $crate::__private::TlsBackend::<$ident>::CHECK;
+                    let state = match self.tls {
+                        ::core::option::Option::Some(tls) => self.state.tls(tls),
+                        ::core::option::Option::None => self.state,
+                    };
+                    let state = match self.proxy {
+                        ::core::option::Option::Some(proxy) => state.proxy(proxy),
+                        ::core::option::Option::None => state,
+                    };
+                    ::core::result::Result::Ok($ident {
+                        client: self
+                            .client
+                            .unwrap_or_else(|| $crate::__private::wrap_client(state.default_client())),
+                        state,
+                        $($field: api!(@field self.$field, $field $(, $default)?),)*
+                    })
+                }
+            }
+
+            impl $ident {
+                #[doc = ::core::concat!("Creates a builder for [`", ::core::stringify!($ident), "`].")]
+                #[allow(dead_code)]
+                $vis fn builder() -> [<$ident Builder>] {
+                    [<$ident Builder>]::default()
+                }
+            }
+        }
+    };
+
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $ident:ident { $($body:tt)* }
+        $(impl Api { $($hooks:tt)* })?
+    ) => {
+        api!(@groups [$(#[$attr])*] [$vis] $ident [] [] [$($($hooks)*)?] $($body)*);
+    };
+
+    (@groups [$($attrs:tt)*] [$vis:vis] $ident:ident [$($fields:tt)*] [$($groups:tt)*] [$($hooks:tt)*] mod $group:ident { $($endpoints:tt)* } $($rest:tt)*) => {
+        api!(@groups [$($attrs)*] [$vis] $ident [$($fields)*] [$($groups)* $group { $($endpoints)* }] [$($hooks)*] $($rest)*);
+    };
+
+    (@groups [$($attrs:tt)*] [$vis:vis] $ident:ident [$($fields:tt)*] [$($groups:tt)*] [$($hooks:tt)*] $(#[$field_attr:meta])* $field_vis:vis $field:ident: $field_ty:ty $(= $default:expr)? $(, $($rest:tt)*)?) => {
+        api!(@groups [$($attrs)*] [$vis] $ident [$($fields)* $(#[$field_attr])* $field_vis $field: $field_ty $(= $default)?,] [$($groups)*] [$($hooks)*] $($($rest)*)?);
+    };
+
+    (@groups [$($attrs:tt)*] [$vis:vis] $ident:ident [$($fields:tt)*] [$($group:ident { $($endpoints:tt)* })*] [$($hooks:tt)*]) => {
+        api! {
+            $($attrs)*
+            $vis struct $ident {
+                $($fields)*
+            }
+
+            impl Api {
+                $($hooks)*
+            }
+        }
+
+        $(api!(@group [$vis] $ident $group { $($endpoints)* });)*
+    };
+
+    (@group [$vis:vis] $ident:ident $group:ident { $($endpoints:tt)* }) => {
+        $crate::__private::paste! {
+            #[doc = ::core::concat!("Endpoints of [`", ::core::stringify!($ident), "`] grouped under [`", ::core::stringify!($group), "`](", ::core::stringify!($ident), "::", ::core::stringify!($group), "), sharing its client, state and hooks.")]
+            $vis struct [<$ident $group:camel>]<'a> {
+                /// The client the endpoints belong to.
+                api: &'a $ident,
+            }
+
+            impl $crate::Api for [<$ident $group:camel>]<'_> {
+                $crate::__api_delegate!(api);
+            }
+
+            impl ::core::ops::Deref for [<$ident $group:camel>]<'_> {
+                type Target = $ident;
+
+                fn deref(&self) -> &$ident {
+                    self.api
+                }
+            }
+
+            impl $ident {
+                #[doc = ::core::concat!("Returns the endpoints grouped under `", ::core::stringify!($group), "`.")]
+                #[allow(dead_code)]
+                $vis fn $group(&self) -> [<$ident $group:camel>]<'_> {
+                    [<$ident $group:camel>] { api: self }
+                }
+            }
+
+            impl [<$ident $group:camel>]<'_> {
+                api! {
+                    #![endpoints]
+                    $($endpoints)*
+                }
+            }
+        }
+    };
+
+    (@field $value:expr, $field:ident) => {
+        $value.ok_or($crate::builder::MissingField(::core::stringify!($field)))?
+    };
+
+    (@field $value:expr, $field:ident, $default:expr) => {
+        $value.unwrap_or_else(|| $default)
+    };
+
+    (@fns) => {};
+
+    (@fns $(#[$attr:meta])* $vis:vis fn $ident:ident [$($generics:tt)*] (&$this:ident $(, $($args:tt)*)?) $($rest:tt)*) => {
+        api!(@[$this] $(#[$attr])* $vis fn $ident [$($generics)*] ($($($args)*)?) $($rest)*);
+    };
+
+    (@fns $(#[$attr:meta])* $vis:vis fn $($rest:tt)*) => {
+        api!(@[self] $(#[$attr])* $vis fn $($rest)*);
+    };
+
+    (#![endpoints] $($rest:tt)*) => {
+        api!(@flatten [@listing [endpoints smoke_test_all openapi_operations]] [] $($rest)*);
+    };
+
+    (#![endpoints($name:ident)] $($rest:tt)*) => {
+        $crate::__private::paste! {
+            api!(@flatten [@listing [[<$name _endpoints>] [<smoke_test_ $name>] [<$name _openapi_operations>]]] [] $($rest)*);
+        }
+    };
+
+    ($(#[$($attr:tt)*])* $vis:vis fn $($rest:tt)*) => {
+        api!(@flatten [] [] $(#[$($attr)*])* $vis fn $($rest)*);
+    };
+
+    (prefix $($rest:tt)*) => {
+        api!(@flatten [] [] prefix $($rest)*);
+    };
+
+    (@flatten [$($endpoints:tt)*] [] prefix $prefix:tt { $($grouped:tt)* } $($rest:tt)*) => {
+        api!(@flatten [$($endpoints)*] [$prefix] $($grouped)* @unprefix $($rest)*);
+    };
+
+    (@flatten [$($endpoints:tt)*] [$prefix:tt] @unprefix $($rest:tt)*) => {
+        api!(@flatten [$($endpoints)*] [] $($rest)*);
+    };
+
+    (@flatten [@listing [$list:ident $smoke:ident $operations:ident] $($endpoints:tt)*] []) => {
+        $crate::__api_endpoints!(@collect [$list $smoke] [] $($endpoints)*);
+        $crate::__api_openapi!(@collect [$operations] [] [] $($endpoints)*);
+        api!(@fns $($endpoints)*);
+    };
+
+    (@flatten [$($endpoints:tt)*] []) => {
+        api!(@fns $($endpoints)*);
+    };
+
+    (@flatten [$($endpoints:tt)*] [$($prefix:tt)?] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> $kind:ident<$res:ty>($pointer:literal) $([$($where:tt)*])? { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        api!(@flatten [$($endpoints)* $(#[$($attr)*])* $vis fn $ident [$($($generics)*)?] ($($params)*) -> $kind<$res>($pointer) [$($($where)*)?] { $method $([$prefix])? $($url)+ }] [$($prefix)?] $($rest)*);
+        $crate::__api_response!(@check $ident $kind<$res>);
+        $crate::__api_spec!(@check_method $ident $method);
+    };
+
+    (@flatten [$($endpoints:tt)*] [$($prefix:tt)?] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> $kind:ident<$inner:ident<$res:ty>> $([$($where:tt)*])? { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        api!(@flatten [$($endpoints)* $(#[$($attr)*])* $vis fn $ident [$($($generics)*)?] ($($params)*) -> $kind<$inner<$res>> [$($($where)*)?] { $method $([$prefix])? $($url)+ }] [$($prefix)?] $($rest)*);
+        $crate::__api_response!(@check $ident $kind<$inner<$res> >);
+        $crate::__api_spec!(@check_method $ident $method);
+    };
+
+    (@flatten [$($endpoints:tt)*] [$($prefix:tt)?] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> $kind:ident<$inner:ident$(<$res:ty>)?> $([$($where:tt)*])? { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        api!(@flatten [$($endpoints)* $(#[$($attr)*])* $vis fn $ident [$($($generics)*)?] ($($params)*) -> $kind<$inner$(<$res>)?> [$($($where)*)?] { $method $([$prefix])? $($url)+ }] [$($prefix)?] $($rest)*);
+        $crate::__api_response!(@check $ident $kind<$inner$(<$res>)?>);
+        $crate::__api_spec!(@check_method $ident $method);
+    };
+
+    (@flatten [$($endpoints:tt)*] [$($prefix:tt)?] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> $kind:ident$(<$res:ty>)? $([$($where:tt)*])? { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        api!(@flatten [$($endpoints)* $(#[$($attr)*])* $vis fn $ident [$($($generics)*)?] ($($params)*) -> $kind$(<$res>)? [$($($where)*)?] { $method $([$prefix])? $($url)+ }] [$($prefix)?] $($rest)*);
+        $crate::__api_response!(@check $ident $kind$(<$res>)?);
+        $crate::__api_spec!(@check_method $ident $method);
+    };
+
+    (@flatten [$($endpoints:tt)*] [$($prefix:tt)?] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident < $($rest:tt)*) => {
+        api!(@generics [$($endpoints)*] [$($prefix)?] [$(#[$($attr)*])* $vis fn] $ident [] $($rest)*);
+    };
+
+    (@flatten [$($endpoints:tt)*] [$($prefix:tt)?] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> $($rest:tt)*) => {
+        api!(@where [$($endpoints)*] [$($prefix)?] [$(#[$($attr)*])* $vis fn $ident [$($($generics)*)?] ($($params)*) ->] $ident [] $($rest)*);
+    };
+
+    (@flatten [$($endpoints:tt)*] [$($prefix:tt)?] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $($rest:tt)*) => {
+        api!(@invalid $ident);
+    };
+
+    (@flatten [$($endpoints:tt)*] [$($prefix:tt)?] $next:tt $($rest:tt)*) => {
+        ::core::compile_error!(::core::concat!(
+            "unexpected `", ::core::stringify!($next), "` in a block of endpoints, expected `fn` or, ",
+            "outside of other prefix blocks, `prefix \"...\" { ... }`"
+        ));
+    };
 
-        impl $crate::Api for $ident {
-            fn client(&self) -> &::reqwest_middleware::ClientWithMiddleware {
-                &self.0
-            }
+    (@generics [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($generics:tt)*] > ($($params:tt)*) $($rest:tt)*) => {
+        api!(@flatten [$($endpoints)*] [$($prefix)?] $($decl)* $ident [$($generics)*] ($($params)*) $($rest)*);
+    };
 
-            fn new() -> Self where Self: Sized {
-                $ident(::reqwest_middleware::ClientBuilder::new(::reqwest::Client::new()).build())
-            }
-        }
+    (@generics [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($generics:tt)*] >> ($($params:tt)*) $($rest:tt)*) => {
+        api!(@flatten [$($endpoints)*] [$($prefix)?] $($decl)* $ident [$($generics)* >] ($($params)*) $($rest)*);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> StatusCode { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name $ty),*) -> ::reqwest_middleware::Result<::reqwest::StatusCode> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Json(request)).await.map(|res| res.status())
-        }
-        api!($($rest)*);
+    (@generics [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($generics:tt)*] $next:tt $($rest:tt)*) => {
+        api!(@generics [$($endpoints)*] [$($prefix)?] [$($decl)*] $ident [$($generics)* $next] $($rest)*);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> String { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest_middleware::Result<String> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Json(request)).await?.text().await
-        }
-        api!($($rest)*);
+    (@generics [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($generics:tt)*]) => {
+        api!(@invalid $ident);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> Bytes { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest_middleware::Result<::bytes::Bytes> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Json(request)).await?.bytes().await
-        }
-        api!($($rest)*);
+    (@where [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($kind:tt)*] where $($rest:tt)*) => {
+        api!(@where_clause [$($endpoints)*] [$($prefix)?] [$($decl)* $($kind)*] $ident [] $($rest)*);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> Json<$res:ty> { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest_middleware::Result<$res> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Json(request)).await?.json().await.map_err(reqwest_middleware::Error::from)
-        }
-        api!($($rest)*);
+    (@where [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($kind:tt)*] { $($body:tt)* } $($rest:tt)*) => {
+        api!(@invalid $ident);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> StatusCode { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest_middleware::Result<::reqwest::StatusCode> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Form(request)).await.map(|res| res.status())
-        }
-        api!($($rest)*);
+    (@where [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($kind:tt)*] $next:tt $($rest:tt)*) => {
+        api!(@where [$($endpoints)*] [$($prefix)?] [$($decl)*] $ident [$($kind)* $next] $($rest)*);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> String { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest_middleware::Result<String> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Form(request)).await?.text().await
-        }
-        api!($($rest)*);
+    (@where [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($kind:tt)*]) => {
+        api!(@invalid $ident);
+    };
+
+    (@where_clause [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($where:tt)*] { $($body:tt)* } $($rest:tt)*) => {
+        api!(@flatten [$($endpoints)*] [$($prefix)?] $($decl)* [$($where)*] { $($body)* } $($rest)*);
+    };
+
+    (@where_clause [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($where:tt)*] $next:tt $($rest:tt)*) => {
+        api!(@where_clause [$($endpoints)*] [$($prefix)?] [$($decl)*] $ident [$($where)* $next] $($rest)*);
+    };
+
+    (@where_clause [$($endpoints:tt)*] [$($prefix:tt)?] [$($decl:tt)*] $ident:ident [$($where:tt)*]) => {
+        api!(@invalid $ident);
+    };
+
+    (@invalid $ident:ident) => {
+        ::core::compile_error!(::core::concat!(
+            "invalid declaration of the endpoint `", ::core::stringify!($ident), "`, expected `fn ",
+            ::core::stringify!($ident), "<generics>(params) -> Kind where ... { METHOD \"url\" }` with ",
+            "optional generics and where clause, and an optional `with { ... }` block after the URL"
+        ));
+    };
+
+    (@[$this:ident] $(#[$attr:meta])* $vis:vis fn $ident:ident [$($generics:tt)*] ($($params:tt)*) -> $kind:ident<$res:ty>($pointer:literal) [$($where:tt)*] { $($endpoint:tt)+ } $($rest:tt)*) => {
+        api!(@[$this] @[$kind<$res>] @[$($generics)*] @[$($where)*] $(#[$attr])* $vis fn $ident($($params)*) { $($endpoint)+ POINTER $pointer } $($rest)*);
+    };
+
+    (@[$this:ident] $(#[$attr:meta])* $vis:vis fn $ident:ident [$($generics:tt)*] ($($params:tt)*) -> $kind:ident<$inner:ident<$res:ty>> [$($where:tt)*] { $($endpoint:tt)+ } $($rest:tt)*) => {
+        api!(@[$this] @[$kind<$inner<$res> >] @[$($generics)*] @[$($where)*] $(#[$attr])* $vis fn $ident($($params)*) { $($endpoint)+ } $($rest)*);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> Bytes { $method:tt $url:literal } $($rest:tt)*) => {
+    (@[$this:ident] $(#[$attr:meta])* $vis:vis fn $ident:ident [$($generics:tt)*] ($($params:tt)*) -> $kind:ident<$inner:ident$(<$res:ty>)?> [$($where:tt)*] { $($endpoint:tt)+ } $($rest:tt)*) => {
+        api!(@[$this] @[$kind<$inner$(<$res>)?>] @[$($generics)*] @[$($where)*] $(#[$attr])* $vis fn $ident($($params)*) { $($endpoint)+ } $($rest)*);
+    };
+
+    (@[$this:ident] $(#[$attr:meta])* $vis:vis fn $ident:ident [$($generics:tt)*] ($($params:tt)*) -> $kind:ident$(<$res:ty>)? [$($where:tt)*] { $($endpoint:tt)+ } $($rest:tt)*) => {
+        api!(@[$this] @[$kind$(<$res>)?] @[$($generics)*] @[$($where)*] $(#[$attr])* $vis fn $ident($($params)*) { $($endpoint)+ } $($rest)*);
+    };
+
+    (@[$this:ident] @[GraphQlSubscription<$res:ty>] @[$($generics:tt)*] @[$($where:tt)*] $(#[$attr:meta])* $vis:vis fn $ident:ident(request: GraphQl<$vars:ty>$(, $name:ident: $ty:ty)*) { $method:tt $($url:tt)+ } $($rest:tt)*) => {
         $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest_middleware::Result<::bytes::Bytes> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Form(request)).await?.bytes().await
+        $vis async fn $ident<$($generics)*>(&$this, request: &$crate::graphql::GraphQlRequest<$vars>, $($name: $ty),*) -> $crate::ResultType<$crate::graphql::ws::Subscription<$res>> where $($where)* {
+            let spec = $crate::__api_spec!($ident $method $($url)+);
+            let url = $crate::__api_url!([$crate::Api::state($this)] $($url)+);
+            $crate::graphql::ws::subscribe::<_, $res, $vars>($this, &spec, &url, request).await
         }
-        api!($($rest)*);
+        api!(@fns $($rest)*);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> Json<$res:ty> { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, request: &$req, $($name: $ty),*) -> ::reqwest_middleware::Result<$res> {
-            use $crate::Api as _;
-            self.request(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::Form(request)).await?.json().await.map_err(reqwest_middleware::Error::from)
+    (@[$this:ident] @[$($kind:tt)+] @[$($generics:tt)*] @[$($where:tt)*] $(#[$attr:meta])* $vis:vis fn $ident:ident(params: RpcParams<$params:ty>$(, $name:ident: $ty:ty)*) { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        $crate::__private::paste! {
+            $(#[$attr])*
+            $vis async fn $ident<$($generics)*>(&$this, params: &$params, $($name: $ty),*) -> $crate::ResultType<<$crate::__api_response!($($kind)+) as $crate::response::ResponseKind>::Output> where $($where)* {
+                let request = $crate::jsonrpc::RpcRequest::new($crate::__api_spec!(@rpc $ident $($url)+), params);
+                api!(@execute [$this] [$($kind)+] [$crate::jsonrpc::RpcRequest<'_, $params>] $ident [$method $($url)+] $crate::Body::Json(&request), ::core::option::Option::None)
+            }
+
+            $(#[$attr])*
+            ///
+            #[doc = ::core::concat!("Same as [`", ::core::stringify!($ident), "`](Self::", ::core::stringify!($ident), "), with `options` applied to the request of this call only, e.g. to add a header, query parameter or timeout.")]
+            #[allow(dead_code)]
+            $vis async fn [<$ident _with>]<$($generics)*>(
+                &$this,
+                params: &$params,
+                $($name: $ty,)*
+                options: impl ::core::ops::FnOnce($crate::RequestBuilder) -> $crate::RequestBuilder,
+            ) -> $crate::ResultType<<$crate::__api_response!($($kind)+) as $crate::response::ResponseKind>::Output> where $($where)* {
+                let request = $crate::jsonrpc::RpcRequest::new($crate::__api_spec!(@rpc $ident $($url)+), params);
+                api!(@execute [$this] [$($kind)+] [$crate::jsonrpc::RpcRequest<'_, $params>] $ident [$method $($url)+] $crate::Body::Json(&request), ::core::option::Option::Some(::std::boxed::Box::new(options)))
+            }
         }
-        api!($($rest)*);
+        api!(@fns $($rest)*);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> StatusCode { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, $($name: $ty),*) -> ::reqwest_middleware::Result<::reqwest::StatusCode> {
-            use $crate::Api as _;
-            self.request::<()>(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::None).await.map(|res| res.status())
+    (@[$this:ident] @[$($kind:tt)+] @[$($generics:tt)*] @[$($where:tt)*] $(#[$attr:meta])* $vis:vis fn $ident:ident(request: GraphQl<$vars:ty>$(, $name:ident: $ty:ty)*) { $($endpoint:tt)+ } $($rest:tt)*) => {
+        api!(@[$this] @[$($kind)+] @[$($generics)*] @[$($where)*] $(#[$attr])* $vis fn $ident(request: Json<$crate::graphql::GraphQlRequest<$vars>>$(, $name: $ty)*) { $($endpoint)+ } $($rest)*);
+    };
+
+    (@[$this:ident] @[$($kind:tt)+] @[$($generics:tt)*] @[$($where:tt)*] $(#[$attr:meta])* $vis:vis fn $ident:ident(request: JsonPatch$(, $name:ident: $ty:ty)*) { $($endpoint:tt)+ } $($rest:tt)*) => {
+        api!(@[$this] @[$($kind)+] @[$($generics)*] @[$($where)*] $(#[$attr])* $vis fn $ident(request: JsonPatch<$crate::patch::JsonPatch>$(, $name: $ty)*) { $($endpoint)+ } $($rest)*);
+    };
+
+    (@[$this:ident] @[$($kind:tt)+] @[$($generics:tt)*] @[$($where:tt)*] $(#[$attr:meta])* $vis:vis fn $ident:ident(request: Stream$(, $name:ident: $ty:ty)*) { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        $crate::__private::paste! {
+            $(#[$attr])*
+            $vis async fn $ident<$($generics)*>(&$this, request: $crate::upload::Upload, $($name: $ty),*) -> $crate::ResultType<<$crate::__api_response!($($kind)+) as $crate::response::ResponseKind>::Output> where $($where)* {
+                api!(@execute [$this] [$($kind)+] [()] $ident [$method $($url)+] $crate::Body::Stream(request), ::core::option::Option::None)
+            }
+
+            $(#[$attr])*
+            ///
+            #[doc = ::core::concat!("Same as [`", ::core::stringify!($ident), "`](Self::", ::core::stringify!($ident), "), with `options` applied to the request of this call only, e.g. to add a header, query parameter or timeout.")]
+            #[allow(dead_code)]
+            $vis async fn [<$ident _with>]<$($generics)*>(
+                &$this,
+                request: $crate::upload::Upload,
+                $($name: $ty,)*
+                options: impl ::core::ops::FnOnce($crate::RequestBuilder) -> $crate::RequestBuilder,
+            ) -> $crate::ResultType<<$crate::__api_response!($($kind)+) as $crate::response::ResponseKind>::Output> where $($where)* {
+                api!(@execute [$this] [$($kind)+] [()] $ident [$method $($url)+] $crate::Body::Stream(request), ::core::option::Option::Some(::std::boxed::Box::new(options)))
+            }
+
+            $crate::__api_unless_await!([
+                $(#[$attr])*
+                ///
+                #[doc = ::core::concat!("Builds the request of [`", ::core::stringify!($ident), "`](Self::", ::core::stringify!($ident), ") with the `pre_request` hook and the body applied, without sending it, e.g. to sign or queue it or to check it in tests.")]
+                #[allow(dead_code, clippy::extra_unused_type_parameters)]
+                $vis fn [<build_ $ident _request>]<$($generics)*>(&$this, request: $crate::upload::Upload, $($name: $ty),*) -> $crate::ResultType<$crate::reqwest::Request> where $($where)* {
+                    let spec = $crate::__api_spec!($ident $method $($url)+);
+                    let url = $crate::__api_url!([$crate::Api::state($this)] $($url)+);
+                    $crate::endpoint::build::<_, ()>($this, &spec, &url, $crate::Body::Stream(request))
+                }
+            ] $($url)+);
         }
-        api!($($rest)*);
+        $crate::__api_raw!(@with [[$this] [$(#[$attr])*] [$vis] $ident [$($generics)*] [$($where)*] [request: $crate::upload::Upload, $($name: $ty),*] [()] [$crate::Body::Stream(request)] [$method $($url)+]] $($url)+);
+        api!(@fns $($rest)*);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> String { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, $($name: $ty),*) -> ::reqwest_middleware::Result<String> {
-            use $crate::Api as _;
-            self.request::<()>(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::None).await?.text().await.map_err(reqwest_middleware::Error::from)
+    (@[$this:ident] @[$($kind:tt)+] @[$($generics:tt)*] @[$($where:tt)*] $(#[$attr:meta])* $vis:vis fn $ident:ident(request: GrpcWeb<$req:ty>$(, $name:ident: $ty:ty)*) { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        $crate::__private::paste! {
+            $(#[$attr])*
+            $vis async fn $ident<$($generics)*>(&$this, request: &$req, $($name: $ty),*) -> $crate::ResultType<<$crate::__api_response!($($kind)+) as $crate::response::ResponseKind>::Output> where $($where)* {
+                api!(@execute [$this] [$($kind)+] [()] $ident [$method $($url)+] $crate::Body::GrpcWeb($crate::grpc::web::encode(request)), ::core::option::Option::None)
+            }
+
+            $(#[$attr])*
+            ///
+            #[doc = ::core::concat!("Same as [`", ::core::stringify!($ident), "`](Self::", ::core::stringify!($ident), "), with `options` applied to the request of this call only, e.g. to add a header, query parameter or timeout.")]
+            #[allow(dead_code)]
+            $vis async fn [<$ident _with>]<$($generics)*>(
+                &$this,
+                request: &$req,
+                $($name: $ty,)*
+                options: impl ::core::ops::FnOnce($crate::RequestBuilder) -> $crate::RequestBuilder,
+            ) -> $crate::ResultType<<$crate::__api_response!($($kind)+) as $crate::response::ResponseKind>::Output> where $($where)* {
+                api!(@execute [$this] [$($kind)+] [()] $ident [$method $($url)+] $crate::Body::GrpcWeb($crate::grpc::web::encode(request)), ::core::option::Option::Some(::std::boxed::Box::new(options)))
+            }
+
+            $crate::__api_unless_await!([
+                $(#[$attr])*
+                ///
+                #[doc = ::core::concat!("Builds the request of [`", ::core::stringify!($ident), "`](Self::", ::core::stringify!($ident), ") with the `pre_request` hook and the body applied, without sending it, e.g. to sign or queue it or to check it in tests.")]
+                #[allow(dead_code, clippy::extra_unused_type_parameters)]
+                $vis fn [<build_ $ident _request>]<$($generics)*>(&$this, request: &$req, $($name: $ty),*) -> $crate::ResultType<$crate::reqwest::Request> where $($where)* {
+                    let spec = $crate::__api_spec!($ident $method $($url)+);
+                    let url = $crate::__api_url!([$crate::Api::state($this)] $($url)+);
+                    $crate::endpoint::build::<_, ()>($this, &spec, &url, $crate::Body::GrpcWeb($crate::grpc::web::encode(request)))
+                }
+            ] $($url)+);
         }
-        api!($($rest)*);
+        $crate::__api_raw!(@with [[$this] [$(#[$attr])*] [$vis] $ident [$($generics)*] [$($where)*] [request: &$req, $($name: $ty),*] [()] [$crate::Body::GrpcWeb($crate::grpc::web::encode(request))] [$method $($url)+]] $($url)+);
+        api!(@fns $($rest)*);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> Bytes { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, $($name: $ty),*) -> ::reqwest_middleware::Result<::bytes::Bytes> {
-            use $crate::Api as _;
-            self.request::<()>(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::None).await?.bytes().await.map_err(reqwest_middleware::Error::from)
+    (@[$this:ident] @[$($kind:tt)+] @[$($generics:tt)*] @[$($where:tt)*] $(#[$attr:meta])* $vis:vis fn $ident:ident(request: $body:ident<$req:ty> $(as $media:literal)?$(, $name:ident: $ty:ty)*) { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        $crate::__private::paste! {
+            $(#[$attr])*
+            $vis async fn $ident<$($generics)*>(&$this, request: &$req, $($name: $ty),*) -> $crate::ResultType<<$crate::__api_response!($($kind)+) as $crate::response::ResponseKind>::Output> where $($where)* {
+                api!(@execute [$this] [$($kind)+] [$req] $ident [$method $($url)+] api!(@body request $body $($media)?), ::core::option::Option::None)
+            }
+
+            $(#[$attr])*
+            ///
+            #[doc = ::core::concat!("Same as [`", ::core::stringify!($ident), "`](Self::", ::core::stringify!($ident), "), with `options` applied to the request of this call only, e.g. to add a header, query parameter or timeout.")]
+            #[allow(dead_code)]
+            $vis async fn [<$ident _with>]<$($generics)*>(
+                &$this,
+                request: &$req,
+                $($name: $ty,)*
+                options: impl ::core::ops::FnOnce($crate::RequestBuilder) -> $crate::RequestBuilder,
+            ) -> $crate::ResultType<<$crate::__api_response!($($kind)+) as $crate::response::ResponseKind>::Output> where $($where)* {
+                api!(@execute [$this] [$($kind)+] [$req] $ident [$method $($url)+] api!(@body request $body $($media)?), ::core::option::Option::Some(::std::boxed::Box::new(options)))
+            }
+
+            $crate::__api_unless_await!([
+                $(#[$attr])*
+                ///
+                #[doc = ::core::concat!("Builds the request of [`", ::core::stringify!($ident), "`](Self::", ::core::stringify!($ident), ") with the `pre_request` hook and the body applied, without sending it, e.g. to sign or queue it or to check it in tests.")]
+                #[allow(dead_code, clippy::extra_unused_type_parameters)]
+                $vis fn [<build_ $ident _request>]<$($generics)*>(&$this, request: &$req, $($name: $ty),*) -> $crate::ResultType<$crate::reqwest::Request> where $($where)* {
+                    let spec = $crate::__api_spec!($ident $method $($url)+);
+                    let url = $crate::__api_url!([$crate::Api::state($this)] $($url)+);
+                    $crate::endpoint::build::<_, $req>($this, &spec, &url, api!(@body request $body $($media)?))
+                }
+            ] $($url)+);
         }
-        api!($($rest)*);
+        $crate::__api_raw!(@with [[$this] [$(#[$attr])*] [$vis] $ident [$($generics)*] [$($where)*] [request: &$req, $($name: $ty),*] [$req] [api!(@body request $body $($media)?)] [$method $($url)+]] $($url)+);
+        api!(@fns $($rest)*);
     };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> Json<$res:ty> { $method:tt $url:literal } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&mut self, $($name: $ty),*) -> ::reqwest_middleware::Result<$res> {
-            use $crate::Api as _;
-            self.request::<()>(::reqwest::Method::$method, format!($url).as_str(), $crate::Body::None).await?.json().await.map_err(reqwest_middleware::Error::from)
+    (@[$this:ident] @[$($kind:tt)+] @[$($generics:tt)*] @[$($where:tt)*] $(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) { $method:tt $($url:tt)+ } $($rest:tt)*) => {
+        $crate::__private::paste! {
+            $(#[$attr])*
+            $vis async fn $ident<$($generics)*>(&$this, $($name: $ty),*) -> $crate::ResultType<<$crate::__api_response!($($kind)+) as $crate::response::ResponseKind>::Output> where $($where)* {
+                api!(@execute [$this] [$($kind)+] [()] $ident [$method $($url)+] $crate::Body::None, ::core::option::Option::None)
+            }
+
+            $(#[$attr])*
+            ///
+            #[doc = ::core::concat!("Same as [`", ::core::stringify!($ident), "`](Self::", ::core::stringify!($ident), "), with `options` applied to the request of this call only, e.g. to add a header, query parameter or timeout.")]
+            #[allow(dead_code)]
+            $vis async fn [<$ident _with>]<$($generics)*>(
+                &$this,
+                $($name: $ty,)*
+                options: impl ::core::ops::FnOnce($crate::RequestBuilder) -> $crate::RequestBuilder,
+            ) -> $crate::ResultType<<$crate::__api_response!($($kind)+) as $crate::response::ResponseKind>::Output> where $($where)* {
+                api!(@execute [$this] [$($kind)+] [()] $ident [$method $($url)+] $crate::Body::None, ::core::option::Option::Some(::std::boxed::Box::new(options)))
+            }
+
+            $crate::__api_unless_await!([
+                $(#[$attr])*
+                ///
+                #[doc = ::core::concat!("Builds the request of [`", ::core::stringify!($ident), "`](Self::", ::core::stringify!($ident), ") with the `pre_request` hook and the body applied, without sending it, e.g. to sign or queue it or to check it in tests.")]
+                #[allow(dead_code, clippy::extra_unused_type_parameters)]
+                $vis fn [<build_ $ident _request>]<$($generics)*>(&$this, $($name: $ty),*) -> $crate::ResultType<$crate::reqwest::Request> where $($where)* {
+                    let spec = $crate::__api_spec!($ident $method $($url)+);
+                    let url = $crate::__api_url!([$crate::Api::state($this)] $($url)+);
+                    $crate::endpoint::build::<_, ()>($this, &spec, &url, $crate::Body::None)
+                }
+            ] $($url)+);
         }
-        api!($($rest)*);
+        $crate::__api_raw!(@with [[$this] [$(#[$attr])*] [$vis] $ident [$($generics)*] [$($where)*] [$($name: $ty),*] [()] [$crate::Body::None] [$method $($url)+]] $($url)+);
+        api!(@fns $($rest)*);
+    };
+
+    (@body $request:ident Json $media:literal) => {
+        $crate::Body::JsonAs($request, $media)
+    };
+
+    (@body $request:ident $body:ident $media:literal) => {
+        ::core::compile_error!(::core::concat!(
+            "`", ::core::stringify!($body), "` bodies can't be sent as ", ::core::stringify!($media),
+            ", only `Json<T>` bodies can be sent with another media type"
+        ))
     };
+
+    (@body $request:ident $body:ident) => {
+        $crate::Body::$body($request)
+    };
+
+    (@execute [$this:ident] [$($kind:tt)+] [$req:ty] $ident:ident [$method:tt $($url:tt)+] $body:expr, $options:expr) => {{
+        let spec = $crate::__api_spec!($ident $method $($url)+);
+        let url = $crate::__api_url!([$crate::Api::state($this)] $($url)+);
+        let result = $crate::endpoint::execute::<_, $crate::__api_response!($($kind)+), $req>(
+            $this,
+            &spec,
+            &url,
+            $body,
+            $options,
+        )
+        .await;
+        $crate::__api_fallback!(@with [$this, spec, url, result] $($url)+)
+    }};
 }
 
 #[cfg(test)]
@@ -645,6 +3097,523 @@ mod tests {
         }
     }
 
+    mod fields {
+        use crate::{api, Api, RequestBuilder, ResultType};
+
+        api! {
+            pub struct Fields {
+                pub token: String,
+                pub base_url: String = "http://localhost".to_string(),
+            }
+
+            impl Api {
+                fn pre_request(&self, request: RequestBuilder) -> ResultType<RequestBuilder> {
+                    Ok(request.bearer_auth(&self.token))
+                }
+            }
+        }
+
+        impl Fields {
+            api! {
+                pub fn authorization(&self) -> String {
+                    GET "{}/authorization", self.base_url
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn struct_with_fields() {
+        use crate::{builder::MissingField, test_server};
+        use fields::Fields;
+
+        assert_eq!(Fields::builder().build().err(), Some(MissingField("token")));
+        let fields = Fields::builder().token("a".to_string()).build().unwrap();
+        assert_eq!(fields.base_url, "http://localhost");
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                test_server::Response::new(200).body(request.header("authorization").unwrap())
+            })
+            .await;
+            let api = Fields::builder()
+                .token("secret".to_string())
+                .base_url(base_url)
+                .build()
+                .unwrap();
+            assert_eq!(api.authorization().await.unwrap(), "Bearer secret");
+        });
+    }
+
+    #[test]
+    fn per_call_options() {
+        use crate::test_server;
+        use fields::Fields;
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                test_server::Response::new(200).body(format!(
+                    "{} {} {}",
+                    request.path,
+                    request.header("authorization").unwrap(),
+                    request.header("x-trace").unwrap_or("-"),
+                ))
+            })
+            .await;
+            let api = Fields::builder()
+                .token("secret".to_string())
+                .base_url(base_url)
+                .build()
+                .unwrap();
+
+            let response = api
+                .authorization_with(|request| {
+                    request.header("x-trace", "1").query(&[("page", "2")])
+                })
+                .await
+                .unwrap();
+            assert_eq!(response, "/authorization?page=2 Bearer secret 1");
+            assert_eq!(
+                api.authorization().await.unwrap(),
+                "/authorization Bearer secret -"
+            );
+        });
+    }
+
+    #[test]
+    fn raw_responses() {
+        use crate::{api, test_server, Api};
+
+        api!(struct Exports);
+
+        impl Exports {
+            api! {
+                fn export(&self, base_url: &str) -> String {
+                    GET "{base_url}/export"
+                    with { raw }
+                }
+
+                fn response(&self, base_url: &str) -> Response {
+                    GET "{base_url}/export"
+                }
+            }
+        }
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|_| {
+                test_server::Response::new(200)
+                    .header("x-export", "1")
+                    .body("data")
+            })
+            .await;
+            let api = Exports::new();
+
+            assert_eq!(api.export(&base_url).await.unwrap(), "data");
+            let response = api.export_raw(&base_url).await.unwrap();
+            assert_eq!(response.headers()["x-export"], "1");
+            assert_eq!(response.text().await.unwrap(), "data");
+            let response = api.response(&base_url).await.unwrap();
+            assert_eq!(response.text().await.unwrap(), "data");
+        });
+    }
+
+    #[test]
+    fn sub_clients() {
+        use crate::{api, test_server, Api, RequestBuilder, ResultType};
+
+        api! {
+            struct Shop {
+                token: String,
+                base_url: String = "http://localhost".to_string(),
+                mod orders {
+                    fn list(&self) -> String {
+                        GET "{}/orders", self.base_url
+                    }
+
+                    fn get(&self, id: u32) -> String {
+                        GET "{}/orders/{id}", self.base_url
+                    }
+                }
+                mod customers {
+                    fn me(&self) -> String {
+                        GET "{}/customers/me", self.base_url
+                    }
+                }
+            }
+
+            impl Api {
+                fn pre_request(&self, request: RequestBuilder) -> ResultType<RequestBuilder> {
+                    Ok(request.bearer_auth(&self.token))
+                }
+            }
+        }
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                test_server::Response::new(200).body(format!(
+                    "{} {}",
+                    request.path,
+                    request.header("authorization").unwrap()
+                ))
+            })
+            .await;
+            let shop = Shop::builder()
+                .token("secret".to_string())
+                .base_url(base_url)
+                .build()
+                .unwrap();
+
+            assert_eq!(shop.orders().list().await.unwrap(), "/orders Bearer secret");
+            assert_eq!(
+                shop.orders().get(7).await.unwrap(),
+                "/orders/7 Bearer secret"
+            );
+            assert_eq!(
+                shop.customers().me().await.unwrap(),
+                "/customers/me Bearer secret"
+            );
+            assert!(std::ptr::eq(shop.orders().state(), shop.state()));
+            assert_eq!(ShopOrders::endpoints().len(), 2);
+        });
+    }
+
+    #[test]
+    fn path_prefixes() {
+        use crate::{api, test_server, Api};
+
+        api!(struct Todos);
+
+        impl Todos {
+            api! {
+                #![endpoints]
+
+                fn health(&self, base_url: &str) -> String {
+                    GET "{base_url}/health"
+                }
+
+                prefix "{base_url}/v2/todos" {
+                    fn list(&self, base_url: &str) -> String {
+                        GET ""
+                    }
+
+                    fn get(&self, base_url: &str, id: u32) -> String {
+                        GET "/{id}"
+                        with { raw }
+                    }
+                }
+
+                prefix ("{}/v1", base_url) {
+                    fn legacy(&self, base_url: &str, id: u32) -> String {
+                        GET "/todo?id={id}"
+                    }
+                }
+            }
+        }
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                test_server::Response::new(200).body(request.path.clone())
+            })
+            .await;
+            let api = Todos::new();
+
+            assert_eq!(api.health(&base_url).await.unwrap(), "/health");
+            assert_eq!(api.list(&base_url).await.unwrap(), "/v2/todos");
+            assert_eq!(api.get(&base_url, 1).await.unwrap(), "/v2/todos/1");
+            let response = api.get_raw(&base_url, 2).await.unwrap();
+            assert_eq!(response.text().await.unwrap(), "/v2/todos/2");
+            assert_eq!(api.legacy(&base_url, 3).await.unwrap(), "/v1/todo?id=3");
+        });
+
+        let urls = Todos::endpoints()
+            .iter()
+            .map(|endpoint| endpoint.url)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "{base_url}/health",
+                "{base_url}/v2/todos",
+                "{base_url}/v2/todos/{id}",
+                "{}/v1/todo?id={id}",
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_endpoints_per_block() {
+        api!(struct Admin);
+
+        impl Admin {
+            api! {
+                #![endpoints]
+
+                fn health(&self, base_url: &str) -> String {
+                    GET "{base_url}/health"
+                }
+            }
+        }
+
+        impl Admin {
+            api! {
+                #![endpoints(users)]
+
+                fn users(&self, base_url: &str) -> String {
+                    GET "{base_url}/users"
+                }
+
+                fn user(&self, base_url: &str, id: u32) -> String {
+                    GET "{base_url}/users/{id}"
+                }
+            }
+        }
+
+        impl Admin {
+            api! {
+                fn audit(&self, base_url: &str) -> String {
+                    GET "{base_url}/audit"
+                }
+            }
+        }
+
+        assert_eq!(Admin::endpoints().len(), 1);
+        let names = Admin::users_endpoints()
+            .iter()
+            .map(|endpoint| endpoint.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["users", "user"]);
+
+        tokio_test::block_on(async {
+            let base_url =
+                crate::test_server::serve(|_| crate::test_server::Response::new(200)).await;
+            let api = Admin::builder().build().unwrap();
+            let params = crate::smoke::SmokeParams::new().param("base_url", &base_url);
+            assert_eq!(api.smoke_test_all(params).await.results.len(), 1);
+            let params = crate::smoke::SmokeParams::new()
+                .param("base_url", &base_url)
+                .param("id", 1);
+            assert!(api.smoke_test_users(params).await.is_success());
+            assert_eq!(api.audit(&base_url).await.unwrap(), "");
+        });
+    }
+
+    #[test]
+    fn generic_endpoints() {
+        use serde::{de::DeserializeOwned, Serialize};
+
+        use crate::{api, test_server, Api};
+
+        api!(struct Store);
+
+        impl Store {
+            api! {
+                #![endpoints]
+
+                fn put<T: Serialize>(&self, request: Json<T>, base_url: &str, key: &str) -> StatusCode {
+                    PUT "{base_url}/items/{key}"
+                }
+
+                fn get<'a, T>(&self, base_url: &'a str, key: &'a str) -> Json<T>
+                where
+                    T: DeserializeOwned,
+                {
+                    GET "{base_url}/items/{key}"
+                    with { raw }
+                }
+
+                prefix "{base_url}/items" {
+                    fn list<T: DeserializeOwned>(&self, base_url: &str) -> Json<Vec<T>> {
+                        GET ""
+                    }
+                }
+            }
+        }
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.method.as_str() {
+                "PUT" => {
+                    assert_eq!(request.body, br#"{"id":1}"#);
+                    test_server::Response::new(204)
+                }
+                _ if request.path == "/items" => test_server::Response::new(200).body("[1, 2]"),
+                _ => test_server::Response::new(200).body(r#""done""#),
+            })
+            .await;
+            let api = Store::new();
+
+            let status = api
+                .put(&serde_json::json!({ "id": 1 }), &base_url, "a")
+                .await
+                .unwrap();
+            assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+            let value: String = api.get(&base_url, "a").await.unwrap();
+            assert_eq!(value, "done");
+            let response = api.get_raw::<String>(&base_url, "a").await.unwrap();
+            assert_eq!(response.text().await.unwrap(), r#""done""#);
+            assert_eq!(api.list::<u32>(&base_url).await.unwrap(), [1, 2]);
+        });
+
+        let names = Store::endpoints()
+            .iter()
+            .map(|endpoint| endpoint.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["put", "get", "list"]);
+    }
+
+    #[test]
+    fn media_types() {
+        use crate::{test_server, Api};
+
+        api!(struct Articles);
+
+        impl Articles {
+            api! {
+                #![endpoints]
+
+                fn create(&self, request: Json<serde_json::Value> as "application/vnd.api+json", base_url: &str) -> String {
+                    POST "{base_url}/articles"
+                }
+
+                fn update(&self, request: Json<serde_json::Value>, base_url: &str) -> String {
+                    PATCH "{base_url}/articles"
+                }
+            }
+        }
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let content_type = request.header("content-type").unwrap_or_default();
+                test_server::Response::new(200).body(format!(
+                    "{} {}",
+                    content_type,
+                    String::from_utf8_lossy(&request.body)
+                ))
+            })
+            .await;
+            let api = Articles::new();
+            let article = serde_json::json!({ "title": "a" });
+
+            let echo = api.create(&article, &base_url).await.unwrap();
+            assert_eq!(echo, r#"application/vnd.api+json {"title":"a"}"#);
+            let echo = api.update(&article, &base_url).await.unwrap();
+            assert_eq!(echo, r#"application/json {"title":"a"}"#);
+            let request = api.build_create_request(&article, &base_url).unwrap();
+            assert_eq!(request.headers().get_all("content-type").iter().count(), 1);
+        });
+
+        let params = Articles::endpoints()
+            .iter()
+            .map(|endpoint| endpoint.params)
+            .collect::<Vec<_>>();
+        assert_eq!(params, [["request", "base_url"], ["request", "base_url"]]);
+    }
+
+    #[test]
+    fn struct_defaults() {
+        use crate::{test_server, RequestBuilder, ResultType};
+
+        api! {
+            struct Weather {
+                key: String,
+                version: &'static str = "2024-06",
+                mod stations {
+                    fn list(&self, base_url: &str) -> String {
+                        GET "{base_url}/stations"
+                    }
+                }
+            }
+            headers { "x-api-version": self.version, "x-client": "test" }
+            query { "api_key": self.key, "units": 1 }
+            impl Api {
+                fn pre_request(&self, request: RequestBuilder) -> ResultType<RequestBuilder> {
+                    Ok(request.header("x-hook", "1"))
+                }
+            }
+        }
+
+        api! {
+            struct Plain {}
+            headers { "x-client": "plain" }
+        }
+
+        impl Weather {
+            api! {
+                fn forecast(&self, base_url: &str) -> String {
+                    GET "{base_url}/forecast?city=bonn"
+                }
+            }
+        }
+
+        impl Plain {
+            api! {
+                fn forecast(&self, base_url: &str) -> String {
+                    GET "{base_url}/forecast"
+                }
+            }
+        }
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                test_server::Response::new(200).body(format!(
+                    "{} {} {} {}",
+                    request.path,
+                    request.header("x-api-version").unwrap_or_default(),
+                    request.header("x-client").unwrap_or_default(),
+                    request.header("x-hook").unwrap_or_default()
+                ))
+            })
+            .await;
+            let api = Weather::builder()
+                .key("secret".to_string())
+                .build()
+                .unwrap();
+
+            let echo = api.forecast(&base_url).await.unwrap();
+            assert_eq!(
+                echo,
+                "/forecast?city=bonn&api_key=secret&units=1 2024-06 test 1"
+            );
+            let echo = api.stations().list(&base_url).await.unwrap();
+            assert_eq!(echo, "/stations?api_key=secret&units=1 2024-06 test 1");
+            let echo = Plain::builder()
+                .build()
+                .unwrap()
+                .forecast(&base_url)
+                .await
+                .unwrap();
+            assert_eq!(echo, "/forecast  plain ");
+        });
+    }
+
+    #[test]
+    fn fetch_related() {
+        use crate::{related::Related, test_server, Api};
+
+        #[derive(Debug, serde::Deserialize)]
+        struct Todo {
+            owner: Related<String>,
+        }
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.path.as_str() {
+                "/todo" => test_server::Response::new(200).json(&format!(
+                    r#"{{"owner": "http://{}/users/1"}}"#,
+                    request.header("host").unwrap()
+                )),
+                "/users/1" => test_server::Response::new(200).json(r#""jane""#),
+                _ => test_server::Response::new(404),
+            })
+            .await;
+            let api = example::JsonPlaceholder::new();
+
+            let todo: Todo = api
+                .fetch(&Related::new(format!("{base_url}/todo")))
+                .await
+                .unwrap();
+            assert_eq!(api.fetch(&todo.owner).await.unwrap(), "jane");
+        });
+    }
+
     #[test]
     fn json_placeholder() {
         tokio_test::block_on(async {
@@ -0,0 +1,345 @@
+//! Hypermedia links between resources, as sent in `Link` headers ([RFC 8288]) and the `_links`
+//! of HAL documents.
+//!
+//! [`Links::from_headers`], or [`ResponseParts::links`](crate::response::ResponseParts::links) for
+//! endpoints declared with [`WithHeaders`](crate::response::WithHeaders), reads the links of a
+//! response, e.g. the `next` page of a GitHub listing. With the `json` feature, models deserialize
+//! the `_links` of HAL documents into [`Links`] as well. [`Api::follow`](crate::Api::follow)
+//! fetches the resource a link points to through the same client and hooks as every endpoint.
+//!
+//! [RFC 8288]: https://www.rfc-editor.org/rfc/rfc8288
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, Api, ResultType};
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Issue {
+//!     title: String,
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn issues(repository: &str) -> WithHeaders<Json<Vec<Issue>>> {
+//!            GET "https://api.github.com/repos/{repository}/issues"
+//!         }
+//!     }
+//! }
+//!
+//! async fn first_two_pages(api: &ExampleApi) -> ResultType<Vec<Issue>> {
+//!     let page = api.issues("rust-lang/rust").await?;
+//!     let next = page.links().get("next").cloned();
+//!     let mut issues = page.into_body();
+//!     if let Some(next) = next {
+//!         issues.extend(api.follow::<Vec<Issue>>(&next).await?);
+//!     }
+//!     Ok(issues)
+//! }
+//! ```
+
+use reqwest::header::{HeaderMap, LINK};
+
+/// Link to a resource, see [`links`](crate::links).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// URL of the linked resource, absolute unless the server sent a relative one, see
+    /// [`Links::resolve`].
+    pub href: String,
+    /// Relation of the resource to the one the link was read from, e.g. `next` or `self`.
+    pub rel: String,
+    /// Title of the link, if given.
+    pub title: Option<String>,
+    /// Media type of the linked resource, if given.
+    pub media_type: Option<String>,
+}
+
+impl Link {
+    /// Creates a link to `href` with the relation `rel`.
+    pub fn new(rel: impl Into<String>, href: impl Into<String>) -> Self {
+        Self {
+            href: href.into(),
+            rel: rel.into(),
+            title: None,
+            media_type: None,
+        }
+    }
+}
+
+/// Links read from a response, see [`links`](crate::links).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Links(Vec<Link>);
+
+impl Links {
+    /// Parses the `Link` `headers` of a response.
+    ///
+    /// Links with several space-separated relations are listed once per relation, and malformed
+    /// ones are skipped.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut links = Vec::new();
+        for value in headers.get_all(LINK) {
+            if let Ok(value) = value.to_str() {
+                parse_header(value, &mut links);
+            }
+        }
+        Self(links)
+    }
+
+    /// Returns the first link with the relation `rel`.
+    #[must_use]
+    pub fn get(&self, rel: &str) -> Option<&Link> {
+        self.0
+            .iter()
+            .find(|link| link.rel.eq_ignore_ascii_case(rel))
+    }
+
+    /// Returns all links with the relation `rel`.
+    pub fn all<'a>(&'a self, rel: &'a str) -> impl Iterator<Item = &'a Link> + 'a {
+        self.0
+            .iter()
+            .filter(move |link| link.rel.eq_ignore_ascii_case(rel))
+    }
+
+    /// Returns all links.
+    pub fn iter(&self) -> impl Iterator<Item = &Link> {
+        self.0.iter()
+    }
+
+    /// Returns whether there are no links.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Resolves relative links against `base`, the URL of the resource they were read from, so
+    /// they can be followed. Links which are not valid URLs are left as they are.
+    #[must_use]
+    pub fn resolve(mut self, base: &reqwest::Url) -> Self {
+        for link in &mut self.0 {
+            if let Ok(url) = base.join(&link.href) {
+                link.href = url.into();
+            }
+        }
+        self
+    }
+}
+
+impl IntoIterator for Links {
+    type Item = Link;
+    type IntoIter = std::vec::IntoIter<Link>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Appends the links of the `Link` header `value` to `links`.
+fn parse_header(value: &str, links: &mut Vec<Link>) {
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        let href = match rest.strip_prefix('<').and_then(|rest| rest.split_once('>')) {
+            Some((href, after)) => {
+                rest = after;
+                href
+            }
+            None => return,
+        };
+        let (mut rels, mut title, mut media_type) = (None, None, None);
+        while let Some(after) = rest.trim_start().strip_prefix(';') {
+            let (name, value, after) = parse_param(after);
+            rest = after;
+            match name.to_ascii_lowercase().as_str() {
+                "rel" => rels = rels.or(Some(value)),
+                "title" => title = title.or(Some(value)),
+                "type" => media_type = media_type.or(Some(value)),
+                _ => {}
+            }
+        }
+        for rel in rels.iter().flat_map(|rels| rels.split_whitespace()) {
+            links.push(Link {
+                href: href.to_string(),
+                rel: rel.to_string(),
+                title: title.clone(),
+                media_type: media_type.clone(),
+            });
+        }
+    }
+}
+
+/// Parses the parameter at the start of `input`, returning its name, its value without quotes
+/// and the input after it.
+fn parse_param(input: &str) -> (&str, String, &str) {
+    let input = input.trim_start();
+    let end = input.find(['=', ';', ',']).unwrap_or(input.len());
+    let name = input[..end].trim();
+    let rest = match input[end..].strip_prefix('=') {
+        Some(rest) => rest.trim_start(),
+        None => return (name, String::new(), &input[end..]),
+    };
+    if let Some(quoted) = rest.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                '"' => return (name, value, &quoted[index + 1..]),
+                c => value.push(c),
+            }
+        }
+        (name, value, "")
+    } else {
+        let end = rest.find([';', ',']).unwrap_or(rest.len());
+        (name, rest[..end].trim().to_string(), &rest[end..])
+    }
+}
+
+/// Deserializes the `_links` object of a HAL document, whose relations map to one link or an
+/// array of links.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+impl<'de> serde::Deserialize<'de> for Links {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// A link of a HAL document.
+        #[derive(serde::Deserialize)]
+        struct HalLink {
+            /// URL of the linked resource.
+            href: String,
+            /// Title of the link.
+            title: Option<String>,
+            /// Media type of the linked resource.
+            #[serde(rename = "type")]
+            media_type: Option<String>,
+        }
+
+        /// The links of a relation.
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum HalLinks {
+            /// A single link.
+            One(HalLink),
+            /// An array of links.
+            Many(Vec<HalLink>),
+        }
+
+        let relations = std::collections::BTreeMap::<String, HalLinks>::deserialize(deserializer)?;
+        let links = relations
+            .into_iter()
+            .flat_map(|(rel, links)| {
+                let links = match links {
+                    HalLinks::One(link) => vec![link],
+                    HalLinks::Many(links) => links,
+                };
+                links.into_iter().map(move |link| Link {
+                    href: link.href,
+                    rel: rel.clone(),
+                    title: link.title,
+                    media_type: link.media_type,
+                })
+            })
+            .collect();
+        Ok(Self(links))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::{Link, Links};
+    #[cfg(feature = "json")]
+    use crate::api;
+
+    #[test]
+    fn parses_link_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "link",
+            HeaderValue::from_static(
+                r#"<https://api.example.com/items?page=2>; rel="next", <https://api.example.com/items?page=9>; rel="last"; title="last, \"final\" page""#,
+            ),
+        );
+        headers.append(
+            "link",
+            HeaderValue::from_static(r#"</items/1>; rel="self canonical"; type=application/json"#),
+        );
+        let links = Links::from_headers(&headers);
+        assert_eq!(
+            links.get("next"),
+            Some(&Link::new("next", "https://api.example.com/items?page=2"))
+        );
+        let last = links.get("LAST").unwrap();
+        assert_eq!(last.title.as_deref(), Some(r#"last, "final" page"#));
+        assert_eq!(links.all("canonical").count(), 1);
+        assert_eq!(
+            links.get("self").unwrap().media_type.as_deref(),
+            Some("application/json")
+        );
+
+        let base = reqwest::Url::parse("https://api.example.com/items").unwrap();
+        let links = links.resolve(&base);
+        assert_eq!(
+            links.get("self").unwrap().href,
+            "https://api.example.com/items/1"
+        );
+        assert!(Links::from_headers(&HeaderMap::new()).is_empty());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn parses_hal_links() {
+        let links: Links = serde_json::from_str(
+            r#"{
+                "self": {"href": "/orders/1"},
+                "items": [{"href": "/items/1", "title": "First"}, {"href": "/items/2"}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(links.get("self").unwrap().href, "/orders/1");
+        let items = links.all("items").map(|link| link.href.as_str());
+        assert_eq!(items.collect::<Vec<_>>(), ["/items/1", "/items/2"]);
+        assert_eq!(links.get("items").unwrap().title.as_deref(), Some("First"));
+    }
+
+    #[cfg(feature = "json")]
+    api!(struct Pages);
+
+    #[cfg(feature = "json")]
+    impl Pages {
+        api! {
+            fn first(&self, base_url: &str) -> WithHeaders<Json<Vec<u32>>> {
+                GET "{base_url}/pages/1"
+            }
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn follows_links() {
+        use crate::{test_server, Api};
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.path.as_str() {
+                "/pages/1" => test_server::Response::new(200)
+                    .header(
+                        "link",
+                        &format!(
+                            "<http://{}/pages/2>; rel=\"next\"",
+                            request.header("host").unwrap()
+                        ),
+                    )
+                    .json("[1, 2]"),
+                _ => test_server::Response::new(200).json("[3]"),
+            })
+            .await;
+            let api = Pages::new();
+
+            let first = api.first(&base_url).await.unwrap();
+            let next = first.links().get("next").cloned().unwrap();
+            assert_eq!(first.body, [1, 2]);
+            assert_eq!(api.follow::<Vec<u32>>(&next).await.unwrap(), [3]);
+        });
+    }
+}
@@ -0,0 +1,488 @@
+//! Formatting numbers and dates in the parameters of APIs which expect localized formats.
+//!
+//! A [`Locale`] set with [`ApiState::locale`](crate::ApiState::locale) describes the decimal and
+//! thousands separators, the date formats and the UTC offset expected by an API. Parameters
+//! wrapped in [`Number`], [`Date`] or [`DateTime`] are then written in that format wherever they
+//! are rendered: interpolated into the URL of an endpoint, or serialized into its query or body,
+//! e.g. in the `*_with` methods. [`WithLocale::with_locale`] uses another locale for a single
+//! call.
+//!
+//! Outside of calls, and for APIs without a locale, the wrappers use [`Locale::default`]: a `.`
+//! decimal separator, ISO 8601 dates and UTC.
+//!
+//! # Usage
+//! ```rust
+//! use std::time::SystemTime;
+//!
+//! use api_client::{
+//!     api,
+//!     locale::{Date, Locale, Number, WithLocale},
+//!     ApiState, ResultType,
+//! };
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn transfers(min: Number<f64>, since: Date) -> String {
+//!            GET "https://example.com/transfers?min={min}&since={since}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn transfers(api: &ExampleApi) -> ResultType<String> {
+//!     // Requests `/transfers?min=1.000,5&since=16.10.2026`
+//!     api.transfers(Number(1000.5), Date(SystemTime::now())).await?;
+//!     // Requests `/transfers?min=1000.5&since=2026-10-16`
+//!     api.transfers(Number(1000.5), Date(SystemTime::now()))
+//!         .with_locale(Locale::default())
+//!         .await
+//! }
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().locale(
+//!         Locale::default()
+//!             .decimal_separator(',')
+//!             .thousands_separator('.')
+//!             .date_format("%d.%m.%Y")
+//!             .utc_offset(60),
+//!     ))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::Poll,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::{ApiState, ResultType};
+
+thread_local! {
+    /// Locale of the call being polled on this thread, if any.
+    static CURRENT: RefCell<Option<Arc<Locale>>> = const { RefCell::new(None) };
+}
+
+/// Formats of the numbers and dates expected by an API, see [`locale`](crate::locale).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    /// Separator between the integer and the fractional part of numbers.
+    decimal_separator: char,
+    /// Separator between groups of three digits of the integer part of numbers, if any.
+    thousands_separator: Option<char>,
+    /// Format of [`Date`]s.
+    date_format: String,
+    /// Format of [`DateTime`]s.
+    datetime_format: String,
+    /// Offset from UTC of the written dates and times, in minutes.
+    utc_offset: i32,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            decimal_separator: '.',
+            thousands_separator: None,
+            date_format: "%Y-%m-%d".to_string(),
+            datetime_format: "%Y-%m-%dT%H:%M:%S%:z".to_string(),
+            utc_offset: 0,
+        }
+    }
+}
+
+impl Locale {
+    /// Separates the integer and the fractional part of numbers with `separator`, `.` by
+    /// default.
+    #[must_use]
+    pub fn decimal_separator(mut self, separator: char) -> Self {
+        self.decimal_separator = separator;
+        self
+    }
+
+    /// Separates groups of three digits of the integer part of numbers with `separator`, numbers
+    /// are not grouped by default.
+    #[must_use]
+    pub fn thousands_separator(mut self, separator: char) -> Self {
+        self.thousands_separator = Some(separator);
+        self
+    }
+
+    /// Writes [`Date`]s in `format`, `%Y-%m-%d` by default.
+    ///
+    /// Formats use the `strftime` specifiers `%Y` (year), `%y` (year without century), `%m`
+    /// (month), `%d` (day), `%H` (hour), `%M` (minute), `%S` (second), `%z` (UTC offset as
+    /// `+0100`), `%:z` (UTC offset as `+01:00`) and `%%`. Other characters are written as they
+    /// are.
+    #[must_use]
+    pub fn date_format(mut self, format: impl Into<String>) -> Self {
+        self.date_format = format.into();
+        self
+    }
+
+    /// Writes [`DateTime`]s in `format`, `%Y-%m-%dT%H:%M:%S%:z` by default, see
+    /// [`Locale::date_format`].
+    #[must_use]
+    pub fn datetime_format(mut self, format: impl Into<String>) -> Self {
+        self.datetime_format = format.into();
+        self
+    }
+
+    /// Writes dates and times at `minutes` east of UTC, UTC by default.
+    ///
+    /// The offset is fixed, daylight saving time has to be accounted for by the caller.
+    #[must_use]
+    pub fn utc_offset(mut self, minutes: i32) -> Self {
+        self.utc_offset = minutes;
+        self
+    }
+
+    /// Writes `number`, as formatted by its [`Display`] implementation, with the separators of
+    /// this locale.
+    fn write_number(&self, f: &mut fmt::Formatter<'_>, number: &str) -> fmt::Result {
+        let (sign, number) = match number.strip_prefix('-') {
+            Some(number) => ("-", number),
+            None => ("", number),
+        };
+        let (integer, fraction) = match number.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (number, None),
+        };
+        f.write_str(sign)?;
+        match self.thousands_separator {
+            Some(separator) if integer.bytes().all(|digit| digit.is_ascii_digit()) => {
+                for (index, digit) in integer.chars().enumerate() {
+                    if index > 0 && (integer.len() - index) % 3 == 0 {
+                        write!(f, "{separator}")?;
+                    }
+                    write!(f, "{digit}")?;
+                }
+            }
+            _ => f.write_str(integer)?,
+        }
+        if let Some(fraction) = fraction {
+            write!(f, "{}{fraction}", self.decimal_separator)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `time` in `format` at the UTC offset of this locale.
+    fn write_time(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        format: &str,
+        time: SystemTime,
+    ) -> fmt::Result {
+        let seconds = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => i64::try_from(since.as_secs()).unwrap_or(i64::MAX),
+            Err(before) => {
+                let before = before.duration();
+                let seconds = i64::try_from(before.as_secs()).unwrap_or(i64::MAX);
+                -seconds - i64::from(before.subsec_nanos() > 0)
+            }
+        };
+        let local = seconds.saturating_add(i64::from(self.utc_offset) * 60);
+        let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+        let second_of_day = local.rem_euclid(86_400);
+        let offset = self.utc_offset.unsigned_abs();
+        let offset_sign = if self.utc_offset < 0 { '-' } else { '+' };
+
+        let mut specifiers = format.chars();
+        while let Some(character) = specifiers.next() {
+            if character != '%' {
+                write!(f, "{character}")?;
+                continue;
+            }
+            match specifiers.next() {
+                Some('Y') => write!(f, "{year:04}")?,
+                Some('y') => write!(f, "{:02}", year.rem_euclid(100))?,
+                Some('m') => write!(f, "{month:02}")?,
+                Some('d') => write!(f, "{day:02}")?,
+                Some('H') => write!(f, "{:02}", second_of_day / 3600)?,
+                Some('M') => write!(f, "{:02}", second_of_day / 60 % 60)?,
+                Some('S') => write!(f, "{:02}", second_of_day % 60)?,
+                Some('z') => write!(f, "{offset_sign}{:02}{:02}", offset / 60, offset % 60)?,
+                Some(':') if specifiers.as_str().starts_with('z') => {
+                    specifiers.next();
+                    write!(f, "{offset_sign}{:02}:{:02}", offset / 60, offset % 60)?;
+                }
+                Some('%') | None => f.write_str("%")?,
+                Some(other) => write!(f, "%{other}")?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls `write` with the locale of the current call, or the default locale outside of
+    /// calls.
+    fn with_current<T>(write: impl FnOnce(&Self) -> T) -> T {
+        let current = CURRENT.with(|current| current.borrow().clone());
+        match current {
+            Some(locale) => write(&locale),
+            None => write(&Self::default()),
+        }
+    }
+}
+
+/// Returns the year, month and day of the date `days` days after the Unix epoch.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    // Both are in range by construction.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    (year, month as u32, day as u32)
+}
+
+/// Number written with the separators of the current [`Locale`].
+///
+/// Wraps any number whose [`Display`] implementation writes digits, an optional `-` sign and an
+/// optional `.` separated fraction, such as the primitive integers and floats. Serializes as a
+/// string.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Number<T>(pub T);
+
+impl<T: Display> Display for Number<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let number = self.0.to_string();
+        Locale::with_current(|locale| locale.write_number(f, &number))
+    }
+}
+
+/// Date written in the date format of the current [`Locale`], see [`Locale::date_format`].
+///
+/// Serializes as a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date(pub SystemTime);
+
+impl Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Locale::with_current(|locale| locale.write_time(f, &locale.date_format, self.0))
+    }
+}
+
+/// Date and time written in the date and time format of the current [`Locale`], see
+/// [`Locale::datetime_format`].
+///
+/// Serializes as a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime(pub SystemTime);
+
+impl Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Locale::with_current(|locale| locale.write_time(f, &locale.datetime_format, self.0))
+    }
+}
+
+impl<T: Display> Serialize for Number<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Serialize for DateTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Formats `url` in the locale of `state`, see [`scope`].
+///
+/// Used internally in the [`api!`](crate::api) macro to build endpoint URLs.
+#[doc(hidden)]
+#[must_use]
+pub fn render(state: &ApiState, url: fmt::Arguments<'_>) -> String {
+    scope(state, || fmt::format(url))
+}
+
+/// Returns `time` as an ISO 8601 date and time in UTC, regardless of the current locale.
+#[cfg(feature = "json")]
+pub(crate) fn utc_datetime(time: SystemTime) -> String {
+    /// Writes the time in the format of the default locale.
+    struct Utc(SystemTime);
+
+    impl Display for Utc {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let locale = Locale::default();
+            locale.write_time(f, &locale.datetime_format, self.0)
+        }
+    }
+
+    Utc(time).to_string()
+}
+
+/// Runs `run` with the locale of `state`, unless the call overrides it with
+/// [`WithLocale::with_locale`].
+pub(crate) fn scope<T>(state: &ApiState, run: impl FnOnce() -> T) -> T {
+    enter(state.default_locale(), false, run)
+}
+
+/// Runs `run` with `locale` as the current locale if it is set, replacing the current one only
+/// if `overrides` is set.
+fn enter<T>(locale: Option<&Arc<Locale>>, overrides: bool, run: impl FnOnce() -> T) -> T {
+    /// Restores the previous locale when dropped, also when `run` panics.
+    struct Restore(Option<Arc<Locale>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    let Some(locale) = locale else {
+        return run();
+    };
+    let previous = CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        if current.is_some() && !overrides {
+            return None;
+        }
+        Some(current.replace(locale.clone()))
+    });
+    let _restore = previous.map(Restore);
+    run()
+}
+
+/// Uses another [`Locale`] for a call, see [`locale`](crate::locale).
+///
+/// Implemented for the futures returned by endpoints and other calls returning a [`ResultType`].
+pub trait WithLocale: Sized {
+    /// Formats the parameters of the call in `locale` instead of the locale of the API.
+    fn with_locale(self, locale: Locale) -> Localized<Self>;
+}
+
+impl<F, T> WithLocale for F
+where
+    F: Future<Output = ResultType<T>>,
+{
+    fn with_locale(self, locale: Locale) -> Localized<Self> {
+        Localized {
+            future: Box::pin(self),
+            locale: Arc::new(locale),
+        }
+    }
+}
+
+/// Future formatting the parameters of a call in a [`Locale`], created by
+/// [`WithLocale::with_locale`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Localized<F> {
+    /// The call.
+    future: Pin<Box<F>>,
+    /// The locale of the call.
+    locale: Arc<Locale>,
+}
+
+impl<F: Future> Future for Localized<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        let locale = self.locale.clone();
+        enter(Some(&locale), true, || self.future.as_mut().poll(cx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{Date, DateTime, Locale, Number, WithLocale};
+    use crate::{api, test_server, ApiState};
+
+    /// Duration of a day.
+    const DAY: Duration = Duration::from_secs(86_400);
+
+    api!(struct Transfers);
+
+    impl Transfers {
+        api! {
+            fn search(&self, base_url: &str, min: Number<f64>, since: Date) -> String {
+                GET "{base_url}/transfers?min={min}&since={since}"
+            }
+        }
+    }
+
+    #[test]
+    fn formats_parameters() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                test_server::Response::new(200).body(request.path.clone())
+            })
+            .await;
+            let german = Locale::default()
+                .decimal_separator(',')
+                .thousands_separator('.')
+                .date_format("%d.%m.%Y")
+                .utc_offset(60);
+            let api = Transfers::builder()
+                .state(ApiState::new().locale(german))
+                .build()
+                .unwrap();
+            // 2026-10-16T23:30:00Z, already the next day at UTC+1.
+            let since = UNIX_EPOCH + DAY * 20_742 + DAY / 48 * 47;
+
+            let path = api.search(&base_url, Number(-1234.5), Date(since)).await;
+            assert_eq!(path.unwrap(), "/transfers?min=-1.234,5&since=17.10.2026");
+            let path = api
+                .search(&base_url, Number(1234.5), Date(since))
+                .with_locale(Locale::default())
+                .await;
+            assert_eq!(path.unwrap(), "/transfers?min=1234.5&since=2026-10-16");
+            let path = api
+                .search_with(&base_url, Number(0.5), Date(since), |request| {
+                    request.query(&[("until", DateTime(since))])
+                })
+                .await;
+            assert_eq!(
+                path.unwrap(),
+                "/transfers?min=0,5&since=17.10.2026&until=2026-10-17T00%3A30%3A00%2B01%3A00"
+            );
+        });
+    }
+
+    #[test]
+    fn formats_outside_of_calls() {
+        assert_eq!(Number(1_000_000).to_string(), "1000000");
+        assert_eq!(
+            DateTime(UNIX_EPOCH - DAY / 2).to_string(),
+            "1969-12-31T12:00:00+00:00"
+        );
+        let locale = Locale::default()
+            .thousands_separator(' ')
+            .datetime_format("%y%m%d %H%M %z %%%q")
+            .utc_offset(-90);
+        super::enter(Some(&locale.into()), true, || {
+            assert_eq!(Number(1_000_000).to_string(), "1 000 000");
+            assert_eq!(Number(-100).to_string(), "-100");
+            assert_eq!(DateTime(UNIX_EPOCH).to_string(), "691231 2230 -0130 %%q");
+        });
+        assert_eq!(Number(1_000_000).to_string(), "1000000");
+    }
+}
@@ -0,0 +1,215 @@
+//! Logging requests and responses with credentials redacted, with the `tracing` feature.
+//!
+//! With a [`RequestLog`] set through [`ApiState::logging`](crate::ApiState::logging), every
+//! request and response of an instance is logged as a `DEBUG` event of the `tracing` crate with
+//! the target `api_client::logging`. Events carry the fields `method`, `url` and `headers`, or
+//! `status` and `headers` for responses, and `body` if bodies are logged.
+//!
+//! Values are redacted before they are logged: the headers named by
+//! [`DEFAULT_REDACTED_HEADERS`](crate::capture::DEFAULT_REDACTED_HEADERS), the query parameters,
+//! form fields and JSON fields named by
+//! [`DEFAULT_REDACTED_PARAMS`](crate::capture::DEFAULT_REDACTED_PARAMS) or [`DEFAULT_REDACTED_FIELDS`],
+//! and any others added with [`RequestLog::redact_header`] and [`RequestLog::redact_field`].
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, logging::RequestLog, ApiState};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().logging(
+//!         RequestLog::new()
+//!             .bodies(1024)
+//!             .redact_header("X-Session")
+//!             .redact_field("iban"),
+//!     ))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::fmt::Write;
+
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+
+pub use crate::capture::DEFAULT_REDACTED_FIELDS;
+use crate::{capture::Redaction, single_flight::SharedResponse, ResultType};
+
+/// Options of the logging of requests and responses, see [`logging`](crate::logging).
+#[derive(Debug, Clone)]
+pub struct RequestLog {
+    /// Headers, query parameters and body fields whose values are redacted.
+    redaction: Redaction,
+    /// Maximum number of logged bytes of bodies, if bodies are logged.
+    body_limit: Option<usize>,
+}
+
+impl Default for RequestLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestLog {
+    /// Logs requests and responses without their bodies.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            redaction: Redaction::for_bodies(),
+            body_limit: None,
+        }
+    }
+
+    /// Also logs the bodies of requests and responses, truncated to `limit` bytes.
+    ///
+    /// Responses are read completely before they are decoded, so logging bodies disables
+    /// streaming, e.g. of [`JsonArrayStream`](crate::response::JsonArrayStream) endpoints. Bodies
+    /// of requests which are streamed themselves are not logged.
+    #[must_use]
+    pub fn bodies(mut self, limit: usize) -> Self {
+        self.body_limit = Some(limit);
+        self
+    }
+
+    /// Also redacts the values of the header `name`, in requests and responses.
+    #[must_use]
+    pub fn redact_header(mut self, name: &str) -> Self {
+        self.redaction.headers.push(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Also redacts the values of the query parameter, form field or JSON field `name`, in
+    /// requests and responses.
+    #[must_use]
+    pub fn redact_field(mut self, name: impl Into<String>) -> Self {
+        self.redaction.params.push(name.into());
+        self
+    }
+
+    /// Logs `request`.
+    pub(crate) fn request(&self, request: &reqwest::Request) {
+        tracing::debug!(
+            method = %request.method(),
+            url = %self.redaction.url(request.url()),
+            headers = %self.headers(request.headers()),
+            body = self.body_of(request).as_deref(),
+            "request"
+        );
+    }
+
+    /// Logs `response`, reading its body first if bodies are logged.
+    ///
+    /// # Errors
+    /// Returns an error if the body could not be read.
+    pub(crate) async fn response(
+        &self,
+        response: reqwest::Response,
+    ) -> ResultType<reqwest::Response> {
+        let status = response.status().as_u16();
+        let headers = self.headers(response.headers());
+        if self.body_limit.is_none() {
+            tracing::debug!(status, headers = %headers, "response");
+            return Ok(response);
+        }
+        let content_type = response.headers().get(CONTENT_TYPE).cloned();
+        let response = SharedResponse::read(response).await?;
+        let body = self.body(response.body(), content_type.as_ref());
+        tracing::debug!(status, headers = %headers, body = %body, "response");
+        Ok(response.to_response())
+    }
+
+    /// Returns `headers` as `name: value` pairs separated by commas, with redacted values.
+    fn headers(&self, headers: &HeaderMap) -> String {
+        let headers: Vec<_> = self
+            .redaction
+            .headers(headers)
+            .into_iter()
+            .map(|(name, value)| format!("{name}: {value}"))
+            .collect();
+        headers.join(", ")
+    }
+
+    /// Returns the body of `request` as text to log, if bodies are logged.
+    fn body_of(&self, request: &reqwest::Request) -> Option<String> {
+        self.body_limit?;
+        let body = request.body()?.as_bytes()?;
+        Some(self.body(body, request.headers().get(CONTENT_TYPE)))
+    }
+
+    /// Returns `body` as text, with redacted fields and truncated to the body limit.
+    fn body(&self, body: &[u8], content_type: Option<&HeaderValue>) -> String {
+        let mut text = self.redaction.body(body, content_type);
+        let limit = self.body_limit.unwrap_or_default();
+        if text.len() > limit {
+            let mut end = limit;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            let length = text.len();
+            text.truncate(end);
+            let _ = write!(text, "... ({length} bytes)");
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+    use super::RequestLog;
+    use crate::{api, capture::REDACTED, test_server, ApiState};
+
+    api!(struct Logged);
+
+    impl Logged {
+        api! {
+            fn count(&self, request: Json<serde_json::Value>, base_url: &str) -> Json<u32> {
+                POST "{base_url}/count"
+            }
+        }
+    }
+
+    #[test]
+    fn redacts_values() {
+        let log = RequestLog::new()
+            .bodies(64)
+            .redact_header("X-Session")
+            .redact_field("iban");
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer token"));
+        headers.insert("x-session", HeaderValue::from_static("abc"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+        assert_eq!(
+            log.headers(&headers),
+            format!("authorization: {REDACTED}, x-session: {REDACTED}, accept: */*")
+        );
+
+        let form = HeaderValue::from_static("application/x-www-form-urlencoded");
+        assert_eq!(
+            log.body(b"user=me&password=hunter2", Some(&form)),
+            "user=me&password=%5Bredacted%5D"
+        );
+        assert_eq!(
+            log.body(br#"{"accounts":[{"iban":"DE00"}]}"#, None),
+            format!(r#"{{"accounts":[{{"iban":"{REDACTED}"}}]}}"#)
+        );
+        assert_eq!(
+            RequestLog::new().bodies(4).body("abcdé".as_bytes(), None),
+            "abcd... (6 bytes)"
+        );
+    }
+
+    #[test]
+    fn logs_calls() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|_| test_server::Response::new(200).json("1")).await;
+            let api = Logged::builder()
+                .state(ApiState::new().logging(RequestLog::new().bodies(16)))
+                .build()
+                .unwrap();
+            let request = serde_json::json!({ "user": "me", "password": "hunter2" });
+            assert_eq!(api.count(&request, &base_url).await.unwrap(), 1);
+        });
+    }
+}
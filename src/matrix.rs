@@ -0,0 +1,290 @@
+//! Tests calling an endpoint for every combination of method, body kind and return kind accepted
+//! by the [`api!`](crate::api) macro, against the [test server](crate::test_server).
+//!
+//! Every combination gets its own module with a struct declaring just that endpoint and a test
+//! checking that the server received the declared method and body, and that the response decodes
+//! into the declared kind. New methods, body kinds or return kinds are covered by adding them to
+//! the lists at the end of this file.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    response::{Downloading, Frames, JsonArrayItems, LengthPrefixed, Redirected, ResponseParts},
+    test_server::{Request, Response},
+};
+
+/// What the test server received, sent back in the format the path asks for.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Echo {
+    /// Method of the request.
+    method: String,
+    /// Content type of the request body, empty without a body.
+    content_type: String,
+    /// Request body.
+    body: String,
+}
+
+/// Body sent by the endpoints declared with one.
+#[derive(Serialize)]
+struct Payload {
+    /// Some value.
+    value: u32,
+}
+
+/// Answers `request` with an [`Echo`] in the format its path asks for.
+fn respond(request: &Request) -> Response {
+    let echo = Echo {
+        method: request.method.clone(),
+        content_type: request
+            .header("content-type")
+            .unwrap_or_default()
+            .to_string(),
+        body: String::from_utf8_lossy(&request.body).into_owned(),
+    };
+    let json = serde_json::to_string(&echo).unwrap();
+    match request.path.as_str() {
+        "/items" => Response::new(200).json(&format!(r#"{{"items":[{json}]}}"#)),
+        "/frames" => {
+            let length = u32::try_from(json.len()).unwrap();
+            Response::new(200).body([&length.to_be_bytes()[..], json.as_bytes()].concat())
+        }
+        "/created" => {
+            let query = serde_urlencoded::to_string(&echo).unwrap();
+            Response::new(201).header("location", &format!("/created?{query}"))
+        }
+        path if path.starts_with("/created?") => {
+            let echo: Echo = serde_urlencoded::from_str(&path["/created?".len()..]).unwrap();
+            Response::new(200).json(&serde_json::to_string(&echo).unwrap())
+        }
+        "/redirect" => {
+            let query = serde_urlencoded::to_string(&echo).unwrap();
+            Response::new(303).header("location", &format!("/?{query}"))
+        }
+        _ => Response::new(200).json(&json),
+    }
+}
+
+/// Output of an endpoint which carries the [`Echo`] of its request.
+#[async_trait::async_trait(?Send)]
+trait Echoed {
+    /// Returns the echo, or `None` for outputs without a body after checking they succeeded.
+    async fn echo(self) -> Option<Echo>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl Echoed for reqwest::StatusCode {
+    async fn echo(self) -> Option<Echo> {
+        assert!(self.is_success());
+        None
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Echoed for String {
+    async fn echo(self) -> Option<Echo> {
+        Some(serde_json::from_str(&self).unwrap())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Echoed for bytes::Bytes {
+    async fn echo(self) -> Option<Echo> {
+        Some(serde_json::from_slice(&self).unwrap())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Echoed for reqwest::Response {
+    async fn echo(self) -> Option<Echo> {
+        Some(self.json().await.unwrap())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Echoed for Echo {
+    async fn echo(self) -> Option<Echo> {
+        Some(self)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl<T: Echoed> Echoed for ResponseParts<T> {
+    async fn echo(self) -> Option<Echo> {
+        assert_eq!(self.header("content-type"), Some("application/json"));
+        self.body.echo().await
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Echoed for Redirected<Echo> {
+    async fn echo(self) -> Option<Echo> {
+        assert_eq!(self.status.as_u16(), 303);
+        Some(self.query)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Echoed for JsonArrayItems<Echo> {
+    async fn echo(mut self) -> Option<Echo> {
+        let echo = self.next().await.unwrap().unwrap();
+        assert!(self.next().await.is_none());
+        Some(echo)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Echoed for Frames<LengthPrefixed> {
+    async fn echo(mut self) -> Option<Echo> {
+        let frame = self.next().await.unwrap().unwrap();
+        assert!(self.next().await.is_none());
+        Some(serde_json::from_slice(&frame).unwrap())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Echoed for Downloading {
+    async fn echo(self) -> Option<Echo> {
+        /// Number of downloads saved so far, keeping their files apart.
+        static DOWNLOADS: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "api-client-matrix-{}-{}",
+            std::process::id(),
+            DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let downloaded = self.save(&path).await.unwrap();
+        let body = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(downloaded.size, body.len() as u64);
+        Some(serde_json::from_slice(&body).unwrap())
+    }
+}
+
+/// Generates a module with a test for every combination of the given methods, body kinds and
+/// return kinds.
+///
+/// Methods are given as `(name METHOD)`, body kinds as `(name Kind "content type" "body")` or
+/// `(none)`, return kinds as `(name "path" Kind...)`, where the path selects the format of the
+/// response, see [`respond`].
+macro_rules! matrix {
+    ([$($method:tt)*] $bodies:tt $kinds:tt) => {
+        $(matrix!(@bodies $method $bodies $kinds);)*
+    };
+
+    (@bodies $method:tt [$($body:tt)*] $kinds:tt) => {
+        $(matrix!(@kinds $method $body $kinds);)*
+    };
+
+    (@kinds $method:tt $body:tt [$($kind:tt)*]) => {
+        $(matrix!(@case $method $body $kind);)*
+    };
+
+    (@case ($method_name:ident $method:tt) (none) ($kind_name:ident $path:literal $($kind:tt)+)) => {
+        $crate::__private::paste! {
+            mod [<$method_name _none_ $kind_name>] {
+                #[allow(unused_imports)]
+                use super::{Echo, Echoed, Payload};
+                #[allow(unused_imports)]
+                use crate::response::LengthPrefixed;
+                use crate::{api, test_server, ApiState};
+
+                api!(struct Matrix);
+
+                impl Matrix {
+                    api! {
+                        fn call(&self, base_url: &str) -> $($kind)+ {
+                            $method "{base_url}/{}", $path
+                        }
+                    }
+                }
+
+                #[test]
+                fn call() {
+                    tokio_test::block_on(async {
+                        let base_url = test_server::serve(|request| super::respond(&request)).await;
+                        let api = Matrix::builder()
+                            .state(ApiState::new().follow_redirects(false))
+                            .build()
+                            .unwrap();
+                        if let Some(echo) = api.call(&base_url).await.unwrap().echo().await {
+                            assert_eq!(echo.method, stringify!($method).trim_matches('"'));
+                            assert_eq!((echo.content_type.as_str(), echo.body.as_str()), ("", ""));
+                        }
+                    });
+                }
+            }
+        }
+    };
+
+    (@case ($method_name:ident $method:tt) ($body_name:ident $body:ident $content_type:literal $sent:literal) ($kind_name:ident $path:literal $($kind:tt)+)) => {
+        $crate::__private::paste! {
+            mod [<$method_name _ $body_name _ $kind_name>] {
+                #[allow(unused_imports)]
+                use super::{Echo, Echoed, Payload};
+                #[allow(unused_imports)]
+                use crate::response::LengthPrefixed;
+                use crate::{api, test_server, ApiState};
+
+                api!(struct Matrix);
+
+                impl Matrix {
+                    api! {
+                        fn call(&self, request: $body<Payload>, base_url: &str) -> $($kind)+ {
+                            $method "{base_url}/{}", $path
+                        }
+                    }
+                }
+
+                #[test]
+                fn call() {
+                    tokio_test::block_on(async {
+                        let base_url = test_server::serve(|request| super::respond(&request)).await;
+                        let api = Matrix::builder()
+                            .state(ApiState::new().follow_redirects(false))
+                            .build()
+                            .unwrap();
+                        let payload = Payload { value: 7 };
+                        if let Some(echo) = api.call(&payload, &base_url).await.unwrap().echo().await {
+                            assert_eq!(echo.method, stringify!($method).trim_matches('"'));
+                            assert_eq!(
+                                (echo.content_type.as_str(), echo.body.as_str()),
+                                ($content_type, $sent)
+                            );
+                        }
+                    });
+                }
+            }
+        }
+    };
+}
+
+matrix!(
+    [
+        (get GET)
+        (post POST)
+        (put PUT)
+        (patch PATCH)
+        (delete DELETE)
+        (propfind "PROPFIND")
+    ]
+    [
+        (none)
+        (json Json "application/json" r#"{"value":7}"#)
+        (form Form "application/x-www-form-urlencoded" "value=7")
+        (merge_patch MergePatch "application/merge-patch+json" r#"{"value":7}"#)
+    ]
+    [
+        (status "echo" StatusCode)
+        (text "echo" String)
+        (bytes "echo" Bytes)
+        (raw "echo" Response)
+        (json "echo" Json<Echo>)
+        (headers "echo" WithHeaders<Json<Echo>>)
+        (redirect "redirect" Redirect<Echo>)
+        (created "created" Created<Json<Echo>>)
+        (items "items" JsonArrayStream<Echo>("/items"))
+        (frames "frames" FrameStream<LengthPrefixed>)
+        (download "echo" Download)
+    ]
+);
@@ -0,0 +1,176 @@
+//! Metrics describing requests, recorded with the `metrics` feature.
+//!
+//! Every endpoint call which sends a request or is answered from the cache records:
+//! - [`REQUESTS`], a counter of calls,
+//! - [`ERRORS`], a counter of calls which failed, because no response was received or it could
+//!   not be decoded,
+//! - [`DURATION`], a histogram of the time until the response was decoded, in seconds.
+//!
+//! All of them are labeled with `endpoint`, the name of the endpoint, and `status`, the class of
+//! the status of the response such as `2xx`, or `none` if no response was received. The metrics
+//! are sent to the recorder installed for the [`metrics`](https://docs.rs/metrics) crate, e.g. a
+//! Prometheus exporter.
+//!
+//! Without the feature, [`record`] compiles to nothing.
+
+use std::time::Instant;
+
+use reqwest::StatusCode;
+
+use crate::endpoint::EndpointSpec;
+
+/// Name of the counter of endpoint calls.
+#[cfg(feature = "metrics")]
+pub(crate) const REQUESTS: &str = "api_client_requests_total";
+
+/// Name of the counter of failed endpoint calls.
+#[cfg(feature = "metrics")]
+pub(crate) const ERRORS: &str = "api_client_errors_total";
+
+/// Name of the histogram of the duration of endpoint calls, in seconds.
+#[cfg(feature = "metrics")]
+pub(crate) const DURATION: &str = "api_client_request_duration_seconds";
+
+/// Records the call of the endpoint described by `spec`, started at `started` and answered with
+/// `status` if a response was received, which `failed` or not.
+pub(crate) fn record(
+    spec: &EndpointSpec,
+    status: Option<StatusCode>,
+    started: Instant,
+    failed: bool,
+) {
+    #[cfg(feature = "metrics")]
+    {
+        let labels = [("endpoint", spec.name), ("status", status_class(status))];
+        metrics::counter!(REQUESTS, &labels).increment(1);
+        if failed {
+            metrics::counter!(ERRORS, &labels).increment(1);
+        }
+        metrics::histogram!(DURATION, &labels).record(started.elapsed());
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (spec, status, started, failed);
+}
+
+/// Returns the class of `status` as a label, e.g. `4xx`.
+#[cfg(feature = "metrics")]
+fn status_class(status: Option<StatusCode>) -> &'static str {
+    match status.map(|status| status.as_u16() / 100) {
+        Some(1) => "1xx",
+        Some(2) => "2xx",
+        Some(3) => "3xx",
+        Some(4) => "4xx",
+        Some(5) => "5xx",
+        Some(_) => "other",
+        None => "none",
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString, Unit,
+    };
+
+    use super::{DURATION, ERRORS, REQUESTS};
+    use crate::{api, test_server, Api};
+
+    /// Recorder keeping every update of a counter or histogram as `name{labels}`.
+    #[derive(Default)]
+    struct Updates(Arc<Mutex<Vec<String>>>);
+
+    /// Counter or histogram reporting its updates to [`Updates`].
+    struct Metric {
+        /// Name and labels of the metric, as `name{labels}`.
+        key: String,
+        /// Updates of all metrics.
+        updates: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CounterFn for Metric {
+        fn increment(&self, _: u64) {
+            self.updates.lock().unwrap().push(self.key.clone());
+        }
+
+        fn absolute(&self, _: u64) {}
+    }
+
+    impl HistogramFn for Metric {
+        fn record(&self, _: f64) {
+            self.updates.lock().unwrap().push(self.key.clone());
+        }
+    }
+
+    impl Updates {
+        /// Returns a handle reporting updates of the metric `key`.
+        fn metric(&self, key: &Key) -> Arc<Metric> {
+            let labels: Vec<_> = key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect();
+            Arc::new(Metric {
+                key: format!("{}{{{}}}", key.name(), labels.join(",")),
+                updates: self.0.clone(),
+            })
+        }
+    }
+
+    impl Recorder for Updates {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.metric(key))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.metric(key))
+        }
+    }
+
+    api!(struct Metered);
+
+    impl Metered {
+        api! {
+            fn count(&self, base_url: &str) -> Json<u32> {
+                GET "{base_url}/count"
+            }
+        }
+    }
+
+    #[test]
+    fn records_requests() {
+        let recorder = Updates::default();
+        metrics::with_local_recorder(&recorder, || {
+            tokio_test::block_on(async {
+                let base_url =
+                    test_server::serve(|_| test_server::Response::new(200).json("1")).await;
+                let api = Metered::new();
+                assert_eq!(api.count(&base_url).await.unwrap(), 1);
+                assert!(api.count("http://localhost:1").await.is_err());
+            });
+        });
+
+        let updates = recorder.0.lock().unwrap();
+        assert_eq!(
+            *updates,
+            [
+                format!("{REQUESTS}{{endpoint=count,status=2xx}}"),
+                format!("{DURATION}{{endpoint=count,status=2xx}}"),
+                format!("{REQUESTS}{{endpoint=count,status=none}}"),
+                format!("{ERRORS}{{endpoint=count,status=none}}"),
+                format!("{DURATION}{{endpoint=count,status=none}}"),
+            ]
+        );
+    }
+}
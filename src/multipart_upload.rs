@@ -0,0 +1,620 @@
+//! Uploading large bodies in parts, as S3 and compatible storage services accept them.
+//!
+//! These APIs start an upload, accept its parts in any order and assemble them once the upload is
+//! completed, or discard them when it is aborted. The endpoints are declared like any other and
+//! tied together by implementing [`PartUploads`]. [`MultipartUpload::run`] then splits a reader
+//! into parts of [`part_size`](MultipartUpload::part_size) bytes, uploads several at once,
+//! retries parts which failed transiently and aborts the upload if a part fails for good. Only the
+//! parts being uploaded are held in memory. Like [`Api::batch`](crate::Api::batch), it uploads
+//! fewer parts at once while the rate limit budget of the instance is low, see
+//! [`rate_limit`](crate::rate_limit).
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{
+//!     api,
+//!     multipart_upload::{CompletedPart, MultipartUpload, PartUploads},
+//!     Api, ApiState, ResultType,
+//! };
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Initiated {
+//!     upload_id: String,
+//! }
+//!
+//! #[derive(serde::Serialize)]
+//! struct Part {
+//!     number: u32,
+//!     etag: String,
+//! }
+//!
+//! api!(pub struct Storage);
+//!
+//! impl Storage {
+//!     api! {
+//!         fn initiate(key: &str) -> Json<Initiated> {
+//!            POST "https://storage.example.com/{key}?uploads"
+//!         }
+//!
+//!         fn upload_part(key: &str, upload_id: &str, number: u32) -> Response {
+//!            PUT "https://storage.example.com/{key}?uploadId={upload_id}&partNumber={number}"
+//!         }
+//!
+//!         fn complete(request: Json<[Part]>, key: &str, upload_id: &str) -> StatusCode {
+//!            POST "https://storage.example.com/{key}?uploadId={upload_id}"
+//!         }
+//!
+//!         fn abort(key: &str, upload_id: &str) -> StatusCode {
+//!            DELETE "https://storage.example.com/{key}?uploadId={upload_id}"
+//!         }
+//!     }
+//! }
+//!
+//! struct Object<'a> {
+//!     storage: &'a Storage,
+//!     key: &'a str,
+//! }
+//!
+//! #[async_trait::async_trait(?Send)]
+//! impl PartUploads for Object<'_> {
+//!     type Upload = String;
+//!     type Part = String;
+//!     type Output = ();
+//!
+//!     fn state(&self) -> &ApiState {
+//!         self.storage.state()
+//!     }
+//!
+//!     async fn initiate(&self) -> ResultType<String> {
+//!         Ok(self.storage.initiate(self.key).await?.upload_id)
+//!     }
+//!
+//!     async fn upload_part(&self, upload: &String, number: u32, data: bytes::Bytes) -> ResultType<String> {
+//!         let response = self
+//!             .storage
+//!             .upload_part_with(self.key, upload, number, |request| request.body(data))
+//!             .await?
+//!             // Fails on `4xx` and `5xx`, so the part is retried or the upload aborted.
+//!             .error_for_status()?;
+//!         let etag = response.headers().get("etag").and_then(|etag| etag.to_str().ok());
+//!         Ok(etag.unwrap_or_default().to_string())
+//!     }
+//!
+//!     async fn complete(&self, upload: &String, parts: Vec<CompletedPart<String>>) -> ResultType<()> {
+//!         let parts: Vec<Part> = parts
+//!             .into_iter()
+//!             .map(|part| Part { number: part.number, etag: part.receipt })
+//!             .collect();
+//!         self.storage.complete(&parts, self.key, upload).await?;
+//!         Ok(())
+//!     }
+//!
+//!     async fn abort(&self, upload: &String) -> ResultType<()> {
+//!         self.storage.abort(self.key, upload).await?;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! async fn upload_backup(storage: &Storage) -> ResultType<()> {
+//!     let file = tokio::fs::File::open("backup.tar").await?;
+//!     let object = Object { storage, key: "backups/latest.tar" };
+//!     MultipartUpload::new()
+//!         .part_size(16 << 20)
+//!         .concurrency(4)
+//!         .run(&object, file)
+//!         .await
+//! }
+//! ```
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::{
+    future::{select, Either},
+    stream::FuturesUnordered,
+    StreamExt,
+};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{
+    rate_limit, retry,
+    runtime::{self, Runtime, Tokio},
+    trace, ApiState, ResultType,
+};
+
+/// Size of the parts of a [`MultipartUpload`] unless configured otherwise, above the 5 MiB S3
+/// requires of all but the last part.
+pub const DEFAULT_PART_SIZE: usize = 8 << 20;
+
+/// Number of parts a [`MultipartUpload`] uploads at once unless configured otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// The endpoints of an upload in parts, see [`multipart_upload`](crate::multipart_upload).
+#[async_trait::async_trait(?Send)]
+pub trait PartUploads {
+    /// Identifies a started upload, e.g. its upload ID.
+    type Upload;
+    /// Receipt of an uploaded part needed to complete the upload, e.g. its `ETag`.
+    type Part;
+    /// Returned once the upload is completed.
+    type Output;
+
+    /// Returns the state of the instance uploading the parts, e.g. `self.storage.state()`, whose
+    /// rate limit budget lowers how many parts are uploaded at once.
+    fn state(&self) -> &ApiState;
+
+    /// Starts an upload.
+    ///
+    /// # Errors
+    /// Returns an error if the upload could not be started, nothing is aborted then.
+    async fn initiate(&self) -> ResultType<Self::Upload>;
+
+    /// Uploads the part `number` of `upload`, counted from 1.
+    ///
+    /// # Errors
+    /// Returns an error if the part could not be uploaded. It is retried if the error is
+    /// transient: failing to connect, a timeout, `429 Too Many Requests` or a server error.
+    async fn upload_part(
+        &self,
+        upload: &Self::Upload,
+        number: u32,
+        data: Bytes,
+    ) -> ResultType<Self::Part>;
+
+    /// Completes `upload` from all of its `parts`, ordered by their numbers.
+    ///
+    /// # Errors
+    /// Returns an error if the upload could not be completed, it is aborted then.
+    async fn complete(
+        &self,
+        upload: &Self::Upload,
+        parts: Vec<CompletedPart<Self::Part>>,
+    ) -> ResultType<Self::Output>;
+
+    /// Aborts `upload` after it failed, discarding its parts.
+    ///
+    /// # Errors
+    /// Returns an error if the upload could not be aborted, which is ignored in favor of the
+    /// error which made it fail.
+    async fn abort(&self, upload: &Self::Upload) -> ResultType<()>;
+}
+
+/// An uploaded part, see [`PartUploads::complete`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletedPart<P> {
+    /// Number of the part, counted from 1.
+    pub number: u32,
+    /// Receipt returned by [`PartUploads::upload_part`].
+    pub receipt: P,
+}
+
+/// Uploads a body in parts through [`PartUploads`], see
+/// [`multipart_upload`](crate::multipart_upload).
+#[derive(Clone)]
+pub struct MultipartUpload {
+    /// Size of every part but the last one.
+    part_size: usize,
+    /// Maximum number of parts uploaded at once.
+    concurrency: usize,
+    /// Number of times a part is tried before the upload fails.
+    attempts: u32,
+    /// Wait before the first retry of a part, doubled for every further one.
+    backoff: Duration,
+    /// Runtime sleeping between retries.
+    runtime: Arc<dyn Runtime>,
+}
+
+impl fmt::Debug for MultipartUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultipartUpload")
+            .field("part_size", &self.part_size)
+            .field("concurrency", &self.concurrency)
+            .field("attempts", &self.attempts)
+            .field("backoff", &self.backoff)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for MultipartUpload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MultipartUpload {
+    /// Creates an upload in parts of [`DEFAULT_PART_SIZE`], [`DEFAULT_CONCURRENCY`] of them at
+    /// once, trying each part 3 times.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            part_size: DEFAULT_PART_SIZE,
+            concurrency: DEFAULT_CONCURRENCY,
+            attempts: 3,
+            backoff: Duration::from_millis(500),
+            runtime: Arc::new(Tokio),
+        }
+    }
+
+    /// Sets the size of every part but the last one.
+    ///
+    /// # Panics
+    /// Panics if `part_size` is zero.
+    #[must_use]
+    pub fn part_size(mut self, part_size: usize) -> Self {
+        assert!(part_size > 0, "parts have to hold at least one byte");
+        self.part_size = part_size;
+        self
+    }
+
+    /// Sets the maximum number of parts uploaded at once, fewer while the rate limit budget is
+    /// lower.
+    ///
+    /// # Panics
+    /// Panics if `concurrency` is zero.
+    #[must_use]
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        assert!(
+            concurrency > 0,
+            "an upload has to upload at least one part at once"
+        );
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Sets how often a part is tried before the upload fails, 3 times by default.
+    ///
+    /// # Panics
+    /// Panics if `attempts` is zero.
+    #[must_use]
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        assert!(attempts > 0, "parts have to be tried at least once");
+        self.attempts = attempts;
+        self
+    }
+
+    /// Sets the wait before the first retry of a part, doubled for every further one, 500ms by
+    /// default.
+    #[must_use]
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Waits between retries on `runtime` instead of Tokio, see [`runtime`](crate::runtime).
+    #[must_use]
+    pub fn runtime(mut self, runtime: impl Runtime + 'static) -> Self {
+        self.runtime = Arc::new(runtime);
+        self
+    }
+
+    /// Uploads everything read from `body` through `parts`.
+    ///
+    /// A body which is empty is uploaded as a single empty part.
+    ///
+    /// # Errors
+    /// Returns the error of starting the upload, reading the body, the part which failed on its
+    /// last attempt, labelled with its number, or completing the upload. The upload is aborted
+    /// unless it failed to start.
+    pub async fn run<P: PartUploads + ?Sized>(
+        &self,
+        parts: &P,
+        body: impl AsyncRead + Unpin,
+    ) -> ResultType<P::Output> {
+        let upload = parts.initiate().await?;
+        let result = match self.upload_parts(parts, &upload, body).await {
+            Ok(completed) => parts.complete(&upload, completed).await,
+            Err(error) => Err(error),
+        };
+        if result.is_err() {
+            let _ = parts.abort(&upload).await;
+        }
+        result
+    }
+
+    /// Reads `body` in parts and uploads them, returning the uploaded parts in order.
+    ///
+    /// The next part is read while the parts before it are uploaded.
+    async fn upload_parts<P: PartUploads + ?Sized>(
+        &self,
+        parts: &P,
+        upload: &P::Upload,
+        body: impl AsyncRead + Unpin,
+    ) -> ResultType<Vec<CompletedPart<P::Part>>> {
+        let mut in_flight = FuturesUnordered::new();
+        let mut completed = Vec::new();
+        let mut number = 0;
+        // The body while it isn't being read, until it has been read to its end.
+        let mut body = Some(body);
+        let mut reading = None;
+        loop {
+            if reading.is_none() && body.is_some() {
+                let budget = rate_limit::concurrency(parts.state(), self.concurrency);
+                if budget < self.concurrency {
+                    runtime::yield_now().await;
+                }
+                if in_flight.len() < budget {
+                    if let Some(mut rest) = body.take() {
+                        let size = self.part_size;
+                        reading = Some(Box::pin(async move {
+                            let data = read_part(&mut rest, size).await;
+                            (rest, data)
+                        }));
+                    }
+                }
+            }
+            let step = match reading.as_mut() {
+                Some(read) if !in_flight.is_empty() => match select(read, in_flight.next()).await {
+                    Either::Left((read, _)) => Either::Left(read),
+                    Either::Right((part, _)) => Either::Right(part),
+                },
+                Some(read) => Either::Left(read.await),
+                None => Either::Right(in_flight.next().await),
+            };
+            match step {
+                Either::Left((rest, data)) => {
+                    reading = None;
+                    let data = data?;
+                    if data.len() == self.part_size {
+                        body = Some(rest);
+                    }
+                    if !data.is_empty() || number == 0 {
+                        number += 1;
+                        in_flight.push(self.upload_part(parts, upload, number, data));
+                    }
+                }
+                Either::Right(Some(part)) => completed.push(part?),
+                Either::Right(None) => break,
+            }
+        }
+        completed.sort_by_key(|part: &CompletedPart<P::Part>| part.number);
+        Ok(completed)
+    }
+
+    /// Uploads a part, retrying it with backoff until it succeeds, fails with an error which isn't
+    /// [transient](crate::retry::is_transient) or runs out of attempts.
+    async fn upload_part<P: PartUploads + ?Sized>(
+        &self,
+        parts: &P,
+        upload: &P::Upload,
+        number: u32,
+        data: Bytes,
+    ) -> ResultType<CompletedPart<P::Part>> {
+        let mut backoff = self.backoff;
+        for attempt in 1..self.attempts {
+            match parts.upload_part(upload, number, data.clone()).await {
+                Ok(receipt) => return Ok(CompletedPart { number, receipt }),
+                Err(error) if !retry::is_transient(&error) => {
+                    return Err(error.context(format!("uploading part {number}")));
+                }
+                Err(error) => {
+                    trace::retry(None, attempt + 1, &format_args!("part {number}: {error}"));
+                }
+            }
+            self.runtime.sleep(backoff).await;
+            backoff = backoff.saturating_mul(2);
+        }
+        match parts.upload_part(upload, number, data).await {
+            Ok(receipt) => Ok(CompletedPart { number, receipt }),
+            Err(error) => Err(error.context(format!("uploading part {number}"))),
+        }
+    }
+}
+
+/// Reads up to `size` bytes from `body`, fewer only at its end.
+async fn read_part(body: &mut (impl AsyncRead + Unpin), size: usize) -> ResultType<Bytes> {
+    let mut part = BytesMut::with_capacity(size);
+    while part.len() < size {
+        let mut limited = (&mut *body).take((size - part.len()) as u64);
+        if limited.read_buf(&mut part).await? == 0 {
+            break;
+        }
+    }
+    Ok(part.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    use bytes::Bytes;
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    use super::{CompletedPart, MultipartUpload, PartUploads};
+    use crate::{rate_limit::TokenBucket, ApiState, Error, ResultType};
+
+    /// Uploads parts into memory, failing the attempts listed in `failures` and `rejections`.
+    #[derive(Default)]
+    struct Memory {
+        /// State whose rate limit budget applies.
+        state: ApiState,
+        /// Part numbers whose next attempt fails transiently, once per entry.
+        failures: RefCell<Vec<u32>>,
+        /// Part numbers whose next attempt is rejected for good, once per entry.
+        rejections: RefCell<Vec<u32>>,
+        /// Number of parts being uploaded.
+        active: Cell<usize>,
+        /// Highest number of parts uploaded at once.
+        peak: Cell<usize>,
+        /// Uploaded parts.
+        parts: RefCell<Vec<(u32, Bytes)>>,
+        /// Calls made besides uploading parts.
+        calls: RefCell<Vec<&'static str>>,
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl PartUploads for Memory {
+        type Upload = &'static str;
+        type Part = usize;
+        type Output = Vec<u32>;
+
+        fn state(&self) -> &ApiState {
+            &self.state
+        }
+
+        async fn initiate(&self) -> ResultType<Self::Upload> {
+            self.calls.borrow_mut().push("initiate");
+            Ok("upload")
+        }
+
+        async fn upload_part(
+            &self,
+            upload: &Self::Upload,
+            number: u32,
+            data: Bytes,
+        ) -> ResultType<Self::Part> {
+            assert_eq!(*upload, "upload");
+            self.active.set(self.active.get() + 1);
+            self.peak.set(self.peak.get().max(self.active.get()));
+            tokio::task::yield_now().await;
+            self.active.set(self.active.get() - 1);
+            let mut rejections = self.rejections.borrow_mut();
+            if let Some(index) = rejections.iter().position(|rejection| *rejection == number) {
+                rejections.remove(index);
+                return Err(Error::InvalidToken);
+            }
+            let mut failures = self.failures.borrow_mut();
+            if let Some(index) = failures.iter().position(|failure| *failure == number) {
+                failures.remove(index);
+                return Err(Error::Transport("connection reset".into()));
+            }
+            let length = data.len();
+            self.parts.borrow_mut().push((number, data));
+            Ok(length)
+        }
+
+        async fn complete(
+            &self,
+            _upload: &Self::Upload,
+            parts: Vec<CompletedPart<Self::Part>>,
+        ) -> ResultType<Self::Output> {
+            self.calls.borrow_mut().push("complete");
+            Ok(parts.iter().map(|part| part.number).collect())
+        }
+
+        async fn abort(&self, _upload: &Self::Upload) -> ResultType<()> {
+            self.calls.borrow_mut().push("abort");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn uploads_parts() {
+        tokio_test::block_on(async {
+            let upload = MultipartUpload::new()
+                .part_size(4)
+                .concurrency(2)
+                .backoff(Duration::from_millis(1));
+
+            let memory = Memory {
+                failures: RefCell::new(vec![2, 3, 3]),
+                ..Memory::default()
+            };
+            let numbers = upload.run(&memory, &b"0123456789"[..]).await.unwrap();
+            assert_eq!(numbers, [1, 2, 3]);
+            let mut parts = memory.parts.into_inner();
+            parts.sort();
+            assert_eq!(
+                parts,
+                [(1, "0123".into()), (2, "4567".into()), (3, "89".into())]
+            );
+            assert_eq!(memory.calls.into_inner(), ["initiate", "complete"]);
+
+            let memory = Memory::default();
+            assert_eq!(upload.run(&memory, &b"0123"[..]).await.unwrap(), [1]);
+            assert_eq!(upload.run(&memory, &b""[..]).await.unwrap(), [1]);
+
+            let memory = Memory {
+                failures: RefCell::new(vec![2; 3]),
+                ..Memory::default()
+            };
+            let error = upload.run(&memory, &b"0123456789"[..]).await.unwrap_err();
+            assert_eq!(error.labels().collect::<Vec<_>>(), ["uploading part 2"]);
+            assert_eq!(memory.calls.into_inner(), ["initiate", "abort"]);
+
+            let memory = Memory {
+                failures: RefCell::new(vec![2]),
+                rejections: RefCell::new(vec![2]),
+                ..Memory::default()
+            };
+            let error = upload.run(&memory, &b"0123456789"[..]).await.unwrap_err();
+            assert!(matches!(error.root(), Error::InvalidToken), "{error}");
+            assert_eq!(*memory.failures.borrow(), [2]);
+        });
+    }
+
+    /// Reads a byte at a time, pending before every one, recording the number of parts being
+    /// uploaded as every byte is read.
+    struct Slow<'a> {
+        /// Bytes left to read.
+        data: &'static [u8],
+        /// Whether the next read returns a byte.
+        ready: bool,
+        /// Number of parts being uploaded.
+        active: &'a Cell<usize>,
+        /// Number of parts being uploaded as each byte was read.
+        seen: Vec<usize>,
+    }
+
+    impl AsyncRead for Slow<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            if !self.ready {
+                self.ready = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            self.ready = false;
+            let active = self.active.get();
+            self.seen.push(active);
+            let length = self.data.len().min(buf.remaining()).min(1);
+            buf.put_slice(&self.data[..length]);
+            self.data = &self.data[length..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn reads_while_uploading() {
+        tokio_test::block_on(async {
+            let upload = MultipartUpload::new().part_size(2).concurrency(2);
+            let memory = Memory::default();
+            let mut body = Slow {
+                data: b"012345",
+                ready: false,
+                active: &memory.active,
+                seen: Vec::new(),
+            };
+            let numbers = upload.run(&memory, &mut body).await.unwrap();
+            assert_eq!(numbers, [1, 2, 3]);
+            // The second part starts being read while the first one is uploaded.
+            assert_eq!(body.seen[2], 1);
+        });
+    }
+
+    #[test]
+    fn follows_rate_limit_budget() {
+        tokio_test::block_on(async {
+            let upload = MultipartUpload::new().part_size(2).concurrency(4);
+            let memory = Memory::default();
+            upload.run(&memory, &b"0123456789"[..]).await.unwrap();
+            assert_eq!(memory.peak.get(), 4);
+
+            let memory = Memory {
+                state: ApiState::new().rate_limiter(TokenBucket::new(1000.0, 1)),
+                ..Memory::default()
+            };
+            upload.run(&memory, &b"0123456789"[..]).await.unwrap();
+            assert_eq!(memory.peak.get(), 1);
+            assert_eq!(memory.parts.borrow().len(), 5);
+        });
+    }
+}
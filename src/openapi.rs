@@ -0,0 +1,965 @@
+//! Generating clients from `OpenAPI` 3.x documents, and documents from clients.
+//!
+//! A [`Generator`] reads an `OpenAPI` document in JSON and emits Rust source declaring a struct
+//! with an [`api!`](crate::api) endpoint per operation, and serde models for the schemas of the
+//! document. It is meant to run in a build script, with this crate as a build dependency with the
+//! `openapi` feature, and the output included into a module of its own, as it imports the `api!`
+//! macro:
+//! ```rust,no_run
+//! // build.rs
+//! use std::{env, fs, path::Path};
+//!
+//! use api_client::openapi::Generator;
+//!
+//! fn main() {
+//!     println!("cargo:rerun-if-changed=petstore.json");
+//!     let document = fs::read_to_string("petstore.json").unwrap();
+//!     let output = Path::new(&env::var("OUT_DIR").unwrap()).join("petstore.rs");
+//!     Generator::new(&document)
+//!         .unwrap()
+//!         .name("PetStore")
+//!         .write_to(output)
+//!         .unwrap();
+//! }
+//! ```
+//! ```rust,ignore
+//! // src/lib.rs
+//! pub mod petstore {
+//!     include!(concat!(env!("OUT_DIR"), "/petstore.rs"));
+//! }
+//! ```
+//!
+//! The generated struct has a `base_url` field, which defaults to the first server of the
+//! document. Operations become endpoints named after their `operationId`:
+//! - Path parameters and required query parameters are parameters of the endpoint. Other query
+//!   parameters and headers are left to the `_with` variant of the endpoint.
+//! - JSON and form request bodies are taken as `request: Json<T>` and `request: Form<T>`.
+//! - The first `2xx` response is returned as `Json<T>`, `String` or `Bytes` depending on its
+//!   content type, or as the `StatusCode` if it has no content.
+//!
+//! Object schemas become structs, string enums become enums and other schemas type aliases.
+//! Inline object schemas are named after the operation or property they belong to, and
+//! `oneOf` and `anyOf` schemas are kept as [`serde_json::Value`]. The generated models derive
+//! `serde::Serialize` and `serde::Deserialize`, so the crate including them depends on serde with
+//! the `derive` feature.
+//!
+//! The [`export`] module goes the other way, describing the endpoints declared with the `api!`
+//! macro as an `OpenAPI` document.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fmt::Write as _,
+    path::Path,
+};
+
+use serde_json::{Map, Value};
+
+use crate::patch;
+
+pub mod export;
+
+/// HTTP methods of the operations of a path item, in the order they are generated.
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Keywords of Rust, which get a trailing `_` when used as names.
+const KEYWORDS: [&str; 51] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Names of the prelude which models can't take, as the generated code uses them.
+const RESERVED_TYPES: [&str; 6] = ["Box", "Option", "Result", "String", "Value", "Vec"];
+
+/// Reason an `OpenAPI` document could not be turned into a client.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum GenerateError {
+    /// The document is not valid JSON.
+    Json(serde_json::Error),
+    /// The document is not an `OpenAPI` 3.x document, or uses something the generator can't
+    /// express, described by the message.
+    Unsupported(String),
+    /// The generated source could not be written.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerateError::Json(error) => write!(f, "invalid OpenAPI document: {error}"),
+            GenerateError::Unsupported(reason) => {
+                write!(f, "unsupported OpenAPI document: {reason}")
+            }
+            GenerateError::Io(error) => write!(f, "failed to write the generated client: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GenerateError::Json(error) => Some(error),
+            GenerateError::Io(error) => Some(error),
+            GenerateError::Unsupported(_) => None,
+        }
+    }
+}
+
+/// Generator of a client from an `OpenAPI` document, see [`openapi`](crate::openapi).
+#[derive(Debug, Clone)]
+pub struct Generator {
+    /// The parsed document.
+    document: Value,
+    /// Name of the generated struct, derived from the title of the document if unset.
+    name: Option<String>,
+    /// Path the generated code refers to this crate by.
+    crate_path: String,
+}
+
+impl Generator {
+    /// Reads the `OpenAPI` `document`, given as JSON.
+    ///
+    /// # Errors
+    /// Returns an error if `document` is not valid JSON or not an `OpenAPI` 3.x document.
+    pub fn new(document: &str) -> Result<Self, GenerateError> {
+        let document: Value = serde_json::from_str(document).map_err(GenerateError::Json)?;
+        let version = document
+            .get("openapi")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if !version.starts_with("3.") {
+            return Err(GenerateError::Unsupported(format!(
+                "expected an OpenAPI 3.x document, got version `{version}`"
+            )));
+        }
+        Ok(Self {
+            document,
+            name: None,
+            crate_path: "api_client".to_string(),
+        })
+    }
+
+    /// Sets the name of the generated struct, the title of the document in `UpperCamelCase` by
+    /// default.
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the path the generated code refers to this crate by, `api_client` by default, e.g.
+    /// when it is re-exported by another crate.
+    #[must_use]
+    pub fn crate_path(mut self, path: impl Into<String>) -> Self {
+        self.crate_path = path.into();
+        self
+    }
+
+    /// Returns the generated source.
+    ///
+    /// # Errors
+    /// Returns an error if the document uses something the generator can't express.
+    pub fn generate(&self) -> Result<String, GenerateError> {
+        let info = self.document.get("info");
+        let title = info
+            .and_then(|info| info.get("title"))
+            .and_then(Value::as_str)
+            .unwrap_or("API");
+        let version = info
+            .and_then(|info| info.get("version"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let name = self.name.clone().unwrap_or_else(|| type_name(title));
+
+        let mut models = Models::new(&self.document);
+        if let Some(schemas) = self
+            .document
+            .pointer("/components/schemas")
+            .and_then(Value::as_object)
+        {
+            for (schema_name, schema) in schemas {
+                models.define(&type_name(schema_name), schema)?;
+            }
+        }
+
+        let mut endpoints = Vec::new();
+        if let Some(paths) = self.document.get("paths").and_then(Value::as_object) {
+            for (path, item) in paths {
+                let item = models.resolve(item);
+                for method in METHODS {
+                    if let Some(operation) = item.get(method) {
+                        endpoints.push(endpoint(&mut models, path, method, item, operation)?);
+                    }
+                }
+            }
+        }
+
+        let mut source = String::new();
+        let _ = writeln!(
+            source,
+            "// Generated by api-client from the OpenAPI document of {title} {version}."
+        );
+        let _ = writeln!(source, "// Changes are lost when it is generated again.");
+        let _ = writeln!(source);
+        let _ = writeln!(source, "use {}::api;", self.crate_path);
+        for model in models.sources.values() {
+            let _ = writeln!(source);
+            source.push_str(model);
+        }
+        let _ = writeln!(source);
+        let _ = writeln!(source, "api! {{");
+        doc_comment(&mut source, "    ", Some(title));
+        let _ = writeln!(source, "    pub struct {name} {{");
+        let _ = writeln!(
+            source,
+            "        /// URL the paths of the endpoints are relative to."
+        );
+        let _ = writeln!(
+            source,
+            "        pub base_url: String = {:?}.to_string(),",
+            self.server_url()
+        );
+        let _ = writeln!(source, "    }}");
+        let _ = writeln!(source, "}}");
+        let _ = writeln!(source);
+        let _ = writeln!(source, "impl {name} {{");
+        let _ = writeln!(source, "    api! {{");
+        let _ = writeln!(source, "        #![endpoints]");
+        let _ = writeln!(source);
+        source.push_str(&endpoints.join("\n"));
+        let _ = writeln!(source, "    }}");
+        let _ = writeln!(source, "}}");
+        Ok(source)
+    }
+
+    /// Writes the generated source to the file at `path`, replacing it.
+    ///
+    /// # Errors
+    /// Returns an error if the document uses something the generator can't express or the file
+    /// could not be written.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), GenerateError> {
+        std::fs::write(path, self.generate()?).map_err(GenerateError::Io)
+    }
+
+    /// Returns the URL of the first server with its variables set to their defaults, without a
+    /// trailing slash.
+    fn server_url(&self) -> String {
+        let Some(server) = self.document.pointer("/servers/0") else {
+            return String::new();
+        };
+        let mut url = server
+            .get("url")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        if let Some(variables) = server.get("variables").and_then(Value::as_object) {
+            for (name, variable) in variables {
+                if let Some(default) = variable.get("default").and_then(Value::as_str) {
+                    url = url.replace(&format!("{{{name}}}"), default);
+                }
+            }
+        }
+        url.trim_end_matches('/').to_string()
+    }
+}
+
+/// Models generated for the schemas of a document.
+struct Models<'a> {
+    /// The document, which references are resolved in.
+    document: &'a Value,
+    /// Names of the component schemas by their reference.
+    names: BTreeMap<String, String>,
+    /// Sources of the generated models by their name.
+    sources: BTreeMap<String, String>,
+}
+
+impl<'a> Models<'a> {
+    /// Creates the models of `document`, reserving the names of its component schemas.
+    fn new(document: &'a Value) -> Self {
+        let names = document
+            .pointer("/components/schemas")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+            .map(|(name, _)| (format!("#/components/schemas/{name}"), type_name(name)))
+            .collect();
+        Self {
+            document,
+            names,
+            sources: BTreeMap::new(),
+        }
+    }
+
+    /// Follows the `$ref` of `value`, if any, within the document.
+    fn resolve<'v>(&self, value: &'v Value) -> &'v Value
+    where
+        'a: 'v,
+    {
+        match value.get("$ref").and_then(Value::as_str) {
+            Some(reference) => match reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.document.pointer(pointer))
+            {
+                Some(target) => self.resolve(target),
+                None => value,
+            },
+            None => value,
+        }
+    }
+
+    /// Returns the Rust type of `schema`, defining a model named `inline_name` for inline
+    /// object and enum schemas.
+    fn type_of(&mut self, schema: &Value, inline_name: &str) -> Result<String, GenerateError> {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            if let Some(name) = self.names.get(reference) {
+                return Ok(name.clone());
+            }
+            let target = self.resolve(schema);
+            if std::ptr::eq(target, schema) {
+                return Err(GenerateError::Unsupported(format!(
+                    "unresolved reference `{reference}`"
+                )));
+            }
+            return self.type_of(target, inline_name);
+        }
+        let ty = match schema_type(schema) {
+            Some("string") if schema.get("enum").is_some() => {
+                self.define(inline_name, schema)?;
+                inline_name.to_string()
+            }
+            Some("string") => "String".to_string(),
+            Some("integer") => match schema.get("format").and_then(Value::as_str) {
+                Some("int32") => "i32".to_string(),
+                _ => "i64".to_string(),
+            },
+            Some("number") => match schema.get("format").and_then(Value::as_str) {
+                Some("float") => "f32".to_string(),
+                _ => "f64".to_string(),
+            },
+            Some("boolean") => "bool".to_string(),
+            Some("array") => {
+                let items = schema.get("items").unwrap_or(&Value::Null);
+                format!(
+                    "Vec<{}>",
+                    self.type_of(items, &format!("{inline_name}Item"))?
+                )
+            }
+            Some("object") if has_properties(schema) => {
+                self.define(inline_name, schema)?;
+                inline_name.to_string()
+            }
+            Some("object") => self.map_type(schema, inline_name)?,
+            _ => "serde_json::Value".to_string(),
+        };
+        Ok(
+            if schema.get("nullable").and_then(Value::as_bool) == Some(true) {
+                format!("Option<{ty}>")
+            } else {
+                ty
+            },
+        )
+    }
+
+    /// Returns the Rust type of an object `schema` without properties, a map if it declares the
+    /// schema of its values.
+    fn map_type(&mut self, schema: &Value, inline_name: &str) -> Result<String, GenerateError> {
+        match schema.get("additionalProperties") {
+            Some(values @ Value::Object(object)) if !object.is_empty() => Ok(format!(
+                "std::collections::HashMap<String, {}>",
+                self.type_of(values, &format!("{inline_name}Value"))?
+            )),
+            _ => Ok("serde_json::Value".to_string()),
+        }
+    }
+
+    /// Returns the type of an endpoint parameter with `schema`.
+    fn param_type(&self, schema: &Value) -> &'static str {
+        let schema = self.resolve(schema);
+        match schema_type(schema) {
+            Some("integer") => match schema.get("format").and_then(Value::as_str) {
+                Some("int32") => "i32",
+                _ => "i64",
+            },
+            Some("number") => match schema.get("format").and_then(Value::as_str) {
+                Some("float") => "f32",
+                _ => "f64",
+            },
+            Some("boolean") => "bool",
+            _ => "&str",
+        }
+    }
+
+    /// Defines the model `name` for `schema`.
+    fn define(&mut self, name: &str, schema: &Value) -> Result<(), GenerateError> {
+        if self.sources.contains_key(name) {
+            return Ok(());
+        }
+        // Reserved first, so recursive schemas don't define it again.
+        self.sources.insert(name.to_string(), String::new());
+        let mut source = String::new();
+        doc_comment(
+            &mut source,
+            "",
+            schema
+                .get("description")
+                .or_else(|| schema.get("title"))
+                .and_then(Value::as_str),
+        );
+        let values = schema.get("enum").and_then(Value::as_array);
+        let (properties, required) = self.properties(schema);
+        if let (Some("string"), Some(values)) = (schema_type(schema), values) {
+            let _ = writeln!(
+                source,
+                "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]"
+            );
+            let _ = writeln!(source, "pub enum {name} {{");
+            for value in values.iter().filter_map(Value::as_str) {
+                let variant = match type_name(value) {
+                    variant if variant.starts_with(|c: char| c.is_ascii_digit()) => {
+                        format!("V{variant}")
+                    }
+                    variant if variant.is_empty() => "Empty".to_string(),
+                    variant => variant,
+                };
+                let _ = writeln!(source, "    #[serde(rename = {value:?})]");
+                let _ = writeln!(source, "    {variant},");
+            }
+            let _ = writeln!(source, "}}");
+        } else if !properties.is_empty() {
+            let _ = writeln!(
+                source,
+                "#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]"
+            );
+            let _ = writeln!(source, "pub struct {name} {{");
+            for (original, property) in properties {
+                let field = field_name(&original);
+                let mut ty = self.type_of(&property, &format!("{name}{}", type_name(&original)))?;
+                if ty == name {
+                    ty = format!("Box<{ty}>");
+                }
+                doc_comment(
+                    &mut source,
+                    "    ",
+                    property.get("description").and_then(Value::as_str),
+                );
+                if field != original {
+                    let _ = writeln!(source, "    #[serde(rename = {original:?})]");
+                }
+                if required.contains(&original) {
+                    let _ = writeln!(source, "    pub {field}: {ty},");
+                } else {
+                    let _ = writeln!(
+                        source,
+                        "    #[serde(default, skip_serializing_if = \"Option::is_none\")]"
+                    );
+                    let ty = if ty.starts_with("Option<") {
+                        ty
+                    } else {
+                        format!("Option<{ty}>")
+                    };
+                    let _ = writeln!(source, "    pub {field}: {ty},");
+                }
+            }
+            let _ = writeln!(source, "}}");
+        } else {
+            let ty = match schema_type(schema) {
+                Some("object") => self.map_type(schema, name)?,
+                _ => self.type_of(schema, &format!("{name}Value"))?,
+            };
+            let _ = writeln!(source, "pub type {name} = {ty};");
+        }
+        self.sources.insert(name.to_string(), source);
+        Ok(())
+    }
+
+    /// Returns the properties of an object `schema` and the names of the required ones, merging
+    /// the schemas it lists in `allOf`.
+    fn properties(&self, schema: &Value) -> (Map<String, Value>, BTreeSet<String>) {
+        let schema = self.resolve(schema);
+        let mut properties = Map::new();
+        let mut required = BTreeSet::new();
+        for part in schema
+            .get("allOf")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let (part_properties, part_required) = self.properties(part);
+            properties.extend(part_properties);
+            required.extend(part_required);
+        }
+        if let Some(own) = schema.get("properties").and_then(Value::as_object) {
+            properties.extend(own.clone());
+        }
+        required.extend(
+            schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(String::from),
+        );
+        (properties, required)
+    }
+}
+
+/// Returns the `api!` declaration of an operation.
+fn endpoint(
+    models: &mut Models<'_>,
+    path: &str,
+    method: &str,
+    item: &Value,
+    operation: &Value,
+) -> Result<String, GenerateError> {
+    let name = match operation.get("operationId").and_then(Value::as_str) {
+        Some(id) => field_name(id),
+        None => field_name(&format!("{method} {path}")),
+    };
+    let mut params = vec!["&self".to_string()];
+    let mut notes = Vec::new();
+    if let Some(body) = operation.get("requestBody") {
+        let body = models.resolve(body);
+        match request_kind(models, body, &format!("{}Request", type_name(&name)))? {
+            Ok(kind) => params.push(format!("request: {kind}")),
+            Err(media_types) => notes.push(format!(
+                "The request body ({media_types}) can be set with `{name}_with`."
+            )),
+        }
+    }
+    let (url, optional) = url(models, path, item, operation, &mut params);
+    if !optional.is_empty() {
+        let noun = if optional.len() == 1 {
+            "parameter"
+        } else {
+            "parameters"
+        };
+        notes.push(format!(
+            "The query {noun} {} can be set with `{name}_with`.",
+            optional.join(", ")
+        ));
+    }
+    let returns = returns(models, operation, &format!("{}Response", type_name(&name)))?;
+
+    let mut source = String::new();
+    let summary = operation
+        .get("summary")
+        .or_else(|| operation.get("description"))
+        .and_then(Value::as_str);
+    doc_comment(&mut source, "        ", summary);
+    if !notes.is_empty() {
+        if summary.is_some() {
+            let _ = writeln!(source, "        ///");
+        }
+        doc_comment(&mut source, "        ", Some(&notes.join(" ")));
+    }
+    if operation.get("deprecated").and_then(Value::as_bool) == Some(true) {
+        let _ = writeln!(source, "        #[deprecated]");
+    }
+    let _ = writeln!(
+        source,
+        "        pub fn {name}({}) -> {returns} {{",
+        params.join(", ")
+    );
+    let _ = writeln!(
+        source,
+        "            {} {:?}, self.base_url",
+        method.to_ascii_uppercase(),
+        format!("{{}}{url}")
+    );
+    let _ = writeln!(source, "        }}");
+    Ok(source)
+}
+
+/// Returns the request kind of a request `body`, e.g. `Json<T>`, or its media types if it has
+/// none which the generator supports.
+fn request_kind(
+    models: &mut Models<'_>,
+    body: &Value,
+    inline_name: &str,
+) -> Result<Result<String, String>, GenerateError> {
+    let content = body.get("content").and_then(Value::as_object);
+    if let Some((media_type, schema)) = content.and_then(find_json) {
+        if media_type == patch::JSON_PATCH_CONTENT_TYPE {
+            return Ok(Ok("JsonPatch".to_string()));
+        }
+        let ty = models.type_of(schema, inline_name)?;
+        return Ok(Ok(match media_type {
+            "application/json" => format!("Json<{ty}>"),
+            patch::MERGE_PATCH_CONTENT_TYPE => format!("MergePatch<{ty}>"),
+            _ => format!("Json<{ty}> as \"{media_type}\""),
+        }));
+    }
+    if let Some(media) =
+        content.and_then(|content| content.get("application/x-www-form-urlencoded"))
+    {
+        let schema = media.get("schema").unwrap_or(&Value::Null);
+        return Ok(Ok(format!(
+            "Form<{}>",
+            models.type_of(schema, inline_name)?
+        )));
+    }
+    let media_types = content
+        .map(|content| content.keys().cloned().collect::<Vec<_>>().join(", "))
+        .unwrap_or_default();
+    Ok(Err(media_types))
+}
+
+/// Returns the URL template of an operation, adding its path parameters and required query
+/// parameters to `params`, and the names of the query parameters left out of it.
+fn url(
+    models: &Models<'_>,
+    path: &str,
+    item: &Value,
+    operation: &Value,
+    params: &mut Vec<String>,
+) -> (String, Vec<String>) {
+    let mut parameters: Vec<&Value> = Vec::new();
+    let declared = [item, operation]
+        .into_iter()
+        .filter_map(|level| level.get("parameters").and_then(Value::as_array))
+        .flatten();
+    for parameter in declared {
+        let parameter = models.resolve(parameter);
+        let key =
+            |parameter: &Value| (parameter.get("name").cloned(), parameter.get("in").cloned());
+        // Parameters of the operation override those of the path with the same name and location.
+        match parameters
+            .iter_mut()
+            .find(|known| key(known) == key(parameter))
+        {
+            Some(known) => *known = parameter,
+            None => parameters.push(parameter),
+        }
+    }
+
+    let mut url = path.to_string();
+    let mut query = Vec::new();
+    let mut optional = Vec::new();
+    for parameter in parameters {
+        let original = parameter
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let schema = parameter.get("schema").unwrap_or(&Value::Null);
+        let required = parameter.get("required").and_then(Value::as_bool) == Some(true);
+        let param = field_name(original);
+        match parameter.get("in").and_then(Value::as_str) {
+            Some("path") => {
+                url = url.replace(&format!("{{{original}}}"), &format!("{{{param}}}"));
+                params.push(format!("{param}: {}", models.param_type(schema)));
+            }
+            Some("query") if required && is_scalar(models.resolve(schema)) => {
+                query.push(format!("{original}={{{param}}}"));
+                params.push(format!("{param}: {}", models.param_type(schema)));
+            }
+            Some("query") => optional.push(format!("`{original}`")),
+            _ => {}
+        }
+    }
+    if !query.is_empty() {
+        url = format!("{url}?{}", query.join("&"));
+    }
+    (url, optional)
+}
+
+/// Returns the return kind of an operation, from its first `2xx` response.
+fn returns(
+    models: &mut Models<'_>,
+    operation: &Value,
+    inline_name: &str,
+) -> Result<String, GenerateError> {
+    let responses = operation.get("responses").and_then(Value::as_object);
+    let response = responses.and_then(|responses| {
+        responses
+            .iter()
+            .find(|(status, _)| status.starts_with('2'))
+            .or_else(|| responses.get_key_value("default"))
+            .map(|(_, response)| models.resolve(response).clone())
+    });
+    let content = match response
+        .as_ref()
+        .and_then(|response| response.get("content"))
+        .and_then(Value::as_object)
+    {
+        Some(content) if !content.is_empty() => content,
+        _ => return Ok("StatusCode".to_string()),
+    };
+    if let Some((_, schema)) = find_json(content) {
+        return Ok(format!("Json<{}>", models.type_of(schema, inline_name)?));
+    }
+    if content
+        .keys()
+        .any(|media_type| media_type.starts_with("text/"))
+    {
+        return Ok("String".to_string());
+    }
+    Ok("Bytes".to_string())
+}
+
+/// Returns the `type` of `schema`, `object` for schemas with properties or `allOf` and without
+/// one.
+fn schema_type(schema: &Value) -> Option<&str> {
+    match schema.get("type") {
+        Some(Value::String(ty)) => Some(ty),
+        // OpenAPI 3.1 lists `null` among the types of nullable schemas.
+        Some(Value::Array(types)) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|&ty| ty != "null"),
+        _ if has_properties(schema) => Some("object"),
+        _ => None,
+    }
+}
+
+/// Returns whether `schema` declares properties, itself or in `allOf`.
+fn has_properties(schema: &Value) -> bool {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    properties.is_some_and(|properties| !properties.is_empty()) || schema.get("allOf").is_some()
+}
+
+/// Returns whether `schema` is a string, number or boolean, which can be formatted into a URL.
+fn is_scalar(schema: &Value) -> bool {
+    matches!(
+        schema_type(schema),
+        Some("string" | "integer" | "number" | "boolean")
+    )
+}
+
+/// Returns the JSON media type of `content` without parameters and its schema, if any.
+fn find_json(content: &Map<String, Value>) -> Option<(&str, &Value)> {
+    content.iter().find_map(|(media_type, media)| {
+        let media_type = media_type.split(';').next().unwrap_or_default().trim();
+        (media_type == "application/json" || media_type.ends_with("+json"))
+            .then(|| (media_type, media.get("schema").unwrap_or(&Value::Null)))
+    })
+}
+
+/// Appends `text` as a doc comment indented by `indent`.
+fn doc_comment(source: &mut String, indent: &str, text: Option<&str>) {
+    for line in text.into_iter().flat_map(str::lines) {
+        let line = line.trim_end();
+        if line.is_empty() {
+            let _ = writeln!(source, "{indent}///");
+        } else {
+            let _ = writeln!(source, "{indent}/// {line}");
+        }
+    }
+}
+
+/// Splits `name` into lowercase words at case changes and separators.
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let previous = index.checked_sub(1).map(|index| chars[index]);
+        let next = chars.get(index + 1);
+        let boundary = c.is_ascii_uppercase()
+            && previous.is_some_and(|previous| {
+                previous.is_ascii_lowercase()
+                    || previous.is_ascii_digit()
+                    || (previous.is_ascii_uppercase() && next.is_some_and(char::is_ascii_lowercase))
+            });
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c.to_ascii_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Returns `name` as a `snake_case` identifier of a field, parameter or endpoint.
+fn field_name(name: &str) -> String {
+    let mut field = words(name).join("_");
+    if field.is_empty() {
+        field.push_str("value");
+    }
+    if field.starts_with(|c: char| c.is_ascii_digit()) {
+        field.insert(0, '_');
+    }
+    if KEYWORDS.contains(&field.as_str()) {
+        field.push('_');
+    }
+    field
+}
+
+/// Returns `name` as an `UpperCamelCase` type name.
+fn type_name(name: &str) -> String {
+    let mut ty: String = words(name)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    if KEYWORDS.contains(&ty.as_str()) || RESERVED_TYPES.contains(&ty.as_str()) {
+        ty.push_str("Model");
+    }
+    ty
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{field_name, type_name, GenerateError, Generator};
+
+    const PETSTORE: &str = r##"{
+        "openapi": "3.0.3",
+        "info": {"title": "Swagger Petstore", "version": "1.0.0"},
+        "servers": [{"url": "https://{region}.petstore.example.com/v1/", "variables": {"region": {"default": "eu"}}}],
+        "paths": {
+            "/pets": {
+                "get": {
+                    "operationId": "listPets",
+                    "summary": "List all pets",
+                    "parameters": [
+                        {"name": "limit", "in": "query", "schema": {"type": "integer", "format": "int32"}},
+                        {"name": "kind", "in": "query", "required": true, "schema": {"$ref": "#/components/schemas/Kind"}}
+                    ],
+                    "responses": {
+                        "200": {"description": "A list of pets", "content": {"application/json": {"schema": {"type": "array", "items": {"$ref": "#/components/schemas/Pet"}}}}}
+                    }
+                },
+                "post": {
+                    "operationId": "createPet",
+                    "requestBody": {"content": {"application/json": {"schema": {"type": "object", "required": ["name"], "properties": {"name": {"type": "string"}}}}}},
+                    "responses": {"201": {"description": "Created"}}
+                }
+            },
+            "/settings": {
+                "patch": {
+                    "operationId": "updateSettings",
+                    "requestBody": {"content": {"application/merge-patch+json": {"schema": {"type": "object", "properties": {"theme": {"type": "string"}}}}}},
+                    "responses": {"204": {"description": "Updated"}}
+                },
+                "post": {
+                    "operationId": "patchSettings",
+                    "requestBody": {"content": {"application/json-patch+json": {"schema": {"type": "array", "items": {"type": "object"}}}}},
+                    "responses": {"204": {"description": "Patched"}}
+                }
+            },
+            "/pets/{petId}": {
+                "parameters": [{"$ref": "#/components/parameters/PetId"}],
+                "get": {
+                    "responses": {"200": {"description": "A pet", "content": {"application/json": {"schema": {"$ref": "#/components/schemas/Pet"}}}}}
+                },
+                "patch": {
+                    "operationId": "updatePet",
+                    "requestBody": {"content": {"application/vnd.petstore+json; charset=utf-8": {"schema": {"$ref": "#/components/schemas/Pet"}}}},
+                    "responses": {"204": {"description": "Updated"}}
+                },
+                "delete": {
+                    "operationId": "deletePet",
+                    "deprecated": true,
+                    "responses": {"204": {"description": "Deleted"}}
+                }
+            }
+        },
+        "components": {
+            "parameters": {
+                "PetId": {"name": "petId", "in": "path", "required": true, "schema": {"type": "integer", "format": "int64"}}
+            },
+            "schemas": {
+                "Kind": {"type": "string", "enum": ["cat", "dog", "hamster-like"]},
+                "Pet": {
+                    "description": "A pet of the store.",
+                    "type": "object",
+                    "required": ["id", "name", "kind"],
+                    "properties": {
+                        "id": {"type": "integer", "format": "int64"},
+                        "name": {"type": "string"},
+                        "kind": {"$ref": "#/components/schemas/Kind"},
+                        "type": {"type": "string", "nullable": true},
+                        "ownerName": {"type": "string", "description": "Name of the owner."},
+                        "tags": {"type": "object", "additionalProperties": {"type": "string"}},
+                        "parent": {"$ref": "#/components/schemas/Pet"}
+                    }
+                }
+            }
+        }
+    }"##;
+
+    #[test]
+    fn generates_clients() {
+        let source = Generator::new(PETSTORE).unwrap().generate().unwrap();
+        let expected = [
+            "use api_client::api;",
+            "    api! {\n        #![endpoints]\n",
+            "pub struct SwaggerPetstore {",
+            "pub base_url: String = \"https://eu.petstore.example.com/v1\".to_string(),",
+            "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]\npub enum Kind {",
+            "    #[serde(rename = \"hamster-like\")]\n    HamsterLike,",
+            "/// A pet of the store.\n#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]\npub struct Pet {",
+            "    pub id: i64,",
+            "    pub kind: Kind,",
+            "    /// Name of the owner.\n    #[serde(rename = \"ownerName\")]\n    #[serde(default, skip_serializing_if = \"Option::is_none\")]\n    pub owner_name: Option<String>,",
+            "    #[serde(rename = \"type\")]\n    #[serde(default, skip_serializing_if = \"Option::is_none\")]\n    pub type_: Option<String>,",
+            "    pub tags: Option<std::collections::HashMap<String, String>>,",
+            "    pub parent: Option<Box<Pet>>,",
+            "pub struct CreatePetRequest {\n    pub name: String,\n}",
+            "        /// List all pets\n        ///\n        /// The query parameter `limit` can be set with `list_pets_with`.\n        pub fn list_pets(&self, kind: &str) -> Json<Vec<Pet>> {\n            GET \"{}/pets?kind={kind}\", self.base_url\n        }",
+            "        pub fn create_pet(&self, request: Json<CreatePetRequest>) -> StatusCode {\n            POST \"{}/pets\", self.base_url",
+            "        pub fn get_pets_pet_id(&self, pet_id: i64) -> Json<Pet> {\n            GET \"{}/pets/{pet_id}\", self.base_url",
+            "        pub fn update_pet(&self, request: Json<Pet> as \"application/vnd.petstore+json\", pet_id: i64) -> StatusCode {",
+            "        pub fn update_settings(&self, request: MergePatch<UpdateSettingsRequest>) -> StatusCode {",
+            "        pub fn patch_settings(&self, request: JsonPatch) -> StatusCode {",
+            "        #[deprecated]\n        pub fn delete_pet(&self, pet_id: i64) -> StatusCode {",
+        ];
+        for expected in expected {
+            assert!(
+                source.contains(expected),
+                "missing\n{expected}\nin\n{source}"
+            );
+        }
+
+        let renamed = Generator::new(PETSTORE)
+            .unwrap()
+            .name("Pets")
+            .crate_path("client::api_client")
+            .generate()
+            .unwrap();
+        assert!(renamed.contains("use client::api_client::api;"));
+        assert!(renamed.contains("pub struct Pets {"));
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        assert!(matches!(
+            Generator::new(r#"{"swagger": "2.0"}"#),
+            Err(GenerateError::Unsupported(_))
+        ));
+        assert!(matches!(
+            Generator::new("openapi: 3.0.0"),
+            Err(GenerateError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn converts_names() {
+        assert_eq!(field_name("listPets"), "list_pets");
+        assert_eq!(field_name("HTTPServerURL"), "http_server_url");
+        assert_eq!(field_name("x-rate-limit"), "x_rate_limit");
+        assert_eq!(field_name("type"), "type_");
+        assert_eq!(field_name("2fa"), "_2fa");
+        assert_eq!(type_name("pet_store"), "PetStore");
+        assert_eq!(type_name("Result"), "ResultModel");
+    }
+}
@@ -0,0 +1,557 @@
+//! Exporting the endpoints of a client as an `OpenAPI` 3.1 document.
+//!
+//! Blocks of endpoints declared with the [`api!`](crate::api) macro which list their endpoints
+//! with `#![endpoints]` generate an `openapi_operations()` function describing them, or
+//! `admin_openapi_operations()` with `#![endpoints(admin)]`. A [`Document`] turns the operations
+//! of one or more blocks into the paths of an `OpenAPI` document, e.g. to publish a spec of what a
+//! client actually calls:
+//! - Parameters in the path of the URL template, and query parameters written as
+//!   `name={param}`, become parameters of the operation.
+//! - `Json<T>` and `Form<T>` request bodies and `Json<T>` responses are described by the JSON
+//!   schema of `T`, which is derived with [schemars](https://docs.rs/schemars). Types which
+//!   don't implement [`JsonSchema`](schemars::JsonSchema) get an empty schema, as do the
+//!   parameters and bodies of generic endpoints.
+//! - The doc comment of an endpoint becomes the summary and description of its operation.
+//!
+//! Schemas of named types are listed in the `components` of the document. Hosts and base URL
+//! parameters at the start of URL templates are left out of the paths; the servers of the
+//! document are set with [`Document::server`].
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, openapi::export::Document};
+//!
+//! #[derive(serde::Deserialize, schemars::JsonSchema)]
+//! struct Todo {
+//!     id: u32,
+//!     title: String,
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         #![endpoints]
+//!
+//!         /// Returns a todo.
+//!         fn todo(id: u32) -> Json<Todo> {
+//!            GET "https://example.com/todos/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! let document = Document::new("Example API", "1.0.0")
+//!     .server("https://example.com")
+//!     .operations(ExampleApi::openapi_operations())
+//!     .to_json();
+//! assert_eq!(document["paths"]["/todos/{id}"]["get"]["summary"], "Returns a todo.");
+//! ```
+
+use std::marker::PhantomData;
+
+use schemars::{gen::SchemaSettings, schema::Schema, JsonSchema};
+use serde_json::{json, Map, Value};
+
+pub use schemars::gen::SchemaGenerator;
+
+/// Version of the `OpenAPI` specification of exported documents.
+pub const VERSION: &str = "3.1.0";
+
+/// Returns the schema of a type, if it has one, adding the schemas it refers to to the generator.
+pub type SchemaFn = fn(&mut SchemaGenerator) -> Option<Schema>;
+
+/// An endpoint declared with the [`api!`](crate::api) macro, as listed by the generated
+/// `openapi_operations()` functions of blocks of endpoints.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    /// Name of the generated method.
+    name: &'static str,
+    /// HTTP method of the endpoint.
+    method: &'static str,
+    /// URL template as written in the declaration.
+    url: &'static str,
+    /// Lines of the doc comment of the endpoint.
+    docs: Vec<&'static str>,
+    /// Names and schemas of the parameters of the generated method, without the body.
+    params: Vec<(&'static str, SchemaFn)>,
+    /// Content of the request body, if the endpoint sends one.
+    request: Option<Content>,
+    /// Content of successful responses, if they have any.
+    response: Option<Content>,
+}
+
+impl Operation {
+    /// Describes the endpoint `name` sending `method` requests to the `url` template.
+    ///
+    /// Used internally in the [`api!`](crate::api) macro.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn new(name: &'static str, method: &'static str, url: &'static str) -> Self {
+        Self {
+            name,
+            method,
+            url,
+            docs: Vec::new(),
+            params: Vec::new(),
+            request: None,
+            response: None,
+        }
+    }
+
+    /// Adds a line of the doc comment of the endpoint.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn doc(mut self, line: &'static str) -> Self {
+        self.docs.push(line);
+        self
+    }
+
+    /// Adds a parameter of the generated method.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn param(mut self, name: &'static str, schema: SchemaFn) -> Self {
+        self.params.push((name, schema));
+        self
+    }
+
+    /// Sets the content of the request body.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn request(mut self, content: Option<Content>) -> Self {
+        self.request = content;
+        self
+    }
+
+    /// Sets the content of successful responses.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn response(mut self, content: Option<Content>) -> Self {
+        self.response = content;
+        self
+    }
+
+    /// Name of the generated method, used as the `operationId`.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// HTTP method of the endpoint, e.g. `GET`.
+    #[must_use]
+    pub fn method(&self) -> &'static str {
+        self.method
+    }
+
+    /// URL template as written in the declaration.
+    #[must_use]
+    pub fn url(&self) -> &'static str {
+        self.url
+    }
+
+    /// Returns the path of the operation in the document and its parameter objects.
+    fn parameters(&self, generator: &mut SchemaGenerator) -> (String, Vec<Value>) {
+        let (path, query) = split_url(self.url);
+        let mut parameters = Vec::new();
+        let mut template = String::new();
+        let mut rest = path;
+        while let Some((start, end)) = placeholder(rest) {
+            let name = rest[start + 1..end].split(':').next().unwrap_or_default();
+            template.push_str(&rest[..start]);
+            template.push('{');
+            template.push_str(name);
+            template.push('}');
+            if !name.is_empty() {
+                parameters.push(self.parameter(generator, name, name, "path"));
+            }
+            rest = &rest[end + 1..];
+        }
+        template.push_str(rest);
+        for pair in query.split('&') {
+            let param = pair
+                .split_once('=')
+                .and_then(|(key, value)| Some((key, value.strip_prefix('{')?.strip_suffix('}')?)));
+            if let Some((key, param)) = param {
+                let param = param.split(':').next().unwrap_or_default();
+                parameters.push(self.parameter(generator, key, param, "query"));
+            }
+        }
+        if template.is_empty() {
+            template.push('/');
+        }
+        (template, parameters)
+    }
+
+    /// Returns the parameter object of the method parameter `param`, sent as `name`.
+    fn parameter(
+        &self,
+        generator: &mut SchemaGenerator,
+        name: &str,
+        param: &str,
+        location: &str,
+    ) -> Value {
+        let schema = self
+            .params
+            .iter()
+            .find(|(known, _)| *known == param)
+            .and_then(|(_, schema)| schema(generator));
+        json!({
+            "name": name,
+            "in": location,
+            "required": true,
+            "schema": schema_value(schema),
+        })
+    }
+
+    /// Returns the operation object of the endpoint and its path in the document.
+    fn to_json(&self, generator: &mut SchemaGenerator) -> (String, Value) {
+        let (path, parameters) = self.parameters(generator);
+        let mut operation = Map::new();
+        operation.insert("operationId".to_string(), json!(self.name));
+        let docs = self.docs.iter().map(|line| line.trim()).collect::<Vec<_>>();
+        if let Some(summary) = docs.first().filter(|line| !line.is_empty()) {
+            operation.insert("summary".to_string(), json!(summary));
+        }
+        if docs.len() > 1 {
+            operation.insert("description".to_string(), json!(docs.join("\n").trim()));
+        }
+        if !parameters.is_empty() {
+            operation.insert("parameters".to_string(), Value::Array(parameters));
+        }
+        if let Some(request) = &self.request {
+            operation.insert(
+                "requestBody".to_string(),
+                json!({ "required": true, "content": request.to_json(generator) }),
+            );
+        }
+        let mut response = json!({ "description": "Successful response" });
+        if let Some(content) = &self.response {
+            response["content"] = content.to_json(generator);
+        }
+        operation.insert("responses".to_string(), json!({ "2XX": response }));
+        (path, Value::Object(operation))
+    }
+}
+
+/// Media type and schema of a request or response body.
+#[derive(Debug, Clone, Copy)]
+pub struct Content {
+    /// Media type of the body.
+    media_type: &'static str,
+    /// Schema of the body, if it has one.
+    schema: Option<SchemaFn>,
+}
+
+impl Content {
+    /// Describes a body of `media_type` with the schema returned by `schema`.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn new(media_type: &'static str, schema: SchemaFn) -> Option<Self> {
+        Some(Self {
+            media_type,
+            schema: Some(schema),
+        })
+    }
+
+    /// Describes a body of `media_type` without a schema.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn opaque(media_type: &'static str) -> Option<Self> {
+        Some(Self {
+            media_type,
+            schema: None,
+        })
+    }
+
+    /// Media type of the body, e.g. `application/json`.
+    #[must_use]
+    pub fn media_type(&self) -> &'static str {
+        self.media_type
+    }
+
+    /// Returns the content map of a request body or response.
+    fn to_json(self, generator: &mut SchemaGenerator) -> Value {
+        let schema = self.schema.and_then(|schema| schema(generator));
+        json!({ self.media_type: { "schema": schema_value(schema) } })
+    }
+}
+
+/// An `OpenAPI` document describing the endpoints of one or more clients.
+#[derive(Debug, Clone)]
+pub struct Document {
+    /// Title of the API in the `info` of the document.
+    title: String,
+    /// Version of the API in the `info` of the document.
+    version: String,
+    /// URLs of the servers of the document.
+    servers: Vec<String>,
+    /// Operations of the document, in the order they were added.
+    operations: Vec<Operation>,
+}
+
+impl Document {
+    /// Creates a document without operations, with the `title` and `version` of its `info`.
+    #[must_use]
+    pub fn new(title: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            version: version.into(),
+            servers: Vec::new(),
+            operations: Vec::new(),
+        }
+    }
+
+    /// Adds a server the paths of the document are relative to.
+    #[must_use]
+    pub fn server(mut self, url: impl Into<String>) -> Self {
+        self.servers.push(url.into());
+        self
+    }
+
+    /// Adds operations, usually those returned by the `openapi_operations()` function of a block
+    /// of endpoints, or the `<name>_openapi_operations()` function of a block listing them as
+    /// `#![endpoints(<name>)]`.
+    #[must_use]
+    pub fn operations(mut self, operations: impl IntoIterator<Item = Operation>) -> Self {
+        self.operations.extend(operations);
+        self
+    }
+
+    /// Returns the document as JSON.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        let mut settings = SchemaSettings::draft2019_09();
+        settings.definitions_path = "#/components/schemas/".to_string();
+        settings.meta_schema = None;
+        let mut generator = settings.into_generator();
+
+        let mut paths = Map::new();
+        for operation in &self.operations {
+            let (path, value) = operation.to_json(&mut generator);
+            let item = paths.entry(path).or_insert_with(|| json!({}));
+            item[operation.method.to_ascii_lowercase()] = value;
+        }
+        let servers = self
+            .servers
+            .iter()
+            .map(|url| json!({ "url": url }))
+            .collect::<Vec<_>>();
+        let mut document = json!({
+            "openapi": VERSION,
+            "info": { "title": self.title, "version": self.version },
+            "paths": paths,
+        });
+        if !servers.is_empty() {
+            document["servers"] = Value::Array(servers);
+        }
+        let schemas = generator.take_definitions();
+        if !schemas.is_empty() {
+            document["components"] = json!({ "schemas": schemas });
+        }
+        document
+    }
+}
+
+/// Splits a URL template into its path and query, without its host or base URL parameter.
+fn split_url(url: &str) -> (&str, &str) {
+    let path = if let Some(rest) = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    {
+        rest.find('/').map_or("", |start| &rest[start..])
+    } else if url.starts_with('{') {
+        url.find('}').map_or(url, |end| &url[end + 1..])
+    } else {
+        url
+    };
+    path.split_once('?').unwrap_or((path, ""))
+}
+
+/// Returns the byte range of the braces of the first `{name}` placeholder in `template`.
+fn placeholder(template: &str) -> Option<(usize, usize)> {
+    let start = template.find('{')?;
+    let end = template[start..].find('}')?;
+    Some((start, start + end))
+}
+
+/// Returns `schema` as JSON, or the empty schema matching anything.
+fn schema_value(schema: Option<Schema>) -> Value {
+    schema
+        .and_then(|schema| serde_json::to_value(schema).ok())
+        .unwrap_or_else(|| json!({}))
+}
+
+/// Used internally in the [`api!`](crate::api) macro to get the schema of types which have one.
+///
+/// `(&Probe::<T>::new()).schema(generator)` resolves to [`WithSchema`] if `T` implements
+/// [`JsonSchema`] and to [`WithoutSchema`] otherwise.
+#[doc(hidden)]
+pub struct Probe<T: ?Sized>(PhantomData<T>);
+
+impl<T: ?Sized> Probe<T> {
+    #[doc(hidden)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait WithSchema {
+    fn schema(&self, generator: &mut SchemaGenerator) -> Option<Schema>;
+}
+
+impl<T: JsonSchema + ?Sized> WithSchema for Probe<T> {
+    fn schema(&self, generator: &mut SchemaGenerator) -> Option<Schema> {
+        Some(generator.subschema_for::<T>())
+    }
+}
+
+#[doc(hidden)]
+pub trait WithoutSchema {
+    fn schema(&self, generator: &mut SchemaGenerator) -> Option<Schema>;
+}
+
+impl<T: ?Sized> WithoutSchema for &Probe<T> {
+    fn schema(&self, _generator: &mut SchemaGenerator) -> Option<Schema> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Document;
+    use crate::api;
+
+    #[derive(serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+    struct Todo {
+        id: u32,
+        title: String,
+    }
+
+    #[derive(serde::Serialize)]
+    struct Untyped {
+        note: String,
+    }
+
+    api! {
+        pub struct TodoApi {
+            base_url: String = "https://example.com".to_string(),
+        }
+    }
+
+    #[allow(dead_code)]
+    impl TodoApi {
+        api! {
+            #![endpoints]
+
+            /// Returns a todo.
+            ///
+            /// Todos are numbered from 1.
+            fn todo(&self, id: u32) -> Json<Todo> {
+                GET "{}/todos/{id}", self.base_url
+            }
+
+            fn search(&self, query: &str, limit: u8) -> Json<Vec<Todo>> {
+                GET "{}/todos?q={query}&limit={limit}&sort=id", self.base_url
+            }
+
+            fn create(request: Json<Todo>) -> Created<Json<Todo>> {
+                POST "https://example.com/todos"
+            }
+
+            fn annotate(&self, request: Json<Untyped>, id: u32) -> StatusCode {
+                PUT "{}/todos/{id}/note", self.base_url
+            }
+
+            fn replace<T: serde::Serialize>(&self, request: Json<T>, id: u32) -> StatusCode {
+                PUT "{}/todos/{id}", self.base_url
+            }
+
+            fn rename(&self, request: Json<Todo> as "application/vnd.todo+json", id: u32) -> StatusCode {
+                PATCH "{}/todos/{id}", self.base_url
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    impl TodoApi {
+        api! {
+            #![endpoints(admin)]
+
+            fn purge(&self) -> StatusCode {
+                DELETE "{}/todos", self.base_url
+            }
+        }
+    }
+
+    #[test]
+    fn exports_endpoints() {
+        let document = Document::new("Todos", "1.0.0")
+            .server("https://example.com")
+            .operations(TodoApi::openapi_operations())
+            .operations(TodoApi::admin_openapi_operations())
+            .to_json();
+        assert_eq!(document["openapi"], "3.1.0");
+        assert_eq!(
+            document["paths"]["/todos"]["delete"]["operationId"],
+            "purge"
+        );
+        assert_eq!(
+            document["servers"],
+            json!([{ "url": "https://example.com" }])
+        );
+
+        let todo = &document["paths"]["/todos/{id}"]["get"];
+        assert_eq!(todo["operationId"], "todo");
+        assert_eq!(todo["summary"], "Returns a todo.");
+        assert_eq!(
+            todo["description"],
+            "Returns a todo.\n\nTodos are numbered from 1."
+        );
+        assert_eq!(todo["parameters"][0]["in"], "path");
+        assert_eq!(todo["parameters"][0]["schema"]["type"], "integer");
+        let schema = &todo["responses"]["2XX"]["content"]["application/json"]["schema"];
+        assert_eq!(schema["$ref"], "#/components/schemas/Todo");
+        assert_eq!(
+            document["components"]["schemas"]["Todo"]["required"],
+            json!(["id", "title"])
+        );
+
+        let search = &document["paths"]["/todos"]["get"];
+        let names = search["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|parameter| (parameter["name"].clone(), parameter["in"].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                (json!("q"), json!("query")),
+                (json!("limit"), json!("query"))
+            ]
+        );
+        assert_eq!(search["parameters"][0]["schema"]["type"], "string");
+
+        let create = &document["paths"]["/todos"]["post"];
+        let schema = &create["requestBody"]["content"]["application/json"]["schema"];
+        assert_eq!(schema["$ref"], "#/components/schemas/Todo");
+
+        let annotate = &document["paths"]["/todos/{id}/note"]["put"];
+        let schema = &annotate["requestBody"]["content"]["application/json"]["schema"];
+        assert_eq!(schema, &json!({}));
+        assert!(annotate["responses"]["2XX"].get("content").is_none());
+
+        let replace = &document["paths"]["/todos/{id}"]["put"];
+        assert_eq!(replace["parameters"][0]["name"], "id");
+        assert_eq!(replace["parameters"][0]["schema"], json!({}));
+        let schema = &replace["requestBody"]["content"]["application/json"]["schema"];
+        assert_eq!(schema, &json!({}));
+
+        let rename = &document["paths"]["/todos/{id}"]["patch"];
+        let schema = &rename["requestBody"]["content"]["application/vnd.todo+json"]["schema"];
+        assert_eq!(schema["$ref"], "#/components/schemas/Todo");
+    }
+}
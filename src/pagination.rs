@@ -0,0 +1,298 @@
+//! Scanning offset-paginated collections with several pages in flight at once.
+//!
+//! See [`Api::parallel_scan`](crate::Api::parallel_scan). When the total number of items is
+//! known, the collection is split into pages which are fetched by up to
+//! [`ParallelScan::workers`] concurrent calls, and their items are yielded in collection order.
+//! Fewer pages are started while the server's rate limit budget or the tokens of the rate limiter
+//! of the instance, see [`rate_limit`](crate::rate_limit), are lower than the number of workers,
+//! and other tasks go first before each further page, so a scan doesn't exhaust them on its own.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, Api, ResultType};
+//! use futures_util::StreamExt;
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn users(offset: usize, limit: usize) -> Json<Vec<String>> {
+//!            GET "https://example.com/users?offset={offset}&limit={limit}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn export(api: &ExampleApi, total: usize) -> ResultType<()> {
+//!     let users = api
+//!         .parallel_scan(total, 100, |api, page| api.users(page.offset, page.limit))
+//!         .workers(8)
+//!         .into_stream();
+//!     futures_util::pin_mut!(users);
+//!     while let Some(user) = users.next().await {
+//!         println!("{}", user?);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::{collections::VecDeque, fmt, future::Future};
+
+use futures_util::{stream::FuturesOrdered, Stream, StreamExt};
+
+pub use crate::protocol::Page;
+use crate::{rate_limit, runtime, Api, ResultType};
+
+/// Number of pages a [`ParallelScan`] fetches at once unless configured otherwise.
+pub const DEFAULT_WORKERS: usize = 4;
+
+/// Scan of a collection split into pages, created by
+/// [`Api::parallel_scan`](crate::Api::parallel_scan).
+#[must_use = "a scan does nothing until it is collected or streamed"]
+pub struct ParallelScan<'a, A: ?Sized, F> {
+    /// The api the pages are fetched with.
+    api: &'a A,
+    /// Number of items in the collection.
+    total: usize,
+    /// Number of items per page.
+    page_size: usize,
+    /// Fetches a page.
+    fetch: F,
+    /// Maximum number of pages fetched at once.
+    workers: usize,
+}
+
+impl<A: ?Sized, F> fmt::Debug for ParallelScan<'_, A, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParallelScan")
+            .field("total", &self.total)
+            .field("page_size", &self.page_size)
+            .field("workers", &self.workers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, A, F, Fut, T> ParallelScan<'a, A, F>
+where
+    A: Api + ?Sized,
+    F: FnMut(&'a A, Page) -> Fut,
+    Fut: Future<Output = ResultType<Vec<T>>> + 'a,
+{
+    /// Creates a scan of `total` items fetching `page_size` items at a time with `fetch`.
+    ///
+    /// # Panics
+    /// Panics if `page_size` is zero.
+    pub fn new(api: &'a A, total: usize, page_size: usize, fetch: F) -> Self {
+        assert!(page_size > 0, "pages have to hold at least one item");
+        Self {
+            api,
+            total,
+            page_size,
+            fetch,
+            workers: DEFAULT_WORKERS,
+        }
+    }
+
+    /// Sets the maximum number of pages fetched at once, [`DEFAULT_WORKERS`] by default.
+    ///
+    /// # Panics
+    /// Panics if `workers` is zero.
+    pub fn workers(mut self, workers: usize) -> Self {
+        assert!(workers > 0, "a scan needs at least one worker");
+        self.workers = workers;
+        self
+    }
+
+    /// Returns the pages the collection is split into.
+    pub fn pages(&self) -> impl Iterator<Item = Page> {
+        let (total, page_size) = (self.total, self.page_size);
+        (0..total).step_by(page_size).map(move |offset| Page {
+            offset,
+            limit: page_size.min(total - offset),
+        })
+    }
+
+    /// Fetches all pages, yielding their items in collection order.
+    ///
+    /// The stream ends after the first error.
+    pub fn into_stream(self) -> impl Stream<Item = ResultType<T>> + 'a
+    where
+        T: 'a,
+        F: 'a,
+    {
+        let pages = self.pages().collect();
+        let scan = Scan {
+            api: self.api,
+            fetch: self.fetch,
+            workers: self.workers,
+            pages,
+            in_flight: FuturesOrdered::new(),
+            items: VecDeque::new(),
+            failed: false,
+        };
+        futures_util::stream::unfold(scan, |mut scan| async move {
+            scan.next().await.map(|item| (item, scan))
+        })
+    }
+
+    /// Fetches all pages, returning their items in collection order.
+    ///
+    /// # Errors
+    /// Returns the first error of a page.
+    pub async fn collect(self) -> ResultType<Vec<T>>
+    where
+        T: 'a,
+        F: 'a,
+    {
+        let mut items = Vec::with_capacity(self.total);
+        let stream = self.into_stream();
+        futures_util::pin_mut!(stream);
+        while let Some(item) = stream.next().await {
+            items.push(item?);
+        }
+        Ok(items)
+    }
+}
+
+/// Progress of a [`ParallelScan`] turned into a stream.
+struct Scan<'a, A: ?Sized, F, Fut: Future, T> {
+    /// The api the pages are fetched with.
+    api: &'a A,
+    /// Fetches a page.
+    fetch: F,
+    /// Maximum number of pages fetched at once.
+    workers: usize,
+    /// Pages which have not been started yet.
+    pages: VecDeque<Page>,
+    /// Pages being fetched, in collection order.
+    in_flight: FuturesOrdered<Fut>,
+    /// Items of the last fetched page which have not been yielded yet.
+    items: VecDeque<T>,
+    /// Whether a page failed, which ends the scan.
+    failed: bool,
+}
+
+impl<'a, A, F, Fut, T> Scan<'a, A, F, Fut, T>
+where
+    A: Api + ?Sized,
+    F: FnMut(&'a A, Page) -> Fut,
+    Fut: Future<Output = ResultType<Vec<T>>>,
+{
+    /// Returns the next item, starting pages as the rate limit budget allows.
+    async fn next(&mut self) -> Option<ResultType<T>> {
+        loop {
+            if let Some(item) = self.items.pop_front() {
+                return Some(Ok(item));
+            }
+            if self.failed {
+                return None;
+            }
+            self.start_pages().await;
+            match self.in_flight.next().await? {
+                Ok(items) => self.items.extend(items),
+                Err(error) => {
+                    self.failed = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+
+    /// Starts pages until [`ParallelScan::workers`] are in flight, or as many as the remaining
+    /// rate limit budget, but at least one.
+    ///
+    /// While the budget is low, other tasks run first.
+    async fn start_pages(&mut self) {
+        if self.pages.is_empty() {
+            return;
+        }
+        let budget = rate_limit::concurrency(self.api.state(), self.workers);
+        if budget < self.workers {
+            runtime::yield_now().await;
+        }
+        while self.in_flight.len() < budget {
+            match self.pages.pop_front() {
+                Some(page) => self.in_flight.push_back((self.fetch)(self.api, page)),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use futures_util::StreamExt;
+
+    use crate::{api, test_server, Api};
+
+    api!(struct Users);
+
+    impl Users {
+        api! {
+            fn page(&self, base_url: &str, offset: usize, limit: usize) -> Json<Vec<usize>> {
+                GET "{base_url}/users?offset={offset}&limit={limit}"
+            }
+        }
+    }
+
+    #[test]
+    fn scans_pages_in_order() {
+        tokio_test::block_on(async {
+            let requests = Arc::new(AtomicUsize::new(0));
+            let server_requests = requests.clone();
+            let base_url = test_server::serve(move |request| {
+                server_requests.fetch_add(1, Ordering::SeqCst);
+                let query = request.path.split_once('?').unwrap().1;
+                let mut numbers = query
+                    .split('&')
+                    .map(|pair| pair.split_once('=').unwrap().1.parse::<usize>().unwrap());
+                let (offset, limit) = (numbers.next().unwrap(), numbers.next().unwrap());
+                if offset == 40 {
+                    return test_server::Response::new(500);
+                }
+                let items = (offset..offset + limit)
+                    .map(|item| item.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                test_server::Response::new(200)
+                    .delay(std::time::Duration::from_millis(
+                        ((offset / 10) % 3) as u64 * 20,
+                    ))
+                    .json(&format!("[{items}]"))
+            })
+            .await;
+            let api = Users::new();
+
+            let items = api
+                .parallel_scan(35, 10, |api, page| {
+                    api.page(&base_url, page.offset, page.limit)
+                })
+                .workers(3)
+                .collect()
+                .await
+                .unwrap();
+            assert_eq!(items, (0..35).collect::<Vec<_>>());
+            assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+            let stream = api
+                .parallel_scan(60, 10, |api, page| {
+                    api.page(&base_url, page.offset, page.limit)
+                })
+                .into_stream();
+            futures_util::pin_mut!(stream);
+            let mut count = 0;
+            while let Some(item) = stream.next().await {
+                if item.is_err() {
+                    break;
+                }
+                count += 1;
+            }
+            assert_eq!(count, 40);
+            assert!(stream.next().await.is_none());
+        });
+    }
+}
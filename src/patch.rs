@@ -0,0 +1,327 @@
+//! Partial updates with JSON Merge Patch ([RFC 7396]) and JSON Patch ([RFC 6902]) bodies.
+//!
+//! `PATCH` endpoints disagree on what their body means, and a plain `Json<T>` body sent as
+//! `application/json` leaves it to the server to guess. The two standard formats have their own
+//! request kinds, which send the media type the server needs to tell them apart:
+//! - `request: MergePatch<T>` sends `T` as an `application/merge-patch+json` document. Fields set
+//!   to `null` are removed from the resource and missing fields are left as they are, so `Option`
+//!   fields which shouldn't be touched when they are `None` need
+//!   `#[serde(skip_serializing_if = "Option::is_none")]`.
+//! - `request: JsonPatch` sends a [`JsonPatch`] built operation by operation as an
+//!   `application/json-patch+json` document, which the server applies in order and all or
+//!   nothing. Keys containing `/` or `~` have to be escaped in paths, which [`pointer`] does.
+//!
+//! [RFC 7396]: https://www.rfc-editor.org/rfc/rfc7396
+//! [RFC 6902]: https://www.rfc-editor.org/rfc/rfc6902
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, patch::{self, JsonPatch}, reqwest::StatusCode, ResultType};
+//!
+//! #[derive(serde::Serialize)]
+//! struct TodoChanges {
+//!     #[serde(skip_serializing_if = "Option::is_none")]
+//!     title: Option<String>,
+//!     #[serde(skip_serializing_if = "Option::is_none")]
+//!     completed: Option<bool>,
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn update_todo(request: MergePatch<TodoChanges>, id: u32) -> StatusCode {
+//!            PATCH "https://example.com/todos/{id}"
+//!         }
+//!
+//!         fn patch_todo(request: JsonPatch, id: u32) -> StatusCode {
+//!            PATCH "https://example.com/todos/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn complete(api: &ExampleApi) -> ResultType<StatusCode> {
+//!     // {"completed":true}
+//!     let changes = TodoChanges { title: None, completed: Some(true) };
+//!     api.update_todo(&changes, 1).await?;
+//!
+//!     // [{"op":"test","path":"/completed","value":false},{"op":"replace",...},...]
+//!     let patch = JsonPatch::new()
+//!         .test("/completed", false)
+//!         .replace("/completed", true)
+//!         .remove(patch::pointer(["labels", "to/do"]));
+//!     api.patch_todo(&patch, 1).await
+//! }
+//! ```
+
+use serde::{ser::Error as _, Serialize, Serializer};
+use serde_json::Value;
+
+/// Media type of JSON Merge Patch documents.
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+
+/// Media type of JSON Patch documents.
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+/// JSON Patch document, a list of operations applied in order, see [`patch`](crate::patch).
+///
+/// Values are serialized when they are added. If one can't be represented as JSON, e.g. a map
+/// with keys which aren't strings, the request fails to be built when it is sent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonPatch {
+    /// Operations in the order they are applied.
+    operations: Vec<Operation>,
+    /// Error of the first value which couldn't be serialized, if any.
+    error: Option<String>,
+}
+
+impl JsonPatch {
+    /// Creates a patch without operations.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `value` at `path`, inserting it into arrays and replacing existing object members.
+    #[must_use]
+    pub fn add(self, path: impl Into<String>, value: impl Serialize) -> Self {
+        self.with_value(value, |value| Operation::Add {
+            path: path.into(),
+            value,
+        })
+    }
+
+    /// Removes the value at `path`, which has to exist.
+    #[must_use]
+    pub fn remove(self, path: impl Into<String>) -> Self {
+        self.operation(Operation::Remove { path: path.into() })
+    }
+
+    /// Replaces the value at `path`, which has to exist, with `value`.
+    #[must_use]
+    pub fn replace(self, path: impl Into<String>, value: impl Serialize) -> Self {
+        self.with_value(value, |value| Operation::Replace {
+            path: path.into(),
+            value,
+        })
+    }
+
+    /// Moves the value at `from` to `path`.
+    #[must_use]
+    pub fn move_from(self, from: impl Into<String>, path: impl Into<String>) -> Self {
+        self.operation(Operation::Move {
+            from: from.into(),
+            path: path.into(),
+        })
+    }
+
+    /// Copies the value at `from` to `path`.
+    #[must_use]
+    pub fn copy_from(self, from: impl Into<String>, path: impl Into<String>) -> Self {
+        self.operation(Operation::Copy {
+            from: from.into(),
+            path: path.into(),
+        })
+    }
+
+    /// Checks that the value at `path` equals `value`, failing the whole patch otherwise, e.g. to
+    /// only change a resource which wasn't changed since it was read.
+    #[must_use]
+    pub fn test(self, path: impl Into<String>, value: impl Serialize) -> Self {
+        self.with_value(value, |value| Operation::Test {
+            path: path.into(),
+            value,
+        })
+    }
+
+    /// Appends `operation`.
+    #[must_use]
+    pub fn operation(mut self, operation: Operation) -> Self {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Returns the operations in the order they are applied.
+    #[must_use]
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Returns whether the patch has no operations.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Appends the operation created by `operation` from the serialized `value`, or records the
+    /// error if `value` can't be serialized.
+    fn with_value(
+        mut self,
+        value: impl Serialize,
+        operation: impl FnOnce(Value) -> Operation,
+    ) -> Self {
+        match serde_json::to_value(value) {
+            Ok(value) => self.operation(operation(value)),
+            Err(error) => {
+                self.error.get_or_insert_with(|| error.to_string());
+                self
+            }
+        }
+    }
+}
+
+impl Serialize for JsonPatch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.error {
+            Some(error) => Err(S::Error::custom(error)),
+            None => self.operations.serialize(serializer),
+        }
+    }
+}
+
+/// Operation of a [`JsonPatch`], with paths and `from` given as JSON pointers.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    /// Adds a value, see [`JsonPatch::add`].
+    Add {
+        /// Location of the value.
+        path: String,
+        /// Value to add.
+        value: Value,
+    },
+    /// Removes a value, see [`JsonPatch::remove`].
+    Remove {
+        /// Location of the value.
+        path: String,
+    },
+    /// Replaces a value, see [`JsonPatch::replace`].
+    Replace {
+        /// Location of the value.
+        path: String,
+        /// Value replacing the current one.
+        value: Value,
+    },
+    /// Moves a value, see [`JsonPatch::move_from`].
+    Move {
+        /// Location of the value to move.
+        from: String,
+        /// Location the value is moved to.
+        path: String,
+    },
+    /// Copies a value, see [`JsonPatch::copy_from`].
+    Copy {
+        /// Location of the value to copy.
+        from: String,
+        /// Location the value is copied to.
+        path: String,
+    },
+    /// Checks a value, see [`JsonPatch::test`].
+    Test {
+        /// Location of the value.
+        path: String,
+        /// Value the current one has to equal.
+        value: Value,
+    },
+}
+
+/// Returns the JSON pointer to the value reached through `segments`, escaping `~` and `/` in
+/// them, e.g. `/labels/to~1do` for `["labels", "to/do"]`.
+pub fn pointer<I>(segments: I) -> String
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut pointer = String::new();
+    for segment in segments {
+        pointer.push('/');
+        pointer.push_str(&segment.as_ref().replace('~', "~0").replace('/', "~1"));
+    }
+    pointer
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{pointer, JsonPatch};
+    use crate::{api, test_server, Api};
+
+    #[test]
+    fn builds_operations() {
+        let patch = JsonPatch::new()
+            .test("/version", 3)
+            .add("/tags/-", "new")
+            .replace("/title", "Buy milk")
+            .remove(pointer(["labels", "to/do", "a~b"]))
+            .move_from("/draft", "/body")
+            .copy_from("/body", "/summary");
+        assert_eq!(
+            serde_json::to_value(&patch).unwrap(),
+            serde_json::json!([
+                { "op": "test", "path": "/version", "value": 3 },
+                { "op": "add", "path": "/tags/-", "value": "new" },
+                { "op": "replace", "path": "/title", "value": "Buy milk" },
+                { "op": "remove", "path": "/labels/to~1do/a~0b" },
+                { "op": "move", "from": "/draft", "path": "/body" },
+                { "op": "copy", "from": "/body", "path": "/summary" },
+            ])
+        );
+        assert_eq!(patch.operations().len(), 6);
+        assert!(JsonPatch::new().is_empty());
+
+        let invalid = JsonPatch::new().add("/map", HashMap::from([((1, 2), 3)]));
+        assert!(serde_json::to_value(&invalid).is_err());
+    }
+
+    #[derive(serde::Serialize)]
+    struct Changes {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<&'static str>,
+        due: Option<u32>,
+    }
+
+    api!(struct Todos);
+
+    impl Todos {
+        api! {
+            fn update(&self, request: MergePatch<Changes>, base_url: &str) -> String {
+                PATCH "{base_url}/todos/1"
+            }
+
+            fn patch(&self, request: JsonPatch, base_url: &str) -> String {
+                PATCH "{base_url}/todos/1"
+            }
+        }
+    }
+
+    #[test]
+    fn sends_media_types() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let content_type = request.header("content-type").unwrap_or_default();
+                test_server::Response::new(200).body(format!(
+                    "{} {}",
+                    content_type,
+                    String::from_utf8_lossy(&request.body)
+                ))
+            })
+            .await;
+            let api = Todos::new();
+
+            let changes = Changes {
+                title: None,
+                due: None,
+            };
+            let echo = api.update(&changes, &base_url).await.unwrap();
+            assert_eq!(echo, r#"application/merge-patch+json {"due":null}"#);
+
+            let patch = JsonPatch::new().replace("/title", "a");
+            let echo = api.patch(&patch, &base_url).await.unwrap();
+            assert_eq!(
+                echo,
+                r#"application/json-patch+json [{"op":"replace","path":"/title","value":"a"}]"#
+            );
+        });
+    }
+}
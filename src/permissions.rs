@@ -0,0 +1,239 @@
+//! Scopes required by endpoints, checked before their requests are sent.
+//!
+//! Endpoints declare the scopes they need with `with { scopes: ["repo", "read:org"] }`. When
+//! [`Api::granted_scopes`](crate::Api::granted_scopes) returns the scopes of the current token,
+//! calls to endpoints needing others fail with [`Error::MissingScopes`] without sending a
+//! request, instead of with an opaque `403 Forbidden`.
+//! [`Api::check_permissions`](crate::Api::check_permissions) runs the same check ahead of time,
+//! e.g. at startup.
+//!
+//! Granted scopes are usually read from the claims of the token with [`Scopes::from_jwt`], or
+//! from the response of an OAuth 2.0 token introspection endpoint with [`Scopes::from_claims`].
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, permissions::Scopes, Api};
+//!
+//! api! {
+//!     pub struct GitHub {
+//!         token: String,
+//!     }
+//!
+//!     impl Api {
+//!         fn granted_scopes(&self) -> Option<Scopes> {
+//!             Scopes::from_jwt(&self.token)
+//!         }
+//!     }
+//! }
+//!
+//! impl GitHub {
+//!     api! {
+//!         fn delete_repo(&self, repo: &str) -> StatusCode {
+//!            DELETE "https://api.github.com/repos/{repo}"
+//!            with { scopes: ["delete_repo"] }
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{collections::BTreeSet, fmt};
+
+use crate::Error;
+
+/// Set of scopes granted to a token.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scopes(BTreeSet<String>);
+
+impl Scopes {
+    /// Parses a space-delimited list of scopes, as in the `scope` parameter of OAuth 2.0.
+    #[must_use]
+    pub fn parse(scopes: &str) -> Self {
+        scopes.split_whitespace().collect()
+    }
+
+    /// Reads the scopes from the claims of a token or the response of a token introspection
+    /// endpoint.
+    ///
+    /// Both the `scope` claim, a space-delimited string, and the `scp` claim, a string or an
+    /// array, are read.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    #[must_use]
+    pub fn from_claims(claims: &serde_json::Value) -> Self {
+        let mut scopes = Self::default();
+        for name in ["scope", "scp"] {
+            match &claims[name] {
+                serde_json::Value::String(list) => scopes.0.extend(Self::parse(list).0),
+                serde_json::Value::Array(items) => scopes.0.extend(
+                    items
+                        .iter()
+                        .filter_map(serde_json::Value::as_str)
+                        .map(String::from),
+                ),
+                _ => {}
+            }
+        }
+        scopes
+    }
+
+    /// Reads the scopes from the claims of a JWT, see [`Scopes::from_claims`].
+    ///
+    /// The signature is not verified, as the scopes are only used to fail early: the server still
+    /// checks the token. Returns `None` if `token` is not a JWT.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    #[must_use]
+    pub fn from_jwt(token: &str) -> Option<Self> {
+        use base64::Engine;
+
+        let payload = token.split('.').nth(1)?;
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .ok()?;
+        let claims = serde_json::from_slice(&payload).ok()?;
+        Some(Self::from_claims(&claims))
+    }
+
+    /// Returns whether `scope` is granted.
+    #[must_use]
+    pub fn contains(&self, scope: &str) -> bool {
+        self.0.contains(scope)
+    }
+
+    /// Returns the granted scopes in alphabetical order.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    /// Checks that all of the `required` scopes are granted.
+    ///
+    /// `endpoint` is the name of the endpoint requiring them, if any.
+    ///
+    /// # Errors
+    /// Returns [`Error::MissingScopes`] listing the scopes which are not granted.
+    pub fn require(&self, endpoint: Option<&'static str>, required: &[&str]) -> Result<(), Error> {
+        let missing: Vec<_> = required
+            .iter()
+            .filter(|scope| !self.contains(scope))
+            .map(|scope| (*scope).to_string())
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        Err(Error::MissingScopes(MissingScopes { endpoint, missing }))
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for Scopes {
+    fn from_iter<I: IntoIterator<Item = S>>(scopes: I) -> Self {
+        Self(scopes.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Scopes which are required but not granted, see [`Error::MissingScopes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingScopes {
+    /// Name of the endpoint requiring the scopes, if the check was made for an endpoint.
+    pub endpoint: Option<&'static str>,
+    /// The scopes which are not granted, in the order they are required.
+    pub missing: Vec<String>,
+}
+
+impl fmt::Display for MissingScopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("missing scopes ")?;
+        for (index, scope) in self.missing.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "`{scope}`")?;
+        }
+        match self.endpoint {
+            Some(endpoint) => write!(f, " required by `{endpoint}`"),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use base64::Engine;
+
+    use super::{MissingScopes, Scopes};
+    use crate::{api, test_server, Api, Error};
+
+    api! {
+        struct Repos {
+            token: String,
+        }
+
+        impl Api {
+            fn granted_scopes(&self) -> Option<Scopes> {
+                Scopes::from_jwt(&self.token)
+            }
+        }
+    }
+
+    impl Repos {
+        api! {
+            fn repo(&self, base_url: &str) -> String {
+                GET "{base_url}/repo"
+                with { scopes: ["repo:read"] }
+            }
+
+            fn delete(&self, base_url: &str) -> StatusCode {
+                DELETE "{base_url}/repo"
+                with { scopes: ["repo:read", "repo:delete", "admin"] }
+            }
+        }
+    }
+
+    #[test]
+    fn fails_fast_on_missing_scopes() {
+        tokio_test::block_on(async {
+            let hits = Arc::new(AtomicUsize::new(0));
+            let server_hits = hits.clone();
+            let base_url = test_server::serve(move |_| {
+                server_hits.fetch_add(1, Ordering::SeqCst);
+                test_server::Response::new(200).body("repo")
+            })
+            .await;
+            let claims = base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .encode(r#"{"sub":"jane","scope":"repo:read admin"}"#);
+            let api = Repos::builder()
+                .token(format!("eyJhbGciOiJub25lIn0.{claims}.signature"))
+                .build()
+                .unwrap();
+
+            assert_eq!(api.repo(&base_url).await.unwrap(), "repo");
+            let error = api.delete(&base_url).await.unwrap_err();
+            let expected = MissingScopes {
+                endpoint: Some("delete"),
+                missing: vec!["repo:delete".to_string()],
+            };
+            assert!(matches!(&error, Error::MissingScopes(missing) if *missing == expected));
+            assert_eq!(
+                error.to_string(),
+                "missing scopes `repo:delete` required by `delete`"
+            );
+            assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+            assert!(api.check_permissions(&["admin", "repo:read"]).is_ok());
+            assert!(api.check_permissions(&["repo:write"]).is_err());
+        });
+    }
+
+    #[test]
+    fn reads_scopes_from_claims() {
+        let claims = serde_json::json!({ "scope": "a b", "scp": ["c", "b"] });
+        let scopes = Scopes::from_claims(&claims);
+        assert_eq!(scopes.iter().collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(Scopes::parse(" a  b "), ["a", "b"].into_iter().collect());
+        assert!(Scopes::from_jwt("not a token").is_none());
+    }
+}
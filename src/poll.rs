@@ -0,0 +1,359 @@
+//! Polling a status endpoint until a long-running operation has finished.
+//!
+//! Many APIs answer requests starting slow work with `202 Accepted` and a job which is then
+//! polled at a status endpoint until it is done. [`Api::poll_until`](crate::Api::poll_until)
+//! calls such an endpoint until a predicate on its output holds, waiting
+//! [`interval`](Poll::interval) between calls, growing by the [`backoff`](Poll::backoff) factor up
+//! to [`max_interval`](Poll::max_interval), and fails with
+//! [`Error::DeadlineExceeded`](crate::Error::DeadlineExceeded) once the
+//! [`timeout`](Poll::timeout) would pass before the next call, or passes during one, see
+//! [`deadline`](crate::deadline). Errors of the calls end polling.
+//!
+//! Servers often say when to ask again with a `Retry-After` header. Endpoints declared with
+//! [`WithHeaders`](crate::response::WithHeaders) can honour it with
+//! `.retry_after(ResponseParts::retry_after)`, which waits as long as the last response asked
+//! instead of the interval.
+//!
+//! # Usage
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use api_client::{api, response::ResponseParts, Api, ResultType};
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Job {
+//!     state: String,
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn job(id: &str) -> WithHeaders<Json<Job>> {
+//!            GET "https://example.com/jobs/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn wait_for(api: &ExampleApi, id: &str) -> ResultType<Job> {
+//!     let job = api
+//!         .poll_until(|api| api.job(id), |job| job.body.state != "running")
+//!         .interval(Duration::from_secs(2))
+//!         .backoff(1.5)
+//!         .timeout(Duration::from_secs(600))
+//!         .retry_after(ResponseParts::retry_after)
+//!         .run()
+//!         .await?;
+//!     Ok(job.into_body())
+//! }
+//! ```
+
+use std::{
+    fmt,
+    future::Future,
+    time::{Duration, Instant, SystemTime},
+};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+use crate::{deadline, Api, Error, ResultType};
+
+/// Time waited between calls unless configured otherwise.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest time waited between calls unless configured otherwise.
+pub const DEFAULT_MAX_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Reads how long the server asks to wait before the next request from the `Retry-After`
+/// `headers` of a response, given in seconds or as an HTTP date.
+#[must_use]
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Reads the time the server asks to wait from an output of a [`Poll`].
+type RetryAfter<'a, T> = Box<dyn Fn(&T) -> Option<Duration> + 'a>;
+
+/// Polling of an endpoint, created by [`Api::poll_until`](crate::Api::poll_until).
+#[must_use = "polling does nothing until it is run"]
+pub struct Poll<'a, A: ?Sized, F, P, T> {
+    /// The api the endpoint is called with.
+    api: &'a A,
+    /// Calls the endpoint.
+    call: F,
+    /// Returns whether the operation has finished.
+    done: P,
+    /// Time waited after the first call.
+    interval: Duration,
+    /// Factor the interval grows by after every call.
+    backoff: f64,
+    /// Longest time waited between calls.
+    max_interval: Duration,
+    /// Time after which polling gives up, if any.
+    timeout: Option<Duration>,
+    /// Reads the time the server asks to wait from an output, if set.
+    retry_after: Option<RetryAfter<'a, T>>,
+}
+
+impl<A: ?Sized, F, P, T> fmt::Debug for Poll<'_, A, F, P, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Poll")
+            .field("interval", &self.interval)
+            .field("backoff", &self.backoff)
+            .field("max_interval", &self.max_interval)
+            .field("timeout", &self.timeout)
+            .field("retry_after", &self.retry_after.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, A, F, Fut, P, T> Poll<'a, A, F, P, T>
+where
+    A: Api + ?Sized,
+    F: FnMut(&'a A) -> Fut,
+    Fut: Future<Output = ResultType<T>>,
+    P: FnMut(&T) -> bool,
+{
+    /// Creates polling calling the endpoint with `call` until `done` returns `true` for its
+    /// output.
+    pub fn new(api: &'a A, call: F, done: P) -> Self {
+        Self {
+            api,
+            call,
+            done,
+            interval: DEFAULT_INTERVAL,
+            backoff: 1.0,
+            max_interval: DEFAULT_MAX_INTERVAL,
+            timeout: None,
+            retry_after: None,
+        }
+    }
+
+    /// Sets the time waited after the first call, [`DEFAULT_INTERVAL`] by default, but no longer
+    /// than the [`max_interval`](Poll::max_interval).
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the factor the interval grows by after every call, `1.0` by default so calls are
+    /// evenly spaced.
+    ///
+    /// # Panics
+    /// Panics if `factor` is smaller than `1.0` or NaN.
+    pub fn backoff(mut self, factor: f64) -> Self {
+        assert!(factor >= 1.0, "the interval can't shrink between calls");
+        self.backoff = factor;
+        self
+    }
+
+    /// Sets the longest time waited between calls, [`DEFAULT_MAX_INTERVAL`] by default.
+    pub fn max_interval(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval;
+        self
+    }
+
+    /// Gives up with [`Error::DeadlineExceeded`] once `timeout` would pass before the next
+    /// call, or passes while a call is running. Polling goes on until the operation has finished
+    /// by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Waits as long as `retry_after` reads from an output instead of the interval, if it returns
+    /// a time, e.g. [`ResponseParts::retry_after`](crate::response::ResponseParts::retry_after).
+    pub fn retry_after(mut self, retry_after: impl Fn(&T) -> Option<Duration> + 'a) -> Self {
+        self.retry_after = Some(Box::new(retry_after));
+        self
+    }
+
+    /// Calls the endpoint until the operation has finished, returning the last output.
+    ///
+    /// # Errors
+    /// Returns the first error of a call, or [`Error::DeadlineExceeded`] if the timeout passed.
+    pub async fn run(self) -> ResultType<T> {
+        let (api, timeout) = (self.api, self.timeout);
+        deadline::enforce(api.state().async_runtime(), timeout, self.poll()).await
+    }
+
+    /// Calls the endpoint until the operation has finished, for [`Poll::run`].
+    async fn poll(mut self) -> ResultType<T> {
+        let started = Instant::now();
+        let mut interval = self.interval.min(self.max_interval);
+        loop {
+            let output = (self.call)(self.api).await?;
+            if (self.done)(&output) {
+                return Ok(output);
+            }
+            let wait = self
+                .retry_after
+                .as_ref()
+                .and_then(|retry_after| retry_after(&output))
+                .unwrap_or(interval);
+            if let Some(timeout) = self.timeout {
+                if started.elapsed().saturating_add(wait) >= timeout {
+                    return Err(Error::DeadlineExceeded(timeout));
+                }
+            }
+            self.api.state().async_runtime().sleep(wait).await;
+            interval = grown(interval, self.backoff, self.max_interval);
+        }
+    }
+}
+
+/// Returns `interval` grown by `factor`, but no longer than `max_interval`, even if it doesn't fit
+/// in a [`Duration`].
+fn grown(interval: Duration, factor: f64, max_interval: Duration) -> Duration {
+    let seconds = interval.as_secs_f64() * factor;
+    if seconds < max_interval.as_secs_f64() {
+        Duration::from_secs_f64(seconds)
+    } else {
+        max_interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::{grown, retry_after};
+    use crate::{api, response::ResponseParts, test_server, Api, Error};
+
+    api!(struct Jobs);
+
+    impl Jobs {
+        api! {
+            fn job(&self, base_url: &str) -> WithHeaders<String> {
+                GET "{base_url}/job"
+            }
+        }
+    }
+
+    #[test]
+    fn polls_until_done() {
+        tokio_test::block_on(async {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let counter = calls.clone();
+            let base_url =
+                test_server::serve(move |_| match counter.fetch_add(1, Ordering::SeqCst) {
+                    0 => test_server::Response::new(202).header("retry-after", "0"),
+                    1 => test_server::Response::new(202),
+                    _ => test_server::Response::new(200).body("done"),
+                })
+                .await;
+            let api = Jobs::new();
+
+            let job = api
+                .poll_until(|api| api.job(&base_url), |job| job.status == 200)
+                .interval(Duration::from_millis(10))
+                .backoff(2.0)
+                .retry_after(ResponseParts::retry_after)
+                .run()
+                .await
+                .unwrap();
+            assert_eq!(job.body, "done");
+            assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+            let error = api
+                .poll_until(|api| api.job(&base_url), |job| job.body == "never")
+                .interval(Duration::from_millis(20))
+                .timeout(Duration::from_millis(50))
+                .run()
+                .await
+                .unwrap_err();
+            assert!(matches!(error, Error::DeadlineExceeded(timeout) if timeout.as_millis() == 50));
+            // Calls at 0ms, 20ms and 40ms unless they are slow, and none which would start after
+            // the timeout.
+            assert!((4..=6).contains(&calls.load(Ordering::SeqCst)));
+        });
+    }
+
+    #[test]
+    fn clamps_the_first_interval() {
+        tokio_test::block_on(async {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let counter = calls.clone();
+            let base_url = test_server::serve(move |_| {
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    test_server::Response::new(202)
+                } else {
+                    test_server::Response::new(200)
+                }
+            })
+            .await;
+            let api = Jobs::new();
+
+            let job = api
+                .poll_until(|api| api.job(&base_url), |job| job.status == 200)
+                .interval(Duration::from_secs(60))
+                .max_interval(Duration::from_millis(10))
+                .timeout(Duration::from_secs(5))
+                .run()
+                .await
+                .unwrap();
+            assert_eq!(job.status, 200);
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[test]
+    fn times_out_during_calls() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|_| test_server::Response::new(200)).await;
+            let api = Jobs::new();
+            let started = Instant::now();
+            let error = api
+                .poll_until(
+                    |api| async {
+                        let runtime = api.state().async_runtime();
+                        runtime.sleep(Duration::from_secs(5)).await;
+                        api.job(&base_url).await
+                    },
+                    |_| true,
+                )
+                .timeout(Duration::from_millis(50))
+                .run()
+                .await
+                .unwrap_err();
+            assert!(matches!(error, Error::DeadlineExceeded(timeout) if timeout.as_millis() == 50));
+            assert!(started.elapsed() < Duration::from_secs(5));
+        });
+    }
+
+    #[test]
+    fn grows_intervals() {
+        let (second, minute) = (Duration::from_secs(1), Duration::from_secs(60));
+        assert_eq!(grown(second, 1.5, minute), Duration::from_millis(1500));
+        assert_eq!(grown(Duration::from_secs(50), 2.0, minute), minute);
+        assert_eq!(grown(second, f64::INFINITY, minute), minute);
+        assert_eq!(grown(Duration::MAX, 2.0, Duration::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn reads_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("retry-after", HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(30));
+        headers.insert("retry-after", HeaderValue::from_str(&date).unwrap());
+        let wait = retry_after(&headers).unwrap();
+        assert!(wait > Duration::from_secs(28) && wait <= Duration::from_secs(30));
+        headers.insert("retry-after", HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), None);
+    }
+}
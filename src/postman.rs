@@ -0,0 +1,319 @@
+//! Exporting the endpoints of a client as a Postman collection.
+//!
+//! A [`Collection`] turns the endpoints listed by the `endpoints()` function of a block, see
+//! [`EndpointMeta`], into the requests of a collection in the Postman v2.1 format, which Insomnia
+//! imports as well, e.g. to hand exploratory tooling to teammates who don't use Rust:
+//! - Hosts and base URL parameters at the start of URL templates become the `{{baseUrl}}`
+//!   variable of the collection, which defaults to the first host found unless set with
+//!   [`Collection::base_url`].
+//! - Parameters in the path become path variables, e.g. `:id` for `{id}`.
+//! - Query parameters written as `name={param}` refer to the `{{param}}` variable of the
+//!   collection.
+//!
+//! Request bodies and headers are left to be filled in, as the endpoint metadata doesn't describe
+//! them.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, postman::Collection};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         #![endpoints]
+//!
+//!         fn todo(id: u32) -> String {
+//!            GET "https://example.com/todos/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! let collection = Collection::new("Example API")
+//!     .endpoints(ExampleApi::endpoints())
+//!     .to_json();
+//! assert_eq!(collection["item"][0]["request"]["url"]["raw"], "{{baseUrl}}/todos/:id");
+//! ```
+
+use serde_json::{json, Value};
+
+use crate::endpoint::EndpointMeta;
+
+/// Schema of the Postman collection format.
+pub const SCHEMA: &str = "https://schema.getpostman.com/json/collection/v2.1.0/collection.json";
+
+/// Name of the collection variable holding the base URL.
+const BASE_URL: &str = "baseUrl";
+
+/// A Postman collection of the endpoints of one or more clients, see [`postman`](crate::postman).
+#[derive(Debug, Clone)]
+pub struct Collection {
+    /// Name of the collection.
+    name: String,
+    /// Default value of the `{{baseUrl}}` variable, if set.
+    base_url: Option<String>,
+    /// Endpoints of the collection, in the order they were added.
+    endpoints: Vec<EndpointMeta>,
+}
+
+impl Collection {
+    /// Creates a collection named `name` without requests.
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            base_url: None,
+            endpoints: Vec::new(),
+        }
+    }
+
+    /// Sets the default value of the `{{baseUrl}}` variable.
+    #[must_use]
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
+    /// Adds a request per endpoint, usually those returned by the `endpoints()` function of a
+    /// block of endpoints.
+    #[must_use]
+    pub fn endpoints(mut self, endpoints: &[EndpointMeta]) -> Self {
+        self.endpoints.extend_from_slice(endpoints);
+        self
+    }
+
+    /// Returns the collection as JSON.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        let mut base_url = self.base_url.clone();
+        let mut variables = Vec::new();
+        let mut items = Vec::new();
+        for endpoint in &self.endpoints {
+            let url = Url::parse(endpoint.url);
+            if base_url.is_none() {
+                base_url = url.host.map(str::to_string);
+            }
+            for (_, value) in &url.query {
+                if let Ok(param) = value {
+                    if !variables.contains(param) {
+                        variables.push(*param);
+                    }
+                }
+            }
+            items.push(item(endpoint, &url));
+        }
+        let mut variables = variables
+            .into_iter()
+            .map(|name| json!({ "key": name, "value": "" }))
+            .collect::<Vec<_>>();
+        variables.insert(
+            0,
+            json!({ "key": BASE_URL, "value": base_url.unwrap_or_default() }),
+        );
+        json!({
+            "info": { "name": self.name, "schema": SCHEMA },
+            "item": items,
+            "variable": variables,
+        })
+    }
+}
+
+/// Parts of a URL template.
+struct Url<'a> {
+    /// Scheme and host at the start of the template, if any.
+    host: Option<&'a str>,
+    /// Segments of the path, without leading slash.
+    path: Vec<&'a str>,
+    /// Query parameters with their literal value, or the name of the parameter they are set from.
+    query: Vec<(&'a str, Result<&'a str, &'a str>)>,
+}
+
+impl<'a> Url<'a> {
+    /// Splits `template` into its parts.
+    fn parse(template: &'a str) -> Self {
+        let (host, rest) = if let Some(after) = template
+            .strip_prefix("https://")
+            .or_else(|| template.strip_prefix("http://"))
+        {
+            let end = after.find('/').unwrap_or(after.len());
+            let end = template.len() - after.len() + end;
+            (Some(&template[..end]), &template[end..])
+        } else if template.starts_with('{') {
+            (
+                None,
+                template.find('}').map_or("", |end| &template[end + 1..]),
+            )
+        } else {
+            (None, template)
+        };
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let path = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        let query = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (key, placeholder(value).ok_or(value)))
+            .collect();
+        Self { host, path, query }
+    }
+}
+
+/// Returns the request item of `endpoint`, whose URL template was split into `url`.
+fn item(endpoint: &EndpointMeta, url: &Url<'_>) -> Value {
+    let mut variables = Vec::new();
+    let path = url
+        .path
+        .iter()
+        .map(|segment| match placeholder(segment) {
+            Some(name) => {
+                variables.push(json!({ "key": name, "value": "" }));
+                format!(":{name}")
+            }
+            None => (*segment).to_string(),
+        })
+        .collect::<Vec<_>>();
+    let query = url
+        .query
+        .iter()
+        .map(|(key, value)| match value {
+            Ok(param) => (*key, format!("{{{{{param}}}}}")),
+            Err(literal) => (*key, (*literal).to_string()),
+        })
+        .collect::<Vec<_>>();
+    let mut raw = format!("{{{{{BASE_URL}}}}}/{}", path.join("/"));
+    if !query.is_empty() {
+        let pairs = query
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>();
+        raw = format!("{raw}?{}", pairs.join("&"));
+    }
+    let mut request = json!({
+        "method": endpoint.method,
+        "url": {
+            "raw": raw,
+            "host": [format!("{{{{{BASE_URL}}}}}")],
+            "path": path,
+        },
+    });
+    if !query.is_empty() {
+        let query = query
+            .into_iter()
+            .map(|(key, value)| json!({ "key": key, "value": value }))
+            .collect();
+        request["url"]["query"] = Value::Array(query);
+    }
+    if !variables.is_empty() {
+        request["url"]["variable"] = Value::Array(variables);
+    }
+    if let Some(description) = description(endpoint) {
+        request["description"] = json!(description);
+    }
+    json!({ "name": endpoint.name, "request": request })
+}
+
+/// Returns the scopes and requirements of `endpoint` as a description, if it has any.
+fn description(endpoint: &EndpointMeta) -> Option<String> {
+    let list = |names: &[&str]| {
+        names
+            .iter()
+            .map(|name| format!("`{name}`"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut lines = Vec::new();
+    if !endpoint.scopes.is_empty() {
+        lines.push(format!("Requires the scopes {}.", list(endpoint.scopes)));
+    }
+    if !endpoint.requires.is_empty() {
+        lines.push(format!("Call {} first.", list(endpoint.requires)));
+    }
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+/// Returns the name of the parameter of a `{name}` placeholder.
+fn placeholder(text: &str) -> Option<&str> {
+    let name = text.strip_prefix('{')?.strip_suffix('}')?;
+    Some(name.split(':').next().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Collection;
+    use crate::api;
+
+    api!(pub struct TodoApi);
+
+    #[allow(dead_code)]
+    impl TodoApi {
+        api! {
+            #![endpoints]
+
+            fn todo(id: u32) -> String {
+                GET "https://example.com/todos/{id}"
+            }
+
+            fn search(base_url: &str, query: &str) -> String {
+                GET "{base_url}/todos?q={query}&sort=id"
+            }
+
+            fn delete(id: u32) -> StatusCode {
+                DELETE "https://example.com/todos/{id}" with { scopes: ["todos:write"] }
+            }
+        }
+    }
+
+    #[test]
+    fn exports_collections() {
+        let collection = Collection::new("Todos")
+            .endpoints(TodoApi::endpoints())
+            .to_json();
+        assert_eq!(collection["info"]["name"], "Todos");
+        assert_eq!(
+            collection["variable"],
+            json!([
+                { "key": "baseUrl", "value": "https://example.com" },
+                { "key": "query", "value": "" },
+            ])
+        );
+
+        let todo = &collection["item"][0]["request"];
+        assert_eq!(todo["method"], "GET");
+        assert_eq!(todo["url"]["raw"], "{{baseUrl}}/todos/:id");
+        assert_eq!(todo["url"]["path"], json!(["todos", ":id"]));
+        assert_eq!(
+            todo["url"]["variable"],
+            json!([{ "key": "id", "value": "" }])
+        );
+
+        let search = &collection["item"][1]["request"];
+        assert_eq!(
+            search["url"]["raw"],
+            "{{baseUrl}}/todos?q={{query}}&sort=id"
+        );
+        assert_eq!(
+            search["url"]["query"],
+            json!([
+                { "key": "q", "value": "{{query}}" },
+                { "key": "sort", "value": "id" },
+            ])
+        );
+
+        let delete = &collection["item"][2];
+        assert_eq!(delete["name"], "delete");
+        assert_eq!(
+            delete["request"]["description"],
+            "Requires the scopes `todos:write`."
+        );
+
+        let collection = Collection::new("Todos")
+            .base_url("http://localhost:8080")
+            .endpoints(TodoApi::endpoints())
+            .to_json();
+        assert_eq!(collection["variable"][0]["value"], "http://localhost:8080");
+    }
+}
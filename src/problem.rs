@@ -0,0 +1,189 @@
+//! Problem Details ([RFC 9457], formerly RFC 7807) in error responses.
+//!
+//! Error responses with an `application/problem+json` body fail with
+//! [`Error::Problem`](crate::Error::Problem) carrying the parsed [`ProblemDetails`], instead of
+//! being decoded as the declared return kind. Endpoints returning a `StatusCode` or a `Response`,
+//! and the `_raw` variants, return them as they are.
+//!
+//! [RFC 9457]: https://www.rfc-editor.org/rfc/rfc9457
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, Error, ResultType};
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Order {
+//!     id: u32,
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn order(id: u32) -> Json<Order> {
+//!            GET "https://example.com/orders/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn order(api: &ExampleApi) -> ResultType<Option<Order>> {
+//!     match api.order(1).await {
+//!         Ok(order) => Ok(Some(order)),
+//!         Err(Error::Problem(problem)) if problem.status == Some(404) => Ok(None),
+//!         Err(Error::Problem(problem)) => {
+//!             let balance = problem.extension::<u64>("balance");
+//!             eprintln!("{} (balance: {balance:?})", problem.title.as_deref().unwrap_or_default());
+//!             Err(Error::Problem(problem))
+//!         }
+//!         Err(error) => Err(error),
+//!     }
+//! }
+//! ```
+
+use std::fmt;
+
+use reqwest::header::CONTENT_TYPE;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{Error, ResultType};
+
+/// Media type of Problem Details documents.
+pub const MEDIA_TYPE: &str = "application/problem+json";
+
+/// Problem Details document of an error response, see [`problem`](crate::problem).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProblemDetails {
+    /// URI identifying the type of the problem, `about:blank` if the server sent none.
+    #[serde(rename = "type", default = "about_blank")]
+    pub problem_type: String,
+    /// Short summary of the type of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// HTTP status of the response, taken from the response if the document has none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Explanation of this occurrence of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// URI identifying this occurrence of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Further members defined by the type of the problem.
+    #[serde(flatten)]
+    pub extensions: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ProblemDetails {
+    /// Returns the extension member `name` deserialized as `T`, if present and valid.
+    #[must_use]
+    pub fn extension<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        serde_json::from_value(self.extensions.get(name)?.clone()).ok()
+    }
+}
+
+impl fmt::Display for ProblemDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request failed")?;
+        if let Some(status) = self.status {
+            write!(f, " with status {status}")?;
+        }
+        write!(
+            f,
+            ": {}",
+            self.title.as_deref().unwrap_or(&self.problem_type)
+        )?;
+        if let Some(detail) = &self.detail {
+            write!(f, " ({detail})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Default `type` of a Problem Details document.
+fn about_blank() -> String {
+    "about:blank".to_string()
+}
+
+/// Fails with [`Error::Problem`] if `response` is an error response with a Problem Details body,
+/// returning it unread otherwise.
+pub(crate) async fn check(response: reqwest::Response) -> ResultType<reqwest::Response> {
+    let status = response.status();
+    let is_problem = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case(MEDIA_TYPE));
+    if !is_problem || !(status.is_client_error() || status.is_server_error()) {
+        return Ok(response);
+    }
+    let mut problem: ProblemDetails =
+        serde_json::from_slice(&response.bytes().await?).map_err(Error::Json)?;
+    problem.status = problem.status.or(Some(status.as_u16()));
+    Err(Error::Problem(Box::new(problem)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{api, test_server, Api, Error};
+
+    api!(struct Orders);
+
+    impl Orders {
+        api! {
+            fn order(&self, base_url: &str, id: u32) -> Json<u32> {
+                GET "{base_url}/orders/{id}"
+            }
+
+            fn status(&self, base_url: &str, id: u32) -> StatusCode {
+                GET "{base_url}/orders/{id}"
+            }
+        }
+    }
+
+    #[test]
+    fn surfaces_problem_details() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.path.as_str() {
+                "/orders/1" => test_server::Response::new(403)
+                    .header("content-type", "application/problem+json; charset=utf-8")
+                    .body(
+                        r#"{
+                            "type": "https://example.com/probs/out-of-credit",
+                            "title": "You do not have enough credit.",
+                            "detail": "Your current balance is 30, but that costs 50.",
+                            "balance": 30
+                        }"#,
+                    ),
+                "/orders/2" => test_server::Response::new(200).json("2"),
+                _ => test_server::Response::new(404).json(r#"{"error": "not found"}"#),
+            })
+            .await;
+            let api = Orders::new();
+
+            let error = api.order(&base_url, 1).await.unwrap_err();
+            let problem = match &error {
+                Error::Problem(problem) => problem,
+                error => panic!("expected a problem, got {error:?}"),
+            };
+            assert_eq!(
+                problem.problem_type,
+                "https://example.com/probs/out-of-credit"
+            );
+            assert_eq!(problem.status, Some(403));
+            assert_eq!(problem.extension::<u32>("balance"), Some(30));
+            assert_eq!(
+                error.to_string(),
+                "request failed with status 403: You do not have enough credit. \
+                 (Your current balance is 30, but that costs 50.)"
+            );
+
+            assert_eq!(api.order(&base_url, 2).await.unwrap(), 2);
+            assert_eq!(api.status(&base_url, 1).await.unwrap(), 403);
+            assert!(matches!(
+                api.order(&base_url, 3).await,
+                Err(Error::Client(_))
+            ));
+        });
+    }
+}
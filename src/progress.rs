@@ -0,0 +1,133 @@
+//! Progress of streamed uploads and downloads, e.g. for progress bars.
+//!
+//! A callback given to [`Downloading::on_progress`](crate::response::Downloading::on_progress),
+//! or with the `stream` feature to `Upload::on_progress`, is called with a [`Progress`] after
+//! every chunk of the body is transferred. It belongs to that one call, so concurrent calls report
+//! their progress separately.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, progress::Progress, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn artifact(name: &str) -> Download {
+//!            GET "https://example.com/artifacts/{name}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn fetch_artifact(api: &ExampleApi) -> ResultType<()> {
+//!     api.artifact("release.tar.gz")
+//!         .await?
+//!         .on_progress(|progress: Progress| {
+//!             let percent = progress.fraction().map(|fraction| fraction * 100.0);
+//!             eprintln!(
+//!                 "{} bytes, {percent:?}%, {:.0} bytes/s",
+//!                 progress.transferred,
+//!                 progress.throughput()
+//!             );
+//!         })
+//!         .save("target/release.tar.gz")
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::time::{Duration, Instant};
+
+/// Bytes of a body transferred so far, see [`progress`](crate::progress).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Bytes transferred so far.
+    pub transferred: u64,
+    /// Size of the whole body, if known.
+    pub total: Option<u64>,
+    /// Time since the transfer started.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Returns the average number of bytes transferred per second so far.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn throughput(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.transferred as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the share of the body transferred so far, between `0.0` and `1.0`, if its size is
+    /// known.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total? {
+            0 => Some(1.0),
+            total => Some((self.transferred as f64 / total as f64).min(1.0)),
+        }
+    }
+}
+
+/// Counts the bytes of a transfer and reports them to a callback.
+pub(crate) struct Tracker<F> {
+    /// Called after every chunk.
+    callback: F,
+    /// When the transfer started.
+    started: Instant,
+    /// Bytes transferred so far.
+    transferred: u64,
+    /// Size of the whole body, if known.
+    total: Option<u64>,
+}
+
+impl<F: FnMut(Progress)> Tracker<F> {
+    /// Starts tracking a transfer of `total` bytes, if known.
+    pub(crate) fn new(callback: F, total: Option<u64>) -> Self {
+        Self {
+            callback,
+            started: Instant::now(),
+            transferred: 0,
+            total,
+        }
+    }
+
+    /// Reports that `length` more bytes were transferred.
+    pub(crate) fn advance(&mut self, length: usize) {
+        self.transferred += length as u64;
+        (self.callback)(Progress {
+            transferred: self.transferred,
+            total: self.total,
+            elapsed: self.started.elapsed(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Progress;
+
+    #[test]
+    fn computes_rates() {
+        let progress = Progress {
+            transferred: 500,
+            total: Some(2_000),
+            elapsed: Duration::from_millis(250),
+        };
+        assert!((progress.throughput() - 2_000.0).abs() < f64::EPSILON);
+        assert_eq!(progress.fraction(), Some(0.25));
+        let unknown = Progress {
+            total: None,
+            elapsed: Duration::ZERO,
+            ..progress
+        };
+        assert_eq!((unknown.throughput(), unknown.fraction()), (0.0, None));
+    }
+}
@@ -0,0 +1,152 @@
+//! Transport-independent parts of the declarative layer.
+//!
+//! The descriptions of endpoints generated by the [`api!`](crate::api) macro, the pages of
+//! paginated collections and the requests and responses exchanged with a
+//! [`Transport`](crate::transport::Transport) only use the types of the [`http`] crate, so they
+//! can be reused over any HTTP stack. The [`transport`](crate::transport) module describes how
+//! requests are sent, with reqwest unless another transport is set.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+
+/// Request handed to a [`Transport`](crate::transport::Transport), with its whole body.
+pub type Request = http::Request<Bytes>;
+
+/// Response returned by a [`Transport`](crate::transport::Transport), with its whole body.
+pub type Response = http::Response<Bytes>;
+
+/// Trailers of a response, sent after its body.
+///
+/// reqwest doesn't expose trailers, so they are only available with a
+/// [`Transport`](crate::transport::Transport) which receives them and inserts them into the
+/// extensions of its [`Response`]. Endpoints read them with
+/// [`response::trailers`](crate::response::trailers) or
+/// [`ResponseParts::trailers`](crate::response::ResponseParts::trailers), and map gRPC statuses
+/// in them to errors with `with { grpc_status }`, see [`grpc`](crate::grpc).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trailers(pub http::HeaderMap);
+
+/// Static description of an endpoint declared with the [`api!`](crate::api) macro.
+#[derive(Debug, Clone)]
+pub struct EndpointSpec {
+    /// Name of the generated method.
+    pub name: &'static str,
+    /// HTTP method of the endpoint.
+    pub method: http::Method,
+    /// URL template as written in the declaration, before any parameters are interpolated.
+    pub url: &'static str,
+    /// Timeout of a single request to the endpoint, declared with `with { timeout: 30s }` or
+    /// `TIMEOUT 30s`.
+    pub timeout: Option<Duration>,
+    /// Time a whole call of the endpoint has to finish in, including retries and reading the
+    /// body, declared with `with { deadline: 2s }`, see [`deadline`](crate::deadline).
+    pub deadline: Option<Duration>,
+    /// How often the request is sent again after transient failures, declared with
+    /// `with { retry: default }`, see [`retry`](crate::retry).
+    pub retry: Option<crate::retry::RetryPolicy>,
+    /// Whether the endpoint is safe to repeat, which is the default for idempotent methods and
+    /// can be declared with `with { idempotent }`. Identical requests in flight of endpoints with
+    /// other methods declared idempotent, such as searches sent as `POST`, are coalesced like
+    /// `GET` requests, see [`ApiState::deduplicate`](crate::ApiState::deduplicate).
+    pub idempotent: bool,
+    /// How the HTTP cache answers requests of the endpoint unless they select another mode,
+    /// declared with `with { cache: force_refresh }`, see [`cache`](crate::cache).
+    pub cache_mode: Option<crate::cache::CacheMode>,
+    /// How long responses of the endpoint stay fresh in the HTTP cache if their headers don't
+    /// say, declared with `with { cache: 5m }`, see [`cache`](crate::cache).
+    pub cache_lifetime: Option<Duration>,
+    /// JSON pointer to the data read by the return kind, declared as its argument, e.g.
+    /// `JsonArrayStream<T>("/items")`.
+    pub pointer: Option<&'static str>,
+    /// Scopes the token needs for the endpoint, declared with `with { scopes: ["repo"] }`, see
+    /// [`permissions`](crate::permissions).
+    pub scopes: &'static [&'static str],
+    /// Endpoints which have to succeed before this one is called, declared with
+    /// `with { requires: [login] }`.
+    pub requires: &'static [&'static str],
+    /// Whether a `grpc-status` other than `0` in the trailers or headers of responses is an
+    /// error, declared with `with { grpc_status }`, see [`grpc`](crate::grpc).
+    pub grpc_status: bool,
+    /// Where the response carries the token to send with later requests, declared with
+    /// `with { login: token("/access_token") }`, see [`auth`](crate::auth#login-endpoints).
+    pub login: Option<Login>,
+}
+
+/// Where the response of a login endpoint carries the token, see
+/// [`auth`](crate::auth#login-endpoints).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Login {
+    /// The string at a JSON pointer into the body, declared as `token("/access_token")`.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    Token(&'static str),
+    /// The value of a header, declared as `header("X-Auth-Token")`.
+    Header(&'static str),
+    /// The value of a cookie set by the response, declared as `cookie("session")`.
+    Cookie(&'static str),
+}
+
+impl std::fmt::Display for Login {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "json")]
+            Login::Token(pointer) => write!(f, "the JSON body at `{pointer}`"),
+            Login::Header(name) => write!(f, "the `{name}` header"),
+            Login::Cookie(name) => write!(f, "the `{name}` cookie"),
+        }
+    }
+}
+
+/// Summary of an endpoint declared with the [`api!`](crate::api) macro, e.g. to generate
+/// documentation, `OpenAPI` stubs or command line help from a client.
+///
+/// Blocks of endpoints starting with `#![endpoints]` generate an `endpoints()` function listing
+/// them in declaration order. Other blocks of the same type, which would define it again, name
+/// their listing instead, e.g. `#![endpoints(admin)]` generates `admin_endpoints()`. Blocks
+/// without either generate no listing. The `_with`, `_raw` and `build_*_request` methods
+/// generated alongside the endpoints are not listed.
+/// ```rust
+/// use api_client::{api, Api};
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         #![endpoints]
+///
+///         fn todo(id: u32) -> String {
+///            GET "https://example.com/todos/{id}"
+///         }
+///     }
+/// }
+///
+/// for endpoint in ExampleApi::endpoints() {
+///     println!("{} {} {:?}", endpoint.method, endpoint.url, endpoint.params);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndpointMeta {
+    /// Name of the generated method.
+    pub name: &'static str,
+    /// HTTP method of the endpoint, e.g. `GET`.
+    pub method: &'static str,
+    /// URL template as written in the declaration.
+    pub url: &'static str,
+    /// Names of the parameters of the generated method, without `&self`.
+    pub params: &'static [&'static str],
+    /// Scopes the token needs for the endpoint, see [`permissions`](crate::permissions).
+    pub scopes: &'static [&'static str],
+    /// Endpoints which have to succeed before this one is called, declared with
+    /// `with { requires: [login] }`.
+    pub requires: &'static [&'static str],
+}
+
+/// A page of an offset-paginated collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    /// Index of the first item of the page.
+    pub offset: usize,
+    /// Number of items on the page, the last page may be shorter than the others.
+    pub limit: usize,
+}
@@ -0,0 +1,334 @@
+//! Proxies of the clients created for an [`ApiState`](crate::ApiState).
+//!
+//! A [`ProxyConfig`] sends requests through HTTP, HTTPS or, with the `socks` feature, SOCKS5
+//! proxies, optionally with credentials, instead of the proxy configured by the system. Whether a
+//! request bypasses the proxy is decided from its URL, by `NO_PROXY` style host, domain and
+//! network patterns and by a custom predicate, e.g. to reach internal hosts directly while
+//! scraping through a proxy. It is set with [`ApiState::proxy`](crate::ApiState::proxy), or the
+//! `proxy` method of the builders of structs generated by the [`api!`](crate::api) macro. Clients
+//! passed to a builder explicitly are used as they are.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, proxy::ProxyConfig};
+//!
+//! api!(pub struct ScrapingApi);
+//!
+//! # fn main() -> api_client::ResultType<()> {
+//! let proxy = ProxyConfig::new()
+//!     .all("http://proxy.corp.example:3128")?
+//!     .basic_auth("user", "secret")
+//!     .bypass("localhost, .corp.example, 10.0.0.0/8");
+//! let api = ScrapingApi::builder().proxy(proxy).build().unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fmt, net::IpAddr, sync::Arc};
+
+use reqwest::Url;
+
+use crate::{Error, ResultType};
+
+/// Predicate deciding whether a request to a URL bypasses the proxy.
+type BypassFn = dyn Fn(&Url) -> bool + Send + Sync;
+
+/// Proxies of the clients created for a state and the requests which bypass them, see
+/// [`proxy`](self).
+#[derive(Clone, Default)]
+pub struct ProxyConfig {
+    /// Proxy of requests to `http` URLs.
+    http: Option<Url>,
+    /// Proxy of requests to `https` URLs.
+    https: Option<Url>,
+    /// Patterns of the hosts requests to which bypass the proxy.
+    bypass: Vec<Bypass>,
+    /// Predicate deciding whether other requests bypass the proxy, if any.
+    bypass_if: Option<Arc<BypassFn>>,
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("http", &self.http.as_ref().map(redacted))
+            .field("https", &self.https.as_ref().map(redacted))
+            .field("bypass", &self.bypass)
+            .field("bypass_if", &self.bypass_if.is_some())
+            .finish()
+    }
+}
+
+impl ProxyConfig {
+    /// Creates a configuration sending every request directly.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            http: None,
+            https: None,
+            bypass: Vec::new(),
+            bypass_if: None,
+        }
+    }
+
+    /// Sends all requests through the proxy at `url`, e.g. `http://proxy:3128` or, with the
+    /// `socks` feature, `socks5://proxy:1080`. `socks5h` proxies also resolve host names.
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidProxy`] if `url` is not a proxy URL, or if it is a SOCKS proxy and
+    /// the `socks` feature is not enabled.
+    pub fn all(self, url: &str) -> ResultType<Self> {
+        let url = parse(url)?;
+        Ok(Self {
+            http: Some(url.clone()),
+            https: Some(url),
+            ..self
+        })
+    }
+
+    /// Sends requests to `http` URLs through the proxy at `url`, see [`ProxyConfig::all`].
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidProxy`] if `url` is not a proxy URL.
+    pub fn http(mut self, url: &str) -> ResultType<Self> {
+        self.http = Some(parse(url)?);
+        Ok(self)
+    }
+
+    /// Sends requests to `https` URLs through the proxy at `url`, see [`ProxyConfig::all`].
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidProxy`] if `url` is not a proxy URL.
+    pub fn https(mut self, url: &str) -> ResultType<Self> {
+        self.https = Some(parse(url)?);
+        Ok(self)
+    }
+
+    /// Authenticates with `username` and `password` to the proxies set so far, with the
+    /// `Proxy-Authorization` header for HTTP proxies and the SOCKS5 handshake for SOCKS proxies.
+    #[must_use]
+    pub fn basic_auth(mut self, username: &str, password: &str) -> Self {
+        for url in self.http.iter_mut().chain(self.https.iter_mut()) {
+            // Proxy URLs always have a host, so they accept credentials.
+            let _ = url.set_username(username);
+            let _ = url.set_password(Some(password));
+        }
+        self
+    }
+
+    /// Sends requests to the hosts matching `patterns` directly, a comma separated list in the
+    /// format of the `NO_PROXY` environment variable.
+    ///
+    /// A pattern is `*`, matching every host, a domain such as `example.com` or `.example.com`,
+    /// matching it and its subdomains, an IP address, or a network such as `10.0.0.0/8`.
+    #[must_use]
+    pub fn bypass(mut self, patterns: &str) -> Self {
+        self.bypass.extend(
+            patterns
+                .split(|separator: char| separator == ',' || separator.is_whitespace())
+                .filter(|pattern| !pattern.is_empty())
+                .map(Bypass::parse),
+        );
+        self
+    }
+
+    /// Sends the requests to the URLs for which `bypass` returns `true` directly, e.g. to exempt
+    /// some paths or ports.
+    #[must_use]
+    pub fn bypass_if(mut self, bypass: impl Fn(&Url) -> bool + Send + Sync + 'static) -> Self {
+        self.bypass_if = Some(Arc::new(bypass));
+        self
+    }
+
+    /// Returns the proxy a request to `url` is sent through, or `None` if it is sent directly.
+    #[must_use]
+    pub fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let proxy = match url.scheme() {
+            "http" => self.http.as_ref(),
+            "https" => self.https.as_ref(),
+            _ => None,
+        }?;
+        let bypassed = self.bypass.iter().any(|bypass| bypass.matches(url))
+            || self.bypass_if.as_ref().is_some_and(|bypass| bypass(url));
+        (!bypassed).then(|| proxy.clone())
+    }
+
+    /// Returns the proxy of a reqwest client, which only uses the proxies of this configuration.
+    pub(crate) fn reqwest_proxy(&self) -> reqwest::Proxy {
+        let config = self.clone();
+        reqwest::Proxy::custom(move |url| config.proxy_for(url))
+    }
+}
+
+/// Pattern of the hosts requests to which bypass the proxy, see [`ProxyConfig::bypass`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Bypass {
+    /// Every host.
+    All,
+    /// A domain and its subdomains, in lowercase.
+    Domain(String),
+    /// The addresses whose first bits equal those of an address.
+    Network(IpAddr, u8),
+}
+
+impl Bypass {
+    /// Parses a pattern of [`ProxyConfig::bypass`].
+    fn parse(pattern: &str) -> Self {
+        if pattern == "*" {
+            return Bypass::All;
+        }
+        let (address, prefix) = match pattern.split_once('/') {
+            Some((address, prefix)) => (address, prefix.parse().ok()),
+            None => (pattern, None),
+        };
+        match address
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+        {
+            Ok(address @ IpAddr::V4(_)) => Bypass::Network(address, prefix.unwrap_or(32).min(32)),
+            Ok(address @ IpAddr::V6(_)) => Bypass::Network(address, prefix.unwrap_or(128).min(128)),
+            Err(_) => Bypass::Domain(
+                pattern
+                    .trim_start_matches("*.")
+                    .trim_start_matches('.')
+                    .to_ascii_lowercase(),
+            ),
+        }
+    }
+
+    /// Returns whether requests to `url` bypass the proxy.
+    fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => return false,
+        };
+        match (self, host.parse()) {
+            (Bypass::All, _) => true,
+            (Bypass::Domain(domain), Err(_)) => {
+                let host = host.to_ascii_lowercase();
+                host == *domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|subdomain| subdomain.ends_with('.'))
+            }
+            (Bypass::Network(IpAddr::V4(network), prefix), Ok(IpAddr::V4(address))) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(*prefix)).unwrap_or(0);
+                u32::from(*network) & mask == u32::from(address) & mask
+            }
+            (Bypass::Network(IpAddr::V6(network), prefix), Ok(IpAddr::V6(address))) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(*prefix)).unwrap_or(0);
+                u128::from(*network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses and checks the URL of a proxy.
+fn parse(url: &str) -> ResultType<Url> {
+    let invalid = |error: &dyn fmt::Display| Error::InvalidProxy(format!("{url}: {error}"));
+    reqwest::Proxy::all(url).map_err(|error| invalid(&error))?;
+    Url::parse(url).map_err(|error| invalid(&error))
+}
+
+/// Returns `url` without its password, for debug output.
+fn redacted(url: &Url) -> String {
+    let mut url = url.clone();
+    if url.password().is_some() {
+        let _ = url.set_password(Some("redacted"));
+    }
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use super::ProxyConfig;
+    use crate::{api, test_server, ApiState, Error};
+
+    api!(struct Scraper);
+
+    impl Scraper {
+        api! {
+            fn page(&self, url: &str) -> String {
+                GET "{url}"
+            }
+        }
+    }
+
+    /// Returns the proxy `config` sends requests to `url` through, if any.
+    fn proxy_for(config: &ProxyConfig, url: &str) -> Option<String> {
+        config
+            .proxy_for(&Url::parse(url).unwrap())
+            .map(String::from)
+    }
+
+    #[test]
+    fn bypasses_hosts() {
+        let config = ProxyConfig::new()
+            .https("http://proxy:3128")
+            .unwrap()
+            .bypass("localhost, .corp.example,10.0.0.0/8 ::1")
+            .bypass_if(|url| url.path().starts_with("/direct"));
+        let proxied = Some("http://proxy:3128/".to_string());
+        assert_eq!(proxy_for(&config, "https://example.com/"), proxied);
+        assert_eq!(proxy_for(&config, "https://notcorp.example/"), proxied);
+        assert_eq!(proxy_for(&config, "https://11.0.0.1/"), proxied);
+        assert_eq!(proxy_for(&config, "http://example.com/"), None);
+        for direct in [
+            "https://localhost:8080/",
+            "https://corp.example/",
+            "https://Wiki.Corp.Example/",
+            "https://10.1.2.3/",
+            "https://[::1]/",
+            "https://example.com/direct/page",
+        ] {
+            assert_eq!(proxy_for(&config, direct), None, "{direct}");
+        }
+
+        assert!(matches!(
+            ProxyConfig::new().all("not a url"),
+            Err(Error::InvalidProxy(_))
+        ));
+        #[cfg(not(feature = "socks"))]
+        assert!(ProxyConfig::new().all("socks5://proxy:1080").is_err());
+        let config = ProxyConfig::new()
+            .all("http://proxy:3128")
+            .unwrap()
+            .basic_auth("user", "secret");
+        assert!(!format!("{config:?}").contains("secret"));
+    }
+
+    #[test]
+    fn sends_requests_through_proxies() {
+        tokio_test::block_on(async {
+            let proxy = test_server::serve(|request| {
+                let authorization = request.header("proxy-authorization").unwrap_or_default();
+                test_server::Response::new(200).body(format!("{} {authorization}", request.path))
+            })
+            .await;
+            let direct =
+                test_server::serve(|_| test_server::Response::new(200).body("direct")).await;
+            let config = ProxyConfig::new()
+                .all(&proxy)
+                .unwrap()
+                .basic_auth("user", "pass")
+                .bypass("127.0.0.1");
+            let api = Scraper::builder().proxy(config.clone()).build().unwrap();
+            assert_eq!(
+                api.page("http://api.example.invalid/items?page=2")
+                    .await
+                    .unwrap(),
+                "http://api.example.invalid/items?page=2 Basic dXNlcjpwYXNz"
+            );
+            assert_eq!(api.page(&direct).await.unwrap(), "direct");
+
+            let api = Scraper::builder()
+                .state(ApiState::new().proxy(config))
+                .build()
+                .unwrap();
+            assert_eq!(api.page(&direct).await.unwrap(), "direct");
+        });
+    }
+}
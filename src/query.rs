@@ -0,0 +1,878 @@
+//! Optional query parameters of endpoints.
+//!
+//! Parameters listed in the `query` option of an endpoint are appended to its URL as
+//! `name=value` pairs, so they don't have to be written into the URL template:
+//! - `Option` parameters are left out entirely when they are `None`, instead of being sent
+//!   empty.
+//! - `..name` spreads a parameter which serializes as a struct or map into one pair per field,
+//!   again skipping `None` fields. [`Params`] builds such a parameter call by call, e.g. for
+//!   endpoints with many optional filters which don't deserve a struct of their own.
+//!
+//! # Sequences
+//! Parameters which serialize as sequences, e.g. `Vec<T>` or `&[T]`, are encoded in the
+//! [`ArrayFormat`] of the endpoint, selected with the `arrays` option, or else the one of its
+//! [`ApiState`](crate::ApiState::array_format), which repeats the key by default:
+//!
+//! | Option            | Format                   | `ids: vec![1, 2]`   |
+//! |-------------------|--------------------------|---------------------|
+//! | `arrays: repeat`  | [`ArrayFormat::Repeat`]   | `ids=1&ids=2`       |
+//! | `arrays: comma`   | [`ArrayFormat::Comma`]    | `ids=1,2`           |
+//! | `arrays: brackets`| [`ArrayFormat::Brackets`] | `ids[]=1&ids[]=2`   |
+//! | `arrays: pipes`   | [`ArrayFormat::Pipes`]    | `ids=1\|2`          |
+//!
+//! Empty sequences are left out like `None`. Elements are escaped before they are joined, so a
+//! comma or pipe within an element doesn't split it.
+//!
+//! # Nested values
+//! Other values than scalars and sequences of scalars are encoded with
+//! [`serde_urlencoded`](https://docs.rs/serde_urlencoded), which rejects them. With the `qs`
+//! feature they are encoded with [`serde_qs`](https://docs.rs/serde_qs) instead, so nested
+//! structs, maps and sequences get bracketed keys, e.g.
+//! `filter[status]=open&ids[0]=1&ids[1]=2`, as Rails, PHP and the `qs` package of Node.js parse
+//! them. A spread parameter with a nested field is encoded this way as a whole, ignoring the
+//! [`ArrayFormat`]. Values which can't be encoded fail with [`Error::InvalidQuery`] without
+//! sending the request.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, query::Params, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn todos(page: Option<u32>, filters: Params) -> String {
+//!            GET "https://example.com/todos"
+//!            with { query: [page, ..filters] }
+//!         }
+//!
+//!         fn issues(ids: Vec<u32>) -> String {
+//!            GET "https://example.com/issues"
+//!            with { query: [ids], arrays: comma }
+//!         }
+//!     }
+//! }
+//!
+//! async fn open_todos(api: &ExampleApi) -> ResultType<String> {
+//!     // GET https://example.com/todos?completed=false
+//!     let filters = Params::new()
+//!         .param("completed", false)
+//!         .param_opt("user", None::<u32>);
+//!     api.todos(None, filters).await
+//! }
+//!
+//! async fn issues(api: &ExampleApi) -> ResultType<String> {
+//!     // GET https://example.com/issues?ids=1,2
+//!     api.issues(vec![1, 2]).await
+//! }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write},
+};
+
+use serde::{
+    ser::{
+        self, Impossible, SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple,
+        SerializeTupleStruct,
+    },
+    Serialize, Serializer,
+};
+
+use crate::{ApiState, Error, ResultType};
+
+/// Encoding of parameters which serialize as sequences, see [`query`](crate::query#sequences).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArrayFormat {
+    /// Repeats the key for every element, e.g. `ids=1&ids=2`.
+    #[default]
+    Repeat,
+    /// Joins the elements with commas, e.g. `ids=1,2`.
+    Comma,
+    /// Repeats the key with empty brackets for every element, e.g. `ids[]=1&ids[]=2`.
+    Brackets,
+    /// Joins the elements with pipes, e.g. `ids=1|2`.
+    Pipes,
+}
+
+impl ArrayFormat {
+    /// Encodes the parameter `name` with the flattened `value`, or returns an empty string if it
+    /// has no values.
+    fn encode(self, name: &str, value: &Flat) -> String {
+        let name = escape(name);
+        let values = value
+            .values
+            .iter()
+            .map(|value| escape(value))
+            .collect::<Vec<_>>();
+        let key = match self {
+            _ if values.is_empty() => return String::new(),
+            Self::Comma if value.sequence => return format!("{name}={}", values.join(",")),
+            Self::Pipes if value.sequence => return format!("{name}={}", values.join("|")),
+            Self::Brackets if value.sequence => format!("{name}[]"),
+            _ => name,
+        };
+        values
+            .iter()
+            .map(|value| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+}
+
+/// Query parameters built call by call, see [`query`](crate::query).
+///
+/// Spread into the query of an endpoint with `..name` in its `query` option.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+    /// Creates an empty set of parameters.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the parameter `name` set to `value`.
+    #[must_use]
+    pub fn param(mut self, name: impl Into<String>, value: impl Display) -> Self {
+        self.0.push((name.into(), value.to_string()));
+        self
+    }
+
+    /// Adds the parameter `name` if `value` is set, and leaves it out if it is `None`.
+    #[must_use]
+    pub fn param_opt(self, name: impl Into<String>, value: Option<impl Display>) -> Self {
+        match value {
+            Some(value) => self.param(name, value),
+            None => self,
+        }
+    }
+}
+
+impl Serialize for Params {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, value)| (name, value)))
+    }
+}
+
+/// Appends the parameter `name` to the query of `url`, unless `value` is `None`.
+///
+/// Used internally in the [`api!`](crate::api) macro.
+///
+/// # Errors
+/// Returns [`Error::InvalidQuery`] if `value` can't be encoded as a query parameter.
+#[doc(hidden)]
+pub fn append<T: Serialize + ?Sized>(
+    url: &mut String,
+    name: &str,
+    value: &T,
+    format: ArrayFormat,
+) -> ResultType<()> {
+    let query = match flatten(value) {
+        Some(value) => format.encode(name, &value),
+        None => encode(&BTreeMap::from([(name, value)]))
+            .map_err(|error| Error::InvalidQuery(format!("`{name}`: {error}")))?,
+    };
+    push(url, &query);
+    Ok(())
+}
+
+/// Appends the fields of `params` to the query of `url`, skipping `None` fields.
+///
+/// Used internally in the [`api!`](crate::api) macro.
+///
+/// # Errors
+/// Returns [`Error::InvalidQuery`] if `params` can't be encoded as query parameters.
+#[doc(hidden)]
+pub fn extend<T: Serialize + ?Sized>(
+    url: &mut String,
+    params: &T,
+    format: ArrayFormat,
+) -> ResultType<()> {
+    let mut fields = Vec::new();
+    let query = if params.serialize(Fields(&mut fields)).is_ok() {
+        fields
+            .iter()
+            .map(|(name, value)| format.encode(name, value))
+            .filter(|pair| !pair.is_empty())
+            .collect::<Vec<_>>()
+            .join("&")
+    } else {
+        encode(params).map_err(Error::InvalidQuery)?
+    };
+    push(url, &query);
+    Ok(())
+}
+
+/// Returns the format of sequences of an endpoint, the one of `state` if it doesn't select one.
+///
+/// Used internally in the [`api!`](crate::api) macro.
+#[doc(hidden)]
+#[must_use]
+pub fn array_format(state: &ApiState, endpoint: Option<ArrayFormat>) -> ArrayFormat {
+    endpoint.unwrap_or_else(|| state.default_array_format())
+}
+
+/// Encodes the fields of `params` as a query.
+#[cfg(not(feature = "qs"))]
+fn encode<T: Serialize + ?Sized>(params: &T) -> Result<String, String> {
+    serde_urlencoded::to_string(params).map_err(|error| error.to_string())
+}
+
+/// Encodes the fields of `params` as a query, with bracketed keys for nested values.
+#[cfg(feature = "qs")]
+fn encode<T: Serialize + ?Sized>(params: &T) -> Result<String, String> {
+    serde_qs::to_string(&params).map_err(|error| error.to_string())
+}
+
+/// Escapes `text` for a query, as `application/x-www-form-urlencoded` does.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'*' => {
+                escaped.push(char::from(byte));
+            }
+            b' ' => escaped.push('+'),
+            _ => {
+                let _ = write!(escaped, "%{byte:02X}");
+            }
+        }
+    }
+    escaped
+}
+
+/// Scalar values of a parameter, flattened from a scalar, an `Option` or a sequence of scalars.
+#[derive(Debug, Default)]
+struct Flat {
+    /// Values in the order they were serialized, empty if the parameter is `None`.
+    values: Vec<String>,
+    /// Whether the parameter is a sequence, which is encoded in an [`ArrayFormat`].
+    sequence: bool,
+}
+
+/// Flattens `value`, or returns `None` if it is nested and left to [`encode`].
+fn flatten<T: Serialize + ?Sized>(value: &T) -> Option<Flat> {
+    let mut flat = Flat::default();
+    value.serialize(Values(&mut flat)).ok()?;
+    Some(flat)
+}
+
+/// Error of the serializers flattening parameters, which are nested or of unsupported types.
+#[derive(Debug)]
+struct Nested;
+
+impl Display for Nested {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("nested value")
+    }
+}
+
+impl std::error::Error for Nested {}
+
+impl ser::Error for Nested {
+    fn custom<T: Display>(_: T) -> Self {
+        Self
+    }
+}
+
+/// Implements the serializer methods of `$ty` which push scalars as strings to `self.0.values`.
+macro_rules! scalars {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method(self, value: $ty) -> Result<(), Nested> {
+                self.0.values.push(value.to_string());
+                Ok(())
+            }
+        )*
+    };
+}
+
+/// Serializer flattening a parameter into [`Flat`].
+struct Values<'a>(&'a mut Flat);
+
+impl<'a> Serializer for Values<'a> {
+    type Ok = ();
+    type Error = Nested;
+    type SerializeSeq = Elements<'a>;
+    type SerializeTuple = Elements<'a>;
+    type SerializeTupleStruct = Elements<'a>;
+    type SerializeTupleVariant = Impossible<(), Nested>;
+    type SerializeMap = Impossible<(), Nested>;
+    type SerializeStruct = Impossible<(), Nested>;
+    type SerializeStructVariant = Impossible<(), Nested>;
+
+    scalars! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_none(self) -> Result<(), Nested> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Nested> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<(), Nested> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Nested> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Elements<'a>, Nested> {
+        if self.0.sequence {
+            return Err(Nested);
+        }
+        self.0.sequence = true;
+        Ok(Elements(self.0))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Elements<'a>, Nested> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<Elements<'a>, Nested> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Nested> {
+        Err(Nested)
+    }
+}
+
+/// Serializer of the elements of a sequence parameter, which have to be scalars.
+struct Elements<'a>(&'a mut Flat);
+
+impl SerializeSeq for Elements<'_> {
+    type Ok = ();
+    type Error = Nested;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Nested> {
+        value.serialize(Values(self.0))
+    }
+
+    fn end(self) -> Result<(), Nested> {
+        Ok(())
+    }
+}
+
+impl SerializeTuple for Elements<'_> {
+    type Ok = ();
+    type Error = Nested;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Nested> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Nested> {
+        Ok(())
+    }
+}
+
+impl SerializeTupleStruct for Elements<'_> {
+    type Ok = ();
+    type Error = Nested;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Nested> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<(), Nested> {
+        Ok(())
+    }
+}
+
+/// Serializer flattening the fields of a spread parameter, which has to be a struct or map.
+struct Fields<'a>(&'a mut Vec<(String, Flat)>);
+
+impl<'a> Serializer for Fields<'a> {
+    type Ok = ();
+    type Error = Nested;
+    type SerializeSeq = Impossible<(), Nested>;
+    type SerializeTuple = Impossible<(), Nested>;
+    type SerializeTupleStruct = Impossible<(), Nested>;
+    type SerializeTupleVariant = Impossible<(), Nested>;
+    type SerializeMap = FieldMap<'a>;
+    type SerializeStruct = FieldMap<'a>;
+    type SerializeStructVariant = Impossible<(), Nested>;
+
+    fn serialize_bool(self, _: bool) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_i8(self, _: i8) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_i16(self, _: i16) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_i32(self, _: i32) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_i64(self, _: i64) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_u8(self, _: u8) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_u16(self, _: u16) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_u32(self, _: u32) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_u64(self, _: u64) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_char(self, _: char) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_str(self, _: &str) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_none(self) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _: &T) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_unit(self) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+    ) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Nested> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Nested> {
+        Err(Nested)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<FieldMap<'a>, Nested> {
+        Ok(FieldMap {
+            fields: self.0,
+            name: None,
+        })
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<FieldMap<'a>, Nested> {
+        self.serialize_map(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Nested> {
+        Err(Nested)
+    }
+}
+
+/// Serializer of the fields of a spread parameter, whose names have to be scalars.
+struct FieldMap<'a> {
+    /// Names and flattened values of the fields serialized so far.
+    fields: &'a mut Vec<(String, Flat)>,
+    /// Name of the field whose value is serialized next.
+    name: Option<String>,
+}
+
+impl SerializeMap for FieldMap<'_> {
+    type Ok = ();
+    type Error = Nested;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Nested> {
+        let key = flatten(key).ok_or(Nested)?;
+        match <[String; 1]>::try_from(key.values) {
+            Ok([name]) if !key.sequence => {
+                self.name = Some(name);
+                Ok(())
+            }
+            _ => Err(Nested),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Nested> {
+        let name = self.name.take().ok_or(Nested)?;
+        self.fields.push((name, flatten(value).ok_or(Nested)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Nested> {
+        Ok(())
+    }
+}
+
+impl SerializeStruct for FieldMap<'_> {
+    type Ok = ();
+    type Error = Nested;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), Nested> {
+        self.fields
+            .push((name.to_string(), flatten(value).ok_or(Nested)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Nested> {
+        Ok(())
+    }
+}
+
+/// Appends the encoded `query` to `url`, after the query it already has, if any.
+fn push(url: &mut String, query: &str) {
+    if query.is_empty() {
+        return;
+    }
+    if !url.contains('?') {
+        url.push('?');
+    } else if !url.ends_with('?') && !url.ends_with('&') {
+        url.push('&');
+    }
+    url.push_str(query);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append, extend, ArrayFormat, Params};
+    use crate::{api, test_server, Api, ApiState, Error};
+
+    #[derive(serde::Serialize)]
+    struct Filters {
+        user: Option<u32>,
+        tag: &'static str,
+    }
+
+    #[test]
+    fn appends_set_values() {
+        let mut url = "https://example.com/todos".to_string();
+        append(&mut url, "page", &Some(2), ArrayFormat::Repeat).unwrap();
+        append(&mut url, "user", &None::<u32>, ArrayFormat::Repeat).unwrap();
+        append(&mut url, "q", "a b", ArrayFormat::Repeat).unwrap();
+        extend(
+            &mut url,
+            &Filters {
+                user: None,
+                tag: "x&y",
+            },
+            ArrayFormat::Repeat,
+        )
+        .unwrap();
+        assert_eq!(url, "https://example.com/todos?page=2&q=a+b&tag=x%26y");
+
+        let mut url = "https://example.com/todos?sort=id".to_string();
+        let params = Params::new().param_opt("user", None::<u32>);
+        extend(&mut url, &params, ArrayFormat::Repeat).unwrap();
+        assert_eq!(url, "https://example.com/todos?sort=id");
+        let params = Params::new().param("done", true);
+        extend(&mut url, &params, ArrayFormat::Repeat).unwrap();
+        assert_eq!(url, "https://example.com/todos?sort=id&done=true");
+
+        #[cfg(not(feature = "qs"))]
+        {
+            let nested = Filters {
+                user: Some(1),
+                tag: "x",
+            };
+            assert!(matches!(
+                append(&mut url, "filters", &nested, ArrayFormat::Repeat),
+                Err(Error::InvalidQuery(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "qs")]
+    #[test]
+    fn encodes_nested_values() {
+        use std::collections::BTreeMap;
+
+        #[derive(serde::Serialize)]
+        struct Search {
+            filter: BTreeMap<&'static str, &'static str>,
+            ids: Vec<u32>,
+            page: Option<u32>,
+        }
+
+        let mut url = "https://example.com/issues".to_string();
+        let search = Search {
+            filter: BTreeMap::from([("status", "open"), ("label", "a b")]),
+            ids: vec![1, 2],
+            page: None,
+        };
+        extend(&mut url, &search, ArrayFormat::Brackets).unwrap();
+        assert_eq!(
+            url,
+            "https://example.com/issues?filter[label]=a+b&filter[status]=open&ids[0]=1&ids[1]=2"
+        );
+
+        let mut url = "https://example.com/todos".to_string();
+        let nested = Filters {
+            user: Some(1),
+            tag: "x",
+        };
+        append(&mut url, "filters", &nested, ArrayFormat::Repeat).unwrap();
+        assert_eq!(
+            url,
+            "https://example.com/todos?filters[user]=1&filters[tag]=x"
+        );
+        assert!(matches!(
+            extend(&mut url, "flat", ArrayFormat::Repeat),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn encodes_sequences() {
+        #[derive(serde::Serialize)]
+        struct Search {
+            ids: Vec<u32>,
+            state: Option<&'static str>,
+        }
+
+        let encode = |format| {
+            let mut url = "https://example.com/issues".to_string();
+            append(&mut url, "ids", &vec![1, 2], format).unwrap();
+            append(&mut url, "labels", &["a,b", "c|d"], format).unwrap();
+            append(&mut url, "tags", &Vec::<&str>::new(), format).unwrap();
+            append(&mut url, "page", &2, format).unwrap();
+            url
+        };
+        assert_eq!(
+            encode(ArrayFormat::Repeat),
+            "https://example.com/issues?ids=1&ids=2&labels=a%2Cb&labels=c%7Cd&page=2"
+        );
+        assert_eq!(
+            encode(ArrayFormat::Comma),
+            "https://example.com/issues?ids=1,2&labels=a%2Cb,c%7Cd&page=2"
+        );
+        assert_eq!(
+            encode(ArrayFormat::Brackets),
+            "https://example.com/issues?ids[]=1&ids[]=2&labels[]=a%2Cb&labels[]=c%7Cd&page=2"
+        );
+        assert_eq!(
+            encode(ArrayFormat::Pipes),
+            "https://example.com/issues?ids=1|2&labels=a%2Cb|c%7Cd&page=2"
+        );
+
+        let mut url = "https://example.com/issues".to_string();
+        let search = Search {
+            ids: vec![3, 4],
+            state: Some("open"),
+        };
+        extend(&mut url, &search, ArrayFormat::Comma).unwrap();
+        assert_eq!(url, "https://example.com/issues?ids=3,4&state=open");
+    }
+
+    api!(struct Todos);
+
+    impl Todos {
+        api! {
+            fn list(&self, base_url: &str, page: Option<u32>, filters: Params) -> String {
+                GET "{base_url}/todos?sort=id"
+                with { query: [page, ..filters] }
+            }
+
+            fn issues(&self, base_url: &str, ids: Vec<u32>) -> String {
+                GET "{base_url}/issues"
+                with { query: [ids] }
+            }
+
+            fn labels(&self, base_url: &str, names: &[&str]) -> String {
+                GET "{base_url}/labels"
+                with { query: [names], arrays: pipes }
+            }
+        }
+    }
+
+    #[test]
+    fn skips_unset_params() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                test_server::Response::new(200).body(request.path.clone())
+            })
+            .await;
+            let api = Todos::new();
+
+            let path = api.list(&base_url, None, Params::new()).await.unwrap();
+            assert_eq!(path, "/todos?sort=id");
+            let filters = Params::new()
+                .param_opt("user", Some(7))
+                .param_opt("tag", None::<&str>);
+            let path = api.list(&base_url, Some(2), filters).await.unwrap();
+            assert_eq!(path, "/todos?sort=id&page=2&user=7");
+        });
+    }
+
+    #[test]
+    fn selects_array_formats() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                test_server::Response::new(200).body(request.path.clone())
+            })
+            .await;
+            let api = Todos::new();
+            let path = api.issues(&base_url, vec![1, 2]).await.unwrap();
+            assert_eq!(path, "/issues?ids=1&ids=2");
+
+            let api = Todos::builder()
+                .state(ApiState::new().array_format(ArrayFormat::Comma))
+                .build()
+                .unwrap();
+            let path = api.issues(&base_url, vec![1, 2]).await.unwrap();
+            assert_eq!(path, "/issues?ids=1,2");
+            let path = api.labels(&base_url, &["bug", "ui"]).await.unwrap();
+            assert_eq!(path, "/labels?names=bug|ui");
+        });
+    }
+}
@@ -0,0 +1,341 @@
+//! Rate limiting, both as announced by servers and enforced by the client itself.
+//!
+//! After every response, the headers `RateLimit-Limit`, `RateLimit-Remaining` and
+//! `RateLimit-Reset`, their `X-RateLimit-*` counterparts used by e.g. GitHub, and `Retry-After`
+//! are parsed into a [`RateLimit`], which is available through
+//! [`Api::rate_limit_state`](crate::Api::rate_limit_state). With
+//! [`ApiState::throttle`](crate::ApiState::throttle) enabled, requests made while the budget is
+//! exhausted wait until it resets instead of being rejected by the server.
+//!
+//! Independently of the server, a [`RateLimiter`] such as a [`TokenBucket`] set with
+//! [`ApiState::rate_limiter`](crate::ApiState::rate_limiter) limits how fast an instance sends
+//! requests.
+//!
+//! Composite operations, [`Api::batch`](crate::Api::batch) and
+//! [`Api::parallel_scan`](crate::Api::parallel_scan), start fewer calls at once while either
+//! budget is lower than their concurrency, and let other tasks of the instance go first before
+//! starting another call, so they don't use up a quota on their own.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, rate_limit::TokenBucket, Api, ApiState};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let api = ExampleApi::builder()
+//!     .state(
+//!         ApiState::new()
+//!             .throttle(true)
+//!             .rate_limiter(TokenBucket::new(10.0, 5)),
+//!     )
+//!     .build()
+//!     .unwrap();
+//!
+//! if let Some(rate_limit) = api.rate_limit_state() {
+//!     println!("{:?} requests left", rate_limit.remaining);
+//! }
+//! ```
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::header::HeaderMap;
+
+use crate::{
+    runtime::{Runtime, Tokio},
+    ApiState,
+};
+
+/// Reset values above this are unix timestamps rather than a number of seconds.
+const TIMESTAMP_THRESHOLD: u64 = 1_000_000_000;
+
+/// Rate limit budget announced by the last response which carried rate limit headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Number of requests allowed in the current window.
+    pub limit: Option<u64>,
+    /// Number of requests left in the current window.
+    pub remaining: Option<u64>,
+    /// Point in time at which the budget resets.
+    pub reset: Option<Instant>,
+}
+
+impl RateLimit {
+    /// Parses the rate limit headers of a response.
+    ///
+    /// `RateLimit-*` headers take precedence over `X-RateLimit-*` headers. Resets are read as a
+    /// number of seconds, or as a unix timestamp for large values as sent by GitHub. A
+    /// `Retry-After` header in seconds marks the budget as exhausted until the given time.
+    /// Returns `None` if the response carries none of these headers.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let number = |name: &str| {
+            headers
+                .get(format!("ratelimit-{name}"))
+                .or_else(|| headers.get(format!("x-ratelimit-{name}")))
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        };
+
+        let mut rate_limit = Self {
+            limit: number("limit"),
+            remaining: number("remaining"),
+            reset: number("reset").map(reset_instant),
+        };
+        if let Some(retry_after) = headers
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+        {
+            rate_limit.remaining = Some(0);
+            rate_limit.reset = Some(Instant::now() + Duration::from_secs(retry_after));
+        }
+
+        (rate_limit.limit.is_some() || rate_limit.remaining.is_some() || rate_limit.reset.is_some())
+            .then_some(rate_limit)
+    }
+
+    /// Returns whether no requests are left in the current window.
+    #[must_use]
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == Some(0)
+    }
+
+    /// Returns how long to wait before the next request, if the budget is exhausted and has not
+    /// reset yet.
+    #[must_use]
+    pub fn wait_time(&self) -> Option<Duration> {
+        if !self.is_exhausted() {
+            return None;
+        }
+        self.reset
+            .and_then(|reset| reset.checked_duration_since(Instant::now()))
+            .filter(|wait| !wait.is_zero())
+    }
+}
+
+/// Limits how fast requests are sent, independently of the limits announced by the server.
+///
+/// Set one for an instance with [`ApiState::rate_limiter`](crate::ApiState::rate_limiter). Wrap
+/// it in an [`Arc`] to share the limit between several instances.
+#[async_trait::async_trait(?Send)]
+pub trait RateLimiter: fmt::Debug + Send + Sync {
+    /// Waits until the next request may be sent.
+    async fn acquire(&self);
+
+    /// Returns how many requests may be sent right away, if known.
+    ///
+    /// Composite operations such as [`Api::batch`](crate::Api::batch) and
+    /// [`Api::parallel_scan`](crate::Api::parallel_scan) start no more calls at once than this.
+    fn available(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl<L: RateLimiter + ?Sized> RateLimiter for Arc<L> {
+    async fn acquire(&self) {
+        (**self).acquire().await;
+    }
+
+    fn available(&self) -> Option<u64> {
+        (**self).available()
+    }
+}
+
+/// Token bucket allowing a steady number of requests per second with bursts of up to `burst`
+/// requests.
+///
+/// Requests waiting for a token are let through in the order they arrived.
+#[derive(Debug)]
+pub struct TokenBucket {
+    /// Number of tokens added per second.
+    rate: f64,
+    /// Maximum number of tokens.
+    burst: f64,
+    /// Available tokens, negative while requests are waiting, and the time they were counted.
+    tokens: Mutex<(f64, Instant)>,
+    /// Runtime waiting for tokens.
+    runtime: Arc<dyn Runtime>,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket allowing `requests_per_second` with bursts of up to `burst` requests.
+    ///
+    /// # Panics
+    /// Panics if `requests_per_second` is not positive or `burst` is zero.
+    #[must_use]
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        assert!(
+            requests_per_second > 0.0,
+            "the request rate of a token bucket must be positive"
+        );
+        assert!(burst > 0, "the burst of a token bucket must not be zero");
+        Self {
+            rate: requests_per_second,
+            burst: f64::from(burst),
+            tokens: Mutex::new((f64::from(burst), Instant::now())),
+            runtime: Arc::new(Tokio),
+        }
+    }
+
+    /// Waits for tokens on `runtime` instead of Tokio, see [`runtime`](crate::runtime).
+    #[must_use]
+    pub fn runtime(mut self, runtime: impl Runtime + 'static) -> Self {
+        self.runtime = Arc::new(runtime);
+        self
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl RateLimiter for TokenBucket {
+    async fn acquire(&self) {
+        let missing = {
+            let mut guard = self.tokens.lock().unwrap_or_else(PoisonError::into_inner);
+            let (tokens, counted) = &mut *guard;
+            let now = Instant::now();
+            *tokens = (*tokens + now.duration_since(*counted).as_secs_f64() * self.rate)
+                .min(self.burst)
+                - 1.0;
+            *counted = now;
+            -*tokens
+        };
+        if missing > 0.0 {
+            self.runtime
+                .sleep(Duration::from_secs_f64(missing / self.rate))
+                .await;
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn available(&self) -> Option<u64> {
+        let (tokens, counted) = *self.tokens.lock().unwrap_or_else(PoisonError::into_inner);
+        let tokens = (tokens + counted.elapsed().as_secs_f64() * self.rate).min(self.burst);
+        Some(tokens.max(0.0) as u64)
+    }
+}
+
+/// Returns how many calls of a composite operation such as [`Api::batch`](crate::Api::batch)
+/// may run at once: `max`, unless the budget left by the server or the rate limiter of `state`
+/// is lower, but at least one.
+pub(crate) fn concurrency(state: &ApiState, max: usize) -> usize {
+    state.request_budget().map_or(max, |budget| {
+        usize::try_from(budget).unwrap_or(usize::MAX).clamp(1, max)
+    })
+}
+
+/// Converts a reset header value to the point in time it refers to.
+fn reset_instant(reset: u64) -> Instant {
+    let delay = if reset > TIMESTAMP_THRESHOLD {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Duration::from_secs(reset).saturating_sub(now)
+    } else {
+        Duration::from_secs(reset)
+    };
+    Instant::now() + delay
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::{RateLimit, RateLimiter, TokenBucket};
+    use crate::{api, test_server, Api, ApiState};
+
+    /// Builds a header map from name and value pairs.
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (*name, HeaderValue::from_str(value).unwrap()))
+            .map(|(name, value)| (reqwest::header::HeaderName::from_static(name), value))
+            .collect()
+    }
+
+    #[test]
+    fn parses_headers() {
+        assert_eq!(RateLimit::from_headers(&HeaderMap::new()), None);
+
+        let rate_limit = RateLimit::from_headers(&headers(&[
+            ("ratelimit-limit", "100"),
+            ("x-ratelimit-limit", "50"),
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset", "60"),
+        ]))
+        .unwrap();
+        assert_eq!(rate_limit.limit, Some(100));
+        assert!(rate_limit.is_exhausted());
+        assert!(rate_limit.wait_time().unwrap() > Duration::from_secs(59));
+
+        let timestamp =
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap() + Duration::from_secs(60);
+        let rate_limit = RateLimit::from_headers(&headers(&[
+            ("x-ratelimit-remaining", "5"),
+            ("x-ratelimit-reset", &timestamp.as_secs().to_string()),
+        ]))
+        .unwrap();
+        assert_eq!(rate_limit.wait_time(), None);
+        assert!(rate_limit.reset.unwrap() > Instant::now() + Duration::from_secs(58));
+
+        let rate_limit = RateLimit::from_headers(&headers(&[("retry-after", "30")])).unwrap();
+        assert!(rate_limit.is_exhausted());
+        assert!(rate_limit.wait_time().unwrap() > Duration::from_secs(29));
+    }
+
+    #[test]
+    fn token_bucket_limits_rate() {
+        tokio_test::block_on(async {
+            let bucket = TokenBucket::new(20.0, 2);
+            let start = Instant::now();
+            assert_eq!(bucket.available(), Some(2));
+            bucket.acquire().await;
+            bucket.acquire().await;
+            assert_eq!(bucket.available(), Some(0));
+            assert!(start.elapsed() < Duration::from_millis(20));
+            bucket.acquire().await;
+            bucket.acquire().await;
+            assert!(start.elapsed() > Duration::from_millis(90));
+        });
+    }
+
+    api!(struct Limited);
+
+    impl Limited {
+        api! {
+            fn get(&self, base_url: &str) -> StatusCode {
+                GET "{base_url}/"
+            }
+        }
+    }
+
+    #[test]
+    fn waits_for_reset() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|_| {
+                test_server::Response::new(200)
+                    .header("ratelimit-limit", "1")
+                    .header("ratelimit-remaining", "0")
+                    .header("ratelimit-reset", "1")
+            })
+            .await;
+            let api = Limited::builder()
+                .state(ApiState::new().throttle(true))
+                .build()
+                .unwrap();
+            assert_eq!(api.rate_limit_state(), None);
+
+            api.get(&base_url).await.unwrap();
+            assert!(api.rate_limit_state().unwrap().is_exhausted());
+
+            let start = Instant::now();
+            api.get(&base_url).await.unwrap();
+            assert!(start.elapsed() > Duration::from_millis(900));
+        });
+    }
+}
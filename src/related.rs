@@ -0,0 +1,97 @@
+//! Links to related resources inside response models.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, related::Related, Api};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! pub struct User {
+//!     pub name: String,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! pub struct Todo {
+//!     pub title: String,
+//!     pub owner: Related<User>,
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         pub fn todo(id: u32) -> Json<Todo> {
+//!             GET "https://example.com/todos/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn owner_name(api: &ExampleApi) -> api_client::ResultType<String> {
+//!     let todo = api.todo(1).await?;
+//!     Ok(api.fetch(&todo.owner).await?.name)
+//! }
+//! ```
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// URL of a related resource of type `T`.
+///
+/// (De)serializes as a plain URL string, and can be fetched with [`Api::fetch`](crate::Api::fetch)
+/// which sends a `GET` request through the same client and hooks as every other endpoint.
+/// The URL has to be absolute.
+pub struct Related<T> {
+    /// URL of the related resource.
+    url: String,
+    /// Type of the related resource.
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Related<T> {
+    /// Creates a link to the resource at `url`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the URL of the related resource.
+    #[must_use]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl<T> Clone for Related<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.url.clone())
+    }
+}
+
+impl<T> PartialEq for Related<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url
+    }
+}
+
+impl<T> Eq for Related<T> {}
+
+impl<T> fmt::Debug for Related<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Related").field(&self.url).finish()
+    }
+}
+
+impl<T> Serialize for Related<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.url)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Related<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::new)
+    }
+}
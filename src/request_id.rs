@@ -0,0 +1,104 @@
+//! Request ids, which correlate calls with the logs of the server.
+//!
+//! The support of a third-party API can only find a failed call in its logs by its request id.
+//! Instances with [`ApiState::request_ids`](crate::ApiState::request_ids) send a new random UUID
+//! with every call in the `X-Request-Id` header, or the one set with
+//! [`ApiState::request_id_header`](crate::ApiState::request_id_header). All attempts of a call,
+//! e.g. retries by interceptors, send the same id.
+//!
+//! The id of a call is
+//! - recorded as the `request_id` field of its `request` span with the `tracing` feature, see
+//!   [`trace`](crate::trace),
+//! - returned by [`Api::last_request_id`](crate::Api::last_request_id) until the next call
+//!   starts,
+//! - attached to its error as [`Error::RequestId`](crate::Error::RequestId), which is reported as
+//!   `<error> (request id <id>)` and returned by [`Error::request_id`](crate::Error::request_id).
+//!   [`Error::root`](crate::Error::root) returns the error without it.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, ApiState, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn invoice(id: u32) -> String {
+//!            GET "https://example.com/invoices/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn invoice(api: &ExampleApi, id: u32) -> ResultType<String> {
+//!     api.invoice(id).await.map_err(|error| {
+//!         eprintln!("request {:?} failed", error.request_id());
+//!         error
+//!     })
+//! }
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().request_ids(true))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+/// Name of the header carrying the id unless another one is set.
+pub const REQUEST_ID: &str = "x-request-id";
+
+#[cfg(test)]
+mod tests {
+    use crate::{api, test_server, Api, ApiState};
+
+    api!(struct Invoices);
+
+    impl Invoices {
+        api! {
+            fn get(&self, base_url: &str, id: u32) -> Json<String> {
+                GET "{base_url}/invoices/{id}"
+            }
+        }
+    }
+
+    #[test]
+    fn sends_request_ids() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let id = request
+                    .header("x-request-id")
+                    .or_else(|| request.header("x-correlation-id"))
+                    .unwrap_or_default();
+                if request.path.ends_with("/2") {
+                    test_server::Response::new(200).body("not json")
+                } else {
+                    test_server::Response::new(200).body(format!("\"{id}\""))
+                }
+            })
+            .await;
+            let api = Invoices::builder()
+                .state(ApiState::new().request_ids(true))
+                .build()
+                .unwrap();
+            assert_eq!(api.last_request_id(), None);
+
+            let first = api.get(&base_url, 1).await.unwrap();
+            assert_eq!(first.len(), 36);
+            assert_eq!(api.last_request_id(), Some(first.clone()));
+
+            let error = api.get(&base_url, 2).await.unwrap_err();
+            let id = error.request_id().unwrap().to_string();
+            assert_ne!(id, first);
+            assert_eq!(api.last_request_id(), Some(id.clone()));
+            assert!(error.to_string().ends_with(&format!("(request id {id})")));
+            assert_eq!(error.root().request_id(), None);
+            assert_eq!(error.context("syncing").request_id(), Some(id.as_str()));
+
+            let api = Invoices::builder()
+                .state(ApiState::new().request_id_header("X-Correlation-Id"))
+                .build()
+                .unwrap();
+            let id = api.get(&base_url, 1).await.unwrap();
+            assert_eq!(api.last_request_id(), Some(id));
+            assert_eq!(Invoices::new().last_request_id(), None);
+        });
+    }
+}
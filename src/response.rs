@@ -0,0 +1,436 @@
+//! Return kinds of endpoints declared with the [`api!`](crate::api) macro.
+//!
+//! Each return kind written in a declaration maps to a type implementing [`ResponseKind`]:
+//!
+//! | Declaration | Kind       | Output                |
+//! |-------------|------------|-----------------------|
+//! | `StatusCode`| [`Status`] | [`reqwest::StatusCode`] |
+//! | `String`    | [`Text`]   | [`String`]            |
+//! | `Bytes`     | [`Bytes`]  | [`bytes::Bytes`]      |
+//! | `Json<T>`   | [`Json`]   | `T`                   |
+//! | `WithHeaders<K>` | [`WithHeaders`] | [`ResponseParts`] of the output of `K` |
+//! | `Redirect<T>` | [`Redirect`] | [`Redirected<T>`](Redirected) |
+//! | `Created<K>` | [`Created`] | output of `K` for the resource at the `Location` of the response |
+//! | `JsonArrayStream<T>(pointer)` | [`JsonArrayStream`] | [`JsonArrayItems<T>`](JsonArrayItems) |
+//! | `FrameStream<C>` | [`FrameStream`] | [`Frames<C>`](Frames) |
+//! | `Download`  | [`Download`] | [`Downloading`], saved into a file with [`Downloading::save`] |
+//! | `Response`  | [`Raw`]    | [`reqwest::Response`] |
+//!
+//! Endpoints declared `with { raw }` also get a `_raw` sibling method returning the [`Raw`]
+//! response, see [`api!`](crate::api).
+
+use crate::{endpoint::EndpointSpec, Api, Body, Error, ResultType};
+
+mod download;
+mod frames;
+#[cfg(feature = "json")]
+mod json_stream;
+
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use json_stream::{JsonArrayItems, JsonArrayStream};
+
+pub use download::{Download, Downloaded, Downloading};
+pub use frames::{
+    Envelope, Enveloped, FrameCodec, FrameStream, Frames, LengthPrefixed, MAX_FRAME_LENGTH,
+};
+
+/// Returns the trailers of `response`, if the transport received any, see
+/// [`Trailers`](crate::protocol::Trailers).
+#[must_use]
+pub fn trailers(response: &reqwest::Response) -> Option<&reqwest::header::HeaderMap> {
+    response
+        .extensions()
+        .get::<crate::protocol::Trailers>()
+        .map(|trailers| &trailers.0)
+}
+
+/// Decodes a response into the output of an endpoint.
+#[async_trait::async_trait(?Send)]
+pub trait ResponseKind {
+    /// Type returned by the endpoint.
+    type Output;
+
+    /// Whether error responses with a Problem Details body fail with
+    /// [`Error::Problem`](crate::Error::Problem) before they are decoded, see
+    /// [`problem`](crate::problem). Kinds returning the status or the whole response turn this off.
+    const DETECTS_PROBLEMS: bool = true;
+
+    /// Decodes `response` into the output of the endpoint described by `spec`.
+    ///
+    /// # Errors
+    /// Returns an error if the response body could not be read or decoded.
+    async fn from_response(
+        response: reqwest::Response,
+        spec: &EndpointSpec,
+    ) -> ResultType<Self::Output>;
+
+    /// Decodes `response`, received by `_api`, into the output of the endpoint described by
+    /// `spec`.
+    ///
+    /// Calls [`from_response`](ResponseKind::from_response) unless overridden by kinds which send
+    /// further requests, e.g. [`Created`].
+    ///
+    /// # Errors
+    /// Returns an error if the response body could not be read or decoded, or a further request
+    /// failed.
+    async fn from_response_of<A: Api + ?Sized>(
+        _api: &A,
+        response: reqwest::Response,
+        spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        Self::from_response(response, spec).await
+    }
+}
+
+/// Returns only the status code of the response.
+#[derive(Debug)]
+pub struct Status;
+
+#[async_trait::async_trait(?Send)]
+impl ResponseKind for Status {
+    type Output = reqwest::StatusCode;
+    const DETECTS_PROBLEMS: bool = false;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        Ok(response.status())
+    }
+}
+
+/// Returns the response body as text.
+#[derive(Debug)]
+pub struct Text;
+
+#[async_trait::async_trait(?Send)]
+impl ResponseKind for Text {
+    type Output = String;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        Ok(response.text().await?)
+    }
+}
+
+/// Returns the raw response body.
+#[derive(Debug)]
+pub struct Bytes;
+
+#[async_trait::async_trait(?Send)]
+impl ResponseKind for Bytes {
+    type Output = bytes::Bytes;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        Ok(response.bytes().await?)
+    }
+}
+
+/// Returns the response without reading its body, e.g. to stream it or to read its headers.
+#[derive(Debug)]
+pub struct Raw;
+
+#[async_trait::async_trait(?Send)]
+impl ResponseKind for Raw {
+    type Output = reqwest::Response;
+    const DETECTS_PROBLEMS: bool = false;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        Ok(response)
+    }
+}
+
+/// Deserializes the response body from JSON.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Debug)]
+pub struct Json<T>(std::marker::PhantomData<T>);
+
+#[cfg(feature = "json")]
+#[async_trait::async_trait(?Send)]
+impl<T: serde::de::DeserializeOwned> ResponseKind for Json<T> {
+    type Output = T;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        Ok(response.json().await?)
+    }
+}
+
+/// Returns the output of another kind together with the status and headers of the response.
+///
+/// Useful for endpoints which return data such as rate limits, pagination cursors or `ETag`s
+/// in their headers.
+/// ```rust
+/// # use api_client::{api, Api};
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn todos() -> WithHeaders<String> {
+///            GET "https://example.com/todos"
+///         }
+///     }
+/// }
+///
+/// async fn todos_etag(api: &ExampleApi) -> api_client::ResultType<Option<String>> {
+///     let todos = api.todos().await?;
+///     Ok(todos.header("etag").map(String::from))
+/// }
+/// ```
+#[derive(Debug)]
+pub struct WithHeaders<K>(std::marker::PhantomData<K>);
+
+/// Output of a [`WithHeaders`] endpoint: the decoded body together with the response status and
+/// headers.
+#[derive(Debug, Clone)]
+pub struct ResponseParts<T> {
+    /// Status of the response.
+    pub status: reqwest::StatusCode,
+    /// Headers of the response.
+    pub headers: reqwest::header::HeaderMap,
+    /// Trailers of the response, if the transport received any, see
+    /// [`Trailers`](crate::protocol::Trailers).
+    pub trailers: Option<reqwest::header::HeaderMap>,
+    /// Decoded body of the response.
+    pub body: T,
+}
+
+impl<T> ResponseParts<T> {
+    /// Returns the value of the header `name`, if it is present and valid UTF-8.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// Returns the value of the trailer `name`, if it is present and valid UTF-8.
+    #[must_use]
+    pub fn trailer(&self, name: &str) -> Option<&str> {
+        self.trailers.as_ref()?.get(name)?.to_str().ok()
+    }
+
+    /// Returns how long the server asks to wait before the next request in its `Retry-After`
+    /// header, if any, see [`poll::retry_after`](crate::poll::retry_after).
+    #[must_use]
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        crate::poll::retry_after(&self.headers)
+    }
+
+    /// Returns the links in the `Link` headers of the response, see [`links`](crate::links).
+    #[must_use]
+    pub fn links(&self) -> crate::links::Links {
+        crate::links::Links::from_headers(&self.headers)
+    }
+
+    /// Returns the decoded body, dropping the status and headers.
+    pub fn into_body(self) -> T {
+        self.body
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl<K: ResponseKind> ResponseKind for WithHeaders<K> {
+    type Output = ResponseParts<K::Output>;
+    const DETECTS_PROBLEMS: bool = K::DETECTS_PROBLEMS;
+
+    async fn from_response(
+        response: reqwest::Response,
+        spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let trailers = trailers(&response).cloned();
+        let body = K::from_response(response, spec).await?;
+        Ok(ResponseParts {
+            status,
+            headers,
+            trailers,
+            body,
+        })
+    }
+}
+
+/// Captures the target of a redirect without following it, parsing its query string as `T`.
+///
+/// This is how OAuth authorization responses and payment gateway callbacks hand their data to
+/// the client. The client must not follow redirects itself: disable that with
+/// [`ApiState::follow_redirects`](crate::ApiState::follow_redirects) for clients created by the
+/// generated builders, or with [`reqwest::redirect::Policy::none`] for your own clients.
+/// ```rust
+/// # use api_client::{api, Api, ApiState};
+/// #[derive(serde::Deserialize)]
+/// struct Authorization {
+///     code: String,
+///     state: String,
+/// }
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn authorize(request: Form<[(&str, &str)]>) -> Redirect<Authorization> {
+///            POST "https://example.com/oauth/authorize"
+///         }
+///     }
+/// }
+///
+/// let api = ExampleApi::builder()
+///     .state(ApiState::new().follow_redirects(false))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Redirect<T>(std::marker::PhantomData<T>);
+
+/// Output of a [`Redirect`] endpoint: the redirect target and the data parsed from its query.
+#[derive(Debug, Clone)]
+pub struct Redirected<T> {
+    /// Status of the redirect response, e.g. `302 Found`.
+    pub status: reqwest::StatusCode,
+    /// Target of the redirect, resolved against the request URL.
+    pub url: reqwest::Url,
+    /// Data parsed from the query string of the target.
+    pub query: T,
+}
+
+#[async_trait::async_trait(?Send)]
+impl<T: serde::de::DeserializeOwned> ResponseKind for Redirect<T> {
+    type Output = Redirected<T>;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        let status = response.status();
+        if !status.is_redirection() {
+            return Err(Error::NotRedirected(status));
+        }
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .ok_or_else(|| Error::InvalidRedirect("missing `Location` header".to_string()))?
+            .to_str()
+            .map_err(|error| Error::InvalidRedirect(error.to_string()))?;
+        let url = response
+            .url()
+            .join(location)
+            .map_err(|error| Error::InvalidRedirect(error.to_string()))?;
+        let query = serde_urlencoded::from_str(url.query().unwrap_or_default())
+            .map_err(|error| Error::InvalidRedirect(error.to_string()))?;
+        Ok(Redirected { status, url, query })
+    }
+}
+
+/// Fetches the resource a `201 Created` or `303 See Other` response refers to in its `Location`
+/// header, decoding it as `K`.
+///
+/// This is how many REST APIs answer requests creating a resource. The resource is fetched with a
+/// `GET` request through the same client and hooks as every endpoint, and responses without a
+/// `Location` are decoded as they are, e.g. a `201 Created` carrying the resource in its body.
+/// ```rust
+/// # use api_client::api;
+/// #[derive(serde::Serialize)]
+/// struct NewTodo {
+///     title: String,
+/// }
+///
+/// #[derive(serde::Deserialize)]
+/// struct Todo {
+///     id: u32,
+///     title: String,
+/// }
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn create_todo(request: Json<NewTodo>) -> Created<Json<Todo>> {
+///            POST "https://example.com/todos"
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Created<K>(std::marker::PhantomData<K>);
+
+/// Returns the URL the `Location` header of `response` refers to, resolved against the request
+/// URL, if it is a `201 Created` response carrying one or a `303 See Other` response.
+fn created_location(response: &reqwest::Response) -> ResultType<Option<reqwest::Url>> {
+    let status = response.status();
+    let location = response.headers().get(reqwest::header::LOCATION);
+    let location = match (status.as_u16(), location) {
+        (201 | 303, Some(location)) => location,
+        (303, None) => {
+            return Err(Error::InvalidRedirect(
+                "missing `Location` header".to_string(),
+            ));
+        }
+        _ => return Ok(None),
+    };
+    let location = location
+        .to_str()
+        .map_err(|error| Error::InvalidRedirect(error.to_string()))?;
+    let url = response
+        .url()
+        .join(location)
+        .map_err(|error| Error::InvalidRedirect(error.to_string()))?;
+    Ok(Some(url))
+}
+
+#[async_trait::async_trait(?Send)]
+impl<K: ResponseKind> ResponseKind for Created<K> {
+    type Output = K::Output;
+    const DETECTS_PROBLEMS: bool = K::DETECTS_PROBLEMS;
+
+    /// Decodes responses without a `Location` as `K`, as the resource can only be fetched by the
+    /// instance which received the response, see [`from_response_of`](Self::from_response_of).
+    async fn from_response(
+        response: reqwest::Response,
+        spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        if created_location(&response)?.is_some() {
+            return Err(Error::InvalidRedirect(
+                "the `Location` of a created resource is only followed by endpoints".to_string(),
+            ));
+        }
+        K::from_response(response, spec).await
+    }
+
+    async fn from_response_of<A: Api + ?Sized>(
+        api: &A,
+        response: reqwest::Response,
+        spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        let Some(url) = created_location(&response)? else {
+            return K::from_response_of(api, response, spec).await;
+        };
+        let follow = EndpointSpec {
+            name: spec.name,
+            method: reqwest::Method::GET,
+            url: "{location}",
+            timeout: spec.timeout,
+            deadline: None,
+            retry: spec.retry,
+            idempotent: true,
+            pointer: spec.pointer,
+            scopes: &[],
+            requires: &[],
+            grpc_status: spec.grpc_status,
+            login: None,
+            cache_mode: None,
+            cache_lifetime: None,
+        };
+        crate::endpoint::execute::<A, K, ()>(api, &follow, url.as_str(), Body::None, None).await
+    }
+}
@@ -0,0 +1,310 @@
+//! Streaming response bodies into files, see [`Download`].
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use super::ResponseKind;
+use crate::{
+    checksum::{Checksum, Verifier},
+    endpoint::EndpointSpec,
+    progress::{Progress, Tracker},
+    ResultType,
+};
+
+/// Writes the response body into a file as it arrives, without buffering it in memory.
+///
+/// The endpoint returns a [`Downloading`] response, whose [`save`](Downloading::save) writes the
+/// body into a temporary file next to the given path and renames it there once the body is
+/// complete, so the path never holds a partial file. The SHA-256 hash of the body is computed
+/// while writing it if asked for with [`sha256`](Downloading::sha256), and its progress is
+/// reported to a callback given to [`on_progress`](Downloading::on_progress). The body can also
+/// be checked against its checksums, see [`checksum`](crate::checksum).
+/// ```rust
+/// # use api_client::{api, Api, ResultType};
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn artifact(name: &str) -> Download {
+///            GET "https://example.com/artifacts/{name}"
+///         }
+///     }
+/// }
+///
+/// async fn fetch_artifact(api: &ExampleApi) -> ResultType<String> {
+///     let downloaded = api
+///         .artifact("release.tar.gz")
+///         .await?
+///         .sha256()
+///         .save("target/release.tar.gz")
+///         .await?;
+///     println!("downloaded {} bytes", downloaded.size);
+///     Ok(downloaded.sha256.unwrap_or_default())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Download;
+
+#[async_trait::async_trait(?Send)]
+impl ResponseKind for Download {
+    type Output = Downloading;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        Ok(Downloading {
+            response,
+            checksum: false,
+            verify: false,
+            expected: Vec::new(),
+            progress: None,
+        })
+    }
+}
+
+/// A response whose body has not been read yet, see [`Download`].
+#[must_use = "the body is only downloaded by `save`"]
+pub struct Downloading {
+    /// The response being downloaded.
+    response: reqwest::Response,
+    /// Whether the SHA-256 hash of the body is computed.
+    checksum: bool,
+    /// Whether the body is checked against the checksums in the headers of the response.
+    verify: bool,
+    /// Checksums the body is checked against, given by the caller.
+    expected: Vec<Checksum>,
+    /// Called with the progress of the download, if set.
+    progress: Option<Box<dyn FnMut(Progress)>>,
+}
+
+impl fmt::Debug for Downloading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Downloading")
+            .field("response", &self.response)
+            .field("checksum", &self.checksum)
+            .field("verify", &self.verify)
+            .field("expected", &self.expected)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Downloading {
+    /// Also computes the SHA-256 hash of the body while saving it.
+    pub fn sha256(mut self) -> Self {
+        self.checksum = true;
+        self
+    }
+
+    /// Checks the body against the checksums announced in the headers of the response, if any,
+    /// see [`Checksum::from_headers`].
+    pub fn verify(mut self) -> Self {
+        self.verify = true;
+        self
+    }
+
+    /// Checks the body against `checksum`, e.g. one published with the artifact.
+    pub fn expect_checksum(mut self, checksum: Checksum) -> Self {
+        self.expected.push(checksum);
+        self
+    }
+
+    /// Calls `callback` with the progress of the download after every chunk written, see
+    /// [`progress`](crate::progress).
+    pub fn on_progress(mut self, callback: impl FnMut(Progress) + 'static) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Returns the size of the body announced by the server, if any.
+    #[must_use]
+    pub fn content_length(&self) -> Option<u64> {
+        self.response.content_length()
+    }
+
+    /// Returns the response, e.g. to read its headers before saving it.
+    #[must_use]
+    pub fn response(&self) -> &reqwest::Response {
+        &self.response
+    }
+
+    /// Writes the body into the file at `path`, replacing it at once when the body is complete.
+    ///
+    /// The body is written into a new temporary file in the same directory first, which is
+    /// removed if the download fails, so concurrent downloads to the same path don't mix.
+    ///
+    /// # Errors
+    /// Returns an error if reading the body failed or the file could not be written, or
+    /// [`Error::ChecksumMismatch`](crate::Error::ChecksumMismatch) if the body doesn't match a
+    /// checksum it is checked against, leaving `path` as it was.
+    pub async fn save(self, path: impl AsRef<Path>) -> ResultType<Downloaded> {
+        let path = path.as_ref();
+        let (file, temporary) = temporary_file(path).await?;
+        match self.write(file).await {
+            Ok((size, sha256)) => {
+                if let Err(error) = tokio::fs::rename(&temporary, path).await {
+                    let _ = tokio::fs::remove_file(&temporary).await;
+                    return Err(error.into());
+                }
+                Ok(Downloaded {
+                    path: path.to_path_buf(),
+                    size,
+                    sha256,
+                })
+            }
+            Err(error) => {
+                let _ = tokio::fs::remove_file(&temporary).await;
+                Err(error)
+            }
+        }
+    }
+
+    /// Writes the body into `file`, returning its size and, if asked for, its hash.
+    async fn write(mut self, mut file: tokio::fs::File) -> ResultType<(u64, Option<String>)> {
+        let mut hasher = self.checksum.then(Sha256::new);
+        let announced = if self.verify {
+            Checksum::from_headers(self.response.headers())
+        } else {
+            Vec::new()
+        };
+        let mut verifier = Verifier::new(announced).expect(self.expected.drain(..));
+        let total = self.response.content_length();
+        let mut tracker = self
+            .progress
+            .take()
+            .map(|callback| Tracker::new(callback, total));
+        let mut size = 0;
+        while let Some(chunk) = self.response.chunk().await? {
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&chunk);
+            }
+            verifier.update(&chunk);
+            file.write_all(&chunk).await?;
+            size += chunk.len() as u64;
+            if let Some(tracker) = &mut tracker {
+                tracker.advance(chunk.len());
+            }
+        }
+        verifier.finish()?;
+        file.sync_all().await?;
+        Ok((
+            size,
+            hasher.map(|hasher| crate::signing::hex(&hasher.finalize())),
+        ))
+    }
+}
+
+/// A body saved by [`Downloading::save`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Downloaded {
+    /// Path of the file holding the body.
+    pub path: PathBuf,
+    /// Size of the body in bytes.
+    pub size: u64,
+    /// Lowercase hex SHA-256 hash of the body, if asked for with [`Downloading::sha256`].
+    pub sha256: Option<String>,
+}
+
+/// Creates a new temporary file next to `path`, which no other download writes into.
+async fn temporary_file(path: &Path) -> io::Result<(tokio::fs::File, PathBuf)> {
+    /// Number of temporary files created by this process, making their names unique.
+    static CREATED: AtomicU64 = AtomicU64::new(0);
+    loop {
+        let mut temporary = path.as_os_str().to_owned();
+        let created = CREATED.fetch_add(1, Ordering::Relaxed);
+        temporary.push(format!(".{}.{created}.part", std::process::id()));
+        let temporary = PathBuf::from(temporary);
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temporary)
+            .await;
+        match file {
+            Ok(file) => return Ok((file, temporary)),
+            // Left behind by a process which had the same id.
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{api, checksum::Checksum, test_server, Api, Error};
+
+    api!(struct Artifacts);
+
+    impl Artifacts {
+        api! {
+            fn artifact(&self, base_url: &str, name: &str) -> Download {
+                GET "{base_url}/artifacts/{name}"
+            }
+        }
+    }
+
+    #[test]
+    fn saves_bodies_to_files() {
+        tokio_test::block_on(async {
+            let base_url =
+                test_server::serve(|_| test_server::Response::new(200).body("abc")).await;
+            let directory =
+                std::env::temp_dir().join(format!("api-client-download-{}", std::process::id()));
+            std::fs::create_dir_all(&directory).unwrap();
+            let path = directory.join("artifact");
+            std::fs::write(&path, "old").unwrap();
+            let api = Artifacts::new();
+
+            let downloading = api.artifact(&base_url, "abc").await.unwrap();
+            assert_eq!(downloading.content_length(), Some(3));
+            let downloaded = downloading.sha256().save(&path).await.unwrap();
+            assert_eq!(
+                (downloaded.path.as_path(), downloaded.size),
+                (path.as_path(), 3)
+            );
+            assert_eq!(
+                downloaded.sha256.as_deref(),
+                Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+            );
+            assert_eq!(std::fs::read(&path).unwrap(), b"abc");
+            assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+            let reported = Rc::new(Cell::new((0, None)));
+            let progress = reported.clone();
+            let downloading = api.artifact(&base_url, "abc").await.unwrap();
+            let downloaded = downloading
+                .on_progress(move |update| progress.set((update.transferred, update.total)))
+                .save(&path)
+                .await
+                .unwrap();
+            assert_eq!((downloaded.size, downloaded.sha256), (3, None));
+            assert_eq!(reported.get(), (3, Some(3)));
+
+            let downloading = api.artifact(&base_url, "abc").await.unwrap();
+            let expected = Checksum::sha256_hex(&"0".repeat(64));
+            let error = downloading.expect_checksum(expected).save(&path).await;
+            assert!(matches!(error, Err(Error::ChecksumMismatch(_))));
+            assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+            let downloading = api.artifact(&base_url, "abc").await.unwrap();
+            let error = downloading.save(directory.join("missing/artifact")).await;
+            assert!(matches!(error, Err(Error::Io(_))));
+            assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+            let first = api.artifact(&base_url, "abc").await.unwrap();
+            let second = api.artifact(&base_url, "abc").await.unwrap();
+            let (first, second) = futures_util::join!(first.save(&path), second.save(&path));
+            assert_eq!((first.unwrap().size, second.unwrap().size), (3, 3));
+            assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+            std::fs::remove_dir_all(&directory).unwrap();
+        });
+    }
+}
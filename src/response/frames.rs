@@ -0,0 +1,270 @@
+//! Incremental decoding of binary frames, see [`FrameStream`].
+
+use std::marker::PhantomData;
+
+use super::ResponseKind;
+use crate::{endpoint::EndpointSpec, Error, ResultType};
+
+/// Longest frame the built-in codecs accept, so a corrupt length can't exhaust memory.
+pub const MAX_FRAME_LENGTH: usize = 16 << 20;
+
+/// Splits a byte stream into frames, see [`FrameStream`].
+///
+/// Implement this for custom framings, e.g. type-length-value records. A codec is created with
+/// [`Default`] for every response.
+pub trait FrameCodec: Default {
+    /// Type of the decoded frames.
+    type Frame;
+
+    /// Decodes the frame at the start of `buffer`, returning it and the number of bytes it takes
+    /// up, or `None` if `buffer` doesn't hold a whole frame yet.
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidFrame`] if `buffer` doesn't start with a valid frame.
+    fn decode(&mut self, buffer: &[u8]) -> ResultType<Option<(Self::Frame, usize)>>;
+}
+
+/// Frames made of a 32-bit big-endian length followed by as many bytes of payload.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LengthPrefixed;
+
+impl FrameCodec for LengthPrefixed {
+    type Frame = bytes::Bytes;
+
+    fn decode(&mut self, buffer: &[u8]) -> ResultType<Option<(Self::Frame, usize)>> {
+        payload(buffer, 0)
+    }
+}
+
+/// Frames of gRPC-web and Connect streams: a byte of flags, a 32-bit big-endian length and as
+/// many bytes of payload.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Enveloped;
+
+impl FrameCodec for Enveloped {
+    type Frame = Envelope;
+
+    fn decode(&mut self, buffer: &[u8]) -> ResultType<Option<(Self::Frame, usize)>> {
+        Ok(payload(buffer, 1)?.map(|(data, length)| {
+            let flags = buffer[0];
+            (Envelope { flags, data }, length)
+        }))
+    }
+}
+
+/// A frame decoded by [`Enveloped`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    /// Flags of the frame.
+    pub flags: u8,
+    /// Payload of the frame.
+    pub data: bytes::Bytes,
+}
+
+impl Envelope {
+    /// Returns whether the payload is compressed.
+    #[must_use]
+    pub fn is_compressed(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+
+    /// Returns whether the frame ends the stream: it carries the trailers of a gRPC-web
+    /// response, or the end-of-stream message of a Connect response.
+    #[must_use]
+    pub fn is_end(&self) -> bool {
+        self.flags & 0x82 != 0
+    }
+}
+
+/// Streams the frames of a binary response without buffering the whole response.
+///
+/// The frames are split off with the [`FrameCodec`] given as the argument of the return kind,
+/// e.g. [`LengthPrefixed`] or [`Enveloped`], as they arrive.
+/// ```rust
+/// # use api_client::{api, Api, response::LengthPrefixed};
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn events() -> FrameStream<LengthPrefixed> {
+///            GET "https://example.com/events"
+///         }
+///     }
+/// }
+///
+/// async fn count_bytes(api: &ExampleApi) -> api_client::ResultType<usize> {
+///     let mut frames = api.events().await?;
+///     let mut count = 0;
+///     while let Some(frame) = frames.next().await {
+///         count += frame?.len();
+///     }
+///     Ok(count)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FrameStream<C>(PhantomData<C>);
+
+#[async_trait::async_trait(?Send)]
+impl<C: FrameCodec> ResponseKind for FrameStream<C> {
+    type Output = Frames<C>;
+
+    async fn from_response(
+        response: reqwest::Response,
+        _spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        Ok(Frames {
+            response,
+            buffer: Vec::new(),
+            position: 0,
+            codec: C::default(),
+            done: false,
+        })
+    }
+}
+
+/// Frames read incrementally from a response, see [`FrameStream`].
+#[derive(Debug)]
+pub struct Frames<C> {
+    /// The response being read.
+    response: reqwest::Response,
+    /// Bytes received but not consumed yet, starting at `position`.
+    buffer: Vec<u8>,
+    /// Position of the next unconsumed byte in `buffer`.
+    position: usize,
+    /// Splits the bytes into frames.
+    codec: C,
+    /// Whether the response ended or an error occurred.
+    done: bool,
+}
+
+impl<C: FrameCodec> Frames<C> {
+    /// Reads the next frame, or returns `None` once the response ended.
+    ///
+    /// After an error, no further frames are read.
+    pub async fn next(&mut self) -> Option<ResultType<C::Frame>> {
+        if self.done {
+            return None;
+        }
+        let frame = self.next_frame().await.transpose();
+        if !matches!(frame, Some(Ok(_))) {
+            self.done = true;
+        }
+        frame
+    }
+
+    /// Turns the frames into a [`Stream`](futures_util::Stream).
+    pub fn into_stream(self) -> impl futures_util::Stream<Item = ResultType<C::Frame>> {
+        futures_util::stream::unfold(self, |mut frames| async move {
+            frames.next().await.map(|frame| (frame, frames))
+        })
+    }
+
+    /// Decodes the next frame, reading more of the response until it is complete.
+    async fn next_frame(&mut self) -> ResultType<Option<C::Frame>> {
+        loop {
+            if let Some((frame, length)) = self.codec.decode(&self.buffer[self.position..])? {
+                self.position += length;
+                return Ok(Some(frame));
+            }
+            self.buffer.drain(..self.position);
+            self.position = 0;
+            match self.response.chunk().await? {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                None if self.buffer.is_empty() => return Ok(None),
+                None => {
+                    return Err(Error::InvalidFrame(
+                        "the response ended within a frame".to_string(),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Returns the payload following `header` bytes and a 32-bit big-endian length at the start of
+/// `buffer`, and the length of the whole frame, if it is complete.
+fn payload(buffer: &[u8], header: usize) -> ResultType<Option<(bytes::Bytes, usize)>> {
+    let length = match buffer.get(header..header + 4) {
+        Some(length) => u32::from_be_bytes([length[0], length[1], length[2], length[3]]),
+        None => return Ok(None),
+    };
+    let length = usize::try_from(length).unwrap_or(usize::MAX);
+    if length > MAX_FRAME_LENGTH {
+        return Err(Error::InvalidFrame(format!(
+            "frame of {length} bytes exceeds the maximum of {MAX_FRAME_LENGTH} bytes"
+        )));
+    }
+    let start = header + 4;
+    Ok(buffer
+        .get(start..start + length)
+        .map(|payload| (bytes::Bytes::copy_from_slice(payload), start + length)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Enveloped, FrameCodec, LengthPrefixed};
+    use crate::{api, response::Envelope, test_server, Api, Error};
+
+    api!(struct Streams);
+
+    impl Streams {
+        api! {
+            fn chunks(&self, base_url: &str) -> FrameStream<LengthPrefixed> {
+                GET "{base_url}/chunks"
+            }
+
+            fn messages(&self, base_url: &str) -> FrameStream<Enveloped> {
+                GET "{base_url}/messages"
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_frames() {
+        let mut codec = Enveloped;
+        assert_eq!(codec.decode(&[0, 0, 0, 0]).unwrap(), None);
+        assert_eq!(codec.decode(&[0, 0, 0, 0, 2, b'a']).unwrap(), None);
+        let (frame, length) = codec.decode(&[0x80, 0, 0, 0, 1, b'a', 0]).unwrap().unwrap();
+        assert_eq!((frame.data.as_ref(), length), (&b"a"[..], 6));
+        assert!(frame.is_end() && !frame.is_compressed());
+        assert!(matches!(
+            LengthPrefixed.decode(&[0xff, 0xff, 0xff, 0xff]),
+            Err(Error::InvalidFrame(_))
+        ));
+    }
+
+    #[test]
+    fn streams_frames() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.path.as_str() {
+                "/chunks" => {
+                    test_server::Response::new(200).body(&b"\0\0\0\x03abc\0\0\0\0\0\0\0\x02de"[..])
+                }
+                _ => test_server::Response::new(200).body(&b"\x01\0\0\0\x01x\x80\0\0"[..]),
+            })
+            .await;
+            let api = Streams::new();
+
+            let mut chunks = api.chunks(&base_url).await.unwrap();
+            let mut collected = Vec::new();
+            while let Some(chunk) = chunks.next().await {
+                collected.push(chunk.unwrap());
+            }
+            assert_eq!(collected, ["abc", "", "de"]);
+
+            let mut messages = api.messages(&base_url).await.unwrap();
+            assert_eq!(
+                messages.next().await.unwrap().unwrap(),
+                Envelope {
+                    flags: 1,
+                    data: "x".into()
+                }
+            );
+            assert!(matches!(
+                messages.next().await,
+                Some(Err(Error::InvalidFrame(_)))
+            ));
+            assert!(messages.next().await.is_none());
+        });
+    }
+}
@@ -0,0 +1,396 @@
+//! Incremental extraction of the elements of a JSON array, see [`JsonArrayStream`].
+
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+
+use super::ResponseKind;
+use crate::{endpoint::EndpointSpec, Error, ResultType};
+
+/// Streams the elements of a JSON array without buffering the whole response.
+///
+/// The array is located with the JSON pointer given as the argument of the return kind, e.g.
+/// `JsonArrayStream<T>("/data/items")`, or is the whole document without one. Only a single
+/// element is held in memory at a time, so huge exports can be processed as they arrive.
+/// ```rust
+/// # use api_client::{api, Api};
+/// #[derive(serde::Deserialize)]
+/// struct Row {
+///     id: u64,
+/// }
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn export() -> JsonArrayStream<Row>("/data/rows") {
+///            GET "https://example.com/export"
+///         }
+///     }
+/// }
+///
+/// async fn count_rows(api: &ExampleApi) -> api_client::ResultType<usize> {
+///     let mut rows = api.export().await?;
+///     let mut count = 0;
+///     while let Some(row) = rows.next().await {
+///         row?;
+///         count += 1;
+///     }
+///     Ok(count)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct JsonArrayStream<T>(PhantomData<T>);
+
+#[async_trait::async_trait(?Send)]
+impl<T: DeserializeOwned> ResponseKind for JsonArrayStream<T> {
+    type Output = JsonArrayItems<T>;
+
+    async fn from_response(
+        response: reqwest::Response,
+        spec: &EndpointSpec,
+    ) -> ResultType<Self::Output> {
+        Ok(JsonArrayItems {
+            response,
+            buffer: Vec::new(),
+            position: 0,
+            pointer: spec.pointer.unwrap_or_default(),
+            state: State::Seek,
+            marker: PhantomData,
+        })
+    }
+}
+
+/// Progress of a [`JsonArrayItems`] through the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// The array has not been located yet.
+    Seek,
+    /// The array has been entered, no element has been read yet.
+    First,
+    /// At least one element has been read.
+    Rest,
+    /// The array ended or an error occurred.
+    Done,
+}
+
+/// Elements of a JSON array read incrementally from a response, see [`JsonArrayStream`].
+#[derive(Debug)]
+pub struct JsonArrayItems<T> {
+    /// The response being read.
+    response: reqwest::Response,
+    /// Bytes received but not consumed yet, starting at `position`.
+    buffer: Vec<u8>,
+    /// Position of the next unconsumed byte in `buffer`.
+    position: usize,
+    /// JSON pointer to the array.
+    pointer: &'static str,
+    /// Progress through the document.
+    state: State,
+    /// Type of the elements.
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> JsonArrayItems<T> {
+    /// Reads the next element, or returns `None` once the array ended.
+    ///
+    /// After an error, no further elements are read.
+    pub async fn next(&mut self) -> Option<ResultType<T>> {
+        match self.next_item().await {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) => {
+                self.state = State::Done;
+                None
+            }
+            Err(error) => {
+                self.state = State::Done;
+                Some(Err(error))
+            }
+        }
+    }
+
+    /// Turns the elements into a [`Stream`](futures_util::Stream).
+    pub fn into_stream(self) -> impl futures_util::Stream<Item = ResultType<T>> {
+        futures_util::stream::unfold(self, |mut items| async move {
+            items.next().await.map(|item| (item, items))
+        })
+    }
+
+    /// Reads the next element, locating the array first if needed.
+    async fn next_item(&mut self) -> ResultType<Option<T>> {
+        if self.state == State::Seek {
+            self.seek().await?;
+            self.state = State::First;
+        }
+        if self.state == State::Done {
+            return Ok(None);
+        }
+
+        self.skip_whitespace().await?;
+        match (self.peek().await?, self.state) {
+            (Some(b']'), _) => {
+                self.position += 1;
+                return Ok(None);
+            }
+            (Some(b','), State::Rest) => self.position += 1,
+            (Some(_), State::First) => {}
+            _ => return Err(invalid("expected `,` or `]` after an array element")),
+        }
+        self.state = State::Rest;
+        let value = self.read_value(true).await?;
+        serde_json::from_slice(&value)
+            .map(Some)
+            .map_err(Error::Json)
+    }
+
+    /// Consumes the document up to the first element of the array at the pointer.
+    async fn seek(&mut self) -> ResultType<()> {
+        if !self.pointer.is_empty() && !self.pointer.starts_with('/') {
+            return Err(invalid(format!(
+                "invalid JSON pointer `{}`, it has to start with `/`",
+                self.pointer
+            )));
+        }
+        for token in self.pointer.split('/').skip(1) {
+            let token = token.replace("~1", "/").replace("~0", "~");
+            self.skip_whitespace().await?;
+            match self.peek().await? {
+                Some(b'{') => {
+                    self.position += 1;
+                    self.seek_key(&token).await?;
+                }
+                Some(b'[') => {
+                    self.position += 1;
+                    let index = token.parse::<usize>().map_err(|_| self.not_found())?;
+                    for _ in 0..index {
+                        self.read_value(false).await?;
+                        self.expect(b',').await.map_err(|_| self.not_found())?;
+                    }
+                }
+                _ => return Err(self.not_found()),
+            }
+        }
+        self.expect(b'[').await.map_err(|_| {
+            invalid(format!(
+                "expected an array at JSON pointer `{}`",
+                self.pointer
+            ))
+        })
+    }
+
+    /// Consumes the members of an object up to the value of `key`.
+    async fn seek_key(&mut self, key: &str) -> ResultType<()> {
+        loop {
+            self.skip_whitespace().await?;
+            if self.peek().await? == Some(b'}') {
+                return Err(self.not_found());
+            }
+            let name = self.read_value(true).await?;
+            let name: String = serde_json::from_slice(&name).map_err(Error::Json)?;
+            self.expect(b':').await?;
+            if name == key {
+                return Ok(());
+            }
+            self.read_value(false).await?;
+            self.skip_whitespace().await?;
+            match self.peek().await? {
+                Some(b',') => self.position += 1,
+                Some(b'}') => return Err(self.not_found()),
+                _ => return Err(invalid("expected `,` or `}` after an object member")),
+            }
+        }
+    }
+
+    /// Returns the error for a pointer which does not exist in the document.
+    fn not_found(&self) -> Error {
+        invalid(format!("JSON pointer `{}` not found", self.pointer))
+    }
+
+    /// Returns the next byte without consuming it, reading more of the response if needed.
+    async fn peek(&mut self) -> ResultType<Option<u8>> {
+        if self.position == self.buffer.len() && !self.fill().await? {
+            return Ok(None);
+        }
+        Ok(Some(self.buffer[self.position]))
+    }
+
+    /// Replaces the consumed bytes with the next chunk of the response.
+    ///
+    /// Returns `false` at the end of the response.
+    async fn fill(&mut self) -> ResultType<bool> {
+        self.buffer.drain(..self.position);
+        self.position = 0;
+        while self.buffer.is_empty() {
+            match self.response.chunk().await? {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Consumes whitespace.
+    async fn skip_whitespace(&mut self) -> ResultType<()> {
+        while let Some(byte) = self.peek().await? {
+            if !byte.is_ascii_whitespace() {
+                break;
+            }
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    /// Consumes whitespace and `byte`, failing if the next byte is a different one.
+    async fn expect(&mut self, byte: u8) -> ResultType<()> {
+        self.skip_whitespace().await?;
+        if self.peek().await? != Some(byte) {
+            return Err(invalid(format!("expected `{}`", char::from(byte))));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Consumes a single JSON value, returning its bytes if `capture` is set.
+    ///
+    /// The value is only checked to be balanced here, it is validated when deserialized.
+    async fn read_value(&mut self, capture: bool) -> ResultType<Vec<u8>> {
+        self.skip_whitespace().await?;
+        let mut value = Vec::new();
+        let mut length = 0_usize;
+        let mut depth = 0_usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        loop {
+            if self.peek().await?.is_none() {
+                if depth == 0 && !in_string && length > 0 {
+                    return Ok(value);
+                }
+                return Err(invalid("unexpected end of the response"));
+            }
+
+            let start = self.position;
+            let mut end = None;
+            for (offset, &byte) in self.buffer[start..].iter().enumerate() {
+                if in_string {
+                    if escaped {
+                        escaped = false;
+                    } else if byte == b'\\' {
+                        escaped = true;
+                    } else if byte == b'"' {
+                        in_string = false;
+                        if depth == 0 {
+                            end = Some(offset + 1);
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                match byte {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => depth += 1,
+                    byte if depth == 0
+                        && (matches!(byte, b'}' | b']' | b',' | b':')
+                            || byte.is_ascii_whitespace()) =>
+                    {
+                        end = Some(offset);
+                        break;
+                    }
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = Some(offset + 1);
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            let consumed = end.unwrap_or(self.buffer.len() - start);
+            if capture {
+                value.extend_from_slice(&self.buffer[start..start + consumed]);
+            }
+            self.position += consumed;
+            length += consumed;
+            if end.is_some() {
+                if length == 0 {
+                    return Err(invalid("expected a value"));
+                }
+                return Ok(value);
+            }
+        }
+    }
+}
+
+/// Creates an error for a response which is not the expected JSON.
+fn invalid(message: impl std::fmt::Display) -> Error {
+    Error::Json(serde::de::Error::custom(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{api, test_server, Api};
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Item {
+        id: u32,
+        name: String,
+    }
+
+    api!(struct Export);
+
+    impl Export {
+        api! {
+            fn items(&self, base_url: &str) -> JsonArrayStream<Item>("/data/a~1b") {
+                GET "{base_url}/items"
+            }
+
+            fn numbers(&self, base_url: &str) -> JsonArrayStream<u32> {
+                GET "{base_url}/numbers"
+            }
+
+            fn missing(&self, base_url: &str) -> JsonArrayStream<u32>("/data/missing") {
+                GET "{base_url}/items"
+            }
+        }
+    }
+
+    #[test]
+    fn streams_array_elements() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.path.as_str() {
+                "/items" => test_server::Response::new(200).json(
+                    r#"{"meta": {"skip": [1, {"]": "}"}]}, "data": {"count": 2, "a/b": [
+                        {"id": 1, "name": "first \"quoted\""},
+                        {"id": 2, "name": "[second]"}
+                    ], "after": true}}"#,
+                ),
+                _ => test_server::Response::new(200).json(" [1, 2 ,3] "),
+            })
+            .await;
+            let api = Export::new();
+
+            let mut items = api.items(&base_url).await.unwrap();
+            assert_eq!(
+                items.next().await.unwrap().unwrap(),
+                Item {
+                    id: 1,
+                    name: "first \"quoted\"".to_string()
+                }
+            );
+            assert_eq!(items.next().await.unwrap().unwrap().name, "[second]");
+            assert!(items.next().await.is_none());
+
+            let mut numbers = api.numbers(&base_url).await.unwrap();
+            let mut collected = Vec::new();
+            while let Some(number) = numbers.next().await {
+                collected.push(number.unwrap());
+            }
+            assert_eq!(collected, [1, 2, 3]);
+
+            let mut missing = api.missing(&base_url).await.unwrap();
+            assert!(missing.next().await.unwrap().is_err());
+            assert!(missing.next().await.is_none());
+        });
+    }
+}
@@ -0,0 +1,388 @@
+//! Retrying calls of endpoints after transient failures.
+//!
+//! An endpoint declared with `with { retry: default }` sends its request again after failing to
+//! connect, a timeout, `429 Too Many Requests` or a server error, making up to three attempts.
+//! It waits as long as the `Retry-After` header of the failed response asks, or 200ms before the
+//! first retry, doubled for every further one. `retry: 5` makes up to five attempts, and
+//! `retry: POLICY` takes any [`RetryPolicy`] constant, e.g. one with another backoff.
+//!
+//! Retries are part of the call, so the other endpoint options apply to all of them: a
+//! [`deadline`](crate::deadline) bounds the attempts and the waits between them, and a retry whose
+//! wait would end after it is not made. A `fallback` applies once the last attempt failed.
+//! Identical requests coalesced by [`ApiState::deduplicate`](crate::ApiState::deduplicate) share
+//! the attempts of one of them, and a cached response is only revalidated after the last.
+//! Interceptors run for every attempt. Requests whose body is a stream can't be repeated and are
+//! sent once.
+//!
+//! `POST` and `PATCH` requests which may not be repeated safely are sent with an
+//! `Idempotency-Key`, a random UUID shared by all attempts of the call, so the server processes
+//! only one of them, see [`idempotency`](crate::idempotency). Endpoints declared
+//! `with { idempotent }` are sent without it.
+//!
+//! # Usage
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use api_client::{api, retry::RetryPolicy};
+//!
+//! /// Retries payments up to five times, a second apart at first.
+//! const PAYMENTS: RetryPolicy = RetryPolicy::new(5)
+//!     .backoff(Duration::from_secs(1))
+//!     .idempotency_key("x-idempotency-key");
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn quote(&self, id: u32) -> String {
+//!             GET "https://example.com/quotes/{id}"
+//!             with { retry: default, timeout: 10s, cache: 5m }
+//!         }
+//!
+//!         fn pay(&self, order: u32) -> StatusCode {
+//!             POST "https://example.com/orders/{order}/payments"
+//!             with { retry: PAYMENTS, deadline: 30s }
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{future::Future, time::Duration};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName},
+    Method, StatusCode,
+};
+
+use crate::{
+    deadline, endpoint::EndpointSpec, idempotency, poll, runtime::Runtime, single_flight, trace,
+    Error, RequestBuilder, ResultType,
+};
+
+/// How often and how far apart the request of an endpoint is sent after transient failures, see
+/// [`retry`](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of attempts, including the first one.
+    attempts: u32,
+    /// Wait before the first retry, doubled for every further one.
+    backoff: Duration,
+    /// Name of the header carrying the idempotency key of `POST` and `PATCH` requests.
+    idempotency_key: &'static str,
+}
+
+impl RetryPolicy {
+    /// Makes up to three attempts, 200ms apart at first, declared with `with { retry: default }`.
+    pub const DEFAULT: Self = Self::new(3);
+
+    /// Creates a policy making up to `attempts` attempts, including the first one.
+    #[must_use]
+    pub const fn new(attempts: u32) -> Self {
+        Self {
+            attempts,
+            backoff: Duration::from_millis(200),
+            idempotency_key: idempotency::IDEMPOTENCY_KEY,
+        }
+    }
+
+    /// Sets the wait before the first retry, doubled for every further one, 200ms by default.
+    ///
+    /// A `Retry-After` header of the failed response takes precedence.
+    #[must_use]
+    pub const fn backoff(self, backoff: Duration) -> Self {
+        Self { backoff, ..self }
+    }
+
+    /// Sends the idempotency keys of `POST` and `PATCH` requests as `name` instead of
+    /// `Idempotency-Key`.
+    ///
+    /// Calls of endpoints with the policy panic if `name` is not a valid header name.
+    #[must_use]
+    pub const fn idempotency_key(self, name: &'static str) -> Self {
+        Self {
+            idempotency_key: name,
+            ..self
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Response of a single attempt, whose status decides whether the request is sent again.
+pub(crate) trait Attempt {
+    /// Returns the status of the response.
+    fn status(&self) -> StatusCode;
+
+    /// Returns the headers of the response.
+    fn headers(&self) -> &HeaderMap;
+}
+
+impl Attempt for reqwest::Response {
+    fn status(&self) -> StatusCode {
+        self.status()
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        self.headers()
+    }
+}
+
+impl Attempt for single_flight::SharedResponse {
+    fn status(&self) -> StatusCode {
+        self.status()
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        self.headers()
+    }
+}
+
+impl<T> Attempt for (reqwest::Response, T) {
+    fn status(&self) -> StatusCode {
+        self.0.status()
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        self.0.headers()
+    }
+}
+
+/// Gives the request of a `POST` or `PATCH` endpoint which retries and isn't declared idempotent
+/// an idempotency key shared by all its attempts, unless it carries one already.
+///
+/// # Panics
+/// Panics if the retry policy of the endpoint names an invalid idempotency key header.
+pub(crate) fn idempotency_key(spec: &EndpointSpec, request: RequestBuilder) -> RequestBuilder {
+    let policy = match spec.retry {
+        Some(policy) if !spec.idempotent && matches!(spec.method, Method::POST | Method::PATCH) => {
+            policy
+        }
+        _ => return request,
+    };
+    let header =
+        HeaderName::try_from(policy.idempotency_key).expect("invalid idempotency key header name");
+    match request.try_clone().and_then(|request| request.build().ok()) {
+        Some(sent) if !sent.headers().contains_key(&header) => {
+            request.header(header, idempotency::key())
+        }
+        _ => request,
+    }
+}
+
+/// Sends `request` with `send`, and again after transient failures as the retry policy of the
+/// endpoint described by `spec` allows, waiting with `runtime` in between.
+///
+/// # Errors
+/// Returns the error of the last attempt.
+pub(crate) async fn send<R, F, Fut>(
+    spec: &EndpointSpec,
+    runtime: &dyn Runtime,
+    mut request: RequestBuilder,
+    mut send: F,
+) -> ResultType<R>
+where
+    R: Attempt,
+    F: FnMut(RequestBuilder) -> Fut,
+    Fut: Future<Output = ResultType<R>>,
+{
+    let Some(policy) = spec.retry else {
+        return send(request).await;
+    };
+    let mut backoff = policy.backoff;
+    for attempt in 1..policy.attempts {
+        let Some(again) = request.try_clone() else {
+            break;
+        };
+        let output = send(request).await;
+        let wait = match &output {
+            Ok(response) if is_transient_status(response.status()) => {
+                poll::retry_after(response.headers()).unwrap_or(backoff)
+            }
+            Err(error) if is_transient(error) => backoff,
+            _ => return output,
+        };
+        if deadline::remaining().is_some_and(|remaining| wait >= remaining) {
+            return output;
+        }
+        match &output {
+            Ok(response) => trace::retry(None, attempt + 1, &response.status()),
+            Err(error) => trace::retry(None, attempt + 1, error),
+        }
+        drop(output);
+        runtime.sleep(wait).await;
+        backoff = backoff.saturating_mul(2);
+        request = again;
+    }
+    send(request).await
+}
+
+/// Returns whether a response with `status` may be followed by a successful attempt:
+/// `429 Too Many Requests` or a server error.
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Returns whether sending a request again may succeed after `error`: failing to connect, a
+/// timeout, `429 Too Many Requests` or a server error.
+pub(crate) fn is_transient(error: &Error) -> bool {
+    let transient = |status: u16| StatusCode::from_u16(status).is_ok_and(is_transient_status);
+    match error.root() {
+        Error::Client(error) => {
+            error.is_connect()
+                || error.is_timeout()
+                || error
+                    .status()
+                    .is_some_and(|status| transient(status.as_u16()))
+        }
+        Error::Transport(_) | Error::DeadlineExceeded(_) => true,
+        #[cfg(feature = "json")]
+        Error::Problem(problem) => problem.status.is_some_and(transient),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    };
+
+    use super::RetryPolicy;
+    use crate::{api, deadline, test_server};
+
+    /// Policy retrying twice without waiting long.
+    const QUICK: RetryPolicy = RetryPolicy::new(3)
+        .backoff(Duration::from_millis(10))
+        .idempotency_key("x-idempotency-key");
+
+    api!(struct Flaky);
+
+    impl Flaky {
+        api! {
+            fn fetch(&self, base_url: &str) -> StatusCode {
+                GET "{base_url}/fetch"
+                with { retry: QUICK }
+            }
+
+            fn once(&self, base_url: &str) -> StatusCode {
+                GET "{base_url}/once"
+            }
+
+            fn create(&self, base_url: &str) -> StatusCode {
+                POST "{base_url}/create"
+                with { retry: QUICK }
+            }
+
+            fn search(&self, base_url: &str) -> StatusCode {
+                POST "{base_url}/search"
+                with { retry: 2, idempotent }
+            }
+
+            fn bounded(&self, base_url: &str) -> StatusCode {
+                GET "{base_url}/bounded"
+                with { retry: 10, deadline: 500ms }
+            }
+        }
+    }
+
+    /// Serves `503` to the first `failures` requests of every path and `200` afterwards, recording
+    /// the path and idempotency key of every request.
+    async fn flaky(failures: usize) -> (String, Arc<Mutex<Vec<(String, Option<String>)>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let server_requests = requests.clone();
+        let base_url = test_server::serve(move |request| {
+            let key = request.header("x-idempotency-key").map(str::to_string);
+            let mut requests = server_requests.lock().unwrap();
+            requests.push((request.path.clone(), key));
+            let attempts = requests
+                .iter()
+                .filter(|(path, _)| *path == request.path)
+                .count();
+            if attempts > failures {
+                test_server::Response::new(200)
+            } else {
+                test_server::Response::new(503).header("retry-after", "0")
+            }
+        })
+        .await;
+        (base_url, requests)
+    }
+
+    /// Returns the number of requests recorded for `path`.
+    fn count(requests: &Mutex<Vec<(String, Option<String>)>>, path: &str) -> usize {
+        let requests = requests.lock().unwrap();
+        requests.iter().filter(|(sent, _)| sent == path).count()
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        tokio_test::block_on(async {
+            let (base_url, requests) = flaky(2).await;
+            let api = Flaky::builder().build().unwrap();
+
+            assert_eq!(api.fetch(&base_url).await.unwrap(), 200);
+            assert_eq!(count(&requests, "/fetch"), 3);
+            assert_eq!(api.once(&base_url).await.unwrap(), 503);
+            assert_eq!(count(&requests, "/once"), 1);
+            assert_eq!(api.search(&base_url).await.unwrap(), 503);
+            assert_eq!(count(&requests, "/search"), 2);
+            assert!(requests
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|(_, key)| key.is_none()));
+        });
+    }
+
+    #[test]
+    fn reuses_idempotency_keys_across_attempts() {
+        tokio_test::block_on(async {
+            let (base_url, requests) = flaky(1).await;
+            let api = Flaky::builder().build().unwrap();
+
+            api.create(&base_url).await.unwrap();
+            api.create(&base_url).await.unwrap();
+            let keys = requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(_, key)| key.clone().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(keys.len(), 3);
+            assert_eq!(keys[0], keys[1]);
+            assert_ne!(keys[1], keys[2]);
+        });
+    }
+
+    #[test]
+    fn stops_at_the_deadline() {
+        tokio_test::block_on(async {
+            let attempts = Arc::new(AtomicUsize::new(0));
+            let server_attempts = attempts.clone();
+            let base_url = test_server::serve(move |_| {
+                server_attempts.fetch_add(1, Ordering::SeqCst);
+                test_server::Response::new(503).header("retry-after", "1")
+            })
+            .await;
+            let api = Flaky::builder().build().unwrap();
+
+            let started = Instant::now();
+            assert_eq!(api.bounded(&base_url).await.unwrap(), 503);
+            assert!(started.elapsed() < Duration::from_millis(500));
+            assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+            let result =
+                deadline::within(Duration::from_millis(100), Box::pin(api.fetch(&base_url))).await;
+            assert_eq!(result.unwrap(), 503);
+            assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        });
+    }
+}
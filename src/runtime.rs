@@ -0,0 +1,162 @@
+//! Running the background tasks and timers of the crate on other async runtimes than Tokio.
+//!
+//! Waiting for rate limits, [`TokenBucket`](crate::rate_limit::TokenBucket)s and the background
+//! revalidation of [`CacheMode::StaleWhileRevalidate`](crate::cache::CacheMode::StaleWhileRevalidate)
+//! sleep and spawn tasks through a [`Runtime`], which is [`Tokio`] unless another one is set with
+//! [`ApiState::runtime`](crate::ApiState::runtime) and
+//! [`TokenBucket::runtime`](crate::rate_limit::TokenBucket::runtime).
+//!
+//! `reqwest` drives its connections with Tokio, so under async-std or smol requests still need a
+//! Tokio reactor, e.g. through the `tokio1` feature of async-std or the `async-compat` crate. The
+//! [`DiskCache`](crate::cache::DiskCache) also uses Tokio for file access.
+//!
+//! # Usage
+//! An implementation for async-std sleeps with `async_std::task::sleep` and spawns with
+//! `async_std::task::spawn`. This one spawns the background tasks on a dedicated Tokio runtime:
+//! ```rust
+//! use std::{future::Future, pin::Pin, time::Duration};
+//!
+//! use api_client::{api, runtime::Runtime, ApiState};
+//!
+//! #[derive(Debug)]
+//! struct Background(tokio::runtime::Handle);
+//!
+//! #[async_trait::async_trait]
+//! impl Runtime for Background {
+//!     async fn sleep(&self, duration: Duration) {
+//!         tokio::time::sleep(duration).await;
+//!     }
+//!
+//!     fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send>>) {
+//!         self.0.spawn(task);
+//!     }
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let background = tokio::runtime::Runtime::new().unwrap();
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().runtime(Background(background.handle().clone())))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{fmt, future::Future, pin::Pin, sync::Arc, task::Poll, time::Duration};
+
+/// Async runtime running the background tasks and timers of the crate, see
+/// [`runtime`](crate::runtime).
+#[async_trait::async_trait]
+pub trait Runtime: fmt::Debug + Send + Sync {
+    /// Waits until `duration` has elapsed.
+    async fn sleep(&self, duration: Duration);
+
+    /// Runs `task` in the background.
+    fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send>>);
+}
+
+#[async_trait::async_trait]
+impl<R: Runtime + ?Sized> Runtime for Arc<R> {
+    async fn sleep(&self, duration: Duration) {
+        (**self).sleep(duration).await;
+    }
+
+    fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        (**self).spawn(task);
+    }
+}
+
+/// The Tokio runtime, used by default.
+///
+/// Tasks are spawned on the runtime the calling task runs on.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tokio;
+
+#[async_trait::async_trait]
+impl Runtime for Tokio {
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+
+    fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        tokio::spawn(task);
+    }
+}
+
+/// Lets other tasks run once before the current one continues, on any runtime.
+pub(crate) async fn yield_now() {
+    let mut yielded = false;
+    futures_util::future::poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::Runtime;
+    use crate::{api, rate_limit::RateLimiter, rate_limit::TokenBucket, test_server, ApiState};
+
+    /// Runtime recording sleeps instead of waiting.
+    #[derive(Debug, Default)]
+    struct Recorded(Mutex<Vec<Duration>>);
+
+    #[async_trait::async_trait]
+    impl Runtime for Recorded {
+        async fn sleep(&self, duration: Duration) {
+            self.0.lock().unwrap().push(duration);
+        }
+
+        fn spawn(&self, task: Pin<Box<dyn Future<Output = ()> + Send>>) {
+            tokio::spawn(task);
+        }
+    }
+
+    api!(struct Throttled);
+
+    impl Throttled {
+        api! {
+            fn get(&self, base_url: &str) -> StatusCode {
+                GET "{base_url}/"
+            }
+        }
+    }
+
+    #[test]
+    fn sleeps_on_the_runtime() {
+        tokio_test::block_on(async {
+            let base_url =
+                test_server::serve(|_| test_server::Response::new(429).header("retry-after", "30"))
+                    .await;
+            let runtime = Arc::new(Recorded::default());
+            let api = Throttled::builder()
+                .state(ApiState::new().throttle(true).runtime(runtime.clone()))
+                .build()
+                .unwrap();
+            api.get(&base_url).await.unwrap();
+            api.get(&base_url).await.unwrap();
+            let sleeps = runtime.0.lock().unwrap().clone();
+            assert_eq!(sleeps.len(), 1);
+            assert!(sleeps[0] > Duration::from_secs(29));
+
+            let bucket = TokenBucket::new(0.5, 1).runtime(runtime.clone());
+            bucket.acquire().await;
+            bucket.acquire().await;
+            let sleeps = runtime.0.lock().unwrap().clone();
+            assert_eq!(sleeps.len(), 2);
+            assert!(sleeps[1] > Duration::from_millis(1900));
+        });
+    }
+}
@@ -0,0 +1,229 @@
+//! Cookie sessions kept in a file, so command line tools stay logged in between runs.
+//!
+//! Enabled with the `cookies` and `json` features. A [`SessionFile`] enables the
+//! [cookie jar](crate::cookies) of the state an instance is built with and fills it with the
+//! cookies saved by the previous run; [`SessionFile::save`] writes the current ones back, e.g.
+//! after logging in. On Unix the file is only readable by its owner, since session cookies are
+//! credentials.
+//!
+//! [`set_cookie`] and [`set_cookies`] parse the cookies a response sets, e.g. in
+//! [`Api::post_response`] to notice a login or a logout.
+//!
+//! # Usage
+//! ```rust,no_run
+//! use api_client::{api, session::SessionFile, Api, ApiState};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let session = SessionFile::new("/home/user/.config/example/session.json");
+//! let api = ExampleApi::builder()
+//!     .state(session.state(ApiState::new())?)
+//!     .build()
+//!     .unwrap();
+//! // ... log in if the restored session has expired ...
+//! session.save(&api)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use reqwest::header::SET_COOKIE;
+
+use crate::{
+    cookies::{CookieSnapshot, StoredCookie},
+    Api, ApiState,
+};
+
+/// File the cookies of an instance are saved to and restored from, see [`session`](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionFile {
+    /// Path of the file.
+    path: PathBuf,
+}
+
+impl SessionFile {
+    /// Creates a session kept in the file at `path`, which is created on the first save.
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns the path of the file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Enables cookies on `state` and restores the saved ones into its jar.
+    ///
+    /// # Errors
+    /// Returns an error if the file exists but could not be read or parsed.
+    pub fn state(&self, state: ApiState) -> io::Result<ApiState> {
+        let state = state.cookies(true);
+        if let Some(jar) = state.cookie_jar() {
+            jar.restore(self.load()?);
+        }
+        Ok(state)
+    }
+
+    /// Returns the saved cookies, or no cookies if the file doesn't exist.
+    ///
+    /// # Errors
+    /// Returns an error if the file exists but could not be read or parsed.
+    pub fn load(&self) -> io::Result<CookieSnapshot> {
+        match fs::read(&self.path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(CookieSnapshot::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Replaces the cookies of `api` with the saved ones, e.g. for an instance built without
+    /// [`SessionFile::state`].
+    ///
+    /// # Errors
+    /// Returns an error if the file exists but could not be read or parsed.
+    pub fn restore(&self, api: &impl Api) -> io::Result<()> {
+        api.import_cookies(self.load()?);
+        Ok(())
+    }
+
+    /// Saves the cookies of `api` which have not expired, replacing the file at once so a
+    /// concurrent run never reads half of it.
+    ///
+    /// # Errors
+    /// Returns an error if the file or its directory could not be written.
+    pub fn save(&self, api: &impl Api) -> io::Result<()> {
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let contents = serde_json::to_vec_pretty(&api.export_cookies())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        write_private(&temporary, &contents)?;
+        fs::rename(&temporary, &self.path)
+    }
+
+    /// Deletes the file, e.g. after logging out.
+    ///
+    /// # Errors
+    /// Returns an error if the file exists but could not be deleted.
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns the cookies set by `response` with its `Set-Cookie` headers, including expired ones,
+/// which delete a cookie.
+#[must_use]
+pub fn set_cookies(response: &reqwest::Response) -> Vec<StoredCookie> {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .filter_map(|header| StoredCookie::parse(header, response.url()))
+        .collect()
+}
+
+/// Returns the cookie named `name` set by `response`, if any.
+#[must_use]
+pub fn set_cookie(response: &reqwest::Response, name: &str) -> Option<StoredCookie> {
+    set_cookies(response)
+        .into_iter()
+        .find(|cookie| cookie.name == name)
+}
+
+/// Writes `contents` to a new file at `path`, which only its owner may read on Unix.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{set_cookie, SessionFile};
+    use crate::{api, test_server, Api, ApiState};
+
+    api!(struct Cli);
+
+    impl Cli {
+        api! {
+            fn login(&self, base_url: &str) -> Response {
+                POST "{base_url}/login"
+            }
+
+            fn me(&self, base_url: &str) -> String {
+                GET "{base_url}/me"
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_sessions_between_runs() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| match request.path.as_str() {
+                "/login" => test_server::Response::new(204)
+                    .header("set-cookie", "session=secret; Path=/; Max-Age=3600")
+                    .header("set-cookie", "theme=dark"),
+                _ => test_server::Response::new(200)
+                    .body(request.header("cookie").unwrap_or_default().to_string()),
+            })
+            .await;
+            let path = std::env::temp_dir()
+                .join(format!("api-client-session-{}", std::process::id()))
+                .join("session.json");
+            let session = SessionFile::new(&path);
+            let run = || {
+                Cli::builder()
+                    .state(session.state(ApiState::new()).unwrap())
+                    .build()
+                    .unwrap()
+            };
+
+            let api = run();
+            assert_eq!(api.me(&base_url).await.unwrap(), "");
+            let response = api.login(&base_url).await.unwrap();
+            let cookie = set_cookie(&response, "session").unwrap();
+            assert_eq!(
+                (cookie.value.as_str(), cookie.path.as_str()),
+                ("secret", "/")
+            );
+            assert!(cookie.expires.is_some());
+            session.save(&api).unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600);
+            }
+
+            let next = run();
+            let url = reqwest::Url::parse(&base_url).unwrap();
+            let jar = next.state().cookie_jar().unwrap();
+            assert_eq!(jar.get("session", &url).unwrap().value, "secret");
+            let cookies = next.me(&base_url).await.unwrap();
+            assert!(cookies.contains("session=secret") && cookies.contains("theme=dark"));
+
+            session.clear().unwrap();
+            assert_eq!(run().me(&base_url).await.unwrap(), "");
+            std::fs::remove_dir(path.parent().unwrap()).unwrap();
+        });
+    }
+}
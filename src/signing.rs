@@ -0,0 +1,401 @@
+//! Signing requests with HMAC-SHA256, as exchange and payment APIs require, with HTTP Message
+//! Signatures, see [`message`], and with AWS Signature Version 4 with the `aws-sigv4` feature, see
+//! `SigV4Signer`.
+//!
+//! An [`HmacSigner`] builds a canonical string from the parts of a request the API signs, e.g. its
+//! method, path, a timestamp and the hash of its body, and sends the HMAC-SHA256 of it with the
+//! shared secret in a header or query parameter. Since the body is only attached after
+//! [`Api::pre_request`](crate::Api::pre_request), the signer is added as an
+//! [interceptor](crate::interceptor), which sees the request as it is sent. Add it after other
+//! interceptors changing the request, so it signs the final one; requests replayed by the ones
+//! before it are signed again with a new timestamp.
+//!
+//! By default the canonical string is `METHOD\npath?query\ntimestamp\nbody-hash`, with the
+//! timestamp in seconds sent in `X-Timestamp` and the hex signature in `X-Signature`.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{
+//!     api,
+//!     signing::{HmacSigner, Part, Placement},
+//!     ApiState,
+//! };
+//!
+//! api!(pub struct Exchange);
+//!
+//! // Binance-style: the timestamp and the signature of the query and body are query parameters.
+//! let signer = HmacSigner::new("secret")
+//!     .parts([Part::Query, Part::Body])
+//!     .separator("")
+//!     .timestamp(Placement::Query("timestamp".to_string()))
+//!     .millis()
+//!     .signature(Placement::Query("signature".to_string()));
+//! let api = Exchange::builder()
+//!     .state(ApiState::new().interceptor(signer))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    fmt::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderName, HeaderValue};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    interceptor::{Interceptor, Next},
+    Error, ResultType,
+};
+
+pub mod message;
+#[cfg(feature = "aws-sigv4")]
+mod sigv4;
+
+#[cfg(feature = "aws-sigv4")]
+#[cfg_attr(docsrs, doc(cfg(feature = "aws-sigv4")))]
+pub use sigv4::SigV4Signer;
+
+/// A part of a request included in the canonical string, see [`HmacSigner::parts`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Part {
+    /// The method, e.g. `POST`.
+    Method,
+    /// The path, e.g. `/api/v3/order`.
+    Path,
+    /// The query string without the leading `?`, empty without one.
+    Query,
+    /// The path followed by the query string, if any.
+    PathAndQuery,
+    /// The timestamp sent with the request.
+    Timestamp,
+    /// The body as it is sent, empty without one.
+    Body,
+    /// The lowercase hex SHA-256 hash of the body.
+    BodyHash,
+    /// The value of the header with the given name, empty if it is not set.
+    Header(String),
+}
+
+/// Where a value is sent, see [`HmacSigner::timestamp`] and [`HmacSigner::signature`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Placement {
+    /// In the header with the given name.
+    Header(String),
+    /// In the query parameter with the given name, appended to the URL.
+    Query(String),
+}
+
+/// Signs requests with HMAC-SHA256, see [`signing`](crate::signing).
+#[derive(Clone)]
+pub struct HmacSigner {
+    /// The shared secret.
+    secret: Vec<u8>,
+    /// Parts of the canonical string.
+    parts: Vec<Part>,
+    /// Separator between the parts.
+    separator: String,
+    /// Where the timestamp is sent, if at all.
+    timestamp: Option<Placement>,
+    /// Whether the timestamp is in milliseconds instead of seconds.
+    millis: bool,
+    /// Where the signature is sent.
+    signature: Placement,
+    /// Whether the signature is encoded as base64 instead of hex.
+    base64: bool,
+}
+
+impl fmt::Debug for HmacSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HmacSigner")
+            .field("parts", &self.parts)
+            .field("separator", &self.separator)
+            .field("timestamp", &self.timestamp)
+            .field("signature", &self.signature)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HmacSigner {
+    /// Creates a signer with the shared `secret` and the default canonical string, see
+    /// [`signing`](crate::signing).
+    #[must_use]
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            secret: secret.as_ref().to_vec(),
+            parts: vec![
+                Part::Method,
+                Part::PathAndQuery,
+                Part::Timestamp,
+                Part::BodyHash,
+            ],
+            separator: "\n".to_string(),
+            timestamp: Some(Placement::Header("x-timestamp".to_string())),
+            millis: false,
+            signature: Placement::Header("x-signature".to_string()),
+            base64: false,
+        }
+    }
+
+    /// Sets the parts of the canonical string, in order.
+    #[must_use]
+    pub fn parts(mut self, parts: impl IntoIterator<Item = Part>) -> Self {
+        self.parts = parts.into_iter().collect();
+        self
+    }
+
+    /// Sets the separator between the parts of the canonical string, `\n` by default.
+    #[must_use]
+    pub fn separator(mut self, separator: &str) -> Self {
+        self.separator = separator.to_string();
+        self
+    }
+
+    /// Sets where the timestamp is sent, the `X-Timestamp` header by default.
+    ///
+    /// A timestamp sent in the query is part of [`Part::Query`].
+    #[must_use]
+    pub fn timestamp(mut self, placement: Placement) -> Self {
+        self.timestamp = Some(placement);
+        self
+    }
+
+    /// Doesn't send a timestamp, [`Part::Timestamp`] is then empty.
+    #[must_use]
+    pub fn without_timestamp(mut self) -> Self {
+        self.timestamp = None;
+        self
+    }
+
+    /// Sends the timestamp in milliseconds instead of seconds.
+    #[must_use]
+    pub fn millis(mut self) -> Self {
+        self.millis = true;
+        self
+    }
+
+    /// Sets where the signature is sent, the `X-Signature` header by default.
+    #[must_use]
+    pub fn signature(mut self, placement: Placement) -> Self {
+        self.signature = placement;
+        self
+    }
+
+    /// Encodes the signature as base64 instead of lowercase hex.
+    #[must_use]
+    pub fn base64(mut self) -> Self {
+        self.base64 = true;
+        self
+    }
+
+    /// Adds the timestamp and the signature to `request`, e.g. one built with a
+    /// `build_<name>_request` method.
+    ///
+    /// # Errors
+    /// Returns [`Error::Signing`] if the body is streamed, e.g. a multipart body, and the body is
+    /// part of the canonical string, or if a header name is invalid.
+    pub fn sign(&self, request: &mut reqwest::Request) -> ResultType<()> {
+        let timestamp = match &self.timestamp {
+            Some(placement) => {
+                let timestamp = self.now();
+                place(request, placement, &timestamp)?;
+                timestamp
+            }
+            None => String::new(),
+        };
+        let canonical = self.canonical(request, &timestamp)?;
+        let signature = hmac_sha256(&self.secret, canonical.as_bytes());
+        let signature = if self.base64 {
+            base64::engine::general_purpose::STANDARD.encode(signature)
+        } else {
+            hex(&signature)
+        };
+        place(request, &self.signature, &signature)
+    }
+
+    /// Returns the current time in seconds, or milliseconds, since the Unix epoch.
+    fn now(&self) -> String {
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        if self.millis {
+            elapsed.as_millis().to_string()
+        } else {
+            elapsed.as_secs().to_string()
+        }
+    }
+
+    /// Returns the canonical string of `request`.
+    fn canonical(&self, request: &reqwest::Request, timestamp: &str) -> ResultType<String> {
+        let url = request.url();
+        let body = || match request.body() {
+            None => Ok(&[][..]),
+            Some(body) => body.as_bytes().ok_or_else(|| {
+                Error::Signing("streamed bodies can't be part of the signature".to_string())
+            }),
+        };
+        let parts = self
+            .parts
+            .iter()
+            .map(|part| {
+                Ok(match part {
+                    Part::Method => request.method().to_string(),
+                    Part::Path => url.path().to_string(),
+                    Part::Query => url.query().unwrap_or_default().to_string(),
+                    Part::PathAndQuery => match url.query() {
+                        Some(query) => format!("{}?{query}", url.path()),
+                        None => url.path().to_string(),
+                    },
+                    Part::Timestamp => timestamp.to_string(),
+                    Part::Body => String::from_utf8_lossy(body()?).into_owned(),
+                    Part::BodyHash => hex(&Sha256::digest(body()?)),
+                    Part::Header(name) => request
+                        .headers()
+                        .get(name.as_str())
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string(),
+                })
+            })
+            .collect::<ResultType<Vec<_>>>()?;
+        Ok(parts.join(&self.separator))
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Interceptor for HmacSigner {
+    async fn intercept(
+        &self,
+        mut request: reqwest::Request,
+        next: Next<'_>,
+    ) -> ResultType<reqwest::Response> {
+        self.sign(&mut request)?;
+        next.run(request).await
+    }
+}
+
+/// Sends `value` in `request` as given by `placement`.
+fn place(request: &mut reqwest::Request, placement: &Placement, value: &str) -> ResultType<()> {
+    match placement {
+        Placement::Header(name) => {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| Error::Signing(format!("invalid header name `{name}`")))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| Error::Signing(format!("invalid value for `{name}`")))?;
+            request.headers_mut().insert(name, value);
+        }
+        Placement::Query(name) => {
+            request.url_mut().query_pairs_mut().append_pair(name, value);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the HMAC-SHA256 of `data` with `key`.
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Encodes `bytes` as lowercase hex.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    use super::{hex, HmacSigner, Part, Placement};
+    use crate::{api, test_server, ApiState};
+
+    #[test]
+    fn signs_canonical_string() {
+        let mut request = reqwest::Client::new()
+            .post("https://example.com/")
+            .body("The quick brown fox jumps over the lazy dog")
+            .build()
+            .unwrap();
+        HmacSigner::new("key")
+            .parts([Part::Body])
+            .without_timestamp()
+            .sign(&mut request)
+            .unwrap();
+        assert_eq!(
+            request.headers()["x-signature"],
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+        assert!(request.headers().get("x-timestamp").is_none());
+    }
+
+    api!(struct Exchange);
+
+    impl Exchange {
+        api! {
+            fn order(&self, request: Json<[u32]>, base_url: &str, symbol: &str) -> StatusCode {
+                POST "{base_url}/order?symbol={symbol}"
+            }
+        }
+    }
+
+    /// Returns the hex HMAC-SHA256 of `message` with the secret of the tests.
+    fn expected(message: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(message.as_bytes());
+        hex(&mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn signs_requests() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let body = String::from_utf8_lossy(&request.body).into_owned();
+                let valid = if let Some((path, signature)) = request.path.split_once("&signature=")
+                {
+                    let query = path.split_once('?').unwrap().1;
+                    signature == expected(&format!("{query}{body}"))
+                } else {
+                    let timestamp = request.header("x-timestamp").unwrap();
+                    let body_hash = hex(&<Sha256 as sha2::Digest>::digest(&request.body));
+                    let canonical = format!("POST\n{}\n{timestamp}\n{body_hash}", request.path);
+                    request.header("x-signature") == Some(&expected(&canonical))
+                };
+                test_server::Response::new(if valid { 200 } else { 401 })
+            })
+            .await;
+            let api = |signer| {
+                Exchange::builder()
+                    .state(ApiState::new().interceptor(signer))
+                    .build()
+                    .unwrap()
+            };
+
+            let signed = api(HmacSigner::new("secret"));
+            let status = signed.order(&[1, 2], &base_url, "BTCUSDT").await.unwrap();
+            assert_eq!(status.as_u16(), 200);
+
+            let signed = api(HmacSigner::new("secret")
+                .parts([Part::Query, Part::Body])
+                .separator("")
+                .timestamp(Placement::Query("timestamp".to_string()))
+                .millis()
+                .signature(Placement::Query("signature".to_string())));
+            let status = signed.order(&[3], &base_url, "ETHUSDT").await.unwrap();
+            assert_eq!(status.as_u16(), 200);
+
+            let wrong = api(HmacSigner::new("wrong"));
+            let status = wrong.order(&[1], &base_url, "BTCUSDT").await.unwrap();
+            assert_eq!(status.as_u16(), 401);
+        });
+    }
+}
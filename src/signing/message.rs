@@ -0,0 +1,869 @@
+//! HTTP Message Signatures ([RFC 9421]) with `Content-Digest` ([RFC 9530]).
+//!
+//! A [`MessageSigner`] signs the chosen components of requests, e.g. their method, target URI and
+//! headers, and sends the signature in the `Signature` and `Signature-Input` headers. If the
+//! components include `content-digest`, the SHA-256 digest of the body is sent in the
+//! `Content-Digest` header first, so the signature covers the body. Like the other signers it is
+//! added as an [interceptor](crate::interceptor).
+//!
+//! A [`MessageVerifier`] checks the signatures of messages received from a peer, e.g. webhooks
+//! or signed responses, given as [`http`] types with their whole body.
+//!
+//! Keys implement [`SigningKey`] and [`VerifyingKey`]. [`HmacSha256Key`] is built in, Ed25519
+//! keys are available with the `ed25519` feature. Component parameters such as `;sf` or `;req`
+//! are not supported.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{
+//!     api,
+//!     signing::message::{HmacSha256Key, MessageSigner},
+//!     ApiState,
+//! };
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let signer = MessageSigner::new("my-key", HmacSha256Key::new("secret"))
+//!     .components(["@method", "@target-uri", "content-type", "content-digest"]);
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().interceptor(signer))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! [RFC 9421]: https://www.rfc-editor.org/rfc/rfc9421
+//! [RFC 9530]: https://www.rfc-editor.org/rfc/rfc9530
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Url,
+};
+use sha2::{Digest, Sha256, Sha512};
+
+use super::hmac_sha256;
+use crate::{
+    interceptor::{Interceptor, Next},
+    Error, ResultType,
+};
+
+/// Key creating signatures for a [`MessageSigner`].
+pub trait SigningKey: fmt::Debug + Send + Sync {
+    /// Name of the algorithm, sent in the `alg` parameter if enabled, e.g. `hmac-sha256`.
+    fn algorithm(&self) -> &'static str;
+
+    /// Signs the signature base.
+    ///
+    /// # Errors
+    /// Returns [`Error::Signing`] if the key can't sign.
+    fn sign(&self, base: &[u8]) -> ResultType<Vec<u8>>;
+}
+
+/// Key checking signatures for a [`MessageVerifier`].
+pub trait VerifyingKey: fmt::Debug + Send + Sync {
+    /// Name of the algorithm, compared to the `alg` parameter if a signature has one.
+    fn algorithm(&self) -> &'static str;
+
+    /// Returns whether `signature` is a valid signature of the signature base.
+    fn verify(&self, base: &[u8], signature: &[u8]) -> bool;
+}
+
+/// Shared secret for HMAC-SHA256 signatures, the `hmac-sha256` algorithm.
+#[derive(Clone)]
+pub struct HmacSha256Key(Vec<u8>);
+
+impl HmacSha256Key {
+    /// Creates a key from the shared `secret`.
+    #[must_use]
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self(secret.as_ref().to_vec())
+    }
+}
+
+impl fmt::Debug for HmacSha256Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HmacSha256Key").finish_non_exhaustive()
+    }
+}
+
+impl SigningKey for HmacSha256Key {
+    fn algorithm(&self) -> &'static str {
+        "hmac-sha256"
+    }
+
+    fn sign(&self, base: &[u8]) -> ResultType<Vec<u8>> {
+        Ok(hmac_sha256(&self.0, base))
+    }
+}
+
+impl VerifyingKey for HmacSha256Key {
+    fn algorithm(&self) -> &'static str {
+        "hmac-sha256"
+    }
+
+    fn verify(&self, base: &[u8], signature: &[u8]) -> bool {
+        Hmac::<Sha256>::new_from_slice(&self.0).is_ok_and(|mut mac| {
+            mac.update(base);
+            mac.verify_slice(signature).is_ok()
+        })
+    }
+}
+
+/// Private key for Ed25519 signatures, the `ed25519` algorithm.
+#[cfg(feature = "ed25519")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ed25519")))]
+pub struct Ed25519SigningKey(ring::signature::Ed25519KeyPair);
+
+#[cfg(feature = "ed25519")]
+impl Ed25519SigningKey {
+    /// Creates a key from its PKCS#8 DER encoding, e.g. the content of a `PRIVATE KEY` PEM block.
+    ///
+    /// # Errors
+    /// Returns [`Error::Signing`] if `der` is not an Ed25519 private key.
+    pub fn from_pkcs8(der: &[u8]) -> ResultType<Self> {
+        ring::signature::Ed25519KeyPair::from_pkcs8_maybe_unchecked(der)
+            .map(Self)
+            .map_err(|error| Error::Signing(format!("invalid Ed25519 private key: {error}")))
+    }
+}
+
+#[cfg(feature = "ed25519")]
+impl fmt::Debug for Ed25519SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ed25519SigningKey").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "ed25519")]
+impl SigningKey for Ed25519SigningKey {
+    fn algorithm(&self) -> &'static str {
+        "ed25519"
+    }
+
+    fn sign(&self, base: &[u8]) -> ResultType<Vec<u8>> {
+        Ok(self.0.sign(base).as_ref().to_vec())
+    }
+}
+
+/// Public key for Ed25519 signatures, the `ed25519` algorithm.
+#[cfg(feature = "ed25519")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ed25519")))]
+#[derive(Debug, Clone)]
+pub struct Ed25519VerifyingKey(Vec<u8>);
+
+#[cfg(feature = "ed25519")]
+impl Ed25519VerifyingKey {
+    /// Creates a key from its 32 raw bytes, the last 32 bytes of its DER encoding.
+    #[must_use]
+    pub fn new(public_key: impl AsRef<[u8]>) -> Self {
+        Self(public_key.as_ref().to_vec())
+    }
+}
+
+#[cfg(feature = "ed25519")]
+impl VerifyingKey for Ed25519VerifyingKey {
+    fn algorithm(&self) -> &'static str {
+        "ed25519"
+    }
+
+    fn verify(&self, base: &[u8], signature: &[u8]) -> bool {
+        ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &self.0)
+            .verify(base, signature)
+            .is_ok()
+    }
+}
+
+/// Signs requests with HTTP Message Signatures, see [`message`](self).
+#[derive(Debug, Clone)]
+pub struct MessageSigner {
+    /// Identifier of the key, sent in the `keyid` parameter.
+    key_id: String,
+    /// The key.
+    key: Arc<dyn SigningKey>,
+    /// Label of the signature in the headers.
+    label: String,
+    /// Components covered by the signature.
+    components: Vec<String>,
+    /// How long signatures are valid, if they expire.
+    expires_after: Option<Duration>,
+    /// Value of the `tag` parameter, if any.
+    tag: Option<String>,
+    /// Whether the `alg` parameter is sent.
+    algorithm: bool,
+}
+
+impl MessageSigner {
+    /// Creates a signer labelling its signatures `sig1`, covering the method, the target URI,
+    /// the `Content-Type` and `Content-Digest` headers.
+    #[must_use]
+    pub fn new(key_id: impl Into<String>, key: impl SigningKey + 'static) -> Self {
+        Self {
+            key_id: key_id.into(),
+            key: Arc::new(key),
+            label: "sig1".to_string(),
+            components: ["@method", "@target-uri", "content-type", "content-digest"]
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            expires_after: None,
+            tag: None,
+            algorithm: false,
+        }
+    }
+
+    /// Sets the label of the signature in the `Signature` and `Signature-Input` headers.
+    #[must_use]
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = label.to_string();
+        self
+    }
+
+    /// Sets the components covered by the signature, in order: derived components such as
+    /// `@method`, `@target-uri`, `@authority`, `@scheme`, `@request-target`, `@path` and `@query`,
+    /// or lowercase header names.
+    ///
+    /// Headers which a request doesn't have are left out of its signature, except
+    /// `content-digest`, which is computed from the body.
+    #[must_use]
+    pub fn components<'a>(mut self, components: impl IntoIterator<Item = &'a str>) -> Self {
+        self.components = components
+            .into_iter()
+            .map(str::to_ascii_lowercase)
+            .collect();
+        self
+    }
+
+    /// Makes signatures expire `duration` after they were created, with the `expires` parameter.
+    #[must_use]
+    pub fn expires_after(mut self, duration: Duration) -> Self {
+        self.expires_after = Some(duration);
+        self
+    }
+
+    /// Sends the `tag` parameter, which tells the application the signature is meant for.
+    #[must_use]
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Sends the algorithm of the key in the `alg` parameter.
+    #[must_use]
+    pub fn with_algorithm(mut self) -> Self {
+        self.algorithm = true;
+        self
+    }
+
+    /// Adds the `Content-Digest`, `Signature-Input` and `Signature` headers to `request`, e.g. one
+    /// built with a `build_<name>_request` method.
+    ///
+    /// # Errors
+    /// Returns [`Error::Signing`] if a component can't be computed, e.g. `content-digest` for a
+    /// streamed body or `@status` for a request, or if the key fails to sign.
+    pub fn sign(&self, request: &mut reqwest::Request) -> ResultType<()> {
+        self.sign_at(request, unix_time(SystemTime::now()))
+    }
+
+    /// Signs `request` as created at `created`, in seconds since the Unix epoch.
+    fn sign_at(&self, request: &mut reqwest::Request, created: u64) -> ResultType<()> {
+        if self.components.iter().any(|name| name == "content-digest") {
+            let body = match request.body() {
+                None => &[][..],
+                Some(body) => body.as_bytes().ok_or_else(|| {
+                    Error::Signing("streamed bodies can't be digested".to_string())
+                })?,
+            };
+            let digest = format!("sha-256=:{}:", STANDARD.encode(Sha256::digest(body)));
+            request
+                .headers_mut()
+                .insert("content-digest", header_value(&digest)?);
+        }
+
+        let message = Message {
+            method: Some(request.method().as_str()),
+            url: Some(request.url().clone()),
+            status: None,
+            headers: request.headers(),
+            body: None,
+        };
+        let components: Vec<_> = self
+            .components
+            .iter()
+            .filter(|name| name.starts_with('@') || request.headers().contains_key(name.as_str()))
+            .cloned()
+            .collect();
+        let mut params = vec![format!("created={created}")];
+        if let Some(duration) = self.expires_after {
+            params.push(format!("expires={}", created + duration.as_secs()));
+        }
+        params.push(format!("keyid=\"{}\"", self.key_id));
+        if self.algorithm {
+            params.push(format!("alg=\"{}\"", self.key.algorithm()));
+        }
+        if let Some(tag) = &self.tag {
+            params.push(format!("tag=\"{tag}\""));
+        }
+        let quoted: Vec<_> = components
+            .iter()
+            .map(|name| format!("\"{name}\""))
+            .collect();
+        let input = format!("({});{}", quoted.join(" "), params.join(";"));
+        let base = message
+            .base(&components, &input)
+            .map_err(|error| Error::Signing(error.to_string()))?;
+        let signature = self.key.sign(base.as_bytes())?;
+
+        let headers = request.headers_mut();
+        headers.insert(
+            "signature-input",
+            header_value(&format!("{}={input}", self.label))?,
+        );
+        headers.insert(
+            "signature",
+            header_value(&format!("{}=:{}:", self.label, STANDARD.encode(signature)))?,
+        );
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Interceptor for MessageSigner {
+    async fn intercept(
+        &self,
+        mut request: reqwest::Request,
+        next: Next<'_>,
+    ) -> ResultType<reqwest::Response> {
+        self.sign(&mut request)?;
+        next.run(request).await
+    }
+}
+
+/// Checks HTTP Message Signatures of received messages, see [`message`](self).
+///
+/// A message is valid if one of its signatures was made with a known key, covers the required
+/// components, has not expired and, if it covers `content-digest`, the digest matches the body.
+/// Requests with a relative URI, as servers receive them, are taken to be `https` requests to the
+/// host in their `Host` header.
+#[derive(Debug, Clone, Default)]
+pub struct MessageVerifier {
+    /// Keys by their identifier.
+    keys: Vec<(String, Arc<dyn VerifyingKey>)>,
+    /// Components every signature has to cover.
+    required: Vec<String>,
+    /// Maximum age of signatures, if limited.
+    max_age: Option<Duration>,
+}
+
+impl MessageVerifier {
+    /// Creates a verifier without keys.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts signatures made with the key identified by `key_id`.
+    #[must_use]
+    pub fn key(mut self, key_id: impl Into<String>, key: impl VerifyingKey + 'static) -> Self {
+        self.keys.push((key_id.into(), Arc::new(key)));
+        self
+    }
+
+    /// Requires signatures to cover `component`, e.g. `content-digest`.
+    #[must_use]
+    pub fn require(mut self, component: &str) -> Self {
+        self.required.push(component.to_ascii_lowercase());
+        self
+    }
+
+    /// Rejects signatures created more than `max_age` ago.
+    #[must_use]
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Checks the signatures of `request`.
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidSignature`] if no signature is valid.
+    pub fn verify_request<B: AsRef<[u8]>>(&self, request: &http::Request<B>) -> ResultType<()> {
+        let uri = request.uri();
+        let url = if uri.scheme().is_some() {
+            uri.to_string()
+        } else {
+            let host = request
+                .headers()
+                .get(http::header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .ok_or_else(|| invalid("relative request URI without a `Host` header"))?;
+            let target = uri
+                .path_and_query()
+                .map_or("/", http::uri::PathAndQuery::as_str);
+            format!("https://{host}{target}")
+        };
+        self.verify(&Message {
+            method: Some(request.method().as_str()),
+            url: Some(Url::parse(&url).map_err(|error| invalid(&error.to_string()))?),
+            status: None,
+            headers: request.headers(),
+            body: Some(request.body().as_ref()),
+        })
+    }
+
+    /// Checks the signatures of `response`.
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidSignature`] if no signature is valid.
+    pub fn verify_response<B: AsRef<[u8]>>(&self, response: &http::Response<B>) -> ResultType<()> {
+        self.verify(&Message {
+            method: None,
+            url: None,
+            status: Some(response.status().as_u16()),
+            headers: response.headers(),
+            body: Some(response.body().as_ref()),
+        })
+    }
+
+    /// Checks the signatures of `message`, returning the error of the last one if none is valid.
+    fn verify(&self, message: &Message<'_>) -> ResultType<()> {
+        let inputs = joined(message.headers, "signature-input");
+        let signatures = joined(message.headers, "signature");
+        let signatures = dictionary(&signatures);
+        let mut result = Err(invalid("no signature made with a known key"));
+        for (label, input) in dictionary(&inputs) {
+            let signature = signatures
+                .iter()
+                .find(|(name, _)| *name == label)
+                .map(|(_, signature)| *signature);
+            let input = match SignatureInput::parse(input) {
+                Ok(input) => input,
+                Err(error) => {
+                    result = Err(error);
+                    continue;
+                }
+            };
+            let key = self
+                .keys
+                .iter()
+                .find(|(key_id, _)| input.key_id.as_deref() == Some(key_id.as_str()));
+            if let Some((_, key)) = key {
+                result = self.check(message, &input, key.as_ref(), signature);
+                if result.is_ok() {
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    /// Checks one signature of `message`.
+    fn check(
+        &self,
+        message: &Message<'_>,
+        input: &SignatureInput<'_>,
+        key: &dyn VerifyingKey,
+        signature: Option<&str>,
+    ) -> ResultType<()> {
+        let signature = signature
+            .and_then(|signature| signature.strip_prefix(':')?.strip_suffix(':'))
+            .and_then(|signature| STANDARD.decode(signature).ok())
+            .ok_or_else(|| invalid("missing or malformed `Signature` header"))?;
+        if let Some(missing) = self
+            .required
+            .iter()
+            .find(|name| !input.components.contains(&name.as_str()))
+        {
+            return Err(invalid(&format!("`{missing}` is not covered")));
+        }
+        if input.algorithm.is_some_and(|alg| alg != key.algorithm()) {
+            return Err(invalid("algorithm doesn't match the key"));
+        }
+        let now = unix_time(SystemTime::now());
+        if input.expires.is_some_and(|expires| expires <= now) {
+            return Err(invalid("signature expired"));
+        }
+        if let (Some(max_age), Some(created)) = (self.max_age, input.created) {
+            if now.saturating_sub(created) > max_age.as_secs() {
+                return Err(invalid("signature is too old"));
+            }
+        }
+        if input.components.contains(&"content-digest") {
+            check_digest(message)?;
+        }
+        let components: Vec<_> = input.components.iter().map(ToString::to_string).collect();
+        let base = message.base(&components, input.raw)?;
+        if key.verify(base.as_bytes(), &signature) {
+            Ok(())
+        } else {
+            Err(invalid("signature doesn't match"))
+        }
+    }
+}
+
+/// The parts of a message signatures are computed from.
+struct Message<'a> {
+    /// Method of a request.
+    method: Option<&'a str>,
+    /// Target URI of a request.
+    url: Option<Url>,
+    /// Status of a response.
+    status: Option<u16>,
+    /// Headers of the message.
+    headers: &'a HeaderMap,
+    /// Body of a received message.
+    body: Option<&'a [u8]>,
+}
+
+impl Message<'_> {
+    /// Returns the value of `component`.
+    fn component(&self, component: &str) -> ResultType<String> {
+        let url = || {
+            self.url
+                .as_ref()
+                .ok_or_else(|| invalid(&format!("`{component}` is not part of responses")))
+        };
+        Ok(match component {
+            "@method" => self
+                .method
+                .ok_or_else(|| invalid("`@method` is not part of responses"))?
+                .to_string(),
+            "@target-uri" => url()?.to_string(),
+            "@authority" => {
+                let url = url()?;
+                let host = url.host_str().unwrap_or_default();
+                match url.port() {
+                    Some(port) => format!("{host}:{port}"),
+                    None => host.to_string(),
+                }
+            }
+            "@scheme" => url()?.scheme().to_string(),
+            "@request-target" => {
+                let url = url()?;
+                match url.query() {
+                    Some(query) => format!("{}?{query}", url.path()),
+                    None => url.path().to_string(),
+                }
+            }
+            "@path" => url()?.path().to_string(),
+            "@query" => format!("?{}", url()?.query().unwrap_or_default()),
+            "@status" => self
+                .status
+                .ok_or_else(|| invalid("`@status` is not part of requests"))?
+                .to_string(),
+            name if name.starts_with('@') => {
+                return Err(invalid(&format!("unsupported component `{name}`")))
+            }
+            name if self.headers.contains_key(name) => joined(self.headers, name),
+            name => return Err(invalid(&format!("missing header `{name}`"))),
+        })
+    }
+
+    /// Returns the signature base covering `components`, with the serialized `input`.
+    fn base(&self, components: &[String], input: &str) -> ResultType<String> {
+        let mut lines = components
+            .iter()
+            .map(|name| Ok(format!("\"{name}\": {}", self.component(name)?)))
+            .collect::<ResultType<Vec<_>>>()?;
+        lines.push(format!("\"@signature-params\": {input}"));
+        Ok(lines.join("\n"))
+    }
+}
+
+/// A parsed entry of the `Signature-Input` header.
+struct SignatureInput<'a> {
+    /// The serialized entry, which is part of the signature base.
+    raw: &'a str,
+    /// Covered components.
+    components: Vec<&'a str>,
+    /// Value of the `created` parameter.
+    created: Option<u64>,
+    /// Value of the `expires` parameter.
+    expires: Option<u64>,
+    /// Value of the `keyid` parameter.
+    key_id: Option<String>,
+    /// Value of the `alg` parameter.
+    algorithm: Option<&'a str>,
+}
+
+impl<'a> SignatureInput<'a> {
+    /// Parses an entry such as `("@method" "@path");created=1618884473;keyid="key"`.
+    fn parse(raw: &'a str) -> ResultType<Self> {
+        let (list, params) = raw
+            .strip_prefix('(')
+            .and_then(|raw| raw.split_once(')'))
+            .ok_or_else(|| invalid("malformed `Signature-Input` header"))?;
+        let components = list
+            .split_whitespace()
+            .map(|name| {
+                name.strip_prefix('"')
+                    .and_then(|name| name.strip_suffix('"'))
+                    .ok_or_else(|| invalid("component parameters are not supported"))
+            })
+            .collect::<ResultType<Vec<_>>>()?;
+        let mut input = Self {
+            raw,
+            components,
+            created: None,
+            expires: None,
+            key_id: None,
+            algorithm: None,
+        };
+        for param in params.split(';').filter(|param| !param.is_empty()) {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            let string = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'));
+            match name {
+                "created" => input.created = value.parse().ok(),
+                "expires" => input.expires = value.parse().ok(),
+                "keyid" => input.key_id = string.map(ToString::to_string),
+                "alg" => input.algorithm = string,
+                _ => {}
+            }
+        }
+        Ok(input)
+    }
+}
+
+/// Checks that the `Content-Digest` of `message` matches its body.
+fn check_digest(message: &Message<'_>) -> ResultType<()> {
+    let body = message.body.unwrap_or_default();
+    let digests = joined(message.headers, "content-digest");
+    let mut checked = false;
+    for (algorithm, digest) in dictionary(&digests) {
+        let expected = match algorithm {
+            "sha-256" => STANDARD.encode(Sha256::digest(body)),
+            "sha-512" => STANDARD.encode(Sha512::digest(body)),
+            _ => continue,
+        };
+        if digest
+            .strip_prefix(':')
+            .and_then(|digest| digest.strip_suffix(':'))
+            != Some(&expected)
+        {
+            return Err(invalid("`Content-Digest` doesn't match the body"));
+        }
+        checked = true;
+    }
+    if checked {
+        Ok(())
+    } else {
+        Err(invalid("no supported `Content-Digest`"))
+    }
+}
+
+/// Splits a structured field dictionary into its labels and values, keeping the values
+/// serialized.
+fn dictionary(field: &str) -> Vec<(&str, &str)> {
+    let mut entries = Vec::new();
+    let (mut start, mut quoted, mut depth) = (0, false, 0_usize);
+    for (index, c) in field.char_indices().chain(Some((field.len(), ','))) {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth = depth.saturating_sub(1),
+            ',' if !quoted && depth == 0 => {
+                if let Some((label, value)) = field[start..index].split_once('=') {
+                    entries.push((label.trim(), value.trim()));
+                }
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// Returns the values of the header `name`, trimmed and joined with `, `.
+fn joined(headers: &HeaderMap, name: &str) -> String {
+    headers
+        .get_all(name)
+        .iter()
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).trim().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns `value` as a header value.
+fn header_value(value: &str) -> ResultType<HeaderValue> {
+    HeaderValue::from_str(value)
+        .map_err(|_| Error::Signing(format!("`{value}` is not a valid header value")))
+}
+
+/// Returns an [`Error::InvalidSignature`] with `reason`.
+fn invalid(reason: &str) -> Error {
+    Error::InvalidSignature(reason.to_string())
+}
+
+/// Returns the seconds from the Unix epoch to `time`.
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{HmacSha256Key, MessageSigner, MessageVerifier};
+    use crate::{api, test_server, ApiState, Error};
+
+    /// Converts `request` into the request a server receives.
+    fn received(request: &reqwest::Request) -> http::Request<Vec<u8>> {
+        let mut received = http::Request::builder()
+            .method(request.method().as_str())
+            .uri(request.url().as_str());
+        for (name, value) in request.headers() {
+            received = received.header(name, value);
+        }
+        let body = request.body().and_then(reqwest::Body::as_bytes);
+        received.body(body.unwrap_or_default().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn signs_and_verifies() {
+        let mut request = reqwest::Client::new()
+            .post("https://example.com/foo?param=Value&Pet=dog")
+            .header("content-type", "application/json")
+            .body(r#"{"hello": "world"}"#)
+            .build()
+            .unwrap();
+        MessageSigner::new("test-key", HmacSha256Key::new("secret"))
+            .with_algorithm()
+            .sign_at(&mut request, 1_618_884_473)
+            .unwrap();
+        assert_eq!(
+            request.headers()["content-digest"],
+            "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"
+        );
+        assert_eq!(
+            request.headers()["signature-input"],
+            r#"sig1=("@method" "@target-uri" "content-type" "content-digest");created=1618884473;keyid="test-key";alg="hmac-sha256""#
+        );
+
+        let verifier = MessageVerifier::new()
+            .key("other-key", HmacSha256Key::new("other"))
+            .key("test-key", HmacSha256Key::new("secret"))
+            .require("content-digest");
+        let mut received = received(&request);
+        assert!(verifier.verify_request(&received).is_ok());
+        assert!(matches!(
+            verifier
+                .clone()
+                .max_age(Duration::from_secs(60))
+                .verify_request(&received),
+            Err(Error::InvalidSignature(_))
+        ));
+        assert!(MessageVerifier::new()
+            .key("test-key", HmacSha256Key::new("wrong"))
+            .verify_request(&received)
+            .is_err());
+
+        received.body_mut().push(b' ');
+        let error = verifier.verify_request(&received).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid message signature: `Content-Digest` doesn't match the body"
+        );
+    }
+
+    api!(struct Signed);
+
+    impl Signed {
+        api! {
+            fn create(&self, request: Json<[u32]>, base_url: &str) -> StatusCode {
+                POST "{base_url}/items?draft=true"
+            }
+        }
+    }
+
+    #[test]
+    fn signs_sent_requests() {
+        tokio_test::block_on(async {
+            let verifier = MessageVerifier::new()
+                .key("client", HmacSha256Key::new("secret"))
+                .max_age(Duration::from_secs(60));
+            let base_url = test_server::serve(move |request| {
+                let mut received = http::Request::builder()
+                    .method(request.method.as_str())
+                    .uri(request.path.as_str());
+                for (name, value) in &request.headers {
+                    received = received.header(name, value);
+                }
+                let received = received.body(request.body).unwrap();
+                let status = if verifier.verify_request(&received).is_ok() {
+                    200
+                } else {
+                    401
+                };
+                test_server::Response::new(status)
+            })
+            .await;
+            let signer = MessageSigner::new("client", HmacSha256Key::new("secret"))
+                .components(["@method", "@authority", "@request-target", "content-digest"])
+                .expires_after(Duration::from_secs(30))
+                .tag("test");
+            let api = Signed::builder()
+                .state(ApiState::new().interceptor(signer))
+                .build()
+                .unwrap();
+            assert_eq!(api.create(&[1], &base_url).await.unwrap().as_u16(), 200);
+        });
+    }
+
+    #[cfg(feature = "ed25519")]
+    #[test]
+    fn matches_rfc_9421_ed25519_example() {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        use super::{Ed25519SigningKey, Ed25519VerifyingKey};
+
+        let private_key = STANDARD
+            .decode("MC4CAQAwBQYDK2VwBCIEIJ+DYvh6SEqVTm50DFtMDoQikTmiCqirVv9mWG9qfSnF")
+            .unwrap();
+        let public_key = STANDARD
+            .decode("MCowBQYDK2VwAyEAJrQLj5P/89iXES9+vFgrIy29clF9CC/oPPsw3c5D0bs=")
+            .unwrap();
+        let mut request = reqwest::Client::new()
+            .post("https://example.com/foo?param=Value&Pet=dog")
+            .header("date", "Tue, 20 Apr 2021 02:07:55 GMT")
+            .header("content-type", "application/json")
+            .header("content-length", "18")
+            .body(r#"{"hello": "world"}"#)
+            .build()
+            .unwrap();
+        MessageSigner::new(
+            "test-key-ed25519",
+            Ed25519SigningKey::from_pkcs8(&private_key).unwrap(),
+        )
+        .label("sig-b26")
+        .components([
+            "date",
+            "@method",
+            "@path",
+            "@authority",
+            "content-type",
+            "content-length",
+        ])
+        .sign_at(&mut request, 1_618_884_473)
+        .unwrap();
+        assert_eq!(
+            request.headers()["signature"],
+            "sig-b26=:wqcAqbmYJ2ji2glfAMaRy4gruYYnx2nEFN2HN6jrnDnQCK1u02Gb04v9EDgwUPiu4A0w6vuQv5lIp5WPpBKRCw==:"
+        );
+
+        let verifier = MessageVerifier::new().key(
+            "test-key-ed25519",
+            Ed25519VerifyingKey::new(&public_key[public_key.len() - 32..]),
+        );
+        assert!(verifier.verify_request(&received(&request)).is_ok());
+    }
+}
@@ -0,0 +1,406 @@
+//! AWS Signature Version 4, see [`SigV4Signer`].
+
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use sha2::{Digest, Sha256};
+
+use super::{hex, hmac_sha256};
+use crate::{
+    interceptor::{Interceptor, Next},
+    Error, ResultType,
+};
+
+/// Hash sent instead of the hash of streamed bodies to S3.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// Signs requests with AWS Signature Version 4, for AWS services and S3-compatible storage.
+///
+/// Like [`HmacSigner`](super::HmacSigner) it is added as an [interceptor](crate::interceptor),
+/// after the ones changing the request. It sends the `X-Amz-Date` header, the session token of
+/// temporary credentials in `X-Amz-Security-Token`, and the `Authorization` header with the
+/// signature of the method, path, query, body and the `Host`, `Content-Type` and `X-Amz-*`
+/// headers. For the `s3` service the hash of the body is also sent in `X-Amz-Content-Sha256`, and
+/// streamed bodies, e.g. multipart uploads, are sent with an unsigned payload.
+/// ```rust
+/// use api_client::{api, signing::SigV4Signer, ApiState};
+///
+/// api!(pub struct Storage);
+///
+/// let signer = SigV4Signer::new("AKIDEXAMPLE", "secret", "eu-central-1", "s3")
+///     .session_token("token");
+/// let api = Storage::builder()
+///     .state(ApiState::new().interceptor(signer))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct SigV4Signer {
+    /// The access key ID.
+    access_key_id: String,
+    /// The secret access key.
+    secret_access_key: String,
+    /// The session token of temporary credentials, if any.
+    session_token: Option<String>,
+    /// The region, e.g. `us-east-1`.
+    region: String,
+    /// The service, e.g. `s3`.
+    service: String,
+}
+
+impl fmt::Debug for SigV4Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigV4Signer")
+            .field("access_key_id", &self.access_key_id)
+            .field("region", &self.region)
+            .field("service", &self.service)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SigV4Signer {
+    /// Creates a signer for `service` in `region` with long-term credentials.
+    #[must_use]
+    pub fn new(
+        access_key_id: impl Into<String>,
+        secret_access_key: impl Into<String>,
+        region: impl Into<String>,
+        service: impl Into<String>,
+    ) -> Self {
+        Self {
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+            region: region.into(),
+            service: service.into(),
+        }
+    }
+
+    /// Sends the session token of temporary credentials, e.g. from STS or an instance role.
+    #[must_use]
+    pub fn session_token(mut self, token: impl Into<String>) -> Self {
+        self.session_token = Some(token.into());
+        self
+    }
+
+    /// Adds the signature and the headers it covers to `request`, e.g. one built with a
+    /// `build_<name>_request` method.
+    ///
+    /// # Errors
+    /// Returns [`Error::Signing`] if the body is streamed and the service is not `s3`, or if the
+    /// credentials contain characters which are not allowed in headers.
+    pub fn sign(&self, request: &mut reqwest::Request) -> ResultType<()> {
+        self.sign_at(request, SystemTime::now())
+    }
+
+    /// Signs `request` as of `time`.
+    fn sign_at(&self, request: &mut reqwest::Request, time: SystemTime) -> ResultType<()> {
+        let is_s3 = self.service == "s3";
+        let payload_hash = match request.body().map(reqwest::Body::as_bytes) {
+            None => hex(&Sha256::digest([])),
+            Some(Some(body)) => hex(&Sha256::digest(body)),
+            Some(None) if is_s3 => UNSIGNED_PAYLOAD.to_string(),
+            Some(None) => {
+                return Err(Error::Signing(
+                    "streamed bodies can only be sent unsigned to S3".to_string(),
+                ))
+            }
+        };
+        let (date, timestamp) = amz_date(time);
+        let headers = request.headers_mut();
+        headers.insert("x-amz-date", header_value(&timestamp)?);
+        if is_s3 {
+            headers.insert("x-amz-content-sha256", header_value(&payload_hash)?);
+        }
+        if let Some(token) = &self.session_token {
+            headers.insert("x-amz-security-token", header_value(token)?);
+        }
+
+        let (signed_headers, canonical_headers) = canonical_headers(request);
+        let canonical_request = [
+            request.method().as_str(),
+            &canonical_path(request.url(), !is_s3),
+            &canonical_query(request.url()),
+            &canonical_headers,
+            &signed_headers,
+            &payload_hash,
+        ]
+        .join("\n");
+        let scope = format!("{date}/{}/{}/aws4_request", self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = [&date, &self.region, &self.service, "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", self.secret_access_key).into_bytes(),
+                |key, part| hmac_sha256(&key, part.as_bytes()),
+            );
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+        let mut authorization = header_value(&format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key_id
+        ))?;
+        authorization.set_sensitive(true);
+        request.headers_mut().insert(AUTHORIZATION, authorization);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Interceptor for SigV4Signer {
+    async fn intercept(
+        &self,
+        mut request: reqwest::Request,
+        next: Next<'_>,
+    ) -> ResultType<reqwest::Response> {
+        self.sign(&mut request)?;
+        next.run(request).await
+    }
+}
+
+/// Returns `value` as a header value.
+fn header_value(value: &str) -> ResultType<HeaderValue> {
+    HeaderValue::from_str(value)
+        .map_err(|_| Error::Signing("credentials are not valid header values".to_string()))
+}
+
+/// Returns the date, e.g. `20150830`, and the timestamp, e.g. `20150830T123600Z`, of `time`.
+fn amz_date(time: SystemTime) -> (String, String) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = seconds / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    let date = format!("{year:04}{month:02}{day:02}");
+    let time_of_day = seconds % 86_400;
+    let timestamp = format!(
+        "{date}T{:02}{:02}{:02}Z",
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60
+    );
+    (date, timestamp)
+}
+
+/// Returns the names of the signed headers and the canonical headers of `request`.
+fn canonical_headers(request: &reqwest::Request) -> (String, String) {
+    let url = request.url();
+    let host = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let mut headers = vec![("host".to_string(), host)];
+    let signed = request
+        .headers()
+        .keys()
+        .filter(|name| *name == CONTENT_TYPE || name.as_str().starts_with("x-amz-"));
+    for name in signed {
+        let values: Vec<_> = request
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|value| {
+                String::from_utf8_lossy(value.as_bytes())
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        headers.push((HeaderName::as_str(name).to_string(), values.join(",")));
+    }
+    headers.sort();
+    let names: Vec<_> = headers.iter().map(|(name, _)| name.as_str()).collect();
+    let canonical = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{value}\n"))
+        .collect::<Vec<_>>()
+        .concat();
+    (names.join(";"), canonical)
+}
+
+/// Returns the canonical path of `url`, with its segments encoded twice if `double_encode`, as
+/// every service but S3 expects.
+fn canonical_path(url: &reqwest::Url, double_encode: bool) -> String {
+    let path = url.path();
+    if path.is_empty() {
+        return "/".to_string();
+    }
+    path.split('/')
+        .map(|segment| {
+            let encoded = encode(&decode(segment));
+            if double_encode {
+                encode(&encoded)
+            } else {
+                encoded
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Returns the canonical query of `url`, its parameters encoded and sorted.
+fn canonical_query(url: &reqwest::Url) -> String {
+    let mut parameters: Vec<_> = url
+        .query_pairs()
+        .map(|(name, value)| (encode(&name), encode(&value)))
+        .collect();
+    parameters.sort();
+    parameters
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Percent-encodes every byte of `value` except the unreserved characters, as AWS expects.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                char::from(byte).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Decodes the percent-encoded bytes of `value`.
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = escaped {
+            decoded.push(byte);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{amz_date, canonical_path, SigV4Signer};
+
+    /// Signs `request` with the credentials of the AWS test suite at its time,
+    /// `20150830T123600Z`, and returns its `Authorization` header.
+    fn sign(mut request: reqwest::Request, service: &str) -> String {
+        SigV4Signer::new(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "us-east-1",
+            service,
+        )
+        .sign_at(
+            &mut request,
+            UNIX_EPOCH + Duration::from_secs(1_440_938_160),
+        )
+        .unwrap();
+        request.headers()["authorization"]
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn formats_dates() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_440_938_160);
+        assert_eq!(
+            amz_date(time),
+            ("20150830".to_string(), "20150830T123600Z".to_string())
+        );
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3599);
+        assert_eq!(amz_date(leap_day).1, "20000229T005959Z");
+    }
+
+    #[test]
+    fn signs_test_suite_requests() {
+        let client = reqwest::Client::new();
+        let vanilla = client
+            .get("https://example.amazonaws.com/")
+            .build()
+            .unwrap();
+        assert_eq!(
+            sign(vanilla, "service"),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+
+        let list_users = client
+            .get("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08")
+            .header(
+                "content-type",
+                "application/x-www-form-urlencoded; charset=utf-8",
+            )
+            .build()
+            .unwrap();
+        assert_eq!(
+            sign(list_users, "iam"),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn signs_s3_requests_with_session_tokens() {
+        let mut request = reqwest::Client::new()
+            .put("https://bucket.s3.amazonaws.com/notes.txt")
+            .body("hi")
+            .build()
+            .unwrap();
+        SigV4Signer::new("AKIDEXAMPLE", "secret", "us-east-1", "s3")
+            .session_token("token")
+            .sign(&mut request)
+            .unwrap();
+        let headers = request.headers();
+        assert_eq!(
+            headers["x-amz-content-sha256"],
+            "8f434346648f6b96df89dda901c5176b10a6d83961dd3c1ac88b59b2dc327aa4"
+        );
+        assert_eq!(headers["x-amz-security-token"], "token");
+        assert!(headers["authorization"].is_sensitive());
+        assert!(headers["authorization"]
+            .to_str()
+            .unwrap()
+            .contains("SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token,"));
+    }
+
+    #[test]
+    fn encodes_paths() {
+        let url = reqwest::Url::parse("https://example.com/a b/c%2Fd/~e").unwrap();
+        assert_eq!(canonical_path(&url, false), "/a%20b/c%2Fd/~e");
+        assert_eq!(canonical_path(&url, true), "/a%2520b/c%252Fd/~e");
+    }
+}
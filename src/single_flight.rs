@@ -0,0 +1,241 @@
+//! Coalescing of identical in-flight requests, see
+//! [`ApiState::deduplicate`](crate::ApiState::deduplicate).
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use bytes::Bytes;
+use reqwest::{header::HeaderMap, ResponseBuilderExt, StatusCode, Url, Version};
+use tokio::sync::OnceCell;
+
+use crate::{protocol::Trailers, RequestBuilder, ResultType};
+
+/// Outcome of a request shared by all its callers, `None` if it failed.
+type Flight = Arc<OnceCell<Option<SharedResponse>>>;
+
+/// Requests in flight, by method, URL, headers and body.
+#[derive(Debug, Default)]
+pub(crate) struct SingleFlight {
+    /// Outcomes of the requests in flight.
+    flights: Mutex<HashMap<String, Flight>>,
+}
+
+impl SingleFlight {
+    /// Sends `request` with `send`, or waits for an identical request already in flight and
+    /// returns a copy of its response.
+    ///
+    /// If the shared request fails, every caller sends its own request, so errors are not
+    /// shared. Requests whose body cannot be inspected are always sent.
+    pub(crate) async fn run<F, Fut>(
+        &self,
+        request: RequestBuilder,
+        send: F,
+    ) -> ResultType<reqwest::Response>
+    where
+        F: FnOnce(RequestBuilder) -> Fut,
+        Fut: Future<Output = ResultType<SharedResponse>>,
+    {
+        let Some(key) = key(&request) else {
+            return send(request).await.map(|shared| shared.to_response());
+        };
+        let flight = self
+            .flights
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        let mut own = Some((request, send));
+        let mut error = None;
+        let (own_ref, error_ref, key, landing) = (&mut own, &mut error, &key, &flight);
+        let shared = flight
+            .get_or_init(|| async move {
+                let (request, send) = own_ref.take().expect("a flight is only sent once");
+                let result = send(request).await;
+                self.land(key, landing);
+                result.map_err(|error| *error_ref = Some(error)).ok()
+            })
+            .await;
+
+        match (shared, error, own) {
+            (_, Some(error), _) => Err(error),
+            (Some(shared), None, _) => Ok(shared.to_response()),
+            (None, None, Some((request, send))) => {
+                send(request).await.map(|shared| shared.to_response())
+            }
+            (None, None, None) => unreachable!("failed flights return their error"),
+        }
+    }
+
+    /// Removes a completed flight, so later requests are sent again.
+    fn land(&self, key: &str, flight: &Flight) {
+        let mut flights = self.flights.lock().unwrap_or_else(PoisonError::into_inner);
+        if flights
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(current, flight))
+        {
+            flights.remove(key);
+        }
+    }
+}
+
+/// Returns the key identifying identical requests, or `None` if the request cannot be inspected,
+/// e.g. because its body is a stream.
+fn key(request: &RequestBuilder) -> Option<String> {
+    let request = request.try_clone()?.build().ok()?;
+    let mut key = format!("{} {}", request.method(), request.url());
+    for (name, value) in request.headers() {
+        write!(key, "\n{name}: {value:?}").ok()?;
+    }
+    if let Some(body) = request.body() {
+        write!(key, "\n\n{:?}", body.as_bytes()?).ok()?;
+    }
+    Some(key)
+}
+
+/// A buffered response which can be handed to several callers.
+#[derive(Debug)]
+pub(crate) struct SharedResponse {
+    /// Status of the response.
+    status: StatusCode,
+    /// HTTP version of the response.
+    version: Version,
+    /// Headers of the response.
+    headers: HeaderMap,
+    /// Final URL of the response.
+    url: Url,
+    /// Whole body of the response.
+    body: Bytes,
+    /// Trailers of the response, if the transport received any.
+    trailers: Option<Trailers>,
+}
+
+impl SharedResponse {
+    /// Reads the whole body of `response`.
+    ///
+    /// # Errors
+    /// Returns an error if the body cannot be read.
+    pub(crate) async fn read(response: reqwest::Response) -> ResultType<Self> {
+        Ok(Self {
+            status: response.status(),
+            version: response.version(),
+            headers: response.headers().clone(),
+            url: response.url().clone(),
+            trailers: response.extensions().get::<Trailers>().cloned(),
+            body: response.bytes().await?,
+        })
+    }
+
+    /// Returns the status of the response.
+    pub(crate) fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the headers of the response.
+    pub(crate) fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the whole body of the response.
+    #[cfg(any(feature = "json", feature = "tracing"))]
+    pub(crate) fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Creates a copy of the response.
+    pub(crate) fn to_response(&self) -> reqwest::Response {
+        let mut response = http::Response::builder()
+            .status(self.status)
+            .version(self.version)
+            .url(self.url.clone())
+            .body(self.body.clone())
+            .expect("the parts of a received response are valid");
+        *response.headers_mut() = self.headers.clone();
+        if let Some(trailers) = &self.trailers {
+            response.extensions_mut().insert(trailers.clone());
+        }
+        reqwest::Response::from(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{api, test_server, ApiState};
+
+    api!(struct Stampede);
+
+    impl Stampede {
+        api! {
+            fn get(&self, base_url: &str, id: u32) -> String {
+                GET "{base_url}/{id}"
+            }
+
+            fn post(&self, base_url: &str, id: u32) -> String {
+                POST "{base_url}/{id}"
+            }
+
+            fn search(&self, request: Json<str>, base_url: &str) -> String {
+                POST "{base_url}/search"
+                with { idempotent }
+            }
+        }
+    }
+
+    #[test]
+    fn coalesces_identical_gets() {
+        tokio_test::block_on(async {
+            let hits = Arc::new(AtomicUsize::new(0));
+            let server_hits = hits.clone();
+            let base_url = test_server::serve(move |request| {
+                server_hits.fetch_add(1, Ordering::SeqCst);
+                test_server::Response::new(200)
+                    .body(request.path.clone())
+                    .delay(Duration::from_millis(100))
+            })
+            .await;
+            let api = Stampede::builder()
+                .state(ApiState::new().deduplicate(true))
+                .build()
+                .unwrap();
+
+            let (a, b, c) = tokio::join!(
+                api.get(&base_url, 1),
+                api.get(&base_url, 1),
+                api.get(&base_url, 2)
+            );
+            assert_eq!(a.unwrap(), "/1");
+            assert_eq!(b.unwrap(), "/1");
+            assert_eq!(c.unwrap(), "/2");
+            assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+            api.get(&base_url, 1).await.unwrap();
+            assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+            let (a, b) = tokio::join!(api.post(&base_url, 1), api.post(&base_url, 1));
+            assert_eq!(a.unwrap(), b.unwrap());
+            assert_eq!(hits.load(Ordering::SeqCst), 5);
+
+            let (a, b, c) = tokio::join!(
+                api.search("todo", &base_url),
+                api.search("todo", &base_url),
+                api.search("done", &base_url)
+            );
+            assert_eq!(a.unwrap(), b.unwrap());
+            assert!(c.is_ok());
+            assert_eq!(hits.load(Ordering::SeqCst), 7);
+        });
+    }
+}
@@ -0,0 +1,338 @@
+//! Calling every `GET` endpoint of a client once, e.g. in a canary job after the upstream API was
+//! deployed.
+//!
+//! Blocks of endpoints declared with the [`api!`](crate::api) macro which list their endpoints
+//! with `#![endpoints]` generate a `smoke_test_all(&self, params)` method next to `endpoints()`.
+//! Blocks listing them with `#![endpoints(admin)]` generate `smoke_test_admin(&self, params)`
+//! instead, so each block of a type is tested on its own. The method renders the URL template of
+//! each `GET` endpoint, see [`EndpointMeta`](crate::endpoint::EndpointMeta), with the sample
+//! values of [`SmokeParams`], calls it through the same executor as the generated methods and
+//! reports the outcome of every endpoint in a [`SmokeReport`].
+//!
+//! Values are looked up by the names used in the template, e.g. `id` for `"{base_url}/todos/{id}"`,
+//! and inserted as given. Endpoints whose template uses a value which is missing, or positional
+//! arguments such as `"{}/todos", self.base_url`, are skipped. Requirements declared with
+//! `with { requires: [...] }` are not checked, since the smoke test calls the endpoints on their
+//! own; endpoints which need a session fail unless the instance has one.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, smoke::SmokeParams};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         #![endpoints]
+//!
+//!         fn todos(base_url: &str) -> String {
+//!            GET "{base_url}/todos"
+//!         }
+//!
+//!         fn todo(base_url: &str, id: u32) -> String {
+//!            GET "{base_url}/todos/{id}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn canary(api: &ExampleApi) -> bool {
+//!     let params = SmokeParams::new()
+//!         .param("base_url", "https://example.com")
+//!         .param("id", 1);
+//!     let report = api.smoke_test_all(params).await;
+//!     println!("{report}");
+//!     report.is_success()
+//! }
+//! ```
+
+use std::{collections::HashMap, fmt};
+
+use reqwest::StatusCode;
+
+use crate::{
+    endpoint::{self, EndpointMeta, EndpointSpec},
+    response::Status,
+    Api, Body, Error,
+};
+
+/// Sample values for the parameters of endpoint URLs, see [`smoke`](crate::smoke).
+#[derive(Debug, Clone, Default)]
+pub struct SmokeParams {
+    /// Values by the name used in URL templates.
+    values: HashMap<String, String>,
+}
+
+impl SmokeParams {
+    /// Creates an empty set of values.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value inserted for `{name}` in URL templates.
+    #[must_use]
+    pub fn param(mut self, name: impl Into<String>, value: impl fmt::Display) -> Self {
+        self.values.insert(name.into(), value.to_string());
+        self
+    }
+
+    /// Renders `template` with the values, or returns the name of the first missing one.
+    ///
+    /// Positional arguments are reported as missing with the name `{}`.
+    fn render(&self, template: &str) -> Result<String, String> {
+        let mut url = String::with_capacity(template.len());
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' | '}' if chars.peek() == Some(&c) => {
+                    chars.next();
+                    url.push(c);
+                }
+                '{' => {
+                    let placeholder: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    let name = placeholder.split(':').next().unwrap_or_default().trim();
+                    match self.values.get(name) {
+                        Some(value) => url.push_str(value),
+                        None if name.is_empty() || name.parse::<usize>().is_ok() => {
+                            return Err("{}".to_string())
+                        }
+                        None => return Err(name.to_string()),
+                    }
+                }
+                c => url.push(c),
+            }
+        }
+        Ok(url)
+    }
+}
+
+/// Outcome of calling one endpoint in a smoke test.
+#[derive(Debug)]
+pub enum SmokeOutcome {
+    /// The endpoint responded with a success status.
+    Passed(StatusCode),
+    /// The endpoint responded with a status other than a success.
+    Rejected(StatusCode),
+    /// No response was received, or the call was refused before sending it.
+    Failed(Error),
+    /// The endpoint was not called, for the given reason, e.g. a missing parameter.
+    Skipped(String),
+}
+
+/// Outcome of calling one endpoint in a smoke test, see [`SmokeReport`].
+#[derive(Debug)]
+pub struct SmokeResult {
+    /// Name of the endpoint.
+    pub endpoint: &'static str,
+    /// URL which was called, unless the endpoint was skipped.
+    pub url: Option<String>,
+    /// Outcome of the call.
+    pub outcome: SmokeOutcome,
+}
+
+impl SmokeResult {
+    /// Returns whether the endpoint was called and responded with a success status.
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        matches!(self.outcome, SmokeOutcome::Passed(_))
+    }
+
+    /// Returns whether the endpoint was called and didn't respond with a success status.
+    #[must_use]
+    pub fn failed(&self) -> bool {
+        matches!(
+            self.outcome,
+            SmokeOutcome::Rejected(_) | SmokeOutcome::Failed(_)
+        )
+    }
+}
+
+/// Outcomes of a smoke test of all `GET` endpoints of a block, in declaration order.
+///
+/// Displayed as one line per endpoint, e.g. `PASS todos 200 OK`.
+#[derive(Debug, Default)]
+pub struct SmokeReport {
+    /// Outcomes of the endpoints.
+    pub results: Vec<SmokeResult>,
+}
+
+impl SmokeReport {
+    /// Returns whether no endpoint failed, skipped endpoints aside.
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        !self.results.iter().any(SmokeResult::failed)
+    }
+
+    /// Returns the results of the endpoints which failed.
+    pub fn failures(&self) -> impl Iterator<Item = &SmokeResult> {
+        self.results.iter().filter(|result| result.failed())
+    }
+}
+
+impl fmt::Display for SmokeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            let endpoint = result.endpoint;
+            match &result.outcome {
+                SmokeOutcome::Passed(status) => writeln!(f, "PASS {endpoint} {status}")?,
+                SmokeOutcome::Rejected(status) => writeln!(f, "FAIL {endpoint} {status}")?,
+                SmokeOutcome::Failed(error) => writeln!(f, "FAIL {endpoint} {error}")?,
+                SmokeOutcome::Skipped(reason) => writeln!(f, "SKIP {endpoint} {reason}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Calls every `GET` endpoint of `endpoints` with the values of `params`.
+///
+/// Used internally in the [`api!`](crate::api) macro for the generated `smoke_test_all`.
+#[doc(hidden)]
+pub async fn run<A: Api + ?Sized>(
+    api: &A,
+    endpoints: &[EndpointMeta],
+    params: &SmokeParams,
+) -> SmokeReport {
+    let mut report = SmokeReport::default();
+    for meta in endpoints.iter().filter(|meta| meta.method == "GET") {
+        let (url, outcome) = match params.render(meta.url) {
+            Ok(url) if reqwest::Url::parse(&url).is_ok() => {
+                let outcome = call(api, meta, &url).await;
+                (Some(url), outcome)
+            }
+            Ok(_) => (
+                None,
+                SmokeOutcome::Skipped("URL is not a template".to_string()),
+            ),
+            Err(missing) => (
+                None,
+                SmokeOutcome::Skipped(format!("missing parameter `{missing}`")),
+            ),
+        };
+        report.results.push(SmokeResult {
+            endpoint: meta.name,
+            url,
+            outcome,
+        });
+    }
+    report
+}
+
+/// Calls the endpoint described by `meta` at `url`.
+async fn call<A: Api + ?Sized>(api: &A, meta: &EndpointMeta, url: &str) -> SmokeOutcome {
+    let spec = EndpointSpec {
+        name: meta.name,
+        method: reqwest::Method::GET,
+        url: meta.url,
+        timeout: None,
+        deadline: None,
+        retry: None,
+        idempotent: true,
+        pointer: None,
+        scopes: meta.scopes,
+        requires: &[],
+        grpc_status: false,
+        login: None,
+        cache_mode: None,
+        cache_lifetime: None,
+    };
+    match endpoint::execute::<A, Status, ()>(api, &spec, url, Body::None, None).await {
+        Ok(status) if status.is_success() => SmokeOutcome::Passed(status),
+        Ok(status) => SmokeOutcome::Rejected(status),
+        Err(error) => SmokeOutcome::Failed(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SmokeOutcome, SmokeParams};
+    use crate::{api, test_server, Api};
+
+    api!(struct Canary);
+
+    #[allow(dead_code)]
+    impl Canary {
+        api! {
+            #![endpoints]
+
+            fn todo(&self, base_url: &str, id: u32) -> String {
+                GET "{base_url}/todos/{id}"
+            }
+
+            fn missing(&self, base_url: &str) -> String {
+                GET "{base_url}/missing"
+            }
+
+            fn create(&self, base_url: &str) -> String {
+                POST "{base_url}/todos"
+            }
+
+            fn user(&self, base_url: &str, name: &str) -> String {
+                GET "{base_url}/users/{name}"
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    impl Canary {
+        api! {
+            #![endpoints(admin)]
+
+            fn stats(&self, base_url: &str) -> String {
+                GET "{base_url}/todos/1"
+            }
+        }
+    }
+
+    #[test]
+    fn renders_templates() {
+        let params = SmokeParams::new()
+            .param("id", 1)
+            .param("base_url", "http://a");
+        assert_eq!(
+            params.render("{base_url}/todos/{id:>3}?q={{x}}"),
+            Ok("http://a/todos/1?q={x}".to_string())
+        );
+        assert_eq!(params.render("{}/todos"), Err("{}".to_string()));
+        assert_eq!(params.render("{base_url}/{name}"), Err("name".to_string()));
+    }
+
+    #[test]
+    fn calls_get_endpoints() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let status = if request.path == "/todos/1" { 200 } else { 404 };
+                test_server::Response::new(status)
+            })
+            .await;
+            let api = Canary::new();
+            let params = SmokeParams::new()
+                .param("base_url", &base_url)
+                .param("id", 1);
+            let report = api.smoke_test_all(params).await;
+
+            let outcomes: Vec<_> = report
+                .results
+                .iter()
+                .map(|result| (result.endpoint, &result.outcome))
+                .collect();
+            assert!(matches!(
+                outcomes[..],
+                [
+                    ("todo", SmokeOutcome::Passed(_)),
+                    ("missing", SmokeOutcome::Rejected(_)),
+                    ("user", SmokeOutcome::Skipped(_)),
+                ]
+            ));
+            assert!(!report.is_success());
+            assert_eq!(
+                report.to_string(),
+                "PASS todo 200 OK\nFAIL missing 404 Not Found\nSKIP user missing parameter `name`\n"
+            );
+
+            let params = SmokeParams::new().param("base_url", &base_url);
+            let report = api.smoke_test_admin(params).await;
+            assert_eq!(report.to_string(), "PASS stats 200 OK\n");
+        });
+    }
+}
@@ -0,0 +1,972 @@
+//! Runtime state shared by all requests of an [`Api`](crate::Api) instance.
+
+use tokio::sync::{Mutex, MutexGuard, Semaphore, SemaphorePermit};
+
+use std::{
+    sync::{Arc, PoisonError},
+    time::Instant,
+};
+
+use reqwest::{header::HeaderName, Method};
+
+#[cfg(feature = "cookies")]
+use crate::cookies::CookieJar;
+#[cfg(feature = "tracing")]
+use crate::logging::RequestLog;
+use crate::{
+    cache::{CacheMode, CacheStore},
+    capture::CaptureBuffer,
+    circuit_breaker::CircuitBreaker,
+    interceptor::Interceptor,
+    locale::Locale,
+    proxy::ProxyConfig,
+    query::ArrayFormat,
+    rate_limit::{RateLimit, RateLimiter},
+    request_id::REQUEST_ID,
+    runtime::{Runtime, Tokio},
+    single_flight::SingleFlight,
+    tls::TlsConfig,
+    transport::Transport,
+    url_policy::UrlPolicy,
+};
+#[cfg(feature = "json")]
+use crate::{har::HarRecorder, last_good::LastGoodStore};
+
+/// Runtime state and options shared by all requests of an [`Api`](crate::Api) instance.
+///
+/// Structs generated by the [`api!`](crate::api) macro hold one, which is set through their
+/// builder. When implementing [`Api`](crate::Api) manually, store one and return it from
+/// [`Api::state`](crate::Api::state).
+///
+/// # Usage
+/// ```rust
+/// use api_client::{api, ApiState};
+///
+/// api!(pub struct ExampleApi);
+///
+/// let api = ExampleApi::builder()
+///     .state(ApiState::new().sequential(true))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ApiState {
+    /// Queue serializing all requests, if sequential mode is enabled.
+    queue: Option<Mutex<()>>,
+    /// Limit of concurrently sent requests, if any.
+    in_flight: Option<Semaphore>,
+    /// Whether requests wait while the rate limit is exhausted.
+    throttle: bool,
+    /// Client side limit of how fast requests are sent.
+    rate_limiter: Option<Arc<dyn RateLimiter>>,
+    /// Circuit breaker failing requests fast while the upstream is down.
+    circuit_breaker: Option<CircuitBreaker>,
+    /// Identical `GET` requests in flight, if deduplication is enabled.
+    single_flight: Option<SingleFlight>,
+    /// Storage of the HTTP cache, if caching is enabled.
+    cache: Option<Arc<dyn CacheStore>>,
+    /// Mode of the HTTP cache for requests which don't select one.
+    cache_mode: CacheMode,
+    /// Lowercase names of the headers the HTTP cache ignores in `Vary` headers and keys.
+    cache_ignored_vary: Vec<String>,
+    /// Summaries of the last requests, if capturing is enabled.
+    capture: Option<CaptureBuffer>,
+    /// Whether requests are printed as `curl` commands before they are sent.
+    debug_curl: bool,
+    /// Recorder of the requests and responses for a HAR file, if recording is enabled.
+    #[cfg(feature = "json")]
+    har: Option<HarRecorder>,
+    /// Options of the logging of requests and responses, if logging is enabled.
+    #[cfg(feature = "tracing")]
+    logging: Option<RequestLog>,
+    /// Formats of the numbers and dates in parameters, if the API expects localized ones.
+    locale: Option<Arc<Locale>>,
+    /// Format of sequences in query parameters of endpoints which don't select another one.
+    array_format: ArrayFormat,
+    /// Runtime sleeping and spawning tasks, [`Tokio`] if not set.
+    runtime: Option<Arc<dyn Runtime>>,
+    /// Transport sending the requests, the client of the instance if not set.
+    transport: Option<Arc<dyn Transport>>,
+    /// Layers around the sending of requests, the outermost first.
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    /// Schemes and ports requests may be sent to, any if not set.
+    url_policy: Option<UrlPolicy>,
+    /// Options of the clients created for this state.
+    client: ClientOptions,
+    /// Whether requests carry the W3C trace context headers of the current trace.
+    #[cfg(feature = "trace-context")]
+    trace_context: bool,
+    /// Store of the last successful outputs of endpoints declared with `fallback: last_good`.
+    #[cfg(feature = "json")]
+    last_good: Option<LastGoodStore>,
+    /// Rate limit announced by the last response carrying rate limit headers.
+    ///
+    /// Not tracked in the shared [`ApiState::empty`] state, which unrelated APIs use.
+    rate_limit: Option<std::sync::Mutex<Option<RateLimit>>>,
+    /// Header carrying the id generated for every call, if request ids are enabled.
+    request_id: Option<HeaderName>,
+    /// Id of the last call, if request ids are enabled.
+    ///
+    /// Not tracked in the shared [`ApiState::empty`] state, which unrelated APIs use.
+    last_request_id: Option<std::sync::Mutex<Option<String>>>,
+    /// Methods and URL templates of the endpoints whose deprecation was reported.
+    ///
+    /// Not tracked in the shared [`ApiState::empty`] state, which unrelated APIs use.
+    deprecated: Option<std::sync::Mutex<std::collections::HashSet<(Method, &'static str)>>>,
+    /// Endpoints which succeeded, to check the order of calls in debug builds, as
+    /// `<type>::<name>` or as the bare names recorded with [`ApiState::mark_called`].
+    ///
+    /// Not tracked in the shared [`ApiState::empty`] state, which unrelated APIs use.
+    #[cfg(debug_assertions)]
+    called: Option<std::sync::Mutex<std::collections::HashSet<String>>>,
+    /// Cookie jar installed in clients created for this state, if cookies are enabled.
+    #[cfg(feature = "cookies")]
+    cookies: Option<Arc<CookieJar>>,
+}
+
+impl Default for ApiState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State used by [`Api`](crate::Api) implementations which don't store their own.
+static EMPTY: ApiState = ApiState::new_const();
+
+impl ApiState {
+    /// Creates a state with all options disabled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            rate_limit: Some(std::sync::Mutex::new(None)),
+            last_request_id: Some(std::sync::Mutex::new(None)),
+            deprecated: Some(std::sync::Mutex::new(std::collections::HashSet::new())),
+            #[cfg(debug_assertions)]
+            called: Some(std::sync::Mutex::new(std::collections::HashSet::new())),
+            ..Self::new_const()
+        }
+    }
+
+    /// Creates a state with all options disabled, usable in constants.
+    const fn new_const() -> Self {
+        Self {
+            queue: None,
+            in_flight: None,
+            throttle: false,
+            rate_limiter: None,
+            circuit_breaker: None,
+            single_flight: None,
+            cache: None,
+            cache_mode: CacheMode::Default,
+            cache_ignored_vary: Vec::new(),
+            capture: None,
+            debug_curl: false,
+            #[cfg(feature = "json")]
+            har: None,
+            #[cfg(feature = "tracing")]
+            logging: None,
+            locale: None,
+            array_format: ArrayFormat::Repeat,
+            runtime: None,
+            transport: None,
+            interceptors: Vec::new(),
+            url_policy: None,
+            client: ClientOptions {
+                follow_redirects: true,
+                system_proxy: true,
+                #[cfg(feature = "system-config")]
+                system_certificates: true,
+                tls: TlsConfig::new(),
+                proxy: None,
+            },
+            #[cfg(feature = "trace-context")]
+            trace_context: false,
+            #[cfg(feature = "json")]
+            last_good: None,
+            rate_limit: None,
+            request_id: None,
+            last_request_id: None,
+            deprecated: None,
+            #[cfg(debug_assertions)]
+            called: None,
+            #[cfg(feature = "cookies")]
+            cookies: None,
+        }
+    }
+
+    /// Returns a shared state with all options disabled.
+    #[must_use]
+    pub fn empty() -> &'static Self {
+        &EMPTY
+    }
+
+    /// Sends requests strictly one at a time, in the order they were made.
+    ///
+    /// Each request waits until the previous one has been sent and its response decoded, which
+    /// makes the traffic of an instance reproducible, e.g. for recorded test fixtures.
+    /// Features which send several requests for one call keep them within that call's turn.
+    #[must_use]
+    pub fn sequential(mut self, sequential: bool) -> Self {
+        self.queue = sequential.then(|| Mutex::new(()));
+        self
+    }
+
+    /// Returns whether requests are sent one at a time, see [`ApiState::sequential`].
+    #[must_use]
+    pub fn is_sequential(&self) -> bool {
+        self.queue.is_some()
+    }
+
+    /// Allows at most `max` requests of this instance to be in flight at once.
+    ///
+    /// Further requests wait in a queue until a slot is free, so fanning out calls, e.g. with
+    /// [`api_join!`](crate::api_join), does not open more connections than `max`.
+    ///
+    /// # Panics
+    /// Panics if `max` is zero.
+    #[must_use]
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        assert!(max > 0, "at least one request has to be allowed in flight");
+        self.in_flight = Some(Semaphore::new(max));
+        self
+    }
+
+    /// Keeps the last successful outputs of endpoints declared with `fallback: last_good` in
+    /// `store`, see [`last_good`](crate::last_good).
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    #[must_use]
+    pub fn last_good_store(mut self, store: LastGoodStore) -> Self {
+        self.last_good = Some(store);
+        self
+    }
+
+    /// Returns the store of the last successful outputs, if any, see
+    /// [`ApiState::last_good_store`].
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    #[must_use]
+    pub fn last_good(&self) -> Option<&LastGoodStore> {
+        self.last_good.as_ref()
+    }
+
+    /// Waits until fewer than [`ApiState::max_in_flight`] requests are in flight.
+    ///
+    /// The returned permit has to be held until the response is decoded.
+    pub(crate) async fn slot(&self) -> Option<SemaphorePermit<'_>> {
+        match &self.in_flight {
+            Some(in_flight) => in_flight.acquire().await.ok(),
+            None => None,
+        }
+    }
+
+    /// Waits before requests while the server's rate limit is exhausted.
+    ///
+    /// The budget is read from the rate limit headers of each response, see
+    /// [`rate_limit`](crate::rate_limit). Without throttling, the budget is only tracked.
+    #[must_use]
+    pub fn throttle(mut self, throttle: bool) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// Returns whether requests wait for exhausted rate limits, see [`ApiState::throttle`].
+    #[must_use]
+    pub fn is_throttled(&self) -> bool {
+        self.throttle
+    }
+
+    /// Limits how fast requests are sent, e.g. with a [`TokenBucket`](crate::rate_limit::TokenBucket).
+    ///
+    /// Each request waits for the limiter before it is sent. Pass an [`Arc`] to share a limiter
+    /// between instances.
+    #[must_use]
+    pub fn rate_limiter(mut self, limiter: impl RateLimiter + 'static) -> Self {
+        self.rate_limiter = Some(Arc::new(limiter));
+        self
+    }
+
+    /// Sleeps and spawns background tasks on `runtime` instead of Tokio, see
+    /// [`runtime`](crate::runtime).
+    #[must_use]
+    pub fn runtime(mut self, runtime: impl Runtime + 'static) -> Self {
+        self.runtime = Some(Arc::new(runtime));
+        self
+    }
+
+    /// Returns the runtime sleeping and spawning tasks, see [`ApiState::runtime`].
+    pub(crate) fn async_runtime(&self) -> &dyn Runtime {
+        self.runtime.as_deref().unwrap_or(&Tokio)
+    }
+
+    /// Sends requests with `transport` instead of the client of the instance, see
+    /// [`transport`](crate::transport).
+    #[must_use]
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Returns the transport sending the requests, if one is set with [`ApiState::transport`].
+    pub(crate) fn transport_backend(&self) -> Option<&dyn Transport> {
+        self.transport.as_deref()
+    }
+
+    /// Adds `interceptor` around the sending of every request, see
+    /// [`interceptor`](crate::interceptor).
+    ///
+    /// Interceptors run in the order they were added, the first one outermost.
+    #[must_use]
+    pub fn interceptor(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Returns the interceptors around the sending of requests, the outermost first.
+    pub(crate) fn interceptors(&self) -> &[Arc<dyn Interceptor>] {
+        &self.interceptors
+    }
+
+    /// Only sends requests to the schemes and ports allowed by `policy`, see
+    /// [`url_policy`](crate::url_policy).
+    ///
+    /// Clients created for this state don't follow redirects to other URLs either, clients
+    /// passed to the builder explicitly do.
+    #[must_use]
+    pub fn url_policy(mut self, policy: UrlPolicy) -> Self {
+        self.url_policy = Some(policy);
+        self
+    }
+
+    /// Returns the schemes and ports requests may be sent to, if restricted, see
+    /// [`ApiState::url_policy`].
+    pub(crate) fn allowed_urls(&self) -> Option<&UrlPolicy> {
+        self.url_policy.as_ref()
+    }
+
+    /// Fails requests fast while the upstream is down, see [`circuit_breaker`](crate::circuit_breaker).
+    #[must_use]
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Returns the circuit breaker, if any, see [`ApiState::circuit_breaker`].
+    #[must_use]
+    pub fn breaker(&self) -> Option<&CircuitBreaker> {
+        self.circuit_breaker.as_ref()
+    }
+
+    /// Coalesces identical `GET` requests which are in flight at the same time into one.
+    ///
+    /// Requests with the same URL, headers and body wait for the first one and receive a copy of
+    /// its response, which avoids stampedes when many callers ask for the same resource at once.
+    /// The responses of such requests are buffered. If the shared request fails, every caller
+    /// sends its own request. Requests of endpoints with other methods which are declared
+    /// `with { idempotent }`, such as searches sent as `POST`, are coalesced as well.
+    #[must_use]
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.single_flight = deduplicate.then(SingleFlight::default);
+        self
+    }
+
+    /// Returns whether identical `GET` requests are coalesced, see [`ApiState::deduplicate`].
+    #[must_use]
+    pub fn is_deduplicating(&self) -> bool {
+        self.single_flight.is_some()
+    }
+
+    /// Returns the identical `GET` requests in flight, if deduplication is enabled.
+    pub(crate) fn single_flight(&self) -> Option<&SingleFlight> {
+        self.single_flight.as_ref()
+    }
+
+    /// Caches responses according to their caching headers in `store`, e.g. a
+    /// [`MemoryCache`](crate::cache::MemoryCache), see [`cache`](crate::cache).
+    ///
+    /// Pass an [`Arc`] to share a store between instances.
+    #[must_use]
+    pub fn cache(mut self, store: impl CacheStore + 'static) -> Self {
+        self.cache = Some(Arc::new(store));
+        self
+    }
+
+    /// Returns the storage of the HTTP cache, if caching is enabled.
+    pub(crate) fn cache_store(&self) -> Option<&Arc<dyn CacheStore>> {
+        self.cache.as_ref()
+    }
+
+    /// Answers requests from the HTTP cache in `mode` unless they select another one, see
+    /// [`CacheMode`]. Only takes effect together with [`ApiState::cache`].
+    #[must_use]
+    pub fn cache_mode(mut self, mode: CacheMode) -> Self {
+        self.cache_mode = mode;
+        self
+    }
+
+    /// Returns the mode of the HTTP cache for requests which don't select one.
+    pub(crate) fn default_cache_mode(&self) -> CacheMode {
+        self.cache_mode
+    }
+
+    /// Lets the HTTP cache ignore the request header `name`, both where the `Vary` header of a
+    /// response names it and in the [`DEFAULT_VARY_HEADERS`](crate::cache::DEFAULT_VARY_HEADERS),
+    /// e.g. for a `User-Agent` which doesn't change responses.
+    #[must_use]
+    pub fn cache_ignore_vary(mut self, name: &str) -> Self {
+        self.cache_ignored_vary.push(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Returns the lowercase names of the headers the HTTP cache ignores, see
+    /// [`ApiState::cache_ignore_vary`].
+    pub(crate) fn cache_ignored_vary(&self) -> &[String] {
+        &self.cache_ignored_vary
+    }
+
+    /// Returns the runtime revalidating stale responses in the background, or `None` if they are
+    /// revalidated before they are used.
+    ///
+    /// Background tasks can't borrow the instance, so they send requests with its client alone:
+    /// interceptors and transports, whose futures aren't `Send`, can't run there, and captures,
+    /// HAR files and request logs would miss the request.
+    pub(crate) fn background_revalidation(&self) -> Option<&dyn Runtime> {
+        let observed =
+            !self.interceptors.is_empty() || self.transport.is_some() || self.capture.is_some();
+        #[cfg(feature = "json")]
+        let observed = observed || self.har.is_some();
+        #[cfg(feature = "tracing")]
+        let observed = observed || self.logging.is_some();
+        (!observed).then(|| self.async_runtime())
+    }
+
+    /// Keeps summaries of the last requests in `buffer` for debugging, see
+    /// [`capture`](crate::capture).
+    #[must_use]
+    pub fn capture(mut self, buffer: CaptureBuffer) -> Self {
+        self.capture = Some(buffer);
+        self
+    }
+
+    /// Returns the summaries of the last requests, if capturing is enabled, see
+    /// [`ApiState::capture`].
+    #[must_use]
+    pub fn capture_buffer(&self) -> Option<&CaptureBuffer> {
+        self.capture.as_ref()
+    }
+
+    /// Prints every request to standard error as a `curl` command with masked credentials before
+    /// it is sent, see [`curl`](crate::curl).
+    #[must_use]
+    pub fn debug_curl(mut self, enabled: bool) -> Self {
+        self.debug_curl = enabled;
+        self
+    }
+
+    /// Returns whether requests are printed as `curl` commands, see [`ApiState::debug_curl`].
+    #[must_use]
+    pub fn prints_curl(&self) -> bool {
+        self.debug_curl
+    }
+
+    /// Records every request and response for a HAR file, see [`har`](crate::har).
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    #[must_use]
+    pub fn har(mut self, recorder: HarRecorder) -> Self {
+        self.har = Some(recorder);
+        self
+    }
+
+    /// Returns the recorder of the requests and responses, if recording is enabled, see
+    /// [`ApiState::har`].
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    #[must_use]
+    pub fn har_recorder(&self) -> Option<&HarRecorder> {
+        self.har.as_ref()
+    }
+
+    /// Formats the numbers and dates in parameters of calls which don't select another locale in
+    /// `locale`, see [`locale`](crate::locale).
+    #[must_use]
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.locale = Some(Arc::new(locale));
+        self
+    }
+
+    /// Returns the locale of calls which don't select another one.
+    pub(crate) fn default_locale(&self) -> Option<&Arc<Locale>> {
+        self.locale.as_ref()
+    }
+
+    /// Encodes sequences in the `query` parameters of endpoints which don't select another format
+    /// in `arrays`, see [`query`](crate::query#sequences).
+    #[must_use]
+    pub fn array_format(mut self, format: ArrayFormat) -> Self {
+        self.array_format = format;
+        self
+    }
+
+    /// Returns the format of sequences in query parameters of endpoints which don't select another
+    /// one.
+    pub(crate) fn default_array_format(&self) -> ArrayFormat {
+        self.array_format
+    }
+
+    /// Logs requests and responses with the options of `log`, see [`logging`](crate::logging).
+    #[cfg(feature = "tracing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+    #[must_use]
+    pub fn logging(mut self, log: RequestLog) -> Self {
+        self.logging = Some(log);
+        self
+    }
+
+    /// Returns the options of the logging of requests and responses, if logging is enabled, see
+    /// [`ApiState::logging`].
+    #[cfg(feature = "tracing")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+    #[must_use]
+    pub fn request_log(&self) -> Option<&RequestLog> {
+        self.logging.as_ref()
+    }
+
+    /// Returns the rate limit announced by the last response which carried rate limit headers.
+    #[must_use]
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        let rate_limit = self.rate_limit.as_ref()?;
+        *rate_limit.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Records the rate limit announced by the headers of a response, if any.
+    pub(crate) fn update_rate_limit(&self, headers: &reqwest::header::HeaderMap) {
+        if let (Some(state), Some(rate_limit)) =
+            (&self.rate_limit, RateLimit::from_headers(headers))
+        {
+            *state.lock().unwrap_or_else(PoisonError::into_inner) = Some(rate_limit);
+        }
+    }
+
+    /// Sends a new id with every call in the `X-Request-Id` header, see
+    /// [`request_id`](crate::request_id).
+    #[must_use]
+    pub fn request_ids(mut self, enabled: bool) -> Self {
+        self.request_id = enabled.then(|| HeaderName::from_static(REQUEST_ID));
+        self
+    }
+
+    /// Sends a new id with every call in the header `name` instead of `X-Request-Id`, see
+    /// [`request_id`](crate::request_id).
+    ///
+    /// # Panics
+    /// Panics if `name` is not a valid header name.
+    #[must_use]
+    pub fn request_id_header(mut self, name: &str) -> Self {
+        self.request_id = Some(HeaderName::try_from(name).expect("invalid request id header name"));
+        self
+    }
+
+    /// Returns the header carrying the id of every call, if request ids are enabled.
+    pub(crate) fn request_id_header_name(&self) -> Option<&HeaderName> {
+        self.request_id.as_ref()
+    }
+
+    /// Returns the id sent with the last call, if request ids are enabled.
+    #[must_use]
+    pub fn last_request_id(&self) -> Option<String> {
+        let last = self.last_request_id.as_ref()?;
+        last.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Records `id` as the id of the last call.
+    pub(crate) fn set_last_request_id(&self, id: &str) {
+        if let Some(last) = &self.last_request_id {
+            *last.lock().unwrap_or_else(PoisonError::into_inner) = Some(id.to_string());
+        }
+    }
+
+    /// Records that the deprecation of the endpoint described by `spec` is reported, returning
+    /// whether it was not reported before.
+    ///
+    /// Endpoints are told apart by their method and URL template, since endpoints of sub-clients
+    /// and of other structs sharing the state may have the same name.
+    pub(crate) fn first_deprecation(&self, spec: &crate::endpoint::EndpointSpec) -> bool {
+        self.deprecated.as_ref().is_some_and(|deprecated| {
+            deprecated
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert((spec.method.clone(), spec.url))
+        })
+    }
+
+    /// Returns how many requests may be sent right away, as the lower of the budget left by the
+    /// server until its reset and the tokens of the rate limiter, if either is known.
+    pub(crate) fn request_budget(&self) -> Option<u64> {
+        let server = self
+            .rate_limit()
+            .filter(|rate_limit| {
+                rate_limit
+                    .reset
+                    .map_or(true, |reset| reset > Instant::now())
+            })
+            .and_then(|rate_limit| rate_limit.remaining);
+        let limiter = self.rate_limiter.as_ref().and_then(RateLimiter::available);
+        match (server, limiter) {
+            (Some(server), Some(limiter)) => Some(server.min(limiter)),
+            (budget, None) | (None, budget) => budget,
+        }
+    }
+
+    /// Waits until the rate limit resets, if throttling is enabled and the budget is exhausted,
+    /// and then for the rate limiter, if any.
+    pub(crate) async fn wait_for_rate_limit(&self) {
+        if let Some(wait) = self
+            .rate_limit()
+            .filter(|_| self.throttle)
+            .and_then(|rate_limit| rate_limit.wait_time())
+        {
+            self.async_runtime().sleep(wait).await;
+        }
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await;
+        }
+    }
+
+    /// Sends the current trace to the server in the W3C `traceparent` and `tracestate` headers,
+    /// so it can continue the trace.
+    ///
+    /// The trace is read from the current `tracing` span if it is exported with
+    /// `tracing-opentelemetry`, and from the current OpenTelemetry context otherwise. Requests
+    /// outside of a sampled or remote trace carry no headers. Disabled by default, as the headers
+    /// reveal trace IDs to the server.
+    #[cfg(feature = "trace-context")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "trace-context")))]
+    #[must_use]
+    pub fn propagate_trace_context(mut self, propagate: bool) -> Self {
+        self.trace_context = propagate;
+        self
+    }
+
+    /// Returns whether requests carry the trace context headers, see
+    /// [`ApiState::propagate_trace_context`].
+    #[cfg(feature = "trace-context")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "trace-context")))]
+    #[must_use]
+    pub fn propagates_trace_context(&self) -> bool {
+        self.trace_context
+    }
+
+    /// Sets whether clients created for this state follow redirects, which they do by default.
+    ///
+    /// Disable this for APIs using [`Redirect`](crate::response::Redirect) endpoints. Has no
+    /// effect on clients passed to the builder explicitly.
+    #[must_use]
+    pub fn follow_redirects(mut self, follow: bool) -> Self {
+        self.client.follow_redirects = follow;
+        self
+    }
+
+    /// Sets whether clients created for this state use the proxy configured by the system, which
+    /// they do by default.
+    ///
+    /// The proxy is read from the system settings on Windows and macOS, and from the
+    /// `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables on every platform.
+    /// Disable this to always connect directly. Has no effect on clients passed to the builder
+    /// explicitly.
+    #[must_use]
+    pub fn system_proxy(mut self, enabled: bool) -> Self {
+        self.client.system_proxy = enabled;
+        self
+    }
+
+    /// Sets the proxies of clients created for this state, which they use instead of the one
+    /// configured by the system, see [`proxy`](crate::proxy). Has no effect on clients passed to
+    /// the builder explicitly.
+    #[must_use]
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.client.proxy = Some(proxy);
+        self
+    }
+
+    /// Sets whether clients created for this state trust the certificates in the system's store,
+    /// in addition to the roots of the TLS backend, which they do by default.
+    ///
+    /// This lets clients connect through TLS-inspecting corporate proxies whose certificate is
+    /// installed on the system. The `native-tls` backend uses the system's store either way. Has
+    /// no effect on clients passed to the builder explicitly.
+    #[cfg(feature = "system-config")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "system-config")))]
+    #[must_use]
+    pub fn system_certificates(mut self, enabled: bool) -> Self {
+        self.client.system_certificates = enabled;
+        self
+    }
+
+    /// Sets the extra root certificates and the client identity of clients created for this
+    /// state, see [`tls`](crate::tls). Has no effect on clients passed to the builder explicitly.
+    #[must_use]
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.client.tls = tls;
+        self
+    }
+
+    /// Stores the cookies set by responses and sends them with later requests.
+    ///
+    /// Clients created by the builders of the [`api!`](crate::api) macro use the jar of their
+    /// state. When passing a client yourself, install [`ApiState::cookie_jar`] in it with
+    /// [`reqwest::ClientBuilder::cookie_provider`].
+    #[cfg(feature = "cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+    #[must_use]
+    pub fn cookies(mut self, enabled: bool) -> Self {
+        self.cookies = enabled.then(|| Arc::new(CookieJar::new()));
+        self
+    }
+
+    /// Returns the cookie jar, if cookies are enabled, see [`ApiState::cookies`].
+    #[cfg(feature = "cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookies")))]
+    #[must_use]
+    pub fn cookie_jar(&self) -> Option<&Arc<CookieJar>> {
+        self.cookies.as_ref()
+    }
+
+    /// Creates a client configured for this state, used when no client is given explicitly.
+    ///
+    /// # Panics
+    /// Panics if the TLS backend cannot be initialized, like [`reqwest::Client::new`].
+    #[doc(hidden)]
+    #[must_use]
+    pub fn default_client(&self) -> reqwest::Client {
+        let mut builder = reqwest::Client::builder();
+        if !self.client.follow_redirects {
+            builder = builder.redirect(reqwest::redirect::Policy::none());
+        } else if let Some(policy) = &self.url_policy {
+            builder = builder.redirect(policy.redirects());
+        }
+        if let Some(proxy) = &self.client.proxy {
+            builder = builder.no_proxy().proxy(proxy.reqwest_proxy());
+        } else if !self.client.system_proxy {
+            builder = builder.no_proxy();
+        }
+        #[cfg(feature = "system-config")]
+        let system_roots = if self.client.system_certificates {
+            system_certificates()
+        } else {
+            Vec::new()
+        };
+        #[cfg(not(feature = "system-config"))]
+        let system_roots: Vec<Vec<u8>> = Vec::new();
+        #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+        for certificate in &system_roots {
+            if let Ok(certificate) = reqwest::Certificate::from_der(certificate) {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        builder = self.client.tls.apply(builder, &system_roots);
+        #[cfg(feature = "cookies")]
+        if let Some(jar) = &self.cookies {
+            builder = builder.cookie_provider(jar.clone());
+        }
+        builder
+            .build()
+            .expect("failed to initialize the TLS backend")
+    }
+
+    /// Records that `endpoint` succeeded, so endpoints declared with `with { requires: [...] }`
+    /// naming it may be called, e.g. after restoring a session instead of logging in.
+    ///
+    /// The endpoint counts as called for every struct and sub-client using this state.
+    /// Calls are only tracked in debug builds, this does nothing in release builds.
+    pub fn mark_called(&self, endpoint: &str) {
+        #[cfg(debug_assertions)]
+        if let Some(called) = &self.called {
+            called
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(endpoint.to_string());
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = endpoint;
+    }
+
+    /// Records that the endpoint `name` of the struct or sub-client `owner` succeeded.
+    #[cfg(debug_assertions)]
+    pub(crate) fn record_call(&self, owner: &str, name: &str) {
+        if let Some(called) = &self.called {
+            called
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(format!("{owner}::{name}"));
+        }
+    }
+
+    /// Checks that all endpoints required by the one described by `spec`, declared on the struct
+    /// or sub-client `owner`, have succeeded.
+    ///
+    /// # Panics
+    /// Panics naming the first endpoint which has not succeeded yet.
+    #[cfg(debug_assertions)]
+    pub(crate) fn check_call_order(&self, owner: &str, spec: &crate::endpoint::EndpointSpec) {
+        let called = match &self.called {
+            Some(called) if !spec.requires.is_empty() => called,
+            _ => return,
+        };
+        let missing = {
+            let called = called.lock().unwrap_or_else(PoisonError::into_inner);
+            spec.requires
+                .iter()
+                .find(|required| {
+                    !called.contains(**required)
+                        && !called.contains(&format!("{owner}::{required}"))
+                })
+                .copied()
+        };
+        if let Some(required) = missing {
+            panic!(
+                "`{}` was called before `{required}`, which it requires; call `{required}` \
+                 first or record it with `ApiState::mark_called`",
+                spec.name
+            );
+        }
+    }
+
+    /// Waits until a request may be sent in sequential mode.
+    ///
+    /// The returned guard has to be held until the response is decoded.
+    pub(crate) async fn turn(&self) -> Option<MutexGuard<'_, ()>> {
+        match &self.queue {
+            Some(queue) => Some(queue.lock().await),
+            None => None,
+        }
+    }
+}
+
+/// Options of the clients created by [`ApiState::default_client`].
+#[derive(Debug)]
+struct ClientOptions {
+    /// Whether redirects are followed.
+    follow_redirects: bool,
+    /// Whether the proxy configured by the system is used.
+    system_proxy: bool,
+    /// Whether the system's certificate store is trusted.
+    #[cfg(feature = "system-config")]
+    system_certificates: bool,
+    /// Extra root certificates and the client identity.
+    tls: TlsConfig,
+    /// Proxies used instead of the system's, if any.
+    proxy: Option<ProxyConfig>,
+}
+
+/// Loads the DER encoded certificates in the system's store.
+///
+/// Certificates which cannot be read are skipped, so a broken store only makes clients fall back
+/// to the roots of the TLS backend.
+#[cfg(feature = "system-config")]
+fn system_certificates() -> Vec<Vec<u8>> {
+    rustls_native_certs::load_native_certs()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|certificate| certificate.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use crate::{api, test_server, ApiState};
+
+    api!(struct Sequential);
+
+    impl Sequential {
+        api! {
+            fn get(&self, base_url: &str, id: u32) -> String {
+                GET "{base_url}/{id}"
+            }
+        }
+    }
+
+    #[test]
+    fn sends_requests_in_order() {
+        tokio_test::block_on(async {
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let server_log = log.clone();
+            let base_url = test_server::serve(move |request| {
+                server_log.lock().unwrap().push(request.path);
+                test_server::Response::new(200)
+            })
+            .await;
+            let api = Sequential::builder()
+                .state(ApiState::new().sequential(true))
+                .build()
+                .unwrap();
+
+            crate::api_join!(
+                api.get(&base_url, 1),
+                api.get(&base_url, 2),
+                api.get(&base_url, 3)
+            )
+            .unwrap();
+            assert_eq!(*log.lock().unwrap(), ["/1", "/2", "/3"]);
+        });
+    }
+
+    #[test]
+    fn waits_for_bodies_to_be_decoded() {
+        tokio_test::block_on(async {
+            let arrivals = Arc::new(Mutex::new(Vec::new()));
+            let server_arrivals = arrivals.clone();
+            let base_url = test_server::serve(move |request| {
+                server_arrivals.lock().unwrap().push(Instant::now());
+                test_server::Response::new(200)
+                    .body(request.path)
+                    .body_delay(Duration::from_millis(100))
+            })
+            .await;
+
+            for state in [
+                ApiState::new().sequential(true),
+                ApiState::new().max_in_flight(1),
+            ] {
+                arrivals.lock().unwrap().clear();
+                let api = Sequential::builder().state(state).build().unwrap();
+                let (first, second) =
+                    crate::api_join!(api.get(&base_url, 1), api.get(&base_url, 2)).unwrap();
+                assert_eq!((first.as_str(), second.as_str()), ("/1", "/2"));
+                let arrivals = arrivals.lock().unwrap();
+                assert!(arrivals[1] - arrivals[0] >= Duration::from_millis(100));
+            }
+        });
+    }
+
+    #[test]
+    fn limits_requests_in_flight() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|_| {
+                test_server::Response::new(200).delay(Duration::from_millis(100))
+            })
+            .await;
+            let api = Sequential::builder()
+                .state(ApiState::new().max_in_flight(2))
+                .build()
+                .unwrap();
+
+            let start = Instant::now();
+            crate::api_join!(
+                api.get(&base_url, 1),
+                api.get(&base_url, 2),
+                api.get(&base_url, 3),
+                api.get(&base_url, 4)
+            )
+            .unwrap();
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(200));
+            assert!(elapsed < Duration::from_millis(400));
+        });
+    }
+}
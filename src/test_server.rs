@@ -0,0 +1,219 @@
+//! Minimal HTTP server for testing clients, used by the tests of this crate and available to
+//! others with the `test-support` feature.
+//!
+//! [`serve`] answers every request with a handler, on a port of `127.0.0.1` picked by the system,
+//! and returns the base URL to declare endpoints against. Each connection handles one request. It
+//! has to run on a tokio runtime.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, test_server, Api};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn greet(base_url: &str, name: &str) -> String {
+//!            GET "{base_url}/greet/{name}"
+//!         }
+//!     }
+//! }
+//!
+//! #[tokio::test]
+//! async fn greets() {
+//!     let base_url = test_server::serve(|request| {
+//!         let name = request.path.trim_start_matches("/greet/");
+//!         test_server::Response::new(200).body(format!("Hello, {name}!"))
+//!     })
+//!     .await;
+//!     let greeting = ExampleApi::new().greet(&base_url, "Ferris").await.unwrap();
+//!     assert_eq!(greeting, "Hello, Ferris!");
+//! }
+//! ```
+
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// A request received by the test server.
+#[derive(Debug, Clone)]
+pub struct Request {
+    /// Request method, e.g. `GET`.
+    pub method: String,
+    /// Request path including the query string.
+    pub path: String,
+    /// Request headers, names are lowercase.
+    pub headers: Vec<(String, String)>,
+    /// Request body.
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Returns the value of the first header named `name`.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A response sent by the test server.
+#[derive(Debug, Clone)]
+pub struct Response {
+    /// Status code.
+    status: u16,
+    /// Response headers.
+    headers: Vec<(String, String)>,
+    /// Response body.
+    body: Vec<u8>,
+    /// Time to wait before responding.
+    delay: Option<Duration>,
+    /// Time to wait between the head and the body.
+    body_delay: Option<Duration>,
+}
+
+impl Response {
+    /// Creates an empty response with `status`.
+    #[must_use]
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            delay: None,
+            body_delay: None,
+        }
+    }
+
+    /// Adds a header.
+    #[must_use]
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the body.
+    #[must_use]
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Waits for `delay` before responding.
+    #[must_use]
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Waits for `delay` after sending the head before sending the body.
+    #[must_use]
+    pub fn body_delay(mut self, delay: Duration) -> Self {
+        self.body_delay = Some(delay);
+        self
+    }
+
+    /// Sets a JSON body.
+    #[must_use]
+    pub fn json(self, body: &str) -> Self {
+        self.header("content-type", "application/json").body(body)
+    }
+}
+
+/// Starts a server answering every request with `handler`, returning its base URL.
+///
+/// # Panics
+/// Panics if no local port can be bound.
+pub async fn serve<F>(handler: F) -> String
+where
+    F: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                if let Some(request) = read_request(&mut stream).await {
+                    write_response(&mut stream, handler(request)).await;
+                }
+            });
+        }
+    });
+    base_url
+}
+
+/// Reads a single request from `stream`.
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut buffer = Vec::new();
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        let mut chunk = [0; 4096];
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: buffer[head_end + 4..].to_vec(),
+    };
+    let length = request
+        .header("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    while request.body.len() < length {
+        let mut chunk = [0; 4096];
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        request.body.extend_from_slice(&chunk[..read]);
+    }
+    Some(request)
+}
+
+/// Writes `response` to `stream` and closes the connection.
+async fn write_response(stream: &mut TcpStream, response: Response) {
+    if let Some(delay) = response.delay {
+        tokio::time::sleep(delay).await;
+    }
+    let mut head = format!("HTTP/1.1 {} STATUS\r\n", response.status);
+    for (name, value) in &response.headers {
+        let _ = write!(head, "{name}: {value}\r\n");
+    }
+    let _ = write!(
+        head,
+        "content-length: {}\r\nconnection: close\r\n\r\n",
+        response.body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    if let Some(delay) = response.body_delay {
+        let _ = stream.flush().await;
+        tokio::time::sleep(delay).await;
+    }
+    let _ = stream.write_all(&response.body).await;
+    let _ = stream.shutdown().await;
+}
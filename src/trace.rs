@@ -0,0 +1,360 @@
+//! Spans and events describing requests, emitted with the `tracing` feature.
+//!
+//! Every endpoint call runs in a `request` span with the fields `endpoint`, `method`, `url`,
+//! `status`, `latency_ms` and, if [request ids](crate::request_id) are enabled, `request_id`. `url` is the template as declared, e.g. `/users/{id}`, so spans of
+//! the same endpoint can be aggregated. Failed calls emit a `WARN` event with the error, fallbacks
+//! and background revalidations of cached responses, which repeat or replace a request, emit an
+//! `INFO` and a `DEBUG` event. Requests sent again by [`TokenAuth`](crate::auth::TokenAuth),
+//! [`DigestAuth`](crate::auth::DigestAuth) and
+//! [`MultipartUpload`](crate::multipart_upload::MultipartUpload) emit an `INFO` event with the
+//! number of the attempt and what failed.
+//!
+//! Without the feature, all functions of this module compile to nothing.
+//!
+//! With the `trace-context` feature, instances with
+//! [`ApiState::propagate_trace_context`](crate::ApiState::propagate_trace_context) also send the
+//! current trace to the server in the W3C `traceparent` and `tracestate` headers.
+
+use std::{fmt, future::Future};
+
+use crate::{endpoint::EndpointSpec, Error, ResultType};
+
+/// Runs `call`, the call of the endpoint described by `spec`, in a `request` span.
+#[cfg(feature = "tracing")]
+pub(crate) async fn instrument<O>(
+    spec: &EndpointSpec,
+    call: impl Future<Output = ResultType<O>>,
+) -> ResultType<O> {
+    use tracing::{field, Instrument};
+
+    let span = tracing::info_span!(
+        "request",
+        endpoint = spec.name,
+        method = %spec.method,
+        url = spec.url,
+        status = field::Empty,
+        latency_ms = field::Empty,
+        request_id = field::Empty,
+    );
+    let started = std::time::Instant::now();
+    let output = call.instrument(span.clone()).await;
+    span.record(
+        "latency_ms",
+        u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+    );
+    if let Err(error) = &output {
+        span.in_scope(|| tracing::warn!(error = %error, "request failed"));
+    }
+    output
+}
+
+/// Runs `call`, the call of the endpoint described by `spec`.
+#[cfg(not(feature = "tracing"))]
+pub(crate) async fn instrument<O>(
+    _spec: &EndpointSpec,
+    call: impl Future<Output = ResultType<O>>,
+) -> ResultType<O> {
+    call.await
+}
+
+/// Records the status of `response` in the current `request` span.
+pub(crate) fn status(response: &reqwest::Response) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("status", response.status().as_u16());
+    #[cfg(not(feature = "tracing"))]
+    let _ = response;
+}
+
+/// Records the request id of the call in the current `request` span.
+pub(crate) fn request_id(id: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("request_id", id);
+    #[cfg(not(feature = "tracing"))]
+    let _ = id;
+}
+
+/// Emits an event for the call of the endpoint described by `spec` falling back after `error`.
+pub(crate) fn fallback(spec: &EndpointSpec, error: &Error) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(endpoint = spec.name, error = %error, "falling back");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (spec, error);
+}
+
+/// Emits an event for attempt `attempt`, counting from 1, of a request being sent after `error`.
+///
+/// `spec` describes the endpoint unless the retry happens in its `request` span already, e.g. in
+/// an interceptor, or outside of any endpoint call.
+pub(crate) fn retry(spec: Option<&EndpointSpec>, attempt: u32, error: &dyn fmt::Display) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(
+        endpoint = spec.map(|spec| spec.name),
+        attempt,
+        error = %error,
+        "retrying request"
+    );
+    #[cfg(not(feature = "tracing"))]
+    let _ = (spec, attempt, error);
+}
+
+/// Emits an event for `endpoint` being deprecated.
+pub(crate) fn deprecation(endpoint: &str, deprecation: &crate::deprecation::Deprecation) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(endpoint, deprecation = %deprecation, "endpoint is deprecated");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (endpoint, deprecation);
+}
+
+/// Emits an event for the request to `url` being sent again to revalidate a stale cached
+/// response.
+pub(crate) fn revalidation(url: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(url, "revalidating stale cached response");
+    #[cfg(not(feature = "tracing"))]
+    let _ = url;
+}
+
+/// Adds the W3C `traceparent` and `tracestate` headers of the current trace to `request`, if
+/// `state` propagates it.
+///
+/// The trace is read from the current `tracing` span if it is exported with
+/// `tracing-opentelemetry`, and from the current OpenTelemetry context otherwise.
+#[cfg(feature = "trace-context")]
+pub(crate) fn propagate(
+    state: &crate::ApiState,
+    request: crate::RequestBuilder,
+) -> crate::RequestBuilder {
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    if !state.propagates_trace_context() {
+        return request;
+    }
+    let mut context = tracing::Span::current().context();
+    if !context.span().span_context().is_valid() {
+        context = opentelemetry::Context::current();
+    }
+    let span = context.span();
+    let trace = span.span_context();
+    if !trace.is_valid() {
+        return request;
+    }
+    let request = request.header(
+        "traceparent",
+        format!(
+            "00-{}-{}-{:02x}",
+            trace.trace_id(),
+            trace.span_id(),
+            trace.trace_flags().to_u8()
+        ),
+    );
+    match trace.trace_state().header() {
+        trace_state if trace_state.is_empty() => request,
+        trace_state => request.header("tracestate", trace_state),
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::{
+        cell::RefCell,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+    use tracing_core::span::Current;
+
+    use crate::{api, auth::TokenAuth, test_server, Api, ApiState};
+
+    thread_local! {
+        /// Spans entered on the current thread, the innermost last.
+        static ENTERED: RefCell<Vec<span::Id>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// Metadata and fields, as `name=value`, of spans indexed by their id minus one.
+    type Spans = Vec<(&'static Metadata<'static>, Vec<String>)>;
+
+    /// Subscriber keeping the fields of all spans and events as text.
+    #[derive(Default, Clone)]
+    struct Recorder {
+        /// Recorded spans.
+        spans: Arc<Mutex<Spans>>,
+        /// Fields of the events.
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    /// Visitor collecting fields as `name=value`.
+    struct Fields<'a>(&'a mut Vec<String>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.push(format!("{}={value:?}", field.name()));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push(format!("{}={value}", field.name()));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+            let mut fields = Vec::new();
+            span.record(&mut Fields(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata(), fields));
+            span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+            let index = usize::try_from(span.into_u64() - 1).unwrap();
+            values.record(&mut Fields(&mut self.spans.lock().unwrap()[index].1));
+        }
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Vec::new();
+            event.record(&mut Fields(&mut fields));
+            self.events.lock().unwrap().push(fields.join(" "));
+        }
+
+        fn enter(&self, span: &span::Id) {
+            ENTERED.with(|entered| entered.borrow_mut().push(span.clone()));
+        }
+
+        fn exit(&self, _: &span::Id) {
+            ENTERED.with(|entered| entered.borrow_mut().pop());
+        }
+
+        fn current_span(&self) -> Current {
+            let current = ENTERED.with(|entered| entered.borrow().last().cloned());
+            match current {
+                Some(id) => {
+                    let index = usize::try_from(id.into_u64() - 1).unwrap();
+                    Current::new(id, self.spans.lock().unwrap()[index].0)
+                }
+                None => Current::none(),
+            }
+        }
+    }
+
+    api!(struct Traced);
+
+    impl Traced {
+        api! {
+            fn user(&self, base_url: &str, id: u32) -> String {
+                GET "{base_url}/users/{id}"
+                with { fallback: default }
+            }
+        }
+    }
+
+    #[test]
+    fn traces_requests() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            tokio_test::block_on(async {
+                let base_url = test_server::serve(|request| match request.path.as_str() {
+                    "/users/1" => test_server::Response::new(200).body("jane"),
+                    _ => test_server::Response::new(404),
+                })
+                .await;
+                let api = Traced::new();
+                assert_eq!(api.user(&base_url, 1).await.unwrap(), "jane");
+                assert_eq!(api.user("http://", 2).await.unwrap(), "");
+            });
+        });
+
+        let spans = recorder.spans.lock().unwrap();
+        let requests: Vec<_> = spans
+            .iter()
+            .map(|(_, fields)| fields)
+            .filter(|fields| fields.iter().any(|field| field == "endpoint=user"))
+            .collect();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains(&"url={base_url}/users/{id}".to_string()));
+        assert!(requests[0].contains(&"method=GET".to_string()));
+        assert!(requests[0].contains(&"status=200".to_string()));
+        assert!(requests[0]
+            .iter()
+            .any(|field| field.starts_with("latency_ms=")));
+        assert!(!requests[1].iter().any(|field| field.starts_with("status=")));
+        let events = recorder.events.lock().unwrap();
+        let emitted = |message: &str| events.iter().any(|event| event.contains(message));
+        assert!(emitted("message=request failed"));
+        assert!(emitted("message=falling back"));
+    }
+
+    #[test]
+    fn traces_retries() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            tokio_test::block_on(async {
+                let base_url =
+                    test_server::serve(|request| match request.header("authorization") {
+                        Some("Bearer valid") => test_server::Response::new(200).body("jane"),
+                        _ => test_server::Response::new(401),
+                    })
+                    .await;
+                let auth = TokenAuth::new("expired", || async { Ok("valid".to_string()) });
+                let api = Traced::builder()
+                    .state(ApiState::new().interceptor(auth))
+                    .build()
+                    .unwrap();
+                assert_eq!(api.user(&base_url, 1).await.unwrap(), "jane");
+            });
+        });
+
+        let events = recorder.events.lock().unwrap();
+        assert!(events
+            .iter()
+            .any(|event| event.contains("message=retrying request")
+                && event.contains("attempt=2")
+                && event.contains("error=401 Unauthorized")));
+    }
+
+    #[cfg(feature = "trace-context")]
+    #[test]
+    fn propagates_trace_context() {
+        use opentelemetry::trace::{
+            SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+        };
+
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let header = |name| request.header(name).unwrap_or("-").to_string();
+                let headers = format!("{} {}", header("traceparent"), header("tracestate"));
+                test_server::Response::new(200).body(headers)
+            })
+            .await;
+            let trace = SpanContext::new(
+                TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+                SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+                TraceFlags::SAMPLED,
+                true,
+                TraceState::from_key_value([("vendor", "abc")]).unwrap(),
+            );
+            let propagating = Traced::builder()
+                .state(ApiState::new().propagate_trace_context(true))
+                .build()
+                .unwrap();
+
+            assert_eq!(propagating.user(&base_url, 1).await.unwrap(), "- -");
+            let _trace = opentelemetry::Context::current()
+                .with_remote_span_context(trace)
+                .attach();
+            assert_eq!(
+                propagating.user(&base_url, 1).await.unwrap(),
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01 vendor=abc"
+            );
+            assert_eq!(Traced::new().user(&base_url, 1).await.unwrap(), "- -");
+        });
+    }
+}
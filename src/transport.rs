@@ -0,0 +1,201 @@
+//! Sending requests over other HTTP stacks than reqwest, e.g. on embedded, wasm or edge runtimes.
+//!
+//! Requests are still described with the [`api!`](crate::api) macro and built with the client of
+//! the instance, including [`Api::pre_request`](crate::Api::pre_request). With a [`Transport`]
+//! set through [`ApiState::transport`](crate::ApiState::transport), they are then handed to it
+//! as a [`protocol::Request`](crate::protocol::Request) instead of being sent by the client, and
+//! its [`protocol::Response`](crate::protocol::Response) is decoded by the return kind of the
+//! endpoint as usual. Caching, rate limits and the other options of the [`ApiState`] apply to
+//! both.
+//!
+//! Transports receive whole bodies, so multipart bodies, which reqwest streams, can't be sent
+//! with them. Middleware of the client and the timeouts of endpoints are not applied either, a
+//! transport enforces its own.
+//!
+//! reqwest itself is one such backend: [`reqwest::Client`] implements [`Transport`].
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{
+//!     api,
+//!     protocol::{Request, Response},
+//!     transport::Transport,
+//!     ApiState, ResultType,
+//! };
+//!
+//! /// Answers every request from memory instead of the network.
+//! #[derive(Debug)]
+//! struct Echo;
+//!
+//! #[async_trait::async_trait(?Send)]
+//! impl Transport for Echo {
+//!     async fn send(&self, request: Request) -> ResultType<Response> {
+//!         Ok(Response::new(format!("{} {}", request.method(), request.uri()).into()))
+//!     }
+//! }
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn hello() -> String {
+//!            GET "https://example.com/hello"
+//!         }
+//!     }
+//! }
+//!
+//! # tokio_test::block_on(async {
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().transport(Echo))
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(api.hello().await.unwrap(), "GET https://example.com/hello");
+//! # });
+//! ```
+//!
+//! [`ApiState`]: crate::ApiState
+
+use std::{fmt, sync::Arc};
+
+use bytes::Bytes;
+use reqwest::ResponseBuilderExt;
+
+use crate::{
+    protocol::{Request, Response},
+    Error, ResultType,
+};
+
+/// HTTP stack sending the requests of an instance, see [`transport`](crate::transport).
+#[async_trait::async_trait(?Send)]
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Sends `request` and returns the whole response.
+    ///
+    /// # Errors
+    /// Returns an error if no response was received, usually [`Error::Transport`].
+    async fn send(&self, request: Request) -> ResultType<Response>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    async fn send(&self, request: Request) -> ResultType<Response> {
+        (**self).send(request).await
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Transport for reqwest::Client {
+    async fn send(&self, request: Request) -> ResultType<Response> {
+        let response = self.execute(reqwest::Request::try_from(request)?).await?;
+        let headers = response.headers().clone();
+        let mut received = http::Response::builder()
+            .status(response.status())
+            .version(response.version())
+            .body(response.bytes().await?)
+            .expect("the parts of a received response are valid");
+        *received.headers_mut() = headers;
+        Ok(received)
+    }
+}
+
+/// Sends `request` with `transport`.
+///
+/// # Errors
+/// Returns an error if the request has a streamed body or `transport` failed to send it.
+pub(crate) async fn send(
+    transport: &dyn Transport,
+    request: reqwest::Request,
+) -> ResultType<reqwest::Response> {
+    let url = request.url().clone();
+    let body = match request.body() {
+        Some(body) => body.as_bytes().map(Bytes::copy_from_slice).ok_or_else(|| {
+            Error::Transport("streamed request bodies can't be sent with a transport".into())
+        })?,
+        None => Bytes::new(),
+    };
+    let mut sent = http::Request::builder()
+        .method(request.method().clone())
+        .uri(url.as_str())
+        .version(request.version())
+        .body(body)
+        .map_err(|error| Error::Transport(error.into()))?;
+    *sent.headers_mut() = request.headers().clone();
+
+    let (parts, body) = transport.send(sent).await?.into_parts();
+    let mut response = http::Response::builder()
+        .status(parts.status)
+        .version(parts.version)
+        .url(url)
+        .body(body)
+        .expect("the parts of a received response are valid");
+    *response.headers_mut() = parts.headers;
+    response.extensions_mut().extend(parts.extensions);
+    Ok(reqwest::Response::from(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::Transport;
+    use crate::{
+        api,
+        protocol::{Request, Response},
+        test_server, ApiState, ResultType,
+    };
+
+    /// Transport recording requests and answering them with their body.
+    #[derive(Debug, Default)]
+    struct Recorded(Mutex<Vec<String>>);
+
+    #[async_trait::async_trait(?Send)]
+    impl Transport for Recorded {
+        async fn send(&self, request: Request) -> ResultType<Response> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", request.method(), request.uri()));
+            Ok(Response::new(request.into_body()))
+        }
+    }
+
+    api!(struct Transported);
+
+    impl Transported {
+        api! {
+            fn echo(&self, request: Json<Vec<u32>>, base_url: &str) -> Json<Vec<u32>> {
+                POST "{base_url}/echo"
+            }
+
+            fn path(&self, base_url: &str) -> String {
+                GET "{base_url}/path"
+            }
+        }
+    }
+
+    #[test]
+    fn sends_with_transports() {
+        tokio_test::block_on(async {
+            let recorded = Arc::new(Recorded::default());
+            let api = Transported::builder()
+                .state(ApiState::new().transport(recorded.clone()))
+                .build()
+                .unwrap();
+            let echoed = api.echo(&vec![1, 2], "http://example.com").await.unwrap();
+            assert_eq!(echoed, [1, 2]);
+            assert_eq!(
+                *recorded.0.lock().unwrap(),
+                ["POST http://example.com/echo"]
+            );
+
+            let base_url = test_server::serve(|request| {
+                test_server::Response::new(200).body(request.path.clone())
+            })
+            .await;
+            let api = Transported::builder()
+                .state(ApiState::new().transport(reqwest::Client::new()))
+                .build()
+                .unwrap();
+            assert_eq!(api.path(&base_url).await.unwrap(), "/path");
+        });
+    }
+}
@@ -0,0 +1,414 @@
+//! Resumable uploads with the [tus 1.0](https://tus.io/protocols/resumable-upload) protocol, as
+//! media APIs such as Vimeo and Supabase Storage accept them.
+//!
+//! [`Tus::create`] creates an upload at the creation URL of the server, [`Tus::upload`] sends the
+//! body in chunks with `PATCH` requests, and [`Tus::status`] asks the server how much of an upload
+//! it has received, so an upload interrupted e.g. by a lost connection continues where it
+//! stopped instead of starting over. With [`Tus::checksum`], every chunk carries its SHA-256 hash
+//! as the checksum extension defines, and the server rejects chunks which were corrupted on the
+//! way.
+//!
+//! All requests are sent through the client and hooks of an [`Api`], like the calls of its
+//! endpoints, so they carry its credentials.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, tus::Tus, ResultType};
+//!
+//! api!(pub struct Videos);
+//!
+//! async fn upload_video(api: &Videos) -> ResultType<String> {
+//!     let mut file = tokio::fs::File::open("talk.mp4").await?;
+//!     let length = file.metadata().await?.len();
+//!     let tus = Tus::new()
+//!         .chunk_size(8 << 20)
+//!         .checksum(true)
+//!         .metadata("filename", "talk.mp4");
+//!     let mut upload = tus.create(api, "https://example.com/files/", length).await?;
+//!     if tus.upload(api, &mut upload, &mut file).await.is_err() {
+//!         upload = tus.status(api, upload.url.as_str()).await?;
+//!         tus.upload(api, &mut upload, &mut file).await?;
+//!     }
+//!     Ok(upload.url.to_string())
+//! }
+//! ```
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::BytesMut;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+use crate::{
+    endpoint::{self, EndpointSpec},
+    response::Raw,
+    Api, Body, Error, ResultType,
+};
+
+/// Version of the protocol sent with every request.
+const VERSION: &str = "1.0.0";
+
+/// Size of the chunks sent by [`Tus`] unless configured otherwise.
+pub const DEFAULT_CHUNK_SIZE: usize = 4 << 20;
+
+/// Client of the tus protocol, see [`tus`](crate::tus).
+#[derive(Debug, Clone)]
+pub struct Tus {
+    /// Maximum size of the body of a `PATCH` request.
+    chunk_size: usize,
+    /// Whether chunks carry an `Upload-Checksum` header.
+    checksum: bool,
+    /// Metadata sent when creating uploads.
+    metadata: Vec<(String, String)>,
+}
+
+impl Default for Tus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An upload known to the server, see [`tus`](crate::tus).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TusUpload {
+    /// URL of the upload, to resume it later.
+    pub url: reqwest::Url,
+    /// Number of bytes the server has received.
+    pub offset: u64,
+    /// Size of the whole body, if the server knows it.
+    pub length: Option<u64>,
+}
+
+impl TusUpload {
+    /// Returns whether the server has received the whole body.
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.length == Some(self.offset)
+    }
+}
+
+impl Tus {
+    /// Creates a client sending chunks of [`DEFAULT_CHUNK_SIZE`] without checksums.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            checksum: false,
+            metadata: Vec::new(),
+        }
+    }
+
+    /// Sets the maximum size of the body of a `PATCH` request.
+    ///
+    /// # Panics
+    /// Panics if `chunk_size` is zero.
+    #[must_use]
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunks have to hold at least one byte");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Sends the SHA-256 hash of every chunk in an `Upload-Checksum` header, which servers
+    /// supporting the checksum extension verify.
+    #[must_use]
+    pub fn checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Adds a metadata entry sent when creating uploads, e.g. the `filename`.
+    #[must_use]
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.push((key.into(), value.into()));
+        self
+    }
+
+    /// Creates an upload of `length` bytes at the creation URL `endpoint`.
+    ///
+    /// # Errors
+    /// Returns [`Error::Tus`] if the server didn't create the upload or its URL is invalid, or
+    /// any error from sending the request.
+    pub async fn create<A: Api + ?Sized>(
+        &self,
+        api: &A,
+        endpoint: &str,
+        length: u64,
+    ) -> ResultType<TusUpload> {
+        let metadata = self
+            .metadata
+            .iter()
+            .map(|(key, value)| format!("{key} {}", STANDARD.encode(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let response = send(
+            api,
+            "tus_create",
+            reqwest::Method::POST,
+            endpoint,
+            |request| {
+                let request = request.header("Upload-Length", length);
+                if metadata.is_empty() {
+                    request
+                } else {
+                    request.header("Upload-Metadata", metadata)
+                }
+            },
+        )
+        .await?;
+        if response.status() != reqwest::StatusCode::CREATED {
+            return Err(rejected("creating the upload", &response));
+        }
+        let location = header(&response, "location")
+            .ok_or_else(|| Error::Tus("the created upload has no `Location`".to_string()))?;
+        let url = response
+            .url()
+            .join(location)
+            .map_err(|error| Error::Tus(format!("invalid upload URL: {error}")))?;
+        Ok(TusUpload {
+            url,
+            offset: 0,
+            length: Some(length),
+        })
+    }
+
+    /// Asks the server how much of the upload at `url` it has received, e.g. to resume it.
+    ///
+    /// # Errors
+    /// Returns [`Error::Tus`] if the server doesn't know the upload, or any error from sending
+    /// the request.
+    pub async fn status<A: Api + ?Sized>(&self, api: &A, url: &str) -> ResultType<TusUpload> {
+        let response = send(api, "tus_status", reqwest::Method::HEAD, url, |request| {
+            request.header("Cache-Control", "no-store")
+        })
+        .await?;
+        if !response.status().is_success() {
+            return Err(rejected("querying the offset", &response));
+        }
+        Ok(TusUpload {
+            url: response.url().clone(),
+            offset: offset(&response)?,
+            length: header(&response, "upload-length").and_then(|length| length.parse().ok()),
+        })
+    }
+
+    /// Sends the rest of the body of `upload` from `body`, starting at the offset the server has
+    /// received, which `upload` is kept up to date with. If the server accepts only part of a
+    /// chunk, the rest is sent again from the offset it returned.
+    ///
+    /// # Errors
+    /// Returns [`Error::Tus`] if the server rejected a chunk, e.g. because its checksum didn't
+    /// match or the offset changed, or any error from reading the body or sending the request.
+    /// The upload can be resumed after querying its [`status`](Tus::status).
+    pub async fn upload<A: Api + ?Sized>(
+        &self,
+        api: &A,
+        upload: &mut TusUpload,
+        body: &mut (impl AsyncRead + AsyncSeek + Unpin),
+    ) -> ResultType<()> {
+        loop {
+            body.seek(std::io::SeekFrom::Start(upload.offset)).await?;
+            let mut chunk = BytesMut::with_capacity(self.chunk_size);
+            while chunk.len() < self.chunk_size {
+                let mut limited = (&mut *body).take((self.chunk_size - chunk.len()) as u64);
+                if limited.read_buf(&mut chunk).await? == 0 {
+                    break;
+                }
+            }
+            if chunk.is_empty() && upload.offset > 0 {
+                return Ok(());
+            }
+            let chunk = chunk.freeze();
+            let last = chunk.len() < self.chunk_size;
+            let end = upload.offset + chunk.len() as u64;
+            let checksum = self
+                .checksum
+                .then(|| format!("sha256 {}", STANDARD.encode(Sha256::digest(&chunk))));
+            let start = upload.offset;
+            let url = upload.url.to_string();
+            let response = send(api, "tus_upload", reqwest::Method::PATCH, &url, |request| {
+                let request = request
+                    .header("Upload-Offset", start)
+                    .header("Content-Type", "application/offset+octet-stream");
+                match checksum {
+                    Some(checksum) => request.header("Upload-Checksum", checksum),
+                    None => request,
+                }
+                .body(chunk)
+            })
+            .await?;
+            if response.status() != reqwest::StatusCode::NO_CONTENT {
+                return Err(rejected(
+                    &format!("sending the chunk at {start}"),
+                    &response,
+                ));
+            }
+            upload.offset = offset(&response)?;
+            if upload.is_complete() || (last && upload.offset == end) {
+                return Ok(());
+            }
+            if upload.offset <= start || upload.offset > end {
+                return Err(Error::Tus(format!(
+                    "the chunk at {start} moved the offset to {}",
+                    upload.offset
+                )));
+            }
+        }
+    }
+}
+
+/// Sends a request of the protocol through the hooks of `api`, with `options` applied.
+async fn send<A: Api + ?Sized>(
+    api: &A,
+    name: &'static str,
+    method: reqwest::Method,
+    url: &str,
+    options: impl FnOnce(crate::RequestBuilder) -> crate::RequestBuilder,
+) -> ResultType<reqwest::Response> {
+    let spec = EndpointSpec {
+        name,
+        idempotent: method != reqwest::Method::POST,
+        method,
+        url: "{upload}",
+        timeout: None,
+        deadline: None,
+        retry: None,
+        pointer: None,
+        scopes: &[],
+        requires: &[],
+        grpc_status: false,
+        login: None,
+        cache_mode: None,
+        cache_lifetime: None,
+    };
+    let options = Box::new(|request: crate::RequestBuilder| {
+        options(request.header("Tus-Resumable", VERSION))
+    });
+    endpoint::execute::<A, Raw, ()>(api, &spec, url, Body::None, Some(options)).await
+}
+
+/// Returns the value of the header `name` of `response`, if it is valid UTF-8.
+fn header<'a>(response: &'a reqwest::Response, name: &str) -> Option<&'a str> {
+    response.headers().get(name)?.to_str().ok()
+}
+
+/// Returns the `Upload-Offset` of `response`.
+fn offset(response: &reqwest::Response) -> ResultType<u64> {
+    header(response, "upload-offset")
+        .and_then(|offset| offset.parse().ok())
+        .ok_or_else(|| Error::Tus("the response has no valid `Upload-Offset`".to_string()))
+}
+
+/// Returns the error for a response rejecting `step`.
+fn rejected(step: &str, response: &reqwest::Response) -> Error {
+    let reason = match response.status().as_u16() {
+        409 => "the offset doesn't match the one of the server".to_string(),
+        412 => format!("the server doesn't support version {VERSION}"),
+        460 => "the checksum doesn't match".to_string(),
+        status => format!("status {status}"),
+    };
+    Error::Tus(format!("{step} was rejected: {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    use super::{Tus, TusUpload};
+    use crate::{api, test_server, Api, Error};
+
+    api!(struct Storage);
+
+    #[test]
+    fn uploads_and_resumes() {
+        tokio_test::block_on(async {
+            let received = Arc::new(Mutex::new(Vec::<u8>::new()));
+            let stored = received.clone();
+            let base_url = test_server::serve(move |request| {
+                assert_eq!(request.header("tus-resumable"), Some("1.0.0"));
+                let mut received = stored.lock().unwrap();
+                let offset = received.len().to_string();
+                match request.method.as_str() {
+                    "POST" => {
+                        assert_eq!(request.header("upload-length"), Some("11"));
+                        assert_eq!(request.header("upload-metadata"), Some("name aGVsbG8="));
+                        test_server::Response::new(201).header("location", "/files/1")
+                    }
+                    "HEAD" => test_server::Response::new(200)
+                        .header("upload-offset", &offset)
+                        .header("upload-length", "11"),
+                    _ if request.header("upload-offset") != Some(offset.as_str()) => {
+                        test_server::Response::new(409)
+                    }
+                    _ if request.body == b"o wo" && received.len() == 4 => {
+                        test_server::Response::new(460)
+                    }
+                    _ => {
+                        let checksum = request.header("upload-checksum").unwrap();
+                        assert!(checksum.starts_with("sha256 "), "{checksum}");
+                        received.extend_from_slice(&request.body);
+                        let offset = received.len().to_string();
+                        test_server::Response::new(204).header("upload-offset", &offset)
+                    }
+                }
+            })
+            .await;
+            let api = Storage::new();
+            let tus = Tus::new()
+                .chunk_size(4)
+                .checksum(true)
+                .metadata("name", "hello");
+            let mut body = Cursor::new(b"hello world".to_vec());
+
+            let mut upload = tus
+                .create(&api, &format!("{base_url}/files/"), 11)
+                .await
+                .unwrap();
+            assert_eq!(upload.url.path(), "/files/1");
+            let error = tus.upload(&api, &mut upload, &mut body).await.unwrap_err();
+            assert!(matches!(error, Error::Tus(_)), "{error}");
+            assert_eq!(upload.offset, 4);
+
+            received.lock().unwrap().truncate(2);
+            let mut upload = tus.status(&api, upload.url.as_str()).await.unwrap();
+            assert_eq!((upload.offset, upload.length), (2, Some(11)));
+            tus.upload(&api, &mut upload, &mut body).await.unwrap();
+            assert!(upload.is_complete());
+            assert_eq!(*received.lock().unwrap(), b"hello world");
+        });
+    }
+
+    #[test]
+    fn resends_partially_accepted_chunks() {
+        tokio_test::block_on(async {
+            let received = Arc::new(Mutex::new(Vec::<u8>::new()));
+            let stored = received.clone();
+            let base_url = test_server::serve(move |request| {
+                let mut received = stored.lock().unwrap();
+                let offset = received.len().to_string();
+                if request.header("upload-offset") != Some(offset.as_str()) {
+                    return test_server::Response::new(409);
+                }
+                // Accepts at most three bytes of every chunk.
+                received.extend(request.body.iter().take(3));
+                let offset = received.len().to_string();
+                test_server::Response::new(204).header("upload-offset", &offset)
+            })
+            .await;
+            let api = Storage::new();
+            let tus = Tus::new().chunk_size(4);
+            let mut upload = TusUpload {
+                url: format!("{base_url}/files/1").parse().unwrap(),
+                offset: 0,
+                length: Some(11),
+            };
+            let mut body = Cursor::new(b"hello world".to_vec());
+
+            tus.upload(&api, &mut upload, &mut body).await.unwrap();
+            assert!(upload.is_complete());
+            assert_eq!(*received.lock().unwrap(), b"hello world");
+        });
+    }
+}
@@ -0,0 +1,224 @@
+//! Request bodies streamed from a reader or a stream, so large uploads are never loaded into
+//! memory.
+//!
+//! Endpoints declared with `request: Stream` take an [`Upload`] instead of a reference to their
+//! body. It is sent with a `Content-Length` header when its length is known, e.g. for files opened
+//! with [`Upload::file`] or readers given one with [`Upload::length`], and with chunked transfer
+//! encoding otherwise.
+//!
+//! Its progress is reported to a callback given to [`Upload::on_progress`], see
+//! [`progress`](crate::progress).
+//!
+//! The body can only be read once, so interceptors can't replay these requests, and they are left
+//! out of captures, HAR recordings and request logs.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, upload::Upload, ResultType};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! impl ExampleApi {
+//!     api! {
+//!         fn upload_backup(request: Stream, name: &str) -> StatusCode {
+//!            PUT "https://example.com/backups/{name}"
+//!         }
+//!     }
+//! }
+//!
+//! async fn upload(api: &ExampleApi) -> ResultType<()> {
+//!     let backup = Upload::file("backup.tar").await?.content_type("application/x-tar");
+//!     api.upload_backup(backup, "latest").await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::{fmt, path::Path, pin::Pin};
+
+use futures_util::{Stream, StreamExt};
+use reqwest::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    progress::{Progress, Tracker},
+    RequestBuilder,
+};
+
+/// Error of the chunks of an [`Upload`].
+type ChunkError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Chunks of an [`Upload`].
+type Chunks = Pin<Box<dyn Stream<Item = Result<bytes::Bytes, ChunkError>> + Send + Sync>>;
+
+/// A request body read from a reader or a stream as it is sent, see [`upload`](crate::upload).
+pub struct Upload {
+    /// The chunks of the body.
+    chunks: Chunks,
+    /// Length of the body, if known.
+    length: Option<u64>,
+    /// Content type of the body, if set.
+    content_type: Option<HeaderValue>,
+    /// Called with the progress of the upload, if set.
+    progress: Option<Box<dyn FnMut(Progress) + Send + Sync>>,
+}
+
+impl Upload {
+    /// Creates a body read from `reader`, sent with chunked transfer encoding unless its length
+    /// is set with [`Upload::length`].
+    pub fn from_reader(reader: impl AsyncRead + Send + Sync + 'static) -> Self {
+        Self::from_stream(ReaderStream::new(reader))
+    }
+
+    /// Creates a body made of the chunks of `stream`, sent with chunked transfer encoding unless
+    /// its length is set with [`Upload::length`].
+    pub fn from_stream<S, B, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<B, E>> + Send + Sync + 'static,
+        bytes::Bytes: From<B>,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        Self {
+            chunks: Box::pin(stream.map(|chunk| chunk.map(bytes::Bytes::from).map_err(Into::into))),
+            length: None,
+            content_type: None,
+            progress: None,
+        }
+    }
+
+    /// Opens the file at `path` as a body, sent with its size as `Content-Length`.
+    ///
+    /// # Errors
+    /// Returns an error if the file could not be opened.
+    pub async fn file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = tokio::fs::File::open(path).await?;
+        let length = file.metadata().await?.len();
+        Ok(Self::from_reader(file).length(length))
+    }
+
+    /// Sets the length of the body, sent as `Content-Length` instead of using chunked transfer
+    /// encoding.
+    ///
+    /// The body has to be exactly this long, or the request fails.
+    #[must_use]
+    pub fn length(mut self, length: u64) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    /// Sets the `Content-Type` of the body, e.g. `application/octet-stream`.
+    ///
+    /// # Panics
+    /// Panics if `content_type` is not a valid header value.
+    #[must_use]
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.content_type =
+            Some(HeaderValue::from_str(content_type).expect("content types are valid headers"));
+        self
+    }
+
+    /// Calls `callback` with the progress of the upload after every chunk handed to the
+    /// connection, see [`progress`](crate::progress).
+    ///
+    /// The total is the length of the body, if known.
+    #[must_use]
+    pub fn on_progress(mut self, callback: impl FnMut(Progress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Returns the length of the body, if known.
+    #[must_use]
+    pub fn content_length(&self) -> Option<u64> {
+        self.length
+    }
+
+    /// Attaches the body and its headers to `request`.
+    pub(crate) fn apply(self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(length) = self.length {
+            request = request.header(CONTENT_LENGTH, length);
+        }
+        if let Some(content_type) = self.content_type {
+            request = request.header(CONTENT_TYPE, content_type);
+        }
+        let chunks = match self.progress {
+            Some(callback) => {
+                let mut tracker = Tracker::new(callback, self.length);
+                Box::pin(self.chunks.inspect(move |chunk| {
+                    if let Ok(chunk) = chunk {
+                        tracker.advance(chunk.len());
+                    }
+                }))
+            }
+            None => self.chunks,
+        };
+        request.body(reqwest::Body::wrap_stream(chunks))
+    }
+}
+
+impl fmt::Debug for Upload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Upload")
+            .field("length", &self.length)
+            .field("content_type", &self.content_type)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::Upload;
+    use crate::{api, test_server, Api};
+
+    api!(struct Backups);
+
+    impl Backups {
+        api! {
+            fn upload(&self, request: Stream, base_url: &str) -> String {
+                PUT "{base_url}/backups"
+            }
+        }
+    }
+
+    #[test]
+    fn streams_bodies() {
+        tokio_test::block_on(async {
+            let base_url = test_server::serve(|request| {
+                let length = request.header("content-length").unwrap_or("none");
+                let chunked = request.header("transfer-encoding") == Some("chunked");
+                test_server::Response::new(200).body(if chunked {
+                    format!("chunked, {length}")
+                } else {
+                    format!("{}, {length}", String::from_utf8_lossy(&request.body))
+                })
+            })
+            .await;
+            let api = Backups::new();
+
+            let path =
+                std::env::temp_dir().join(format!("api-client-upload-{}", std::process::id()));
+            std::fs::write(&path, "backup").unwrap();
+            let file = Upload::file(&path).await.unwrap();
+            assert_eq!(file.content_length(), Some(6));
+            assert_eq!(api.upload(file, &base_url).await.unwrap(), "backup, 6");
+            std::fs::remove_file(&path).unwrap();
+
+            let reported = Arc::new(Mutex::new(Vec::new()));
+            let progress = reported.clone();
+            let reader = Upload::from_reader(&b"abc"[..])
+                .on_progress(move |update| progress.lock().unwrap().push(update.fraction()))
+                .length(3);
+            assert_eq!(api.upload(reader, &base_url).await.unwrap(), "abc, 3");
+            assert_eq!(*reported.lock().unwrap(), [Some(1.0)]);
+
+            let chunks = futures_util::stream::iter([Ok::<_, std::io::Error>("a"), Ok("bc")]);
+            let stream = Upload::from_stream(chunks);
+            assert_eq!(
+                api.upload(stream, &base_url).await.unwrap(),
+                "chunked, none"
+            );
+        });
+    }
+}
@@ -0,0 +1,219 @@
+//! Restricting the schemes and ports requests may be sent to.
+//!
+//! Endpoint URLs are often assembled from configuration or from data returned by other services.
+//! With a [`UrlPolicy`] set through [`ApiState::url_policy`](crate::ApiState::url_policy),
+//! requests to URLs it doesn't allow fail with [`Error::UrlRejected`](crate::Error::UrlRejected)
+//! before they are sent, and clients created by the builders of the [`api!`](crate::api) macro
+//! don't follow redirects to them either.
+//!
+//! A policy allows `https` on its default port. Plain `http` has to be allowed per host, e.g. for
+//! a local development server, and other ports one by one. The URL is checked as it is built,
+//! changes made by [interceptors](crate::interceptor) are not checked.
+//!
+//! # Usage
+//! ```rust
+//! use api_client::{api, url_policy::UrlPolicy, ApiState};
+//!
+//! api!(pub struct ExampleApi);
+//!
+//! let api = ExampleApi::builder()
+//!     .state(ApiState::new().url_policy(
+//!         UrlPolicy::new().allow_http("localhost").allow_port(8443),
+//!     ))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::fmt;
+
+use reqwest::Url;
+
+/// Maximum number of redirects followed by clients checking them, the default of reqwest.
+const MAX_REDIRECTS: usize = 10;
+
+/// Schemes and ports requests may be sent to, see [`url_policy`](crate::url_policy).
+#[derive(Debug, Clone, Default)]
+pub struct UrlPolicy {
+    /// Lowercase hosts which may also be reached over plain `http`.
+    http_hosts: Vec<String>,
+    /// Ports allowed besides the default port of the scheme.
+    ports: Vec<u16>,
+}
+
+impl UrlPolicy {
+    /// Creates a policy only allowing `https` on its default port.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also allows plain `http` to `host`, as written in URLs, e.g. `localhost` or `127.0.0.1`.
+    #[must_use]
+    pub fn allow_http(mut self, host: &str) -> Self {
+        self.http_hosts.push(host.to_ascii_lowercase());
+        self
+    }
+
+    /// Also allows `port`, for any allowed scheme.
+    #[must_use]
+    pub fn allow_port(mut self, port: u16) -> Self {
+        self.ports.push(port);
+        self
+    }
+
+    /// Checks whether requests may be sent to `url`.
+    ///
+    /// # Errors
+    /// Returns the reason if the scheme or the port of `url` is not allowed.
+    pub fn check(&self, url: &Url) -> Result<(), UrlRejected> {
+        let host = url.host_str().unwrap_or_default();
+        let rejection = match url.scheme() {
+            "https" => None,
+            "http" if self.http_hosts.iter().any(|allowed| allowed == host) => None,
+            scheme => Some(Rejection::Scheme(scheme.to_string())),
+        }
+        .or_else(|| {
+            url.port()
+                .filter(|port| !self.ports.contains(port))
+                .map(Rejection::Port)
+        });
+        match rejection {
+            Some(reason) => Err(UrlRejected {
+                origin: match url.port() {
+                    Some(port) => format!("{}://{host}:{port}", url.scheme()),
+                    None => format!("{}://{host}", url.scheme()),
+                },
+                reason,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns a redirect policy for clients, following up to ten redirects to allowed URLs.
+    pub(crate) fn redirects(&self) -> reqwest::redirect::Policy {
+        let policy = self.clone();
+        reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match policy.check(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(rejected) => attempt.error(rejected),
+            }
+        })
+    }
+}
+
+/// Why a [`UrlPolicy`] rejected a URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Rejection {
+    /// The scheme is not allowed for the host, e.g. `http`.
+    Scheme(String),
+    /// The port is not allowed.
+    Port(u16),
+}
+
+/// A URL rejected by a [`UrlPolicy`], see [`Error::UrlRejected`](crate::Error::UrlRejected).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlRejected {
+    /// Scheme, host and port of the URL, e.g. `http://example.com:8080`, without the path and
+    /// query, which may carry credentials.
+    pub origin: String,
+    /// Why the URL was rejected.
+    pub reason: Rejection,
+}
+
+impl fmt::Display for UrlRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request to {} rejected, ", self.origin)?;
+        match &self.reason {
+            Rejection::Scheme(scheme) => write!(f, "scheme `{scheme}` is not allowed"),
+            Rejection::Port(port) => write!(f, "port {port} is not allowed"),
+        }
+    }
+}
+
+impl std::error::Error for UrlRejected {}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use super::{Rejection, UrlPolicy};
+    use crate::{api, test_server, ApiState, Error};
+
+    #[test]
+    fn checks_schemes_and_ports() {
+        let policy = UrlPolicy::new().allow_http("LocalHost").allow_port(8443);
+        let check = |url: &str| {
+            policy
+                .check(&Url::parse(url).unwrap())
+                .map_err(|rejected| rejected.reason)
+        };
+
+        assert_eq!(check("https://example.com/a?token=t"), Ok(()));
+        assert_eq!(check("https://example.com:443/"), Ok(()));
+        assert_eq!(check("https://example.com:8443/"), Ok(()));
+        assert_eq!(check("http://localhost/"), Ok(()));
+        assert_eq!(check("http://localhost:8443/"), Ok(()));
+        assert_eq!(
+            check("http://example.com/"),
+            Err(Rejection::Scheme("http".to_string()))
+        );
+        assert_eq!(check("http://localhost:8080/"), Err(Rejection::Port(8080)));
+        assert_eq!(
+            check("ftp://localhost/"),
+            Err(Rejection::Scheme("ftp".to_string()))
+        );
+
+        let rejected = policy
+            .check(&Url::parse("http://example.com:81/login?token=t").unwrap())
+            .unwrap_err();
+        assert_eq!(
+            rejected.to_string(),
+            "request to http://example.com:81 rejected, scheme `http` is not allowed"
+        );
+    }
+
+    api!(struct Guarded);
+
+    impl Guarded {
+        api! {
+            fn get(&self, url: &str) -> StatusCode {
+                GET "{url}"
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_requests_and_redirects() {
+        tokio_test::block_on(async {
+            let other = test_server::serve(|_| test_server::Response::new(200)).await;
+            let redirect = format!("{other}/");
+            let base_url = test_server::serve(move |request| match request.path.as_str() {
+                "/away" => test_server::Response::new(302).header("location", &redirect),
+                _ => test_server::Response::new(200),
+            })
+            .await;
+            let port = Url::parse(&base_url).unwrap().port().unwrap();
+            let api = |policy| {
+                Guarded::builder()
+                    .state(ApiState::new().url_policy(policy))
+                    .build()
+                    .unwrap()
+            };
+
+            let error = api(UrlPolicy::new()).get(&base_url).await.unwrap_err();
+            assert!(matches!(
+                error,
+                Error::UrlRejected(rejected) if rejected.reason == Rejection::Scheme("http".to_string())
+            ));
+
+            let api = api(UrlPolicy::new().allow_http("127.0.0.1").allow_port(port));
+            assert_eq!(api.get(&base_url).await.unwrap().as_u16(), 200);
+            let error = api.get(&format!("{base_url}/away")).await.unwrap_err();
+            assert!(error.client_error().is_some());
+        });
+    }
+}